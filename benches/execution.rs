@@ -0,0 +1,85 @@
+//! Benchmarks comparing the execution modes of the server.
+//!
+//! Run with `cargo bench --bench execution`. Every workload runs against a server
+//! in this process, once in every mode, over the same number of connections.
+//! The read-heavy workload only sends `GET`, and the write-heavy one only `SET`.
+
+use miniredis::benchmark::Benchmark;
+use miniredis::server::{ExecutionMode, Server};
+use miniredis::testing::TestServer;
+
+/// The connections every workload is sent over.
+const CLIENTS: &str = "50";
+
+/// The requests of every workload.
+const REQUESTS: &str = "200000";
+
+/// The modes, by name.
+const MODES: &[(&str, ExecutionMode)] = &[
+    ("threaded", ExecutionMode::Threaded),
+    ("single-writer", ExecutionMode::SingleWriter),
+];
+
+/// The workloads, by name, with the command they send.
+const WORKLOADS: &[(&str, &str)] = &[("read-heavy", "get"), ("write-heavy", "set")];
+
+fn main() {
+    let mut rows = Vec::new();
+    for (workload, command) in WORKLOADS {
+        for (mode, execution_mode) in MODES {
+            let server = TestServer::serve(
+                Server::builder("127.0.0.1:0")
+                    .execution_mode(*execution_mode)
+                    .build(),
+            );
+            // Fill the keyspace first, so reads find their keys.
+            run(&server, "set");
+            let report = run(&server, command);
+            rows.push(format!(
+                "{:<14} {:<14} {:>12.0} {:>10.1?} {:>10.1?}",
+                workload,
+                mode,
+                report.throughput(),
+                report.latency(50.0),
+                report.latency(99.0)
+            ));
+        }
+    }
+
+    // Every run prints its own report, so the comparison comes last.
+    println!();
+    println!(
+        "{:<14} {:<14} {:>12} {:>10} {:>10}",
+        "workload", "mode", "requests/s", "p50", "p99"
+    );
+    for row in rows {
+        println!("{}", row);
+    }
+}
+
+/// Sends one command over many connections, with random keys out of a keyspace.
+fn run(server: &TestServer, command: &str) -> miniredis::benchmark::Report {
+    let (host, port) = server.address().split_once(':').unwrap();
+    let args = [
+        "miniredis-benchmark",
+        "--host",
+        host,
+        "--port",
+        port,
+        "-c",
+        CLIENTS,
+        "-n",
+        REQUESTS,
+        "-r",
+        "10000",
+        "-t",
+        command,
+    ]
+    .map(str::to_string);
+    let mut reports = Benchmark::from_args(&args)
+        .expect("invalid benchmark options")
+        .run()
+        .expect("benchmark failed");
+    assert_eq!(0, reports[0].errors, "{} got error replies", command);
+    reports.remove(0)
+}
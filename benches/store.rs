@@ -0,0 +1,121 @@
+//! Benchmarks for loading and snapshotting large datasets in a KVStore.
+//!
+//! Run with `cargo bench --bench store`. Every case runs in its own child process,
+//! so the reported peak resident set size (read from `/proc`, so Linux only)
+//! belongs to that case alone. The reported time is the part of the case
+//! being measured, which for the snapshot case excludes loading the keys.
+
+use miniredis::kv_store::KVStore;
+use std::{
+    env, fs,
+    process::Command,
+    time::{Duration, Instant},
+};
+
+/// The number of keys loaded by every case.
+const KEYS: usize = 1_000_000;
+
+/// A benchmark case, returning the time taken by the part it measures.
+type Case = fn() -> Duration;
+
+/// The cases, by name.
+const CASES: &[(&str, Case)] = &[
+    ("set into KVStore::new", load_into_new),
+    ("set into KVStore::with_capacity", load_into_with_capacity),
+    ("extend into KVStore::new", extend_into_new),
+    ("collect into KVStore", collect_into_store),
+    ("snapshot_handle", snapshot_handle),
+];
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    if let Some(index) = args.iter().position(|arg| arg == "--case") {
+        let name = &args[index + 1];
+        let (_, case) = CASES
+            .iter()
+            .find(|(case_name, _)| case_name == name)
+            .expect("unknown case");
+        let elapsed = case();
+        println!(
+            "{:<40} {:>10.1?} {:>14}",
+            name,
+            elapsed,
+            peak_rss().unwrap_or_else(|| "n/a".to_string())
+        );
+        return;
+    }
+
+    println!("{:<40} {:>10} {:>14}", "case", "time", "peak rss");
+    let exe = env::current_exe().expect("failed to find the benchmark binary");
+    for (name, _) in CASES {
+        let status = Command::new(&exe)
+            .args(["--case", name])
+            .status()
+            .expect("failed to run case");
+        assert!(status.success(), "case {} failed", name);
+    }
+}
+
+/// Loads the keys one set at a time into a store that starts empty.
+fn load_into_new() -> Duration {
+    let start = Instant::now();
+    load(&KVStore::new());
+    start.elapsed()
+}
+
+/// Loads the keys one set at a time into a store that is pre-sized.
+fn load_into_with_capacity() -> Duration {
+    let start = Instant::now();
+    load(&KVStore::with_capacity(KEYS));
+    start.elapsed()
+}
+
+/// Loads the keys in a single batch into a store that starts empty.
+fn extend_into_new() -> Duration {
+    let store = KVStore::new();
+    let start = Instant::now();
+    store.extend(entries()).unwrap();
+    let elapsed = start.elapsed();
+    assert_eq!(Ok(KEYS), store.len());
+    elapsed
+}
+
+/// Builds a store holding the keys, without any locking or stats.
+fn collect_into_store() -> Duration {
+    let start = Instant::now();
+    let store = entries().collect::<KVStore>();
+    let elapsed = start.elapsed();
+    assert_eq!(Ok(KEYS), store.len());
+    elapsed
+}
+
+/// Takes a snapshot of a loaded store, which is how long writers are paused.
+fn snapshot_handle() -> Duration {
+    let store = KVStore::with_capacity(KEYS);
+    load(&store);
+    let start = Instant::now();
+    let snapshot = store.snapshot_handle().unwrap();
+    let elapsed = start.elapsed();
+    assert_eq!(KEYS, snapshot.len());
+    elapsed
+}
+
+/// Loads the keys one set at a time.
+fn load(store: &KVStore) {
+    for i in 0..KEYS {
+        store.set(&format!("key:{}", i), "value").unwrap();
+    }
+}
+
+/// Generates the same keys and values as `load`.
+fn entries() -> impl Iterator<Item = (String, String)> {
+    (0..KEYS).map(|i| (format!("key:{}", i), "value".to_string()))
+}
+
+/// Reads the peak resident set size of this process.
+fn peak_rss() -> Option<String> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    Some(line["VmHWM:".len()..].trim().to_string())
+}
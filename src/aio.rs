@@ -0,0 +1,8 @@
+mod connection;
+mod server;
+
+pub use connection::Connection;
+pub use server::Server;
+
+/// How many bytes a connection makes room for before every read, like the buffer of a `BufReader`.
+const READ_SIZE: usize = 8 * 1024;
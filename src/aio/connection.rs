@@ -0,0 +1,325 @@
+use super::READ_SIZE;
+use crate::error::MiniRedisError;
+use crate::protocol;
+use crate::response::Response;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// A connection to a server, for sending commands from async code.
+///
+/// This is the async counterpart of [`crate::connection::Connection`]: it keeps one stream
+/// open, speaks RESP, and returns error replies from the server as errors.
+/// It encodes commands and decodes replies with the same code as the sync connection.
+///
+/// # Cancellation
+///
+/// Every method is cancel safe. A future that is dropped before its reply arrives,
+/// like one that lost a `tokio::select!` or hit `tokio::time::timeout`, leaves the connection
+/// in sync: the part of the request not written yet is written by the next command,
+/// and the reply is read and thrown away before the reply of the next command.
+/// Whether the server ran the command of a dropped future is not known,
+/// so dropping one that writes is only harmless if running it or not is.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use miniredis::aio::Connection;
+///
+/// # async fn run() {
+/// let mut connection = Connection::connect("127.0.0.1:6379").await.unwrap();
+/// connection.set("name", "alice").await.unwrap();
+///
+/// assert_eq!(Some("alice".to_string()), connection.get("name").await.unwrap());
+/// # }
+/// ```
+pub struct Connection {
+    address: String,
+    stream: TcpStream,
+    /// Bytes read from the stream that do not make a whole reply yet.
+    input: Vec<u8>,
+    /// Bytes of requests that are not written to the stream yet.
+    output: Vec<u8>,
+    /// How many requests were sent whose replies are not read yet.
+    unread: usize,
+}
+
+impl Connection {
+    /// Connects to a server.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address of the server to connect to,
+    ///   or several separated by commas, like a primary and its replica, to try in order.
+    ///
+    /// # Returns
+    ///
+    /// A connection to the server.
+    ///
+    /// # Errors
+    ///
+    /// If the connection fails, it will return [`MiniRedisError::StreamNotConnected`],
+    /// or [`MiniRedisError::AddressesNotConnected`] with the failure of every address
+    /// if several were given.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::aio::Connection;
+    ///
+    /// # async fn run() {
+    /// let connection = Connection::connect("127.0.0.1:6379").await.unwrap();
+    /// let failover = Connection::connect("10.0.0.1:6379,10.0.0.2:6379").await.unwrap();
+    /// # }
+    /// ```
+    pub async fn connect(address: &str) -> Result<Self, MiniRedisError> {
+        let mut addresses = address
+            .split(',')
+            .map(str::trim)
+            .filter(|address| !address.is_empty())
+            .collect::<Vec<&str>>();
+        if addresses.is_empty() {
+            addresses.push(address);
+        }
+        let mut failures = Vec::new();
+        for address in addresses {
+            match TcpStream::connect(address).await {
+                Ok(stream) => {
+                    return Ok(Self {
+                        address: address.to_string(),
+                        stream,
+                        input: Vec::new(),
+                        output: Vec::new(),
+                        unread: 0,
+                    });
+                }
+                Err(source) => failures.push((
+                    address.to_string(),
+                    MiniRedisError::StreamNotConnected {
+                        address: address.to_string(),
+                        source,
+                    },
+                )),
+            }
+        }
+        match failures.len() {
+            1 => Err(failures.remove(0).1),
+            _ => Err(MiniRedisError::AddressesNotConnected { failures }),
+        }
+    }
+
+    /// Gets the address of the server the connection is connected to.
+    ///
+    /// # Returns
+    ///
+    /// The address of the server, which of several is the one that accepted the connection.
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Checks that the server answers.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the server answered.
+    ///
+    /// # Errors
+    ///
+    /// If the command cannot be sent or answered, it will return an error.
+    pub async fn ping(&mut self) -> Result<(), MiniRedisError> {
+        self.command(&["PING"]).await.map(|_| ())
+    }
+
+    /// Gets the value of a key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to get.
+    ///
+    /// # Returns
+    ///
+    /// The value of the key, or None if the key does not exist.
+    /// Bytes that are not valid UTF-8 are replaced with U+FFFD.
+    ///
+    /// # Errors
+    ///
+    /// If the command cannot be sent or answered, or the server replies with an error,
+    /// it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::aio::Connection;
+    ///
+    /// # async fn run() {
+    /// let mut connection = Connection::connect("127.0.0.1:6379").await.unwrap();
+    ///
+    /// assert_eq!(None, connection.get("missing").await.unwrap());
+    /// # }
+    /// ```
+    pub async fn get(&mut self, key: &str) -> Result<Option<String>, MiniRedisError> {
+        Ok(self
+            .get_bytes(key)
+            .await?
+            .map(|value| String::from_utf8_lossy(&value).into_owned()))
+    }
+
+    /// Gets the value of a key as the bytes it holds.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to get.
+    ///
+    /// # Returns
+    ///
+    /// The bytes of the value of the key, or None if the key does not exist.
+    ///
+    /// # Errors
+    ///
+    /// If the command cannot be sent or answered, or the server replies with an error,
+    /// it will return an error.
+    pub async fn get_bytes(&mut self, key: &str) -> Result<Option<Vec<u8>>, MiniRedisError> {
+        match self.command(&["GET", key]).await? {
+            Response::Nil => Ok(None),
+            Response::BulkBytes(value) => Ok(Some(value)),
+            Response::Bulk(value) | Response::Simple(value) => Ok(Some(value.into_bytes())),
+            response => Ok(Some(response.to_string().into_bytes())),
+        }
+    }
+
+    /// Sets the value of a key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to set.
+    /// * `value` - The value to set the key to.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the server set the key.
+    ///
+    /// # Errors
+    ///
+    /// If the command cannot be sent or answered, or the server replies with an error,
+    /// like [`MiniRedisError::StoreFull`], it will return an error.
+    pub async fn set(&mut self, key: &str, value: &str) -> Result<(), MiniRedisError> {
+        self.command(&["SET", key, value]).await.map(|_| ())
+    }
+
+    /// Deletes a key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to delete.
+    ///
+    /// # Returns
+    ///
+    /// True if the key existed, false otherwise.
+    ///
+    /// # Errors
+    ///
+    /// If the command cannot be sent or answered, or the server replies with an error,
+    /// it will return an error.
+    pub async fn del(&mut self, key: &str) -> Result<bool, MiniRedisError> {
+        self.command(&["DEL", key])
+            .await
+            .map(|reply| matches!(reply, Response::Integer(count) if count > 0))
+    }
+
+    /// Sends any command and waits for its reply.
+    ///
+    /// # Arguments
+    ///
+    /// * `parts` - The name of the command, followed by its arguments.
+    ///
+    /// # Returns
+    ///
+    /// The reply of the server.
+    ///
+    /// # Errors
+    ///
+    /// If the command cannot be sent or answered, it will return an error.
+    /// If the server replies with an error, it will return the error the server ran into,
+    /// or [`MiniRedisError::ServerError`] if the error is not one the client knows.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::aio::Connection;
+    /// use miniredis::response::Response;
+    ///
+    /// # async fn run() {
+    /// let mut connection = Connection::connect("127.0.0.1:6379").await.unwrap();
+    ///
+    /// assert_eq!(Response::Integer(0), connection.command(&["DELPREFIX", "tmp:"]).await.unwrap());
+    /// # }
+    /// ```
+    pub async fn command(&mut self, parts: &[&str]) -> Result<Response, MiniRedisError> {
+        let request = protocol::encode_resp_command(parts)?;
+        self.output.extend_from_slice(request.as_bytes());
+        self.unread += 1;
+        self.flush().await?;
+        while self.unread > 1 {
+            self.read_reply().await?;
+            self.unread -= 1;
+        }
+        let reply = self.read_reply().await?;
+        self.unread -= 1;
+        protocol::decode_reply(reply)
+    }
+
+    /// Writes the requests that are not written yet.
+    ///
+    /// Only what the stream accepted is taken off the requests, so a dropped
+    /// future leaves the rest for the next command to write.
+    ///
+    /// # Errors
+    ///
+    /// If the stream cannot be written, it will return [`MiniRedisError::StreamNotWritable`].
+    async fn flush(&mut self) -> Result<(), MiniRedisError> {
+        while !self.output.is_empty() {
+            let written = self
+                .stream
+                .write(&self.output)
+                .await
+                .map_err(|source| MiniRedisError::StreamNotWritable { source })?;
+            if written == 0 {
+                return Err(MiniRedisError::StreamClosed);
+            }
+            self.output.drain(..written);
+        }
+        Ok(())
+    }
+
+    /// Reads the next reply, once all of it has arrived.
+    ///
+    /// Bytes are only taken off the input when a whole reply is, so a dropped
+    /// future leaves what it read for the next command.
+    ///
+    /// # Returns
+    ///
+    /// The reply, with error replies as [`Response::Error`].
+    ///
+    /// # Errors
+    ///
+    /// If the stream cannot be read, or the reply is not valid RESP, it will return
+    /// [`MiniRedisError::StreamNotReadable`], or [`MiniRedisError::StreamClosed`]
+    /// if the server closes the connection first.
+    async fn read_reply(&mut self) -> Result<Response, MiniRedisError> {
+        loop {
+            if let Some(len) = protocol::resp_frame_len(&self.input) {
+                let reply = protocol::read_resp(&mut &self.input[..len]);
+                self.input.drain(..len);
+                return reply.map_err(|source| MiniRedisError::StreamNotReadable { source });
+            }
+            self.input.reserve(READ_SIZE);
+            let read = self
+                .stream
+                .read_buf(&mut self.input)
+                .await
+                .map_err(|source| MiniRedisError::StreamNotReadable { source })?;
+            if read == 0 {
+                return Err(MiniRedisError::StreamClosed);
+            }
+        }
+    }
+}
@@ -0,0 +1,491 @@
+use super::READ_SIZE;
+use crate::command::Command;
+use crate::connection::Protocol;
+use crate::error::MiniRedisError;
+use crate::hook::ConnectionInfo;
+use crate::log::Level;
+use crate::output::OutputLimit;
+use crate::protocol;
+use crate::server::{self, ACCEPT_BACKOFF, Dispatcher, ShutdownHandle, io_error};
+use std::io::{self, BufReader, Read, Write};
+use std::time::Instant;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// A server that serves every client on a tokio task, instead of a thread of its own.
+///
+/// This is the async counterpart of [`crate::server::Server`], for many clients that are
+/// mostly idle: an idle client costs a task and its buffers, not a thread and its stack.
+/// Only the IO differs. Commands are parsed, run through the hooks, and executed
+/// by the same code, and are replied to the same way, in whichever protocol they were sent in.
+///
+/// Commands are executed on the task of their client, and no lock is held across an `.await`.
+/// A command that `CLIENT PAUSE` holds, and `WAIT`, wait on tokio's blocking pool instead,
+/// so they do not hold up the tasks of other clients. So does a replica, which is sent
+/// the writes of the server on a thread of the blocking pool once it sends `SYNC`.
+///
+/// Replies are written before more commands are read, so the replies a client leaves unread
+/// are those of its last batch. A client whose batch is over the hard limit of
+/// `--client-output-buffer-limit` is dropped, and so is one that does not read
+/// a batch over the soft limit within its time.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use miniredis::aio::Server;
+///
+/// # async fn run() {
+/// let server = Server::new("127.0.0.1:6379");
+/// server.run().await.unwrap();
+/// # }
+/// ```
+pub struct Server {
+    server: server::Server,
+}
+
+/// A request a client sent, all there.
+struct Request {
+    /// The command, or None if the request holds none, like a blank line.
+    command: Option<Command>,
+    /// The protocol the request was sent in, which the reply is written in too.
+    protocol: Protocol,
+}
+
+/// A request that ends the connection, because what comes after it cannot be split into requests.
+enum Invalid {
+    /// A RESP frame that is not a command, which gets an error reply.
+    Frame(io::Error),
+    /// A line that is not UTF-8.
+    Line(io::Error),
+}
+
+impl From<server::Server> for Server {
+    /// Serves the clients of a server built with [`crate::server::Server::builder`] on tasks.
+    ///
+    /// # Arguments
+    ///
+    /// * `server` - The server, with its storage, hooks, and other options.
+    ///
+    /// # Returns
+    ///
+    /// The async server.
+    fn from(server: server::Server) -> Self {
+        Self { server }
+    }
+}
+
+impl Server {
+    /// Creates a new server.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address to listen on.
+    ///
+    /// # Returns
+    ///
+    /// A new server.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::aio::Server;
+    ///
+    /// let server = Server::new("127.0.0.1:6379");
+    /// ```
+    pub fn new(address: &str) -> Self {
+        server::Server::new(address).into()
+    }
+
+    /// Creates a new server from command line arguments,
+    /// which are the same as those of [`crate::server::Server::from_args`].
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - The command line arguments.
+    ///
+    /// # Returns
+    ///
+    /// A new server.
+    ///
+    /// # Errors
+    ///
+    /// If an option is unknown or misses its value, or the storage cannot be opened,
+    /// it will return an error.
+    pub fn from_args(args: &[String]) -> Result<Self, MiniRedisError> {
+        server::Server::from_args(args).map(Self::from)
+    }
+
+    /// Gets a handle that shuts the server down from another thread or task.
+    ///
+    /// # Returns
+    ///
+    /// A handle to shut the server down with.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.server.shutdown_handle()
+    }
+
+    /// Runs the server until a [`ShutdownHandle`] of it shuts it down.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the server was started successfully.
+    ///
+    /// # Errors
+    ///
+    /// If the server fails to bind to the address, it will return an error.
+    pub async fn run(&self) -> Result<(), MiniRedisError> {
+        let listener = TcpListener::bind(&self.server.address)
+            .await
+            .map_err(|source| MiniRedisError::AddressNotBound { source })?;
+        println!("MiniRedis is running on {}", self.server.address);
+        self.serve(listener).await
+    }
+
+    /// Runs the server on a task of its own, like [`Server::run`] does on the calling task.
+    ///
+    /// # Returns
+    ///
+    /// The task, which ends with what [`Server::run`] returned.
+    ///
+    /// # Panics
+    ///
+    /// If it is not called from inside a tokio runtime, it will panic.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::aio::Server;
+    ///
+    /// # async fn run() {
+    /// let server = Server::new("127.0.0.1:6379");
+    /// let shutdown = server.shutdown_handle();
+    /// let running = server.spawn();
+    ///
+    /// shutdown.shutdown();
+    /// running.await.unwrap().unwrap();
+    /// # }
+    /// ```
+    pub fn spawn(self) -> JoinHandle<Result<(), MiniRedisError>> {
+        tokio::spawn(async move { self.run().await })
+    }
+
+    /// Serves clients on a listener that is already bound, like [`Server::run`] does
+    /// on the address of the server.
+    ///
+    /// Shutting down closes the connections of the clients, and waits for the commands
+    /// they are executing to finish, before it returns.
+    ///
+    /// # Arguments
+    ///
+    /// * `listener` - The listener to accept clients on.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the server ran until it was shut down.
+    ///
+    /// # Errors
+    ///
+    /// If the address of the listener cannot be read, it will return an error.
+    pub async fn serve(&self, listener: TcpListener) -> Result<(), MiniRedisError> {
+        let server = &self.server;
+        let local_address = listener.local_addr().map_err(|source| MiniRedisError::Io {
+            operation: "read the address of the listener",
+            address: None,
+            source,
+        })?;
+        server.shutdown.listening_on(local_address);
+
+        let (queue, executor) = server.start_executor();
+        let follower = server.follow();
+        let mut clients: Vec<JoinHandle<()>> = Vec::new();
+        let mut next_id = 0;
+        while !server.shutdown.is_shutdown() {
+            let (stream, address) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(source) => {
+                    let error = MiniRedisError::Io {
+                        operation: "accept a connection",
+                        address: Some(server.address.clone()),
+                        source,
+                    };
+                    server.logger.log(Level::Warning, &error.to_string());
+                    // Give a server out of file descriptors the time to close some.
+                    tokio::time::sleep(ACCEPT_BACKOFF).await;
+                    continue;
+                }
+            };
+            if server.shutdown.is_shutdown() {
+                break;
+            }
+            next_id += 1;
+            let info = ConnectionInfo {
+                id: next_id,
+                address: Some(address.to_string()),
+            };
+            let dispatcher = server.accept(&info, &queue);
+            let engine = server.engine.clone();
+            let logger = server.logger.clone();
+            let limit = server.output_limit;
+            clients.retain(|client| !client.is_finished());
+            clients.push(tokio::spawn(async move {
+                let start = Instant::now();
+                let mut served = 0;
+                let result = handle_client(stream, dispatcher, &info, limit, &mut served).await;
+                server::Server::log_closed(&engine, logger.as_ref(), &info, result, start, served);
+            }));
+        }
+
+        // Dropping the task of a client closes its stream.
+        for client in &clients {
+            client.abort();
+        }
+        // A command held by CLIENT PAUSE or WAIT only lets its thread of the blocking pool go once it is let go.
+        server.engine.unpause();
+        server.engine.replication().close();
+        for client in clients {
+            let _ = client.await;
+        }
+        if let Some(follower) = follower {
+            let _ = tokio::task::spawn_blocking(move || follower.stop()).await;
+        }
+        // The executor ends once the last sender of commands is gone.
+        drop(queue);
+        if let Some(executor) = executor {
+            let _ = tokio::task::spawn_blocking(move || executor.join()).await;
+        }
+        Ok(())
+    }
+}
+
+/// Answers the commands of a client until it stops sending them.
+///
+/// Every command the client sent so far is taken off what was read, executed,
+/// and replied to in one write, so a pipelined batch gets its replies together.
+/// A command that arrives in pieces is executed once all of it is there,
+/// and one cut off by the client going away is not executed at all.
+///
+/// Commands are executed on the task, since the lock of the store is only held for as long
+/// as one takes. A command that `CLIENT PAUSE` holds, and `WAIT`, are executed on tokio's
+/// blocking pool instead, so their wait does not hold up the tasks of other clients.
+///
+/// A client that sends `SYNC` in RESP is a replica, and is handed to [`serve_replica`].
+///
+/// # Arguments
+///
+/// * `stream` - The client stream.
+/// * `dispatcher` - The engine executing the commands, with the hooks of the server.
+/// * `client` - What the server knows about the client.
+/// * `limit` - How many bytes of replies the client may leave unread, if there is a limit.
+/// * `served` - Counts the commands the client got replies to.
+///
+/// # Returns
+///
+/// A result indicating whether the client was handled successfully.
+///
+/// # Errors
+///
+/// If the stream cannot be read from, or written to, it will return [`MiniRedisError::Io`]
+/// with what failed and the address of the client,
+/// and if the client is over the limit, [`MiniRedisError::OutputBufferExceeded`].
+async fn handle_client(
+    mut stream: TcpStream,
+    dispatcher: Dispatcher,
+    client: &ConnectionInfo,
+    limit: Option<OutputLimit>,
+    served: &mut u64,
+) -> Result<(), MiniRedisError> {
+    let mut input = Vec::new();
+    loop {
+        let (requests, invalid) = take_requests(&mut input);
+        let mut replies = Vec::new();
+        for request in requests {
+            let Some(command) = request.command else {
+                continue;
+            };
+            if request.protocol == Protocol::Resp && command.name == "SYNC" {
+                if !replies.is_empty() {
+                    write_replies(&mut stream, client, &replies, limit).await?;
+                }
+                return serve_replica(stream, dispatcher, client, input).await;
+            }
+            let response = if dispatcher.may_wait(&command) {
+                let dispatcher = dispatcher.clone();
+                let info = client.clone();
+                tokio::task::spawn_blocking(move || dispatcher.execute(&info, command))
+                    .await
+                    .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+            } else {
+                dispatcher.execute(client, command)
+            };
+            match request.protocol {
+                Protocol::Resp => replies.extend(protocol::encode_resp_reply(&response)),
+                Protocol::Inline => replies.extend((response.to_string() + "\n").into_bytes()),
+            }
+            *served += 1;
+            let pending = replies.len();
+            if let Some(limit) = limit.filter(|limit| limit.hard > 0 && pending > limit.hard) {
+                return Err(MiniRedisError::OutputBufferExceeded {
+                    pending,
+                    limit: limit.hard,
+                });
+            }
+        }
+        if !replies.is_empty() {
+            write_replies(&mut stream, client, &replies, limit).await?;
+        }
+        match invalid {
+            Some(Invalid::Frame(e)) => {
+                let reply = format!("-ERR {}\r\n", e);
+                return write_replies(&mut stream, client, reply.as_bytes(), limit).await;
+            }
+            Some(Invalid::Line(e)) => return Err(io_error(client, "read the command")(e)),
+            None => {}
+        }
+        input.reserve(READ_SIZE);
+        if stream
+            .read_buf(&mut input)
+            .await
+            .map_err(io_error(client, "read the command"))?
+            == 0
+        {
+            return Ok(());
+        }
+    }
+}
+
+/// Serves a client that sent `SYNC` as a replica, on a thread of tokio's blocking pool,
+/// until it goes away or the server shuts down.
+///
+/// # Arguments
+///
+/// * `stream` - The stream of the replica.
+/// * `dispatcher` - The engine of the server.
+/// * `client` - What the server knows about the replica.
+/// * `input` - What the replica sent after `SYNC`, that was already read.
+///
+/// # Returns
+///
+/// A result indicating whether the replica was served until it went away.
+///
+/// # Errors
+///
+/// If the stream cannot be handed to the thread, the snapshot cannot be taken,
+/// or the replica cannot be written to, it will return the error.
+async fn serve_replica(
+    stream: TcpStream,
+    dispatcher: Dispatcher,
+    client: &ConnectionInfo,
+    input: Vec<u8>,
+) -> Result<(), MiniRedisError> {
+    let stream = stream
+        .into_std()
+        .and_then(|stream| stream.set_nonblocking(false).map(|()| stream))
+        .map_err(io_error(client, "hand the stream to a thread"))?;
+    let info = client.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut writer = stream
+            .try_clone()
+            .map_err(io_error(&info, "clone the stream"))?;
+        let reader = stream
+            .try_clone()
+            .map_err(io_error(&info, "clone the stream"))?;
+        let acks = BufReader::new(Read::chain(io::Cursor::new(input), reader));
+        dispatcher.serve_replica(acks, &stream, |frames| {
+            writer
+                .write_all(frames)
+                .map_err(io_error(&info, "write the reply"))
+        })
+    })
+    .await
+    .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+}
+
+/// Takes every request that is all there off the front of what a client sent.
+///
+/// # Arguments
+///
+/// * `input` - What was read from the client, which keeps the part of a request that is not all there.
+///
+/// # Returns
+///
+/// The requests, in order, and the request after them if it ends the connection.
+fn take_requests(input: &mut Vec<u8>) -> (Vec<Request>, Option<Invalid>) {
+    let mut requests = Vec::new();
+    let mut pos = 0;
+    let invalid = loop {
+        let rest = &input[pos..];
+        let Some(&first_byte) = rest.first() else {
+            break None;
+        };
+        if first_byte == protocol::RESP_REQUEST {
+            let Some(len) = protocol::resp_command_len(rest) else {
+                break None;
+            };
+            pos += len;
+            match protocol::read_resp_command(&mut &rest[..len]) {
+                Ok(command) => {
+                    let sync = command
+                        .as_ref()
+                        .is_some_and(|command| command.name == "SYNC");
+                    requests.push(Request {
+                        command,
+                        protocol: Protocol::Resp,
+                    });
+                    // What a replica sends after SYNC is not requests, but its acknowledgements.
+                    if sync {
+                        break None;
+                    }
+                }
+                Err(e) => break Some(Invalid::Frame(e)),
+            }
+        } else {
+            let Some(end) = rest.iter().position(|&b| b == b'\n') else {
+                break None;
+            };
+            pos += end + 1;
+            match std::str::from_utf8(&rest[..=end]) {
+                Ok(line) => requests.push(Request {
+                    command: Command::parse(line),
+                    protocol: Protocol::Inline,
+                }),
+                Err(e) => break Some(Invalid::Line(io::Error::new(io::ErrorKind::InvalidData, e))),
+            }
+        }
+    };
+    input.drain(..pos);
+    (requests, invalid)
+}
+
+/// Writes the replies to a batch of requests.
+///
+/// # Arguments
+///
+/// * `stream` - The client stream.
+/// * `client` - What the server knows about the client.
+/// * `replies` - The replies.
+/// * `limit` - How many bytes of replies the client may leave unread, if there is a limit.
+///
+/// # Errors
+///
+/// If the replies cannot be written, it will return [`MiniRedisError::Io`], and if they are
+/// over the soft limit and the client does not read them in time,
+/// [`MiniRedisError::OutputBufferExceeded`].
+async fn write_replies(
+    stream: &mut TcpStream,
+    client: &ConnectionInfo,
+    replies: &[u8],
+    limit: Option<OutputLimit>,
+) -> Result<(), MiniRedisError> {
+    let pending = replies.len();
+    let written = match limit.filter(|limit| limit.soft > 0 && pending > limit.soft) {
+        None => stream.write_all(replies).await,
+        Some(limit) => tokio::time::timeout(limit.soft_duration, stream.write_all(replies))
+            .await
+            .map_err(|_| MiniRedisError::OutputBufferExceeded {
+                pending,
+                limit: limit.soft,
+            })?,
+    };
+    written.map_err(io_error(client, "write the reply"))
+}
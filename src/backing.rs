@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+
+/// Loads the value of a key the store is missing, or None if the backing store lacks it too.
+pub(crate) type Loader = dyn Fn(&str) -> Option<String> + Send + Sync;
+
+/// Writes a key that was set, with its value, or deleted, with None, to the backing store.
+pub(crate) type Writer = dyn Fn(&str, Option<&str>) + Send + Sync;
+
+/// A load of a key in progress, that other threads missing the key wait for.
+#[derive(Default)]
+struct Flight {
+    done: Mutex<bool>,
+    landed: Condvar,
+}
+
+/// The store a [`crate::kv_store::KVStore`] reads through to and writes through to.
+///
+/// The callbacks run without the lock of the store held, so they are free to be slow,
+/// and a callback that panics is taken for one that found nothing, leaving the store usable.
+#[derive(Default)]
+pub(crate) struct Backing {
+    loader: Option<Box<Loader>>,
+    writer: Option<Box<Writer>>,
+    /// The keys being loaded.
+    flights: Mutex<HashMap<String, Arc<Flight>>>,
+    /// The next write to hand out, taken under the lock of the store,
+    /// so the writes are numbered in the order they were made.
+    next_ticket: AtomicU64,
+    /// The write whose turn it is to go to the writer.
+    turn: Mutex<u64>,
+    turn_passed: Condvar,
+}
+
+impl Backing {
+    /// Creates a backing store from its callbacks.
+    ///
+    /// # Arguments
+    ///
+    /// * `loader` - What to call on a miss, if anything.
+    /// * `writer` - What to call after a write, if anything.
+    pub(crate) fn new(loader: Option<Box<Loader>>, writer: Option<Box<Writer>>) -> Self {
+        Backing {
+            loader,
+            writer,
+            ..Backing::default()
+        }
+    }
+
+    /// Checks whether misses are loaded.
+    pub(crate) fn loads(&self) -> bool {
+        self.loader.is_some()
+    }
+
+    /// Checks whether writes go through to the backing store.
+    pub(crate) fn writes(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    /// Loads a key once however many threads miss it at the same time.
+    ///
+    /// The first thread to miss the key leads, and the others wait for it to land,
+    /// and then follow, reading what it stored.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key that was missed.
+    /// * `lead` - Stores the value the loader found, if any, and returns what the store holds.
+    /// * `follow` - Reads what the store holds once the leader landed.
+    ///
+    /// # Returns
+    ///
+    /// What `lead` or `follow` returned.
+    pub(crate) fn load<T>(
+        &self,
+        key: &str,
+        lead: impl FnOnce(Option<String>) -> T,
+        follow: impl FnOnce() -> T,
+    ) -> T {
+        let Some(loader) = &self.loader else {
+            return follow();
+        };
+        let (flight, leading) = {
+            let mut flights = self.flights.lock().unwrap_or_else(PoisonError::into_inner);
+            match flights.get(key) {
+                Some(flight) => (Arc::clone(flight), false),
+                None => {
+                    let flight = Arc::new(Flight::default());
+                    flights.insert(key.to_string(), Arc::clone(&flight));
+                    (flight, true)
+                }
+            }
+        };
+
+        if !leading {
+            let mut done = flight.done.lock().unwrap_or_else(PoisonError::into_inner);
+            while !*done {
+                done = flight
+                    .landed
+                    .wait(done)
+                    .unwrap_or_else(PoisonError::into_inner);
+            }
+            return follow();
+        }
+
+        let loaded = panic::catch_unwind(AssertUnwindSafe(|| loader(key))).unwrap_or(None);
+        let result = lead(loaded);
+        self.flights
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(key);
+        *flight.done.lock().unwrap_or_else(PoisonError::into_inner) = true;
+        flight.landed.notify_all();
+        result
+    }
+
+    /// Hands out the number of a write, while the store is still locked after making it.
+    ///
+    /// # Returns
+    ///
+    /// The number of the write, to pass to [`Backing::write`].
+    pub(crate) fn ticket(&self) -> u64 {
+        self.next_ticket.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Writes a change through to the backing store, after every change made before it.
+    ///
+    /// # Arguments
+    ///
+    /// * `ticket` - The number of the write, from [`Backing::ticket`].
+    /// * `key` - The key that changed.
+    /// * `value` - The new value of the key, or None if it was deleted.
+    pub(crate) fn write(&self, ticket: u64, key: &str, value: Option<&str>) {
+        let mut turn = self.turn.lock().unwrap_or_else(PoisonError::into_inner);
+        while *turn != ticket {
+            turn = self
+                .turn_passed
+                .wait(turn)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        if let Some(writer) = &self.writer {
+            // A writer that panics loses this write, and not the ones after it.
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| writer(key, value)));
+        }
+        *turn += 1;
+        self.turn_passed.notify_all();
+    }
+}
@@ -0,0 +1,815 @@
+use crate::connection::Connection;
+use crate::error::MiniRedisError;
+use crate::response::Response;
+use crate::rng::Rng;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A load generator for a server, like `redis-benchmark`.
+///
+/// For every selected command, the benchmark opens a number of connections at once,
+/// sends a total number of requests spread over them, and reports the throughput,
+/// the latency percentiles, and the number of error replies.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use miniredis::benchmark::Benchmark;
+///
+/// let args = ["miniredis-benchmark", "-c", "10", "-n", "1000", "-t", "set,get"]
+///     .map(str::to_string);
+/// let benchmark = Benchmark::from_args(&args).unwrap();
+///
+/// for report in benchmark.run().unwrap() {
+///     println!("{}: {:.0} requests per second", report.command, report.throughput());
+/// }
+/// ```
+pub struct Benchmark {
+    address: String,
+    clients: usize,
+    requests: u64,
+    commands: Vec<BenchCommand>,
+    data_size: usize,
+    pipeline: usize,
+    keyspace: Option<u64>,
+}
+
+/// A command the benchmark can send.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BenchCommand {
+    Ping,
+    Set,
+    Get,
+}
+
+impl BenchCommand {
+    /// Parses the name of a command, as given to `-t`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the command, in any case.
+    ///
+    /// # Returns
+    ///
+    /// The command, or None if the benchmark cannot send it.
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "ping" => Some(BenchCommand::Ping),
+            "set" => Some(BenchCommand::Set),
+            "get" => Some(BenchCommand::Get),
+            _ => None,
+        }
+    }
+
+    /// Gets the name of the command, as the server knows it.
+    ///
+    /// # Returns
+    ///
+    /// The name of the command, in uppercase.
+    fn name(self) -> &'static str {
+        match self {
+            BenchCommand::Ping => "PING",
+            BenchCommand::Set => "SET",
+            BenchCommand::Get => "GET",
+        }
+    }
+}
+
+impl Benchmark {
+    /// Creates a benchmark from command line arguments.
+    ///
+    /// The options are:
+    ///
+    /// * `--host <HOST>` and `--port <PORT>`, the server to benchmark [default: 127.0.0.1:6379].
+    /// * `-c <CLIENTS>`, the number of connections to open at once [default: 50].
+    /// * `-n <REQUESTS>`, the total number of requests per command [default: 100000].
+    /// * `-t <COMMANDS>`, the commands to send, separated by commas [default: set,get].
+    /// * `-d <BYTES>`, the size of the values to set [default: 3].
+    /// * `-P <REQUESTS>`, how many requests to send at once on a connection [default: 1].
+    /// * `-r <KEYSPACE>`, use random keys out of this many, instead of a single key.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - The command line arguments.
+    ///
+    /// # Returns
+    ///
+    /// A new benchmark.
+    ///
+    /// # Errors
+    ///
+    /// If an option is unknown, is missing its value, or has an invalid value,
+    /// it will return [`MiniRedisError::InvalidArguments`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::benchmark::Benchmark;
+    ///
+    /// let args = ["miniredis-benchmark", "--port", "7000", "-r", "100000"].map(str::to_string);
+    ///
+    /// assert!(Benchmark::from_args(&args).is_ok());
+    /// ```
+    pub fn from_args(args: &[String]) -> Result<Self, MiniRedisError> {
+        let mut host = "127.0.0.1".to_string();
+        let mut port = 6379u16;
+        let mut benchmark = Self {
+            address: String::new(),
+            clients: 50,
+            requests: 100_000,
+            commands: vec![BenchCommand::Set, BenchCommand::Get],
+            data_size: 3,
+            pipeline: 1,
+            keyspace: None,
+        };
+
+        let mut args = args.iter().skip(1);
+        while let Some(option) = args.next() {
+            let value = args.next();
+            match option.as_str() {
+                "--host" => host = value.ok_or_else(|| invalid_option(option, value))?.clone(),
+                "--port" => port = parse_positive(option, value)?,
+                "-c" => benchmark.clients = parse_positive(option, value)?,
+                "-n" => benchmark.requests = parse_positive(option, value)?,
+                "-t" => benchmark.commands = parse_commands(option, value)?,
+                "-d" => benchmark.data_size = parse_number(option, value)?,
+                "-P" => benchmark.pipeline = parse_positive(option, value)?,
+                "-r" => benchmark.keyspace = Some(parse_positive(option, value)?),
+                _ => {
+                    return Err(MiniRedisError::InvalidArguments {
+                        arguments: vec![option.clone()],
+                    });
+                }
+            }
+        }
+        benchmark.address = format!("{}:{}", host, port);
+        Ok(benchmark)
+    }
+
+    /// Runs the benchmark, printing a report after every command.
+    ///
+    /// The commands run one after another, so `set,get` fills the keys that `get` reads.
+    ///
+    /// # Returns
+    ///
+    /// The report of every command, in the order they ran.
+    ///
+    /// # Errors
+    ///
+    /// If a connection fails, or its stream fails during the benchmark, it will return an error.
+    /// Error replies from the server do not stop the benchmark, they are counted instead.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::benchmark::Benchmark;
+    ///
+    /// let benchmark = Benchmark::from_args(&["miniredis-benchmark".to_string()]).unwrap();
+    /// benchmark.run().unwrap();
+    /// ```
+    pub fn run(&self) -> Result<Vec<Report>, MiniRedisError> {
+        let mut reports = Vec::new();
+        for &command in &self.commands {
+            let report = self.run_command(command)?;
+            println!("{}", report);
+            reports.push(report);
+        }
+        Ok(reports)
+    }
+
+    /// Prints the help message.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::benchmark::Benchmark;
+    ///
+    /// Benchmark::print_help();
+    /// ```
+    pub fn print_help() {
+        println!("MiniRedis Benchmark");
+        println!();
+        println!("Sends many requests to a MiniRedis server over several connections at once,");
+        println!("and reports the throughput and latency of every command.");
+        println!();
+        println!("USAGE:");
+        println!("    miniredis-benchmark [OPTIONS]");
+        println!();
+        println!("OPTIONS:");
+        println!("    --host <HOST>       The host of the server [default: 127.0.0.1]");
+        println!("    --port <PORT>       The port of the server [default: 6379]");
+        println!("    -c <CLIENTS>        The number of connections to open at once [default: 50]");
+        println!(
+            "    -n <REQUESTS>       The total number of requests per command [default: 100000]"
+        );
+        println!(
+            "    -t <COMMANDS>       The commands to send, out of ping, set, and get [default: set,get]"
+        );
+        println!("    -d <BYTES>          The size of the values to set [default: 3]");
+        println!(
+            "    -P <REQUESTS>       How many requests to send at once on a connection [default: 1]"
+        );
+        println!(
+            "    -r <KEYSPACE>       Use random keys out of this many, instead of a single key"
+        );
+        println!();
+        println!("EXAMPLES:");
+        println!("    miniredis-benchmark -c 50 -n 100000 -t set,get -d 64");
+        println!("    miniredis-benchmark --port 7000 -t get -r 100000 -P 16");
+    }
+
+    /// Runs the benchmark of one command.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command to send.
+    ///
+    /// # Returns
+    ///
+    /// The report of the command.
+    ///
+    /// # Errors
+    ///
+    /// If a connection fails, it will return an error.
+    fn run_command(&self, command: BenchCommand) -> Result<Report, MiniRedisError> {
+        let clients = self.clients.min(self.requests as usize).max(1);
+        let share = self.requests / clients as u64;
+        let extra = self.requests % clients as u64;
+
+        let connections = (0..clients)
+            .map(|_| Connection::connect(&self.address))
+            .collect::<Result<Vec<Connection>, MiniRedisError>>()?;
+
+        let start = Instant::now();
+        let results = thread::scope(|scope| {
+            let workers = connections
+                .into_iter()
+                .enumerate()
+                .map(|(i, connection)| {
+                    let requests = share + u64::from((i as u64) < extra);
+                    scope.spawn(move || self.run_client(command, connection, requests))
+                })
+                .collect::<Vec<_>>();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().expect("A benchmark client panicked"))
+                .collect::<Vec<Result<(Histogram, u64), MiniRedisError>>>()
+        });
+        let elapsed = start.elapsed();
+
+        let mut latencies = Histogram::new();
+        let mut errors = 0;
+        for result in results {
+            let (histogram, failed) = result?;
+            latencies.merge(&histogram);
+            errors += failed;
+        }
+        Ok(Report {
+            command: command.name().to_string(),
+            requests: self.requests,
+            errors,
+            elapsed,
+            clients,
+            data_size: self.data_size,
+            latencies,
+        })
+    }
+
+    /// Sends the requests of one connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command to send.
+    /// * `connection` - The connection to send the requests on.
+    /// * `requests` - How many requests to send.
+    ///
+    /// # Returns
+    ///
+    /// The latencies of the requests, and how many of them got an error reply.
+    ///
+    /// # Errors
+    ///
+    /// If the stream of the connection fails, it will return an error.
+    fn run_client(
+        &self,
+        command: BenchCommand,
+        mut connection: Connection,
+        requests: u64,
+    ) -> Result<(Histogram, u64), MiniRedisError> {
+        let mut rng = Rng::new();
+        let value = random_value(&mut rng, self.data_size);
+        let mut latencies = Histogram::new();
+        let mut errors = 0;
+
+        let mut sent = 0;
+        while sent < requests {
+            let batch = (requests - sent).min(self.pipeline as u64);
+            let lines = (0..batch)
+                .map(|_| self.request(command, &mut rng, &value))
+                .collect::<Vec<String>>();
+            let lines = lines.iter().map(String::as_str).collect::<Vec<&str>>();
+
+            let start = Instant::now();
+            let responses = connection.send_lines(&lines)?;
+            let latency = start.elapsed();
+
+            for response in responses {
+                latencies.record(latency);
+                errors += u64::from(matches!(response, Response::Error(_)));
+            }
+            sent += batch;
+        }
+        Ok((latencies, errors))
+    }
+
+    /// Builds the line of one request.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command to send.
+    /// * `rng` - The generator picking random keys.
+    /// * `value` - The value to set.
+    ///
+    /// # Returns
+    ///
+    /// The line of the request, without a line ending.
+    fn request(&self, command: BenchCommand, rng: &mut Rng, value: &str) -> String {
+        let key = match self.keyspace {
+            Some(keyspace) => format!("key:{:012}", rng.next_u64() % keyspace),
+            None => "key".to_string(),
+        };
+        match command {
+            BenchCommand::Ping => "PING".to_string(),
+            BenchCommand::Set => format!("SET {} {}", key, value),
+            BenchCommand::Get => format!("GET {}", key),
+        }
+    }
+}
+
+/// The results of benchmarking one command.
+pub struct Report {
+    /// The name of the command.
+    pub command: String,
+    /// How many requests were sent.
+    pub requests: u64,
+    /// How many requests got an error reply.
+    pub errors: u64,
+    /// How long sending every request took.
+    pub elapsed: Duration,
+    clients: usize,
+    data_size: usize,
+    latencies: Histogram,
+}
+
+impl Report {
+    /// Gets the throughput of the command.
+    ///
+    /// # Returns
+    ///
+    /// The number of requests per second.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::benchmark::Benchmark;
+    ///
+    /// let benchmark = Benchmark::from_args(&["miniredis-benchmark".to_string()]).unwrap();
+    /// let reports = benchmark.run().unwrap();
+    ///
+    /// println!("{:.0} requests per second", reports[0].throughput());
+    /// ```
+    pub fn throughput(&self) -> f64 {
+        self.requests as f64 / self.elapsed.as_secs_f64()
+    }
+
+    /// Gets a percentile of the latencies of the requests.
+    ///
+    /// Latencies are recorded in buckets about 3% wide, so the result is that close.
+    ///
+    /// # Arguments
+    ///
+    /// * `percentile` - The percentile, from 0 to 100, like 99 for the p99 latency.
+    ///
+    /// # Returns
+    ///
+    /// The latency that this share of the requests took at most.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::benchmark::Benchmark;
+    ///
+    /// let benchmark = Benchmark::from_args(&["miniredis-benchmark".to_string()]).unwrap();
+    /// let reports = benchmark.run().unwrap();
+    ///
+    /// println!("p99: {:?}", reports[0].latency(99.0));
+    /// ```
+    pub fn latency(&self, percentile: f64) -> Duration {
+        self.latencies.percentile(percentile)
+    }
+}
+
+impl std::fmt::Display for Report {
+    /// Formats the report the way the benchmark prints it.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - The formatter to write the report to.
+    ///
+    /// # Errors
+    ///
+    /// If the report cannot be formatted, it will return an error.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let millis = |percentile| self.latency(percentile).as_secs_f64() * 1000.0;
+        writeln!(f, "====== {} ======", self.command)?;
+        writeln!(
+            f,
+            "  {} requests completed in {:.2} seconds",
+            self.requests,
+            self.elapsed.as_secs_f64()
+        )?;
+        writeln!(f, "  {} parallel clients", self.clients)?;
+        writeln!(f, "  {} bytes payload", self.data_size)?;
+        writeln!(f, "  {} errors", self.errors)?;
+        writeln!(f, "  {:.2} requests per second", self.throughput())?;
+        write!(
+            f,
+            "  latency: p50={:.3} ms, p95={:.3} ms, p99={:.3} ms",
+            millis(50.0),
+            millis(95.0),
+            millis(99.0)
+        )
+    }
+}
+
+/// The number of buckets per power of two in a [`Histogram`].
+const SUB_BUCKETS: u64 = 32;
+
+/// The number of buckets in a [`Histogram`], enough for every `u64` of microseconds.
+const BUCKETS: usize = (60 * SUB_BUCKETS) as usize;
+
+/// Counts latencies in buckets, with a fixed relative precision.
+///
+/// Latencies under 32 microseconds get a bucket each. Above that, every power of two
+/// is split into 32 buckets, so a bucket is never more than about 3% wide.
+struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+}
+
+impl Histogram {
+    /// Creates an empty histogram.
+    fn new() -> Self {
+        Self {
+            counts: vec![0; BUCKETS],
+            total: 0,
+        }
+    }
+
+    /// Records a latency.
+    ///
+    /// # Arguments
+    ///
+    /// * `latency` - The latency to record.
+    fn record(&mut self, latency: Duration) {
+        let micros = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+        self.counts[bucket(micros)] += 1;
+        self.total += 1;
+    }
+
+    /// Adds the latencies of another histogram to this one.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The other histogram.
+    fn merge(&mut self, other: &Histogram) {
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.total += other.total;
+    }
+
+    /// Gets a percentile of the recorded latencies.
+    ///
+    /// # Arguments
+    ///
+    /// * `percentile` - The percentile, from 0 to 100.
+    ///
+    /// # Returns
+    ///
+    /// The lowest latency of the bucket holding the percentile, or zero if nothing was recorded.
+    fn percentile(&self, percentile: f64) -> Duration {
+        let rank = ((percentile / 100.0) * self.total as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Duration::from_micros(lowest(index));
+            }
+        }
+        Duration::ZERO
+    }
+}
+
+/// Finds the bucket of a latency.
+///
+/// # Arguments
+///
+/// * `micros` - The latency, in microseconds.
+///
+/// # Returns
+///
+/// The index of the bucket.
+fn bucket(micros: u64) -> usize {
+    if micros < SUB_BUCKETS {
+        return micros as usize;
+    }
+    let exponent = u64::from(63 - micros.leading_zeros());
+    let sub_bucket = (micros >> (exponent - 5)) & (SUB_BUCKETS - 1);
+    ((exponent - 4) * SUB_BUCKETS + sub_bucket) as usize
+}
+
+/// Finds the lowest latency of a bucket.
+///
+/// # Arguments
+///
+/// * `index` - The index of the bucket.
+///
+/// # Returns
+///
+/// The lowest latency in the bucket, in microseconds.
+fn lowest(index: usize) -> u64 {
+    let index = index as u64;
+    if index < SUB_BUCKETS {
+        return index;
+    }
+    let exponent = index / SUB_BUCKETS + 4;
+    (SUB_BUCKETS + index % SUB_BUCKETS) << (exponent - 5)
+}
+
+/// Generates a random value of letters and digits.
+///
+/// # Arguments
+///
+/// * `rng` - The generator to use.
+/// * `size` - The length of the value, in bytes.
+///
+/// # Returns
+///
+/// The value.
+fn random_value(rng: &mut Rng, size: usize) -> String {
+    const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+    (0..size)
+        .map(|_| ALPHABET[rng.below(ALPHABET.len())] as char)
+        .collect()
+}
+
+/// Builds the error for an option with a missing or invalid value.
+///
+/// # Arguments
+///
+/// * `option` - The name of the option.
+/// * `value` - The value following the option, if any.
+///
+/// # Returns
+///
+/// A [`MiniRedisError::InvalidArguments`] holding the option and its value.
+fn invalid_option(option: &str, value: Option<&String>) -> MiniRedisError {
+    MiniRedisError::InvalidArguments {
+        arguments: [Some(option.to_string()), value.cloned()]
+            .into_iter()
+            .flatten()
+            .collect(),
+    }
+}
+
+/// Parses the value of an option that takes a number.
+///
+/// # Arguments
+///
+/// * `option` - The name of the option, for the error.
+/// * `value` - The value following the option, if any.
+///
+/// # Returns
+///
+/// The number.
+///
+/// # Errors
+///
+/// If the value is missing or not a number, it will return an error.
+fn parse_number<T: FromStr>(option: &str, value: Option<&String>) -> Result<T, MiniRedisError> {
+    value
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| invalid_option(option, value))
+}
+
+/// Parses the value of an option that takes a number above zero.
+///
+/// # Arguments
+///
+/// * `option` - The name of the option, for the error.
+/// * `value` - The value following the option, if any.
+///
+/// # Returns
+///
+/// The number.
+///
+/// # Errors
+///
+/// If the value is missing, not a number, or zero, it will return an error.
+fn parse_positive<T: FromStr + Default + PartialEq>(
+    option: &str,
+    value: Option<&String>,
+) -> Result<T, MiniRedisError> {
+    match parse_number(option, value)? {
+        number if number == T::default() => Err(invalid_option(option, value)),
+        number => Ok(number),
+    }
+}
+
+/// Parses the commands given to `-t`.
+///
+/// # Arguments
+///
+/// * `option` - The name of the option, for the error.
+/// * `value` - The value following the option, if any.
+///
+/// # Returns
+///
+/// The commands, in the order given.
+///
+/// # Errors
+///
+/// If the value is missing or names a command the benchmark cannot send, it will return an error.
+fn parse_commands(
+    option: &str,
+    value: Option<&String>,
+) -> Result<Vec<BenchCommand>, MiniRedisError> {
+    value
+        .ok_or_else(|| invalid_option(option, value))?
+        .split(',')
+        .map(|name| BenchCommand::parse(name.trim()).ok_or_else(|| invalid_option(option, value)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        ["miniredis-benchmark"]
+            .iter()
+            .chain(args)
+            .map(|arg| arg.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn from_args_uses_defaults_without_options() {
+        let benchmark = Benchmark::from_args(&args(&[])).unwrap();
+
+        assert_eq!("127.0.0.1:6379", benchmark.address);
+        assert_eq!(50, benchmark.clients);
+        assert_eq!(100_000, benchmark.requests);
+        assert_eq!(
+            vec![BenchCommand::Set, BenchCommand::Get],
+            benchmark.commands
+        );
+        assert_eq!(1, benchmark.pipeline);
+        assert_eq!(None, benchmark.keyspace);
+    }
+
+    #[test]
+    fn from_args_reads_every_option() {
+        let benchmark = Benchmark::from_args(&args(&[
+            "--host",
+            "localhost",
+            "--port",
+            "7000",
+            "-c",
+            "8",
+            "-n",
+            "500",
+            "-t",
+            "PING,get",
+            "-d",
+            "64",
+            "-P",
+            "16",
+            "-r",
+            "1000",
+        ]))
+        .unwrap();
+
+        assert_eq!("localhost:7000", benchmark.address);
+        assert_eq!(8, benchmark.clients);
+        assert_eq!(500, benchmark.requests);
+        assert_eq!(
+            vec![BenchCommand::Ping, BenchCommand::Get],
+            benchmark.commands
+        );
+        assert_eq!(64, benchmark.data_size);
+        assert_eq!(16, benchmark.pipeline);
+        assert_eq!(Some(1000), benchmark.keyspace);
+    }
+
+    #[test]
+    fn from_args_rejects_invalid_values() {
+        for (option, value) in [
+            ("-c", "0"),
+            ("-n", "many"),
+            ("-t", "set,flush"),
+            ("-P", "0"),
+        ] {
+            assert_eq!(
+                Err(MiniRedisError::InvalidArguments {
+                    arguments: vec![option.to_string(), value.to_string()]
+                }),
+                Benchmark::from_args(&args(&[option, value])).map(|_| ())
+            );
+        }
+    }
+
+    #[test]
+    fn from_args_rejects_unknown_and_incomplete_options() {
+        assert_eq!(
+            Err(MiniRedisError::InvalidArguments {
+                arguments: vec!["-q".to_string()]
+            }),
+            Benchmark::from_args(&args(&["-q"])).map(|_| ())
+        );
+        assert_eq!(
+            Err(MiniRedisError::InvalidArguments {
+                arguments: vec!["-n".to_string()]
+            }),
+            Benchmark::from_args(&args(&["-n"])).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn request_picks_keys_from_the_keyspace() {
+        let mut benchmark = Benchmark::from_args(&args(&[])).unwrap();
+        let mut rng = Rng::with_seed(7);
+
+        assert_eq!(
+            "SET key abc",
+            benchmark.request(BenchCommand::Set, &mut rng, "abc")
+        );
+        benchmark.keyspace = Some(10);
+        for _ in 0..100 {
+            let request = benchmark.request(BenchCommand::Get, &mut rng, "abc");
+            let key = request.strip_prefix("GET key:").unwrap();
+            assert!(key.parse::<u64>().unwrap() < 10);
+        }
+    }
+
+    #[test]
+    fn random_value_has_the_given_size_without_spaces() {
+        let value = random_value(&mut Rng::with_seed(7), 64);
+
+        assert_eq!(64, value.len());
+        assert!(value.chars().all(|c| c.is_ascii_alphanumeric()));
+    }
+
+    #[test]
+    fn histogram_buckets_keep_their_lowest_latency() {
+        for micros in [0, 1, 31, 32, 33, 63, 64, 1000, 1_000_000, u64::MAX] {
+            let index = bucket(micros);
+
+            assert!(lowest(index) <= micros);
+            assert!(index + 1 == BUCKETS || lowest(index + 1) > micros);
+        }
+    }
+
+    #[test]
+    fn histogram_finds_percentiles() {
+        let mut histogram = Histogram::new();
+        for micros in 1..=100 {
+            histogram.record(Duration::from_micros(micros));
+        }
+
+        assert_eq!(Duration::from_micros(50), histogram.percentile(50.0));
+        assert_eq!(Duration::from_micros(1), histogram.percentile(0.0));
+        let p99 = histogram.percentile(99.0).as_micros();
+        assert!((96..=99).contains(&p99), "{}", p99);
+    }
+
+    #[test]
+    fn histogram_merges_counts() {
+        let mut a = Histogram::new();
+        let mut b = Histogram::new();
+        a.record(Duration::from_micros(10));
+        b.record(Duration::from_micros(20));
+
+        a.merge(&b);
+
+        assert_eq!(2, a.total);
+        assert_eq!(Duration::from_micros(20), a.percentile(100.0));
+    }
+
+    #[test]
+    fn empty_histogram_has_zero_latency() {
+        assert_eq!(Duration::ZERO, Histogram::new().percentile(99.0));
+    }
+}
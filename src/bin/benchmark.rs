@@ -0,0 +1,22 @@
+use miniredis::benchmark::Benchmark;
+use std::env;
+
+/// Runs the benchmark.
+///
+/// Run gets the environment variables, checks if the user wants to see the help message,
+/// and then creates a benchmark from the arguments and runs it.
+/// It exits with status 1 if the benchmark fails.
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    if args.contains(&"--help".to_string()) || args.contains(&"-h".to_string()) {
+        Benchmark::print_help();
+        return;
+    }
+
+    let result = Benchmark::from_args(&args).and_then(|benchmark| benchmark.run());
+    if let Err(e) = result {
+        eprintln!("Benchmark failed: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -1,10 +1,12 @@
 use miniredis::client::Client;
+use miniredis::error::MiniRedisError;
 use std::env;
 
 /// Runs the client.
 ///
 /// Run gets the environment variables, checks if the user wants to see the help message,
 /// and then creates a client from the arguments and runs it.
+/// It exits with status 1 if the client fails or the server replies with an error.
 fn main() {
     let args: Vec<String> = env::args().collect();
 
@@ -13,10 +15,21 @@ fn main() {
         return;
     }
 
-    let client = Client::from_args(&args);
+    let client = match Client::from_args(&args) {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Client failed: {}", e);
+            std::process::exit(1);
+        }
+    };
 
-    if let Err(e) = client.run() {
-        eprintln!("Client failed: {}", e);
-        std::process::exit(1);
+    match client.run() {
+        Ok(()) => {}
+        // The error reply has already been printed, only the exit status is left.
+        Err(MiniRedisError::ServerError { .. }) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("Client failed: {}", e);
+            std::process::exit(1);
+        }
     }
 }
@@ -0,0 +1,24 @@
+use miniredis::dump::Dump;
+use std::env;
+
+/// Runs the dump.
+///
+/// Run gets the environment variables, checks if the user wants to see the help message,
+/// and then creates a dump from the arguments and runs it.
+/// It exits with status 1 if the dump fails.
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    if args.contains(&"--help".to_string()) || args.contains(&"-h".to_string()) {
+        Dump::print_help();
+        return;
+    }
+
+    match Dump::from_args(&args).and_then(|dump| dump.run()) {
+        Ok(keys) => println!("Dumped {} keys", keys),
+        Err(e) => {
+            eprintln!("Dump failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
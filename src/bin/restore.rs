@@ -0,0 +1,27 @@
+use miniredis::dump::Restore;
+use std::env;
+
+/// Runs the restore.
+///
+/// Run gets the environment variables, checks if the user wants to see the help message,
+/// and then creates a restore from the arguments and runs it.
+/// It exits with status 1 if the restore fails.
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    if args.contains(&"--help".to_string()) || args.contains(&"-h".to_string()) {
+        Restore::print_help();
+        return;
+    }
+
+    match Restore::from_args(&args).and_then(|restore| restore.run()) {
+        Ok(report) => println!(
+            "Restored {} keys, skipped {} that exist",
+            report.restored, report.skipped
+        ),
+        Err(e) => {
+            eprintln!("Restore failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
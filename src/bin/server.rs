@@ -5,6 +5,7 @@ use std::env;
 ///
 /// Run gets the environment variables, checks if the user wants to see the help message,
 /// and then creates a server from the arguments and runs it.
+/// With `--async`, and the `async` feature, it serves clients on tokio tasks instead of threads.
 fn main() {
     let args: Vec<String> = env::args().collect();
 
@@ -13,10 +14,50 @@ fn main() {
         return;
     }
 
-    let server = Server::from_args(&args);
+    #[cfg(feature = "async")]
+    if args.contains(&"--async".to_string()) {
+        let args: Vec<String> = args.into_iter().filter(|arg| arg != "--async").collect();
+        return run_async(&args);
+    }
+
+    let server = match Server::from_args(&args) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("Server failed: {}", e);
+            std::process::exit(1);
+        }
+    };
 
     if let Err(e) = server.run() {
         eprintln!("Server failed: {}", e);
         std::process::exit(1);
     }
 }
+
+/// Runs the async server on a tokio runtime with a worker thread for every core.
+///
+/// # Arguments
+///
+/// * `args` - The command line arguments, without `--async`.
+#[cfg(feature = "async")]
+fn run_async(args: &[String]) {
+    let server = match miniredis::aio::Server::from_args(args) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("Server failed: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Server failed: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(e) = runtime.block_on(server.run()) {
+        eprintln!("Server failed: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,655 @@
+use crate::error::MiniRedisError;
+use crate::eviction::{Capacity, EvictionPolicy};
+use crate::kv_store::KVStore;
+use crate::rng::Rng;
+use crate::stats::StoreStats;
+use crate::storage::{self, ExpireCondition, KeyInfo, SetOptions, Storage};
+use std::hash::{BuildHasher, RandomState};
+use std::time::Duration;
+
+/// The largest number of shards a cache is split into by [`CachedKVStore::new`].
+pub const DEFAULT_SHARDS: usize = 16;
+
+/// A bounded, thread-safe cache that evicts the least recently used keys.
+///
+/// The cache holds at most `capacity` entries. Both gets and sets count as a use,
+/// so keys that are read often survive while cold keys are evicted.
+///
+/// The keys are spread over a number of shards, each a [`KVStore`] with its own lock
+/// and an equal share of the capacity. A get only locks the shard of its key,
+/// and updates the recency of the key while it holds that lock anyway,
+/// so reads never wait on a lock held for the whole cache.
+/// The price is that eviction is least recently used per shard, not across the cache:
+/// the evicted key is the oldest of its shard, which may be younger than
+/// the oldest key of another shard. With evenly spread keys this is close to exact LRU.
+/// Use [`CachedKVStore::with_shards`] with one shard for exact LRU.
+///
+/// # Examples
+///
+/// ```rust
+/// use miniredis::cache::CachedKVStore;
+///
+/// let cache = CachedKVStore::with_shards(2, 1);
+/// cache.set("a", "1").unwrap();
+/// cache.set("b", "2").unwrap();
+/// cache.get("a").unwrap();
+///
+/// cache.set("c", "3").unwrap();
+///
+/// assert_eq!(Ok(Some("1".to_string())), cache.get("a"));
+/// assert_eq!(Ok(None), cache.get("b"));
+/// assert_eq!(1, cache.evicted());
+/// ```
+pub struct CachedKVStore {
+    shards: Vec<KVStore>,
+    hasher: RandomState,
+    capacity: usize,
+}
+
+impl CachedKVStore {
+    /// Creates a new cache.
+    ///
+    /// The cache is split into [`DEFAULT_SHARDS`] shards,
+    /// or fewer if the capacity is smaller than that.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The maximum number of entries.
+    ///
+    /// # Returns
+    ///
+    /// A new, empty cache.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::cache::CachedKVStore;
+    ///
+    /// let cache = CachedKVStore::new(1_000);
+    ///
+    /// assert_eq!(1_000, cache.capacity());
+    /// ```
+    pub fn new(capacity: usize) -> Self {
+        Self::with_shards(capacity, DEFAULT_SHARDS.min(capacity))
+    }
+
+    /// Creates a new cache split into a given number of shards.
+    ///
+    /// The capacity is divided as evenly as possible between the shards.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The maximum number of entries.
+    /// * `shards` - The number of shards, at least 1 and at most `capacity`.
+    ///
+    /// # Returns
+    ///
+    /// A new, empty cache.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::cache::CachedKVStore;
+    ///
+    /// let cache = CachedKVStore::with_shards(1_000, 4);
+    ///
+    /// assert_eq!(1_000, cache.capacity());
+    /// ```
+    pub fn with_shards(capacity: usize, shards: usize) -> Self {
+        let shards = shards.clamp(1, capacity.max(1));
+        let shards = (0..shards)
+            .map(|i| {
+                let share = capacity / shards + usize::from(i < capacity % shards);
+                KVStore::with_capacity_policy(Capacity::Entries(share), EvictionPolicy::Lru)
+            })
+            .collect();
+        Self {
+            shards,
+            hasher: RandomState::new(),
+            capacity,
+        }
+    }
+
+    /// Gets a value from the cache and marks the key as recently used.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to get the value for.
+    ///
+    /// # Returns
+    ///
+    /// The value associated with the key, or None if the key is not cached.
+    ///
+    /// # Errors
+    ///
+    /// If the shard of the key is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::cache::CachedKVStore;
+    ///
+    /// let cache = CachedKVStore::new(10);
+    /// cache.set("key", "value").unwrap();
+    ///
+    /// assert_eq!(Ok(Some("value".to_string())), cache.get("key"));
+    /// ```
+    pub fn get(&self, key: &str) -> Result<Option<String>, MiniRedisError> {
+        self.shard(key).get(key)
+    }
+
+    /// Sets a value in the cache and marks the key as recently used.
+    ///
+    /// If the shard of the key is full, its least recently used key is evicted.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to set the value for.
+    /// * `value` - The value to set.
+    ///
+    /// # Errors
+    ///
+    /// If the shard of the key is already locked, or the cache has no capacity at all,
+    /// it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::cache::CachedKVStore;
+    ///
+    /// let cache = CachedKVStore::new(10);
+    ///
+    /// cache.set("key", "value").unwrap();
+    /// ```
+    pub fn set(&self, key: &str, value: &str) -> Result<(), MiniRedisError> {
+        self.shard(key).set(key, value)
+    }
+
+    /// Deletes a value from the cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to delete the value for.
+    ///
+    /// # Returns
+    ///
+    /// True if the key was cached, false otherwise.
+    ///
+    /// # Errors
+    ///
+    /// If the shard of the key is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::cache::CachedKVStore;
+    ///
+    /// let cache = CachedKVStore::new(10);
+    /// cache.set("key", "value").unwrap();
+    ///
+    /// cache.del("key").unwrap();
+    ///
+    /// assert_eq!(Ok(None), cache.get("key"));
+    /// ```
+    pub fn del(&self, key: &str) -> Result<bool, MiniRedisError> {
+        self.shard(key).del(key)
+    }
+
+    /// Gets the number of cached entries.
+    ///
+    /// # Returns
+    ///
+    /// The number of entries, never more than the capacity.
+    ///
+    /// # Errors
+    ///
+    /// If a shard is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::cache::CachedKVStore;
+    ///
+    /// let cache = CachedKVStore::new(10);
+    /// cache.set("key", "value").unwrap();
+    ///
+    /// assert_eq!(Ok(1), cache.len());
+    /// ```
+    pub fn len(&self) -> Result<usize, MiniRedisError> {
+        self.shards.iter().map(KVStore::len).sum()
+    }
+
+    /// Checks whether the cache has no entries.
+    ///
+    /// # Returns
+    ///
+    /// True if the cache is empty.
+    ///
+    /// # Errors
+    ///
+    /// If a shard is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::cache::CachedKVStore;
+    ///
+    /// let cache = CachedKVStore::new(10);
+    ///
+    /// assert_eq!(Ok(true), cache.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> Result<bool, MiniRedisError> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Gets the maximum number of entries the cache holds.
+    ///
+    /// # Returns
+    ///
+    /// The capacity the cache was created with.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::cache::CachedKVStore;
+    ///
+    /// let cache = CachedKVStore::new(10);
+    ///
+    /// assert_eq!(10, cache.capacity());
+    /// ```
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Gets the number of keys evicted to make room for new ones.
+    ///
+    /// # Returns
+    ///
+    /// The number of evictions since the cache was created.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::cache::CachedKVStore;
+    ///
+    /// let cache = CachedKVStore::with_shards(1, 1);
+    /// cache.set("a", "1").unwrap();
+    /// cache.set("b", "2").unwrap();
+    ///
+    /// assert_eq!(1, cache.evicted());
+    /// ```
+    pub fn evicted(&self) -> u64 {
+        self.shards
+            .iter()
+            .map(|shard| shard.stats().evictions)
+            .sum()
+    }
+
+    /// Finds the shard a key belongs to.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to find the shard for.
+    ///
+    /// # Returns
+    ///
+    /// The shard the key is stored in.
+    fn shard(&self, key: &str) -> &KVStore {
+        let hash = self.hasher.hash_one(key);
+        &self.shards[(hash % self.shards.len() as u64) as usize]
+    }
+}
+
+impl Storage for CachedKVStore {
+    fn get(&self, key: &str) -> Result<Option<String>, MiniRedisError> {
+        CachedKVStore::get(self, key)
+    }
+
+    fn get_bytes(&self, key: &str) -> Result<Option<Vec<u8>>, MiniRedisError> {
+        self.shard(key).get_bytes(key)
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), MiniRedisError> {
+        CachedKVStore::set(self, key, value)
+    }
+
+    fn del(&self, key: &str) -> Result<bool, MiniRedisError> {
+        CachedKVStore::del(self, key)
+    }
+
+    fn rename(&self, source: &str, destination: &str) -> Result<(), MiniRedisError> {
+        let shard = self.shard(source);
+        if std::ptr::eq(shard, self.shard(destination)) {
+            return shard.rename(source, destination);
+        }
+        // Keys in different shards cannot be locked together, so the value is copied
+        // and then deleted, and like a plain set the destination never expires.
+        let value = shard.get(source)?.ok_or(MiniRedisError::NoSuchKey)?;
+        self.shard(destination).set(destination, &value)?;
+        shard.del(source)?;
+        Ok(())
+    }
+
+    fn rename_if_absent(&self, source: &str, destination: &str) -> Result<bool, MiniRedisError> {
+        let shard = self.shard(source);
+        if std::ptr::eq(shard, self.shard(destination)) {
+            return shard.rename_if_absent(source, destination);
+        }
+        // Keys in different shards cannot be locked together, so the destination
+        // is claimed with a conditional set before the source is deleted.
+        let value = shard.get(source)?.ok_or(MiniRedisError::NoSuchKey)?;
+        if !self.shard(destination).set_if_absent(destination, &value)? {
+            return Ok(false);
+        }
+        shard.del(source)?;
+        Ok(true)
+    }
+
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool, MiniRedisError> {
+        self.shard(key).set_if_absent(key, value)
+    }
+
+    fn set_with(
+        &self,
+        key: &str,
+        value: &str,
+        options: &SetOptions,
+    ) -> Result<bool, MiniRedisError> {
+        self.shard(key).set_with(key, value, options)
+    }
+
+    fn set_returning_old(&self, key: &str, value: &str) -> Result<Option<Vec<u8>>, MiniRedisError> {
+        self.shard(key).set_returning_old(key, value)
+    }
+
+    fn multi_set(&self, pairs: &[(String, String)]) -> Result<(), MiniRedisError> {
+        // Each shard writes its own pairs atomically, one shard after the other.
+        for shard in &self.shards {
+            let pairs = pairs
+                .iter()
+                .filter(|(key, _)| std::ptr::eq(self.shard(key), shard))
+                .cloned()
+                .collect::<Vec<(String, String)>>();
+            if !pairs.is_empty() {
+                shard.multi_set(&pairs)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn multi_set_if_absent(&self, pairs: &[(String, String)]) -> Result<bool, MiniRedisError> {
+        // The shards are locked one at a time, so this is only all or nothing
+        // when no other client writes the keys in between.
+        for (key, _) in pairs {
+            if self.shard(key).exists(key)? {
+                return Ok(false);
+            }
+        }
+        self.multi_set(pairs)?;
+        Ok(true)
+    }
+
+    fn increment(&self, key: &str, delta: i64) -> Result<i64, MiniRedisError> {
+        self.shard(key).increment(key, delta)
+    }
+
+    fn increment_float(&self, key: &str, delta: f64) -> Result<f64, MiniRedisError> {
+        self.shard(key).increment_float(key, delta)
+    }
+
+    fn append(&self, key: &str, suffix: &str) -> Result<usize, MiniRedisError> {
+        self.shard(key).append(key, suffix)
+    }
+
+    fn set_range(&self, key: &str, offset: usize, value: &str) -> Result<usize, MiniRedisError> {
+        self.shard(key).set_range(key, offset, value)
+    }
+
+    fn set_bit(&self, key: &str, offset: usize, bit: bool) -> Result<bool, MiniRedisError> {
+        self.shard(key).set_bit(key, offset, bit)
+    }
+
+    fn get_and_expire(
+        &self,
+        key: &str,
+        ttl: Option<Duration>,
+    ) -> Result<Option<Vec<u8>>, MiniRedisError> {
+        self.shard(key).get_and_expire(key, ttl)
+    }
+
+    fn expire(
+        &self,
+        key: &str,
+        ttl: Duration,
+        condition: Option<ExpireCondition>,
+    ) -> Result<bool, MiniRedisError> {
+        self.shard(key).expire(key, ttl, condition)
+    }
+
+    fn persist(&self, key: &str) -> Result<bool, MiniRedisError> {
+        self.shard(key).persist(key)
+    }
+
+    fn ttl(&self, key: &str) -> Result<Option<Duration>, MiniRedisError> {
+        self.shard(key).ttl(key)
+    }
+
+    fn del_prefix(&self, prefix: &str) -> Result<usize, MiniRedisError> {
+        self.shards
+            .iter()
+            .map(|shard| shard.del_prefix(prefix))
+            .sum()
+    }
+
+    fn exists(&self, key: &str) -> Result<bool, MiniRedisError> {
+        self.shard(key).exists(key)
+    }
+
+    fn len(&self) -> Result<usize, MiniRedisError> {
+        CachedKVStore::len(self)
+    }
+
+    fn describe(&self, key: &str) -> Result<Option<KeyInfo>, MiniRedisError> {
+        self.shard(key).describe(key)
+    }
+
+    fn touch(&self, keys: &[String]) -> Result<usize, MiniRedisError> {
+        let mut count = 0;
+        for key in keys {
+            count += self.shard(key).touch(std::slice::from_ref(key))?;
+        }
+        Ok(count)
+    }
+
+    fn unlink(
+        &self,
+        keys: &[String],
+        free: &mut dyn FnMut(Vec<u8>),
+    ) -> Result<usize, MiniRedisError> {
+        let mut count = 0;
+        for key in keys {
+            count += self.shard(key).unlink(std::slice::from_ref(key), free)?;
+        }
+        Ok(count)
+    }
+
+    fn keys(&self) -> Result<Vec<String>, MiniRedisError> {
+        let mut keys = Vec::new();
+        for shard in &self.shards {
+            keys.extend(shard.keys()?);
+        }
+        Ok(keys)
+    }
+
+    fn random_key(&self) -> Result<Option<String>, MiniRedisError> {
+        // Picking a shard in proportion to its keys keeps every key equally likely.
+        let lens = self
+            .shards
+            .iter()
+            .map(KVStore::len)
+            .collect::<Result<Vec<usize>, MiniRedisError>>()?;
+        let total = lens.iter().sum::<usize>();
+        if total == 0 {
+            return Ok(None);
+        }
+        let mut index = Rng::new().below(total);
+        for (shard, len) in self.shards.iter().zip(lens) {
+            if index < len {
+                return shard.random_key();
+            }
+            index -= len;
+        }
+        Ok(None)
+    }
+
+    fn scan(&self, cursor: u64, count: usize) -> Result<(u64, Vec<String>), MiniRedisError> {
+        // Every shard visits keys in the same order, so the batch ends where the shard
+        // that got the least far stopped, and the other shards go over the rest again.
+        let mut next = 0;
+        let mut keys = Vec::new();
+        for shard in &self.shards {
+            let (cursor, batch) = shard.scan(cursor, count)?;
+            if cursor != 0 && (next == 0 || cursor < next) {
+                next = cursor;
+            }
+            keys.extend(batch);
+        }
+        if next != 0 {
+            keys.retain(|key| storage::scan_hash(key) < next);
+        }
+        Ok((next, keys))
+    }
+
+    fn stats(&self) -> StoreStats {
+        self.shards
+            .iter()
+            .map(KVStore::stats)
+            .fold(StoreStats::default(), |total, shard| StoreStats {
+                gets: total.gets + shard.gets,
+                sets: total.sets + shard.sets,
+                dels: total.dels + shard.dels,
+                hits: total.hits + shard.hits,
+                misses: total.misses + shard.misses,
+                evictions: total.evictions + shard.evictions,
+                expired: total.expired + shard.expired,
+            })
+    }
+
+    fn reset_stats(&self) {
+        for shard in &self.shards {
+            shard.reset_stats();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capacity_is_split_between_shards() {
+        let cache = CachedKVStore::with_shards(10, 3);
+
+        for i in 0..1_000 {
+            cache.set(&format!("key{}", i), "value").unwrap();
+        }
+
+        assert_eq!(3, cache.shards.len());
+        assert_eq!(Ok(10), cache.len());
+    }
+
+    #[test]
+    fn len_never_exceeds_capacity() {
+        let cache = CachedKVStore::new(100);
+
+        for i in 0..1_000 {
+            cache.set(&format!("key{}", i), "value").unwrap();
+            assert!(cache.len().unwrap() <= 100);
+        }
+
+        assert!(cache.evicted() >= 900);
+    }
+
+    #[test]
+    fn evicted_counts_every_eviction() {
+        let cache = CachedKVStore::with_shards(3, 1);
+
+        for i in 0..10 {
+            cache.set(&format!("key{}", i), "value").unwrap();
+        }
+
+        assert_eq!(7, cache.evicted());
+        assert_eq!(Ok(3), cache.len());
+    }
+
+    #[test]
+    fn hot_keys_survive_a_stream_of_cold_keys() {
+        let cache = CachedKVStore::new(256);
+        let hot = (0..8).map(|i| format!("hot{}", i)).collect::<Vec<String>>();
+        for key in &hot {
+            cache.set(key, "value").unwrap();
+        }
+
+        for i in 0..10_000 {
+            cache.set(&format!("cold{}", i), "value").unwrap();
+            for key in &hot {
+                cache.get(key).unwrap();
+            }
+        }
+
+        for key in &hot {
+            assert_eq!(Ok(Some("value".to_string())), cache.get(key));
+        }
+    }
+
+    #[test]
+    fn get_marks_key_as_recently_used() {
+        let cache = CachedKVStore::with_shards(2, 1);
+        cache.set("a", "1").unwrap();
+        cache.set("b", "2").unwrap();
+
+        cache.get("a").unwrap();
+        cache.set("c", "3").unwrap();
+
+        assert_eq!(Ok(Some("1".to_string())), cache.get("a"));
+        assert_eq!(Ok(None), cache.get("b"));
+    }
+
+    #[test]
+    fn cache_can_be_used_as_storage() {
+        let cache: Box<dyn Storage> = Box::new(CachedKVStore::new(100));
+
+        cache.set("session:1", "a").unwrap();
+        cache.set("session:2", "b").unwrap();
+
+        assert_eq!(Ok(true), cache.exists("session:1"));
+        assert_eq!(Ok(2), cache.del_prefix("session:"));
+        assert_eq!(Ok(true), cache.is_empty());
+    }
+
+    #[test]
+    fn scan_returns_every_key_of_every_shard_once() {
+        let cache = CachedKVStore::with_shards(1_000, 4);
+        let mut expected = (0..200)
+            .map(|i| format!("key:{}", i))
+            .collect::<Vec<String>>();
+        for key in &expected {
+            cache.set(key, "value").unwrap();
+        }
+
+        let mut keys = Vec::new();
+        let mut cursor = 0;
+        loop {
+            let (next, batch) = Storage::scan(&cache, cursor, 10).unwrap();
+            keys.extend(batch);
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+
+        keys.sort();
+        expected.sort();
+        assert_eq!(expected, keys);
+    }
+}
@@ -1,6 +1,29 @@
+use crate::command::{COMMANDS, Command, CommandInfo};
+use crate::connection::{ConnectOptions, Connection, Protocol, is_connection_error};
 use crate::error::MiniRedisError;
-use std::io::{self, BufRead, BufReader, Write};
-use std::net::TcpStream;
+use crate::format::{Format, format_response};
+use crate::history::{self, DEFAULT_HISTORY_SIZE, History};
+use crate::interrupt;
+use crate::response::Response;
+use crate::tokenizer;
+use std::fs;
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, TrySendError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How many commands of a file are sent at once before reading their responses.
+const FILE_CHUNK: usize = 100;
+
+/// How many commands bulk pipe mode sends ahead of the responses it has read.
+const PIPE_WINDOW: usize = 10_000;
+
+/// How many errors bulk pipe mode prints with its summary.
+const PIPE_ERRORS_SHOWN: usize = 5;
+
+/// How many keys big keys mode examines between redraws of its progress line.
+const BIGKEYS_PROGRESS: usize = 100;
 
 /// A client that connects to a server and sends requests.
 ///
@@ -8,6 +31,14 @@ use std::net::TcpStream;
 /// reading input from the user, sending it to the server,
 /// and printing the response back to the user.
 ///
+/// A client created with a command sends only that command,
+/// which makes it usable from shell scripts.
+/// When the input is not a terminal, or pipe mode is asked for, the client
+/// sends every line of the input as a command without prompting, until the input ends.
+/// In latency mode, the client measures how long the server takes to answer PING instead.
+/// A client with a file sends the commands in the file, and reports a summary.
+/// In big keys mode, the client scans the keyspace for the largest keys instead.
+///
 /// # Examples
 ///
 /// ```rust
@@ -18,6 +49,49 @@ use std::net::TcpStream;
 /// ```
 pub struct Client {
     address: String,
+    command: Vec<String>,
+    pipe: bool,
+    pipe_bulk: bool,
+    connect_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+    repeat: Option<u64>,
+    interval: Duration,
+    format: Option<Format>,
+    latency: Option<Latency>,
+    prompt: bool,
+    protocol: Option<Protocol>,
+    user: Option<String>,
+    password: Option<String>,
+    connect_options: ConnectOptions,
+    file: Option<String>,
+    abort_on_error: bool,
+    bigkeys: bool,
+    bigkeys_sample: Option<usize>,
+    show_rtt: bool,
+    validate: bool,
+    history: Option<String>,
+    history_size: usize,
+}
+
+/// How the client reports latency when it measures it.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use miniredis::client::{Client, Latency};
+/// use std::time::Duration;
+///
+/// let client = Client::builder("127.0.0.1:6379")
+///     .latency(Latency::History(Duration::from_secs(15)))
+///     .build();
+/// client.run();
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Latency {
+    /// One line with the statistics of every sample so far, redrawn after every sample.
+    Live,
+    /// A line with the statistics of every period this long, starting over after each.
+    History(Duration),
 }
 
 impl Client {
@@ -40,13 +114,116 @@ impl Client {
     /// client.run();
     /// ```
     pub fn new(address: &str) -> Self {
-        Self {
-            address: address.to_string(),
+        Self::builder(address).build()
+    }
+
+    /// Creates a builder for a client with more options than [`Client::new`].
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address of the server to connect to.
+    ///
+    /// # Returns
+    ///
+    /// A builder with every option at its default.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::client::Client;
+    /// use std::time::Duration;
+    ///
+    /// let client = Client::builder("127.0.0.1:6379")
+    ///     .connect_timeout(Duration::from_secs(1))
+    ///     .timeout(Duration::from_secs(5))
+    ///     .build();
+    /// client.run();
+    /// ```
+    pub fn builder(address: &str) -> ClientBuilder {
+        ClientBuilder {
+            client: Self {
+                address: address.to_string(),
+                command: Vec::new(),
+                pipe: false,
+                pipe_bulk: false,
+                connect_timeout: None,
+                timeout: None,
+                repeat: Some(1),
+                interval: Duration::ZERO,
+                format: None,
+                latency: None,
+                prompt: true,
+                protocol: None,
+                user: None,
+                password: None,
+                connect_options: ConnectOptions::default(),
+                file: None,
+                abort_on_error: false,
+                bigkeys: false,
+                bigkeys_sample: None,
+                show_rtt: false,
+                validate: true,
+                history: None,
+                history_size: DEFAULT_HISTORY_SIZE,
+            },
         }
     }
 
     /// Creates a new client from command line arguments.
     ///
+    /// Options come first:
+    ///
+    /// * `--pipe` reads commands from the input without prompting,
+    ///   even if the input is a terminal.
+    /// * `--pipe-bulk` sends every line of the input without waiting for the responses,
+    ///   for loading data in bulk, and prints a summary once every response is in.
+    /// * `--connect-timeout <SECONDS>` gives up connecting after that long.
+    /// * `--retry <N>` tries to connect N more times if the server cannot be reached,
+    ///   waiting `--retry-delay <MILLISECONDS>` before the first retry, 100 by default,
+    ///   and twice as long before every retry after it.
+    /// * `--retry-writes` also sends a command again on a new connection
+    ///   if the connection fails after sending it, which may run the command twice.
+    /// * `--file <PATH>`, or `--eval <PATH>`, sends the commands in a file instead,
+    ///   one per line, skipping blank lines and lines starting with `#`.
+    /// * `--abort-on-error` stops sending the commands of a file at the first error.
+    /// * `--bigkeys` scans the whole keyspace with `SCAN` and reports the largest keys,
+    ///   and `--bigkeys-sample <N>` does the same but stops after N keys.
+    /// * `--timeout <SECONDS>` gives up waiting for the server to accept
+    ///   a command or send a response after that long.
+    /// * `--repeat <N>` sends the command N times, or until Ctrl+C if N is -1.
+    /// * `--interval <SECONDS>` waits that long between repeats.
+    /// * `--raw` prints responses exactly as the server sent them.
+    /// * `--no-raw` prints responses for humans, even if the output is not a terminal.
+    /// * `--output json|csv|plain` prints every response as a line of JSON, as CSV rows,
+    ///   or as plain text like `--raw`.
+    /// * `--latency` measures the latency of the server until Ctrl+C, instead of sending commands.
+    /// * `--latency-history <SECONDS>` measures the latency like `--latency`,
+    ///   but prints the statistics of every period this long on a line of its own.
+    /// * `--no-prompt` reads commands from a terminal without printing a prompt.
+    /// * `--show-rtt` prints the round-trip time of every command after its response.
+    /// * `--history <PATH>` keeps the commands typed into the prompt in that file,
+    ///   instead of `~/.miniredis_history`, and `--no-history` does not keep them at all.
+    /// * `--history-size <N>` keeps the last N commands, 1000 by default.
+    /// * `--no-validate` sends commands the client does not know, or with the wrong number
+    ///   of arguments, instead of refusing them, for servers with more commands than the client.
+    /// * `--resp` speaks RESP to the server, and `--inline` speaks the inline protocol,
+    ///   instead of asking the server which protocols it speaks.
+    /// * `--pass <PASSWORD>` authenticates with the password right after connecting,
+    ///   and `--user <USER>` as that user instead of the default one.
+    ///
+    /// Without `--pass`, the password is read from the `MINIREDIS_PASSWORD` environment variable,
+    /// which keeps it out of the shell history.
+    /// Without `--history`, the history file is read from the `MINIREDIS_HISTORY`
+    /// environment variable, and is `~/.miniredis_history` if it is not set.
+    /// The seconds may be fractional, like `0.5`.
+    /// Repeating only applies to a command given on the command line.
+    /// In latency mode, the interval is the wait between samples, 10 milliseconds by default,
+    /// and no command may be given.
+    /// The first argument after the options is the address of the server,
+    /// or several separated by commas, like a primary and its replica, to try in order.
+    /// Any arguments after it are a command to send instead of starting the prompt,
+    /// one word per argument, so a shell-quoted argument with spaces stays one word.
+    ///
     /// # Arguments
     ///
     /// * `args` - The command line arguments.
@@ -55,30 +232,206 @@ impl Client {
     ///
     /// A new client.
     ///
+    /// # Errors
+    ///
+    /// If an option is unknown, is missing its value, or has an invalid value,
+    /// if a repeat or interval is given without a command, if a user is given without a password,
+    /// if a command is given in latency mode or big keys mode, or if a command, repeats,
+    /// or latency mode are given with a file, it will return an error.
+    ///
     /// # Examples
     ///
     /// ```rust,no_run
     /// use miniredis::client::Client;
     ///
-    /// let client = Client::from_args(&["miniredis".to_string(), "127.0.0.1:6379".to_string()]);
+    /// let client =
+    ///     Client::from_args(&["miniredis".to_string(), "127.0.0.1:6379".to_string()]).unwrap();
     /// client.run();
     /// ```
-    pub fn from_args(args: &[String]) -> Self {
-        let address = if args.len() > 1 {
-            &args[1]
-        } else {
-            "127.0.0.1:6379"
-        };
+    pub fn from_args(args: &[String]) -> Result<Self, MiniRedisError> {
+        let mut args = args.iter().skip(1).peekable();
+        let mut pipe = false;
+        let mut pipe_bulk = false;
+        let mut connect_timeout = None;
+        let mut timeout = None;
+        let mut repeat = None;
+        let mut interval = None;
+        let mut format = None;
+        let mut latency = None;
+        let mut prompt = true;
+        let mut protocol = None;
+        let mut user = None;
+        let mut password = None;
+        let mut connect_options = ConnectOptions::default();
+        let mut file = None;
+        let mut abort_on_error = false;
+        let mut bigkeys = false;
+        let mut bigkeys_sample = None;
+        let mut show_rtt = false;
+        let mut validate = true;
+        let mut history = std::env::var("MINIREDIS_HISTORY")
+            .ok()
+            .or_else(|| history::default_path().map(|path| path.display().to_string()));
+        let mut history_size = DEFAULT_HISTORY_SIZE;
+
+        while let Some(option) = args.next_if(|arg| arg.starts_with("--")) {
+            match option.as_str() {
+                "--pipe" => pipe = true,
+                "--pipe-bulk" => pipe_bulk = true,
+                "--no-prompt" => prompt = false,
+                "--show-rtt" => show_rtt = true,
+                "--no-validate" => validate = false,
+                "--history" => history = Some(parse_value(option, args.next())?),
+                "--no-history" => history = None,
+                "--history-size" => history_size = parse_number(option, args.next())? as usize,
+                "--resp" => protocol = Some(Protocol::Resp),
+                "--inline" => protocol = Some(Protocol::Inline),
+                "--user" => user = Some(parse_value(option, args.next())?),
+                "--pass" => password = Some(parse_value(option, args.next())?),
+                "--connect-timeout" => connect_timeout = Some(parse_timeout(option, args.next())?),
+                "--timeout" => timeout = Some(parse_timeout(option, args.next())?),
+                "--retry" => connect_options.retries = parse_number(option, args.next())?,
+                "--retry-delay" => {
+                    connect_options.retry_delay =
+                        Duration::from_millis(parse_number(option, args.next())?.into())
+                }
+                "--retry-writes" => connect_options.retry_writes = true,
+                "--file" | "--eval" => file = Some(parse_value(option, args.next())?),
+                "--abort-on-error" => abort_on_error = true,
+                "--bigkeys" => bigkeys = true,
+                "--bigkeys-sample" => {
+                    bigkeys = true;
+                    bigkeys_sample = Some(parse_number(option, args.next())? as usize);
+                }
+                "--repeat" => repeat = Some(parse_repeat(option, args.next())?),
+                "--interval" => interval = Some(parse_seconds(option, args.next())?),
+                "--raw" => format = Some(Format::Raw),
+                "--no-raw" => format = Some(Format::Pretty),
+                "--output" => format = Some(parse_output(option, args.next())?),
+                "--latency" => latency = Some(Latency::Live),
+                "--latency-history" => {
+                    latency = Some(Latency::History(parse_timeout(option, args.next())?))
+                }
+                _ => {
+                    return Err(MiniRedisError::InvalidArguments {
+                        arguments: vec![option.clone()],
+                    });
+                }
+            }
+        }
+        let address = args.next().map_or("127.0.0.1:6379", String::as_str);
+        let command = args.cloned().collect::<Vec<String>>();
+        let password = password.or_else(|| std::env::var("MINIREDIS_PASSWORD").ok());
+        if user.is_some() && password.is_none() {
+            return Err(MiniRedisError::InvalidArguments {
+                arguments: vec!["--user".to_string()],
+            });
+        }
+
+        let mut builder = Self::builder(address).connect_options(connect_options);
+        if let Some(protocol) = protocol {
+            builder = builder.protocol(protocol);
+        }
+        if let Some(user) = user {
+            builder = builder.user(&user);
+        }
+        if let Some(password) = password {
+            builder = builder.password(&password);
+        }
+        if file.is_some() && (!command.is_empty() || repeat.is_some() || latency.is_some()) {
+            return Err(MiniRedisError::InvalidArguments {
+                arguments: ["--file".to_string()].into_iter().chain(command).collect(),
+            });
+        }
+        if bigkeys && (!command.is_empty() || repeat.is_some() || latency.is_some()) {
+            return Err(MiniRedisError::InvalidArguments {
+                arguments: ["--bigkeys".to_string()]
+                    .into_iter()
+                    .chain(command)
+                    .collect(),
+            });
+        }
+        if bigkeys {
+            let mut builder = builder.bigkeys(true);
+            if let Some(sample) = bigkeys_sample {
+                builder = builder.bigkeys_sample(sample);
+            }
+            return Ok(builder.build());
+        }
+        if latency.is_some() && (!command.is_empty() || repeat.is_some()) {
+            return Err(MiniRedisError::InvalidArguments {
+                arguments: ["--latency".to_string()]
+                    .into_iter()
+                    .chain(command)
+                    .collect(),
+            });
+        }
+        if let Some(latency) = latency {
+            return Ok(builder
+                .latency(latency)
+                .interval(interval.unwrap_or(Duration::from_millis(10)))
+                .build());
+        }
+        if command.is_empty() && (repeat.is_some() || interval.is_some()) {
+            return Err(MiniRedisError::InvalidArguments {
+                arguments: vec!["--repeat".to_string(), "--interval".to_string()],
+            });
+        }
 
-        Self::new(address)
+        let mut builder = builder
+            .command(command)
+            .pipe(pipe)
+            .pipe_bulk(pipe_bulk)
+            .prompt(prompt)
+            .repeat(repeat.unwrap_or(Some(1)))
+            .interval(interval.unwrap_or_default())
+            .abort_on_error(abort_on_error)
+            .show_rtt(show_rtt)
+            .validate(validate)
+            .history_size(history_size);
+        if let Some(history) = history {
+            builder = builder.history(&history);
+        }
+        if let Some(file) = file {
+            builder = builder.file(&file);
+        }
+        if let Some(connect_timeout) = connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+        if let Some(timeout) = timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(format) = format {
+            builder = builder.format(format);
+        }
+        Ok(builder.build())
     }
 
     /// Runs the client.
     ///
     /// Run starts the client and connects to the server.
-    /// It will then enter a loop where it reads input from the user,
+    /// If the client has a command, it sends that command, prints the response, and returns.
+    /// When repeating, it prints every response, and a summary to stderr at the end.
+    /// Ctrl+C then stops the repeats gracefully instead of killing the process.
+    /// In latency mode, it sends PING until Ctrl+C and prints the statistics of the round trips.
+    /// With a file, it sends the commands in the file, prints the errors with their line numbers,
+    /// and a summary to stderr at the end.
+    /// In big keys mode, it scans the keyspace, and prints its progress and a report of the sizes.
+    /// In bulk pipe mode, it sends every line of the input without waiting for the responses,
+    /// and prints a summary of them to stderr.
+    /// If the input is not a terminal, or the client is in pipe mode,
+    /// it sends every line of the input and prints one response per line.
+    /// Otherwise it will enter a loop where it reads input from the user,
     /// sends it to the server, and prints the response.
     ///
+    /// Commands the client does not know, or with the wrong number or kinds of arguments,
+    /// are refused with a hint before anything is sent, unless validation is turned off.
+    ///
+    /// Responses are printed for humans when the output is a terminal,
+    /// and exactly as the server sent them otherwise, unless a format is set.
+    /// When showing round-trip times, the prompt prints them after the responses,
+    /// and the other modes print them to stderr, so the output stays the same.
+    ///
     /// # Returns
     ///
     /// A result indicating whether the client was run successfully.
@@ -87,6 +440,10 @@ impl Client {
     ///
     /// If the client fails to connect to the server,
     /// read from the stream, or write to the stream, it will return an error.
+    /// If the file cannot be read, it will return an error.
+    /// If the server replies to the command of the client, to any of its repeats,
+    /// to any command of the file, or to any line of the input in either pipe mode, with an error,
+    /// it will return [`MiniRedisError::ServerError`] after printing the reply.
     ///
     /// # Examples
     ///
@@ -97,43 +454,27 @@ impl Client {
     /// client.run();
     /// ```
     pub fn run(&self) -> Result<(), MiniRedisError> {
-        let mut stream =
-            TcpStream::connect(&self.address).map_err(|_| MiniRedisError::StreamNotConnected {
-                address: self.address.clone(),
-            })?;
-        let mut reader = BufReader::new(
-            stream
-                .try_clone()
-                .map_err(|_| MiniRedisError::StreamClosed)?,
-        );
-        let mut terminal_reader = BufReader::new(io::stdin());
-
-        println!("Connected to server at {}", self.address);
-
-        loop {
-            print!("> ");
-            io::stdout()
-                .flush()
-                .map_err(|_| MiniRedisError::StreamNotFlushed)?;
-
-            let input = self.read_input(&mut terminal_reader)?;
-
-            if input.is_empty() {
-                continue;
-            }
-
-            if input == "quit" {
-                break;
-            }
-
-            self.send_input(&input, &mut stream)?;
-
-            let response = self.read_response(&mut reader)?;
-
-            println!("{}", response);
+        if let Some(latency) = self.latency {
+            return self.run_latency(latency);
+        }
+        if self.bigkeys {
+            return self.run_bigkeys();
+        }
+        if !self.command.is_empty() {
+            return self.run_command();
+        }
+        if let Some(file) = &self.file {
+            return self.run_file(file);
+        }
+        let stdin = io::stdin();
+        if self.pipe_bulk {
+            return self.run_pipe_bulk(&mut stdin.lock());
+        }
+        if self.pipe || !stdin.is_terminal() {
+            return self.run_pipe(&mut stdin.lock());
         }
 
-        Ok(())
+        self.run_prompt(&mut BufReader::new(io::stdin()))
     }
 
     /// Prints the help message.
@@ -149,192 +490,2792 @@ impl Client {
         println!("MiniRedis Client");
         println!();
         println!("Connects to a MiniRedis server and sends commands to it.");
+        println!("Without a command, it starts a prompt to type commands into.");
+        println!("With a command, it sends that command, prints the reply, and exits,");
+        println!("with a non-zero status if the reply is an error or the connection failed.");
+        println!("When the input is not a terminal, every line of it is sent as a command,");
+        println!("with a non-zero exit status if any reply is an error.");
         println!();
         println!("USAGE:");
-        println!("    miniredis-client <ADDRESS>");
+        println!("    miniredis-client [OPTIONS] [ADDRESS] [COMMAND [ARG]...]");
+        println!();
+        println!("OPTIONS:");
+        println!(
+            "    --pipe                         Read commands from the input without a prompt, even from a terminal"
+        );
+        println!(
+            "    --pipe-bulk                    Send every command of the input without waiting for replies, and print a summary"
+        );
+        println!(
+            "    --no-prompt                    Read commands from a terminal without printing a prompt"
+        );
+        println!(
+            "    --history <PATH>               Keep the commands typed into the prompt in this file [default: ~/.miniredis_history]"
+        );
+        println!(
+            "    --no-history                   Do not keep the commands typed into the prompt"
+        );
+        println!(
+            "    --history-size <N>             Keep this many commands in the history [default: 1000]"
+        );
+        println!(
+            "    --no-validate                  Send commands the client does not know, instead of refusing them"
+        );
+        println!(
+            "    --show-rtt                     Print the round-trip time of every command, to stderr unless in the prompt"
+        );
+        println!(
+            "    --connect-timeout <SECONDS>    Give up connecting to the server after this long"
+        );
+        println!(
+            "    --timeout <SECONDS>            Give up waiting for the server to reply after this long"
+        );
+        println!(
+            "    --retry <N>                    Try to connect N more times if the server cannot be reached"
+        );
+        println!(
+            "    --retry-delay <MILLISECONDS>   Wait this long before the first retry, doubling it every retry [default: 100]"
+        );
+        println!(
+            "    --retry-writes                 Also send a command again if the connection fails after sending it"
+        );
+        println!(
+            "    --file, --eval <PATH>          Send the commands in a file, one per line, and print a summary"
+        );
+        println!(
+            "    --abort-on-error               Stop sending the commands of a file at the first error"
+        );
+        println!(
+            "    --bigkeys                      Scan the keyspace for the largest keys, and report their sizes"
+        );
+        println!(
+            "    --bigkeys-sample <N>           Scan for the largest keys like --bigkeys, but stop after N keys"
+        );
+        println!(
+            "    --repeat <N>                   Send the command N times, or until Ctrl+C if N is -1"
+        );
+        println!("    --interval <SECONDS>           Wait this long between repeats");
+        println!(
+            "    --raw                          Print replies exactly as the server sent them"
+        );
+        println!(
+            "    --no-raw                       Print replies for humans, even if the output is not a terminal"
+        );
+        println!(
+            "    --output <json|csv|plain>      Print replies as JSON lines, CSV rows, or plain text like --raw"
+        );
+        println!(
+            "    --latency                      Measure how long the server takes to answer, until Ctrl+C"
+        );
+        println!(
+            "    --latency-history <SECONDS>    Measure latency, printing the statistics of every period this long"
+        );
+        println!(
+            "    --resp                         Speak RESP to the server, instead of asking it which protocols it speaks"
+        );
+        println!(
+            "    --inline                       Speak the inline protocol to the server, for servers without RESP"
+        );
+        println!(
+            "    --pass <PASSWORD>              Authenticate with this password [env: MINIREDIS_PASSWORD]"
+        );
+        println!(
+            "    --user <USER>                  Authenticate as this user instead of the default one"
+        );
         println!();
         println!("ARGS:");
         println!(
-            "    <ADDRESS>    The address of the server to connect to [default: 127.0.0.1:6379]"
+            "    <ADDRESS>    The address of the server, or several separated by commas to try in order [default: 127.0.0.1:6379]"
         );
+        println!("    <COMMAND>    A command to send instead of starting the prompt");
         println!();
         println!("EXAMPLES:");
         println!("    miniredis-client 127.0.0.1:6379");
+        println!("    miniredis-client 127.0.0.1:6379 SET msg \"hello world\"");
+        println!("    echo \"GET msg\" | miniredis-client 127.0.0.1:6379");
+        println!("    miniredis-client --repeat -1 --interval 0.5 127.0.0.1:6379 GET counter");
+        println!("    miniredis-client --latency-history 15 127.0.0.1:6379");
+        println!("    miniredis-client --file seed.txt 127.0.0.1:6379");
+        println!("    miniredis-client --pipe-bulk 127.0.0.1:6379 < commands.txt");
+        println!("    miniredis-client --bigkeys 127.0.0.1:6379");
         println!("    miniredis-client --help");
         println!();
+        Self::print_commands();
+    }
+
+    /// Prints the commands that can be typed into the prompt.
+    ///
+    /// This is the end of the help message, and what the `help` command prints.
+    fn print_commands() {
         println!("COMMANDS IN THE CLIENT:");
-        println!("    GET <KEY>             Get the value of a key");
-        println!("    SET <KEY> <VALUE>     Set the value of a key");
-        println!("    DEL <KEY>             Delete a key");
+        for info in COMMANDS {
+            match info.usage.len() {
+                0..22 => println!("    {:<22}{}", info.usage, info.summary),
+                _ => println!("    {}\n{:26}{}", info.usage, "", info.summary),
+            }
+        }
+        println!();
+        println!("LOCAL COMMANDS:");
+        println!("    help                  Print this list of commands");
+        println!("    clear                 Clear the screen");
+        println!("    timing on|off         Print the round-trip time after every reply, or stop");
+        println!("    history               Print the commands typed before, oldest first");
+        println!("    exit, quit            Leave the prompt, like Ctrl+D");
     }
 
-    /// Reads input from the user.
+    /// Connects to the server.
     ///
     /// # Returns
     ///
-    /// A string containing the input from the user.
+    /// A connection to the server, with the timeouts, the protocol, and the retries of the client,
+    /// authenticated if the client has a password.
     ///
     /// # Errors
     ///
-    /// If the input cannot be read, it will return an error.
-    fn read_input<R: BufRead>(&self, reader: &mut R) -> Result<String, MiniRedisError> {
-        let mut input = String::new();
-        reader
-            .read_line(&mut input)
-            .map_err(|_| MiniRedisError::StreamNotReadable)?;
-        Ok(input)
+    /// If the client fails to connect to the server, it will return an error,
+    /// which is [`MiniRedisError::AuthenticationFailed`] if the server rejects the password.
+    fn connect(&self) -> Result<Connection, MiniRedisError> {
+        let mut builder = Connection::builder(&self.address).options(self.connect_options);
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(protocol) = self.protocol {
+            builder = builder.protocol(protocol);
+        }
+        if let Some(user) = &self.user {
+            builder = builder.user(user);
+        }
+        if let Some(password) = &self.password {
+            builder = builder.password(password);
+        }
+        builder.connect()
+    }
+
+    /// Sends the command of the client, as many times as it repeats, and prints the responses.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the server accepted every command.
+    ///
+    /// # Errors
+    ///
+    /// If the client fails to connect to the server, the connection closes
+    /// before a response, or any response is an error, it will return an error.
+    fn run_command(&self) -> Result<(), MiniRedisError> {
+        let mut connection = self.connect()?;
+        let command = Command::new(&self.command[0], self.command[1..].to_vec()).to_string();
+        let rtt = Rtt::new(self.show_rtt, false);
+        if self.repeat == Some(1) {
+            return self.send_line(&command, &mut connection, rtt);
+        }
+
+        interrupt::catch();
+        let start = Instant::now();
+        let mut sent = 0;
+        let mut failed = 0;
+        let mut result = Ok(());
+        while self.repeat.is_none_or(|times| sent < times) {
+            if sent > 0 {
+                interrupt::sleep(self.interval);
+            }
+            if interrupt::requested() {
+                break;
+            }
+            sent += 1;
+            match self.send_line(&command, &mut connection, rtt) {
+                Ok(()) => {}
+                Err(MiniRedisError::ServerError { .. }) => failed += 1,
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+
+        eprintln!(
+            "{} commands, {} errors, {:.3?} elapsed",
+            sent,
+            failed,
+            start.elapsed()
+        );
+        result?;
+        if failed > 0 {
+            return Err(MiniRedisError::ServerError {
+                message: format!("{} of {} commands failed.", failed, sent),
+            });
+        }
+        Ok(())
     }
 
-    /// Sends input to the server.
+    /// Sends the commands in a file, and prints the errors and a summary.
+    ///
+    /// Commands are pipelined, up to [`FILE_CHUNK`] at once, unless the client aborts on errors,
+    /// which sends one command at a time so nothing runs after the first error.
+    /// Lines with a quote that is never closed are errors, and are not sent.
     ///
     /// # Arguments
     ///
-    /// * `input` - The input to send to the server.
-    /// * `writer` - The writer to send the input to.
+    /// * `path` - The path of the file.
     ///
     /// # Returns
     ///
-    /// A result indicating whether the input was sent successfully.
+    /// A result indicating whether the server accepted every command.
     ///
     /// # Errors
     ///
-    /// If the input cannot be written to the writer, it will return an error.
-    fn send_input<W: Write>(&self, input: &str, writer: &mut W) -> Result<(), MiniRedisError> {
-        writer
-            .write_all(input.as_bytes())
-            .map_err(|_| MiniRedisError::StreamNotWritable)?;
-        writer
-            .write_all(b"\n")
-            .map_err(|_| MiniRedisError::StreamNotWritable)?;
+    /// If the file cannot be read, the client fails to connect to the server,
+    /// or the connection fails, it will return an error.
+    /// If any command failed, it will return [`MiniRedisError::ServerError`].
+    fn run_file(&self, path: &str) -> Result<(), MiniRedisError> {
+        let script =
+            fs::read_to_string(path).map_err(|source| MiniRedisError::FileNotReadable {
+                path: path.to_string(),
+                source,
+            })?;
+        let commands = script_commands(&script);
+        let chunk = if self.abort_on_error { 1 } else { FILE_CHUNK };
+        let mut connection = self.connect()?;
+        let start = Instant::now();
+        let mut sent = 0;
+        let mut failed = 0;
+
+        let mut batch = Vec::with_capacity(chunk);
+        for (index, (number, line)) in commands.iter().enumerate() {
+            let unterminated = tokenizer::check_quotes(line).err();
+            if unterminated.is_none() {
+                batch.push((*number, *line));
+            }
+            let flush =
+                unterminated.is_some() || batch.len() == chunk || index + 1 == commands.len();
+            if flush && !batch.is_empty() {
+                let lines = batch.iter().map(|(_, line)| *line).collect::<Vec<&str>>();
+                let responses = connection.send_lines(&lines)?;
+                sent += batch.len();
+                for ((number, _), response) in batch.drain(..).zip(&responses) {
+                    if let Response::Error(_) = response {
+                        eprintln!(
+                            "line {}: {}",
+                            number,
+                            format_response(response, Format::Raw)
+                        );
+                        failed += 1;
+                    }
+                }
+            }
+            if let Some(e) = unterminated {
+                eprintln!("line {}: {}", number, Response::Error(e));
+                failed += 1;
+            }
+            if self.abort_on_error && failed > 0 {
+                break;
+            }
+        }
+
+        eprintln!(
+            "{} commands, {} errors, {:.3?} elapsed",
+            sent,
+            failed,
+            start.elapsed()
+        );
+        if failed > 0 {
+            return Err(MiniRedisError::ServerError {
+                message: format!("{} commands of {} failed.", failed, path),
+            });
+        }
         Ok(())
     }
 
-    /// Reads a response from the server.
+    /// Sends PING until Ctrl+C, and prints the statistics of the round trips in milliseconds.
+    ///
+    /// The statistics are printed on one line, which is redrawn in place after every sample.
+    /// With a history, the line is kept at the end of every period and the statistics start over.
     ///
     /// # Arguments
     ///
-    /// * `reader` - The reader to read the response from.
+    /// * `latency` - How to report the latency.
     ///
     /// # Returns
     ///
-    /// A result containing the response from the server.
-    /// If the response is empty, an error is returned.
+    /// A result indicating whether the server answered every PING until Ctrl+C.
     ///
     /// # Errors
     ///
-    /// If the response cannot be read, it will return an error.
-    fn read_response<R: BufRead>(&self, reader: &mut R) -> Result<String, MiniRedisError> {
-        let mut response = String::new();
-        reader
-            .read_line(&mut response)
-            .map_err(|_| MiniRedisError::StreamNotReadable)?;
-        Ok(response)
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use std::io::Cursor;
+    /// If the client fails to connect to the server, the connection fails,
+    /// the server replies with an error, or the output cannot be written, it will return an error.
+    fn run_latency(&self, latency: Latency) -> Result<(), MiniRedisError> {
+        let mut connection = self.connect()?;
+        interrupt::catch();
+        let mut samples = LatencySamples::new();
+        let mut period_start = Instant::now();
 
-    use super::*;
+        while !interrupt::requested() {
+            let start = Instant::now();
+            check_response(&connection.send_line("PING")?)?;
+            samples.record(start.elapsed());
 
-    #[test]
-    fn new_creates_client_with_given_address() {
-        let address = "192.168.1.1:8080";
-        let client = Client::new(address);
+            print!("\r\x1B[K{}", samples);
+            if let Latency::History(period) = latency
+                && period_start.elapsed() >= period
+            {
+                println!(
+                    " -- {:.2} seconds range",
+                    period_start.elapsed().as_secs_f64()
+                );
+                samples = LatencySamples::new();
+                period_start = Instant::now();
+            }
+            io::stdout()
+                .flush()
+                .map_err(|source| MiniRedisError::StreamNotFlushed { source })?;
+            interrupt::sleep(self.interval);
+        }
 
-        assert_eq!(address.to_string(), client.address);
+        println!();
+        Ok(())
     }
 
-    #[test]
-    fn from_args_uses_default_address_when_no_args_provided() {
-        let args = vec!["miniredis".to_string()];
-        let client = Client::from_args(&args);
+    /// Scans the keyspace with `SCAN`, asks for the size of every key, and reports the sizes.
+    ///
+    /// The keys are scanned on one connection while their sizes are asked for on another,
+    /// so the scan itself never blocks the server for longer than one batch.
+    /// A progress line is redrawn in place every [`BIGKEYS_PROGRESS`] keys,
+    /// or whenever a larger key is found.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the scan finished.
+    ///
+    /// # Errors
+    ///
+    /// If the client fails to connect to the server, the connection fails,
+    /// the server replies with an error, or the output cannot be written, it will return an error.
+    fn run_bigkeys(&self) -> Result<(), MiniRedisError> {
+        let mut scanner = self.connect()?;
+        let mut connection = self.connect()?;
+        let mut sizes = KeySizes::new("string", "bytes");
 
-        assert_eq!("127.0.0.1:6379".to_string(), client.address);
-    }
+        println!("Scanning the keyspace for the largest keys");
+        for key in scanner.scan_iter(None) {
+            if self
+                .bigkeys_sample
+                .is_some_and(|sample| sizes.count() >= sample)
+            {
+                break;
+            }
+            let key = key?;
+            let size = match connection.command(&["STRLEN", &key])? {
+                Response::Integer(size) => size.max(0) as usize,
+                response => return Err(unexpected_size(&key, &response)),
+            };
 
-    #[test]
-    fn from_args_uses_provided_address_when_args_given() {
-        let expected_address = "localhost:9999";
-        let args = vec!["miniredis".to_string(), expected_address.to_string()];
-        let client = Client::from_args(&args);
+            if sizes.record(&key, size) || sizes.count().is_multiple_of(BIGKEYS_PROGRESS) {
+                print!("\r\x1B[K{}", sizes.progress());
+                io::stdout()
+                    .flush()
+                    .map_err(|source| MiniRedisError::StreamNotFlushed { source })?;
+            }
+        }
 
-        assert_eq!(expected_address.to_string(), client.address);
+        println!("\r\x1B[K{}", sizes.progress());
+        println!();
+        println!("{}", sizes);
+        Ok(())
     }
 
-    #[test]
-    fn from_args_uses_first_argument_as_address() {
-        let expected_address = "test.example.com:1234";
-        let args = vec![
-            "miniredis".to_string(),
-            expected_address.to_string(),
-            "ignored_arg".to_string(),
-        ];
-        let client = Client::from_args(&args);
-
-        assert_eq!(expected_address.to_string(), client.address);
+    /// Sends a line to the server and prints the response.
+    ///
+    /// A line with a quote that is never closed is not sent,
+    /// and the error is printed the way an error reply would be.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The command to send, without a line ending.
+    /// * `connection` - The connection to send the command on.
+    /// * `rtt` - Where to print the time from sending the command to reading its response.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the server accepted the command.
+    ///
+    /// # Errors
+    ///
+    /// If the command cannot be sent, the connection closes before the response,
+    /// or the response cannot be read, it will return an error.
+    /// If the response is an error, it will return [`MiniRedisError::ServerError`].
+    /// If a quote is never closed, it will return [`MiniRedisError::UnterminatedQuote`].
+    /// If the client validates commands and refuses this one,
+    /// it will return [`MiniRedisError::ServerError`] without sending it.
+    fn send_line(
+        &self,
+        line: &str,
+        connection: &mut Connection,
+        rtt: Rtt,
+    ) -> Result<(), MiniRedisError> {
+        if let Err(e) = tokenizer::check_quotes(line) {
+            self.print_response(&Response::Error(e), None);
+            return Err(MiniRedisError::UnterminatedQuote {
+                line: line.to_string(),
+            });
+        }
+        if let Some(message) = self.validate.then(|| check_command(line).err()).flatten() {
+            let error = MiniRedisError::ServerError {
+                message: message.clone(),
+            };
+            self.print_response(&Response::Error(error), None);
+            return Err(MiniRedisError::ServerError { message });
+        }
+        let start = Instant::now();
+        let response = connection.send_line(line)?;
+        let elapsed = start.elapsed();
+        match rtt {
+            Rtt::Hidden => self.print_response(&response, None),
+            Rtt::Inline => self.print_response(&response, Some(elapsed)),
+            Rtt::Stderr => {
+                self.print_response(&response, None);
+                eprintln!("({})", format_rtt(elapsed));
+            }
+        }
+        check_response(&response)
+    }
+
+    /// Prompts for commands, sends them, and prints the responses.
+    ///
+    /// Local commands are handled by the client without asking the server,
+    /// and everything else is sent as it was typed. Error responses are printed
+    /// without leaving the prompt, and so are lines with a quote that is never closed,
+    /// which are not sent. The prompt ends on `exit`, `quit`, or the end of the input.
+    /// `timing on` and `timing off` start and stop printing round-trip times.
+    ///
+    /// Every line typed is kept in the history, which `history` prints.
+    /// The history is loaded from the history file of the client, if it has one,
+    /// and the new lines are appended to it when the prompt ends, except for `AUTH` commands.
+    /// If the file cannot be read or written, the prompt warns and goes on without it.
+    ///
+    /// If the connection is lost, the prompt ends with the error, unless the client retries,
+    /// in which case it says so, connects again, selects the same database,
+    /// and keeps prompting. The command that was lost is not sent again.
+    ///
+    /// The prompt shows the address of the server, and the selected database when it is
+    /// not the first one, like `127.0.0.1:6379[2]> `. Without a prompt, nothing but
+    /// the responses is printed.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The reader to read the commands from.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the prompt ended without a failure.
+    ///
+    /// # Errors
+    ///
+    /// If the client fails to connect to the server, read from the input or the stream,
+    /// or write to the stream or the output, it will return an error.
+    /// If the connection is lost, it will return an error, which is
+    /// [`MiniRedisError::StreamClosed`] if the server closed it, unless the client retries.
+    fn run_prompt<R: BufRead>(&self, input: &mut R) -> Result<(), MiniRedisError> {
+        let mut history = match &self.history {
+            Some(path) => History::load(Path::new(path), self.history_size).unwrap_or_else(|e| {
+                eprintln!("Warning: {} The history is only kept in memory.", e);
+                History::in_memory(self.history_size)
+            }),
+            None => History::in_memory(self.history_size),
+        };
+
+        let result = self.prompt_commands(input, &mut history);
+        if let Err(e) = history.save() {
+            eprintln!("Warning: {} The history of this session is lost.", e);
+        }
+        result
+    }
+
+    /// Prompts for commands until the prompt ends, as [`Client::run_prompt`] describes,
+    /// and adds them to a history.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The reader to read the commands from.
+    /// * `history` - The history to add the commands to.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the prompt ended without a failure.
+    ///
+    /// # Errors
+    ///
+    /// If the client fails to connect to the server, read from the input or the stream,
+    /// or write to the stream or the output, it will return an error.
+    fn prompt_commands<R: BufRead>(
+        &self,
+        input: &mut R,
+        history: &mut History,
+    ) -> Result<(), MiniRedisError> {
+        let mut connection = self.connect()?;
+        let mut database = 0;
+        let mut show_rtt = self.show_rtt;
+
+        if self.prompt {
+            println!("Connected to server at {}", connection.address());
+        }
+
+        loop {
+            if self.prompt {
+                print!("{}", prompt(connection.address(), database));
+                io::stdout()
+                    .flush()
+                    .map_err(|source| MiniRedisError::StreamNotFlushed { source })?;
+            }
+
+            let line = self.read_input(input)?;
+            if line.is_empty() {
+                println!();
+                break;
+            }
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let rtt = Rtt::new(show_rtt, self.prompt);
+            let local = LocalCommand::parse(line);
+            if local != Some(LocalCommand::Exit) {
+                history.push(line);
+            }
+            match local {
+                Some(LocalCommand::Exit) => break,
+                Some(LocalCommand::Help) => Self::print_commands(),
+                Some(LocalCommand::History) => {
+                    for (number, line) in history.entries().iter().enumerate() {
+                        println!("{:>5}  {}", number + 1, line);
+                    }
+                }
+                Some(LocalCommand::Clear) => print!("\x1B[2J\x1B[H"),
+                Some(LocalCommand::Timing(timing)) => {
+                    show_rtt = timing;
+                    println!("Timing is {}", if timing { "on" } else { "off" });
+                }
+                None => match self.send_line(line, &mut connection, rtt) {
+                    Ok(()) => database = selected_database(line).unwrap_or(database),
+                    Err(MiniRedisError::ServerError { .. })
+                    | Err(MiniRedisError::UnterminatedQuote { .. }) => {}
+                    Err(e) if is_connection_error(&e) && self.connect_options.retries > 0 => {
+                        eprintln!("{} Reconnecting to {}.", e, connection.address());
+                        connection = self.reconnect(database)?;
+                        println!("Reconnected to server at {}", connection.address());
+                    }
+                    Err(e) => return Err(e),
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Connects to the server again after the connection was lost.
+    ///
+    /// # Arguments
+    ///
+    /// * `database` - The database that was selected on the lost connection.
+    ///
+    /// # Returns
+    ///
+    /// A connection to the server, with the database selected again.
+    ///
+    /// # Errors
+    ///
+    /// If the client fails to connect to the server, or to select the database,
+    /// it will return an error.
+    fn reconnect(&self, database: u64) -> Result<Connection, MiniRedisError> {
+        let mut connection = self.connect()?;
+        if database != 0 {
+            connection.command(&["SELECT", &database.to_string()])?;
+        }
+        Ok(connection)
+    }
+
+    /// Sends every line of the input as a command and prints the responses.
+    ///
+    /// Empty lines are skipped. Every other line gets exactly one response,
+    /// so the responses can be matched up with the commands.
+    /// Only arrays and maps span several lines of output.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The reader to read the commands from.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the server accepted every command.
+    ///
+    /// # Errors
+    ///
+    /// If the client fails to connect to the server, read from the input or the stream,
+    /// or write to the stream, it will return an error.
+    /// If any response was an error, it will return an error once the input ends.
+    fn run_pipe<R: BufRead>(&self, input: &mut R) -> Result<(), MiniRedisError> {
+        let mut connection = self.connect()?;
+        let rtt = Rtt::new(self.show_rtt, false);
+        let mut failed = 0;
+
+        loop {
+            let line = self.read_input(input)?;
+            if line.is_empty() {
+                break;
+            }
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match self.send_line(line, &mut connection, rtt) {
+                Ok(()) => {}
+                Err(MiniRedisError::ServerError { .. })
+                | Err(MiniRedisError::UnterminatedQuote { .. }) => failed += 1,
+                Err(e) => return Err(e),
+            }
+        }
+
+        if failed > 0 {
+            return Err(MiniRedisError::ServerError {
+                message: format!("{} commands failed.", failed),
+            });
+        }
+        Ok(())
+    }
+
+    /// Sends every line of the input as fast as the server takes them, without waiting for
+    /// each response, and prints a summary once every response is in.
+    ///
+    /// A thread of its own reads the responses while the lines are sent.
+    /// At most [`PIPE_WINDOW`] commands are sent ahead of the responses read,
+    /// so neither the socket nor the memory of the client fills up with a file of any size.
+    /// Lines with a quote that is never closed, or that the client refuses, are errors,
+    /// and are not sent. The summary goes to stderr, with the first [`PIPE_ERRORS_SHOWN`]
+    /// errors and which command, and line of the input, they were.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The reader to read the commands from.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the server accepted every command.
+    ///
+    /// # Errors
+    ///
+    /// If the client fails to connect to the server, read from the input or the stream,
+    /// or write to the stream, it will return an error.
+    /// If any command failed, it will return [`MiniRedisError::ServerError`].
+    fn run_pipe_bulk<R: BufRead>(&self, input: &mut R) -> Result<(), MiniRedisError> {
+        let (mut requests, mut responses) = self.connect()?.split()?;
+        let start = Instant::now();
+        // A command holds a slot from before it is sent until its response is read.
+        let (window, in_flight) = mpsc::sync_channel::<(u64, u64)>(PIPE_WINDOW);
+        let reader = thread::spawn(move || {
+            let mut tally = PipeTally::default();
+            for (ordinal, number) in in_flight {
+                match responses.read()? {
+                    Response::Error(e) => tally.error(ordinal, number, Response::Error(e)),
+                    _ => tally.ok += 1,
+                }
+            }
+            Ok::<PipeTally, MiniRedisError>(tally)
+        });
+
+        let mut refused = PipeTally::default();
+        let mut sent = 0;
+        let mut ordinal = 0;
+        let mut number = 0;
+        let written = loop {
+            let line = match self.read_input(input) {
+                Ok(line) if line.is_empty() => break Ok(()),
+                Ok(line) => line,
+                Err(e) => break Err(e),
+            };
+            number += 1;
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.trim().is_empty() {
+                continue;
+            }
+            ordinal += 1;
+            if let Err(e) = tokenizer::check_quotes(line) {
+                refused.error(ordinal, number, Response::Error(e));
+                continue;
+            }
+            if let Some(message) = self.validate.then(|| check_command(line).err()).flatten() {
+                let error = MiniRedisError::ServerError { message };
+                refused.error(ordinal, number, Response::Error(error));
+                continue;
+            }
+            match window.try_send((ordinal, number)) {
+                Ok(()) => {}
+                Err(TrySendError::Full(slot)) => {
+                    // Waiting for a slot waits for responses, to commands that must be sent first.
+                    if let Err(e) = requests.flush() {
+                        break Err(e);
+                    }
+                    if window.send(slot).is_err() {
+                        break Ok(());
+                    }
+                }
+                // The reader stopped, and says why once joined.
+                Err(TrySendError::Disconnected(_)) => break Ok(()),
+            }
+            if let Err(e) = requests.send_line(line) {
+                break Err(e);
+            }
+            sent += 1;
+        };
+        let written = written.and_then(|()| requests.flush());
+        if written.is_err() {
+            // The reader would otherwise wait for responses to commands that never went out.
+            requests.abort();
+        }
+        drop(window);
+        let read = reader.join().unwrap_or(Err(MiniRedisError::StreamClosed));
+
+        let read = read?;
+        written?;
+        let errors = refused.merge(read);
+        eprintln!(
+            "{} commands, {} OK, {} errors, {:.3?} elapsed",
+            sent,
+            errors.ok,
+            errors.failed,
+            start.elapsed()
+        );
+        for (ordinal, number, error) in &errors.first {
+            eprintln!(
+                "command {} (line {}): {}",
+                ordinal,
+                number,
+                format_response(error, Format::Raw)
+            );
+        }
+        if errors.failed > 0 {
+            return Err(MiniRedisError::ServerError {
+                message: format!("{} commands failed.", errors.failed),
+            });
+        }
+        Ok(())
+    }
+
+    /// Reads input from the user.
+    ///
+    /// # Returns
+    ///
+    /// A string containing the input from the user.
+    ///
+    /// # Errors
+    ///
+    /// If the input cannot be read, it will return an error.
+    fn read_input<R: BufRead>(&self, reader: &mut R) -> Result<String, MiniRedisError> {
+        let mut input = String::new();
+        reader
+            .read_line(&mut input)
+            .map_err(|source| MiniRedisError::StreamNotReadable { source })?;
+        Ok(input)
+    }
+
+    /// Prints a response in the format of the client.
+    ///
+    /// # Arguments
+    ///
+    /// * `response` - The response to print.
+    /// * `rtt` - The round-trip time of the command, to print after the response, if any.
+    fn print_response(&self, response: &Response, rtt: Option<Duration>) {
+        let format = self.format.unwrap_or(if io::stdout().is_terminal() {
+            Format::Pretty
+        } else {
+            Format::Raw
+        });
+        let response = format_response(response, format);
+        match rtt {
+            Some(rtt) => println!("{} ({})", response, format_rtt(rtt)),
+            None => println!("{}", response),
+        }
+    }
+}
+
+/// Builds a [`Client`] with options.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use miniredis::client::Client;
+/// use std::time::Duration;
+///
+/// let client = Client::builder("127.0.0.1:6379")
+///     .timeout(Duration::from_secs(5))
+///     .command(vec!["GET".to_string(), "key".to_string()])
+///     .build();
+/// client.run();
+/// ```
+pub struct ClientBuilder {
+    client: Client,
+}
+
+impl ClientBuilder {
+    /// Sets a command to send instead of starting the prompt.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The name and arguments of the command, one word per element.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::client::Client;
+    ///
+    /// let builder = Client::builder("127.0.0.1:6379")
+    ///     .command(vec!["SET".to_string(), "msg".to_string(), "hello world".to_string()]);
+    /// ```
+    pub fn command(mut self, command: Vec<String>) -> Self {
+        self.client.command = command;
+        self
+    }
+
+    /// Sets whether to read commands from the input without prompting,
+    /// even if the input is a terminal.
+    ///
+    /// # Arguments
+    ///
+    /// * `pipe` - Whether to use pipe mode.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::client::Client;
+    ///
+    /// let builder = Client::builder("127.0.0.1:6379").pipe(true);
+    /// ```
+    pub fn pipe(mut self, pipe: bool) -> Self {
+        self.client.pipe = pipe;
+        self
+    }
+
+    /// Sets whether to send every line of the input without waiting for the responses,
+    /// and print a summary of them instead, for loading data in bulk.
+    ///
+    /// # Arguments
+    ///
+    /// * `pipe_bulk` - Whether to use bulk pipe mode.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::client::Client;
+    ///
+    /// let builder = Client::builder("127.0.0.1:6379").pipe_bulk(true);
+    /// ```
+    pub fn pipe_bulk(mut self, pipe_bulk: bool) -> Self {
+        self.client.pipe_bulk = pipe_bulk;
+        self
+    }
+
+    /// Sets how long to wait for the connection to the server.
+    ///
+    /// Without it, connecting waits as long as the operating system does.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - How long to wait, more than zero.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::client::Client;
+    /// use std::time::Duration;
+    ///
+    /// let builder = Client::builder("127.0.0.1:6379").connect_timeout(Duration::from_secs(1));
+    /// ```
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.client.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how long to wait for the server to accept a command or send a response.
+    ///
+    /// Without it, the client waits forever.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - How long to wait, more than zero.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::client::Client;
+    /// use std::time::Duration;
+    ///
+    /// let builder = Client::builder("127.0.0.1:6379").timeout(Duration::from_secs(5));
+    /// ```
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.client.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how many times to send the command.
+    ///
+    /// # Arguments
+    ///
+    /// * `times` - The number of times, or None to repeat until Ctrl+C.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::client::Client;
+    ///
+    /// let builder = Client::builder("127.0.0.1:6379").repeat(Some(10));
+    /// ```
+    pub fn repeat(mut self, times: Option<u64>) -> Self {
+        self.client.repeat = times;
+        self
+    }
+
+    /// Sets how long to wait between repeats of the command.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - How long to wait.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::client::Client;
+    /// use std::time::Duration;
+    ///
+    /// let builder = Client::builder("127.0.0.1:6379")
+    ///     .repeat(None)
+    ///     .interval(Duration::from_millis(100));
+    /// ```
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.client.interval = interval;
+        self
+    }
+
+    /// Sets how to print responses, instead of deciding by whether the output is a terminal.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - How to print responses.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::client::Client;
+    /// use miniredis::format::Format;
+    ///
+    /// let builder = Client::builder("127.0.0.1:6379").format(Format::Raw);
+    /// ```
+    pub fn format(mut self, format: Format) -> Self {
+        self.client.format = Some(format);
+        self
+    }
+
+    /// Makes the client measure the latency of the server until Ctrl+C, instead of sending commands.
+    ///
+    /// The interval of the client is the wait between samples.
+    ///
+    /// # Arguments
+    ///
+    /// * `latency` - How to report the latency.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::client::{Client, Latency};
+    /// use std::time::Duration;
+    ///
+    /// let builder = Client::builder("127.0.0.1:6379")
+    ///     .latency(Latency::Live)
+    ///     .interval(Duration::from_millis(10));
+    /// ```
+    pub fn latency(mut self, latency: Latency) -> Self {
+        self.client.latency = Some(latency);
+        self
+    }
+
+    /// Sets whether the prompt prints the address of the server before every command.
+    ///
+    /// # Arguments
+    ///
+    /// * `prompt` - Whether to print the prompt.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::client::Client;
+    ///
+    /// let builder = Client::builder("127.0.0.1:6379").prompt(false);
+    /// ```
+    pub fn prompt(mut self, prompt: bool) -> Self {
+        self.client.prompt = prompt;
+        self
+    }
+
+    /// Sets the protocol to speak to the server, instead of asking the server with `HELLO`.
+    ///
+    /// # Arguments
+    ///
+    /// * `protocol` - The protocol to speak.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::client::Client;
+    /// use miniredis::connection::Protocol;
+    ///
+    /// let builder = Client::builder("127.0.0.1:6379").protocol(Protocol::Inline);
+    /// ```
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        self.client.protocol = Some(protocol);
+        self
+    }
+
+    /// Sets a file of commands to send instead of starting the prompt.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file, with one command per line.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::client::Client;
+    ///
+    /// let builder = Client::builder("127.0.0.1:6379").file("seed.txt");
+    /// ```
+    pub fn file(mut self, path: &str) -> Self {
+        self.client.file = Some(path.to_string());
+        self
+    }
+
+    /// Sets whether to stop sending the commands of a file at the first error.
+    ///
+    /// # Arguments
+    ///
+    /// * `abort_on_error` - Whether to stop at the first error.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::client::Client;
+    ///
+    /// let builder = Client::builder("127.0.0.1:6379").file("seed.txt").abort_on_error(true);
+    /// ```
+    pub fn abort_on_error(mut self, abort_on_error: bool) -> Self {
+        self.client.abort_on_error = abort_on_error;
+        self
+    }
+
+    /// Makes the client scan the keyspace for the largest keys, instead of sending commands.
+    ///
+    /// # Arguments
+    ///
+    /// * `bigkeys` - Whether to scan for the largest keys.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::client::Client;
+    ///
+    /// let builder = Client::builder("127.0.0.1:6379").bigkeys(true);
+    /// ```
+    pub fn bigkeys(mut self, bigkeys: bool) -> Self {
+        self.client.bigkeys = bigkeys;
+        self
+    }
+
+    /// Caps how many keys a scan for the largest keys examines.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample` - How many keys to examine at most.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::client::Client;
+    ///
+    /// let builder = Client::builder("127.0.0.1:6379").bigkeys(true).bigkeys_sample(10_000);
+    /// ```
+    pub fn bigkeys_sample(mut self, sample: usize) -> Self {
+        self.client.bigkeys_sample = Some(sample);
+        self
+    }
+
+    /// Makes the client print the round-trip time of every command after its response.
+    ///
+    /// At the prompt, the time follows the response, like `"value" (1.3ms)`.
+    /// Otherwise it is printed to stderr, so the output can still be read by a program.
+    ///
+    /// # Arguments
+    ///
+    /// * `show_rtt` - Whether to print round-trip times.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::client::Client;
+    ///
+    /// let builder = Client::builder("127.0.0.1:6379").show_rtt(true);
+    /// ```
+    pub fn show_rtt(mut self, show_rtt: bool) -> Self {
+        self.client.show_rtt = show_rtt;
+        self
+    }
+
+    /// Keeps the commands typed into the prompt in a file, across sessions.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the history file.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::client::Client;
+    ///
+    /// let builder = Client::builder("127.0.0.1:6379").history("/tmp/miniredis_history");
+    /// ```
+    pub fn history(mut self, path: &str) -> Self {
+        self.client.history = Some(path.to_string());
+        self
+    }
+
+    /// Sets how many commands the history keeps, 1000 by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - How many of the newest commands to keep.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::client::Client;
+    ///
+    /// let builder = Client::builder("127.0.0.1:6379").history_size(100);
+    /// ```
+    pub fn history_size(mut self, size: usize) -> Self {
+        self.client.history_size = size;
+        self
+    }
+
+    /// Makes the client refuse commands it does not know, or with the wrong number
+    /// of arguments, before sending them. Validation is on by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `validate` - Whether to validate commands.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::client::Client;
+    ///
+    /// let builder = Client::builder("127.0.0.1:6379").validate(false);
+    /// ```
+    pub fn validate(mut self, validate: bool) -> Self {
+        self.client.validate = validate;
+        self
+    }
+
+    /// Sets how the client retries when the server cannot be reached.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The retry policy.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::client::Client;
+    /// use miniredis::connection::ConnectOptions;
+    ///
+    /// let builder = Client::builder("127.0.0.1:6379").connect_options(ConnectOptions {
+    ///     retries: 5,
+    ///     ..ConnectOptions::default()
+    /// });
+    /// ```
+    pub fn connect_options(mut self, options: ConnectOptions) -> Self {
+        self.client.connect_options = options;
+        self
+    }
+
+    /// Sets the password to authenticate with, right after connecting.
+    ///
+    /// # Arguments
+    ///
+    /// * `password` - The password of the user.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::client::Client;
+    ///
+    /// let builder = Client::builder("127.0.0.1:6379").password("secret");
+    /// ```
+    pub fn password(mut self, password: &str) -> Self {
+        self.client.password = Some(password.to_string());
+        self
+    }
+
+    /// Sets the user to authenticate as, instead of the default user.
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - The name of the user.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::client::Client;
+    ///
+    /// let builder = Client::builder("127.0.0.1:6379").user("alice").password("secret");
+    /// ```
+    pub fn user(mut self, user: &str) -> Self {
+        self.client.user = Some(user.to_string());
+        self
+    }
+
+    /// Builds the client.
+    ///
+    /// # Returns
+    ///
+    /// A new client with the options of the builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::client::Client;
+    ///
+    /// let client = Client::builder("127.0.0.1:6379").build();
+    /// ```
+    pub fn build(self) -> Client {
+        self.client
+    }
+}
+
+/// Builds the error for an option with a missing or invalid value.
+///
+/// # Arguments
+///
+/// * `option` - The name of the option.
+/// * `value` - The value following the option, if any.
+///
+/// # Returns
+///
+/// A [`MiniRedisError::InvalidArguments`] holding the option and its value.
+fn invalid_option(option: &str, value: Option<&String>) -> MiniRedisError {
+    MiniRedisError::InvalidArguments {
+        arguments: [Some(option.to_string()), value.cloned()]
+            .into_iter()
+            .flatten()
+            .collect(),
+    }
+}
+
+/// Parses the value of an option that takes a number of seconds.
+///
+/// # Arguments
+///
+/// * `option` - The name of the option, for the error.
+/// * `value` - The value following the option, if any.
+///
+/// # Returns
+///
+/// The duration, which may be zero.
+///
+/// # Errors
+///
+/// If the value is missing, not a number, or negative, it will return an error.
+fn parse_seconds(option: &str, value: Option<&String>) -> Result<Duration, MiniRedisError> {
+    value
+        .and_then(|value| value.parse::<f64>().ok())
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| invalid_option(option, value))
+}
+
+/// Parses the value of a timeout option.
+///
+/// # Arguments
+///
+/// * `option` - The name of the option, for the error.
+/// * `value` - The value following the option, if any.
+///
+/// # Returns
+///
+/// The timeout.
+///
+/// # Errors
+///
+/// If the value is missing, not a number, or not more than zero, it will return an error.
+fn parse_timeout(option: &str, value: Option<&String>) -> Result<Duration, MiniRedisError> {
+    match parse_seconds(option, value)? {
+        timeout if timeout.is_zero() => Err(invalid_option(option, value)),
+        timeout => Ok(timeout),
+    }
+}
+
+/// Parses the value of the repeat option.
+///
+/// # Arguments
+///
+/// * `option` - The name of the option, for the error.
+/// * `value` - The value following the option, if any.
+///
+/// # Returns
+///
+/// The number of times to send the command, or None for -1, which repeats until Ctrl+C.
+///
+/// # Errors
+///
+/// If the value is missing, or neither a positive number nor -1, it will return an error.
+fn parse_repeat(option: &str, value: Option<&String>) -> Result<Option<u64>, MiniRedisError> {
+    match value.and_then(|value| value.parse::<i64>().ok()) {
+        Some(-1) => Ok(None),
+        Some(times) if times > 0 => Ok(Some(times as u64)),
+        _ => Err(invalid_option(option, value)),
+    }
+}
+
+/// Parses the value of an option that takes a whole number.
+///
+/// # Arguments
+///
+/// * `option` - The name of the option, for the error.
+/// * `value` - The value following the option, if any.
+///
+/// # Returns
+///
+/// The number, which may be zero.
+///
+/// # Errors
+///
+/// If the value is missing, or not a whole number that fits, it will return an error.
+fn parse_number(option: &str, value: Option<&String>) -> Result<u32, MiniRedisError> {
+    value
+        .and_then(|value| value.parse::<u32>().ok())
+        .ok_or_else(|| invalid_option(option, value))
+}
+
+/// Parses the value of the output option.
+///
+/// # Arguments
+///
+/// * `option` - The name of the option, for the error.
+/// * `value` - The value following the option, if any.
+///
+/// # Returns
+///
+/// The format to print responses in.
+///
+/// # Errors
+///
+/// If the value is missing, or not `json`, `csv`, or `plain`, it will return an error.
+fn parse_output(option: &str, value: Option<&String>) -> Result<Format, MiniRedisError> {
+    match value.map(String::as_str) {
+        Some("json") => Ok(Format::Json),
+        Some("csv") => Ok(Format::Csv),
+        Some("plain") => Ok(Format::Raw),
+        _ => Err(invalid_option(option, value)),
+    }
+}
+
+/// Picks the commands out of a file of commands.
+///
+/// # Arguments
+///
+/// * `script` - The contents of the file.
+///
+/// # Returns
+///
+/// The line number, starting at 1, and the line of every command,
+/// skipping blank lines and lines starting with `#`.
+fn script_commands(script: &str) -> Vec<(usize, &str)> {
+    script
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+}
+
+/// Parses the value of an option that takes any text.
+///
+/// # Arguments
+///
+/// * `option` - The name of the option, for the error.
+/// * `value` - The value following the option, if any.
+///
+/// # Returns
+///
+/// The value.
+///
+/// # Errors
+///
+/// If the value is missing, it will return an error.
+fn parse_value(option: &str, value: Option<&String>) -> Result<String, MiniRedisError> {
+    value.cloned().ok_or_else(|| invalid_option(option, value))
+}
+
+/// How the commands sent in bulk pipe mode went.
+#[derive(Default)]
+struct PipeTally {
+    /// The number of commands the server accepted.
+    ok: usize,
+    /// The number of commands that failed.
+    failed: usize,
+    /// The first errors, with the command and the line of the input they were.
+    first: Vec<(u64, u64, Response)>,
+}
+
+impl PipeTally {
+    /// Counts a failed command, and keeps it if it is one of the first.
+    ///
+    /// # Arguments
+    ///
+    /// * `ordinal` - Which command of the input it was, from 1.
+    /// * `number` - Which line of the input it was, from 1.
+    /// * `error` - The error.
+    fn error(&mut self, ordinal: u64, number: u64, error: Response) {
+        self.failed += 1;
+        if self.first.len() < PIPE_ERRORS_SHOWN {
+            self.first.push((ordinal, number, error));
+        }
+    }
+
+    /// Adds up two tallies of the same input.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The other tally.
+    ///
+    /// # Returns
+    ///
+    /// The tally of both, keeping the first errors of either.
+    fn merge(mut self, other: PipeTally) -> PipeTally {
+        self.ok += other.ok;
+        self.failed += other.failed;
+        self.first.extend(other.first);
+        self.first.sort_by_key(|(ordinal, _, _)| *ordinal);
+        self.first.truncate(PIPE_ERRORS_SHOWN);
+        self
+    }
+}
+
+/// The statistics of the latency samples taken in latency mode.
+struct LatencySamples {
+    count: u32,
+    min: Duration,
+    max: Duration,
+    total: Duration,
+    last: Duration,
+}
+
+impl LatencySamples {
+    /// Creates statistics without any samples.
+    fn new() -> Self {
+        Self {
+            count: 0,
+            min: Duration::MAX,
+            max: Duration::ZERO,
+            total: Duration::ZERO,
+            last: Duration::ZERO,
+        }
+    }
+
+    /// Adds a sample to the statistics.
+    ///
+    /// # Arguments
+    ///
+    /// * `latency` - How long the round trip took.
+    fn record(&mut self, latency: Duration) {
+        self.count += 1;
+        self.min = self.min.min(latency);
+        self.max = self.max.max(latency);
+        self.total += latency;
+        self.last = latency;
+    }
+
+    /// Gets the average latency of the samples.
+    ///
+    /// # Returns
+    ///
+    /// The average latency, or zero without samples.
+    fn average(&self) -> Duration {
+        self.total.checked_div(self.count).unwrap_or_default()
+    }
+}
+
+impl std::fmt::Display for LatencySamples {
+    /// Formats the statistics in milliseconds, like `min: 0.112, max: 1.401, avg: 0.245, last: 0.201 (1024 samples)`.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - The formatter to write the statistics to.
+    ///
+    /// # Errors
+    ///
+    /// If the statistics cannot be formatted, it will return an error.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let millis = |latency: Duration| latency.as_secs_f64() * 1000.0;
+        write!(
+            f,
+            "min: {:.3}, max: {:.3}, avg: {:.3}, last: {:.3} ({} samples)",
+            millis(self.min.min(self.max)),
+            millis(self.max),
+            millis(self.average()),
+            millis(self.last),
+            self.count
+        )
+    }
+}
+
+/// Where the client prints the round-trip time of a command.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Rtt {
+    /// Does not print it.
+    Hidden,
+    /// Prints it after the response, on the same line.
+    Inline,
+    /// Prints it to stderr, on a line of its own.
+    Stderr,
+}
+
+impl Rtt {
+    /// Picks where round-trip times are printed.
+    ///
+    /// # Arguments
+    ///
+    /// * `show_rtt` - Whether to print round-trip times.
+    /// * `prompt` - Whether the responses are read by someone at a prompt.
+    ///
+    /// # Returns
+    ///
+    /// [`Rtt::Hidden`] unless printing round-trip times, [`Rtt::Inline`] at a prompt,
+    /// and [`Rtt::Stderr`] otherwise, so the output can still be read by a program.
+    fn new(show_rtt: bool, prompt: bool) -> Self {
+        match (show_rtt, prompt) {
+            (false, _) => Rtt::Hidden,
+            (true, true) => Rtt::Inline,
+            (true, false) => Rtt::Stderr,
+        }
+    }
+}
+
+/// Formats a round-trip time for people, like `1.3ms`.
+///
+/// # Arguments
+///
+/// * `rtt` - The round-trip time.
+///
+/// # Returns
+///
+/// The time in microseconds below a millisecond, in milliseconds below a second,
+/// and in seconds otherwise.
+fn format_rtt(rtt: Duration) -> String {
+    if rtt < Duration::from_millis(1) {
+        format!("{}µs", rtt.as_micros())
+    } else if rtt < Duration::from_secs(1) {
+        format!("{:.1}ms", rtt.as_secs_f64() * 1000.0)
+    } else {
+        format!("{:.2}s", rtt.as_secs_f64())
+    }
+}
+
+/// The sizes of the keys of one type, as found in big keys mode.
+struct KeySizes {
+    kind: &'static str,
+    unit: &'static str,
+    sizes: Vec<usize>,
+    total: usize,
+    biggest: Option<(String, usize)>,
+}
+
+impl KeySizes {
+    /// Creates sizes without any keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - The type of the keys, like `string`.
+    /// * `unit` - What the sizes count, like `bytes`.
+    fn new(kind: &'static str, unit: &'static str) -> Self {
+        Self {
+            kind,
+            unit,
+            sizes: Vec::new(),
+            total: 0,
+            biggest: None,
+        }
+    }
+
+    /// Adds the size of a key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key.
+    /// * `size` - The size of its value.
+    ///
+    /// # Returns
+    ///
+    /// True if the key is the largest one so far, false otherwise.
+    fn record(&mut self, key: &str, size: usize) -> bool {
+        self.sizes.push(size);
+        self.total += size;
+        let biggest = self
+            .biggest
+            .as_ref()
+            .is_none_or(|(_, biggest)| size > *biggest);
+        if biggest {
+            self.biggest = Some((key.to_string(), size));
+        }
+        biggest
+    }
+
+    /// Gets how many keys were added.
+    ///
+    /// # Returns
+    ///
+    /// The number of keys.
+    fn count(&self) -> usize {
+        self.sizes.len()
+    }
+
+    /// Describes how far the scan got, like `Scanned 1200 keys, biggest string so far "a" with 812 bytes`.
+    ///
+    /// # Returns
+    ///
+    /// The progress line.
+    fn progress(&self) -> String {
+        match &self.biggest {
+            Some((key, size)) => format!(
+                "Scanned {} keys, biggest {} so far {:?} with {} {}",
+                self.count(),
+                self.kind,
+                key,
+                size,
+                self.unit
+            ),
+            None => format!("Scanned {} keys", self.count()),
+        }
+    }
+
+    /// Picks the size below which a share of the keys fall.
+    ///
+    /// # Arguments
+    ///
+    /// * `sorted` - The sizes, from smallest to largest.
+    /// * `percentile` - The share of the keys, between 0 and 100.
+    ///
+    /// # Returns
+    ///
+    /// The smallest size that at least that share of the keys is not larger than.
+    fn percentile(sorted: &[usize], percentile: f64) -> usize {
+        let rank = (percentile / 100.0 * sorted.len() as f64).ceil() as usize;
+        sorted[rank.clamp(1, sorted.len()) - 1]
+    }
+}
+
+impl std::fmt::Display for KeySizes {
+    /// Formats the report of the sizes, with the largest key and the distribution of the sizes.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - The formatter to write the report to.
+    ///
+    /// # Errors
+    ///
+    /// If the report cannot be formatted, it will return an error.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "-------- summary -------")?;
+        writeln!(f, "Sampled {} keys in the keyspace", self.count())?;
+        let Some((key, size)) = &self.biggest else {
+            return write!(f, "No {} keys found", self.kind);
+        };
+        writeln!(
+            f,
+            "Biggest {} found {:?} has {} {}",
+            self.kind, key, size, self.unit
+        )?;
+        writeln!(
+            f,
+            "{} {}s with {} {} (avg size {:.2})",
+            self.count(),
+            self.kind,
+            self.total,
+            self.unit,
+            self.total as f64 / self.count() as f64
+        )?;
+
+        let mut sorted = self.sizes.clone();
+        sorted.sort_unstable();
+        let percentiles =
+            [50.0, 90.0, 99.0, 100.0].map(|percentile| Self::percentile(&sorted, percentile));
+        write!(
+            f,
+            "Sizes in {}: p50 {}, p90 {}, p99 {}, max {}",
+            self.unit, percentiles[0], percentiles[1], percentiles[2], percentiles[3]
+        )
+    }
+}
+
+/// Builds the error for a reply to a size query that is not a size.
+///
+/// # Arguments
+///
+/// * `key` - The key whose size was asked for.
+/// * `response` - The reply.
+///
+/// # Returns
+///
+/// A [`MiniRedisError::ServerError`] describing the reply.
+fn unexpected_size(key: &str, response: &Response) -> MiniRedisError {
+    MiniRedisError::ServerError {
+        message: format!("Unexpected size of {:?}: {:?}", key, response),
+    }
+}
+
+/// A command the prompt handles itself, instead of sending it to the server.
+#[derive(Debug, PartialEq)]
+enum LocalCommand {
+    /// Leaves the prompt.
+    Exit,
+    /// Prints the commands that can be typed into the prompt.
+    Help,
+    /// Clears the screen.
+    Clear,
+    /// Starts or stops printing round-trip times.
+    Timing(bool),
+    /// Prints the commands typed before.
+    History,
+}
+
+impl LocalCommand {
+    /// Parses a line typed into the prompt as a local command.
+    ///
+    /// Local commands are single words, in any case, with any surrounding whitespace,
+    /// except for `timing on` and `timing off`.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The line typed into the prompt.
+    ///
+    /// # Returns
+    ///
+    /// The local command, or None if the line should be sent to the server.
+    fn parse(line: &str) -> Option<Self> {
+        match line.trim().to_ascii_lowercase().as_str() {
+            "exit" | "quit" => Some(LocalCommand::Exit),
+            "help" => Some(LocalCommand::Help),
+            "clear" => Some(LocalCommand::Clear),
+            "history" => Some(LocalCommand::History),
+            line => match line.split_whitespace().collect::<Vec<&str>>()[..] {
+                ["timing", "on"] => Some(LocalCommand::Timing(true)),
+                ["timing", "off"] => Some(LocalCommand::Timing(false)),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Builds the prompt printed before every command.
+///
+/// # Arguments
+///
+/// * `address` - The address of the server.
+/// * `database` - The index of the selected database.
+///
+/// # Returns
+///
+/// The prompt, like `127.0.0.1:6379> `, with the database after the address unless it is 0.
+fn prompt(address: &str, database: u64) -> String {
+    match database {
+        0 => format!("{}> ", address),
+        database => format!("{}[{}]> ", address, database),
+    }
+}
+
+/// Finds the database a line selects.
+///
+/// # Arguments
+///
+/// * `line` - A line the server accepted.
+///
+/// # Returns
+///
+/// The index of the database, or None if the line is not a `SELECT` of one.
+fn selected_database(line: &str) -> Option<u64> {
+    match Command::parse(line)? {
+        Command { name, args } if name == "SELECT" && args.len() == 1 => args[0].parse().ok(),
+        _ => None,
+    }
+}
+
+/// Checks a line against the commands the client knows, before sending it.
+///
+/// # Arguments
+///
+/// * `line` - The line to check.
+///
+/// # Returns
+///
+/// A result indicating whether the line is a known command with the right number and kinds of arguments.
+///
+/// # Errors
+///
+/// If the command is unknown, it will return a message suggesting a known command
+/// with a similar name, if there is one. If the command has the wrong number or kinds
+/// of arguments, it will return a message with its usage.
+fn check_command(line: &str) -> Result<(), String> {
+    let Some(command) = Command::parse(line) else {
+        return Ok(());
+    };
+    match CommandInfo::find(&command.name) {
+        Some(info) if info.check(&command.args).is_ok() => Ok(()),
+        Some(info) => Err(format!(
+            "Invalid arguments for {}. Usage: {}",
+            info.name, info.usage
+        )),
+        None => Err(match CommandInfo::closest(&command.name) {
+            Some(info) => format!(
+                "Invalid command: {}. Did you mean {}?",
+                command.name, info.name
+            ),
+            None => MiniRedisError::InvalidCommand {
+                command: command.name,
+            }
+            .to_string(),
+        }),
+    }
+}
+
+/// Checks whether a response from the server is an error.
+///
+/// # Arguments
+///
+/// * `response` - The response.
+///
+/// # Returns
+///
+/// A result indicating whether the response is a success.
+/// Errors inside an array or a map do not make the response an error.
+///
+/// # Errors
+///
+/// If the response is an error, it will return [`MiniRedisError::ServerError`]
+/// holding the message of the error.
+fn check_response(response: &Response) -> Result<(), MiniRedisError> {
+    match response {
+        Response::Error(MiniRedisError::ServerError { message }) => {
+            Err(MiniRedisError::ServerError {
+                message: message.clone(),
+            })
+        }
+        Response::Error(e) => Err(MiniRedisError::ServerError {
+            message: e.to_string(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn new_creates_client_with_given_address() {
+        let address = "192.168.1.1:8080";
+        let client = Client::new(address);
+
+        assert_eq!(address.to_string(), client.address);
+    }
+
+    #[test]
+    fn from_args_uses_default_address_when_no_args_provided() {
+        let args = vec!["miniredis".to_string()];
+        let client = Client::from_args(&args).unwrap();
+
+        assert_eq!("127.0.0.1:6379".to_string(), client.address);
+    }
+
+    #[test]
+    fn from_args_uses_provided_address_when_args_given() {
+        let expected_address = "localhost:9999";
+        let args = vec!["miniredis".to_string(), expected_address.to_string()];
+        let client = Client::from_args(&args).unwrap();
+
+        assert_eq!(expected_address.to_string(), client.address);
+    }
+
+    #[test]
+    fn from_args_has_no_command_when_only_address_given() {
+        let args = vec!["miniredis".to_string(), "localhost:9999".to_string()];
+        let client = Client::from_args(&args).unwrap();
+
+        assert!(client.command.is_empty());
     }
 
     #[test]
-    fn read_input_reads_line_from_reader() {
-        let client = Client::new("127.0.0.1:6379");
-        let input_data = "test input\n";
-        let cursor = Cursor::new(input_data.as_bytes());
-        let mut reader = BufReader::new(cursor);
+    fn from_args_keeps_each_trailing_argument_as_one_command_word() {
+        let args = ["miniredis", "localhost:9999", "SET", "msg", "hello world"].map(str::to_string);
+        let client = Client::from_args(&args).unwrap();
 
-        let result = client.read_input(&mut reader).unwrap();
+        assert_eq!("localhost:9999".to_string(), client.address);
+        assert_eq!(
+            vec![
+                "SET".to_string(),
+                "msg".to_string(),
+                "hello world".to_string()
+            ],
+            client.command
+        );
+    }
 
-        assert_eq!("test input\n".to_string(), result);
+    #[test]
+    fn from_args_reads_pipe_flag_before_address() {
+        let args = ["miniredis", "--pipe", "localhost:9999"].map(str::to_string);
+        let client = Client::from_args(&args).unwrap();
+
+        assert!(client.pipe);
+        assert_eq!("localhost:9999".to_string(), client.address);
+        assert!(client.command.is_empty());
     }
 
     #[test]
-    fn send_input_writes_input_with_newline() {
-        let client = Client::new("127.0.0.1:6379");
-        let mut output = Vec::new();
-        let input = "SET key value";
+    fn from_args_does_not_use_pipe_mode_by_default() {
+        let client = Client::from_args(&["miniredis".to_string()]).unwrap();
+
+        assert!(!client.pipe);
+        assert!(!client.pipe_bulk);
+    }
+
+    #[test]
+    fn from_args_reads_pipe_bulk_flag() {
+        let args = ["miniredis", "--pipe-bulk", "localhost:9999"].map(str::to_string);
+        let client = Client::from_args(&args).unwrap();
+
+        assert!(client.pipe_bulk);
+        assert!(client.command.is_empty());
+    }
+
+    #[test]
+    fn pipe_tally_keeps_the_first_errors_of_both_tallies() {
+        let error = || Response::Error(MiniRedisError::NotAnInteger);
+        let mut refused = PipeTally::default();
+        let mut read = PipeTally {
+            ok: 10,
+            ..PipeTally::default()
+        };
+        for ordinal in [2, 4, 6, 8, 10, 12] {
+            read.error(ordinal, ordinal, error());
+        }
+        refused.error(3, 3, error());
 
-        client.send_input(input, &mut output).unwrap();
+        let tally = refused.merge(read);
 
-        assert_eq!("SET key value\n".as_bytes(), output.as_slice());
+        assert_eq!((10, 7), (tally.ok, tally.failed));
+        assert_eq!(
+            vec![2, 3, 4, 6, 8],
+            tally
+                .first
+                .iter()
+                .map(|(ordinal, _, _)| *ordinal)
+                .collect::<Vec<u64>>()
+        );
     }
 
     #[test]
-    fn send_input_handles_empty_input() {
-        let client = Client::new("127.0.0.1:6379");
-        let mut output = Vec::new();
-        let input = "";
+    fn from_args_reads_fractional_timeouts() {
+        let args = [
+            "miniredis",
+            "--connect-timeout",
+            "0.5",
+            "--timeout",
+            "2",
+            "localhost:9999",
+        ]
+        .map(str::to_string);
+        let client = Client::from_args(&args).unwrap();
+
+        assert_eq!(Some(Duration::from_millis(500)), client.connect_timeout);
+        assert_eq!(Some(Duration::from_secs(2)), client.timeout);
+        assert_eq!("localhost:9999".to_string(), client.address);
+    }
 
-        client.send_input(input, &mut output).unwrap();
+    #[test]
+    fn from_args_rejects_invalid_timeouts() {
+        for value in ["0", "-1", "soon", "inf"] {
+            let args = ["miniredis", "--timeout", value].map(str::to_string);
 
-        assert_eq!("\n".as_bytes(), output.as_slice());
+            assert_eq!(
+                Err(MiniRedisError::InvalidArguments {
+                    arguments: vec!["--timeout".to_string(), value.to_string()]
+                }),
+                Client::from_args(&args).map(|_| ())
+            );
+        }
     }
 
     #[test]
-    fn read_response_reads_line_from_reader() {
-        use std::io::Cursor;
+    fn from_args_rejects_missing_timeout_and_unknown_options() {
+        let missing = ["miniredis", "--connect-timeout"].map(str::to_string);
+        let unknown = ["miniredis", "--verbose", "localhost:9999"].map(str::to_string);
 
-        let client = Client::new("127.0.0.1:6379");
-        let response_data = "OK\n";
-        let cursor = Cursor::new(response_data.as_bytes());
-        let mut reader = BufReader::new(cursor);
+        assert!(Client::from_args(&missing).is_err());
+        assert_eq!(
+            Err(MiniRedisError::InvalidArguments {
+                arguments: vec!["--verbose".to_string()]
+            }),
+            Client::from_args(&unknown).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn from_args_reads_output_format() {
+        let raw = ["miniredis", "--raw", "localhost:9999"].map(str::to_string);
+        let pretty = ["miniredis", "--no-raw", "localhost:9999"].map(str::to_string);
+        let default = ["miniredis", "localhost:9999"].map(str::to_string);
+
+        assert_eq!(Some(Format::Raw), Client::from_args(&raw).unwrap().format);
+        assert_eq!(
+            Some(Format::Pretty),
+            Client::from_args(&pretty).unwrap().format
+        );
+        assert_eq!(None, Client::from_args(&default).unwrap().format);
+    }
+
+    #[test]
+    fn script_commands_skip_blank_lines_and_comments() {
+        let script = "# seed\nSET a 1\n\n   \n  # indented comment\n  GET a  \r\nDEL a";
+
+        assert_eq!(
+            vec![(2, "SET a 1"), (6, "GET a"), (7, "DEL a")],
+            script_commands(script)
+        );
+    }
+
+    #[test]
+    fn from_args_reads_file_options() {
+        let args = [
+            "miniredis",
+            "--eval",
+            "seed.txt",
+            "--abort-on-error",
+            "localhost:9999",
+        ]
+        .map(str::to_string);
+
+        let client = Client::from_args(&args).unwrap();
+
+        assert_eq!(Some("seed.txt".to_string()), client.file);
+        assert!(client.abort_on_error);
+    }
+
+    #[test]
+    fn from_args_rejects_file_with_command() {
+        let args = [
+            "miniredis",
+            "--file",
+            "seed.txt",
+            "localhost:9999",
+            "GET",
+            "a",
+        ]
+        .map(str::to_string);
+
+        assert_eq!(
+            Err(MiniRedisError::InvalidArguments {
+                arguments: vec!["--file".to_string(), "GET".to_string(), "a".to_string()]
+            }),
+            Client::from_args(&args).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn from_args_reads_bigkeys_options() {
+        let bigkeys = ["miniredis", "--bigkeys", "localhost:9999"].map(str::to_string);
+        let sample = ["miniredis", "--bigkeys-sample", "50", "localhost:9999"].map(str::to_string);
+        let command = ["miniredis", "--bigkeys", "localhost:9999", "GET", "a"].map(str::to_string);
+
+        let client = Client::from_args(&bigkeys).unwrap();
+        assert!(client.bigkeys);
+        assert_eq!(None, client.bigkeys_sample);
+        let client = Client::from_args(&sample).unwrap();
+        assert!(client.bigkeys);
+        assert_eq!(Some(50), client.bigkeys_sample);
+        assert_eq!(
+            Err(MiniRedisError::InvalidArguments {
+                arguments: vec!["--bigkeys".to_string(), "GET".to_string(), "a".to_string()]
+            }),
+            Client::from_args(&command).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn key_sizes_report_the_biggest_key_and_percentiles() {
+        let mut sizes = KeySizes::new("string", "bytes");
+        for size in 1..=100 {
+            sizes.record(&format!("key:{}", size), size);
+        }
+
+        assert!(!sizes.record("small", 1));
+        assert_eq!(
+            "-------- summary -------\n\
+             Sampled 101 keys in the keyspace\n\
+             Biggest string found \"key:100\" has 100 bytes\n\
+             101 strings with 5051 bytes (avg size 50.01)\n\
+             Sizes in bytes: p50 50, p90 90, p99 99, max 100",
+            sizes.to_string()
+        );
+    }
+
+    #[test]
+    fn key_sizes_report_an_empty_keyspace() {
+        let sizes = KeySizes::new("string", "bytes");
+
+        assert_eq!("Scanned 0 keys", sizes.progress());
+        assert_eq!(
+            "-------- summary -------\nSampled 0 keys in the keyspace\nNo string keys found",
+            sizes.to_string()
+        );
+    }
+
+    #[test]
+    fn from_args_reads_retry_options() {
+        let args = [
+            "miniredis",
+            "--retry",
+            "3",
+            "--retry-delay",
+            "20",
+            "--retry-writes",
+            "localhost:9999",
+        ]
+        .map(str::to_string);
+
+        assert_eq!(
+            ConnectOptions {
+                retries: 3,
+                retry_delay: Duration::from_millis(20),
+                retry_writes: true,
+            },
+            Client::from_args(&args).unwrap().connect_options
+        );
+        assert_eq!(
+            ConnectOptions::default(),
+            Client::new("localhost:9999").connect_options
+        );
+    }
+
+    #[test]
+    fn from_args_rejects_invalid_retries() {
+        for (option, value) in [
+            ("--retry", "-1"),
+            ("--retry", "many"),
+            ("--retry-delay", "0.5"),
+        ] {
+            let args = ["miniredis", option, value].map(str::to_string);
+
+            assert_eq!(
+                Err(MiniRedisError::InvalidArguments {
+                    arguments: vec![option.to_string(), value.to_string()]
+                }),
+                Client::from_args(&args).map(|_| ())
+            );
+        }
+    }
+
+    #[test]
+    fn from_args_reads_output_option() {
+        for (value, format) in [
+            ("json", Format::Json),
+            ("csv", Format::Csv),
+            ("plain", Format::Raw),
+        ] {
+            let args = ["miniredis", "--output", value, "localhost:9999"].map(str::to_string);
+
+            assert_eq!(Some(format), Client::from_args(&args).unwrap().format);
+        }
+
+        let args = ["miniredis", "--output", "xml"].map(str::to_string);
+        assert_eq!(
+            Err(MiniRedisError::InvalidArguments {
+                arguments: vec!["--output".to_string(), "xml".to_string()]
+            }),
+            Client::from_args(&args).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn from_args_reads_repeat_and_interval() {
+        let args = [
+            "miniredis",
+            "--repeat",
+            "5",
+            "--interval",
+            "0.1",
+            "localhost:9999",
+            "PING",
+        ]
+        .map(str::to_string);
+        let forever = ["miniredis", "--repeat", "-1", "localhost:9999", "PING"].map(str::to_string);
+        let client = Client::from_args(&args).unwrap();
+
+        assert_eq!(Some(5), client.repeat);
+        assert_eq!(Duration::from_millis(100), client.interval);
+        assert_eq!(None, Client::from_args(&forever).unwrap().repeat);
+    }
+
+    #[test]
+    fn from_args_sends_command_once_by_default() {
+        let args = ["miniredis", "localhost:9999", "PING"].map(str::to_string);
+        let client = Client::from_args(&args).unwrap();
+
+        assert_eq!(Some(1), client.repeat);
+        assert_eq!(Duration::ZERO, client.interval);
+    }
+
+    #[test]
+    fn from_args_rejects_invalid_repeats() {
+        for value in ["0", "-2", "x"] {
+            let args =
+                ["miniredis", "--repeat", value, "localhost:9999", "PING"].map(str::to_string);
+
+            assert_eq!(
+                Err(MiniRedisError::InvalidArguments {
+                    arguments: vec!["--repeat".to_string(), value.to_string()]
+                }),
+                Client::from_args(&args).map(|_| ())
+            );
+        }
+    }
+
+    #[test]
+    fn from_args_rejects_repeat_without_command() {
+        let args = ["miniredis", "--repeat", "3", "localhost:9999"].map(str::to_string);
+
+        assert_eq!(
+            Err(MiniRedisError::InvalidArguments {
+                arguments: vec!["--repeat".to_string(), "--interval".to_string()]
+            }),
+            Client::from_args(&args).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn from_args_reads_latency_modes() {
+        let live = ["miniredis", "--latency", "localhost:9999"].map(str::to_string);
+        let history =
+            ["miniredis", "--latency-history", "15", "--interval", "0.1"].map(str::to_string);
+
+        let live = Client::from_args(&live).unwrap();
+        let history = Client::from_args(&history).unwrap();
+
+        assert_eq!(Some(Latency::Live), live.latency);
+        assert_eq!(Duration::from_millis(10), live.interval);
+        assert_eq!(
+            Some(Latency::History(Duration::from_secs(15))),
+            history.latency
+        );
+        assert_eq!(Duration::from_millis(100), history.interval);
+    }
+
+    #[test]
+    fn from_args_rejects_command_in_latency_mode() {
+        let args = ["miniredis", "--latency", "localhost:9999", "GET", "a"].map(str::to_string);
+
+        assert_eq!(
+            Err(MiniRedisError::InvalidArguments {
+                arguments: vec!["--latency".to_string(), "GET".to_string(), "a".to_string()]
+            }),
+            Client::from_args(&args).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn latency_samples_track_min_max_average_and_last() {
+        let mut samples = LatencySamples::new();
+        for millis in [3, 1, 5] {
+            samples.record(Duration::from_millis(millis));
+        }
+
+        assert_eq!(
+            "min: 1.000, max: 5.000, avg: 3.000, last: 5.000 (3 samples)",
+            samples.to_string()
+        );
+    }
+
+    #[test]
+    fn latency_samples_without_samples_are_zero() {
+        assert_eq!(
+            "min: 0.000, max: 0.000, avg: 0.000, last: 0.000 (0 samples)",
+            LatencySamples::new().to_string()
+        );
+    }
+
+    #[test]
+    fn command_times_out_when_server_never_replies() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let timeout = Duration::from_millis(100);
+        let client = Client::builder(&address)
+            .timeout(timeout)
+            .command(vec!["GET".to_string(), "key".to_string()])
+            .build();
 
-        let result = client.read_response(&mut reader).unwrap();
+        assert_eq!(
+            Err(MiniRedisError::Timeout { address, timeout }),
+            client.run()
+        );
+    }
+
+    #[test]
+    fn local_command_parses_exit_aliases_in_any_case() {
+        for line in ["exit", "quit", "QUIT", "Exit", "  quit  "] {
+            assert_eq!(Some(LocalCommand::Exit), LocalCommand::parse(line));
+        }
+        assert_eq!(Some(LocalCommand::Help), LocalCommand::parse("HELP"));
+        assert_eq!(Some(LocalCommand::Clear), LocalCommand::parse("clear"));
+        assert_eq!(Some(LocalCommand::History), LocalCommand::parse("History"));
+        assert_eq!(
+            Some(LocalCommand::Timing(true)),
+            LocalCommand::parse("TIMING on")
+        );
+        assert_eq!(
+            Some(LocalCommand::Timing(false)),
+            LocalCommand::parse(" timing  OFF ")
+        );
+    }
+
+    #[test]
+    fn local_command_leaves_other_lines_for_the_server() {
+        for line in [
+            "GET quit",
+            "exit now",
+            "help GET",
+            "SET clear 1",
+            "quitter",
+            "timing",
+            "timing on now",
+        ] {
+            assert_eq!(None, LocalCommand::parse(line));
+        }
+    }
+
+    #[test]
+    fn prompt_ends_at_end_of_input() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = Client::new(&listener.local_addr().unwrap().to_string());
+
+        assert_eq!(Ok(()), client.run_prompt(&mut Cursor::new("")));
+    }
+
+    #[test]
+    fn prompt_ends_on_exit_without_sending_it() {
+        use std::io::Read;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = Client::new(&listener.local_addr().unwrap().to_string());
+
+        assert_eq!(
+            Ok(()),
+            client.run_prompt(&mut Cursor::new("\nhelp\nEXIT\nGET a\n"))
+        );
+        let (mut connection, _) = listener.accept().unwrap();
+        let mut sent = String::new();
+        connection.read_to_string(&mut sent).unwrap();
+        assert_eq!("", sent);
+    }
+
+    #[test]
+    fn from_args_reads_show_rtt_flag() {
+        let args = ["miniredis", "--show-rtt", "localhost:9999"].map(str::to_string);
+
+        assert!(Client::from_args(&args).unwrap().show_rtt);
+        assert!(!Client::new("localhost:9999").show_rtt);
+    }
+
+    #[test]
+    fn rtt_is_printed_inline_only_at_a_prompt() {
+        assert_eq!(Rtt::Inline, Rtt::new(true, true));
+        assert_eq!(Rtt::Stderr, Rtt::new(true, false));
+        assert_eq!(Rtt::Hidden, Rtt::new(false, true));
+        assert_eq!(Rtt::Hidden, Rtt::new(false, false));
+    }
+
+    #[test]
+    fn format_rtt_switches_units_with_the_size_of_the_time() {
+        assert_eq!("0µs", format_rtt(Duration::ZERO));
+        assert_eq!("850µs", format_rtt(Duration::from_nanos(850_400)));
+        assert_eq!("1.0ms", format_rtt(Duration::from_millis(1)));
+        assert_eq!("1.3ms", format_rtt(Duration::from_micros(1_340)));
+        assert_eq!("999.9ms", format_rtt(Duration::from_micros(999_949)));
+        assert_eq!("2.50s", format_rtt(Duration::from_millis(2_500)));
+    }
+
+    /// Listens for the client, and closes every connection right away, except the last one,
+    /// which answers every line with `value`.
+    fn start_closing_server(closes: usize) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            for _ in 0..closes {
+                drop(listener.accept().unwrap());
+            }
+            let (mut stream, _) = listener.accept().unwrap();
+            for _ in BufReader::new(stream.try_clone().unwrap()).lines() {
+                stream.write_all(b"value\n").unwrap();
+            }
+        });
+        address
+    }
+
+    #[test]
+    fn prompt_ends_when_the_server_closes_the_connection() {
+        let address = start_closing_server(1);
+        let client = Client::builder(&address)
+            .protocol(Protocol::Inline)
+            .prompt(false)
+            .build();
+
+        let result = client.run_prompt(&mut Cursor::new("GET a\nGET a\n"));
+
+        assert!(
+            matches!(
+                result,
+                Err(MiniRedisError::StreamClosed)
+                    | Err(MiniRedisError::StreamNotReadable { .. })
+                    | Err(MiniRedisError::StreamNotWritable { .. })
+            ),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn prompt_reconnects_when_the_client_retries() {
+        let address = start_closing_server(1);
+        let client = Client::builder(&address)
+            .protocol(Protocol::Inline)
+            .prompt(false)
+            .connect_options(ConnectOptions {
+                retries: 1,
+                ..ConnectOptions::default()
+            })
+            .build();
+
+        assert_eq!(
+            Ok(()),
+            client.run_prompt(&mut Cursor::new("GET a\nGET a\n"))
+        );
+    }
+
+    #[test]
+    fn from_args_reads_no_prompt_flag() {
+        let args = ["miniredis", "--no-prompt", "localhost:9999"].map(str::to_string);
+
+        assert!(!Client::from_args(&args).unwrap().prompt);
+        assert!(Client::new("localhost:9999").prompt);
+    }
+
+    #[test]
+    fn from_args_reads_credentials() {
+        let args = [
+            "miniredis",
+            "--user",
+            "alice",
+            "--pass",
+            "secret",
+            "localhost:9999",
+        ]
+        .map(str::to_string);
+
+        let client = Client::from_args(&args).unwrap();
+
+        assert_eq!(Some("alice".to_string()), client.user);
+        assert_eq!(Some("secret".to_string()), client.password);
+    }
+
+    #[test]
+    fn from_args_rejects_pass_without_value() {
+        let args = ["miniredis", "--pass"].map(str::to_string);
+
+        assert_eq!(
+            Err(MiniRedisError::InvalidArguments {
+                arguments: vec!["--pass".to_string()]
+            }),
+            Client::from_args(&args).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn from_args_reads_protocol_flags() {
+        let resp = ["miniredis", "--resp", "localhost:9999"].map(str::to_string);
+        let inline = ["miniredis", "--inline", "localhost:9999"].map(str::to_string);
+
+        assert_eq!(
+            Some(Protocol::Resp),
+            Client::from_args(&resp).unwrap().protocol
+        );
+        assert_eq!(
+            Some(Protocol::Inline),
+            Client::from_args(&inline).unwrap().protocol
+        );
+        assert_eq!(None, Client::new("localhost:9999").protocol);
+    }
+
+    #[test]
+    fn prompt_shows_address_and_database_other_than_the_first() {
+        assert_eq!("127.0.0.1:6379> ", prompt("127.0.0.1:6379", 0));
+        assert_eq!("127.0.0.1:6379[2]> ", prompt("127.0.0.1:6379", 2));
+    }
+
+    #[test]
+    fn selected_database_is_read_from_select_commands() {
+        assert_eq!(Some(2), selected_database("select 2"));
+        assert_eq!(Some(0), selected_database("SELECT \"0\""));
+        assert_eq!(None, selected_database("SELECT two"));
+        assert_eq!(None, selected_database("SELECT 1 2"));
+        assert_eq!(None, selected_database("GET 2"));
+    }
+
+    #[test]
+    fn check_command_accepts_known_commands_with_the_right_arity() {
+        for line in [
+            "GET a",
+            "set a \"b c\"",
+            "PING",
+            "PING hello",
+            "SCAN 0 COUNT 5",
+            "",
+        ] {
+            assert_eq!(Ok(()), check_command(line));
+        }
+    }
+
+    #[test]
+    fn check_command_suggests_a_similar_command() {
+        assert_eq!(
+            Err("Invalid command: GTE. Did you mean GET?".to_string()),
+            check_command("gte a")
+        );
+        assert_eq!(
+            Err(MiniRedisError::InvalidCommand {
+                command: "UNKNOWN".to_string()
+            }
+            .to_string()),
+            check_command("UNKNOWN arg")
+        );
+    }
+
+    #[test]
+    fn check_command_prints_the_usage_for_the_wrong_arity() {
+        assert_eq!(
+            Err("Invalid arguments for SET. Usage: SET <KEY> <VALUE> [NX|XX] [EX <SECONDS>|PX <MILLISECONDS>|KEEPTTL]".to_string()),
+            check_command("SET a")
+        );
+        assert_eq!(
+            Err("Invalid arguments for GET. Usage: GET <KEY>".to_string()),
+            check_command("GET a b")
+        );
+        assert_eq!(
+            Err(
+                "Invalid arguments for SCAN. Usage: SCAN <CURSOR> [MATCH <PATTERN>] [COUNT <N>] [TYPE <TYPE>]"
+                    .to_string()
+            ),
+            check_command("SCAN first")
+        );
+    }
+
+    #[test]
+    fn from_args_reads_history_options() {
+        let path = [
+            "miniredis",
+            "--history",
+            "/tmp/h",
+            "--history-size",
+            "5",
+            "localhost:9999",
+        ]
+        .map(str::to_string);
+        let none = ["miniredis", "--no-history", "localhost:9999"].map(str::to_string);
+
+        let client = Client::from_args(&path).unwrap();
+        assert_eq!(Some("/tmp/h".to_string()), client.history);
+        assert_eq!(5, client.history_size);
+        assert_eq!(None, Client::from_args(&none).unwrap().history);
+        assert_eq!(None, Client::new("localhost:9999").history);
+    }
+
+    #[test]
+    fn prompt_saves_typed_lines_to_the_history_file() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let path =
+            std::env::temp_dir().join(format!("miniredis-{}-prompt-history", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let client = Client::builder(&listener.local_addr().unwrap().to_string())
+            .history(&path.display().to_string())
+            .build();
+
+        assert_eq!(
+            Ok(()),
+            client.run_prompt(&mut Cursor::new("help\ntiming on\nexit\n"))
+        );
+        assert_eq!("help\ntiming on\n", std::fs::read_to_string(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn prompt_keeps_going_when_the_history_file_cannot_be_used() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = Client::builder(&listener.local_addr().unwrap().to_string())
+            .history(&std::env::temp_dir().display().to_string())
+            .build();
+
+        assert_eq!(Ok(()), client.run_prompt(&mut Cursor::new("history\n")));
+    }
+
+    #[test]
+    fn from_args_reads_no_validate_flag() {
+        let args = ["miniredis", "--no-validate", "localhost:9999"].map(str::to_string);
 
-        assert_eq!("OK\n".to_string(), result);
+        assert!(!Client::from_args(&args).unwrap().validate);
+        assert!(Client::new("localhost:9999").validate);
     }
 
     #[test]
-    fn read_response_handles_multiline_response() {
-        use std::io::Cursor;
+    fn check_response_accepts_values() {
+        assert_eq!(Ok(()), check_response(&Response::from_line("OK")));
+        assert_eq!(Ok(()), check_response(&Response::from_line("nil")));
+    }
+
+    #[test]
+    fn check_response_returns_server_error_for_error_replies() {
+        assert_eq!(
+            Err(MiniRedisError::ServerError {
+                message: "The key value store is full.".to_string()
+            }),
+            check_response(&Response::from_line("ERR The key value store is full."))
+        );
+    }
+
+    #[test]
+    fn from_args_uses_first_argument_as_address() {
+        let expected_address = "test.example.com:1234";
+        let args = vec![
+            "miniredis".to_string(),
+            expected_address.to_string(),
+            "ignored_arg".to_string(),
+        ];
+        let client = Client::from_args(&args).unwrap();
+
+        assert_eq!(expected_address.to_string(), client.address);
+    }
 
+    #[test]
+    fn read_input_reads_line_from_reader() {
         let client = Client::new("127.0.0.1:6379");
-        let response_data = "value with spaces\nsecond line\n";
-        let cursor = Cursor::new(response_data.as_bytes());
+        let input_data = "test input\n";
+        let cursor = Cursor::new(input_data.as_bytes());
         let mut reader = BufReader::new(cursor);
 
-        let result = client.read_response(&mut reader).unwrap();
+        let result = client.read_input(&mut reader).unwrap();
 
-        assert_eq!("value with spaces\n".to_string(), result);
+        assert_eq!("test input\n".to_string(), result);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,315 @@
+use crate::command::{ArgKind, Command, CommandInfo};
+use crate::error::MiniRedisError;
+use crate::hook::{CommandHook, ConnectionInfo};
+use crate::response::Response;
+use std::ops::RangeInclusive;
+
+/// The number of hash slots that keys are spread over, like in Redis Cluster.
+pub const SLOTS: u16 = 16384;
+
+/// Computes the hash slot of a key, the way Redis Cluster does.
+///
+/// If the key holds a hash tag, a non-empty part between the first `{` and the next `}`,
+/// only the tag is hashed, so keys like `{user:1}:name` and `{user:1}:email` share a slot.
+///
+/// # Arguments
+///
+/// * `key` - The key.
+///
+/// # Returns
+///
+/// The slot of the key, from 0 up to [`SLOTS`].
+///
+/// # Examples
+///
+/// ```rust
+/// use miniredis::cluster::key_slot;
+///
+/// assert_eq!(12182, key_slot("foo"));
+/// assert_eq!(key_slot("{user:1}:name"), key_slot("{user:1}:email"));
+/// ```
+pub fn key_slot(key: &str) -> u16 {
+    crc16(hash_tag(key)) % SLOTS
+}
+
+/// Finds the part of a key that decides where it is stored.
+///
+/// # Arguments
+///
+/// * `key` - The key.
+///
+/// # Returns
+///
+/// The text between the first `{` and the `}` after it, if that is not empty,
+/// or the whole key otherwise.
+pub(crate) fn hash_tag(key: &str) -> &[u8] {
+    let bytes = key.as_bytes();
+    bytes
+        .iter()
+        .position(|&byte| byte == b'{')
+        .and_then(|open| {
+            let tag = &bytes[open + 1..];
+            tag.iter()
+                .position(|&byte| byte == b'}')
+                .filter(|len| *len > 0)
+                .map(|len| &tag[..len])
+        })
+        .unwrap_or(bytes)
+}
+
+/// Computes the CRC16 checksum that Redis Cluster hashes keys with, CRC-16/XMODEM.
+///
+/// # Arguments
+///
+/// * `bytes` - The bytes to checksum.
+///
+/// # Returns
+///
+/// The checksum.
+fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in bytes {
+        crc ^= u16::from(byte) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Which hash slots other nodes serve, configured when a server starts.
+///
+/// A server with a slot map answers commands on keys of those slots with
+/// [`MiniRedisError::Moved`], naming the node to send them to, instead of storing
+/// the keys in the wrong place. Every slot that is not mapped is served by the server itself.
+/// The map is static: nodes do not tell each other about slots that move.
+///
+/// The map is a [`CommandHook`], added to a server with
+/// [`ServerBuilder::slots`](crate::server::ServerBuilder::slots),
+/// and checks the arguments that are keys in the table of commands.
+///
+/// # Examples
+///
+/// ```rust
+/// use miniredis::cluster::{SlotMap, key_slot};
+///
+/// let slots: SlotMap = "8192-16383=127.0.0.1:7001".parse().unwrap();
+///
+/// assert_eq!(None, slots.node_of(key_slot("bar")));
+/// assert_eq!(Some("127.0.0.1:7001"), slots.node_of(key_slot("foo")));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SlotMap {
+    nodes: Vec<(RangeInclusive<u16>, String)>,
+}
+
+impl SlotMap {
+    /// Creates a map where the server serves every slot itself.
+    ///
+    /// # Returns
+    ///
+    /// An empty map.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::cluster::SlotMap;
+    ///
+    /// assert_eq!(None, SlotMap::new().node_of(0));
+    /// ```
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hands a range of slots to another node.
+    ///
+    /// A slot handed out more than once goes to the node it was handed to first.
+    ///
+    /// # Arguments
+    ///
+    /// * `slots` - The slots, like `0..=8191`.
+    /// * `address` - The address of the node that serves them.
+    ///
+    /// # Returns
+    ///
+    /// The map.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::cluster::SlotMap;
+    ///
+    /// let slots = SlotMap::new().assign(0..=8191, "127.0.0.1:7000");
+    ///
+    /// assert_eq!(Some("127.0.0.1:7000"), slots.node_of(8191));
+    /// assert_eq!(None, slots.node_of(8192));
+    /// ```
+    pub fn assign(mut self, slots: RangeInclusive<u16>, address: &str) -> Self {
+        self.nodes.push((slots, address.to_string()));
+        self
+    }
+
+    /// Finds the node that serves a slot.
+    ///
+    /// # Arguments
+    ///
+    /// * `slot` - The slot.
+    ///
+    /// # Returns
+    ///
+    /// The address of the node, or None if the server serves the slot itself.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::cluster::SlotMap;
+    ///
+    /// let slots = SlotMap::new().assign(100..=100, "127.0.0.1:7000");
+    ///
+    /// assert_eq!(Some("127.0.0.1:7000"), slots.node_of(100));
+    /// ```
+    pub fn node_of(&self, slot: u16) -> Option<&str> {
+        self.nodes
+            .iter()
+            .find(|(slots, _)| slots.contains(&slot))
+            .map(|(_, address)| address.as_str())
+    }
+}
+
+impl std::str::FromStr for SlotMap {
+    type Err = MiniRedisError;
+
+    /// Parses a map from ranges of slots and the nodes that serve them, separated by commas,
+    /// like `0-8191=127.0.0.1:7000,16000=127.0.0.1:7002`.
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - The ranges and nodes.
+    ///
+    /// # Returns
+    ///
+    /// The map.
+    ///
+    /// # Errors
+    ///
+    /// If a range has no node, or is not one or two slots below [`SLOTS`] in order,
+    /// it will return [`MiniRedisError::InvalidArguments`] holding the range.
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut map = SlotMap::new();
+        for part in spec
+            .split(',')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+        {
+            let invalid = || MiniRedisError::InvalidArguments {
+                arguments: vec![part.to_string()],
+            };
+            let (slots, address) = part.split_once('=').ok_or_else(invalid)?;
+            let (first, last) = slots.split_once('-').unwrap_or((slots, slots));
+            let slot = |slot: &str| {
+                slot.trim()
+                    .parse::<u16>()
+                    .ok()
+                    .filter(|slot| *slot < SLOTS)
+                    .ok_or_else(invalid)
+            };
+            let (first, last) = (slot(first)?, slot(last)?);
+            if first > last || address.trim().is_empty() {
+                return Err(invalid());
+            }
+            map = map.assign(first..=last, address.trim());
+        }
+        Ok(map)
+    }
+}
+
+impl CommandHook for SlotMap {
+    /// Redirects a command on a key of a slot that another node serves.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The client that sent the command.
+    /// * `command` - The command.
+    ///
+    /// # Returns
+    ///
+    /// A [`MiniRedisError::Moved`] error for the first key of the command that another node
+    /// serves, or None to execute the command here.
+    fn before(&self, _client: &ConnectionInfo, command: &mut Command) -> Option<Response> {
+        let info = CommandInfo::find(&command.name)?;
+        info.args
+            .iter()
+            .zip(&command.args)
+            .filter(|(kind, _)| **kind == ArgKind::Key)
+            .find_map(|(_, key)| {
+                let slot = key_slot(key);
+                self.node_of(slot).map(|address| {
+                    Response::Error(MiniRedisError::Moved {
+                        slot,
+                        address: address.to_string(),
+                    })
+                })
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc16_matches_the_xmodem_check_value() {
+        assert_eq!(0x31C3, crc16(b"123456789"));
+        assert_eq!(0, crc16(b""));
+    }
+
+    #[test]
+    fn key_slot_hashes_only_the_hash_tag() {
+        assert_eq!(5061, key_slot("bar"));
+        assert_eq!(key_slot("user:1000"), key_slot("{user:1000}.following"));
+        assert_eq!(key_slot("{}"), crc16(b"{}") % SLOTS);
+        assert_eq!(key_slot("{}user"), crc16(b"{}user") % SLOTS);
+        assert_eq!(key_slot("a"), key_slot("{a}{b}"));
+    }
+
+    #[test]
+    fn slot_maps_parse_ranges_and_single_slots() {
+        let slots = "0-99=a:1, 100=b:2".parse::<SlotMap>().unwrap();
+
+        assert_eq!(
+            SlotMap::new()
+                .assign(0..=99, "a:1")
+                .assign(100..=100, "b:2"),
+            slots
+        );
+        assert_eq!(Ok(SlotMap::new()), "".parse());
+        for spec in ["0-99", "99-0=a:1", "16384=a:1", "x=a:1", "5="] {
+            assert!(spec.parse::<SlotMap>().is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn slot_maps_redirect_only_keys_of_other_nodes() {
+        let slots = SlotMap::new().assign(key_slot("foo")..=key_slot("foo"), "127.0.0.1:7001");
+        let client = ConnectionInfo {
+            id: 1,
+            address: None,
+        };
+        let redirect = |line: &str| slots.before(&client, &mut Command::parse(line).unwrap());
+
+        assert_eq!(
+            Some(Response::Error(MiniRedisError::Moved {
+                slot: 12182,
+                address: "127.0.0.1:7001".to_string()
+            })),
+            redirect("SET foo 1")
+        );
+        assert_eq!(None, redirect("SET bar foo"));
+        assert_eq!(None, redirect("CLUSTER KEYSLOT foo"));
+        assert_eq!(None, redirect("PING foo"));
+    }
+}
@@ -0,0 +1,1040 @@
+use crate::error::MiniRedisError;
+use crate::tokenizer::{quote, split_words};
+
+/// A parsed command, ready to be executed by an [`Engine`](crate::engine::Engine).
+///
+/// # Examples
+///
+/// ```rust
+/// use miniredis::command::Command;
+///
+/// let command = Command::parse("set mykey myvalue").unwrap();
+///
+/// assert_eq!("SET", command.name);
+/// assert_eq!(vec!["mykey".to_string(), "myvalue".to_string()], command.args);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Command {
+    /// The name of the command, in uppercase.
+    pub name: String,
+    /// The arguments of the command.
+    pub args: Vec<String>,
+}
+
+impl Command {
+    /// Creates a new command.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the command, in any case.
+    /// * `args` - The arguments of the command.
+    ///
+    /// # Returns
+    ///
+    /// A new command with the name in uppercase.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::command::Command;
+    ///
+    /// let command = Command::new("get", vec!["mykey".to_string()]);
+    ///
+    /// assert_eq!("GET", command.name);
+    /// ```
+    pub fn new(name: &str, args: Vec<String>) -> Self {
+        Self {
+            name: name.to_uppercase(),
+            args,
+        }
+    }
+
+    /// Parses a command from a line of text.
+    ///
+    /// The name and arguments are separated by whitespace.
+    /// Double quotes group words into one argument, so `SET msg "hello world"`
+    /// sets `msg` to `hello world`, and `""` is an empty argument.
+    /// Inside quotes, `\"` is a literal quote and `\\` a literal backslash.
+    /// A quote that is never closed runs to the end of the line,
+    /// which the client refuses to send instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The line to parse the command from.
+    ///
+    /// # Returns
+    ///
+    /// The parsed command, or None if the line is empty or only holds whitespace.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::command::Command;
+    ///
+    /// assert_eq!(
+    ///     Some(Command::new("GET", vec!["mykey".to_string()])),
+    ///     Command::parse("GET mykey\n")
+    /// );
+    /// assert_eq!(
+    ///     Some(Command::new(
+    ///         "SET",
+    ///         vec!["msg".to_string(), "hello world".to_string()]
+    ///     )),
+    ///     Command::parse("SET msg \"hello world\"")
+    /// );
+    /// assert_eq!(None, Command::parse("   \n"));
+    /// ```
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut words = split_words(line.trim_end_matches(['\r', '\n'])).into_iter();
+        let name = words.next()?;
+        Some(Self::new(&name, words.collect()))
+    }
+}
+
+impl std::fmt::Display for Command {
+    /// Formats the command as a line that [`Command::parse`] turns back into it.
+    ///
+    /// Arguments that are empty, or hold whitespace, quotes, or backslashes, are quoted.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - The formatter to write the command to.
+    ///
+    /// # Errors
+    ///
+    /// If the command cannot be formatted, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::command::Command;
+    ///
+    /// let command = Command::new("set", vec!["msg".to_string(), "hello world".to_string()]);
+    ///
+    /// assert_eq!("SET msg \"hello world\"", command.to_string());
+    /// ```
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        for arg in &self.args {
+            write!(f, " {}", quote(arg))?;
+        }
+        Ok(())
+    }
+}
+
+/// What an argument of a command must be.
+#[derive(Debug, PartialEq)]
+pub(crate) enum ArgKind {
+    /// The name of a key, which decides the node that serves the command
+    /// when hash slots are mapped to other nodes.
+    Key,
+    /// Any value.
+    Value,
+    /// An integer that fits in 64 bits.
+    Integer,
+    /// A cursor, an integer from 0 up that fits in 64 bits.
+    Cursor,
+    /// One of a few keywords, in any case.
+    Keyword(&'static [&'static str]),
+}
+
+/// What the client and the server know about a command the server accepts.
+///
+/// The server checks every command against it before executing it,
+/// so the engine only sees commands with the right number and kinds of arguments.
+#[derive(Debug, PartialEq)]
+pub(crate) struct CommandInfo {
+    /// The name of the command, in uppercase.
+    pub(crate) name: &'static str,
+    /// How the command is written, like `SET <KEY> <VALUE>`.
+    pub(crate) usage: &'static str,
+    /// What the command does, in a few words.
+    pub(crate) summary: &'static str,
+    /// The fewest arguments the command takes.
+    pub(crate) min_args: usize,
+    /// The most arguments the command takes.
+    pub(crate) max_args: usize,
+    /// What each argument must be, by position.
+    /// Arguments past the end of the list are left to the command to check.
+    pub(crate) args: &'static [ArgKind],
+    /// Whether the command can change the data, so that `CLIENT PAUSE WRITE` holds it.
+    pub(crate) write: bool,
+    /// The version of MiniRedis the command was added in.
+    pub(crate) since: &'static str,
+}
+
+/// The options of `SCAN`, which can come in any order.
+const SCAN_OPTIONS: &[&str] = &["MATCH", "COUNT", "TYPE"];
+
+/// The options of `SET`.
+const SET_OPTIONS: &[&str] = &["NX", "XX", "EX", "PX", "KEEPTTL"];
+
+/// The options of `EXPIRE` and the commands like it.
+const EXPIRE_OPTIONS: &[&str] = &["NX", "XX", "GT", "LT"];
+
+/// The options of `GETEX`.
+const GETEX_OPTIONS: &[&str] = &["EX", "PX", "EXAT", "PXAT", "PERSIST"];
+
+/// Every command the server accepts, in the order the help lists them.
+pub(crate) const COMMANDS: &[CommandInfo] = &[
+    CommandInfo {
+        name: "GET",
+        usage: "GET <KEY>",
+        summary: "Get the value of a key",
+        min_args: 1,
+        max_args: 1,
+        args: &[ArgKind::Key],
+        write: false,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "SET",
+        usage: "SET <KEY> <VALUE> [NX|XX] [EX <SECONDS>|PX <MILLISECONDS>|KEEPTTL]",
+        summary: "Set the value of a key",
+        min_args: 2,
+        max_args: usize::MAX,
+        args: &[ArgKind::Key, ArgKind::Value, ArgKind::Keyword(SET_OPTIONS)],
+        write: true,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "DEL",
+        usage: "DEL <KEY> [KEY ...]",
+        summary: "Delete keys, and get how many existed",
+        min_args: 1,
+        max_args: usize::MAX,
+        args: &[ArgKind::Key],
+        write: true,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "UNLINK",
+        usage: "UNLINK <KEY> [KEY ...]",
+        summary: "Delete keys, freeing their values in the background, and get how many existed",
+        min_args: 1,
+        max_args: usize::MAX,
+        args: &[ArgKind::Key],
+        write: true,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "RENAME",
+        usage: "RENAME <KEY> <NEWKEY>",
+        summary: "Move the value of a key to another key, overwriting it",
+        min_args: 2,
+        max_args: 2,
+        args: &[ArgKind::Key, ArgKind::Key],
+        write: true,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "RENAMENX",
+        usage: "RENAMENX <KEY> <NEWKEY>",
+        summary: "Move the value of a key to another key only if that key does not exist",
+        min_args: 2,
+        max_args: 2,
+        args: &[ArgKind::Key, ArgKind::Key],
+        write: true,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "EXISTS",
+        usage: "EXISTS <KEY> [KEY ...]",
+        summary: "Count how many of the keys exist, counting a key given twice twice",
+        min_args: 1,
+        max_args: usize::MAX,
+        args: &[ArgKind::Key],
+        write: false,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "SETNX",
+        usage: "SETNX <KEY> <VALUE>",
+        summary: "Set the value of a key only if the key does not exist",
+        min_args: 2,
+        max_args: 2,
+        args: &[ArgKind::Key, ArgKind::Value],
+        write: true,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "OBJECT",
+        usage: "OBJECT ENCODING <KEY>",
+        summary: "Get how the value of a key is encoded",
+        min_args: 2,
+        max_args: 2,
+        args: &[ArgKind::Keyword(&["ENCODING"]), ArgKind::Key],
+        write: false,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "DEBUG",
+        usage: "DEBUG OBJECT <KEY>",
+        summary: "Get the encoding, length, memory, idle time and time to live of a key",
+        min_args: 2,
+        max_args: 2,
+        args: &[ArgKind::Keyword(&["OBJECT"]), ArgKind::Key],
+        write: false,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "TOUCH",
+        usage: "TOUCH <KEY> [KEY ...]",
+        summary: "Count how many of the keys exist, and mark them as just used",
+        min_args: 1,
+        max_args: usize::MAX,
+        args: &[ArgKind::Key],
+        write: false,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "TYPE",
+        usage: "TYPE <KEY>",
+        summary: "Get the type of the value of a key",
+        min_args: 1,
+        max_args: 1,
+        args: &[ArgKind::Key],
+        write: false,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "EXPIRE",
+        usage: "EXPIRE <KEY> <SECONDS> [NX|XX|GT|LT]",
+        summary: "Set the time to live of a key in seconds",
+        min_args: 2,
+        max_args: usize::MAX,
+        args: &[
+            ArgKind::Key,
+            ArgKind::Integer,
+            ArgKind::Keyword(EXPIRE_OPTIONS),
+        ],
+        write: true,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "TTL",
+        usage: "TTL <KEY>",
+        summary: "Get the time to live of a key in seconds",
+        min_args: 1,
+        max_args: 1,
+        args: &[ArgKind::Key],
+        write: false,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "PEXPIRE",
+        usage: "PEXPIRE <KEY> <MILLISECONDS> [NX|XX|GT|LT]",
+        summary: "Set the time to live of a key in milliseconds",
+        min_args: 2,
+        max_args: usize::MAX,
+        args: &[
+            ArgKind::Key,
+            ArgKind::Integer,
+            ArgKind::Keyword(EXPIRE_OPTIONS),
+        ],
+        write: true,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "PTTL",
+        usage: "PTTL <KEY>",
+        summary: "Get the time to live of a key in milliseconds",
+        min_args: 1,
+        max_args: 1,
+        args: &[ArgKind::Key],
+        write: false,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "EXPIREAT",
+        usage: "EXPIREAT <KEY> <UNIX-SECONDS> [NX|XX|GT|LT]",
+        summary: "Set when a key expires, as a Unix timestamp in seconds",
+        min_args: 2,
+        max_args: usize::MAX,
+        args: &[
+            ArgKind::Key,
+            ArgKind::Integer,
+            ArgKind::Keyword(EXPIRE_OPTIONS),
+        ],
+        write: true,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "PEXPIREAT",
+        usage: "PEXPIREAT <KEY> <UNIX-MILLISECONDS> [NX|XX|GT|LT]",
+        summary: "Set when a key expires, as a Unix timestamp in milliseconds",
+        min_args: 2,
+        max_args: usize::MAX,
+        args: &[
+            ArgKind::Key,
+            ArgKind::Integer,
+            ArgKind::Keyword(EXPIRE_OPTIONS),
+        ],
+        write: true,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "PERSIST",
+        usage: "PERSIST <KEY>",
+        summary: "Remove the time to live of a key",
+        min_args: 1,
+        max_args: 1,
+        args: &[ArgKind::Key],
+        write: true,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "GETEX",
+        usage: "GETEX <KEY> [EX <SECONDS>|PX <MILLISECONDS>|EXAT <UNIX-SECONDS>|PXAT <UNIX-MILLISECONDS>|PERSIST]",
+        summary: "Get the value of a key, and set or remove its time to live",
+        min_args: 1,
+        max_args: usize::MAX,
+        args: &[ArgKind::Key, ArgKind::Keyword(GETEX_OPTIONS)],
+        write: true,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "MSET",
+        usage: "MSET <KEY> <VALUE> [KEY VALUE ...]",
+        summary: "Set the values of many keys at once",
+        min_args: 2,
+        max_args: usize::MAX,
+        args: &[ArgKind::Key, ArgKind::Value],
+        write: true,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "MSETNX",
+        usage: "MSETNX <KEY> <VALUE> [KEY VALUE ...]",
+        summary: "Set the values of many keys only if none of them exist",
+        min_args: 2,
+        max_args: usize::MAX,
+        args: &[ArgKind::Key, ArgKind::Value],
+        write: true,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "GETSET",
+        usage: "GETSET <KEY> <VALUE>",
+        summary: "Set the value of a key, and get the value it replaced",
+        min_args: 2,
+        max_args: 2,
+        args: &[ArgKind::Key, ArgKind::Value],
+        write: true,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "INCR",
+        usage: "INCR <KEY>",
+        summary: "Add 1 to the integer value of a key, and get the new value",
+        min_args: 1,
+        max_args: 1,
+        args: &[ArgKind::Key],
+        write: true,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "DECR",
+        usage: "DECR <KEY>",
+        summary: "Subtract 1 from the integer value of a key, and get the new value",
+        min_args: 1,
+        max_args: 1,
+        args: &[ArgKind::Key],
+        write: true,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "INCRBY",
+        usage: "INCRBY <KEY> <AMOUNT>",
+        summary: "Add an amount to the integer value of a key, and get the new value",
+        min_args: 2,
+        max_args: 2,
+        args: &[ArgKind::Key, ArgKind::Value],
+        write: true,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "DECRBY",
+        usage: "DECRBY <KEY> <AMOUNT>",
+        summary: "Subtract an amount from the integer value of a key, and get the new value",
+        min_args: 2,
+        max_args: 2,
+        args: &[ArgKind::Key, ArgKind::Value],
+        write: true,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "INCRBYFLOAT",
+        usage: "INCRBYFLOAT <KEY> <AMOUNT>",
+        summary: "Add a floating point amount to the value of a key, and get the new value",
+        min_args: 2,
+        max_args: 2,
+        args: &[ArgKind::Key, ArgKind::Value],
+        write: true,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "APPEND",
+        usage: "APPEND <KEY> <VALUE>",
+        summary: "Append a value to the value of a key, and get the new length",
+        min_args: 2,
+        max_args: 2,
+        args: &[ArgKind::Key, ArgKind::Value],
+        write: true,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "SETBIT",
+        usage: "SETBIT <KEY> <OFFSET> <0|1>",
+        summary: "Set or clear one bit of the value of a key, and get the bit it replaced",
+        min_args: 3,
+        max_args: 3,
+        args: &[
+            ArgKind::Key,
+            ArgKind::Integer,
+            ArgKind::Keyword(&["0", "1"]),
+        ],
+        write: true,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "GETBIT",
+        usage: "GETBIT <KEY> <OFFSET>",
+        summary: "Get one bit of the value of a key",
+        min_args: 2,
+        max_args: 2,
+        args: &[ArgKind::Key, ArgKind::Integer],
+        write: false,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "BITCOUNT",
+        usage: "BITCOUNT <KEY> [<START> <END>]",
+        summary: "Count the set bits of the value of a key, or of the bytes between two indexes",
+        min_args: 1,
+        max_args: 3,
+        args: &[ArgKind::Key, ArgKind::Integer, ArgKind::Integer],
+        write: false,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "BITPOS",
+        usage: "BITPOS <KEY> <0|1> [<START> [<END>]]",
+        summary: "Get the position of the first clear or set bit of the value of a key",
+        min_args: 2,
+        max_args: 4,
+        args: &[
+            ArgKind::Key,
+            ArgKind::Keyword(&["0", "1"]),
+            ArgKind::Integer,
+            ArgKind::Integer,
+        ],
+        write: false,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "SETRANGE",
+        usage: "SETRANGE <KEY> <OFFSET> <VALUE>",
+        summary: "Overwrite part of the value of a key, and get the new length",
+        min_args: 3,
+        max_args: 3,
+        args: &[ArgKind::Key, ArgKind::Integer, ArgKind::Value],
+        write: true,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "GETRANGE",
+        usage: "GETRANGE <KEY> <START> <END>",
+        summary: "Get the bytes of the value of a key between two indexes",
+        min_args: 3,
+        max_args: 3,
+        args: &[ArgKind::Key, ArgKind::Integer, ArgKind::Integer],
+        write: false,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "DELPREFIX",
+        usage: "DELPREFIX <PREFIX>",
+        summary: "Delete all keys starting with a prefix",
+        min_args: 1,
+        max_args: 1,
+        args: &[ArgKind::Value],
+        write: true,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "PING",
+        usage: "PING [MESSAGE]",
+        summary: "Check that the server answers",
+        min_args: 0,
+        max_args: 1,
+        args: &[ArgKind::Value],
+        write: false,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "INFO",
+        usage: "INFO [keyspace|clients|stats]",
+        summary: "Get the version of the server and its number of keys, the keys of every database, whether clients are paused, or the counters of the server",
+        min_args: 0,
+        max_args: 1,
+        args: &[ArgKind::Keyword(&["KEYSPACE", "CLIENTS", "STATS"])],
+        write: false,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "HELLO",
+        usage: "HELLO",
+        summary: "Get the protocols the server speaks",
+        min_args: 0,
+        max_args: 0,
+        args: &[],
+        write: false,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "STRLEN",
+        usage: "STRLEN <KEY>",
+        summary: "Get the length of the value of a key",
+        min_args: 1,
+        max_args: 1,
+        args: &[ArgKind::Key],
+        write: false,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "LCS",
+        usage: "LCS <KEY1> <KEY2> [LEN] [IDX] [MINMATCHLEN <LEN>] [WITHMATCHLEN]",
+        summary: "Get the longest common subsequence of the values of two keys",
+        min_args: 2,
+        max_args: 6,
+        args: &[ArgKind::Key, ArgKind::Key],
+        write: false,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "SORT",
+        usage: "SORT <KEY> [LIMIT <OFFSET> <COUNT>] [ASC|DESC] [ALPHA] [STORE <DESTINATION>]",
+        summary: "Get the elements of a list or set in order, or store them in another key",
+        min_args: 1,
+        max_args: 8,
+        args: &[ArgKind::Key],
+        write: true,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "RANDOMKEY",
+        usage: "RANDOMKEY",
+        summary: "Get a random key",
+        min_args: 0,
+        max_args: 0,
+        args: &[],
+        write: false,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "SCAN",
+        usage: "SCAN <CURSOR> [MATCH <PATTERN>] [COUNT <N>] [TYPE <TYPE>]",
+        summary: "Get the next batch of keys, starting from cursor 0",
+        min_args: 1,
+        max_args: 7,
+        args: &[
+            ArgKind::Cursor,
+            ArgKind::Keyword(SCAN_OPTIONS),
+            ArgKind::Value,
+            ArgKind::Keyword(SCAN_OPTIONS),
+            ArgKind::Value,
+            ArgKind::Keyword(SCAN_OPTIONS),
+            ArgKind::Value,
+        ],
+        write: false,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "WAIT",
+        usage: "WAIT <NUMREPLICAS> <TIMEOUT>",
+        summary: "Wait for replicas to acknowledge the writes so far, and get how many did",
+        min_args: 2,
+        max_args: 2,
+        args: &[ArgKind::Integer, ArgKind::Integer],
+        write: false,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "CLIENT",
+        usage: "CLIENT PAUSE <TIMEOUT> [WRITE|ALL] | CLIENT UNPAUSE",
+        summary: "Hold the commands of every client, or only their writes, for a number of milliseconds, or stop holding them",
+        min_args: 1,
+        max_args: 3,
+        args: &[
+            ArgKind::Keyword(&["PAUSE", "UNPAUSE"]),
+            ArgKind::Integer,
+            ArgKind::Keyword(&["WRITE", "ALL"]),
+        ],
+        write: false,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "CONFIG",
+        usage: "CONFIG RESETSTAT",
+        summary: "Set the counters of INFO stats back to zero",
+        min_args: 1,
+        max_args: 1,
+        args: &[ArgKind::Keyword(&["RESETSTAT"])],
+        write: false,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "CLUSTER",
+        usage: "CLUSTER KEYSLOT <KEY>",
+        summary: "Get the hash slot of a key",
+        min_args: 2,
+        max_args: 2,
+        // The key is only hashed, so asking any node about it is fine.
+        args: &[ArgKind::Keyword(&["KEYSLOT"]), ArgKind::Value],
+        write: false,
+        since: "0.1.0",
+    },
+    CommandInfo {
+        name: "HELP",
+        usage: "HELP [COMMAND]",
+        summary: "Get the commands of the server, or how to use one",
+        min_args: 0,
+        max_args: 1,
+        args: &[ArgKind::Value],
+        write: false,
+        since: "0.1.0",
+    },
+];
+
+impl CommandInfo {
+    /// Finds a command by its name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the command, in uppercase.
+    ///
+    /// # Returns
+    ///
+    /// The command, or None if the server does not accept it.
+    pub(crate) fn find(name: &str) -> Option<&'static CommandInfo> {
+        COMMANDS.iter().find(|info| info.name == name)
+    }
+
+    /// Finds the command that a mistyped name was most likely meant to be.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The mistyped name, in uppercase.
+    ///
+    /// # Returns
+    ///
+    /// The command with the closest name, or None if no name is within two edits.
+    pub(crate) fn closest(name: &str) -> Option<&'static CommandInfo> {
+        COMMANDS
+            .iter()
+            .map(|info| (edit_distance(name, info.name), info))
+            .filter(|(distance, _)| *distance <= 2)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, info)| info)
+    }
+
+    /// Checks whether the command takes a number of arguments.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - The number of arguments.
+    ///
+    /// # Returns
+    ///
+    /// True if the number is within the arity of the command, false otherwise.
+    pub(crate) fn accepts(&self, args: usize) -> bool {
+        (self.min_args..=self.max_args).contains(&args)
+    }
+
+    /// Checks the arguments of a command against its arity and the kinds of its arguments.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - The arguments of the command.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the command can be executed with the arguments.
+    ///
+    /// # Errors
+    ///
+    /// If there are too few or too many arguments, it will return
+    /// [`MiniRedisError::WrongArity`]. If an integer argument is not an integer,
+    /// it will return [`MiniRedisError::NotAnInteger`], and if a keyword argument
+    /// is not one of its keywords, [`MiniRedisError::InvalidArguments`].
+    pub(crate) fn check(&self, args: &[String]) -> Result<(), MiniRedisError> {
+        if !self.accepts(args.len()) {
+            return Err(MiniRedisError::WrongArity {
+                command: self.name.to_string(),
+            });
+        }
+        for (kind, arg) in self.args.iter().zip(args) {
+            match kind {
+                ArgKind::Key | ArgKind::Value => {}
+                ArgKind::Integer => {
+                    integer(arg)?;
+                }
+                ArgKind::Cursor => {
+                    cursor(arg)?;
+                }
+                ArgKind::Keyword(keywords) => {
+                    if !keywords
+                        .iter()
+                        .any(|keyword| keyword.eq_ignore_ascii_case(arg))
+                    {
+                        return Err(MiniRedisError::InvalidArguments {
+                            arguments: args.to_vec(),
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses an integer argument.
+///
+/// # Arguments
+///
+/// * `arg` - The argument.
+///
+/// # Returns
+///
+/// The integer.
+///
+/// # Errors
+///
+/// If the argument is not an integer that fits in 64 bits,
+/// it will return [`MiniRedisError::NotAnInteger`].
+pub(crate) fn integer(arg: &str) -> Result<i64, MiniRedisError> {
+    arg.parse().map_err(|_| MiniRedisError::NotAnInteger)
+}
+
+/// Parses a cursor argument.
+///
+/// # Arguments
+///
+/// * `arg` - The argument.
+///
+/// # Returns
+///
+/// The cursor.
+///
+/// # Errors
+///
+/// If the argument is not an integer from 0 up that fits in 64 bits,
+/// it will return [`MiniRedisError::NotAnInteger`].
+pub(crate) fn cursor(arg: &str) -> Result<u64, MiniRedisError> {
+    arg.parse().map_err(|_| MiniRedisError::NotAnInteger)
+}
+
+/// Counts the insertions, deletions, and substitutions of characters
+/// that turn one word into another.
+///
+/// # Arguments
+///
+/// * `a` - The first word.
+/// * `b` - The second word.
+///
+/// # Returns
+///
+/// The Levenshtein distance between the words.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<char>>();
+    let mut previous = (0..=b.len()).collect::<Vec<usize>>();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_distance_counts_single_character_edits() {
+        assert_eq!(0, edit_distance("GET", "GET"));
+        assert_eq!(1, edit_distance("GT", "GET"));
+        assert_eq!(1, edit_distance("GETS", "GET"));
+        assert_eq!(2, edit_distance("GTE", "GET"));
+        assert_eq!(3, edit_distance("", "DEL"));
+        assert_eq!(6, edit_distance("STRLEN", "PING"));
+    }
+
+    #[test]
+    fn closest_suggests_only_near_names() {
+        assert_eq!(
+            Some("GET"),
+            CommandInfo::closest("GTE").map(|info| info.name)
+        );
+        assert_eq!(
+            Some("PING"),
+            CommandInfo::closest("PIGN").map(|info| info.name)
+        );
+        assert_eq!(
+            Some("DELPREFIX"),
+            CommandInfo::closest("DELPREFX").map(|info| info.name)
+        );
+        assert_eq!(None, CommandInfo::closest("UNKNOWN"));
+    }
+
+    #[test]
+    fn command_infos_know_the_arity_of_every_command() {
+        let set = CommandInfo::find("SET").unwrap();
+        let ping = CommandInfo::find("PING").unwrap();
+
+        assert!(set.accepts(2) && set.accepts(6) && !set.accepts(1));
+        assert!(ping.accepts(0) && ping.accepts(1) && !ping.accepts(2));
+        assert_eq!(None, CommandInfo::find("get"));
+    }
+
+    #[test]
+    fn check_rejects_the_wrong_arity_and_kinds_of_arguments() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        let scan = CommandInfo::find("SCAN").unwrap();
+
+        assert_eq!(
+            Ok(()),
+            scan.check(&args(&["0", "count", "5", "MATCH", "a*"]))
+        );
+        assert_eq!(Ok(()), scan.check(&args(&["18446744073709551615"])));
+        assert_eq!(
+            Err(MiniRedisError::WrongArity {
+                command: "SCAN".to_string()
+            }),
+            scan.check(&[])
+        );
+        assert_eq!(Err(MiniRedisError::NotAnInteger), scan.check(&args(&["x"])));
+        assert_eq!(
+            Err(MiniRedisError::NotAnInteger),
+            scan.check(&args(&["-1"]))
+        );
+        assert_eq!(
+            Err(MiniRedisError::NotAnInteger),
+            scan.check(&args(&["99999999999999999999"]))
+        );
+        assert_eq!(
+            Err(MiniRedisError::InvalidArguments {
+                arguments: args(&["0", "LIMIT", "1"])
+            }),
+            scan.check(&args(&["0", "LIMIT", "1"]))
+        );
+    }
+
+    #[test]
+    fn parse_parses_get_command() {
+        let result = Command::parse("GET mykey\n");
+        assert_eq!(Some(Command::new("GET", vec!["mykey".to_string()])), result);
+    }
+
+    #[test]
+    fn parse_parses_set_command() {
+        let result = Command::parse("SET mykey myvalue\n");
+        assert_eq!(
+            Some(Command::new(
+                "SET",
+                vec!["mykey".to_string(), "myvalue".to_string()]
+            )),
+            result
+        );
+    }
+
+    #[test]
+    fn parse_parses_del_command() {
+        let result = Command::parse("DEL mykey\n");
+        assert_eq!(Some(Command::new("DEL", vec!["mykey".to_string()])), result);
+    }
+
+    #[test]
+    fn parse_handles_lowercase_commands() {
+        let result = Command::parse("get mykey\n");
+        assert_eq!(Some("GET".to_string()), result.map(|command| command.name));
+    }
+
+    #[test]
+    fn parse_handles_mixed_case_commands() {
+        let result = Command::parse("GeT mykey\n");
+        assert_eq!(Some("GET".to_string()), result.map(|command| command.name));
+    }
+
+    #[test]
+    fn parse_handles_extra_whitespace() {
+        let result = Command::parse("  SET   mykey   myvalue  \n");
+        assert_eq!(
+            Some(Command::new(
+                "SET",
+                vec!["mykey".to_string(), "myvalue".to_string()]
+            )),
+            result
+        );
+    }
+
+    #[test]
+    fn parse_returns_none_for_empty_line() {
+        assert_eq!(None, Command::parse("\n"));
+    }
+
+    #[test]
+    fn parse_returns_none_for_whitespace_only() {
+        assert_eq!(None, Command::parse("   \n"));
+    }
+
+    #[test]
+    fn parse_groups_quoted_words_into_one_argument() {
+        let result = Command::parse("SET msg \"hello   world\"\n");
+        assert_eq!(
+            Some(vec!["msg".to_string(), "hello   world".to_string()]),
+            result.map(|command| command.args)
+        );
+    }
+
+    #[test]
+    fn parse_handles_empty_quotes_and_escapes() {
+        let result = Command::parse(r#"SET "" "say \"hi\" \\ bye" a\b"#);
+        assert_eq!(
+            Some(vec![
+                String::new(),
+                r#"say "hi" \ bye"#.to_string(),
+                r"a\b".to_string()
+            ]),
+            result.map(|command| command.args)
+        );
+    }
+
+    #[test]
+    fn parse_runs_unclosed_quote_to_end_of_line() {
+        let result = Command::parse("SET msg \"hello world\n");
+        assert_eq!(
+            Some(vec!["msg".to_string(), "hello world".to_string()]),
+            result.map(|command| command.args)
+        );
+    }
+
+    #[test]
+    fn display_round_trips_through_parse() {
+        let command = Command::new(
+            "SET",
+            vec![
+                "key".to_string(),
+                String::new(),
+                "hello world".to_string(),
+                r#"quote " and \ backslash"#.to_string(),
+            ],
+        );
+
+        assert_eq!(Some(command.clone()), Command::parse(&command.to_string()));
+    }
+}
@@ -0,0 +1,197 @@
+/// The shortest run of bytes that is worth copying from earlier output, instead of
+/// writing it out as literals.
+const MIN_MATCH: usize = 4;
+
+/// The longest run of bytes that one copy token can repeat.
+const MAX_MATCH: usize = MIN_MATCH + 0x7F;
+
+/// How far back a copy token can reach.
+const MAX_OFFSET: usize = u16::MAX as usize;
+
+/// How many literal bytes one literal token can hold.
+const MAX_LITERALS: usize = 0x80;
+
+/// The number of slots in the table of earlier positions, a power of two.
+const HASH_SIZE: usize = 1 << 14;
+
+/// Compresses bytes with a small LZ77 codec.
+///
+/// The output is the length of the input as a little endian u64, followed by tokens.
+/// A token byte below 0x80 is followed by that many literal bytes plus one. A token byte
+/// of 0x80 or more is followed by a little endian u16 offset, and copies the low 7 bits
+/// plus 4 bytes from that far back in the output, which may overlap the bytes it writes.
+/// Matches are found greedily through a table of the last position of every 4-byte prefix,
+/// which is quick and does well on text that repeats itself, like JSON.
+///
+/// # Arguments
+///
+/// * `input` - The bytes to compress.
+///
+/// # Returns
+///
+/// The compressed bytes.
+pub(crate) fn compress(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len() / 2 + 16);
+    output.extend_from_slice(&(input.len() as u64).to_le_bytes());
+    let mut table = vec![usize::MAX; HASH_SIZE];
+    let (mut pos, mut literals) = (0, 0);
+
+    while pos + MIN_MATCH <= input.len() {
+        let prefix = &input[pos..pos + MIN_MATCH];
+        let slot = hash(prefix);
+        let candidate = table[slot];
+        table[slot] = pos;
+        if candidate == usize::MAX
+            || pos - candidate > MAX_OFFSET
+            || &input[candidate..candidate + MIN_MATCH] != prefix
+        {
+            pos += 1;
+            continue;
+        }
+
+        let mut len = MIN_MATCH;
+        while len < MAX_MATCH
+            && pos + len < input.len()
+            && input[candidate + len] == input[pos + len]
+        {
+            len += 1;
+        }
+        write_literals(&mut output, &input[literals..pos]);
+        output.push(0x80 | (len - MIN_MATCH) as u8);
+        output.extend_from_slice(&((pos - candidate) as u16).to_le_bytes());
+        pos += len;
+        literals = pos;
+    }
+    write_literals(&mut output, &input[literals..]);
+    output
+}
+
+/// Decompresses bytes written by [`compress`].
+///
+/// # Arguments
+///
+/// * `bytes` - The compressed bytes.
+///
+/// # Returns
+///
+/// The original bytes.
+///
+/// # Errors
+///
+/// If a token is cut off, copies from before the start of the output,
+/// or the output does not come out at its recorded length,
+/// it will return a description of the problem.
+pub(crate) fn decompress(bytes: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let (len, mut rest) = bytes
+        .split_first_chunk::<8>()
+        .ok_or("compressed payload is too short")?;
+    let len = u64::from_le_bytes(*len) as usize;
+    // A damaged length must not make us allocate more than the tokens could ever write.
+    let mut output = Vec::with_capacity(len.min(bytes.len().saturating_mul(MAX_MATCH)));
+
+    while let Some((&token, tail)) = rest.split_first() {
+        if token < 0x80 {
+            let count = token as usize + 1;
+            if tail.len() < count {
+                return Err("compressed payload ends inside a literal run");
+            }
+            output.extend_from_slice(&tail[..count]);
+            rest = &tail[count..];
+        } else {
+            let Some((offset, tail)) = tail.split_first_chunk::<2>() else {
+                return Err("compressed payload ends inside a match");
+            };
+            let offset = u16::from_le_bytes(*offset) as usize;
+            if offset == 0 || offset > output.len() {
+                return Err("compressed payload copies from before its start");
+            }
+            let start = output.len() - offset;
+            for i in 0..(token & 0x7F) as usize + MIN_MATCH {
+                output.push(output[start + i]);
+            }
+            rest = tail;
+        }
+        if output.len() > len {
+            return Err("compressed payload is longer than its recorded length");
+        }
+    }
+    if output.len() != len {
+        return Err("compressed payload is shorter than its recorded length");
+    }
+    Ok(output)
+}
+
+/// Appends bytes as literal tokens.
+///
+/// # Arguments
+///
+/// * `output` - The buffer to append to.
+/// * `literals` - The bytes, which may be empty.
+fn write_literals(output: &mut Vec<u8>, literals: &[u8]) {
+    for chunk in literals.chunks(MAX_LITERALS) {
+        output.push((chunk.len() - 1) as u8);
+        output.extend_from_slice(chunk);
+    }
+}
+
+/// Hashes the 4-byte prefix at a position into a slot of the table.
+///
+/// # Arguments
+///
+/// * `prefix` - The 4 bytes.
+///
+/// # Returns
+///
+/// The slot, below [`HASH_SIZE`].
+fn hash(prefix: &[u8]) -> usize {
+    let word = u32::from_le_bytes(prefix.try_into().unwrap());
+    (word.wrapping_mul(2_654_435_761) >> (32 - HASH_SIZE.trailing_zeros())) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    #[test]
+    fn decompress_returns_compressed_bytes() {
+        let mut rng = Rng::with_seed(7);
+        let random = (0..5000).map(|_| rng.next_u64() as u8).collect::<Vec<u8>>();
+        let repeated = b"{\"name\":\"alice\",\"tags\":[1,2,3]}".repeat(300);
+        let runs = [vec![b'a'; 1000], b"abc".to_vec(), vec![0; 200]].concat();
+
+        let inputs: [&[u8]; 6] = [b"", b"abc", b"abcdabcdabcd", &random, &repeated, &runs];
+
+        for input in inputs {
+            assert_eq!(Ok(input.to_vec()), decompress(&compress(input)));
+        }
+    }
+
+    #[test]
+    fn compress_shrinks_repetitive_bytes() {
+        let input = b"{\"user\":\"alice\",\"active\":true}".repeat(1000);
+
+        assert!(compress(&input).len() < input.len() / 10);
+    }
+
+    #[test]
+    fn decompress_rejects_damaged_payloads() {
+        let mut bytes = compress(b"abcdabcdabcdabcd");
+
+        assert!(decompress(&bytes[..bytes.len() - 1]).is_err());
+        assert!(decompress(&bytes[..4]).is_err());
+
+        bytes[0] += 1;
+        assert_eq!(
+            Err("compressed payload is shorter than its recorded length"),
+            decompress(&bytes)
+        );
+
+        let mut copy_before_start = 3u64.to_le_bytes().to_vec();
+        copy_before_start.extend_from_slice(&[0x80, 1, 0]);
+        assert_eq!(
+            Err("compressed payload copies from before its start"),
+            decompress(&copy_before_start)
+        );
+    }
+}
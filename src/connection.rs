@@ -0,0 +1,1683 @@
+use crate::error::MiniRedisError;
+use crate::protocol;
+use crate::response::Response;
+use crate::rng::Rng;
+use crate::storage::ScanOptions;
+use crate::tokenizer;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::Duration;
+
+/// The most times a command follows [`MiniRedisError::Moved`] to another node
+/// before the redirection is returned, so nodes that point at each other cannot loop forever.
+const MAX_REDIRECTS: usize = 5;
+
+/// The protocol a [`Connection`] speaks to the server.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protocol {
+    /// Commands are lines of words, with quotes around words holding spaces.
+    Inline,
+    /// Commands are RESP arrays of bulk strings, so words hold anything as they are.
+    Resp,
+}
+
+/// How a connection retries when the server cannot be reached.
+///
+/// Failing to connect, or to authenticate because the connection failed, is retried,
+/// waiting `retry_delay` before the first retry and twice as long before every retry after it,
+/// with some jitter so clients started together do not retry together.
+/// A command is only sent again if `retry_writes` is set, because the server may have
+/// executed it before the connection failed, and writing twice is not always harmless.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use miniredis::connection::{ConnectOptions, Connection};
+///
+/// let connection = Connection::builder("127.0.0.1:6379")
+///     .options(ConnectOptions { retries: 5, ..ConnectOptions::default() })
+///     .connect()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConnectOptions {
+    /// How many times to try again after the first attempt fails.
+    pub retries: u32,
+    /// How long to wait before the first retry.
+    pub retry_delay: Duration,
+    /// Whether to send a command again on a new connection when the connection fails after sending it.
+    pub retry_writes: bool,
+}
+
+impl Default for ConnectOptions {
+    /// Creates options that never retry, with a delay of 100 milliseconds if retries are added.
+    ///
+    /// # Returns
+    ///
+    /// The default options.
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            retry_delay: Duration::from_millis(100),
+            retry_writes: false,
+        }
+    }
+}
+
+impl ConnectOptions {
+    /// Runs an attempt until it succeeds, fails for a reason other than the connection,
+    /// or fails more often than there are retries.
+    ///
+    /// # Arguments
+    ///
+    /// * `attempt` - The attempt to run.
+    ///
+    /// # Returns
+    ///
+    /// What the first successful attempt returned.
+    ///
+    /// # Errors
+    ///
+    /// If an attempt fails for a reason other than the connection, it will return that error.
+    /// If every attempt fails because of the connection, it will return
+    /// [`MiniRedisError::RetriesExhausted`] with the last error, or only the error without retries.
+    fn retry<T>(
+        &self,
+        mut attempt: impl FnMut() -> Result<T, MiniRedisError>,
+    ) -> Result<T, MiniRedisError> {
+        let mut rng = Rng::new();
+        let mut failures = 0;
+        loop {
+            match attempt() {
+                Err(e) if is_connection_error(&e) && failures < self.retries => {
+                    thread::sleep(self.backoff(failures, &mut rng));
+                    failures += 1;
+                }
+                Err(e) if is_connection_error(&e) && failures > 0 => {
+                    return Err(MiniRedisError::RetriesExhausted {
+                        attempts: failures + 1,
+                        source: Box::new(e),
+                    });
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Picks how long to wait before a retry.
+    ///
+    /// # Arguments
+    ///
+    /// * `failures` - How many attempts failed so far, minus one.
+    /// * `rng` - The source of the jitter.
+    ///
+    /// # Returns
+    ///
+    /// Between half and all of the retry delay doubled for every earlier retry.
+    fn backoff(&self, failures: u32, rng: &mut Rng) -> Duration {
+        let delay = self.retry_delay.saturating_mul(1 << failures.min(16));
+        let jitter = rng.below(1_000) as u32;
+        delay / 2 + delay / 2 * jitter / 1_000
+    }
+}
+
+/// A connection to a server, for sending commands from code.
+///
+/// The connection keeps one stream open and reuses it for every command,
+/// so commands are sent without connecting again each time.
+/// Error replies from the server are returned as errors, not as responses.
+///
+/// Unless told which protocol to speak, the connection asks the server with `HELLO`
+/// before its first command, and speaks RESP if the server supports it, or inline otherwise.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use miniredis::connection::Connection;
+///
+/// let mut connection = Connection::connect("127.0.0.1:6379").unwrap();
+/// connection.set("name", "alice").unwrap();
+///
+/// assert_eq!(Some("alice".to_string()), connection.get("name").unwrap());
+/// ```
+pub struct Connection {
+    address: String,
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+    timeout: Option<Duration>,
+    protocol: Option<Protocol>,
+    reconnect: Option<ConnectionBuilder>,
+    redirect: Option<ConnectionBuilder>,
+    broken: bool,
+}
+
+impl Connection {
+    /// Connects to a server.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address of the server to connect to,
+    ///   or several separated by commas, like a primary and its replica, to try in order.
+    ///
+    /// # Returns
+    ///
+    /// A connection to the server.
+    ///
+    /// # Errors
+    ///
+    /// If the connection fails, it will return [`MiniRedisError::StreamNotConnected`],
+    /// or [`MiniRedisError::AddressesNotConnected`] with the failure of every address
+    /// if several were given.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::connection::Connection;
+    ///
+    /// let connection = Connection::connect("127.0.0.1:6379").unwrap();
+    /// let failover = Connection::connect("10.0.0.1:6379,10.0.0.2:6379").unwrap();
+    /// ```
+    pub fn connect(address: &str) -> Result<Self, MiniRedisError> {
+        Self::connect_with(address, None, None)
+    }
+
+    /// Connects to a server, giving up on slow servers.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address of the server to connect to.
+    /// * `connect_timeout` - How long to wait for the connection, or None to wait as long as it takes.
+    /// * `timeout` - How long to wait for the server to accept a command or send a response,
+    ///   or None to wait as long as it takes.
+    ///
+    /// # Returns
+    ///
+    /// A connection to the server.
+    ///
+    /// # Errors
+    ///
+    /// If the connection fails, it will return an error,
+    /// which is [`MiniRedisError::Timeout`] if connecting timed out.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::connection::Connection;
+    /// use std::time::Duration;
+    ///
+    /// let connection = Connection::connect_with(
+    ///     "127.0.0.1:6379",
+    ///     Some(Duration::from_secs(1)),
+    ///     Some(Duration::from_secs(5)),
+    /// )
+    /// .unwrap();
+    /// ```
+    pub fn connect_with(
+        address: &str,
+        connect_timeout: Option<Duration>,
+        timeout: Option<Duration>,
+    ) -> Result<Self, MiniRedisError> {
+        ConnectionBuilder {
+            connect_timeout,
+            timeout,
+            ..Self::builder(address)
+        }
+        .connect()
+    }
+
+    /// Creates a builder for a connection with more options than [`Connection::connect`].
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address of the server to connect to,
+    ///   or several separated by commas to try in order.
+    ///
+    /// # Returns
+    ///
+    /// A builder with every option at its default.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::connection::{Connection, Protocol};
+    /// use std::time::Duration;
+    ///
+    /// let connection = Connection::builder("127.0.0.1:6379")
+    ///     .timeout(Duration::from_secs(5))
+    ///     .protocol(Protocol::Resp)
+    ///     .connect()
+    ///     .unwrap();
+    /// ```
+    pub fn builder(address: &str) -> ConnectionBuilder {
+        let addresses = address
+            .split(',')
+            .map(str::trim)
+            .filter(|address| !address.is_empty())
+            .map(str::to_string)
+            .collect::<Vec<String>>();
+        ConnectionBuilder {
+            addresses: if addresses.is_empty() {
+                vec![address.to_string()]
+            } else {
+                addresses
+            },
+            connect_timeout: None,
+            timeout: None,
+            protocol: None,
+            user: None,
+            password: None,
+            options: ConnectOptions::default(),
+        }
+    }
+
+    /// Opens the stream of a connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address of the server to connect to.
+    /// * `connect_timeout` - How long to wait for the connection, or None to wait as long as it takes.
+    /// * `timeout` - How long to wait for the server, or None to wait as long as it takes.
+    /// * `protocol` - The protocol to speak, or None to ask the server before the first command.
+    ///
+    /// # Returns
+    ///
+    /// A connection to the server.
+    ///
+    /// # Errors
+    ///
+    /// If the connection fails, it will return an error,
+    /// which is [`MiniRedisError::Timeout`] if connecting timed out.
+    fn open(
+        address: &str,
+        connect_timeout: Option<Duration>,
+        timeout: Option<Duration>,
+        protocol: Option<Protocol>,
+    ) -> Result<Self, MiniRedisError> {
+        let stream = match connect_timeout {
+            Some(connect_timeout) => connect_within(address, connect_timeout)?,
+            None => TcpStream::connect(address).map_err(|e| not_connected(address, e))?,
+        };
+        stream
+            .set_read_timeout(timeout)
+            .and_then(|()| stream.set_write_timeout(timeout))
+            .map_err(|e| not_connected(address, e))?;
+        let reader = BufReader::new(stream.try_clone()?);
+        Ok(Self {
+            address: address.to_string(),
+            stream,
+            reader,
+            timeout,
+            protocol,
+            reconnect: None,
+            redirect: None,
+            broken: false,
+        })
+    }
+
+    /// Gets the address of the server the connection is connected to.
+    ///
+    /// Of several addresses, this is the one that accepted the connection,
+    /// and it changes if a failed command is sent again on a connection to another one.
+    ///
+    /// # Returns
+    ///
+    /// The address of the server.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::connection::Connection;
+    ///
+    /// let connection = Connection::connect("10.0.0.1:6379,10.0.0.2:6379").unwrap();
+    ///
+    /// println!("Connected to {}", connection.address());
+    /// ```
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Gets the protocol the connection speaks, asking the server if it was not asked yet.
+    ///
+    /// # Returns
+    ///
+    /// The protocol of the connection.
+    ///
+    /// # Errors
+    ///
+    /// If the server has to be asked, and `HELLO` cannot be sent or answered,
+    /// it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::connection::{Connection, Protocol};
+    ///
+    /// let mut connection = Connection::connect("127.0.0.1:6379").unwrap();
+    ///
+    /// assert_eq!(Protocol::Resp, connection.protocol().unwrap());
+    /// ```
+    pub fn protocol(&mut self) -> Result<Protocol, MiniRedisError> {
+        if let Some(protocol) = self.protocol {
+            return Ok(protocol);
+        }
+        let hello = self.send_requests(Protocol::Inline, &["HELLO".to_string()])?;
+        let protocol = match hello.first() {
+            Some(Response::Map(fields)) if speaks_resp(fields) => Protocol::Resp,
+            _ => Protocol::Inline,
+        };
+        self.protocol = Some(protocol);
+        Ok(protocol)
+    }
+
+    /// Checks that the server answers.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the server answered.
+    ///
+    /// # Errors
+    ///
+    /// If the command cannot be sent or answered, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::connection::Connection;
+    ///
+    /// let mut connection = Connection::connect("127.0.0.1:6379").unwrap();
+    ///
+    /// connection.ping().unwrap();
+    /// ```
+    pub fn ping(&mut self) -> Result<(), MiniRedisError> {
+        self.command(&["PING"]).map(|_| ())
+    }
+
+    /// Gets the value of a key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to get.
+    ///
+    /// # Returns
+    ///
+    /// The value of the key, or None if the key does not exist.
+    ///
+    /// # Errors
+    ///
+    /// If the command cannot be sent or answered, or the server replies with an error,
+    /// it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::connection::Connection;
+    ///
+    /// let mut connection = Connection::connect("127.0.0.1:6379").unwrap();
+    ///
+    /// assert_eq!(None, connection.get("missing").unwrap());
+    /// ```
+    pub fn get(&mut self, key: &str) -> Result<Option<String>, MiniRedisError> {
+        match self.command(&["GET", key])? {
+            Response::Nil => Ok(None),
+            Response::Bulk(value) | Response::Simple(value) => Ok(Some(value)),
+            Response::BulkBytes(value) => Ok(Some(String::from_utf8_lossy(&value).into_owned())),
+            response => Ok(Some(response.to_string())),
+        }
+    }
+
+    /// Gets the value of a key as the bytes it holds.
+    ///
+    /// Unlike [`Connection::get`], this keeps values that are not valid UTF-8,
+    /// like ones built with SETBIT, as they are. Only RESP carries such bytes:
+    /// over the inline protocol they arrive as U+FFFD.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to get.
+    ///
+    /// # Returns
+    ///
+    /// The bytes of the value of the key, or None if the key does not exist.
+    ///
+    /// # Errors
+    ///
+    /// If the command cannot be sent or answered, or the server replies with an error,
+    /// it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::connection::Connection;
+    ///
+    /// let mut connection = Connection::connect("127.0.0.1:6379").unwrap();
+    /// connection.command(&["SETBIT", "bits", "0", "1"]).unwrap();
+    ///
+    /// assert_eq!(Some(vec![0x80]), connection.get_bytes("bits").unwrap());
+    /// ```
+    pub fn get_bytes(&mut self, key: &str) -> Result<Option<Vec<u8>>, MiniRedisError> {
+        match self.command(&["GET", key])? {
+            Response::Nil => Ok(None),
+            Response::BulkBytes(value) => Ok(Some(value)),
+            Response::Bulk(value) | Response::Simple(value) => Ok(Some(value.into_bytes())),
+            response => Ok(Some(response.to_string().into_bytes())),
+        }
+    }
+
+    /// Sets the value of a key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to set.
+    /// * `value` - The value to set the key to.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the server set the key.
+    ///
+    /// # Errors
+    ///
+    /// If the command cannot be sent or answered, or the server replies with an error,
+    /// like [`MiniRedisError::StoreFull`], it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::connection::Connection;
+    ///
+    /// let mut connection = Connection::connect("127.0.0.1:6379").unwrap();
+    ///
+    /// connection.set("name", "alice").unwrap();
+    /// ```
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), MiniRedisError> {
+        self.command(&["SET", key, value]).map(|_| ())
+    }
+
+    /// Deletes a key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to delete.
+    ///
+    /// # Returns
+    ///
+    /// True if the key existed, false otherwise.
+    ///
+    /// # Errors
+    ///
+    /// If the command cannot be sent or answered, or the server replies with an error,
+    /// it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::connection::Connection;
+    ///
+    /// let mut connection = Connection::connect("127.0.0.1:6379").unwrap();
+    ///
+    /// connection.set("name", "alice").unwrap();
+    ///
+    /// assert!(connection.del("name").unwrap());
+    /// assert!(!connection.del("name").unwrap());
+    /// ```
+    pub fn del(&mut self, key: &str) -> Result<bool, MiniRedisError> {
+        self.command(&["DEL", key])
+            .map(|reply| matches!(reply, Response::Integer(count) if count > 0))
+    }
+
+    /// Iterates over the keys of the server with `SCAN`, fetching a batch whenever the last one runs out.
+    ///
+    /// Every key that exists during the whole iteration is yielded,
+    /// and without writes meanwhile, each of them exactly once.
+    /// An error ends the iteration after it is yielded.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - A glob pattern the keys must match, like `session:*`, or None for every key.
+    ///
+    /// # Returns
+    ///
+    /// An iterator over the keys.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::connection::Connection;
+    ///
+    /// let mut connection = Connection::connect("127.0.0.1:6379").unwrap();
+    ///
+    /// for key in connection.scan_iter(Some("session:*")) {
+    ///     println!("{}", key.unwrap());
+    /// }
+    /// ```
+    pub fn scan_iter(
+        &mut self,
+        pattern: Option<&str>,
+    ) -> impl Iterator<Item = Result<String, MiniRedisError>> + '_ {
+        self.scan_iter_with(ScanOptions {
+            pattern: pattern.map(str::to_string),
+            ..ScanOptions::default()
+        })
+    }
+
+    /// Iterates over the keys of the server with `SCAN`, passing on every option of it.
+    ///
+    /// The server filters the keys, so only the kept ones come over the network.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The pattern and the type of the keys to keep,
+    ///   and how many keys the server looks at for every batch.
+    ///
+    /// # Returns
+    ///
+    /// An iterator over the keys.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::connection::Connection;
+    /// use miniredis::storage::ScanOptions;
+    ///
+    /// let mut connection = Connection::connect("127.0.0.1:6379").unwrap();
+    /// let options = ScanOptions {
+    ///     pattern: Some("session:*".to_string()),
+    ///     kind: Some("string".to_string()),
+    ///     count: 1000,
+    /// };
+    ///
+    /// for key in connection.scan_iter_with(options) {
+    ///     println!("{}", key.unwrap());
+    /// }
+    /// ```
+    pub fn scan_iter_with(
+        &mut self,
+        options: ScanOptions,
+    ) -> impl Iterator<Item = Result<String, MiniRedisError>> + '_ {
+        ScanIter {
+            connection: self,
+            options,
+            cursor: Some(0),
+            batch: Vec::new().into_iter(),
+        }
+    }
+
+    /// Fetches one batch of keys with `SCAN`.
+    ///
+    /// Unlike [`Connection::scan_iter`], the connection is free between batches,
+    /// so the keys of a batch can be read before the next one is fetched.
+    ///
+    /// # Arguments
+    ///
+    /// * `cursor` - The cursor to continue from, 0 to start.
+    /// * `options` - Which keys the server keeps, and how many it looks at.
+    ///
+    /// # Returns
+    ///
+    /// The cursor to continue with, 0 if the scan is done, and the batch.
+    ///
+    /// # Errors
+    ///
+    /// If `SCAN` cannot be sent or answered, or the reply is not a cursor and keys,
+    /// it will return an error.
+    pub(crate) fn scan(
+        &mut self,
+        cursor: u64,
+        options: &ScanOptions,
+    ) -> Result<(u64, Vec<String>), MiniRedisError> {
+        let cursor = cursor.to_string();
+        let count = options.count.to_string();
+        let mut parts = vec!["SCAN", &cursor, "COUNT", &count];
+        if let Some(pattern) = &options.pattern {
+            parts.extend(["MATCH", pattern]);
+        }
+        if let Some(kind) = &options.kind {
+            parts.extend(["TYPE", kind]);
+        }
+        let reply = self.command(&parts)?;
+
+        let Response::Array(fields) = &reply else {
+            return Err(unexpected_reply(&reply));
+        };
+        let [next, Response::Array(keys)] = fields.as_slice() else {
+            return Err(unexpected_reply(&reply));
+        };
+        let next = match next {
+            Response::Integer(next) => u64::try_from(*next).ok(),
+            Response::Bulk(next) => next.parse().ok(),
+            _ => None,
+        }
+        .ok_or_else(|| unexpected_reply(&reply))?;
+        let keys = keys
+            .iter()
+            .map(|key| match key {
+                Response::Bulk(key) | Response::Simple(key) => key.clone(),
+                key => key.to_string(),
+            })
+            .collect();
+        Ok((next, keys))
+    }
+
+    /// Sends any command and reads its response.
+    ///
+    /// A word with spaces or quotes is quoted on the way, so it stays one argument.
+    ///
+    /// If the server replies that another node serves the key, with [`MiniRedisError::Moved`],
+    /// the command is sent again to that node, and the connection stays connected to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `parts` - The name of the command, followed by its arguments.
+    ///
+    /// # Returns
+    ///
+    /// The response of the server, which is never [`Response::Error`].
+    ///
+    /// # Errors
+    ///
+    /// If there is no command name, it will return [`MiniRedisError::InvalidArguments`].
+    /// If the command cannot be sent or answered, it will return an error.
+    /// If the server replies with an error, it will return the error the server ran into,
+    /// or [`MiniRedisError::ServerError`] if the error is not one the client knows.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::connection::Connection;
+    /// use miniredis::response::Response;
+    ///
+    /// let mut connection = Connection::connect("127.0.0.1:6379").unwrap();
+    ///
+    /// assert_eq!(Response::Integer(0), connection.command(&["DELPREFIX", "tmp:"]).unwrap());
+    /// ```
+    pub fn command(&mut self, parts: &[&str]) -> Result<Response, MiniRedisError> {
+        let mut reply = protocol::decode_reply(self.request(parts)?);
+        for _ in 0..MAX_REDIRECTS {
+            let Err(MiniRedisError::Moved { address, .. }) = &reply else {
+                break;
+            };
+            let Some(redirect) = &self.redirect else {
+                break;
+            };
+            let mut connection = redirect.attempt_at(address)?;
+            reply = protocol::decode_reply(connection.request(parts)?);
+            *self = connection;
+        }
+        reply
+    }
+
+    /// Authenticates the connection with `AUTH`.
+    ///
+    /// [`ConnectionBuilder::password`] does this right after connecting.
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - The user to authenticate as, or None for the default user.
+    /// * `password` - The password of the user.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the server accepted the credentials.
+    ///
+    /// # Errors
+    ///
+    /// If the server replies with an error, it will return [`MiniRedisError::AuthenticationFailed`]
+    /// holding the reply. If `AUTH` cannot be sent or answered, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::connection::Connection;
+    ///
+    /// let mut connection = Connection::connect("127.0.0.1:6379").unwrap();
+    /// connection.auth(None, "secret").unwrap();
+    /// ```
+    pub fn auth(&mut self, user: Option<&str>, password: &str) -> Result<(), MiniRedisError> {
+        let parts = match user {
+            Some(user) => vec!["AUTH", user, password],
+            None => vec!["AUTH", password],
+        };
+        match self.request(&parts)? {
+            Response::Error(MiniRedisError::ServerError { message }) => {
+                Err(MiniRedisError::AuthenticationFailed { message })
+            }
+            Response::Error(e) => Err(MiniRedisError::AuthenticationFailed {
+                message: e.to_string(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Sends a command in the protocol of the connection and reads its response as it came.
+    ///
+    /// # Arguments
+    ///
+    /// * `parts` - The name of the command, followed by its arguments.
+    ///
+    /// # Returns
+    ///
+    /// The response of the server, which is a [`Response::Error`] if the server replied with one.
+    ///
+    /// # Errors
+    ///
+    /// If there is no command name, it will return [`MiniRedisError::InvalidArguments`].
+    /// If the command cannot be sent or answered, it will return an error.
+    fn request(&mut self, parts: &[&str]) -> Result<Response, MiniRedisError> {
+        let protocol = self.protocol()?;
+        let request = match protocol {
+            Protocol::Inline => protocol::encode_command(parts)?,
+            Protocol::Resp => protocol::encode_resp_command(parts)?,
+        };
+        let mut responses = self.send_requests(protocol, &[request])?;
+        Ok(responses.remove(0))
+    }
+
+    /// Sends a line to the server as it is and reads the response.
+    ///
+    /// Over RESP, the line is split into words the way the server splits inline commands.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The line to send, without a line ending.
+    ///
+    /// # Returns
+    ///
+    /// The response, with every line of it read.
+    /// An error reply is returned as a [`Response::Error`] holding a [`MiniRedisError::ServerError`].
+    ///
+    /// # Errors
+    ///
+    /// If the line cannot be sent, the response cannot be read,
+    /// or the connection closes before the response, it will return an error.
+    /// If a timeout is set and runs out, it will return [`MiniRedisError::Timeout`].
+    pub(crate) fn send_line(&mut self, line: &str) -> Result<Response, MiniRedisError> {
+        let mut responses = self.send_lines(&[line])?;
+        Ok(responses.remove(0))
+    }
+
+    /// Sends several lines to the server at once, then reads every response.
+    ///
+    /// Sending every line before reading any response saves a round trip per line.
+    ///
+    /// # Arguments
+    ///
+    /// * `lines` - The lines to send, without line endings.
+    ///
+    /// # Returns
+    ///
+    /// The response to every line, in order.
+    ///
+    /// # Errors
+    ///
+    /// If the lines cannot be sent, a response cannot be read,
+    /// or the connection closes before every response, it will return an error.
+    /// If a timeout is set and runs out, it will return [`MiniRedisError::Timeout`].
+    pub(crate) fn send_lines(&mut self, lines: &[&str]) -> Result<Vec<Response>, MiniRedisError> {
+        let protocol = self.protocol()?;
+        let requests = match protocol {
+            Protocol::Inline => lines.iter().map(|line| line.to_string()).collect(),
+            Protocol::Resp => lines
+                .iter()
+                .map(|line| protocol::encode_resp_command(&tokenizer::split_words(line)))
+                .collect::<Result<Vec<String>, MiniRedisError>>()?,
+        };
+        self.send_requests(protocol, &requests)
+    }
+
+    /// Sends several commands to the server at once, then reads every response.
+    ///
+    /// Unlike [`Connection::send_lines`], the arguments are sent as they are,
+    /// so they may hold spaces and quotes.
+    ///
+    /// # Arguments
+    ///
+    /// * `commands` - The commands, each the name of the command followed by its arguments.
+    ///
+    /// # Returns
+    ///
+    /// The response to every command, in order.
+    /// An error reply is returned as a [`Response::Error`].
+    ///
+    /// # Errors
+    ///
+    /// If a command has no name, it will return [`MiniRedisError::InvalidArguments`].
+    /// If the commands cannot be sent, a response cannot be read,
+    /// or the connection closes before every response, it will return an error.
+    pub(crate) fn send_commands(
+        &mut self,
+        commands: &[Vec<&str>],
+    ) -> Result<Vec<Response>, MiniRedisError> {
+        let protocol = self.protocol()?;
+        let requests = commands
+            .iter()
+            .map(|parts| match protocol {
+                Protocol::Inline => protocol::encode_command(parts),
+                Protocol::Resp => protocol::encode_resp_command(parts),
+            })
+            .collect::<Result<Vec<String>, MiniRedisError>>()?;
+        self.send_requests(protocol, &requests)
+    }
+
+    /// Sends encoded requests to the server at once, then reads every response.
+    ///
+    /// # Arguments
+    ///
+    /// * `protocol` - The protocol the requests are encoded in.
+    /// * `requests` - The lines of inline requests, without line endings, or the frames of RESP requests.
+    ///
+    /// # Returns
+    ///
+    /// The response to every request, in order.
+    ///
+    /// # Errors
+    ///
+    /// If the requests cannot be sent, a response cannot be read,
+    /// or the connection closes before every response, it will return an error.
+    fn send_requests(
+        &mut self,
+        protocol: Protocol,
+        requests: &[String],
+    ) -> Result<Vec<Response>, MiniRedisError> {
+        let result = self.exchange(protocol, requests);
+        self.broken |= result.is_err();
+        let Some(reconnect) = self.reconnect.clone().filter(|_| result.is_err()) else {
+            return result;
+        };
+
+        // Only now that the first attempt is done can retries replace the connection.
+        let reconnect = reconnect.protocol(protocol);
+        let mut first = Some(result);
+        reconnect.options.retry(|| {
+            if let Some(result) = first.take() {
+                return result;
+            }
+            let mut connection = reconnect.attempt()?;
+            let responses = connection.exchange(protocol, requests)?;
+            *self = connection;
+            Ok(responses)
+        })
+    }
+
+    /// Splits the connection into a half that sends requests and a half that reads responses,
+    /// so one thread can keep sending while another reads.
+    ///
+    /// The halves do not retry, or follow redirections.
+    ///
+    /// # Returns
+    ///
+    /// The half that sends requests, and the half that reads their responses.
+    ///
+    /// # Errors
+    ///
+    /// If the connection has to ask the server for its protocol and that fails,
+    /// or the stream cannot be cloned, it will return an error.
+    pub(crate) fn split(mut self) -> Result<(RequestWriter, ResponseReader), MiniRedisError> {
+        let protocol = self.protocol()?;
+        let writer = RequestWriter {
+            writer: BufWriter::new(self.stream),
+            protocol,
+        };
+        let reader = ResponseReader {
+            address: self.address,
+            reader: self.reader,
+            timeout: self.timeout,
+            protocol,
+        };
+        Ok((writer, reader))
+    }
+
+    /// Checks whether the stream failed, so the connection should not be used again.
+    ///
+    /// After a failed read or write, a late response may still be on its way,
+    /// and would be taken for the response to the next command.
+    ///
+    /// # Returns
+    ///
+    /// True if sending a command or reading its response failed.
+    pub(crate) fn is_broken(&self) -> bool {
+        self.broken
+    }
+
+    /// Writes requests to the stream and reads every response.
+    ///
+    /// # Arguments
+    ///
+    /// * `protocol` - The protocol the requests are encoded in.
+    /// * `requests` - The encoded requests.
+    ///
+    /// # Returns
+    ///
+    /// Every response, in order.
+    ///
+    /// # Errors
+    ///
+    /// If the requests cannot be sent, a response cannot be read,
+    /// or the connection closes before every response, it will return an error.
+    fn exchange(
+        &mut self,
+        protocol: Protocol,
+        requests: &[String],
+    ) -> Result<Vec<Response>, MiniRedisError> {
+        let written = match protocol {
+            Protocol::Inline => write_lines(&mut self.stream, requests),
+            Protocol::Resp => self.stream.write_all(requests.concat().as_bytes()),
+        };
+        written.map_err(|e| {
+            self.timeout_or(e, |source| MiniRedisError::StreamNotWritable { source })
+        })?;
+
+        let mut responses = Vec::with_capacity(requests.len());
+        for _ in requests {
+            let response = match protocol {
+                Protocol::Inline => protocol::read_reply(&mut || self.next_line())?,
+                Protocol::Resp => {
+                    protocol::read_resp(&mut self.reader).map_err(|e| match e.kind() {
+                        io::ErrorKind::UnexpectedEof => MiniRedisError::StreamClosed,
+                        _ => self
+                            .timeout_or(e, |source| MiniRedisError::StreamNotReadable { source }),
+                    })?
+                }
+            };
+            responses.push(response);
+        }
+        Ok(responses)
+    }
+
+    /// Reads the next line of a response.
+    ///
+    /// # Returns
+    ///
+    /// The line, without its line ending.
+    ///
+    /// # Errors
+    ///
+    /// If the line cannot be read, or the connection closes before it, it will return an error.
+    fn next_line(&mut self) -> Result<String, MiniRedisError> {
+        let line = read_line(&mut self.reader).map_err(|e| {
+            self.timeout_or(e, |source| MiniRedisError::StreamNotReadable { source })
+        })?;
+        if line.is_empty() {
+            return Err(MiniRedisError::StreamClosed);
+        }
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+
+    /// Turns an IO error on the stream into a timeout, if one is set, or another error.
+    ///
+    /// # Arguments
+    ///
+    /// * `error` - The IO error.
+    /// * `otherwise` - Builds the error to return for anything but a timeout from the IO error.
+    ///
+    /// # Returns
+    ///
+    /// [`MiniRedisError::Timeout`] if the operation timed out, or the other error.
+    fn timeout_or(
+        &self,
+        error: io::Error,
+        otherwise: impl FnOnce(io::Error) -> MiniRedisError,
+    ) -> MiniRedisError {
+        match self.timeout {
+            Some(timeout) => stream_error(&self.address, error, timeout, otherwise),
+            None => otherwise(error),
+        }
+    }
+}
+
+/// The half of a split [`Connection`] that sends requests, buffering them until flushed.
+pub(crate) struct RequestWriter {
+    writer: BufWriter<TcpStream>,
+    protocol: Protocol,
+}
+
+impl RequestWriter {
+    /// Sends a line to the server, once the buffer fills or is flushed.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The line to send, without a line ending.
+    ///
+    /// # Errors
+    ///
+    /// If the line has a quote that is never closed in RESP, or cannot be written,
+    /// it will return an error.
+    pub(crate) fn send_line(&mut self, line: &str) -> Result<(), MiniRedisError> {
+        let written = match self.protocol {
+            Protocol::Inline => write_lines(&mut self.writer, &[line]),
+            Protocol::Resp => {
+                let request = protocol::encode_resp_command(&tokenizer::split_words(line))?;
+                self.writer.write_all(request.as_bytes())
+            }
+        };
+        written.map_err(|source| MiniRedisError::StreamNotWritable { source })
+    }
+
+    /// Sends every buffered line to the server.
+    ///
+    /// # Errors
+    ///
+    /// If the lines cannot be written, it will return an error.
+    pub(crate) fn flush(&mut self) -> Result<(), MiniRedisError> {
+        self.writer
+            .flush()
+            .map_err(|source| MiniRedisError::StreamNotFlushed { source })
+    }
+
+    /// Closes the connection both ways, so the reading half stops waiting for responses.
+    pub(crate) fn abort(&self) {
+        let _ = self.writer.get_ref().shutdown(Shutdown::Both);
+    }
+}
+
+/// The half of a split [`Connection`] that reads responses.
+pub(crate) struct ResponseReader {
+    address: String,
+    reader: BufReader<TcpStream>,
+    timeout: Option<Duration>,
+    protocol: Protocol,
+}
+
+impl ResponseReader {
+    /// Reads the next response.
+    ///
+    /// # Returns
+    ///
+    /// The response, with every line of it read.
+    /// An error reply is returned as a [`Response::Error`].
+    ///
+    /// # Errors
+    ///
+    /// If the response cannot be read, or the connection closes before it, it will return an error.
+    /// If a timeout is set and runs out, it will return [`MiniRedisError::Timeout`].
+    pub(crate) fn read(&mut self) -> Result<Response, MiniRedisError> {
+        let (address, timeout) = (&self.address, self.timeout);
+        let error = |e: io::Error| match (e.kind(), timeout) {
+            (io::ErrorKind::UnexpectedEof, _) => MiniRedisError::StreamClosed,
+            (_, Some(timeout)) => stream_error(address, e, timeout, |source| {
+                MiniRedisError::StreamNotReadable { source }
+            }),
+            (_, None) => MiniRedisError::StreamNotReadable { source: e },
+        };
+        match self.protocol {
+            Protocol::Inline => protocol::read_reply(&mut || {
+                let line = read_line(&mut self.reader).map_err(error)?;
+                if line.is_empty() {
+                    return Err(MiniRedisError::StreamClosed);
+                }
+                Ok(line.trim_end_matches(['\r', '\n']).to_string())
+            }),
+            Protocol::Resp => protocol::read_resp(&mut self.reader).map_err(error),
+        }
+    }
+}
+
+/// Builds a [`Connection`] with options.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use miniredis::connection::Connection;
+/// use std::time::Duration;
+///
+/// let connection = Connection::builder("127.0.0.1:6379")
+///     .connect_timeout(Duration::from_secs(1))
+///     .connect()
+///     .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct ConnectionBuilder {
+    addresses: Vec<String>,
+    connect_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+    protocol: Option<Protocol>,
+    user: Option<String>,
+    password: Option<String>,
+    options: ConnectOptions,
+}
+
+impl ConnectionBuilder {
+    /// Sets how long connecting waits for the server.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - How long to wait for the connection.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::connection::Connection;
+    /// use std::time::Duration;
+    ///
+    /// let builder = Connection::builder("127.0.0.1:6379").connect_timeout(Duration::from_secs(1));
+    /// ```
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how long reads and writes wait for the server.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - How long to wait for each read and write.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::connection::Connection;
+    /// use std::time::Duration;
+    ///
+    /// let builder = Connection::builder("127.0.0.1:6379").timeout(Duration::from_secs(5));
+    /// ```
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the protocol to speak, instead of asking the server with `HELLO`.
+    ///
+    /// # Arguments
+    ///
+    /// * `protocol` - The protocol to speak.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::connection::{Connection, Protocol};
+    ///
+    /// let builder = Connection::builder("127.0.0.1:6379").protocol(Protocol::Inline);
+    /// ```
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = Some(protocol);
+        self
+    }
+
+    /// Sets the password to authenticate with, right after connecting.
+    ///
+    /// # Arguments
+    ///
+    /// * `password` - The password of the user.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::connection::Connection;
+    ///
+    /// let builder = Connection::builder("127.0.0.1:6379").password("secret");
+    /// ```
+    pub fn password(mut self, password: &str) -> Self {
+        self.password = Some(password.to_string());
+        self
+    }
+
+    /// Sets the user to authenticate as, instead of the default user.
+    ///
+    /// The user is only sent along with a password.
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - The name of the user.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::connection::Connection;
+    ///
+    /// let builder = Connection::builder("127.0.0.1:6379").user("alice").password("secret");
+    /// ```
+    pub fn user(mut self, user: &str) -> Self {
+        self.user = Some(user.to_string());
+        self
+    }
+
+    /// Sets how the connection retries when the server cannot be reached.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The retry policy.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::connection::{ConnectOptions, Connection};
+    /// use std::time::Duration;
+    ///
+    /// let builder = Connection::builder("127.0.0.1:6379").options(ConnectOptions {
+    ///     retries: 3,
+    ///     retry_delay: Duration::from_millis(50),
+    ///     retry_writes: false,
+    /// });
+    /// ```
+    pub fn options(mut self, options: ConnectOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Connects to the server, and authenticates if a password is set,
+    /// retrying as the options of the builder allow.
+    ///
+    /// Of several addresses, each is tried in order until one accepts the connection,
+    /// and every retry starts over from the first.
+    ///
+    /// # Returns
+    ///
+    /// A connection to the server.
+    ///
+    /// # Errors
+    ///
+    /// If the connection fails, it will return an error,
+    /// which is [`MiniRedisError::Timeout`] if connecting timed out,
+    /// [`MiniRedisError::AddressesNotConnected`] if none of several addresses accepted it,
+    /// or [`MiniRedisError::RetriesExhausted`] if it was retried.
+    /// If the server does not accept the password,
+    /// it will return [`MiniRedisError::AuthenticationFailed`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::connection::Connection;
+    ///
+    /// let connection = Connection::builder("127.0.0.1:6379").connect().unwrap();
+    /// ```
+    pub fn connect(self) -> Result<Connection, MiniRedisError> {
+        self.options.retry(|| self.attempt())
+    }
+
+    /// Connects to the first address that accepts the connection, and authenticates
+    /// if a password is set.
+    ///
+    /// Only failures to connect move on to the next address,
+    /// a rejected password is returned right away.
+    ///
+    /// # Returns
+    ///
+    /// A connection to the server, which sends commands again on a new connection
+    /// if the options of the builder retry writes. The new connection tries the address
+    /// that worked first, and then the ones after it.
+    ///
+    /// # Errors
+    ///
+    /// If the connection or the authentication fails, it will return an error,
+    /// which is [`MiniRedisError::AddressesNotConnected`] if several addresses failed.
+    fn attempt(&self) -> Result<Connection, MiniRedisError> {
+        let mut failures = Vec::new();
+        for (index, address) in self.addresses.iter().enumerate() {
+            match self.attempt_at(address) {
+                Ok(mut connection) => {
+                    let options = self.options;
+                    connection.reconnect = (options.retry_writes && options.retries > 0)
+                        .then(|| self.starting_at(index));
+                    return Ok(connection);
+                }
+                Err(e) if is_connection_error(&e) => failures.push((address.clone(), e)),
+                Err(e) => return Err(e),
+            }
+        }
+        match failures.len() {
+            1 => Err(failures.remove(0).1),
+            _ => Err(MiniRedisError::AddressesNotConnected { failures }),
+        }
+    }
+
+    /// Connects to one address, and authenticates if a password is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address of the server.
+    ///
+    /// # Returns
+    ///
+    /// A connection to the server.
+    ///
+    /// # Errors
+    ///
+    /// If the connection or the authentication fails, it will return an error.
+    fn attempt_at(&self, address: &str) -> Result<Connection, MiniRedisError> {
+        let mut connection =
+            Connection::open(address, self.connect_timeout, self.timeout, self.protocol)?;
+        if let Some(password) = &self.password {
+            connection.auth(self.user.as_deref(), password)?;
+        }
+        connection.redirect = Some(self.clone());
+        Ok(connection)
+    }
+
+    /// Copies the builder, with its addresses starting at one of them and wrapping around.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the address to try first.
+    ///
+    /// # Returns
+    ///
+    /// The copy of the builder.
+    fn starting_at(&self, index: usize) -> Self {
+        let mut builder = self.clone();
+        builder.addresses.rotate_left(index);
+        builder
+    }
+}
+
+/// An iteration over the keys of a server, as returned by [`Connection::scan_iter_with`].
+struct ScanIter<'a> {
+    connection: &'a mut Connection,
+    options: ScanOptions,
+    cursor: Option<u64>,
+    batch: std::vec::IntoIter<String>,
+}
+
+impl ScanIter<'_> {
+    /// Fetches the next batch of keys with `SCAN`.
+    ///
+    /// # Returns
+    ///
+    /// The cursor to continue with, 0 if the scan is done, and the batch.
+    ///
+    /// # Errors
+    ///
+    /// If `SCAN` cannot be sent or answered, or the reply is not a cursor and keys,
+    /// it will return an error.
+    fn fetch(&mut self, cursor: u64) -> Result<(u64, Vec<String>), MiniRedisError> {
+        self.connection.scan(cursor, &self.options)
+    }
+}
+
+impl Iterator for ScanIter<'_> {
+    type Item = Result<String, MiniRedisError>;
+
+    /// Gets the next key, fetching batches until one has a key or the scan is done.
+    ///
+    /// # Returns
+    ///
+    /// The next key, an error that ends the iteration, or None once the scan is done.
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(key) = self.batch.next() {
+                return Some(Ok(key));
+            }
+            let cursor = self.cursor.take()?;
+            match self.fetch(cursor) {
+                Ok((next, batch)) => {
+                    self.cursor = (next != 0).then_some(next);
+                    self.batch = batch.into_iter();
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Builds the error for a reply that does not have the shape of a `SCAN` reply.
+///
+/// # Arguments
+///
+/// * `reply` - The reply.
+///
+/// # Returns
+///
+/// A [`MiniRedisError::ServerError`] describing the reply.
+fn unexpected_reply(reply: &Response) -> MiniRedisError {
+    MiniRedisError::ServerError {
+        message: format!("Unexpected reply to SCAN: {:?}", reply),
+    }
+}
+
+/// Checks if an error means the connection failed, rather than the server rejecting a command.
+///
+/// # Arguments
+///
+/// * `error` - The error to check.
+///
+/// # Returns
+///
+/// True if connecting again could help, false otherwise.
+pub(crate) fn is_connection_error(error: &MiniRedisError) -> bool {
+    matches!(
+        error,
+        MiniRedisError::StreamNotConnected { .. }
+            | MiniRedisError::Timeout { .. }
+            | MiniRedisError::StreamClosed
+            | MiniRedisError::StreamNotReadable { .. }
+            | MiniRedisError::StreamNotWritable { .. }
+            | MiniRedisError::AddressesNotConnected { .. }
+    )
+}
+
+/// Checks if the fields of a `HELLO` reply list RESP among the protocols of the server.
+///
+/// # Arguments
+///
+/// * `fields` - The fields of the reply.
+///
+/// # Returns
+///
+/// True if the server speaks RESP, false otherwise.
+fn speaks_resp(fields: &[(String, Response)]) -> bool {
+    fields
+        .iter()
+        .any(|(field, value)| match (field.as_str(), value) {
+            ("protocols", Response::Array(protocols)) => protocols
+                .iter()
+                .any(|protocol| *protocol == Response::Bulk("resp".to_string())),
+            _ => false,
+        })
+}
+
+/// Connects to a server, trying each address it resolves to in turn.
+///
+/// # Arguments
+///
+/// * `address` - The address of the server.
+/// * `timeout` - How long to wait for each address.
+///
+/// # Returns
+///
+/// The connected stream.
+///
+/// # Errors
+///
+/// If no address accepts the connection, it will return an error,
+/// which is [`MiniRedisError::Timeout`] if the last attempt timed out.
+fn connect_within(address: &str, timeout: Duration) -> Result<TcpStream, MiniRedisError> {
+    let addresses = address
+        .to_socket_addrs()
+        .map_err(|e| not_connected(address, e))?;
+
+    let mut error = None;
+    for socket_address in addresses {
+        match TcpStream::connect_timeout(&socket_address, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => {
+                error = Some(stream_error(address, e, timeout, |e| {
+                    not_connected(address, e)
+                }))
+            }
+        }
+    }
+    Err(error.unwrap_or_else(|| {
+        let source = io::Error::new(io::ErrorKind::NotFound, "the address resolves to nothing");
+        not_connected(address, source)
+    }))
+}
+
+/// Builds the error for failing to connect to a server.
+///
+/// # Arguments
+///
+/// * `address` - The address of the server.
+/// * `source` - Why the connection failed.
+///
+/// # Returns
+///
+/// A [`MiniRedisError::StreamNotConnected`] for the address.
+fn not_connected(address: &str, source: io::Error) -> MiniRedisError {
+    MiniRedisError::StreamNotConnected {
+        address: address.to_string(),
+        source,
+    }
+}
+
+/// Turns an IO error on the stream into a MiniRedis error.
+///
+/// # Arguments
+///
+/// * `address` - The address of the server.
+/// * `error` - The IO error.
+/// * `timeout` - The timeout that was in effect.
+/// * `otherwise` - Builds the error to return for anything but a timeout from the IO error.
+///
+/// # Returns
+///
+/// [`MiniRedisError::Timeout`] if the operation timed out, or the other error.
+fn stream_error(
+    address: &str,
+    error: io::Error,
+    timeout: Duration,
+    otherwise: impl FnOnce(io::Error) -> MiniRedisError,
+) -> MiniRedisError {
+    match error.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => MiniRedisError::Timeout {
+            address: address.to_string(),
+            timeout,
+        },
+        _ => otherwise(error),
+    }
+}
+
+/// Writes lines, each with its line ending.
+///
+/// Every line is written at once, so they go out together instead of
+/// later lines waiting for the server to acknowledge earlier ones.
+///
+/// # Arguments
+///
+/// * `writer` - The writer to write the lines to.
+/// * `lines` - The lines, without line endings.
+///
+/// # Returns
+///
+/// A result indicating whether the lines were written.
+///
+/// # Errors
+///
+/// If the lines cannot be written, it will return the IO error.
+fn write_lines<W: Write, S: AsRef<str>>(writer: &mut W, lines: &[S]) -> io::Result<()> {
+    let mut frame = String::new();
+    for line in lines {
+        frame.push_str(line.as_ref());
+        frame.push('\n');
+    }
+    writer.write_all(frame.as_bytes())
+}
+
+/// Reads a line.
+///
+/// # Arguments
+///
+/// * `reader` - The reader to read the line from.
+///
+/// # Returns
+///
+/// The line with its line ending, or an empty string if the reader has ended.
+///
+/// # Errors
+///
+/// If the line cannot be read, it will return the IO error.
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<String> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    Ok(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn backoff_doubles_the_delay_with_jitter() {
+        let options = ConnectOptions {
+            retries: 20,
+            retry_delay: Duration::from_millis(100),
+            retry_writes: false,
+        };
+        let mut rng = Rng::with_seed(7);
+
+        for (failures, delay) in [(0, 100), (1, 200), (3, 800), (20, 100 << 16)] {
+            let delay = Duration::from_millis(delay);
+            for _ in 0..20 {
+                let backoff = options.backoff(failures, &mut rng);
+
+                assert!(delay / 2 <= backoff && backoff <= delay, "{:?}", backoff);
+            }
+        }
+    }
+
+    #[test]
+    fn write_lines_writes_line_with_newline() {
+        let mut output = Vec::new();
+
+        write_lines(&mut output, &["SET key value"]).unwrap();
+
+        assert_eq!("SET key value\n".as_bytes(), output.as_slice());
+    }
+
+    #[test]
+    fn write_lines_handles_empty_line() {
+        let mut output = Vec::new();
+
+        write_lines(&mut output, &[""]).unwrap();
+
+        assert_eq!("\n".as_bytes(), output.as_slice());
+    }
+
+    #[test]
+    fn write_lines_writes_every_line() {
+        let mut output = Vec::new();
+
+        write_lines(&mut output, &["SET a 1", "GET a"]).unwrap();
+
+        assert_eq!("SET a 1\nGET a\n".as_bytes(), output.as_slice());
+    }
+
+    #[test]
+    fn read_line_reads_line_from_reader() {
+        let mut reader = BufReader::new(Cursor::new("OK\n".as_bytes()));
+
+        assert_eq!("OK\n".to_string(), read_line(&mut reader).unwrap());
+    }
+
+    #[test]
+    fn read_line_reads_only_first_of_several_lines() {
+        let mut reader = BufReader::new(Cursor::new("value with spaces\nsecond line\n".as_bytes()));
+
+        assert_eq!(
+            "value with spaces\n".to_string(),
+            read_line(&mut reader).unwrap()
+        );
+    }
+}
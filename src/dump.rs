@@ -0,0 +1,995 @@
+use crate::connection::{Connection, Protocol};
+use crate::error::MiniRedisError;
+use crate::format::json_string;
+use crate::response::Response;
+use crate::storage::ScanOptions;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Bytes, Write};
+use std::iter::Peekable;
+
+/// Writes the keys of a server to a JSON file, like `redis-dump`.
+///
+/// The keys are read with `SCAN` a batch at a time, and every batch is written
+/// before the next one is fetched, so the dataset is never held in memory at once.
+/// The file is an array with an object per key, on a line of its own:
+///
+/// ```json
+/// [
+/// {"key":"user:1","type":"string","value":"{\"name\":\"alice\"}","ttl":null},
+/// {"key":"bits","type":"string","value":"gAE=","encoding":"base64","ttl":59980}
+/// ]
+/// ```
+///
+/// The `ttl` is what `PTTL` read for the key, in milliseconds, or `null` if it never expires.
+/// A value that is not valid UTF-8, like one built with `SETBIT`, cannot be a JSON string,
+/// so it is written in base64 and marked with an `encoding` of `base64`.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use miniredis::dump::Dump;
+///
+/// let args = ["miniredis-dump", "--addr", "127.0.0.1:6379", "--out", "dump.json"]
+///     .map(str::to_string);
+/// let keys = Dump::from_args(&args).unwrap().run().unwrap();
+///
+/// println!("Dumped {} keys", keys);
+/// ```
+pub struct Dump {
+    address: String,
+    out: String,
+}
+
+impl Dump {
+    /// Creates a dump from command line arguments.
+    ///
+    /// * `--addr <ADDRESS>` is the address of the server, `127.0.0.1:6379` by default.
+    /// * `--out <PATH>` is the file to write, `dump.json` by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - The command line arguments.
+    ///
+    /// # Returns
+    ///
+    /// A new dump.
+    ///
+    /// # Errors
+    ///
+    /// If an option is unknown or is missing its value, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::dump::Dump;
+    ///
+    /// let dump = Dump::from_args(&["miniredis-dump".to_string()]).unwrap();
+    /// ```
+    pub fn from_args(args: &[String]) -> Result<Self, MiniRedisError> {
+        let mut dump = Self {
+            address: "127.0.0.1:6379".to_string(),
+            out: "dump.json".to_string(),
+        };
+
+        let mut args = args.iter().skip(1);
+        while let Some(option) = args.next() {
+            match option.as_str() {
+                "--addr" => dump.address = parse_value(option, args.next())?,
+                "--out" => dump.out = parse_value(option, args.next())?,
+                _ => {
+                    return Err(MiniRedisError::InvalidArguments {
+                        arguments: vec![option.clone()],
+                    });
+                }
+            }
+        }
+        Ok(dump)
+    }
+
+    /// Connects to the server and writes every key of it to the file.
+    ///
+    /// Keys that are deleted while the dump runs are left out,
+    /// and keys that are written meanwhile may or may not be in it.
+    ///
+    /// # Returns
+    ///
+    /// The number of keys written.
+    ///
+    /// # Errors
+    ///
+    /// If the server cannot be reached, a command fails,
+    /// or the file cannot be written, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::dump::Dump;
+    ///
+    /// let dump = Dump::from_args(&["miniredis-dump".to_string()]).unwrap();
+    /// dump.run().unwrap();
+    /// ```
+    pub fn run(&self) -> Result<usize, MiniRedisError> {
+        // Only RESP carries values that are not valid UTF-8 as they are.
+        let mut connection = Connection::builder(&self.address)
+            .protocol(Protocol::Resp)
+            .connect()?;
+        let not_writable = |source| MiniRedisError::FileNotWritable {
+            path: self.out.clone(),
+            source,
+        };
+        let file = File::create(&self.out).map_err(not_writable)?;
+        let mut out = BufWriter::new(file);
+
+        let keys = write_entries(&mut connection, &mut out).map_err(|e| match e {
+            MiniRedisError::Io { source, .. } => not_writable(source),
+            e => e,
+        })?;
+        out.flush().map_err(not_writable)?;
+        Ok(keys)
+    }
+
+    /// Prints the help message of the dump binary.
+    pub fn print_help() {
+        println!("MiniRedis Dump");
+        println!();
+        println!("Writes every key of a MiniRedis server, with its value, to a JSON file.");
+        println!();
+        println!("USAGE:");
+        println!("    miniredis-dump [OPTIONS]");
+        println!();
+        println!("OPTIONS:");
+        println!("    --addr <ADDRESS>    The address of the server [default: 127.0.0.1:6379]");
+        println!("    --out <PATH>        The file to write [default: dump.json]");
+        println!();
+        println!("EXAMPLES:");
+        println!("    miniredis-dump --addr 127.0.0.1:6379 --out dump.json");
+    }
+}
+
+/// Sets the keys of a JSON file written by [`Dump`] on a server.
+///
+/// The file is read one key at a time, and every key is set before the next one is read,
+/// so a file larger than memory can be restored. Keys with a `ttl` in milliseconds
+/// are set with `PX`, so they never exist without it. A value in base64 is written
+/// a part at a time: `SETRANGE` for runs of valid UTF-8, and `SETBIT` for the bits
+/// of the bytes between them, since commands are text.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use miniredis::dump::Restore;
+///
+/// let args = ["miniredis-restore", "--in", "dump.json", "--replace"].map(str::to_string);
+/// let report = Restore::from_args(&args).unwrap().run().unwrap();
+///
+/// println!("Restored {} keys, skipped {}", report.restored, report.skipped);
+/// ```
+pub struct Restore {
+    address: String,
+    input: String,
+    replace: bool,
+    dry_run: bool,
+}
+
+/// What a [`Restore`] did, or would have done on a dry run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RestoreReport {
+    /// The number of keys that were set.
+    pub restored: usize,
+    /// The number of keys that were left alone, because they exist and `--replace` was not given.
+    pub skipped: usize,
+}
+
+impl Restore {
+    /// Creates a restore from command line arguments.
+    ///
+    /// * `--addr <ADDRESS>` is the address of the server, `127.0.0.1:6379` by default.
+    /// * `--in <PATH>` is the file to read, which must be given.
+    /// * `--replace` overwrites keys that exist, instead of skipping them.
+    /// * `--dry-run` reads the file and checks which keys exist, but sets none.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - The command line arguments.
+    ///
+    /// # Returns
+    ///
+    /// A new restore.
+    ///
+    /// # Errors
+    ///
+    /// If an option is unknown or is missing its value, or no file is given,
+    /// it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::dump::Restore;
+    ///
+    /// let args = ["miniredis-restore", "--in", "dump.json", "--dry-run"].map(str::to_string);
+    /// let restore = Restore::from_args(&args).unwrap();
+    /// ```
+    pub fn from_args(args: &[String]) -> Result<Self, MiniRedisError> {
+        let mut address = "127.0.0.1:6379".to_string();
+        let mut input = None;
+        let mut replace = false;
+        let mut dry_run = false;
+
+        let mut args = args.iter().skip(1);
+        while let Some(option) = args.next() {
+            match option.as_str() {
+                "--addr" => address = parse_value(option, args.next())?,
+                "--in" => input = Some(parse_value(option, args.next())?),
+                "--replace" => replace = true,
+                "--dry-run" => dry_run = true,
+                _ => {
+                    return Err(MiniRedisError::InvalidArguments {
+                        arguments: vec![option.clone()],
+                    });
+                }
+            }
+        }
+        let input = input.ok_or_else(|| MiniRedisError::InvalidArguments {
+            arguments: vec!["--in".to_string()],
+        })?;
+        Ok(Self {
+            address,
+            input,
+            replace,
+            dry_run,
+        })
+    }
+
+    /// Connects to the server and sets the keys of the file on it.
+    ///
+    /// Keys before an invalid entry of the file are set, since the file is streamed.
+    ///
+    /// # Returns
+    ///
+    /// How many keys were set and skipped.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be read or is not a valid dump, the server cannot be reached,
+    /// or a command fails, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::dump::Restore;
+    ///
+    /// let args = ["miniredis-restore", "--in", "dump.json"].map(str::to_string);
+    /// Restore::from_args(&args).unwrap().run().unwrap();
+    /// ```
+    pub fn run(&self) -> Result<RestoreReport, MiniRedisError> {
+        let file = File::open(&self.input).map_err(|source| MiniRedisError::FileNotReadable {
+            path: self.input.clone(),
+            source,
+        })?;
+        let mut connection = Connection::connect(&self.address)?;
+        let mut entries = Entries::new(BufReader::new(file), &self.input)?;
+
+        let mut report = RestoreReport::default();
+        while let Some(entry) = entries.next_entry()? {
+            if !self.replace && connection.command(&["EXISTS", &entry.key])? == Response::Integer(1)
+            {
+                report.skipped += 1;
+                continue;
+            }
+            if !self.dry_run {
+                set_entry(&mut connection, &entry)?;
+            }
+            report.restored += 1;
+        }
+        Ok(report)
+    }
+
+    /// Prints the help message of the restore binary.
+    pub fn print_help() {
+        println!("MiniRedis Restore");
+        println!();
+        println!("Sets the keys of a JSON file written by miniredis-dump on a MiniRedis server.");
+        println!();
+        println!("USAGE:");
+        println!("    miniredis-restore --in <PATH> [OPTIONS]");
+        println!();
+        println!("OPTIONS:");
+        println!("    --addr <ADDRESS>    The address of the server [default: 127.0.0.1:6379]");
+        println!("    --in <PATH>         The file to read");
+        println!("    --replace           Overwrite keys that exist, instead of skipping them");
+        println!("    --dry-run           Report what would be restored, without setting any key");
+        println!();
+        println!("EXAMPLES:");
+        println!("    miniredis-restore --in dump.json");
+        println!("    miniredis-restore --addr 127.0.0.1:7000 --in dump.json --replace --dry-run");
+    }
+}
+
+/// Writes every key of the server as a JSON array, one batch of `SCAN` at a time.
+///
+/// # Arguments
+///
+/// * `connection` - The connection to the server.
+/// * `out` - Where to write the array.
+///
+/// # Returns
+///
+/// The number of keys written.
+///
+/// # Errors
+///
+/// If a command fails, or the output cannot be written, it will return an error.
+fn write_entries(
+    connection: &mut Connection,
+    out: &mut impl Write,
+) -> Result<usize, MiniRedisError> {
+    let mut written = 0;
+    let mut cursor = 0;
+    out.write_all(b"[")?;
+    loop {
+        let (next, keys) = connection.scan(cursor, &ScanOptions::default())?;
+        for key in keys {
+            // The key was deleted since the batch was fetched.
+            let Some(value) = connection.get_bytes(&key)? else {
+                continue;
+            };
+            let ttl = match connection.command(&["PTTL", &key])? {
+                Response::Integer(-1) => "null".to_string(),
+                Response::Integer(ttl) if ttl > 0 => ttl.to_string(),
+                // The key expired or was deleted since its value was read.
+                _ => continue,
+            };
+            let value = match String::from_utf8(value) {
+                Ok(value) => json_string(&value),
+                Err(error) => format!(
+                    "{},\"encoding\":\"base64\"",
+                    json_string(&base64_encode(error.as_bytes()))
+                ),
+            };
+            let separator = if written == 0 { "\n" } else { ",\n" };
+            write!(
+                out,
+                "{}{{\"key\":{},\"type\":\"string\",\"value\":{},\"ttl\":{}}}",
+                separator,
+                json_string(&key),
+                value,
+                ttl
+            )?;
+            written += 1;
+        }
+        if next == 0 {
+            break;
+        }
+        cursor = next;
+    }
+    out.write_all(b"\n]\n")?;
+    Ok(written)
+}
+
+/// Sets a key of a dump, with its time to live.
+///
+/// # Arguments
+///
+/// * `connection` - The connection to the server.
+/// * `entry` - The key to set.
+///
+/// # Errors
+///
+/// If a command fails, it will return an error.
+fn set_entry(connection: &mut Connection, entry: &Entry) -> Result<(), MiniRedisError> {
+    for command in set_commands(&entry.key, &entry.value, entry.ttl) {
+        let parts: Vec<&str> = command.iter().map(String::as_str).collect();
+        connection.command(&parts)?;
+    }
+    Ok(())
+}
+
+/// Builds the commands that set a key to a value, with its time to live.
+///
+/// A value that is valid UTF-8 takes one `SET`. Any other value is set to its valid runs
+/// with `SETRANGE`, and to the bits of the bytes between them with `SETBIT`,
+/// since a command can only carry text.
+///
+/// # Arguments
+///
+/// * `key` - The key to set.
+/// * `value` - The value of the key, which does not have to be UTF-8.
+/// * `ttl` - The time to live of the key in milliseconds, or None if it never expires.
+///
+/// # Returns
+///
+/// The commands, in the order they must be executed.
+pub(crate) fn set_commands(key: &str, value: &[u8], ttl: Option<u64>) -> Vec<Vec<String>> {
+    let text = std::str::from_utf8(value);
+    let mut set = vec![
+        "SET".to_string(),
+        key.to_string(),
+        text.unwrap_or("").to_string(),
+    ];
+    // A ttl of 0 ran out as the dump was written, and PX takes no less than 1.
+    if let Some(ttl) = ttl {
+        set.extend(["PX".to_string(), ttl.max(1).to_string()]);
+    }
+    let mut commands = vec![set];
+    if text.is_ok() {
+        return commands;
+    }
+
+    // Bytes that are not valid UTF-8 always have a bit set, so the last one sets the length.
+    let mut offset = 0;
+    for chunk in value.utf8_chunks() {
+        if !chunk.valid().is_empty() {
+            commands.push(vec![
+                "SETRANGE".to_string(),
+                key.to_string(),
+                offset.to_string(),
+                chunk.valid().to_string(),
+            ]);
+        }
+        offset += chunk.valid().len();
+        for byte in chunk.invalid() {
+            for bit in (0..8).filter(|bit| byte & (0x80 >> bit) != 0) {
+                commands.push(vec![
+                    "SETBIT".to_string(),
+                    key.to_string(),
+                    (offset * 8 + bit).to_string(),
+                    "1".to_string(),
+                ]);
+            }
+            offset += 1;
+        }
+    }
+    commands
+}
+
+/// The alphabet of base64, from the value of a digit to its character.
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes bytes in base64, with padding.
+///
+/// # Arguments
+///
+/// * `bytes` - The bytes to encode.
+///
+/// # Returns
+///
+/// The base64 text of the bytes.
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for group in bytes.chunks(3) {
+        let bits = group.iter().enumerate().fold(0u32, |bits, (i, byte)| {
+            bits | (*byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            text.push(if i <= group.len() {
+                BASE64[(bits >> (18 - 6 * i) & 0x3f) as usize] as char
+            } else {
+                '='
+            });
+        }
+    }
+    text
+}
+
+/// Decodes base64 text, with padding.
+///
+/// # Arguments
+///
+/// * `text` - The base64 text.
+///
+/// # Returns
+///
+/// The bytes, or None if the text is not valid base64.
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    for (n, group) in text.as_bytes().chunks(4).enumerate() {
+        let padding = group.iter().rev().take_while(|c| **c == b'=').count();
+        if padding > 2 || (padding > 0 && (n + 1) * 4 != text.len()) {
+            return None;
+        }
+        let mut bits = 0u32;
+        for c in &group[..4 - padding] {
+            let digit = BASE64.iter().position(|digit| digit == c)?;
+            bits = bits << 6 | digit as u32;
+        }
+        bits <<= 6 * padding;
+        bytes.extend_from_slice(&bits.to_be_bytes()[1..4 - padding]);
+    }
+    Some(bytes)
+}
+
+/// A key of a dump, as read back from the file.
+#[derive(Debug, PartialEq)]
+struct Entry {
+    key: String,
+    value: Vec<u8>,
+    ttl: Option<u64>,
+}
+
+/// A value of a JSON document.
+#[derive(Debug, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+/// Reads the entries of a dump one at a time, without reading the rest of the file.
+struct Entries<'a, R: BufRead> {
+    bytes: Peekable<Bytes<R>>,
+    path: &'a str,
+    line: usize,
+    done: bool,
+}
+
+impl<'a, R: BufRead> Entries<'a, R> {
+    /// Starts reading a dump, up to the bracket that opens its array.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The contents of the dump.
+    /// * `path` - The path of the dump, for errors.
+    ///
+    /// # Errors
+    ///
+    /// If the dump does not start with an array, it will return an error.
+    fn new(reader: R, path: &'a str) -> Result<Self, MiniRedisError> {
+        let mut entries = Self {
+            bytes: reader.bytes().peekable(),
+            path,
+            line: 1,
+            done: false,
+        };
+        entries.expect(b'[')?;
+        entries.done = entries.next_if(b']')?;
+        Ok(entries)
+    }
+
+    /// Reads the next entry.
+    ///
+    /// # Returns
+    ///
+    /// The entry, or None after the last one.
+    ///
+    /// # Errors
+    ///
+    /// If the entry is not an object with a string key and value,
+    /// or the file cannot be read, it will return an error.
+    fn next_entry(&mut self) -> Result<Option<Entry>, MiniRedisError> {
+        if self.done {
+            return Ok(None);
+        }
+        let Json::Object(fields) = self.value()? else {
+            return Err(self.invalid("every element must be an object"));
+        };
+        self.done = match self.next_byte()? {
+            Some(b',') => false,
+            Some(b']') => true,
+            _ => return Err(self.invalid("expected ',' or ']' after an element")),
+        };
+
+        let (mut key, mut value, mut ttl, mut base64) = (None, None, None, false);
+        for (name, field) in fields {
+            match (name.as_str(), field) {
+                ("key", Json::String(field)) => key = Some(field),
+                ("value", Json::String(field)) => value = Some(field),
+                ("encoding", Json::String(encoding)) if encoding == "base64" => base64 = true,
+                ("encoding", _) => return Err(self.invalid("the only encoding is base64")),
+                ("type", Json::String(kind)) if kind == "string" => {}
+                ("type", _) => return Err(self.invalid("only string keys can be restored")),
+                ("ttl", Json::Null) => {}
+                ("ttl", Json::Number(millis)) => {
+                    ttl =
+                        Some(millis.parse::<u64>().map_err(|_| {
+                            self.invalid("ttl must be a whole number of milliseconds")
+                        })?)
+                }
+                ("key" | "value" | "ttl", _) => {
+                    return Err(self.invalid(&format!("{} has the wrong type", name)));
+                }
+                _ => {}
+            }
+        }
+        let (Some(key), Some(value)) = (key, value) else {
+            return Err(self.invalid("every element needs a key and a value"));
+        };
+        let value = if base64 {
+            base64_decode(&value).ok_or_else(|| self.invalid("value is not valid base64"))?
+        } else {
+            value.into_bytes()
+        };
+        Ok(Some(Entry { key, value, ttl }))
+    }
+
+    /// Reads a JSON value, after any whitespace.
+    fn value(&mut self) -> Result<Json, MiniRedisError> {
+        match self.next_byte()? {
+            Some(b'{') => {
+                let mut fields = Vec::new();
+                if self.next_if(b'}')? {
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    self.expect(b'"')?;
+                    let name = self.string()?;
+                    self.expect(b':')?;
+                    fields.push((name, self.value()?));
+                    match self.next_byte()? {
+                        Some(b',') => {}
+                        Some(b'}') => return Ok(Json::Object(fields)),
+                        _ => return Err(self.invalid("expected ',' or '}' in an object")),
+                    }
+                }
+            }
+            Some(b'[') => {
+                let mut items = Vec::new();
+                if self.next_if(b']')? {
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    match self.next_byte()? {
+                        Some(b',') => {}
+                        Some(b']') => return Ok(Json::Array(items)),
+                        _ => return Err(self.invalid("expected ',' or ']' in an array")),
+                    }
+                }
+            }
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'n') => self.word(b"ull", Json::Null),
+            Some(b't') => self.word(b"rue", Json::Bool(true)),
+            Some(b'f') => self.word(b"alse", Json::Bool(false)),
+            Some(byte @ (b'-' | b'0'..=b'9')) => {
+                let mut number = String::from(byte as char);
+                while let Some(byte) = self.peek()? {
+                    if !matches!(byte, b'0'..=b'9' | b'.' | b'e' | b'E' | b'+' | b'-') {
+                        break;
+                    }
+                    number.push(byte as char);
+                    self.bytes.next();
+                }
+                Ok(Json::Number(number))
+            }
+            Some(_) => Err(self.invalid("expected a value")),
+            None => Err(self.invalid("the file ends inside the array")),
+        }
+    }
+
+    /// Reads the rest of a string, after its opening quote.
+    fn string(&mut self) -> Result<String, MiniRedisError> {
+        let mut bytes = Vec::new();
+        loop {
+            match self.raw_byte()? {
+                Some(b'"') => break,
+                Some(b'\\') => match self.raw_byte()? {
+                    Some(b'"') => bytes.push(b'"'),
+                    Some(b'\\') => bytes.push(b'\\'),
+                    Some(b'/') => bytes.push(b'/'),
+                    Some(b'b') => bytes.push(0x08),
+                    Some(b'f') => bytes.push(0x0C),
+                    Some(b'n') => bytes.push(b'\n'),
+                    Some(b'r') => bytes.push(b'\r'),
+                    Some(b't') => bytes.push(b'\t'),
+                    Some(b'u') => {
+                        let c = self.escaped_char()?;
+                        bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                    }
+                    _ => return Err(self.invalid("unknown escape in a string")),
+                },
+                Some(b'\n') => return Err(self.invalid("line ending inside a string")),
+                Some(byte) => bytes.push(byte),
+                None => return Err(self.invalid("the file ends inside a string")),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| self.invalid("string is not valid UTF-8"))
+    }
+
+    /// Reads the character of a `\u` escape, after the `u`, joining a surrogate pair.
+    fn escaped_char(&mut self) -> Result<char, MiniRedisError> {
+        let high = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if self.raw_byte()? != Some(b'\\') || self.raw_byte()? != Some(b'u') {
+                return Err(self.invalid("unpaired surrogate in a string"));
+            }
+            let low = self.hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(self.invalid("unpaired surrogate in a string"));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.invalid("unpaired surrogate in a string"))
+    }
+
+    /// Reads the 4 hex digits of a `\u` escape.
+    fn hex4(&mut self) -> Result<u32, MiniRedisError> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .raw_byte()?
+                .and_then(|byte| (byte as char).to_digit(16))
+                .ok_or_else(|| self.invalid("\\u must be followed by 4 hex digits"))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    /// Reads the rest of `null`, `true` or `false`, after its first letter.
+    fn word(&mut self, rest: &[u8], value: Json) -> Result<Json, MiniRedisError> {
+        for expected in rest {
+            if self.raw_byte()? != Some(*expected) {
+                return Err(self.invalid("expected a value"));
+            }
+        }
+        Ok(value)
+    }
+
+    /// Reads the next byte after any whitespace, and fails if it is not the expected one.
+    fn expect(&mut self, expected: u8) -> Result<(), MiniRedisError> {
+        match self.next_byte()? {
+            Some(byte) if byte == expected => Ok(()),
+            _ => Err(self.invalid(&format!("expected '{}'", expected as char))),
+        }
+    }
+
+    /// Reads the next byte after any whitespace if it is the expected one.
+    fn next_if(&mut self, expected: u8) -> Result<bool, MiniRedisError> {
+        self.skip_whitespace()?;
+        if self.peek()? == Some(expected) {
+            self.bytes.next();
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Reads the next byte after any whitespace.
+    fn next_byte(&mut self) -> Result<Option<u8>, MiniRedisError> {
+        self.skip_whitespace()?;
+        self.raw_byte()
+    }
+
+    /// Skips whitespace, counting lines.
+    fn skip_whitespace(&mut self) -> Result<(), MiniRedisError> {
+        while let Some(b' ' | b'\t' | b'\r' | b'\n') = self.peek()? {
+            self.raw_byte()?;
+        }
+        Ok(())
+    }
+
+    /// Reads the next byte as it is, counting lines.
+    fn raw_byte(&mut self) -> Result<Option<u8>, MiniRedisError> {
+        let byte = self
+            .bytes
+            .next()
+            .transpose()
+            .map_err(|source| self.not_readable(source))?;
+        if byte == Some(b'\n') {
+            self.line += 1;
+        }
+        Ok(byte)
+    }
+
+    /// Looks at the next byte without reading it.
+    fn peek(&mut self) -> Result<Option<u8>, MiniRedisError> {
+        match self.bytes.peek() {
+            Some(Ok(byte)) => Ok(Some(*byte)),
+            Some(Err(_)) => {
+                let Some(Err(source)) = self.bytes.next() else {
+                    unreachable!()
+                };
+                Err(self.not_readable(source))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Builds the error for a dump that cannot be read.
+    fn not_readable(&self, source: std::io::Error) -> MiniRedisError {
+        MiniRedisError::FileNotReadable {
+            path: self.path.to_string(),
+            source,
+        }
+    }
+
+    /// Builds the error for a dump that is not valid, at the current line.
+    fn invalid(&self, reason: &str) -> MiniRedisError {
+        MiniRedisError::DumpInvalid {
+            path: self.path.to_string(),
+            reason: format!("line {}: {}", self.line, reason),
+        }
+    }
+}
+
+/// Parses the value of an option.
+///
+/// # Arguments
+///
+/// * `option` - The name of the option, for the error.
+/// * `value` - The value following the option, if any.
+///
+/// # Returns
+///
+/// The value.
+///
+/// # Errors
+///
+/// If the value is missing, it will return an error.
+fn parse_value(option: &str, value: Option<&String>) -> Result<String, MiniRedisError> {
+    value
+        .cloned()
+        .ok_or_else(|| MiniRedisError::InvalidArguments {
+            arguments: vec![option.to_string()],
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads every entry of a dump.
+    fn entries(dump: &str) -> Result<Vec<Entry>, MiniRedisError> {
+        let mut entries = Entries::new(dump.as_bytes(), "dump.json")?;
+        let mut all = Vec::new();
+        while let Some(entry) = entries.next_entry()? {
+            all.push(entry);
+        }
+        Ok(all)
+    }
+
+    fn entry(key: &str, value: &str, ttl: Option<u64>) -> Entry {
+        Entry {
+            key: key.to_string(),
+            value: value.as_bytes().to_vec(),
+            ttl,
+        }
+    }
+
+    fn invalid(reason: &str) -> MiniRedisError {
+        MiniRedisError::DumpInvalid {
+            path: "dump.json".to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    #[test]
+    fn entries_reads_keys_values_and_ttls() {
+        let dump = "[\n{\"key\":\"a\",\"type\":\"string\",\"value\":\"1\",\"ttl\":null},\n\
+                    {\"value\": \"2\", \"key\": \"b\", \"ttl\": 1500, \"extra\": [true, {}]}\n]\n";
+
+        assert_eq!(
+            Ok(vec![entry("a", "1", None), entry("b", "2", Some(1500))]),
+            entries(dump)
+        );
+        assert_eq!(Ok(vec![]), entries(" [ ] "));
+    }
+
+    #[test]
+    fn entries_unescapes_strings() {
+        let dump = r#"[{"key":"q\"\\\/\n\t","value":"café 😀"}]"#;
+
+        assert_eq!(
+            Ok(vec![entry("q\"\\/\n\t", "café 😀", None)]),
+            entries(dump)
+        );
+    }
+
+    #[test]
+    fn entries_reads_what_json_string_writes() {
+        let value = "line\r\nbreak \"quoted\" \\ \u{1} zoë";
+        let dump = format!(
+            "[{{\"key\":{},\"value\":{}}}]",
+            json_string("k"),
+            json_string(value)
+        );
+
+        assert_eq!(Ok(vec![entry("k", value, None)]), entries(&dump));
+    }
+
+    #[test]
+    fn entries_decodes_values_marked_as_base64() {
+        let dump = r#"[{"key":"bits","value":"gAH/","encoding":"base64","ttl":10}]"#;
+
+        assert_eq!(
+            Ok(vec![Entry {
+                key: "bits".to_string(),
+                value: vec![0x80, 0x01, 0xff],
+                ttl: Some(10),
+            }]),
+            entries(dump)
+        );
+    }
+
+    #[test]
+    fn base64_decode_reads_what_base64_encode_writes() {
+        for bytes in [
+            &b""[..],
+            b"f",
+            b"fo",
+            b"foo",
+            b"foob",
+            &[0x80, 0, 0xff, 0xfe],
+        ] {
+            let text = base64_encode(bytes);
+            assert!(text.len().is_multiple_of(4), "{}", text);
+            assert_eq!(Some(bytes.to_vec()), base64_decode(&text), "{}", text);
+        }
+        assert_eq!("Zm9vYg==", base64_encode(b"foob"));
+        for text in ["Zm9", "Zm9v!A==", "Zg==Zg==", "Z==="] {
+            assert_eq!(None, base64_decode(text), "{}", text);
+        }
+    }
+
+    #[test]
+    fn entries_rejects_invalid_dumps_with_their_line() {
+        assert_eq!(Err(invalid("line 1: expected '['")), entries("{}"));
+        assert_eq!(
+            Err(invalid("line 2: every element needs a key and a value")),
+            entries("[\n{\"key\":\"a\"}]")
+        );
+        assert_eq!(
+            Err(invalid("line 1: the file ends inside the array")),
+            entries("[{\"key\":\"a\",\"value\":\"1\"},")
+        );
+        assert_eq!(
+            Err(invalid("line 1: only string keys can be restored")),
+            entries("[{\"key\":\"a\",\"value\":\"1\",\"type\":\"list\"}]")
+        );
+        assert_eq!(
+            Err(invalid(
+                "line 1: ttl must be a whole number of milliseconds"
+            )),
+            entries("[{\"key\":\"a\",\"value\":\"1\",\"ttl\":-5}]")
+        );
+        assert_eq!(
+            Err(invalid("line 1: value is not valid base64")),
+            entries(r#"[{"key":"a","value":"%%","encoding":"base64"}]"#)
+        );
+        assert_eq!(
+            Err(invalid("line 1: the only encoding is base64")),
+            entries(r#"[{"key":"a","value":"1","encoding":"hex"}]"#)
+        );
+        assert_eq!(
+            Err(invalid("line 1: unpaired surrogate in a string")),
+            entries(r#"[{"key":"\ud83d","value":"1"}]"#)
+        );
+    }
+
+    #[test]
+    fn from_args_reads_every_option() {
+        let args = [
+            "miniredis-restore",
+            "--addr",
+            "localhost:7000",
+            "--in",
+            "in.json",
+            "--replace",
+            "--dry-run",
+        ]
+        .map(str::to_string);
+
+        let restore = Restore::from_args(&args).unwrap();
+
+        assert_eq!("localhost:7000", restore.address);
+        assert_eq!("in.json", restore.input);
+        assert!(restore.replace && restore.dry_run);
+    }
+
+    #[test]
+    fn from_args_requires_a_file_to_restore() {
+        let result = Restore::from_args(&["miniredis-restore".to_string()]);
+
+        assert_eq!(
+            Err(MiniRedisError::InvalidArguments {
+                arguments: vec!["--in".to_string()]
+            }),
+            result.map(|_| ())
+        );
+    }
+}
@@ -0,0 +1,3369 @@
+use crate::cluster;
+use crate::command::{self, COMMANDS, Command, CommandInfo};
+use crate::error::MiniRedisError;
+use crate::kv_store::KVStore;
+use crate::lcs;
+use crate::pause::{Pause, PauseMode};
+use crate::replication::Replication;
+use crate::response::Response;
+use crate::sort::{self, SortOptions};
+use crate::stats::{ServerCounters, ServerStats};
+use crate::storage::{
+    self, ExpireCondition, KeyInfo, ScanOptions, SetCondition, SetExpiry, SetOptions, Storage,
+};
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, SystemTime};
+
+/// Executes commands against a storage backend.
+///
+/// The engine is the command layer of MiniRedis, independent of any network code.
+/// The server hands every parsed command to an engine, and embedded users can do
+/// the same without opening a socket, getting the replies the server would send,
+/// errors and all. Cloning an engine is cheap and returns
+/// another engine executing against the same storage.
+///
+/// # Examples
+///
+/// ```rust
+/// use miniredis::engine::Engine;
+/// use miniredis::kv_store::KVStore;
+/// use miniredis::response::Response;
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// let store = Arc::new(KVStore::new());
+/// let engine = Engine::with_store(Arc::clone(&store));
+///
+/// engine.execute_str("SET name alice");
+/// store
+///     .extend_with_ttl([("session".to_string(), "token".to_string(), Some(Duration::ZERO))])
+///     .unwrap();
+///
+/// assert_eq!(Response::Bulk("alice".to_string()), engine.execute_str("GET name"));
+/// assert_eq!(Response::Nil, engine.execute_str("GET session"));
+/// ```
+#[derive(Clone)]
+pub struct Engine {
+    storage: Arc<dyn Storage>,
+    lcs_limit: usize,
+    pause: Arc<Pause>,
+    stats: Arc<ServerCounters>,
+    /// Where `UNLINK` sends the values it deletes, to be dropped by another thread.
+    lazy_free: Option<Sender<Vec<u8>>>,
+    /// The replicas the writes are sent to, which `WAIT` waits for.
+    replication: Arc<Replication>,
+}
+
+impl Engine {
+    /// Creates an engine with a store of its own, kept in memory.
+    ///
+    /// # Returns
+    ///
+    /// A new engine with an empty store.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::engine::Engine;
+    ///
+    /// let engine = Engine::new();
+    ///
+    /// assert_eq!(Ok(0), engine.storage().len());
+    /// ```
+    pub fn new() -> Self {
+        Self::with_store(Arc::new(KVStore::new()))
+    }
+
+    /// Creates an engine that executes commands against an existing store.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The store to execute commands against.
+    ///
+    /// # Returns
+    ///
+    /// A new engine.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::engine::Engine;
+    /// use miniredis::kv_store::KVStore;
+    /// use std::sync::Arc;
+    ///
+    /// let engine = Engine::with_store(Arc::new(KVStore::new()));
+    /// ```
+    pub fn with_store(store: Arc<KVStore>) -> Self {
+        Self::with_storage(store)
+    }
+
+    /// Creates an engine that executes commands against any storage backend.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - The backend to execute commands against.
+    ///
+    /// # Returns
+    ///
+    /// A new engine.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::engine::Engine;
+    /// use miniredis::kv_store::KVStore;
+    /// use miniredis::storage::LoggingStorage;
+    /// use std::sync::Arc;
+    ///
+    /// let engine = Engine::with_storage(Arc::new(LoggingStorage::new(KVStore::new())));
+    /// ```
+    pub fn with_storage(storage: Arc<dyn Storage>) -> Self {
+        Self {
+            storage,
+            lcs_limit: lcs::DEFAULT_LIMIT,
+            pause: Arc::new(Pause::default()),
+            stats: Arc::new(ServerCounters::default()),
+            lazy_free: None,
+            replication: Arc::new(Replication::default()),
+        }
+    }
+
+    /// Sets how large a comparison `LCS` takes on, in cells of its table,
+    /// the length of one value times the length of the other.
+    ///
+    /// The time and memory a comparison takes grow with both lengths,
+    /// so a larger one is refused with [`MiniRedisError::ComparisonTooLarge`].
+    ///
+    /// # Arguments
+    ///
+    /// * `cells` - The most cells, 16777216 by default.
+    ///
+    /// # Returns
+    ///
+    /// The engine.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::engine::Engine;
+    /// use miniredis::response::Response;
+    ///
+    /// let engine = Engine::new().lcs_limit(100);
+    /// engine.execute_str("SET a ohmytext");
+    /// engine.execute_str("SET b mynewtext");
+    ///
+    /// assert_eq!(Response::Bulk("mytext".to_string()), engine.execute_str("LCS a b"));
+    /// engine.execute_str("SET b mynewtextmynewtext");
+    /// assert!(matches!(engine.execute_str("LCS a b"), Response::Error(_)));
+    /// ```
+    pub fn lcs_limit(mut self, cells: usize) -> Self {
+        self.lcs_limit = cells;
+        self
+    }
+
+    /// Sets where `UNLINK` sends the values it deletes, for a thread that drops them.
+    ///
+    /// Without one, `UNLINK` drops the values itself, like `DEL`.
+    ///
+    /// # Arguments
+    ///
+    /// * `values` - The sending end of the channel the thread receives from.
+    ///
+    /// # Returns
+    ///
+    /// The engine.
+    pub(crate) fn lazy_free(mut self, values: Sender<Vec<u8>>) -> Self {
+        self.lazy_free = Some(values);
+        self
+    }
+
+    /// Gets the storage backend the engine executes commands against.
+    ///
+    /// # Returns
+    ///
+    /// A reference to the backend.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::command::Command;
+    /// use miniredis::engine::Engine;
+    /// use miniredis::kv_store::KVStore;
+    /// use std::sync::Arc;
+    ///
+    /// let engine = Engine::with_store(Arc::new(KVStore::new()));
+    /// engine.execute(Command::parse("SET name alice").unwrap());
+    ///
+    /// assert_eq!(Ok(1), engine.storage().len());
+    /// ```
+    pub fn storage(&self) -> &dyn Storage {
+        self.storage.as_ref()
+    }
+
+    /// Gets the counters of the commands the engine executed,
+    /// and of the connections the server running it accepted.
+    ///
+    /// The counters are shared by all clones of the engine,
+    /// and count from its creation or the last `CONFIG RESETSTAT`.
+    /// A command is counted when it starts, so `CONFIG RESETSTAT` is not counted itself.
+    ///
+    /// # Returns
+    ///
+    /// A copy of the current counters.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::engine::Engine;
+    ///
+    /// let engine = Engine::new();
+    /// engine.execute_str("SET name alice");
+    /// engine.execute_str("GET name");
+    ///
+    /// assert_eq!(2, engine.stats().commands_processed);
+    /// engine.execute_str("CONFIG RESETSTAT");
+    /// assert_eq!(0, engine.stats().commands_processed);
+    /// ```
+    pub fn stats(&self) -> ServerStats {
+        self.stats.snapshot()
+    }
+
+    /// Counts a connection the server accepted.
+    pub(crate) fn record_connection(&self) {
+        self.stats.record_connection();
+    }
+
+    /// Counts a client the server dropped for leaving too many replies unread.
+    pub(crate) fn record_output_buffer_disconnection(&self) {
+        self.stats.record_output_buffer_disconnection();
+    }
+
+    /// Executes a command.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command to execute.
+    ///
+    /// # Returns
+    ///
+    /// The response to the command.
+    /// If the command is unknown, its arguments are invalid,
+    /// or the storage fails, it is a [`Response::Error`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::command::Command;
+    /// use miniredis::engine::Engine;
+    /// use miniredis::kv_store::KVStore;
+    /// use miniredis::response::Response;
+    /// use std::sync::Arc;
+    ///
+    /// let engine = Engine::with_store(Arc::new(KVStore::new()));
+    ///
+    /// assert_eq!(Response::Nil, engine.execute(Command::parse("GET name").unwrap()));
+    /// ```
+    pub fn execute(&self, command: Command) -> Response {
+        self.stats.record_command();
+        let write = CommandInfo::find(&command.name).is_some_and(|info| info.write);
+        let result = if write {
+            self.replication.write(command, |command| self.run(command))
+        } else {
+            self.run(command)
+        };
+        match result {
+            Ok(response) => response,
+            Err(e) => Response::Error(e),
+        }
+    }
+
+    /// Waits while `CLIENT PAUSE` holds a command, before the command is executed.
+    ///
+    /// `CLIENT` commands are never held, so a client can always lift a pause.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command about to be executed.
+    pub(crate) fn wait_while_paused(&self, command: &Command) {
+        if command.name == "CLIENT" {
+            return;
+        }
+        let write = CommandInfo::find(&command.name).is_some_and(|info| info.write);
+        self.pause.wait(write);
+    }
+
+    /// Checks whether `CLIENT PAUSE` holds a command right now,
+    /// so [`Engine::wait_while_paused`] would wait before it is executed.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command about to be executed.
+    ///
+    /// # Returns
+    ///
+    /// True if the command would wait, false otherwise.
+    #[cfg(feature = "async")]
+    pub(crate) fn is_paused_for(&self, command: &Command) -> bool {
+        let write = CommandInfo::find(&command.name).is_some_and(|info| info.write);
+        command.name != "CLIENT"
+            && self
+                .pause
+                .state()
+                .is_some_and(|(mode, _)| mode == PauseMode::All || write)
+    }
+
+    /// Lifts a `CLIENT PAUSE`, letting every held command go on.
+    pub(crate) fn unpause(&self) {
+        self.pause.unpause();
+    }
+
+    /// Gets the replicas the writes of the engine are sent to.
+    ///
+    /// # Returns
+    ///
+    /// The replicas, shared by all clones of the engine.
+    pub(crate) fn replication(&self) -> &Replication {
+        &self.replication
+    }
+
+    /// Parses a line the way the server parses inline commands, and executes it.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The command line, like `SET name "alice smith"`.
+    ///
+    /// # Returns
+    ///
+    /// The response to the command.
+    /// If the line holds no command, it is a [`Response::Error`]
+    /// holding [`MiniRedisError::InvalidArguments`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::engine::Engine;
+    /// use miniredis::error::MiniRedisError;
+    /// use miniredis::response::Response;
+    ///
+    /// let engine = Engine::new();
+    ///
+    /// assert_eq!(Response::ok(), engine.execute_str("SET name \"alice smith\""));
+    /// assert_eq!(
+    ///     Response::Error(MiniRedisError::InvalidArguments { arguments: vec![] }),
+    ///     engine.execute_str("   ")
+    /// );
+    /// assert_eq!(
+    ///     Response::Error(MiniRedisError::WrongArity { command: "GET".to_string() }),
+    ///     engine.execute_str("GET")
+    /// );
+    /// ```
+    pub fn execute_str(&self, line: &str) -> Response {
+        match Command::parse(line) {
+            Some(command) => self.execute(command),
+            None => Response::Error(MiniRedisError::InvalidArguments { arguments: vec![] }),
+        }
+    }
+
+    /// Runs a command against the storage.
+    ///
+    /// The command is checked against its [`CommandInfo`] first,
+    /// so every handler can rely on the number and kinds of its arguments.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command to run.
+    ///
+    /// # Returns
+    ///
+    /// The response to the command.
+    ///
+    /// # Errors
+    ///
+    /// If the command is invalid, the arguments are invalid,
+    /// or the storage fails, it will return an error.
+    fn run(&self, command: Command) -> Result<Response, MiniRedisError> {
+        let storage = self.storage();
+        CommandInfo::find(&command.name)
+            .ok_or_else(|| MiniRedisError::InvalidCommand {
+                command: command.name.clone(),
+            })?
+            .check(&command.args)?;
+
+        match (command.name.as_str(), command.args.as_slice()) {
+            ("GET", [key]) => Ok(match storage.get_bytes(key)? {
+                Some(value) => Response::bulk(value),
+                None => Response::Nil,
+            }),
+            ("SET", [key, value]) => {
+                storage.set(key, value)?;
+                Ok(Response::ok())
+            }
+            ("SET", args) => Self::set(storage, args),
+            ("RENAME", [source, destination]) => {
+                storage.rename(source, destination)?;
+                Ok(Response::ok())
+            }
+            ("RENAMENX", [source, destination]) => Ok(Response::Integer(
+                storage.rename_if_absent(source, destination)? as i64,
+            )),
+            ("DEL", keys) => {
+                let mut count = 0;
+                for key in keys {
+                    count += storage.del(key)? as i64;
+                }
+                Ok(Response::Integer(count))
+            }
+            ("UNLINK", keys) => {
+                let count = storage.unlink(keys, &mut |value| {
+                    if let Some(values) = &self.lazy_free {
+                        // If the thread is gone, the value is dropped here instead.
+                        let _ = values.send(value);
+                    }
+                })?;
+                Ok(Response::Integer(count as i64))
+            }
+            ("TOUCH", keys) => Ok(Response::Integer(storage.touch(keys)? as i64)),
+            ("OBJECT", [_encoding, key]) => Ok(Response::Bulk(
+                Self::describe(storage, key)?.encoding.to_string(),
+            )),
+            ("DEBUG", [_object, key]) => {
+                let info = Self::describe(storage, key)?;
+                // Like the reply of Redis, without the parts only its own memory layout has.
+                Ok(Response::Simple(format!(
+                    "encoding:{} serializedlength:{} memory:{} lru_seconds_idle:{} ttl_ms:{}",
+                    info.encoding,
+                    info.serialized_length,
+                    info.memory,
+                    info.idle.as_secs(),
+                    info.ttl.map_or(-1, |ttl| ttl.as_millis() as i64)
+                )))
+            }
+            ("TYPE", [key]) => Ok(Response::Simple(
+                storage.value_type(key)?.unwrap_or("none").to_string(),
+            )),
+            ("EXISTS", keys) => {
+                let mut count = 0;
+                for key in keys {
+                    count += storage.exists(key)? as i64;
+                }
+                Ok(Response::Integer(count))
+            }
+            ("SETNX", [key, value]) => {
+                Ok(Response::Integer(storage.set_if_absent(key, value)? as i64))
+            }
+            ("EXPIRE", [_, seconds, ..]) => Self::expire(
+                storage,
+                &command.args,
+                Self::after("EXPIRE", seconds, Duration::from_secs)?,
+            ),
+            ("PEXPIRE", [_, milliseconds, ..]) => Self::expire(
+                storage,
+                &command.args,
+                Self::after("PEXPIRE", milliseconds, Duration::from_millis)?,
+            ),
+            ("EXPIREAT", [_, seconds, ..]) => Self::expire(
+                storage,
+                &command.args,
+                Self::until("EXPIREAT", seconds, Duration::from_secs)?,
+            ),
+            ("PEXPIREAT", [_, milliseconds, ..]) => Self::expire(
+                storage,
+                &command.args,
+                Self::until("PEXPIREAT", milliseconds, Duration::from_millis)?,
+            ),
+            ("PERSIST", [key]) => Ok(Response::Integer(storage.persist(key)? as i64)),
+            // Rounded to the nearest second, like in Redis.
+            ("TTL", [key]) => Self::ttl(storage, key, |ttl| (ttl.as_millis() + 500) / 1000),
+            ("PTTL", [key]) => Self::ttl(storage, key, |ttl| ttl.as_millis()),
+            ("GETEX", [key]) => Ok(storage
+                .get_bytes(key)?
+                .map_or(Response::Nil, Response::bulk)),
+            ("GETEX", args) => Self::getex(storage, args),
+            ("MSET", args) => {
+                storage.multi_set(&Self::pairs(args)?)?;
+                Ok(Response::ok())
+            }
+            ("MSETNX", args) => Ok(Response::Integer(
+                storage.multi_set_if_absent(&Self::pairs(args)?)? as i64,
+            )),
+            ("GETSET", [key, value]) => Ok(storage
+                .set_returning_old(key, value)?
+                .map_or(Response::Nil, Response::bulk)),
+            ("INCR", [key]) => Ok(Response::Integer(storage.increment(key, 1)?)),
+            ("DECR", [key]) => Ok(Response::Integer(storage.increment(key, -1)?)),
+            ("INCRBY", [key, amount]) => Ok(Response::Integer(
+                storage.increment(key, command::integer(amount)?)?,
+            )),
+            ("DECRBY", [key, amount]) => {
+                // The amount is negated, which i64::MIN cannot be.
+                let delta = command::integer(amount)?
+                    .checked_neg()
+                    .ok_or(MiniRedisError::IncrementOverflow)?;
+                Ok(Response::Integer(storage.increment(key, delta)?))
+            }
+            ("INCRBYFLOAT", [key, amount]) => Ok(Response::Bulk(
+                storage
+                    .increment_float(key, storage::parse_float(amount)?)?
+                    .to_string(),
+            )),
+            ("APPEND", [key, suffix]) => Ok(Response::Integer(storage.append(key, suffix)? as i64)),
+            ("GETRANGE", [key, start, end]) => {
+                let (start, end) = (command::integer(start)?, command::integer(end)?);
+                let value = storage.get_bytes(key)?.unwrap_or_default();
+                let range = storage::byte_range(value.len(), start, end)
+                    .map_or_else(Vec::new, |range| value[range].to_vec());
+                Ok(Response::bulk(range))
+            }
+            ("SETRANGE", [key, offset, value]) => {
+                let offset = usize::try_from(command::integer(offset)?).map_err(|_| {
+                    MiniRedisError::InvalidArguments {
+                        arguments: command.args.clone(),
+                    }
+                })?;
+                // Like in Redis, writing nothing leaves a missing key missing.
+                let len = if value.is_empty() {
+                    storage.get_bytes(key)?.map_or(0, |value| value.len())
+                } else {
+                    storage.set_range(key, offset, value)?
+                };
+                Ok(Response::Integer(len as i64))
+            }
+            ("SETBIT", [key, offset, bit]) => {
+                let offset = usize::try_from(command::integer(offset)?).map_err(|_| {
+                    MiniRedisError::InvalidArguments {
+                        arguments: command.args.clone(),
+                    }
+                })?;
+                Ok(Response::Integer(
+                    storage.set_bit(key, offset, bit == "1")? as i64
+                ))
+            }
+            ("GETBIT", [key, offset]) => {
+                let offset = usize::try_from(command::integer(offset)?).map_err(|_| {
+                    MiniRedisError::InvalidArguments {
+                        arguments: command.args.clone(),
+                    }
+                })?;
+                let value = storage.get_bytes(key)?.unwrap_or_default();
+                Ok(Response::Integer(storage::read_bit(&value, offset)? as i64))
+            }
+            ("BITCOUNT", [key, range @ ..]) => {
+                let (start, end) = match range {
+                    [] => (0, -1),
+                    [start, end] => (command::integer(start)?, command::integer(end)?),
+                    _ => {
+                        return Err(MiniRedisError::InvalidArguments {
+                            arguments: command.args.clone(),
+                        });
+                    }
+                };
+                let value = storage.get_bytes(key)?.unwrap_or_default();
+                let count = storage::byte_range(value.len(), start, end).map_or(0, |range| {
+                    value[range]
+                        .iter()
+                        .map(|byte| byte.count_ones() as i64)
+                        .sum()
+                });
+                Ok(Response::Integer(count))
+            }
+            ("BITPOS", [key, bit, range @ ..]) => {
+                let bit = bit == "1";
+                let (start, end) = match range {
+                    [] => (0, None),
+                    [start] => (command::integer(start)?, None),
+                    [start, end, ..] => (command::integer(start)?, Some(command::integer(end)?)),
+                };
+                // A missing key counts as all clear bits, however far they go.
+                Ok(Response::Integer(match storage.get_bytes(key)? {
+                    Some(value) => storage::find_bit(&value, bit, start, end),
+                    None if bit => -1,
+                    None => 0,
+                }))
+            }
+            ("STRLEN", [key]) => Ok(Response::Integer(
+                storage.get_bytes(key)?.map_or(0, |value| value.len()) as i64,
+            )),
+            ("DELPREFIX", [prefix]) => Ok(Response::Integer(storage.del_prefix(prefix)? as i64)),
+            ("PING", []) => Ok(Response::Simple("PONG".to_string())),
+            ("PING", [message]) => Ok(Response::Bulk(message.clone())),
+            ("INFO", []) => Ok(Response::Map(vec![
+                (
+                    "version".to_string(),
+                    Response::Bulk(env!("CARGO_PKG_VERSION").to_string()),
+                ),
+                ("keys".to_string(), Response::Integer(storage.len()? as i64)),
+            ])),
+            ("INFO", [section]) if section.eq_ignore_ascii_case("CLIENTS") => {
+                let (paused, left) = match self.pause.state() {
+                    Some((mode, left)) => (mode.to_string(), left),
+                    None => ("no".to_string(), Duration::ZERO),
+                };
+                Ok(Response::Map(vec![
+                    ("paused".to_string(), Response::Bulk(paused)),
+                    (
+                        "paused_remaining_ms".to_string(),
+                        Response::Integer(left.as_millis() as i64),
+                    ),
+                ]))
+            }
+            ("INFO", [section]) if section.eq_ignore_ascii_case("STATS") => Ok(self.info_stats()),
+            ("INFO", [_keyspace]) => {
+                // The server has a single database, db0, left out while it is empty.
+                let keyspace = storage.keyspace()?;
+                let mut databases = Vec::new();
+                if keyspace.keys > 0 {
+                    databases.push(("db0".to_string(), Response::Bulk(keyspace.to_string())));
+                }
+                Ok(Response::Map(databases))
+            }
+            ("HELLO", []) => Ok(Response::Map(vec![
+                (
+                    "server".to_string(),
+                    Response::Bulk("miniredis".to_string()),
+                ),
+                (
+                    "version".to_string(),
+                    Response::Bulk(env!("CARGO_PKG_VERSION").to_string()),
+                ),
+                (
+                    "protocols".to_string(),
+                    Response::Array(vec![
+                        Response::Bulk("inline".to_string()),
+                        Response::Bulk("resp".to_string()),
+                    ]),
+                ),
+            ])),
+            ("LCS", [first, second, options @ ..]) => self.lcs(first, second, options),
+            ("SORT", [key, options @ ..]) => Self::sort(storage, key, options),
+            ("RANDOMKEY", []) => Ok(storage.random_key()?.map_or(Response::Nil, Response::Bulk)),
+            ("SCAN", args) => Self::scan(storage, args),
+            ("WAIT", [replicas, timeout]) => {
+                let invalid = || MiniRedisError::InvalidArguments {
+                    arguments: command.args.clone(),
+                };
+                let replicas =
+                    usize::try_from(command::integer(replicas)?).map_err(|_| invalid())?;
+                let timeout = u64::try_from(command::integer(timeout)?).map_err(|_| invalid())?;
+                // A timeout of 0 waits for as long as it takes.
+                let timeout = (timeout > 0).then(|| Duration::from_millis(timeout));
+                let acknowledged = self.replication.wait(replicas, timeout);
+                Ok(Response::Integer(acknowledged as i64))
+            }
+            ("CLIENT", [pause, timeout, mode @ ..]) if pause.eq_ignore_ascii_case("PAUSE") => {
+                let timeout = u64::try_from(command::integer(timeout)?).map_err(|_| {
+                    MiniRedisError::InvalidArguments {
+                        arguments: command.args.clone(),
+                    }
+                })?;
+                let mode = match mode {
+                    [mode] if mode.eq_ignore_ascii_case("WRITE") => PauseMode::Write,
+                    _ => PauseMode::All,
+                };
+                // The reply goes out at once; only the commands after it are held.
+                self.pause.pause(Duration::from_millis(timeout), mode);
+                Ok(Response::ok())
+            }
+            ("CLIENT", [unpause]) if unpause.eq_ignore_ascii_case("UNPAUSE") => {
+                self.unpause();
+                Ok(Response::ok())
+            }
+            ("CLIENT", _) => Err(MiniRedisError::InvalidArguments {
+                arguments: command.args.clone(),
+            }),
+            ("CONFIG", [_resetstat]) => {
+                self.stats.reset();
+                storage.reset_stats();
+                Ok(Response::ok())
+            }
+            ("CLUSTER", [_keyslot, key]) => {
+                Ok(Response::Integer(i64::from(cluster::key_slot(key))))
+            }
+            ("HELP", []) => Ok(Self::help(None)),
+            ("HELP", [name]) => Ok(Self::help(Some(name))),
+            _ => Err(MiniRedisError::InvalidCommand {
+                command: command.name.clone(),
+            }),
+        }
+    }
+
+    /// Runs `INFO stats`.
+    ///
+    /// Every field is a counter that `CONFIG RESETSTAT` sets back to zero.
+    /// Gauges, like the number of keys in `INFO` and `INFO keyspace`
+    /// or the pause in `INFO clients`, are left out, and are never reset.
+    ///
+    /// # Returns
+    ///
+    /// A map of the counters of the engine and of its storage, named the way Redis names them.
+    fn info_stats(&self) -> Response {
+        let server = self.stats.snapshot();
+        let store = self.storage.stats();
+        let counter = |name: &str, value: u64| (name.to_string(), Response::Integer(value as i64));
+        Response::Map(vec![
+            counter("total_connections_received", server.connections_received),
+            counter("total_commands_processed", server.commands_processed),
+            counter("keyspace_hits", store.hits),
+            counter("keyspace_misses", store.misses),
+            counter("expired_keys", store.expired),
+            counter("evicted_keys", store.evictions),
+            counter(
+                "client_output_buffer_limit_disconnections",
+                server.output_buffer_disconnections,
+            ),
+        ])
+    }
+
+    /// Runs `HELP [COMMAND]`, from the same table the client and the argument checks use.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The command to get help on in any case, or None for every command.
+    ///
+    /// # Returns
+    ///
+    /// An array of lines, which for a command are its usage, what it does,
+    /// and the version it was added in. For a command the server does not know,
+    /// a line saying so instead of an error.
+    fn help(name: Option<&str>) -> Response {
+        let lines = match name {
+            None => std::iter::once(format!(
+                "MiniRedis {}. The commands of the server are:",
+                env!("CARGO_PKG_VERSION")
+            ))
+            .chain(
+                COMMANDS
+                    .iter()
+                    .map(|info| format!("{} - {}", info.usage, info.summary)),
+            )
+            .chain(std::iter::once(
+                "Run HELP <COMMAND> for how to use one of them.".to_string(),
+            ))
+            .collect(),
+            Some(name) => match CommandInfo::find(&name.to_uppercase()) {
+                Some(info) => vec![
+                    info.usage.to_string(),
+                    format!("{}.", info.summary),
+                    format!("Since {}.", info.since),
+                ],
+                None => {
+                    return Response::Simple(format!("No help available for {}, try HELP.", name));
+                }
+            },
+        };
+        Response::Array(lines.into_iter().map(Response::Simple).collect())
+    }
+
+    /// Runs `LCS <KEY1> <KEY2> [LEN] [IDX] [MINMATCHLEN <LEN>] [WITHMATCHLEN]`.
+    ///
+    /// The values are copied out of the storage before they are compared,
+    /// so a long comparison does not hold up other clients. A missing key
+    /// compares like an empty string.
+    ///
+    /// # Arguments
+    ///
+    /// * `first` - The key of the first value.
+    /// * `second` - The key of the second value.
+    /// * `options` - The options after the keys.
+    ///
+    /// # Returns
+    ///
+    /// The longest common subsequence of the values, or with `LEN` its length.
+    /// With `IDX`, a map of its length and the runs it is made of, from the end of
+    /// the values back, each as the positions of the run in both values, and with
+    /// `WITHMATCHLEN` its length. `MINMATCHLEN` leaves out shorter runs.
+    ///
+    /// # Errors
+    ///
+    /// If an option is unknown or misses its value, or `LEN` and `IDX` are both given,
+    /// it will return [`MiniRedisError::InvalidArguments`]. If the values are too long
+    /// to compare, it will return [`MiniRedisError::ComparisonTooLarge`].
+    fn lcs(
+        &self,
+        first: &str,
+        second: &str,
+        options: &[String],
+    ) -> Result<Response, MiniRedisError> {
+        let invalid = || MiniRedisError::InvalidArguments {
+            arguments: options.to_vec(),
+        };
+        let (mut len, mut idx, mut min_len, mut with_len) = (false, false, 0, false);
+        let mut words = options.iter();
+        while let Some(word) = words.next() {
+            match word.to_ascii_uppercase().as_str() {
+                "LEN" => len = true,
+                "IDX" => idx = true,
+                "MINMATCHLEN" => {
+                    let value = words.next().ok_or_else(invalid)?;
+                    min_len = usize::try_from(command::integer(value)?).unwrap_or(0);
+                }
+                "WITHMATCHLEN" => with_len = true,
+                _ => return Err(invalid()),
+            }
+        }
+        if len && idx {
+            return Err(invalid());
+        }
+
+        let storage = self.storage();
+        let a = storage.get(first)?.unwrap_or_default();
+        let b = storage.get(second)?.unwrap_or_default();
+        let lcs = lcs::lcs(&a, &b, self.lcs_limit)?;
+        let length = Response::Integer(lcs.text.chars().count() as i64);
+        if len {
+            return Ok(length);
+        }
+        if !idx {
+            return Ok(Response::Bulk(lcs.text));
+        }
+
+        let range = |range: &RangeInclusive<usize>| {
+            Response::Array(vec![
+                Response::Integer(*range.start() as i64),
+                Response::Integer(*range.end() as i64),
+            ])
+        };
+        let matches = lcs
+            .matches
+            .iter()
+            .map(|(in_a, in_b)| (in_a, in_b, in_a.end() - in_a.start() + 1))
+            .filter(|(_, _, run)| *run >= min_len)
+            .map(|(in_a, in_b, run)| {
+                let mut reply = vec![range(in_a), range(in_b)];
+                if with_len {
+                    reply.push(Response::Integer(run as i64));
+                }
+                Response::Array(reply)
+            })
+            .collect();
+        Ok(Response::Map(vec![
+            ("matches".to_string(), Response::Array(matches)),
+            ("len".to_string(), length),
+        ]))
+    }
+
+    /// Runs `SORT <KEY> [LIMIT <OFFSET> <COUNT>] [ASC|DESC] [ALPHA] [STORE <DESTINATION>]`.
+    ///
+    /// The elements are copied out of the storage before they are sorted,
+    /// so the storage is not held while they are compared.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - The storage holding the collection.
+    /// * `key` - The key of the collection.
+    /// * `options` - The options after the key.
+    ///
+    /// # Returns
+    ///
+    /// The sorted elements as an array, or with `STORE`, the number of elements stored.
+    ///
+    /// # Errors
+    ///
+    /// If the key holds a string, it will return [`MiniRedisError::WrongType`].
+    /// If the options are invalid, it will return [`MiniRedisError::InvalidArguments`]
+    /// or [`MiniRedisError::NotAnInteger`], and if an element is not a number
+    /// and `ALPHA` is not given, [`MiniRedisError::NotADouble`].
+    fn sort(
+        storage: &dyn Storage,
+        key: &str,
+        options: &[String],
+    ) -> Result<Response, MiniRedisError> {
+        let options = SortOptions::parse(options)?;
+        // The storage only holds strings, so a key that is set cannot be sorted,
+        // and a missing key sorts like an empty list.
+        let elements = match storage.get(key)? {
+            Some(_) => return Err(MiniRedisError::WrongType),
+            None => Vec::new(),
+        };
+        let sorted = sort::sort(elements, &options)?;
+        match options.store {
+            Some(destination) => {
+                // Storing an empty list deletes the key, like in Redis.
+                storage.del(&destination)?;
+                Ok(Response::Integer(sorted.len() as i64))
+            }
+            None => Ok(Response::Array(
+                sorted.into_iter().map(Response::Bulk).collect(),
+            )),
+        }
+    }
+
+    /// Splits the arguments of `MSET` or `MSETNX` into key and value pairs.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - The keys, each followed by its value.
+    ///
+    /// # Returns
+    ///
+    /// The pairs, in the order they were given.
+    ///
+    /// # Errors
+    ///
+    /// If a key has no value, it will return [`MiniRedisError::InvalidArguments`].
+    fn pairs(args: &[String]) -> Result<Vec<(String, String)>, MiniRedisError> {
+        if !args.len().is_multiple_of(2) {
+            return Err(MiniRedisError::InvalidArguments {
+                arguments: args.to_vec(),
+            });
+        }
+        Ok(args
+            .chunks(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect())
+    }
+
+    /// Runs `SET <KEY> <VALUE>` with its options against the storage.
+    ///
+    /// `NX` or `XX` decide whether the value is written, and `EX`, `PX` or `KEEPTTL`
+    /// what happens to the time to live of the key, which is cleared without them.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - The storage to set the key in.
+    /// * `args` - The key, the value and the options.
+    ///
+    /// # Returns
+    ///
+    /// OK if the value was set, or nil if the key did not meet the condition.
+    ///
+    /// # Errors
+    ///
+    /// If an option is unknown or misses its value, two options conflict, or a time
+    /// to live is not positive, it will return [`MiniRedisError::InvalidArguments`].
+    /// If a time to live is not an integer, it will return [`MiniRedisError::NotAnInteger`],
+    /// and if it sets a deadline out of range, [`MiniRedisError::InvalidExpireTime`].
+    fn set(storage: &dyn Storage, args: &[String]) -> Result<Response, MiniRedisError> {
+        let invalid = || MiniRedisError::InvalidArguments {
+            arguments: args.to_vec(),
+        };
+        let [key, value, options @ ..] = args else {
+            return Err(invalid());
+        };
+        let mut set = SetOptions::default();
+        let mut options = options.iter();
+        while let Some(option) = options.next() {
+            match option.to_ascii_uppercase().as_str() {
+                "NX" if set.condition != Some(SetCondition::IfPresent) => {
+                    set.condition = Some(SetCondition::IfAbsent)
+                }
+                "XX" if set.condition != Some(SetCondition::IfAbsent) => {
+                    set.condition = Some(SetCondition::IfPresent)
+                }
+                "KEEPTTL" if set.expiry == SetExpiry::Persist => set.expiry = SetExpiry::Keep,
+                unit @ ("EX" | "PX") if set.expiry == SetExpiry::Persist => {
+                    let amount = options.next().ok_or_else(invalid)?;
+                    let amount = u64::try_from(command::integer(amount)?)
+                        .ok()
+                        .filter(|amount| *amount > 0)
+                        .ok_or_else(invalid)?;
+                    set.expiry = SetExpiry::After(Self::deadline(
+                        "SET",
+                        if unit == "EX" {
+                            Duration::from_secs(amount)
+                        } else {
+                            Duration::from_millis(amount)
+                        },
+                    )?);
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(if storage.set_with(key, value, &set)? {
+            Response::ok()
+        } else {
+            Response::Nil
+        })
+    }
+
+    /// Describes a key for `OBJECT` or `DEBUG OBJECT`.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - The storage holding the key.
+    /// * `key` - The key to describe.
+    ///
+    /// # Returns
+    ///
+    /// What the storage knows about the key.
+    ///
+    /// # Errors
+    ///
+    /// If the key does not exist, it will return [`MiniRedisError::NoSuchKey`].
+    fn describe(storage: &dyn Storage, key: &str) -> Result<KeyInfo, MiniRedisError> {
+        storage.describe(key)?.ok_or(MiniRedisError::NoSuchKey)
+    }
+
+    /// Runs `EXPIRE`, `PEXPIRE`, `EXPIREAT` or `PEXPIREAT` against the storage.
+    ///
+    /// Like in Redis, a time to live that has already run out deletes the key at once,
+    /// if the condition of the `NX`, `XX`, `GT` or `LT` option holds.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - The storage holding the key.
+    /// * `args` - The key, the time to live or timestamp, and the options.
+    /// * `ttl` - The time to live, or None if it has already run out.
+    ///
+    /// # Returns
+    ///
+    /// 1 if the time to live was set or the key deleted, 0 if the key does not exist
+    /// or the condition does not hold.
+    ///
+    /// # Errors
+    ///
+    /// If an option is not one of the four, it will return
+    /// [`MiniRedisError::InvalidArguments`]. If NX comes with another option,
+    /// or GT with LT, it will return [`MiniRedisError::IncompatibleOptions`].
+    /// If the storage cannot give keys
+    /// a time to live, it will return [`MiniRedisError::ExpiryNotSupported`].
+    fn expire(
+        storage: &dyn Storage,
+        args: &[String],
+        ttl: Option<Duration>,
+    ) -> Result<Response, MiniRedisError> {
+        let [key, _, options @ ..] = args else {
+            return Err(MiniRedisError::InvalidArguments {
+                arguments: args.to_vec(),
+            });
+        };
+        let (mut nx, mut xx, mut gt, mut lt) = (false, false, false, false);
+        for option in options {
+            match option.to_ascii_uppercase().as_str() {
+                "NX" => nx = true,
+                "XX" => xx = true,
+                "GT" => gt = true,
+                "LT" => lt = true,
+                _ => {
+                    return Err(MiniRedisError::InvalidArguments {
+                        arguments: args.to_vec(),
+                    });
+                }
+            }
+        }
+        let incompatible = |options: &str| MiniRedisError::IncompatibleOptions {
+            options: options.to_string(),
+        };
+        // A key without a time to live is never earlier than a deadline,
+        // so XX only adds anything to LT.
+        let condition = match (nx, xx, gt, lt) {
+            (true, false, false, false) => Some(ExpireCondition::IfNone),
+            (true, ..) => return Err(incompatible("NX and XX, GT or LT")),
+            (_, _, true, true) => return Err(incompatible("GT and LT")),
+            (_, _, true, false) => Some(ExpireCondition::IfLater),
+            (_, true, false, true) => Some(ExpireCondition::IfSomeEarlier),
+            (_, false, false, true) => Some(ExpireCondition::IfEarlier),
+            (_, true, false, false) => Some(ExpireCondition::IfSome),
+            (_, false, false, false) => None,
+        };
+        let set = match (ttl, condition) {
+            (Some(ttl), condition) if !ttl.is_zero() => storage.expire(key, ttl, condition)?,
+            (_, None) => storage.del(key)?,
+            (_, condition) => storage.expire(key, Duration::ZERO, condition)?,
+        };
+        Ok(Response::Integer(set as i64))
+    }
+
+    /// Parses the time to live of `EXPIRE` or `PEXPIRE`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the command, for the error.
+    /// * `amount` - The time to live, in the unit of the command.
+    /// * `unit` - Turns the amount into a time to live.
+    ///
+    /// # Returns
+    ///
+    /// The time to live, or None if it is not positive.
+    ///
+    /// # Errors
+    ///
+    /// If the amount is not an integer, it will return [`MiniRedisError::NotAnInteger`].
+    /// If the deadline it sets is out of range, it will return
+    /// [`MiniRedisError::InvalidExpireTime`].
+    fn after(
+        name: &str,
+        amount: &str,
+        unit: fn(u64) -> Duration,
+    ) -> Result<Option<Duration>, MiniRedisError> {
+        u64::try_from(command::integer(amount)?)
+            .ok()
+            .map(|amount| Self::deadline(name, unit(amount)))
+            .transpose()
+    }
+
+    /// Checks that a time to live sets a deadline within the range of the clock.
+    ///
+    /// Like in Redis, the deadline must fit in 64 bits as milliseconds since the Unix epoch.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the command, for the error.
+    /// * `ttl` - The time to live.
+    ///
+    /// # Returns
+    ///
+    /// The time to live.
+    ///
+    /// # Errors
+    ///
+    /// If the deadline is out of range, it will return [`MiniRedisError::InvalidExpireTime`].
+    fn deadline(name: &str, ttl: Duration) -> Result<Duration, MiniRedisError> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        match now.checked_add(ttl) {
+            Some(at) if at.as_millis() <= i64::MAX as u128 => Ok(ttl),
+            _ => Err(MiniRedisError::InvalidExpireTime {
+                command: name.to_string(),
+            }),
+        }
+    }
+
+    /// Parses the Unix timestamp of `EXPIREAT` or `PEXPIREAT` into the time to live left until it.
+    ///
+    /// The deadline is kept as a time to live, so a later change to the clock
+    /// of the system does not move it.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the command, for the error.
+    /// * `timestamp` - The time since the Unix epoch, in the unit of the command.
+    /// * `unit` - Turns the timestamp into a time since the Unix epoch.
+    ///
+    /// # Returns
+    ///
+    /// The time to live, or None if the timestamp has passed.
+    ///
+    /// # Errors
+    ///
+    /// If the timestamp is not an integer, it will return [`MiniRedisError::NotAnInteger`].
+    /// If it does not fit in 64 bits as milliseconds, it will return
+    /// [`MiniRedisError::InvalidExpireTime`].
+    fn until(
+        name: &str,
+        timestamp: &str,
+        unit: fn(u64) -> Duration,
+    ) -> Result<Option<Duration>, MiniRedisError> {
+        let Ok(timestamp) = u64::try_from(command::integer(timestamp)?) else {
+            return Ok(None);
+        };
+        let invalid = || MiniRedisError::InvalidExpireTime {
+            command: name.to_string(),
+        };
+        let since_epoch = unit(timestamp);
+        if since_epoch.as_millis() > i64::MAX as u128 {
+            return Err(invalid());
+        }
+        let at = SystemTime::UNIX_EPOCH
+            .checked_add(since_epoch)
+            .ok_or_else(invalid)?;
+        Ok(at.duration_since(SystemTime::now()).ok())
+    }
+
+    /// Runs `TTL` or `PTTL` against the storage.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - The storage holding the key.
+    /// * `key` - The key to get the time to live for.
+    /// * `unit` - Turns the time to live into the unit of the command.
+    ///
+    /// # Returns
+    ///
+    /// The time to live, -1 if the key never expires, or -2 if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// If the storage cannot be accessed, it will return an error.
+    fn ttl(
+        storage: &dyn Storage,
+        key: &str,
+        unit: fn(Duration) -> u128,
+    ) -> Result<Response, MiniRedisError> {
+        Ok(Response::Integer(match storage.ttl(key)? {
+            Some(ttl) => i64::try_from(unit(ttl)).unwrap_or(i64::MAX),
+            None if storage.exists(key)? => -1,
+            None => -2,
+        }))
+    }
+
+    /// Runs `GETEX <KEY> EX|PX|EXAT|PXAT <TIME>` or `GETEX <KEY> PERSIST` against the storage.
+    ///
+    /// Like in Redis, a timestamp that has already passed leaves the key to expire at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - The storage holding the key.
+    /// * `args` - The key and the options.
+    ///
+    /// # Returns
+    ///
+    /// The value of the key, or nil if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// If an option is unknown or misses its value, or the value is not positive,
+    /// it will return [`MiniRedisError::InvalidArguments`]. If more than one option is
+    /// given, it will return [`MiniRedisError::SyntaxError`]. If they set a deadline
+    /// out of range, it will return [`MiniRedisError::InvalidExpireTime`]. If the storage
+    /// cannot give keys a time to live, it will return [`MiniRedisError::ExpiryNotSupported`].
+    fn getex(storage: &dyn Storage, args: &[String]) -> Result<Response, MiniRedisError> {
+        let invalid = || MiniRedisError::InvalidArguments {
+            arguments: args.to_vec(),
+        };
+        let [key, options @ ..] = args else {
+            return Err(invalid());
+        };
+        let mut expiry = None;
+        let mut options = options.iter();
+        while let Some(option) = options.next() {
+            let option = option.to_ascii_uppercase();
+            let ttl = match option.as_str() {
+                "PERSIST" => None,
+                "EX" | "PX" | "EXAT" | "PXAT" => {
+                    let amount = options.next().ok_or_else(invalid)?;
+                    if command::integer(amount)? <= 0 {
+                        return Err(invalid());
+                    }
+                    let unit: fn(u64) -> Duration = if option.starts_with("EX") {
+                        Duration::from_secs
+                    } else {
+                        Duration::from_millis
+                    };
+                    Some(if option.ends_with("AT") {
+                        Self::until("GETEX", amount, unit)?.unwrap_or_default()
+                    } else {
+                        Self::after("GETEX", amount, unit)?.ok_or_else(invalid)?
+                    })
+                }
+                _ => return Err(invalid()),
+            };
+            if expiry.replace(ttl).is_some() {
+                return Err(MiniRedisError::SyntaxError);
+            }
+        }
+        let ttl = expiry.ok_or_else(invalid)?;
+        Ok(storage
+            .get_and_expire(key, ttl)?
+            .map_or(Response::Nil, Response::bulk))
+    }
+
+    /// Runs `SCAN <CURSOR> [MATCH <PATTERN>] [COUNT <N>] [TYPE <TYPE>]` against the storage.
+    ///
+    /// The pattern and the type filter the batch the storage picks, so a batch can be empty
+    /// while the scan goes on. The count is a hint for how many keys to look at, 10 by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - The storage to scan.
+    /// * `args` - The cursor returned by the previous call, or 0 to start, and the options.
+    ///
+    /// # Returns
+    ///
+    /// The result of the scan, as an array of the next cursor and an array of keys.
+    ///
+    /// # Errors
+    ///
+    /// If an option misses its value, the count is not positive, or the type is not one
+    /// Redis has, it will return [`MiniRedisError::InvalidArguments`]. If the cursor or the count
+    /// is not an integer, it will return [`MiniRedisError::NotAnInteger`].
+    fn scan(storage: &dyn Storage, args: &[String]) -> Result<Response, MiniRedisError> {
+        let invalid = || MiniRedisError::InvalidArguments {
+            arguments: args.to_vec(),
+        };
+        let [cursor, options @ ..] = args else {
+            return Err(invalid());
+        };
+        let cursor = command::cursor(cursor)?;
+        let mut scan = ScanOptions::default();
+        for option in options.chunks(2) {
+            match option {
+                [name, value] if name.eq_ignore_ascii_case("MATCH") => {
+                    scan.pattern = Some(value.clone())
+                }
+                [name, value] if name.eq_ignore_ascii_case("COUNT") => {
+                    scan.count = usize::try_from(command::integer(value)?)
+                        .ok()
+                        .filter(|count| *count > 0)
+                        .ok_or_else(invalid)?
+                }
+                [name, value] if name.eq_ignore_ascii_case("TYPE") => {
+                    if !storage::TYPES
+                        .iter()
+                        .any(|kind| kind.eq_ignore_ascii_case(value))
+                    {
+                        return Err(invalid());
+                    }
+                    scan.kind = Some(value.to_ascii_lowercase())
+                }
+                _ => return Err(invalid()),
+            }
+        }
+
+        storage.scan_with(cursor, &scan).map(|(next, keys)| {
+            Response::Array(vec![
+                Response::Bulk(next.to_string()),
+                Response::Array(keys.into_iter().map(Response::Bulk).collect()),
+            ])
+        })
+    }
+}
+
+impl Default for Engine {
+    /// Creates an engine with a store of its own, kept in memory.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::engine::Engine;
+    ///
+    /// let engine = Engine::default();
+    ///
+    /// assert_eq!(Ok(0), engine.storage().len());
+    /// ```
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Creates an engine on a fresh store, returning both.
+    fn engine() -> (Engine, Arc<KVStore>) {
+        let store = Arc::new(KVStore::new());
+        (Engine::with_store(Arc::clone(&store)), store)
+    }
+
+    /// Builds a command from its name and arguments.
+    fn command(name: &str, args: &[&str]) -> Command {
+        Command::new(name, args.iter().map(|arg| arg.to_string()).collect())
+    }
+
+    #[test]
+    fn execute_str_replies_like_the_server() {
+        let engine = Engine::new();
+
+        assert_eq!(
+            Response::ok(),
+            engine.execute_str("SET msg \"hello world\"\n")
+        );
+        assert_eq!(
+            Response::Bulk("hello world".to_string()),
+            engine.execute_str("GET msg")
+        );
+        assert_eq!(
+            Response::Error(MiniRedisError::InvalidArguments { arguments: vec![] }),
+            engine.execute_str("   ")
+        );
+        assert_eq!(
+            Response::Error(MiniRedisError::InvalidCommand {
+                command: "BOGUS".to_string()
+            }),
+            engine.execute_str("bogus")
+        );
+    }
+
+    #[test]
+    fn get_returns_value_when_key_exists() {
+        let (engine, store) = engine();
+        store.set("testkey", "testvalue").unwrap();
+
+        let response = engine.execute(command("GET", &["testkey"]));
+
+        assert_eq!(Response::Bulk("testvalue".to_string()), response);
+    }
+
+    #[test]
+    fn get_returns_nil_when_key_does_not_exist() {
+        let (engine, _) = engine();
+
+        let response = engine.execute(command("GET", &["nonexistent"]));
+
+        assert_eq!(Response::Nil, response);
+    }
+
+    #[test]
+    fn get_returns_error_with_no_arguments() {
+        let (engine, _) = engine();
+
+        let response = engine.execute(command("GET", &[]));
+
+        assert_eq!(
+            Response::Error(MiniRedisError::WrongArity {
+                command: "GET".to_string()
+            }),
+            response
+        );
+    }
+
+    #[test]
+    fn set_stores_value_and_returns_ok() {
+        let (engine, store) = engine();
+
+        let response = engine.execute(command("SET", &["testkey", "testvalue"]));
+
+        assert_eq!(Response::ok(), response);
+        assert_eq!(Some("testvalue".to_string()), store.get("testkey").unwrap());
+    }
+
+    #[test]
+    fn set_overwrites_existing_value() {
+        let (engine, store) = engine();
+        store.set("testkey", "oldvalue").unwrap();
+
+        let response = engine.execute(command("SET", &["testkey", "newvalue"]));
+
+        assert_eq!(Response::ok(), response);
+        assert_eq!(Some("newvalue".to_string()), store.get("testkey").unwrap());
+    }
+
+    #[test]
+    fn set_returns_error_with_no_value() {
+        let (engine, _) = engine();
+
+        let response = engine.execute(command("SET", &["testkey"]));
+
+        assert_eq!(
+            Response::Error(MiniRedisError::WrongArity {
+                command: "SET".to_string()
+            }),
+            response
+        );
+    }
+
+    #[test]
+    fn set_returns_error_with_no_arguments() {
+        let (engine, _) = engine();
+
+        let response = engine.execute(command("SET", &[]));
+
+        assert_eq!(
+            Response::Error(MiniRedisError::WrongArity {
+                command: "SET".to_string()
+            }),
+            response
+        );
+    }
+
+    #[test]
+    fn del_removes_key_and_returns_one() {
+        let (engine, store) = engine();
+        store.set("testkey", "testvalue").unwrap();
+
+        let response = engine.execute(command("DEL", &["testkey"]));
+
+        assert_eq!(Response::Integer(1), response);
+        assert_eq!(None, store.get("testkey").unwrap());
+    }
+
+    #[test]
+    fn del_returns_zero_if_key_does_not_exist() {
+        let (engine, _) = engine();
+
+        let response = engine.execute(command("DEL", &["nonexistent"]));
+
+        assert_eq!(Response::Integer(0), response);
+    }
+
+    #[test]
+    fn del_counts_only_the_keys_that_existed() {
+        let (engine, store) = engine();
+        store.set("k1", "a").unwrap();
+        store.set("k3", "c").unwrap();
+
+        let response = engine.execute(command("DEL", &["k1", "k2", "k3", "k1"]));
+
+        assert_eq!(Response::Integer(2), response);
+        assert_eq!(Ok(0), store.len());
+    }
+
+    #[test]
+    fn unlink_deletes_the_same_keys_as_del() {
+        let (engine, store) = engine();
+        store.set("k1", "a").unwrap();
+        store.set("k3", "c").unwrap();
+
+        let response = engine.execute(command("UNLINK", &["k1", "k2", "k3", "k1"]));
+
+        assert_eq!(Response::Integer(2), response);
+        assert_eq!(Ok(0), store.len());
+        assert_eq!(
+            Response::Error(MiniRedisError::WrongArity {
+                command: "UNLINK".to_string()
+            }),
+            engine.execute(command("UNLINK", &[]))
+        );
+    }
+
+    #[test]
+    fn unlink_sends_the_values_to_the_lazy_free_thread() {
+        let (engine, store) = engine();
+        let (values, freed) = std::sync::mpsc::channel();
+        let engine = engine.lazy_free(values);
+        store.set("big", "value").unwrap();
+
+        assert_eq!(
+            Response::Integer(1),
+            engine.execute(command("UNLINK", &["big", "missing"]))
+        );
+        assert_eq!(Ok(b"value".to_vec()), freed.try_recv());
+        assert!(freed.try_recv().is_err());
+        assert_eq!(Ok(None), store.get("big"));
+    }
+
+    #[test]
+    fn exists_counts_every_key_given_that_exists() {
+        let (engine, store) = engine();
+        store.set("a", "1").unwrap();
+
+        assert_eq!(
+            Response::Integer(2),
+            engine.execute(command("EXISTS", &["a", "a", "b"]))
+        );
+        assert_eq!(
+            Response::Error(MiniRedisError::WrongArity {
+                command: "EXISTS".to_string()
+            }),
+            engine.execute(command("EXISTS", &[]))
+        );
+    }
+
+    #[test]
+    fn object_encoding_tells_integers_from_other_strings() {
+        let (engine, store) = engine();
+        store.set("counter", "12").unwrap();
+        store.set("padded", "012").unwrap();
+        store.set("name", "alice").unwrap();
+
+        assert_eq!(
+            Response::Bulk("int".to_string()),
+            engine.execute(command("OBJECT", &["ENCODING", "counter"]))
+        );
+        assert_eq!(
+            Response::Bulk("raw".to_string()),
+            engine.execute(command("object", &["encoding", "padded"]))
+        );
+        assert_eq!(
+            Response::Bulk("raw".to_string()),
+            engine.execute(command("OBJECT", &["ENCODING", "name"]))
+        );
+        assert_eq!(
+            Response::Error(MiniRedisError::NoSuchKey),
+            engine.execute(command("OBJECT", &["ENCODING", "missing"]))
+        );
+    }
+
+    #[test]
+    fn debug_object_reports_the_length_of_the_value() {
+        let (engine, store) = engine();
+        store.set("name", "alice").unwrap();
+
+        let Response::Simple(info) = engine.execute(command("DEBUG", &["OBJECT", "name"])) else {
+            panic!("DEBUG OBJECT did not return a simple string");
+        };
+        assert!(
+            info.starts_with("encoding:raw serializedlength:5 "),
+            "{}",
+            info
+        );
+        assert!(info.ends_with(" ttl_ms:-1"), "{}", info);
+        assert_eq!(
+            Response::Error(MiniRedisError::NoSuchKey),
+            engine.execute(command("DEBUG", &["OBJECT", "missing"]))
+        );
+    }
+
+    #[test]
+    fn touch_counts_the_keys_that_exist() {
+        let (engine, store) = engine();
+        store.set("a", "1").unwrap();
+        store.set("b", "2").unwrap();
+
+        assert_eq!(
+            Response::Integer(3),
+            engine.execute(command("TOUCH", &["a", "b", "missing", "a"]))
+        );
+        assert_eq!(
+            Response::Error(MiniRedisError::WrongArity {
+                command: "TOUCH".to_string()
+            }),
+            engine.execute(command("TOUCH", &[]))
+        );
+    }
+
+    #[test]
+    fn type_names_the_value_of_a_key_or_none() {
+        let (engine, store) = engine();
+        store.set("name", "alice").unwrap();
+
+        assert_eq!(
+            Response::Simple("string".to_string()),
+            engine.execute(command("TYPE", &["name"]))
+        );
+        assert_eq!(
+            Response::Simple("none".to_string()),
+            engine.execute(command("TYPE", &["missing"]))
+        );
+        assert_eq!(
+            Response::Error(MiniRedisError::WrongArity {
+                command: "TYPE".to_string()
+            }),
+            engine.execute(command("TYPE", &["name", "missing"]))
+        );
+    }
+
+    #[test]
+    fn set_with_nx_or_xx_only_writes_when_the_condition_holds() {
+        for (option, exists, written) in [
+            ("NX", false, true),
+            ("NX", true, false),
+            ("XX", false, false),
+            ("XX", true, true),
+        ] {
+            let (engine, store) = engine();
+            if exists {
+                store.set("key", "old").unwrap();
+            }
+
+            let response = engine.execute(command("SET", &["key", "new", option]));
+
+            let (expected, value) = match (written, exists) {
+                (true, _) => (Response::ok(), Some("new")),
+                (false, true) => (Response::Nil, Some("old")),
+                (false, false) => (Response::Nil, None),
+            };
+            assert_eq!(expected, response, "{} {}", option, exists);
+            assert_eq!(
+                Ok(value.map(str::to_string)),
+                store.get("key"),
+                "{} {}",
+                option,
+                exists
+            );
+        }
+    }
+
+    #[test]
+    fn set_rejects_nx_with_xx_and_unknown_options() {
+        let (engine, store) = engine();
+
+        for options in [
+            &["NX", "XX"][..],
+            &["xx", "nx"],
+            &["EXTRA"],
+            &["NX", "EXTRA"],
+            &["EX", "10", "PX", "500"],
+            &["EX", "10", "KEEPTTL"],
+            &["KEEPTTL", "EX", "10"],
+            &["EX"],
+            &["EX", "0"],
+            &["PX", "-1"],
+        ] {
+            let mut args = vec!["key", "value"];
+            args.extend(options);
+
+            assert_eq!(
+                Response::Error(MiniRedisError::InvalidArguments {
+                    arguments: args.iter().map(|arg| arg.to_string()).collect()
+                }),
+                engine.execute(command("SET", &args)),
+                "{:?}",
+                options
+            );
+        }
+        assert_eq!(Ok(None), store.get("key"));
+    }
+
+    #[test]
+    fn set_with_ex_or_px_sets_a_time_to_live_that_a_plain_set_clears() {
+        let (engine, store) = engine();
+
+        assert_eq!(
+            Response::ok(),
+            engine.execute(command("SET", &["key", "a", "EX", "100"]))
+        );
+        let ttl = store.ttl("key").unwrap().unwrap();
+        assert!(ttl > Duration::from_secs(99) && ttl <= Duration::from_secs(100));
+        assert_eq!(
+            Response::ok(),
+            engine.execute(command("SET", &["key", "b", "keepttl"]))
+        );
+        assert!(store.ttl("key").unwrap().unwrap() > Duration::from_secs(99));
+        assert_eq!(
+            Response::ok(),
+            engine.execute(command("SET", &["key", "c"]))
+        );
+        assert_eq!(Ok(None), store.ttl("key"));
+
+        assert_eq!(
+            Response::ok(),
+            engine.execute(command("SET", &["key", "d", "XX", "px", "1500"]))
+        );
+        let ttl = store.ttl("key").unwrap().unwrap();
+        assert!(ttl > Duration::from_millis(1400) && ttl <= Duration::from_millis(1500));
+        assert_eq!(Ok(Some("d".to_string())), store.get("key"));
+    }
+
+    #[test]
+    fn set_rejects_non_numeric_times_to_live() {
+        let (engine, store) = engine();
+
+        for args in [&["key", "a", "EX", "ten"][..], &["key", "a", "PX", "1.5"]] {
+            assert_eq!(
+                Response::Error(MiniRedisError::NotAnInteger),
+                engine.execute(command("SET", args))
+            );
+        }
+        assert_eq!(Ok(None), store.get("key"));
+    }
+
+    #[test]
+    fn set_rejects_a_time_to_live_past_the_range_of_the_clock() {
+        let (engine, store) = engine();
+
+        for args in [
+            &["key", "a", "EX", "9223372036854775"][..],
+            &["key", "a", "PX", "9223372036854775807"],
+        ] {
+            assert_eq!(
+                Response::Error(MiniRedisError::InvalidExpireTime {
+                    command: "SET".to_string()
+                }),
+                engine.execute(command("SET", args)),
+                "{:?}",
+                args
+            );
+        }
+        assert_eq!(Ok(None), store.get("key"));
+    }
+
+    #[test]
+    fn setnx_only_sets_a_missing_key() {
+        let (engine, store) = engine();
+
+        assert_eq!(
+            Response::Integer(1),
+            engine.execute(command("SETNX", &["lock", "alice"]))
+        );
+        assert_eq!(
+            Response::Integer(0),
+            engine.execute(command("SETNX", &["lock", "bob"]))
+        );
+        assert_eq!(Ok(Some("alice".to_string())), store.get("lock"));
+    }
+
+    #[test]
+    fn expire_sets_a_time_to_live_that_ttl_reports() {
+        let (engine, store) = engine();
+        store.set("session", "abc").unwrap();
+
+        assert_eq!(
+            Response::Integer(-1),
+            engine.execute(command("TTL", &["session"]))
+        );
+        assert_eq!(
+            Response::Integer(1),
+            engine.execute(command("EXPIRE", &["session", "100"]))
+        );
+        assert_eq!(
+            Response::Integer(100),
+            engine.execute(command("TTL", &["session"]))
+        );
+        assert_eq!(
+            Response::Integer(0),
+            engine.execute(command("EXPIRE", &["missing", "100"]))
+        );
+        assert_eq!(
+            Response::Integer(-2),
+            engine.execute(command("TTL", &["missing"]))
+        );
+    }
+
+    #[test]
+    fn pexpire_and_pttl_work_in_milliseconds() {
+        let (engine, store) = engine();
+        store.set("a", "1").unwrap();
+        store.set("b", "2").unwrap();
+
+        assert_eq!(
+            Response::Integer(1),
+            engine.execute(command("PEXPIRE", &["a", "2600"]))
+        );
+        let Response::Integer(ttl) = engine.execute(command("PTTL", &["a"])) else {
+            panic!("PTTL did not return an integer");
+        };
+        assert!(ttl > 2500 && ttl <= 2600, "{}", ttl);
+        assert_eq!(Response::Integer(3), engine.execute(command("TTL", &["a"])));
+
+        engine.execute(command("EXPIRE", &["b", "2"]));
+        let Response::Integer(ttl) = engine.execute(command("PTTL", &["b"])) else {
+            panic!("PTTL did not return an integer");
+        };
+        assert!(ttl > 1900 && ttl <= 2000, "{}", ttl);
+
+        store.set("c", "3").unwrap();
+        assert_eq!(
+            Response::Integer(-1),
+            engine.execute(command("PTTL", &["c"]))
+        );
+        assert_eq!(
+            Response::Integer(-2),
+            engine.execute(command("PTTL", &["missing"]))
+        );
+        assert_eq!(
+            Response::Integer(1),
+            engine.execute(command("PEXPIRE", &["c", "0"]))
+        );
+        assert_eq!(Ok(false), store.exists("c"));
+    }
+
+    #[test]
+    fn expireat_sets_a_deadline_that_ttl_counts_down_to() {
+        let (engine, store) = engine();
+        store.set("a", "1").unwrap();
+        store.set("b", "2").unwrap();
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap();
+
+        let seconds = (now.as_secs() + 100).to_string();
+        assert_eq!(
+            Response::Integer(1),
+            engine.execute(command("EXPIREAT", &["a", &seconds]))
+        );
+        let ttl = store.ttl("a").unwrap().unwrap();
+        assert!(ttl > Duration::from_secs(98) && ttl <= Duration::from_secs(100));
+
+        let milliseconds = (now.as_millis() + 1500).to_string();
+        assert_eq!(
+            Response::Integer(1),
+            engine.execute(command("PEXPIREAT", &["b", &milliseconds]))
+        );
+        let Response::Integer(ttl) = engine.execute(command("PTTL", &["b"])) else {
+            panic!("PTTL did not return an integer");
+        };
+        assert!(ttl > 1400 && ttl <= 1500, "{}", ttl);
+        assert_eq!(
+            Response::Integer(0),
+            engine.execute(command("EXPIREAT", &["missing", &seconds]))
+        );
+    }
+
+    #[test]
+    fn expire_rejects_a_deadline_past_the_range_of_the_clock() {
+        let (engine, store) = engine();
+        store.set("a", "1").unwrap();
+
+        for (name, amount) in [
+            ("EXPIRE", "9223372036854775"),
+            ("PEXPIRE", "9223372036854775807"),
+            ("EXPIREAT", "9223372036854776"),
+        ] {
+            assert_eq!(
+                Response::Error(MiniRedisError::InvalidExpireTime {
+                    command: name.to_string()
+                }),
+                engine.execute(command(name, &["a", amount])),
+                "{}",
+                name
+            );
+        }
+        assert_eq!(Ok(None), store.ttl("a"));
+        assert_eq!(Ok(Some("1".to_string())), store.get("a"));
+    }
+
+    #[test]
+    fn expireat_in_the_past_deletes_the_key() {
+        let (engine, store) = engine();
+        store.set("a", "1").unwrap();
+        store.set("b", "2").unwrap();
+        store.set("c", "3").unwrap();
+
+        assert_eq!(
+            Response::Integer(1),
+            engine.execute(command("EXPIREAT", &["a", "1000"]))
+        );
+        assert_eq!(
+            Response::Integer(1),
+            engine.execute(command("PEXPIREAT", &["b", "1000"]))
+        );
+        assert_eq!(
+            Response::Integer(1),
+            engine.execute(command("EXPIREAT", &["c", "-1"]))
+        );
+        assert_eq!(Ok(0), store.len());
+        assert_eq!(
+            Response::Integer(0),
+            engine.execute(command("EXPIREAT", &["a", "1000"]))
+        );
+    }
+
+    #[test]
+    fn persisting_a_key_removes_the_deadline_expireat_set() {
+        let (engine, store) = engine();
+        store.set("a", "1").unwrap();
+        let seconds = (SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 100)
+            .to_string();
+
+        engine.execute(command("EXPIREAT", &["a", &seconds]));
+        engine.execute(command("GETEX", &["a", "PERSIST"]));
+
+        assert_eq!(
+            Response::Integer(-1),
+            engine.execute(command("TTL", &["a"]))
+        );
+        assert_eq!(Ok(Some("1".to_string())), store.get("a"));
+    }
+
+    #[test]
+    fn persist_removes_the_time_to_live_of_a_key() {
+        let (engine, store) = engine();
+        store.set("session", "abc").unwrap();
+
+        assert_eq!(
+            Response::Integer(0),
+            engine.execute(command("PERSIST", &["session"]))
+        );
+        engine.execute(command("EXPIRE", &["session", "100"]));
+        assert_eq!(
+            Response::Integer(1),
+            engine.execute(command("PERSIST", &["session"]))
+        );
+        assert_eq!(
+            Response::Integer(-1),
+            engine.execute(command("TTL", &["session"]))
+        );
+        assert_eq!(
+            Response::Integer(0),
+            engine.execute(command("PERSIST", &["missing"]))
+        );
+    }
+
+    #[test]
+    fn expire_options_set_the_time_to_live_only_if_their_condition_holds() {
+        // The new time to live is 100 seconds, against none, 50 and 200.
+        let expected = [
+            ("NX", [1, 0, 0]),
+            ("XX", [0, 1, 1]),
+            ("GT", [0, 1, 0]),
+            ("LT", [1, 0, 1]),
+            ("XX GT", [0, 1, 0]),
+            ("XX LT", [0, 0, 1]),
+            ("nx nx", [1, 0, 0]),
+        ];
+        for (option, results) in expected {
+            let (engine, store) = engine();
+            for key in ["none", "shorter", "longer"] {
+                store.set(key, "value").unwrap();
+            }
+            store
+                .expire("shorter", Duration::from_secs(50), None)
+                .unwrap();
+            store
+                .expire("longer", Duration::from_secs(200), None)
+                .unwrap();
+
+            for (key, result) in ["none", "shorter", "longer"].into_iter().zip(results) {
+                let args = [&[key, "100"][..], &option.split(' ').collect::<Vec<_>>()].concat();
+                assert_eq!(
+                    Response::Integer(result),
+                    engine.execute(command("EXPIRE", &args)),
+                    "{} {}",
+                    option,
+                    key
+                );
+                let ttl = store.ttl(key).unwrap();
+                assert_eq!(
+                    result == 1,
+                    ttl.is_some_and(
+                        |ttl| ttl > Duration::from_secs(99) && ttl <= Duration::from_secs(100)
+                    ),
+                    "{} {}",
+                    option,
+                    key
+                );
+            }
+            assert_eq!(
+                Response::Integer(0),
+                engine.execute(command(
+                    "PEXPIRE",
+                    &["missing", "100", option.split(' ').next().unwrap()]
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn expire_options_decide_whether_a_time_to_live_run_out_deletes_the_key() {
+        let (engine, store) = engine();
+        store.set("a", "1").unwrap();
+
+        assert_eq!(
+            Response::Integer(0),
+            engine.execute(command("EXPIRE", &["a", "0", "XX"]))
+        );
+        assert_eq!(Ok(true), store.exists("a"));
+        assert_eq!(
+            Response::Integer(1),
+            engine.execute(command("EXPIREAT", &["a", "1000", "lt"]))
+        );
+        assert_eq!(Ok(false), store.exists("a"));
+    }
+
+    #[test]
+    fn expire_rejects_unknown_or_combined_options() {
+        let (engine, store) = engine();
+        store.set("a", "1").unwrap();
+
+        let incompatible = |options: &str| {
+            Response::Error(MiniRedisError::IncompatibleOptions {
+                options: options.to_string(),
+            })
+        };
+        for (args, error) in [
+            (
+                &["a", "100", "NX", "GT"][..],
+                incompatible("NX and XX, GT or LT"),
+            ),
+            (
+                &["a", "100", "lt", "nx"],
+                incompatible("NX and XX, GT or LT"),
+            ),
+            (
+                &["a", "100", "NX", "XX"],
+                incompatible("NX and XX, GT or LT"),
+            ),
+            (&["a", "100", "GT", "XX", "LT"], incompatible("GT and LT")),
+            (
+                &["a", "100", "XX", "SOON"],
+                Response::Error(MiniRedisError::InvalidArguments {
+                    arguments: ["a", "100", "XX", "SOON"].map(String::from).to_vec(),
+                }),
+            ),
+        ] {
+            assert_eq!(error, engine.execute(command("EXPIRE", args)), "{:?}", args);
+        }
+        assert_eq!(Ok(None), store.ttl("a"));
+    }
+
+    #[test]
+    fn expire_without_a_positive_time_to_live_deletes_the_key() {
+        let (engine, store) = engine();
+        store.set("a", "1").unwrap();
+        store.set("b", "2").unwrap();
+
+        assert_eq!(
+            Response::Integer(1),
+            engine.execute(command("EXPIRE", &["a", "0"]))
+        );
+        assert_eq!(
+            Response::Integer(1),
+            engine.execute(command("EXPIRE", &["b", "-10"]))
+        );
+        assert_eq!(Ok(0), store.len());
+        assert_eq!(
+            Response::Integer(0),
+            engine.execute(command("EXPIRE", &["a", "0"]))
+        );
+    }
+
+    #[test]
+    fn getex_sets_a_new_time_to_live_or_persists_the_key() {
+        let (engine, store) = engine();
+        store.set("session", "abc").unwrap();
+
+        assert_eq!(
+            Response::Bulk("abc".to_string()),
+            engine.execute(command("GETEX", &["session"]))
+        );
+        assert_eq!(Ok(None), store.ttl("session"));
+
+        assert_eq!(
+            Response::Bulk("abc".to_string()),
+            engine.execute(command("GETEX", &["session", "ex", "100"]))
+        );
+        let ttl = store.ttl("session").unwrap().unwrap();
+        assert!(ttl > Duration::from_secs(99) && ttl <= Duration::from_secs(100));
+
+        assert_eq!(
+            Response::Bulk("abc".to_string()),
+            engine.execute(command("GETEX", &["session", "PERSIST"]))
+        );
+        assert_eq!(Ok(None), store.ttl("session"));
+        assert_eq!(
+            Response::Nil,
+            engine.execute(command("GETEX", &["missing", "EX", "10"]))
+        );
+
+        let in_a_minute = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            + Duration::from_secs(60);
+        let (seconds, milliseconds) = (
+            in_a_minute.as_secs().to_string(),
+            in_a_minute.as_millis().to_string(),
+        );
+        for options in [
+            ["PX", "60000"],
+            ["EXAT", seconds.as_str()],
+            ["pxat", milliseconds.as_str()],
+        ] {
+            assert_eq!(
+                Response::Bulk("abc".to_string()),
+                engine.execute(command("GETEX", &[&["session"][..], &options].concat()))
+            );
+            let ttl = store.ttl("session").unwrap().unwrap();
+            assert!(
+                ttl > Duration::from_secs(58) && ttl <= Duration::from_secs(60),
+                "{:?}",
+                options
+            );
+        }
+        assert_eq!(
+            Response::Bulk("abc".to_string()),
+            engine.execute(command("GETEX", &["session", "EXAT", "1"]))
+        );
+        assert_eq!(Ok(false), store.exists("session"));
+    }
+
+    #[test]
+    fn getex_rejects_invalid_options() {
+        let (engine, store) = engine();
+        store.set("session", "abc").unwrap();
+
+        for args in [
+            &["session", "EX", "0"][..],
+            &["session", "EX", "-5"],
+            &["session", "EX"],
+            &["session", "PERSIST", "10"],
+        ] {
+            assert_eq!(
+                Response::Error(MiniRedisError::InvalidArguments {
+                    arguments: args.iter().map(|arg| arg.to_string()).collect()
+                }),
+                engine.execute(command("GETEX", args)),
+                "{:?}",
+                args
+            );
+        }
+        assert_eq!(Ok(None), store.ttl("session"));
+    }
+
+    #[test]
+    fn getex_rejects_more_than_one_option() {
+        let (engine, store) = engine();
+        store.set("session", "abc").unwrap();
+
+        for args in [
+            &["session", "EX", "10", "PERSIST"][..],
+            &["session", "PERSIST", "PX", "100"],
+            &["session", "EX", "10", "PXAT", "100"],
+            &["session", "persist", "PERSIST"],
+        ] {
+            assert_eq!(
+                Response::Error(MiniRedisError::SyntaxError),
+                engine.execute(command("GETEX", args)),
+                "{:?}",
+                args
+            );
+        }
+        assert_eq!(Ok(None), store.ttl("session"));
+    }
+
+    #[test]
+    fn getex_rejects_a_time_to_live_past_the_range_of_the_clock() {
+        let (engine, store) = engine();
+        store.set("session", "abc").unwrap();
+
+        assert_eq!(
+            Response::Error(MiniRedisError::InvalidExpireTime {
+                command: "GETEX".to_string()
+            }),
+            engine.execute(command("GETEX", &["session", "EX", "9223372036854775"]))
+        );
+        assert_eq!(Ok(None), store.ttl("session"));
+    }
+
+    #[test]
+    fn mset_sets_every_pair() {
+        let (engine, store) = engine();
+
+        assert_eq!(
+            Response::ok(),
+            engine.execute(command("MSET", &["first", "alice", "last", "smith"]))
+        );
+        assert_eq!(Ok(Some("alice".to_string())), store.get("first"));
+        assert_eq!(Ok(Some("smith".to_string())), store.get("last"));
+    }
+
+    #[test]
+    fn mset_rejects_a_key_without_a_value() {
+        let (engine, store) = engine();
+
+        assert_eq!(
+            Response::Error(MiniRedisError::InvalidArguments {
+                arguments: vec!["a".to_string(), "1".to_string(), "b".to_string()]
+            }),
+            engine.execute(command("MSET", &["a", "1", "b"]))
+        );
+        assert_eq!(Ok(0), store.len());
+    }
+
+    #[test]
+    fn msetnx_writes_nothing_if_one_of_the_keys_exists() {
+        let (engine, store) = engine();
+        store.set("c", "taken").unwrap();
+
+        assert_eq!(
+            Response::Integer(0),
+            engine.execute(command(
+                "MSETNX",
+                &["a", "1", "b", "2", "c", "3", "d", "4", "e", "5"]
+            ))
+        );
+        for key in ["a", "b", "d", "e"] {
+            assert_eq!(Ok(None), store.get(key), "{}", key);
+        }
+        assert_eq!(Ok(Some("taken".to_string())), store.get("c"));
+
+        assert_eq!(
+            Response::Integer(1),
+            engine.execute(command("MSETNX", &["a", "1", "b", "2"]))
+        );
+        assert_eq!(Ok(Some("2".to_string())), store.get("b"));
+        assert_eq!(
+            Response::Error(MiniRedisError::InvalidArguments {
+                arguments: vec!["x".to_string(), "1".to_string(), "y".to_string()]
+            }),
+            engine.execute(command("MSETNX", &["x", "1", "y"]))
+        );
+    }
+
+    #[test]
+    fn getset_returns_the_old_value_and_stores_the_new_one() {
+        let (engine, store) = engine();
+
+        assert_eq!(
+            Response::Nil,
+            engine.execute(command("GETSET", &["token", "a"]))
+        );
+        assert_eq!(
+            Response::Bulk("a".to_string()),
+            engine.execute(command("GETSET", &["token", "b"]))
+        );
+        assert_eq!(Ok(Some("b".to_string())), store.get("token"));
+    }
+
+    #[test]
+    fn incr_and_decr_count_from_zero_and_refuse_non_integers() {
+        let (engine, store) = engine();
+        store.set("name", "alice").unwrap();
+
+        assert_eq!(
+            Response::Integer(1),
+            engine.execute(command("INCR", &["n"]))
+        );
+        assert_eq!(
+            Response::Integer(2),
+            engine.execute(command("INCR", &["n"]))
+        );
+        assert_eq!(
+            Response::Integer(1),
+            engine.execute(command("DECR", &["n"]))
+        );
+        assert_eq!(
+            Response::Integer(-1),
+            engine.execute(command("DECR", &["m"]))
+        );
+        assert_eq!(
+            Response::Error(MiniRedisError::NotAnInteger),
+            engine.execute(command("INCR", &["name"]))
+        );
+        assert_eq!(Ok(Some("alice".to_string())), store.get("name"));
+    }
+
+    #[test]
+    fn incrby_and_decrby_add_signed_amounts() {
+        let (engine, store) = engine();
+        store.set("name", "alice").unwrap();
+
+        assert_eq!(
+            Response::Integer(10),
+            engine.execute(command("INCRBY", &["n", "10"]))
+        );
+        assert_eq!(
+            Response::Integer(7),
+            engine.execute(command("INCRBY", &["n", "-3"]))
+        );
+        assert_eq!(
+            Response::Integer(12),
+            engine.execute(command("DECRBY", &["n", "-5"]))
+        );
+        assert_eq!(
+            Response::Integer(-4),
+            engine.execute(command("DECRBY", &["m", "4"]))
+        );
+        assert_eq!(
+            Response::Error(MiniRedisError::NotAnInteger),
+            engine.execute(command("INCRBY", &["name", "1"]))
+        );
+        assert_eq!(
+            Response::Error(MiniRedisError::NotAnInteger),
+            engine.execute(command("INCRBY", &["n", "ten"]))
+        );
+        assert_eq!(Ok(Some("12".to_string())), store.get("n"));
+    }
+
+    #[test]
+    fn incrby_and_decrby_refuse_to_overflow() {
+        let (engine, store) = engine();
+        store.set("max", &i64::MAX.to_string()).unwrap();
+        store.set("min", &i64::MIN.to_string()).unwrap();
+
+        assert_eq!(
+            Response::Error(MiniRedisError::IncrementOverflow),
+            engine.execute(command("INCRBY", &["max", "1"]))
+        );
+        assert_eq!(
+            Response::Error(MiniRedisError::IncrementOverflow),
+            engine.execute(command("DECRBY", &["min", "1"]))
+        );
+        assert_eq!(
+            Response::Error(MiniRedisError::IncrementOverflow),
+            engine.execute(command("DECRBY", &["n", &i64::MIN.to_string()]))
+        );
+        assert_eq!(Ok(Some(i64::MAX.to_string())), store.get("max"));
+        assert_eq!(Ok(None), store.get("n"));
+    }
+
+    #[test]
+    fn incrbyfloat_adds_and_stores_the_shortest_form() {
+        let (engine, store) = engine();
+        store.set("n", "3").unwrap();
+
+        assert_eq!(
+            Response::Bulk("3.1".to_string()),
+            engine.execute(command("INCRBYFLOAT", &["n", "0.1"]))
+        );
+        assert_eq!(
+            Response::Bulk("3".to_string()),
+            engine.execute(command("INCRBYFLOAT", &["n", "-0.1"]))
+        );
+        assert_eq!(
+            Response::Bulk("-2.5".to_string()),
+            engine.execute(command("INCRBYFLOAT", &["m", "-2.5"]))
+        );
+        assert_eq!(
+            Response::Bulk("5000".to_string()),
+            engine.execute(command("INCRBYFLOAT", &["e", "5e3"]))
+        );
+        assert_eq!(Ok(Some("3".to_string())), store.get("n"));
+    }
+
+    #[test]
+    fn incrbyfloat_refuses_non_numbers_and_non_finite_results() {
+        let (engine, store) = engine();
+        store.set("name", "alice").unwrap();
+        store.set("inf", "inf").unwrap();
+        store.set("max", &f64::MAX.to_string()).unwrap();
+
+        for (key, amount) in [("name", "1"), ("inf", "1"), ("n", "one"), ("n", "nan")] {
+            assert_eq!(
+                Response::Error(MiniRedisError::NotAFloat),
+                engine.execute(command("INCRBYFLOAT", &[key, amount])),
+                "{} {}",
+                key,
+                amount
+            );
+        }
+        assert_eq!(
+            Response::Error(MiniRedisError::IncrementNotFinite),
+            engine.execute(command("INCRBYFLOAT", &["max", &f64::MAX.to_string()]))
+        );
+        assert_eq!(Ok(Some(f64::MAX.to_string())), store.get("max"));
+        assert_eq!(Ok(None), store.get("n"));
+    }
+
+    #[test]
+    fn append_sets_a_missing_key_and_returns_the_new_length() {
+        let (engine, store) = engine();
+
+        assert_eq!(
+            Response::Integer(5),
+            engine.execute(command("APPEND", &["greeting", "hello"]))
+        );
+        assert_eq!(
+            Response::Integer(11),
+            engine.execute(command("APPEND", &["greeting", " world"]))
+        );
+        assert_eq!(
+            Response::Integer(13),
+            engine.execute(command("APPEND", &["greeting", "é"]))
+        );
+        assert_eq!(Ok(Some("hello worldé".to_string())), store.get("greeting"));
+    }
+
+    #[test]
+    fn getrange_returns_the_bytes_between_the_indexes() {
+        let (engine, store) = engine();
+        store.set("key", "This is a string").unwrap();
+
+        for (start, end, expected) in [
+            ("0", "3", "This"),
+            ("-3", "-1", "ing"),
+            ("0", "-1", "This is a string"),
+            ("10", "100", "string"),
+            ("5", "2", ""),
+            ("100", "200", ""),
+        ] {
+            assert_eq!(
+                Response::Bulk(expected.to_string()),
+                engine.execute(command("GETRANGE", &["key", start, end])),
+                "{} {}",
+                start,
+                end
+            );
+        }
+        assert_eq!(
+            Response::Bulk(String::new()),
+            engine.execute(command("GETRANGE", &["missing", "0", "-1"]))
+        );
+        assert_eq!(
+            Response::Error(MiniRedisError::NotAnInteger),
+            engine.execute(command("GETRANGE", &["key", "zero", "-1"]))
+        );
+    }
+
+    #[test]
+    fn setrange_pads_overwrites_and_extends_the_value() {
+        let (engine, store) = engine();
+
+        assert_eq!(
+            Response::Integer(5),
+            engine.execute(command("SETRANGE", &["key", "2", "abc"]))
+        );
+        assert_eq!(Ok(Some("\0\0abc".to_string())), store.get("key"));
+        assert_eq!(
+            Response::Integer(5),
+            engine.execute(command("SETRANGE", &["key", "1", "XY"]))
+        );
+        assert_eq!(Ok(Some("\0XYbc".to_string())), store.get("key"));
+        assert_eq!(
+            Response::Integer(7),
+            engine.execute(command("SETRANGE", &["key", "4", "end"]))
+        );
+        assert_eq!(Ok(Some("\0XYbend".to_string())), store.get("key"));
+        assert_eq!(
+            Response::Integer(0),
+            engine.execute(command("SETRANGE", &["missing", "3", ""]))
+        );
+        assert_eq!(Ok(false), store.exists("missing"));
+    }
+
+    #[test]
+    fn setbit_sets_scattered_bits_and_getbit_reads_them_back() {
+        let (engine, store) = engine();
+
+        for offset in ["1", "7", "13", "100"] {
+            assert_eq!(
+                Response::Integer(0),
+                engine.execute(command("SETBIT", &["bits", offset, "1"]))
+            );
+        }
+        assert_eq!(
+            Response::Integer(1),
+            engine.execute(command("SETBIT", &["bits", "7", "0"]))
+        );
+
+        let value = store.get("bits").unwrap().unwrap();
+        assert_eq!(13, value.len());
+        assert_eq!(&[0x40, 0x04], &value.as_bytes()[..2]);
+        assert!(value.as_bytes()[2..12].iter().all(|byte| *byte == 0));
+        assert_eq!(0x08, value.as_bytes()[12]);
+        for (offset, bit) in [
+            ("1", 1),
+            ("7", 0),
+            ("13", 1),
+            ("100", 1),
+            ("50", 0),
+            ("5000", 0),
+        ] {
+            assert_eq!(
+                Response::Integer(bit),
+                engine.execute(command("GETBIT", &["bits", offset])),
+                "{}",
+                offset
+            );
+        }
+        assert_eq!(
+            Response::Integer(0),
+            engine.execute(command("GETBIT", &["missing", "3"]))
+        );
+    }
+
+    #[test]
+    fn setbit_rejects_bad_offsets_and_bits() {
+        let (engine, store) = engine();
+        store.set("key", "a").unwrap();
+
+        assert_eq!(
+            Response::Error(MiniRedisError::InvalidArguments {
+                arguments: vec!["key".to_string(), "-1".to_string(), "1".to_string()]
+            }),
+            engine.execute(command("SETBIT", &["key", "-1", "1"]))
+        );
+        assert_eq!(
+            Response::Error(MiniRedisError::InvalidArguments {
+                arguments: vec!["key".to_string(), "3".to_string(), "2".to_string()]
+            }),
+            engine.execute(command("SETBIT", &["key", "3", "2"]))
+        );
+        assert_eq!(
+            Response::Error(MiniRedisError::ValueTooLarge),
+            engine.execute(command("SETBIT", &["key", "4294967296", "1"]))
+        );
+        assert_eq!(
+            Response::Error(MiniRedisError::ValueTooLarge),
+            engine.execute(command("GETBIT", &["key", "4294967296"]))
+        );
+        assert_eq!(Ok(Some("a".to_string())), store.get("key"));
+
+        assert_eq!(
+            Response::Integer(0),
+            engine.execute(command("SETBIT", &["key", "0", "1"]))
+        );
+        assert_eq!(
+            Response::Integer(1),
+            engine.execute(command("GETBIT", &["key", "0"]))
+        );
+        assert_eq!(Ok(Some(vec![0xe1])), store.get_bytes("key"));
+    }
+
+    #[test]
+    fn bitcount_counts_set_bits_in_a_byte_range() {
+        let (engine, store) = engine();
+        store.set("key", "foobar").unwrap();
+
+        for (range, count) in [
+            (&[][..], 26),
+            (&["0", "0"], 4),
+            (&["1", "1"], 6),
+            (&["1", "-2"], 18),
+            (&["-3", "-1"], 10),
+            (&["-100", "100"], 26),
+            (&["4", "2"], 0),
+        ] {
+            let args = [&["key"][..], range].concat();
+            assert_eq!(
+                Response::Integer(count),
+                engine.execute(command("BITCOUNT", &args)),
+                "{:?}",
+                range
+            );
+        }
+        assert_eq!(
+            Response::Integer(0),
+            engine.execute(command("BITCOUNT", &["missing", "0", "-1"]))
+        );
+        assert_eq!(
+            Response::Error(MiniRedisError::InvalidArguments {
+                arguments: vec!["key".to_string(), "1".to_string()]
+            }),
+            engine.execute(command("BITCOUNT", &["key", "1"]))
+        );
+    }
+
+    #[test]
+    fn bitpos_finds_the_first_clear_or_set_bit() {
+        let (engine, store) = engine();
+        // 0x00 0x0f 0x7f
+        store.set("key", "\0\u{f}\u{7f}").unwrap();
+
+        for (args, position) in [
+            (&["1"][..], 12),
+            (&["0"], 0),
+            (&["1", "2"], 17),
+            (&["0", "1"], 8),
+            (&["0", "-1"], 16),
+            (&["1", "1", "1"], 12),
+            (&["1", "0", "0"], -1),
+            (&["1", "2", "1"], -1),
+        ] {
+            let args = [&["key"][..], args].concat();
+            assert_eq!(
+                Response::Integer(position),
+                engine.execute(command("BITPOS", &args)),
+                "{:?}",
+                args
+            );
+        }
+        assert_eq!(
+            Response::Integer(0),
+            engine.execute(command("BITPOS", &["missing", "0"]))
+        );
+        assert_eq!(
+            Response::Integer(-1),
+            engine.execute(command("BITPOS", &["missing", "1", "2"]))
+        );
+    }
+
+    #[test]
+    fn bitcount_and_bitpos_look_at_bytes_setbit_filled_with_ones() {
+        let (engine, store) = engine();
+        for offset in 0..16 {
+            assert_eq!(
+                Response::Integer(0),
+                engine.execute(command("SETBIT", &["key", &offset.to_string(), "1"]))
+            );
+        }
+        assert_eq!(Ok(Some(vec![0xff, 0xff])), store.get_bytes("key"));
+
+        for (args, count) in [(&[][..], 16), (&["1", "1"], 8), (&["0", "-1"], 16)] {
+            let args = [&["key"][..], args].concat();
+            assert_eq!(
+                Response::Integer(count),
+                engine.execute(command("BITCOUNT", &args)),
+                "{:?}",
+                args
+            );
+        }
+        for (args, position) in [
+            (&["1"][..], 0),
+            (&["1", "1"], 8),
+            (&["1", "0", "0"], 0),
+            // Without an end, the clear bits right after the value count.
+            (&["0"], 16),
+            (&["0", "1"], 16),
+            // With one, only the bytes of the range do.
+            (&["0", "0", "-1"], -1),
+            (&["0", "1", "1"], -1),
+        ] {
+            let args = [&["key"][..], args].concat();
+            assert_eq!(
+                Response::Integer(position),
+                engine.execute(command("BITPOS", &args)),
+                "{:?}",
+                args
+            );
+        }
+    }
+
+    #[test]
+    fn get_and_getrange_return_the_bytes_setbit_wrote() {
+        let (engine, _store) = engine();
+        engine.execute(command("SETBIT", &["key", "0", "1"]));
+        engine.execute(command("SETBIT", &["key", "15", "1"]));
+
+        assert_eq!(
+            Response::BulkBytes(vec![0x80, 0x01]),
+            engine.execute(command("GET", &["key"]))
+        );
+        assert_eq!(
+            Response::BulkBytes(vec![0x80, 0x01]),
+            engine.execute(command("GETSET", &["key", "a"]))
+        );
+        engine.execute(command("SETBIT", &["key", "8", "1"]));
+        assert_eq!(
+            Response::BulkBytes(vec![0x80]),
+            engine.execute(command("GETRANGE", &["key", "1", "1"]))
+        );
+        assert_eq!(
+            Response::Bulk("a".to_string()),
+            engine.execute(command("GETRANGE", &["key", "0", "0"]))
+        );
+    }
+
+    #[test]
+    fn setrange_rejects_bad_offsets() {
+        let (engine, store) = engine();
+        store.set("key", "value").unwrap();
+
+        assert_eq!(
+            Response::Error(MiniRedisError::InvalidArguments {
+                arguments: vec!["key".to_string(), "-1".to_string(), "x".to_string()]
+            }),
+            engine.execute(command("SETRANGE", &["key", "-1", "x"]))
+        );
+        assert_eq!(
+            Response::Error(MiniRedisError::NotAnInteger),
+            engine.execute(command("SETRANGE", &["key", "one", "x"]))
+        );
+        assert_eq!(
+            Response::Error(MiniRedisError::ValueTooLarge),
+            engine.execute(command("SETRANGE", &["key", "536870912", "x"]))
+        );
+        assert_eq!(Ok(Some("value".to_string())), store.get("key"));
+    }
+
+    #[test]
+    fn rename_overwrites_the_destination() {
+        let (engine, store) = engine();
+        store.set("source", "new").unwrap();
+        store.set("destination", "old").unwrap();
+
+        assert_eq!(
+            Response::ok(),
+            engine.execute(command("RENAME", &["source", "destination"]))
+        );
+        assert_eq!(Ok(None), store.get("source"));
+        assert_eq!(Ok(Some("new".to_string())), store.get("destination"));
+        assert_eq!(
+            Response::ok(),
+            engine.execute(command("RENAME", &["destination", "destination"]))
+        );
+        assert_eq!(Ok(Some("new".to_string())), store.get("destination"));
+    }
+
+    #[test]
+    fn rename_of_a_missing_key_is_an_error() {
+        let (engine, store) = engine();
+        store.set("destination", "old").unwrap();
+
+        assert_eq!(
+            Response::Error(MiniRedisError::NoSuchKey),
+            engine.execute(command("RENAME", &["missing", "destination"]))
+        );
+        assert_eq!(Ok(Some("old".to_string())), store.get("destination"));
+    }
+
+    #[test]
+    fn renamenx_only_moves_onto_a_missing_key() {
+        let (engine, store) = engine();
+        store.set("source", "new").unwrap();
+        store.set("destination", "old").unwrap();
+
+        assert_eq!(
+            Response::Integer(0),
+            engine.execute(command("RENAMENX", &["source", "destination"]))
+        );
+        assert_eq!(Ok(Some("old".to_string())), store.get("destination"));
+        assert_eq!(
+            Response::Integer(1),
+            engine.execute(command("RENAMENX", &["source", "other"]))
+        );
+        assert_eq!(Ok(Some("new".to_string())), store.get("other"));
+        assert_eq!(
+            Response::Error(MiniRedisError::NoSuchKey),
+            engine.execute(command("RENAMENX", &["source", "elsewhere"]))
+        );
+    }
+
+    #[test]
+    fn del_returns_error_with_no_arguments() {
+        let (engine, _) = engine();
+
+        let response = engine.execute(command("DEL", &[]));
+
+        assert_eq!(
+            Response::Error(MiniRedisError::WrongArity {
+                command: "DEL".to_string()
+            }),
+            response
+        );
+    }
+
+    #[test]
+    fn delprefix_removes_matching_keys_and_returns_count() {
+        let (engine, store) = engine();
+        store.set("session:1", "a").unwrap();
+        store.set("session:2", "b").unwrap();
+        store.set("user:1", "c").unwrap();
+
+        let response = engine.execute(command("DELPREFIX", &["session:"]));
+
+        assert_eq!(Response::Integer(2), response);
+        assert_eq!(Some("c".to_string()), store.get("user:1").unwrap());
+    }
+
+    #[test]
+    fn delprefix_returns_error_with_no_arguments() {
+        let (engine, _) = engine();
+
+        let response = engine.execute(command("DELPREFIX", &[]));
+
+        assert_eq!(
+            Response::Error(MiniRedisError::WrongArity {
+                command: "DELPREFIX".to_string()
+            }),
+            response
+        );
+    }
+
+    #[test]
+    fn ping_returns_pong_or_the_message() {
+        let (engine, _) = engine();
+
+        assert_eq!(
+            Response::Simple("PONG".to_string()),
+            engine.execute(command("PING", &[]))
+        );
+        assert_eq!(
+            Response::Bulk("hello".to_string()),
+            engine.execute(command("PING", &["hello"]))
+        );
+    }
+
+    #[test]
+    fn ping_returns_error_with_too_many_arguments() {
+        let (engine, _) = engine();
+
+        let response = engine.execute(command("PING", &["a", "b"]));
+
+        assert_eq!(
+            Response::Error(MiniRedisError::WrongArity {
+                command: "PING".to_string()
+            }),
+            response
+        );
+    }
+
+    #[test]
+    fn info_returns_fields_about_the_server() {
+        let (engine, store) = engine();
+        store.set("a", "1").unwrap();
+
+        assert_eq!(
+            Response::Map(vec![
+                (
+                    "version".to_string(),
+                    Response::Bulk(env!("CARGO_PKG_VERSION").to_string())
+                ),
+                ("keys".to_string(), Response::Integer(1)),
+            ]),
+            engine.execute(command("INFO", &[]))
+        );
+    }
+
+    #[test]
+    fn info_keyspace_reports_the_database_unless_it_is_empty() {
+        let (engine, store) = engine();
+
+        assert_eq!(
+            Response::Map(vec![]),
+            engine.execute(command("INFO", &["keyspace"]))
+        );
+
+        store.set("a", "1").unwrap();
+        store
+            .extend_with_ttl([("b".to_string(), "2".to_string(), Some(Duration::ZERO))])
+            .unwrap();
+
+        assert_eq!(
+            Response::Map(vec![(
+                "db0".to_string(),
+                Response::Bulk("keys=2,expires=1,avg_ttl=0".to_string())
+            )]),
+            engine.execute(command("INFO", &["KEYSPACE"]))
+        );
+        assert_eq!(
+            Response::Error(MiniRedisError::InvalidArguments {
+                arguments: vec!["memory".to_string()]
+            }),
+            engine.execute(command("INFO", &["memory"]))
+        );
+    }
+
+    /// Gets the counters of `INFO stats` by name.
+    fn info_stats(engine: &Engine) -> Vec<(String, i64)> {
+        let Response::Map(fields) = engine.execute(command("INFO", &["stats"])) else {
+            panic!("INFO stats did not return a map");
+        };
+        fields
+            .into_iter()
+            .map(|(name, value)| match value {
+                Response::Integer(value) => (name, value),
+                value => panic!("{} is not a counter: {:?}", name, value),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn config_resetstat_zeroes_the_counters_of_info_stats() {
+        let (engine, store) = engine();
+        store.set("a", "1").unwrap();
+        engine.execute(command("GET", &["a"]));
+        engine.execute(command("GET", &["b"]));
+        engine.record_connection();
+
+        assert_eq!(
+            vec![
+                ("total_connections_received".to_string(), 1),
+                ("total_commands_processed".to_string(), 3),
+                ("keyspace_hits".to_string(), 1),
+                ("keyspace_misses".to_string(), 1),
+                ("expired_keys".to_string(), 0),
+                ("evicted_keys".to_string(), 0),
+                ("client_output_buffer_limit_disconnections".to_string(), 0),
+            ],
+            info_stats(&engine)
+        );
+
+        assert_eq!(
+            Response::ok(),
+            engine.execute(command("CONFIG", &["resetstat"]))
+        );
+        assert_eq!(ServerStats::default(), engine.stats());
+        assert_eq!(
+            vec![
+                ("total_connections_received".to_string(), 0),
+                ("total_commands_processed".to_string(), 1),
+                ("keyspace_hits".to_string(), 0),
+                ("keyspace_misses".to_string(), 0),
+                ("expired_keys".to_string(), 0),
+                ("evicted_keys".to_string(), 0),
+                ("client_output_buffer_limit_disconnections".to_string(), 0),
+            ],
+            info_stats(&engine)
+        );
+    }
+
+    #[test]
+    fn config_resetstat_leaves_gauges_alone() {
+        let (engine, store) = engine();
+        store.set("a", "1").unwrap();
+
+        engine.execute(command("CONFIG", &["RESETSTAT"]));
+        engine.execute(command("GET", &["a"]));
+
+        assert_eq!(1, store.stats().hits);
+        assert_eq!(Ok(1), store.len());
+        assert_eq!(
+            Response::Map(vec![(
+                "db0".to_string(),
+                Response::Bulk("keys=1,expires=0,avg_ttl=0".to_string())
+            )]),
+            engine.execute(command("INFO", &["keyspace"]))
+        );
+        assert_eq!(
+            Response::Error(MiniRedisError::InvalidArguments {
+                arguments: vec!["SET".to_string()]
+            }),
+            engine.execute(command("CONFIG", &["SET"]))
+        );
+    }
+
+    #[test]
+    fn hello_lists_the_protocols_of_the_server() {
+        let (engine, _) = engine();
+
+        let Response::Map(fields) = engine.execute(command("HELLO", &[])) else {
+            panic!("HELLO did not return a map");
+        };
+
+        assert_eq!(
+            Some(&Response::Array(vec![
+                Response::Bulk("inline".to_string()),
+                Response::Bulk("resp".to_string()),
+            ])),
+            fields
+                .iter()
+                .find(|(field, _)| field == "protocols")
+                .map(|(_, value)| value)
+        );
+    }
+
+    #[test]
+    fn strlen_returns_length_of_value_in_bytes() {
+        let (engine, store) = engine();
+        store.set("name", "zoë").unwrap();
+
+        assert_eq!(
+            Response::Integer(4),
+            engine.execute(command("STRLEN", &["name"]))
+        );
+        assert_eq!(
+            Response::Integer(0),
+            engine.execute(command("STRLEN", &["missing"]))
+        );
+    }
+
+    #[test]
+    fn randomkey_returns_existing_keys_or_nil() {
+        let (engine, store) = engine();
+        assert_eq!(Response::Nil, engine.execute(command("RANDOMKEY", &[])));
+        for i in 0..10 {
+            store.set(&format!("key:{}", i), "value").unwrap();
+        }
+
+        let mut seen = std::collections::HashSet::new();
+        for _ in 0..200 {
+            let Response::Bulk(key) = engine.execute(command("RANDOMKEY", &[])) else {
+                panic!("RANDOMKEY did not return a key");
+            };
+            assert_eq!(Ok(true), store.exists(&key), "{}", key);
+            seen.insert(key);
+        }
+        assert!(seen.len() > 1, "{:?}", seen);
+    }
+
+    #[test]
+    fn scan_visits_every_key_once_and_filters_by_pattern() {
+        let (engine, store) = engine();
+        for i in 0..25 {
+            store.set(&format!("key:{}", i), "value").unwrap();
+        }
+        store.set("other", "value").unwrap();
+
+        let mut keys = Vec::new();
+        let mut cursor = "0".to_string();
+        loop {
+            let response =
+                engine.execute(command("SCAN", &[&cursor, "MATCH", "key:*", "COUNT", "4"]));
+            let Response::Array(reply) = response else {
+                panic!("SCAN did not return an array: {:?}", response);
+            };
+            let [Response::Bulk(next), Response::Array(batch)] = reply.as_slice() else {
+                panic!("SCAN returned an unexpected reply: {:?}", reply);
+            };
+            keys.extend(batch.iter().map(|key| key.to_string()));
+            if next == "0" {
+                break;
+            }
+            cursor = next.clone();
+        }
+        keys.sort();
+
+        let mut expected = (0..25)
+            .map(|i| format!("key:{}", i))
+            .collect::<Vec<String>>();
+        expected.sort();
+        assert_eq!(expected, keys);
+    }
+
+    #[test]
+    fn scan_filters_by_type_and_still_moves_the_cursor() {
+        let (engine, store) = engine();
+        for i in 0..20 {
+            store.set(&format!("key:{}", i), "value").unwrap();
+        }
+        let scan = |kind: &str| engine.execute(command("SCAN", &["0", "COUNT", "5", "TYPE", kind]));
+
+        let Response::Array(reply) = scan("list") else {
+            panic!("SCAN did not return an array");
+        };
+        assert_ne!(Response::Bulk("0".to_string()), reply[0]);
+        assert_eq!(Response::Array(Vec::new()), reply[1]);
+
+        let Response::Array(reply) = scan("STRING") else {
+            panic!("SCAN did not return an array");
+        };
+        assert!(matches!(&reply[1], Response::Array(batch) if batch.len() >= 5));
+    }
+
+    #[test]
+    fn scan_rejects_invalid_cursor_and_options() {
+        let (engine, _) = engine();
+
+        assert_eq!(
+            Response::Error(MiniRedisError::NotAnInteger),
+            engine.execute(command("SCAN", &["x"]))
+        );
+        assert_eq!(
+            Response::Error(MiniRedisError::NotAnInteger),
+            engine.execute(command("SCAN", &["0", "COUNT", "many"]))
+        );
+        for args in [
+            &["0", "COUNT", "0"][..],
+            &["0", "COUNT", "-1"][..],
+            &["0", "MATCH"][..],
+            &["0", "LIMIT", "1"][..],
+            &["0", "TYPE", "widget"][..],
+        ] {
+            assert_eq!(
+                Response::Error(MiniRedisError::InvalidArguments {
+                    arguments: args.iter().map(|arg| arg.to_string()).collect()
+                }),
+                engine.execute(command("SCAN", args))
+            );
+        }
+    }
+
+    #[test]
+    fn wait_times_out_without_replicas_to_acknowledge() {
+        let (engine, _) = engine();
+
+        assert_eq!(
+            Response::Integer(0),
+            engine.execute(command("WAIT", &["0", "0"]))
+        );
+        let start = std::time::Instant::now();
+        assert_eq!(
+            Response::Integer(0),
+            engine.execute(command("WAIT", &["1", "50"]))
+        );
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(
+            Response::Error(MiniRedisError::NotAnInteger),
+            engine.execute(command("WAIT", &["one", "100"]))
+        );
+        assert_eq!(
+            Response::Error(MiniRedisError::InvalidArguments {
+                arguments: vec!["1".to_string(), "-1".to_string()]
+            }),
+            engine.execute(command("WAIT", &["1", "-1"]))
+        );
+    }
+
+    #[test]
+    fn lcs_returns_the_subsequence_its_length_or_its_runs() {
+        let (engine, store) = engine();
+        store.set("a", "ohmytext").unwrap();
+        store.set("b", "mynewtext").unwrap();
+        let range = |start: i64, end: i64| {
+            Response::Array(vec![Response::Integer(start), Response::Integer(end)])
+        };
+
+        assert_eq!(
+            Response::Bulk("mytext".to_string()),
+            engine.execute(command("LCS", &["a", "b"]))
+        );
+        assert_eq!(
+            Response::Integer(6),
+            engine.execute(command("LCS", &["a", "b", "len"]))
+        );
+        assert_eq!(
+            Response::Bulk(String::new()),
+            engine.execute(command("LCS", &["a", "missing"]))
+        );
+        assert_eq!(
+            Response::Map(vec![
+                (
+                    "matches".to_string(),
+                    Response::Array(vec![
+                        Response::Array(vec![range(4, 7), range(5, 8)]),
+                        Response::Array(vec![range(2, 3), range(0, 1)]),
+                    ])
+                ),
+                ("len".to_string(), Response::Integer(6)),
+            ]),
+            engine.execute(command("LCS", &["a", "b", "IDX"]))
+        );
+        assert_eq!(
+            Response::Map(vec![
+                (
+                    "matches".to_string(),
+                    Response::Array(vec![Response::Array(vec![
+                        range(4, 7),
+                        range(5, 8),
+                        Response::Integer(4)
+                    ])])
+                ),
+                ("len".to_string(), Response::Integer(6)),
+            ]),
+            engine.execute(command(
+                "LCS",
+                &["a", "b", "IDX", "MINMATCHLEN", "3", "WITHMATCHLEN"]
+            ))
+        );
+    }
+
+    #[test]
+    fn lcs_rejects_conflicting_options_and_values_past_the_limit() {
+        let (engine, store) = engine();
+        store.set("a", "ohmytext").unwrap();
+        store.set("b", "mynewtext").unwrap();
+
+        for args in [
+            &["a", "b", "LEN", "IDX"][..],
+            &["a", "b", "MINMATCHLEN"][..],
+        ] {
+            assert_eq!(
+                Response::Error(MiniRedisError::InvalidArguments {
+                    arguments: args[2..].iter().map(|arg| arg.to_string()).collect()
+                }),
+                engine.execute(command("LCS", args))
+            );
+        }
+        assert_eq!(
+            Response::Error(MiniRedisError::ComparisonTooLarge {
+                cells: 72,
+                limit: 71
+            }),
+            engine.lcs_limit(71).execute(command("LCS", &["a", "b"]))
+        );
+    }
+
+    #[test]
+    fn sort_treats_strings_as_the_wrong_type_and_missing_keys_as_empty() {
+        let (engine, store) = engine();
+        store.set("name", "alice").unwrap();
+        store.set("out", "old").unwrap();
+
+        assert_eq!(
+            Response::Error(MiniRedisError::WrongType),
+            engine.execute(command("SORT", &["name", "ALPHA"]))
+        );
+        assert_eq!(
+            Response::Array(vec![]),
+            engine.execute(command("SORT", &["missing", "LIMIT", "0", "10", "DESC"]))
+        );
+        assert_eq!(
+            Response::Integer(0),
+            engine.execute(command("SORT", &["missing", "STORE", "out"]))
+        );
+        assert_eq!(None, store.get("out").unwrap());
+        assert_eq!(
+            Response::Error(MiniRedisError::InvalidArguments {
+                arguments: vec!["BY".to_string(), "weight_*".to_string()]
+            }),
+            engine.execute(command("SORT", &["missing", "BY", "weight_*"]))
+        );
+    }
+
+    #[test]
+    fn client_pause_shows_in_info_until_unpaused() {
+        let (engine, _) = engine();
+        let info = |paused: &str| {
+            let Response::Map(fields) = engine.execute(command("INFO", &["clients"])) else {
+                panic!("INFO clients is not a map");
+            };
+            assert_eq!(
+                ("paused".to_string(), Response::Bulk(paused.to_string())),
+                fields[0]
+            );
+        };
+
+        info("no");
+        assert_eq!(
+            Response::ok(),
+            engine.execute(command("CLIENT", &["PAUSE", "60000", "write"]))
+        );
+        info("write");
+        assert_eq!(
+            Response::Bulk("alice".to_string()),
+            engine.execute(command("PING", &["alice"]))
+        );
+        assert_eq!(
+            Response::ok(),
+            engine.execute(command("CLIENT", &["UNPAUSE"]))
+        );
+        info("no");
+        for args in [&["PAUSE", "-1"][..], &["UNPAUSE", "10"][..], &["PAUSE"][..]] {
+            assert_eq!(
+                Response::Error(MiniRedisError::InvalidArguments {
+                    arguments: args.iter().map(|arg| arg.to_string()).collect()
+                }),
+                engine.execute(command("CLIENT", args))
+            );
+        }
+    }
+
+    #[test]
+    fn cluster_keyslot_returns_the_hash_slot_of_a_key() {
+        let (engine, _) = engine();
+
+        assert_eq!(
+            Response::Integer(12182),
+            engine.execute(command("CLUSTER", &["keyslot", "foo"]))
+        );
+        assert_eq!(
+            Response::Error(MiniRedisError::InvalidArguments {
+                arguments: vec!["NODES".to_string(), "foo".to_string()]
+            }),
+            engine.execute(command("CLUSTER", &["NODES", "foo"]))
+        );
+    }
+
+    #[test]
+    fn help_lists_every_command_or_explains_one() {
+        let (engine, _) = engine();
+
+        let Response::Array(lines) = engine.execute(command("HELP", &[])) else {
+            panic!("HELP did not return an array");
+        };
+        assert_eq!(COMMANDS.len() + 2, lines.len());
+        assert_eq!(
+            Response::Simple(
+                "SET <KEY> <VALUE> [NX|XX] [EX <SECONDS>|PX <MILLISECONDS>|KEEPTTL] - Set the value of a key"
+                    .to_string()
+            ),
+            lines[2]
+        );
+        assert_eq!(
+            Response::Array(vec![
+                Response::Simple("GET <KEY>".to_string()),
+                Response::Simple("Get the value of a key.".to_string()),
+                Response::Simple("Since 0.1.0.".to_string()),
+            ]),
+            engine.execute(command("HELP", &["get"]))
+        );
+        assert_eq!(
+            Response::Simple("No help available for BOGUS, try HELP.".to_string()),
+            engine.execute(command("HELP", &["BOGUS"]))
+        );
+    }
+
+    #[test]
+    fn returns_error_for_unknown_command() {
+        let (engine, _) = engine();
+
+        let response = engine.execute(command("UNKNOWN", &["arg"]));
+
+        assert_eq!(
+            Response::Error(MiniRedisError::InvalidCommand {
+                command: "UNKNOWN".to_string()
+            }),
+            response
+        );
+    }
+
+    #[test]
+    fn returns_error_for_extra_arguments() {
+        let (engine, _) = engine();
+
+        for (name, args) in [
+            ("GET", &["testkey", "extra"][..]),
+            ("STRLEN", &["testkey", "extra"][..]),
+        ] {
+            let response = engine.execute(command(name, args));
+
+            assert_eq!(
+                Response::Error(MiniRedisError::WrongArity {
+                    command: name.to_string()
+                }),
+                response
+            );
+        }
+    }
+
+    #[test]
+    fn with_storage_executes_against_any_backend() {
+        let store = KVStore::new();
+        let engine = Engine::with_storage(Arc::new(store.clone()));
+
+        engine.execute(command("SET", &["testkey", "testvalue"]));
+
+        assert_eq!(Some("testvalue".to_string()), store.get("testkey").unwrap());
+    }
+
+    #[test]
+    fn responses_display_as_the_server_writes_them() {
+        let (engine, _) = engine();
+
+        assert_eq!(
+            "OK",
+            engine.execute(command("SET", &["a", "1"])).to_string()
+        );
+        assert_eq!("1", engine.execute(command("GET", &["a"])).to_string());
+        assert_eq!("nil", engine.execute(command("GET", &["b"])).to_string());
+        assert_eq!(
+            "1",
+            engine.execute(command("DELPREFIX", &["a"])).to_string()
+        );
+        assert!(
+            engine
+                .execute(command("UNKNOWN", &[]))
+                .to_string()
+                .starts_with("ERR Invalid command: UNKNOWN.")
+        );
+    }
+}
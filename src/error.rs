@@ -1,50 +1,978 @@
 /// An error that can occur in the MiniRedis library.
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub enum MiniRedisError {
     /// The key value store is locked.
     StoreLocked,
+    /// The key value store is full and its policy is to reject new data.
+    StoreFull,
+    /// The storage backend cannot give keys a time to live.
+    ExpiryNotSupported,
 
     /// The command is invalid.
-    InvalidCommand{command: String},
+    InvalidCommand { command: String },
     /// The arguments are invalid.
-    InvalidArguments{arguments: Vec<String>},
+    InvalidArguments { arguments: Vec<String> },
+    /// The command was given too few or too many arguments.
+    WrongArity { command: String },
+    /// A time to live or timestamp would put the deadline of a key out of the range of the clock.
+    InvalidExpireTime { command: String },
+    /// Options were given together that exclude each other, like `NX` and `GT` of `EXPIRE`.
+    IncompatibleOptions { options: String },
+    /// The options of a command do not fit its syntax, like two times to live for `GETEX`.
+    SyntaxError,
+    /// An argument that must be an integer is not one, or does not fit in 64 bits.
+    NotAnInteger,
+    /// Adding to the integer value of a key would take it out of 64 bits.
+    IncrementOverflow,
+    /// An argument or value that must be a floating point number is not a finite one.
+    NotAFloat,
+    /// Adding to the floating point value of a key would make it NaN or infinite.
+    IncrementNotFinite,
+    /// An element that must be sorted as a number is not one.
+    NotADouble,
+    /// A write would make a value longer than [`crate::storage::MAX_VALUE_LEN`].
+    ValueTooLarge,
+    /// The key holds a kind of value that the command does not work on.
+    WrongType,
+    /// The key the command works on does not exist.
+    NoSuchKey,
+    /// Comparing two values would take more work than the server allows for one command.
+    ComparisonTooLarge { cells: usize, limit: usize },
+    /// The command line has a quote that is never closed.
+    UnterminatedQuote { line: String },
 
     /// The stream is closed.
     StreamClosed,
     /// The stream is not readable.
-    StreamNotReadable,
+    StreamNotReadable { source: std::io::Error },
     /// The stream is not writable.
-    StreamNotWritable,
+    StreamNotWritable { source: std::io::Error },
     /// The stream is not connected.
-    StreamNotConnected{address: String},
+    StreamNotConnected {
+        address: String,
+        source: std::io::Error,
+    },
     /// The stream is not flushed.
-    StreamNotFlushed,
+    StreamNotFlushed { source: std::io::Error },
+    /// The server did not answer in time.
+    Timeout {
+        address: String,
+        timeout: std::time::Duration,
+    },
+    /// None of the addresses of the server accepted the connection.
+    AddressesNotConnected {
+        failures: Vec<(String, MiniRedisError)>,
+    },
+    /// Connecting, or sending a command, kept failing until the retries ran out.
+    RetriesExhausted {
+        attempts: u32,
+        source: Box<MiniRedisError>,
+    },
+    /// A node of a sharded connection failed, so the keys it owns cannot be reached.
+    ShardFailed {
+        address: String,
+        source: Box<MiniRedisError>,
+    },
+    /// Every connection of the pool stayed in use for too long.
+    PoolExhausted {
+        max_size: usize,
+        timeout: std::time::Duration,
+    },
 
     /// The stream is not accepted.
-    AddressNotBound,
+    AddressNotBound { source: std::io::Error },
+    /// A client left more replies unread than the server holds for it.
+    OutputBufferExceeded { pending: usize, limit: usize },
+    /// An IO operation failed, like accepting, reading from, or writing to a connection.
+    Io {
+        operation: &'static str,
+        address: Option<String>,
+        source: std::io::Error,
+    },
+
+    /// The key of the command belongs to a hash slot that another node serves.
+    Moved { slot: u16, address: String },
+
+    /// The server replied to a command with an error.
+    ServerError { message: String },
+    /// The server did not accept the credentials of the client.
+    AuthenticationFailed { message: String },
+
+    /// The file could not be read.
+    FileNotReadable {
+        path: String,
+        source: std::io::Error,
+    },
+    /// The file could not be written.
+    FileNotWritable {
+        path: String,
+        source: std::io::Error,
+    },
+    /// The snapshot file is not valid.
+    SnapshotCorrupted { path: String, reason: String },
+    /// The log file of a file-backed store is not valid.
+    LogCorrupted { path: String, reason: String },
+    /// The JSON dump file is not valid.
+    DumpInvalid { path: String, reason: String },
 }
 
 impl std::fmt::Display for MiniRedisError {
     /// Formats the error as a string.
-    /// 
+    ///
     /// # Arguments
-    /// 
+    ///
     /// * `f` - The formatter to write the error to.
-    /// 
+    ///
     /// # Errors
-    /// 
+    ///
     /// If the error cannot be formatted, it will return an error.
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            MiniRedisError::StoreLocked => write!(f, "Could not access the key value store as it is locked."),
-            MiniRedisError::InvalidCommand{command} => write!(f, "Invalid command: {}. Run 'miniredis-client --help' for more information.", command),
-            MiniRedisError::InvalidArguments{arguments} => write!(f, "Invalid arguments: {:?}. Run 'miniredis-client --help' for more information.", arguments),
-            MiniRedisError::StreamClosed => write!(f, "The stream is closed."),
-            MiniRedisError::StreamNotReadable => write!(f, "Could not read from the stream."),
-            MiniRedisError::StreamNotWritable => write!(f, "Could not write to the stream."),
-            MiniRedisError::StreamNotConnected{address} => write!(f, "Could not connect to the stream at {}.", address),
-            MiniRedisError::AddressNotBound => write!(f, "Could not bind to the address."),
-            MiniRedisError::StreamNotFlushed => write!(f, "Could not flush the stream."),
-        }
-    }
-}
\ No newline at end of file
+            MiniRedisError::StoreLocked => {
+                write!(f, "Could not access the key value store as it is locked.")
+            }
+            MiniRedisError::StoreFull => write!(f, "The key value store is full."),
+            MiniRedisError::ExpiryNotSupported => {
+                write!(f, "The storage backend does not support keys that expire.")
+            }
+            MiniRedisError::InvalidCommand { command } => write!(
+                f,
+                "Invalid command: {}. Run 'miniredis-client --help' for more information.",
+                command
+            ),
+            MiniRedisError::InvalidArguments { arguments } => write!(
+                f,
+                "Invalid arguments: {:?}. Run 'miniredis-client --help' for more information.",
+                arguments
+            ),
+            MiniRedisError::WrongArity { command } => write!(
+                f,
+                "wrong number of arguments for '{}' command",
+                command.to_lowercase()
+            ),
+            MiniRedisError::InvalidExpireTime { command } => write!(
+                f,
+                "invalid expire time in '{}' command",
+                command.to_lowercase()
+            ),
+            MiniRedisError::IncompatibleOptions { options } => {
+                write!(f, "{} options at the same time are not compatible", options)
+            }
+            MiniRedisError::SyntaxError => write!(f, "syntax error"),
+            MiniRedisError::NotAnInteger => write!(f, "value is not an integer or out of range"),
+            MiniRedisError::IncrementOverflow => write!(f, "increment or decrement would overflow"),
+            MiniRedisError::NotAFloat => write!(f, "value is not a valid float"),
+            MiniRedisError::IncrementNotFinite => {
+                write!(f, "increment would produce NaN or Infinity")
+            }
+            MiniRedisError::NotADouble => {
+                write!(f, "one or more elements can't be converted to double")
+            }
+            MiniRedisError::ValueTooLarge => {
+                write!(f, "string exceeds maximum allowed size (512MB)")
+            }
+            MiniRedisError::WrongType => {
+                write!(f, "Operation against a key holding the wrong kind of value")
+            }
+            MiniRedisError::NoSuchKey => write!(f, "no such key"),
+            MiniRedisError::ComparisonTooLarge { cells, limit } => write!(
+                f,
+                "Comparing the values takes {} cells, more than the limit of {}. Compare shorter values, or raise the limit with --lcs-limit.",
+                cells, limit
+            ),
+            MiniRedisError::UnterminatedQuote { line } => write!(
+                f,
+                "Unterminated quote in: {}. Close it with another \", or write a literal quote inside quotes as \\\".",
+                line
+            ),
+            MiniRedisError::StreamClosed => write!(
+                f,
+                "The connection is closed. The server may have stopped, or closed it."
+            ),
+            MiniRedisError::StreamNotReadable { source } => {
+                write!(f, "Could not read from the stream: {}.", source)
+            }
+            MiniRedisError::StreamNotWritable { source } => {
+                write!(f, "Could not write to the stream: {}.", source)
+            }
+            MiniRedisError::StreamNotConnected { address, source } => write!(
+                f,
+                "Could not connect to the stream at {}: {}.",
+                address, source
+            ),
+            MiniRedisError::AddressNotBound { source } => {
+                write!(f, "Could not bind to the address: {}.", source)
+            }
+            MiniRedisError::OutputBufferExceeded { pending, limit } => write!(
+                f,
+                "The client left {} bytes of replies unread, over the limit of {} bytes.",
+                pending, limit
+            ),
+            MiniRedisError::StreamNotFlushed { source } => {
+                write!(f, "Could not flush the stream: {}.", source)
+            }
+            MiniRedisError::Io {
+                operation,
+                address,
+                source,
+            } => write!(
+                f,
+                "Could not {}{}: {} ({:?}).",
+                operation,
+                address
+                    .as_ref()
+                    .map(|address| format!(" for {}", address))
+                    .unwrap_or_default(),
+                source,
+                source.kind()
+            ),
+            MiniRedisError::Timeout { address, timeout } => write!(
+                f,
+                "Timed out after {:?} waiting for the server at {}. Check that it is running, or raise the timeout.",
+                timeout, address
+            ),
+            MiniRedisError::AddressesNotConnected { failures } => write!(
+                f,
+                "Could not connect to any of {} addresses. {}",
+                failures.len(),
+                failures
+                    .iter()
+                    .map(|(address, e)| format!("{}: {}", address, e))
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            ),
+            MiniRedisError::RetriesExhausted { attempts, source } => {
+                write!(f, "Gave up after {} attempts. {}", attempts, source)
+            }
+            MiniRedisError::ShardFailed { address, source } => {
+                write!(f, "The shard at {} failed. {}", address, source)
+            }
+            MiniRedisError::PoolExhausted { max_size, timeout } => write!(
+                f,
+                "All {} connections of the pool stayed in use for {:?}. Return connections sooner, or raise the size of the pool.",
+                max_size, timeout
+            ),
+            // The reply after the code is read by clients, so it has the exact form Redis uses.
+            MiniRedisError::Moved { slot, address } => write!(f, "{} {}", slot, address),
+            MiniRedisError::ServerError { message } => {
+                write!(f, "The server replied with an error: {}", message)
+            }
+            MiniRedisError::AuthenticationFailed { message } => write!(
+                f,
+                "Authentication failed: {}. Check the password given with --pass or MINIREDIS_PASSWORD.",
+                message
+            ),
+            MiniRedisError::FileNotReadable { path, source } => {
+                write!(f, "Could not read the file at {}: {}.", path, source)
+            }
+            MiniRedisError::FileNotWritable { path, source } => {
+                write!(f, "Could not write the file at {}: {}.", path, source)
+            }
+            MiniRedisError::SnapshotCorrupted { path, reason } => {
+                write!(f, "The snapshot at {} is corrupted: {}.", path, reason)
+            }
+            MiniRedisError::LogCorrupted { path, reason } => {
+                write!(f, "The log at {} is corrupted: {}.", path, reason)
+            }
+            MiniRedisError::DumpInvalid { path, reason } => {
+                write!(f, "The dump at {} is not valid: {}.", path, reason)
+            }
+        }
+    }
+}
+
+impl PartialEq for MiniRedisError {
+    /// Compares two errors.
+    ///
+    /// `std::io::Error` does not implement `PartialEq`,
+    /// so errors carrying one are compared by its kind.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The error to compare with.
+    fn eq(&self, other: &Self) -> bool {
+        use MiniRedisError::*;
+
+        // Every variant with fields must be listed here,
+        // the fallback only holds for variants without fields.
+        match (self, other) {
+            (InvalidCommand { command: a }, InvalidCommand { command: b }) => a == b,
+            (InvalidArguments { arguments: a }, InvalidArguments { arguments: b }) => a == b,
+            (WrongArity { command: a }, WrongArity { command: b }) => a == b,
+            (InvalidExpireTime { command: a }, InvalidExpireTime { command: b }) => a == b,
+            (IncompatibleOptions { options: a }, IncompatibleOptions { options: b }) => a == b,
+            (
+                ComparisonTooLarge { cells: a, limit: x },
+                ComparisonTooLarge { cells: b, limit: y },
+            ) => a == b && x == y,
+            (UnterminatedQuote { line: a }, UnterminatedQuote { line: b }) => a == b,
+            (StreamNotReadable { source: x }, StreamNotReadable { source: y }) => {
+                x.kind() == y.kind()
+            }
+            (StreamNotWritable { source: x }, StreamNotWritable { source: y }) => {
+                x.kind() == y.kind()
+            }
+            (
+                StreamNotConnected {
+                    address: a,
+                    source: x,
+                },
+                StreamNotConnected {
+                    address: b,
+                    source: y,
+                },
+            ) => a == b && x.kind() == y.kind(),
+            (StreamNotFlushed { source: x }, StreamNotFlushed { source: y }) => {
+                x.kind() == y.kind()
+            }
+            (AddressNotBound { source: x }, AddressNotBound { source: y }) => x.kind() == y.kind(),
+            (
+                Io {
+                    operation: a,
+                    address: b,
+                    source: x,
+                },
+                Io {
+                    operation: c,
+                    address: d,
+                    source: y,
+                },
+            ) => a == c && b == d && x.kind() == y.kind(),
+            (
+                Moved {
+                    slot: a,
+                    address: x,
+                },
+                Moved {
+                    slot: b,
+                    address: y,
+                },
+            ) => a == b && x == y,
+            (
+                OutputBufferExceeded {
+                    pending: a,
+                    limit: x,
+                },
+                OutputBufferExceeded {
+                    pending: b,
+                    limit: y,
+                },
+            ) => a == b && x == y,
+            (ServerError { message: a }, ServerError { message: b }) => a == b,
+            (AuthenticationFailed { message: a }, AuthenticationFailed { message: b }) => a == b,
+            (
+                Timeout {
+                    address: a,
+                    timeout: x,
+                },
+                Timeout {
+                    address: b,
+                    timeout: y,
+                },
+            ) => a == b && x == y,
+            (AddressesNotConnected { failures: a }, AddressesNotConnected { failures: b }) => {
+                a == b
+            }
+            (
+                RetriesExhausted {
+                    attempts: a,
+                    source: x,
+                },
+                RetriesExhausted {
+                    attempts: b,
+                    source: y,
+                },
+            ) => a == b && x == y,
+            (
+                ShardFailed {
+                    address: a,
+                    source: x,
+                },
+                ShardFailed {
+                    address: b,
+                    source: y,
+                },
+            ) => a == b && x == y,
+            (
+                PoolExhausted {
+                    max_size: a,
+                    timeout: x,
+                },
+                PoolExhausted {
+                    max_size: b,
+                    timeout: y,
+                },
+            ) => a == b && x == y,
+            (FileNotReadable { path: a, source: x }, FileNotReadable { path: b, source: y }) => {
+                a == b && x.kind() == y.kind()
+            }
+            (FileNotWritable { path: a, source: x }, FileNotWritable { path: b, source: y }) => {
+                a == b && x.kind() == y.kind()
+            }
+            (
+                SnapshotCorrupted { path: a, reason: x },
+                SnapshotCorrupted { path: b, reason: y },
+            ) => a == b && x == y,
+            (LogCorrupted { path: a, reason: x }, LogCorrupted { path: b, reason: y }) => {
+                a == b && x == y
+            }
+            (DumpInvalid { path: a, reason: x }, DumpInvalid { path: b, reason: y }) => {
+                a == b && x == y
+            }
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other),
+        }
+    }
+}
+impl std::error::Error for MiniRedisError {
+    /// Gets the error that caused this one.
+    ///
+    /// # Returns
+    ///
+    /// The IO error of a failed read, write, or connection, the last error of retries
+    /// that ran out, the error of a failed shard, or None if the error has no cause.
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MiniRedisError::StreamNotReadable { source }
+            | MiniRedisError::StreamNotWritable { source }
+            | MiniRedisError::StreamNotConnected { source, .. }
+            | MiniRedisError::StreamNotFlushed { source }
+            | MiniRedisError::AddressNotBound { source }
+            | MiniRedisError::Io { source, .. }
+            | MiniRedisError::FileNotReadable { source, .. }
+            | MiniRedisError::FileNotWritable { source, .. } => Some(source),
+            MiniRedisError::RetriesExhausted { source, .. }
+            | MiniRedisError::ShardFailed { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for MiniRedisError {
+    /// Wraps an IO error that no more specific variant describes.
+    ///
+    /// An unexpected end of the stream is [`MiniRedisError::StreamClosed`],
+    /// and any other error is [`MiniRedisError::Io`].
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The IO error.
+    fn from(source: std::io::Error) -> Self {
+        match source.kind() {
+            std::io::ErrorKind::UnexpectedEof => MiniRedisError::StreamClosed,
+            _ => MiniRedisError::Io {
+                operation: "complete an IO operation",
+                address: None,
+                source,
+            },
+        }
+    }
+}
+
+/// The kind of thing that went wrong, for callers that handle errors by group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// A command, its arguments, or a reply was not valid.
+    Protocol,
+    /// The key value store, or a file behind it, could not take or give the data.
+    Storage,
+    /// A connection, or another IO operation, failed.
+    Io,
+    /// The server did not accept the credentials of the client.
+    Auth,
+}
+
+impl MiniRedisError {
+    /// Gets the code of the error, the word an error reply starts with.
+    ///
+    /// Codes are stable, so callers can branch on them instead of on messages,
+    /// and the client turns the code of a reply back into the error it stands for.
+    ///
+    /// # Returns
+    ///
+    /// The code of the error, like `ERR` for a generic error or `OOM` for a full store.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::error::MiniRedisError;
+    ///
+    /// assert_eq!("OOM", MiniRedisError::StoreFull.code());
+    /// assert_eq!("ERR", MiniRedisError::InvalidArguments { arguments: vec![] }.code());
+    /// ```
+    pub fn code(&self) -> &'static str {
+        // No fallback, so every new variant has to pick its code.
+        match self {
+            MiniRedisError::StoreLocked => "BUSY",
+            MiniRedisError::StoreFull => "OOM",
+            MiniRedisError::ExpiryNotSupported => "ERR",
+            MiniRedisError::InvalidCommand { .. } => "ERR",
+            MiniRedisError::InvalidArguments { .. } => "ERR",
+            MiniRedisError::WrongArity { .. } => "ERR",
+            MiniRedisError::InvalidExpireTime { .. } => "ERR",
+            MiniRedisError::IncompatibleOptions { .. } => "ERR",
+            MiniRedisError::SyntaxError => "ERR",
+            MiniRedisError::NotAnInteger => "ERR",
+            MiniRedisError::IncrementOverflow => "ERR",
+            MiniRedisError::NotAFloat => "ERR",
+            MiniRedisError::IncrementNotFinite => "ERR",
+            MiniRedisError::NotADouble => "ERR",
+            MiniRedisError::ValueTooLarge => "ERR",
+            MiniRedisError::NoSuchKey => "ERR",
+            MiniRedisError::WrongType => "WRONGTYPE",
+            MiniRedisError::ComparisonTooLarge { .. } => "ERR",
+            MiniRedisError::UnterminatedQuote { .. } => "ERR",
+            MiniRedisError::StreamClosed => "IOERR",
+            MiniRedisError::StreamNotReadable { .. } => "IOERR",
+            MiniRedisError::StreamNotWritable { .. } => "IOERR",
+            MiniRedisError::StreamNotConnected { .. } => "IOERR",
+            MiniRedisError::StreamNotFlushed { .. } => "IOERR",
+            MiniRedisError::Timeout { .. } => "TIMEOUT",
+            MiniRedisError::AddressesNotConnected { .. } => "IOERR",
+            MiniRedisError::RetriesExhausted { source, .. } => source.code(),
+            MiniRedisError::ShardFailed { source, .. } => source.code(),
+            MiniRedisError::PoolExhausted { .. } => "TIMEOUT",
+            MiniRedisError::AddressNotBound { .. } => "IOERR",
+            MiniRedisError::OutputBufferExceeded { .. } => "IOERR",
+            MiniRedisError::Io { .. } => "IOERR",
+            MiniRedisError::Moved { .. } => "MOVED",
+            MiniRedisError::ServerError { .. } => "ERR",
+            MiniRedisError::AuthenticationFailed { .. } => "NOAUTH",
+            MiniRedisError::FileNotReadable { .. } => "IOERR",
+            MiniRedisError::FileNotWritable { .. } => "IOERR",
+            MiniRedisError::SnapshotCorrupted { .. } => "CORRUPT",
+            MiniRedisError::LogCorrupted { .. } => "CORRUPT",
+            MiniRedisError::DumpInvalid { .. } => "CORRUPT",
+        }
+    }
+
+    /// Gets the category of the error.
+    ///
+    /// # Returns
+    ///
+    /// The category of the error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::error::{ErrorCategory, MiniRedisError};
+    ///
+    /// assert_eq!(ErrorCategory::Storage, MiniRedisError::StoreFull.category());
+    /// assert_eq!(ErrorCategory::Io, MiniRedisError::StreamClosed.category());
+    /// ```
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            MiniRedisError::StoreLocked
+            | MiniRedisError::StoreFull
+            | MiniRedisError::ExpiryNotSupported
+            | MiniRedisError::FileNotReadable { .. }
+            | MiniRedisError::FileNotWritable { .. }
+            | MiniRedisError::SnapshotCorrupted { .. }
+            | MiniRedisError::LogCorrupted { .. }
+            | MiniRedisError::DumpInvalid { .. } => ErrorCategory::Storage,
+            MiniRedisError::InvalidCommand { .. }
+            | MiniRedisError::InvalidArguments { .. }
+            | MiniRedisError::WrongArity { .. }
+            | MiniRedisError::InvalidExpireTime { .. }
+            | MiniRedisError::IncompatibleOptions { .. }
+            | MiniRedisError::SyntaxError
+            | MiniRedisError::NotAnInteger
+            | MiniRedisError::IncrementOverflow
+            | MiniRedisError::NotAFloat
+            | MiniRedisError::IncrementNotFinite
+            | MiniRedisError::NotADouble
+            | MiniRedisError::ValueTooLarge
+            | MiniRedisError::WrongType
+            | MiniRedisError::NoSuchKey
+            | MiniRedisError::ComparisonTooLarge { .. }
+            | MiniRedisError::UnterminatedQuote { .. }
+            | MiniRedisError::Moved { .. }
+            | MiniRedisError::ServerError { .. } => ErrorCategory::Protocol,
+            MiniRedisError::StreamClosed
+            | MiniRedisError::StreamNotReadable { .. }
+            | MiniRedisError::StreamNotWritable { .. }
+            | MiniRedisError::StreamNotConnected { .. }
+            | MiniRedisError::StreamNotFlushed { .. }
+            | MiniRedisError::Timeout { .. }
+            | MiniRedisError::AddressesNotConnected { .. }
+            | MiniRedisError::PoolExhausted { .. }
+            | MiniRedisError::AddressNotBound { .. }
+            | MiniRedisError::OutputBufferExceeded { .. }
+            | MiniRedisError::Io { .. } => ErrorCategory::Io,
+            MiniRedisError::RetriesExhausted { source, .. }
+            | MiniRedisError::ShardFailed { source, .. } => source.category(),
+            MiniRedisError::AuthenticationFailed { .. } => ErrorCategory::Auth,
+        }
+    }
+
+    /// Gets the kind of the IO error behind this error, for comparing errors in tests
+    /// and telling apart, say, a refused connection from a reset one.
+    ///
+    /// # Returns
+    ///
+    /// The kind of the IO error, or None if the error was not caused by one.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::error::MiniRedisError;
+    /// use std::io::ErrorKind;
+    ///
+    /// let error = MiniRedisError::StreamNotConnected {
+    ///     address: "127.0.0.1:6379".to_string(),
+    ///     source: ErrorKind::ConnectionRefused.into(),
+    /// };
+    ///
+    /// assert_eq!(Some(ErrorKind::ConnectionRefused), error.kind());
+    /// assert_eq!(None, MiniRedisError::StoreFull.kind());
+    /// ```
+    pub fn kind(&self) -> Option<std::io::ErrorKind> {
+        std::error::Error::source(self)
+            .and_then(|source| source.downcast_ref::<std::io::Error>())
+            .map(std::io::Error::kind)
+    }
+
+    /// Checks whether the error is the other side going away,
+    /// which ends a connection as a matter of course rather than as a failure.
+    ///
+    /// # Returns
+    ///
+    /// True if the connection was closed, reset, or aborted, or its pipe broke.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::error::MiniRedisError;
+    /// use std::io::ErrorKind;
+    ///
+    /// let reset = MiniRedisError::Io {
+    ///     operation: "write the reply",
+    ///     address: Some("127.0.0.1:50000".to_string()),
+    ///     source: ErrorKind::ConnectionReset.into(),
+    /// };
+    ///
+    /// assert!(reset.is_disconnect());
+    /// assert!(MiniRedisError::StreamClosed.is_disconnect());
+    /// assert!(!MiniRedisError::StoreFull.is_disconnect());
+    /// ```
+    pub fn is_disconnect(&self) -> bool {
+        matches!(self, MiniRedisError::StreamClosed)
+            || matches!(
+                self.kind(),
+                Some(
+                    std::io::ErrorKind::ConnectionReset
+                        | std::io::ErrorKind::ConnectionAborted
+                        | std::io::ErrorKind::BrokenPipe
+                        | std::io::ErrorKind::UnexpectedEof
+                )
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lists one error of every variant with its code.
+    ///
+    /// The match has no fallback, so a new variant does not compile until it is listed here.
+    fn every_error() -> Vec<(MiniRedisError, &'static str)> {
+        let io = || std::io::Error::from(std::io::ErrorKind::Other);
+        let errors = vec![
+            (MiniRedisError::StoreLocked, "BUSY"),
+            (MiniRedisError::StoreFull, "OOM"),
+            (MiniRedisError::ExpiryNotSupported, "ERR"),
+            (
+                MiniRedisError::InvalidCommand {
+                    command: "X".to_string(),
+                },
+                "ERR",
+            ),
+            (
+                MiniRedisError::InvalidArguments { arguments: vec![] },
+                "ERR",
+            ),
+            (
+                MiniRedisError::WrongArity {
+                    command: "GET".to_string(),
+                },
+                "ERR",
+            ),
+            (
+                MiniRedisError::InvalidExpireTime {
+                    command: "EXPIRE".to_string(),
+                },
+                "ERR",
+            ),
+            (
+                MiniRedisError::IncompatibleOptions {
+                    options: "GT and LT".to_string(),
+                },
+                "ERR",
+            ),
+            (MiniRedisError::SyntaxError, "ERR"),
+            (MiniRedisError::NotAnInteger, "ERR"),
+            (MiniRedisError::IncrementOverflow, "ERR"),
+            (MiniRedisError::NotAFloat, "ERR"),
+            (MiniRedisError::IncrementNotFinite, "ERR"),
+            (MiniRedisError::NotADouble, "ERR"),
+            (MiniRedisError::ValueTooLarge, "ERR"),
+            (MiniRedisError::NoSuchKey, "ERR"),
+            (MiniRedisError::WrongType, "WRONGTYPE"),
+            (
+                MiniRedisError::ComparisonTooLarge { cells: 2, limit: 1 },
+                "ERR",
+            ),
+            (
+                MiniRedisError::UnterminatedQuote {
+                    line: "\"".to_string(),
+                },
+                "ERR",
+            ),
+            (MiniRedisError::StreamClosed, "IOERR"),
+            (MiniRedisError::StreamNotReadable { source: io() }, "IOERR"),
+            (MiniRedisError::StreamNotWritable { source: io() }, "IOERR"),
+            (
+                MiniRedisError::StreamNotConnected {
+                    address: "a".to_string(),
+                    source: io(),
+                },
+                "IOERR",
+            ),
+            (MiniRedisError::StreamNotFlushed { source: io() }, "IOERR"),
+            (
+                MiniRedisError::Timeout {
+                    address: "a".to_string(),
+                    timeout: std::time::Duration::ZERO,
+                },
+                "TIMEOUT",
+            ),
+            (
+                MiniRedisError::AddressesNotConnected { failures: vec![] },
+                "IOERR",
+            ),
+            (
+                MiniRedisError::RetriesExhausted {
+                    attempts: 2,
+                    source: Box::new(MiniRedisError::StoreFull),
+                },
+                "OOM",
+            ),
+            (
+                MiniRedisError::ShardFailed {
+                    address: "a".to_string(),
+                    source: Box::new(MiniRedisError::StreamClosed),
+                },
+                "IOERR",
+            ),
+            (
+                MiniRedisError::PoolExhausted {
+                    max_size: 1,
+                    timeout: std::time::Duration::ZERO,
+                },
+                "TIMEOUT",
+            ),
+            (MiniRedisError::AddressNotBound { source: io() }, "IOERR"),
+            (
+                MiniRedisError::OutputBufferExceeded {
+                    pending: 2,
+                    limit: 1,
+                },
+                "IOERR",
+            ),
+            (
+                MiniRedisError::Io {
+                    operation: "read",
+                    address: None,
+                    source: io(),
+                },
+                "IOERR",
+            ),
+            (
+                MiniRedisError::Moved {
+                    slot: 1,
+                    address: "a".to_string(),
+                },
+                "MOVED",
+            ),
+            (
+                MiniRedisError::ServerError {
+                    message: "x".to_string(),
+                },
+                "ERR",
+            ),
+            (
+                MiniRedisError::AuthenticationFailed {
+                    message: "x".to_string(),
+                },
+                "NOAUTH",
+            ),
+            (
+                MiniRedisError::FileNotReadable {
+                    path: "a".to_string(),
+                    source: io(),
+                },
+                "IOERR",
+            ),
+            (
+                MiniRedisError::FileNotWritable {
+                    path: "a".to_string(),
+                    source: io(),
+                },
+                "IOERR",
+            ),
+            (
+                MiniRedisError::SnapshotCorrupted {
+                    path: "a".to_string(),
+                    reason: "x".to_string(),
+                },
+                "CORRUPT",
+            ),
+            (
+                MiniRedisError::LogCorrupted {
+                    path: "a".to_string(),
+                    reason: "x".to_string(),
+                },
+                "CORRUPT",
+            ),
+            (
+                MiniRedisError::DumpInvalid {
+                    path: "a".to_string(),
+                    reason: "x".to_string(),
+                },
+                "CORRUPT",
+            ),
+        ];
+        for (error, _) in &errors {
+            match error {
+                MiniRedisError::StoreLocked
+                | MiniRedisError::StoreFull
+                | MiniRedisError::ExpiryNotSupported
+                | MiniRedisError::InvalidCommand { .. }
+                | MiniRedisError::InvalidArguments { .. }
+                | MiniRedisError::WrongArity { .. }
+                | MiniRedisError::InvalidExpireTime { .. }
+                | MiniRedisError::IncompatibleOptions { .. }
+                | MiniRedisError::SyntaxError
+                | MiniRedisError::NotAnInteger
+                | MiniRedisError::IncrementOverflow
+                | MiniRedisError::NotAFloat
+                | MiniRedisError::IncrementNotFinite
+                | MiniRedisError::NotADouble
+                | MiniRedisError::ValueTooLarge
+                | MiniRedisError::WrongType
+                | MiniRedisError::NoSuchKey
+                | MiniRedisError::ComparisonTooLarge { .. }
+                | MiniRedisError::UnterminatedQuote { .. }
+                | MiniRedisError::StreamClosed
+                | MiniRedisError::StreamNotReadable { .. }
+                | MiniRedisError::StreamNotWritable { .. }
+                | MiniRedisError::StreamNotConnected { .. }
+                | MiniRedisError::StreamNotFlushed { .. }
+                | MiniRedisError::Timeout { .. }
+                | MiniRedisError::AddressesNotConnected { .. }
+                | MiniRedisError::RetriesExhausted { .. }
+                | MiniRedisError::ShardFailed { .. }
+                | MiniRedisError::PoolExhausted { .. }
+                | MiniRedisError::AddressNotBound { .. }
+                | MiniRedisError::OutputBufferExceeded { .. }
+                | MiniRedisError::Io { .. }
+                | MiniRedisError::Moved { .. }
+                | MiniRedisError::ServerError { .. }
+                | MiniRedisError::AuthenticationFailed { .. }
+                | MiniRedisError::FileNotReadable { .. }
+                | MiniRedisError::FileNotWritable { .. }
+                | MiniRedisError::SnapshotCorrupted { .. }
+                | MiniRedisError::LogCorrupted { .. }
+                | MiniRedisError::DumpInvalid { .. } => {}
+            }
+        }
+        errors
+    }
+
+    #[test]
+    fn every_error_has_a_stable_code() {
+        for (error, code) in every_error() {
+            assert_eq!(code, error.code(), "{:?}", error);
+            assert!(code.chars().all(|c| c.is_ascii_uppercase()), "{}", code);
+        }
+    }
+
+    #[test]
+    fn io_errors_say_what_failed_and_for_whom() {
+        let error = MiniRedisError::Io {
+            operation: "accept a connection",
+            address: Some("127.0.0.1:6379".to_string()),
+            source: std::io::Error::from_raw_os_error(24),
+        };
+
+        let message = error.to_string();
+
+        assert!(
+            message.starts_with("Could not accept a connection for 127.0.0.1:6379: "),
+            "{}",
+            message
+        );
+        assert!(
+            message.contains(&format!(
+                "({:?})",
+                std::io::Error::from_raw_os_error(24).kind()
+            )),
+            "{}",
+            message
+        );
+        assert!(!error.is_disconnect());
+    }
+
+    #[test]
+    fn argument_errors_read_like_redis() {
+        assert_eq!(
+            "wrong number of arguments for 'set' command",
+            MiniRedisError::WrongArity {
+                command: "SET".to_string()
+            }
+            .to_string()
+        );
+        assert_eq!(
+            "invalid expire time in 'expire' command",
+            MiniRedisError::InvalidExpireTime {
+                command: "EXPIRE".to_string()
+            }
+            .to_string()
+        );
+        assert_eq!(
+            "value is not an integer or out of range",
+            MiniRedisError::NotAnInteger.to_string()
+        );
+    }
+
+    #[test]
+    fn errors_are_grouped_by_category() {
+        let categories = every_error()
+            .iter()
+            .map(|(error, _)| error.category())
+            .collect::<Vec<ErrorCategory>>();
+
+        for category in [
+            ErrorCategory::Protocol,
+            ErrorCategory::Storage,
+            ErrorCategory::Io,
+            ErrorCategory::Auth,
+        ] {
+            assert!(categories.contains(&category), "{:?}", category);
+        }
+        assert_eq!(
+            ErrorCategory::Auth,
+            MiniRedisError::AuthenticationFailed {
+                message: "x".to_string()
+            }
+            .category()
+        );
+        assert_eq!(
+            ErrorCategory::Protocol,
+            MiniRedisError::InvalidCommand {
+                command: "X".to_string()
+            }
+            .category()
+        );
+    }
+}
@@ -0,0 +1,33 @@
+/// The bound on the size of a key-value store.
+///
+/// # Examples
+///
+/// ```rust
+/// use miniredis::eviction::{Capacity, EvictionPolicy};
+/// use miniredis::kv_store::KVStore;
+///
+/// let store = KVStore::with_capacity_policy(Capacity::Entries(2), EvictionPolicy::Lru);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capacity {
+    /// At most this many entries.
+    Entries(usize),
+    /// At most this many bytes, as estimated by `KVStore::memory_usage`.
+    Bytes(usize),
+}
+
+/// How a key-value store makes room when an insert would exceed its capacity.
+///
+/// Victims are never the key being written. They are chosen by looking at
+/// every entry, so each eviction costs time linear in the size of the store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Evict the least recently used entry.
+    Lru,
+    /// Evict the least frequently used entry, the least recently used one on ties.
+    Lfu,
+    /// Evict a random entry.
+    Random,
+    /// Evict nothing and reject the insert with `MiniRedisError::StoreFull`.
+    Reject,
+}
@@ -0,0 +1,1310 @@
+use crate::error::MiniRedisError;
+use crate::rng::Rng;
+use crate::snapshot::crc32;
+use crate::storage::{self, ScanIndex, SetCondition, SetExpiry, SetOptions, Storage};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+};
+
+/// The name of the log file inside the data directory.
+pub const LOG_FILE_NAME: &str = "miniredis.log";
+
+/// The magic bytes at the start of every log file.
+const MAGIC: &[u8; 4] = b"MRLG";
+
+/// The version of the log format written by this build.
+const VERSION: u8 = 1;
+
+/// The magic bytes followed by the version byte, which start every log file.
+const HEADER: [u8; 5] = [MAGIC[0], MAGIC[1], MAGIC[2], MAGIC[3], VERSION];
+
+/// The length of the header.
+const HEADER_LEN: usize = HEADER.len();
+
+/// The length of the operation byte and the key and value lengths of a record.
+const RECORD_HEADER_LEN: usize = 1 + 4 + 4;
+
+/// The length of the checksum at the end of a record.
+const CHECKSUM_LEN: usize = 4;
+
+/// The operation byte of a record that sets a key.
+const OP_SET: u8 = 1;
+
+/// The operation byte of a record that deletes a key.
+const OP_DEL: u8 = 2;
+
+/// The number of stale bytes the log must hold before it is compacted automatically.
+const COMPACTION_MIN_GARBAGE: u64 = 1024 * 1024;
+
+/// When a [`FileStorage`] forces its writes to disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncMode {
+    /// Every write is synced to disk before it is acknowledged,
+    /// so no acknowledged write is lost, even on power loss.
+    Always,
+    /// Writes are handed to the operating system but never synced explicitly.
+    /// They survive the process being killed, but not a crash of the machine.
+    Never,
+}
+
+/// What a [`FileStorage`] does with a record at the end of its log
+/// that a crash in the middle of an append cut short.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TornTail {
+    /// The log is cut back to the last whole record, losing only the write
+    /// that was never acknowledged, and the storage opens.
+    #[default]
+    Truncate,
+    /// The storage refuses to open, so the log can be looked at before anything is cut off.
+    Refuse,
+}
+
+/// Where the value of a key is stored in the log.
+#[derive(Debug, Clone, Copy)]
+struct ValueRef {
+    /// The offset of the value bytes in the log file.
+    offset: u64,
+    /// The length of the value in bytes.
+    len: u32,
+}
+
+/// The open log file and the index built from it.
+struct Log {
+    file: File,
+    /// The length of the valid part of the log, where the next record is appended.
+    end: u64,
+    index: HashMap<String, ValueRef>,
+    /// The keys of the index in the order `SCAN` visits them.
+    scan_order: ScanIndex,
+    /// The number of bytes taken up by records that no longer affect any key.
+    garbage: u64,
+}
+
+/// A durable storage backend that keeps its data in an append-only log file.
+///
+/// Every set and delete is appended to `miniredis.log` in the data directory,
+/// and an in-memory index maps each key to the offset of its value in the file.
+/// Reads look the key up in the index and read the value from the file.
+///
+/// On startup the index is rebuilt by replaying the log. A torn record at the end
+/// of the file, left behind by a crash in the middle of an append, is cut off,
+/// or refused with [`TornTail::Refuse`]; a damaged record anywhere else is reported
+/// as [`MiniRedisError::LogCorrupted`], with its offset.
+///
+/// Overwritten and deleted values stay in the log until it is compacted, which
+/// rewrites the live entries to a new file and renames it over the old one.
+/// This happens automatically once more than half the log, and at least 1 MiB,
+/// is stale, or on demand with [`FileStorage::compact`].
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use miniredis::file_storage::{FileStorage, SyncMode};
+/// use miniredis::server::Server;
+///
+/// let storage = FileStorage::open("/var/lib/miniredis", SyncMode::Always).unwrap();
+/// let server = Server::with_storage("127.0.0.1:6379", storage);
+/// server.run();
+/// ```
+pub struct FileStorage {
+    dir: PathBuf,
+    path: PathBuf,
+    sync: SyncMode,
+    log: Mutex<Log>,
+    /// The offset the log was cut off at when it was opened, if its last record was torn.
+    truncated_at: Option<u64>,
+}
+
+impl FileStorage {
+    /// Opens the log in a data directory, creating both if they do not exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The data directory.
+    /// * `sync` - When writes are forced to disk.
+    ///
+    /// # Returns
+    ///
+    /// A storage backend holding the entries of the log.
+    ///
+    /// # Errors
+    ///
+    /// If the directory or log cannot be created, read or repaired,
+    /// or the log is corrupted, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::file_storage::{FileStorage, SyncMode};
+    /// use miniredis::storage::Storage;
+    ///
+    /// let storage = FileStorage::open("/var/lib/miniredis", SyncMode::Always).unwrap();
+    /// storage.set("key", "value").unwrap();
+    /// ```
+    pub fn open(dir: impl AsRef<Path>, sync: SyncMode) -> Result<Self, MiniRedisError> {
+        Self::open_with(dir, sync, TornTail::default())
+    }
+
+    /// Opens the log in a data directory, choosing what to do with a torn last record.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The data directory.
+    /// * `sync` - When writes are forced to disk.
+    /// * `torn` - Whether to cut off a torn last record, or refuse to open.
+    ///
+    /// # Returns
+    ///
+    /// A storage backend holding the entries of the log.
+    ///
+    /// # Errors
+    ///
+    /// If the directory or log cannot be created, read or repaired, or the log is corrupted,
+    /// it will return an error. With [`TornTail::Refuse`], a torn last record is
+    /// [`MiniRedisError::LogCorrupted`] too.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::file_storage::{FileStorage, SyncMode, TornTail};
+    ///
+    /// let storage =
+    ///     FileStorage::open_with("/var/lib/miniredis", SyncMode::Always, TornTail::Refuse);
+    /// ```
+    pub fn open_with(
+        dir: impl AsRef<Path>,
+        sync: SyncMode,
+        torn: TornTail,
+    ) -> Result<Self, MiniRedisError> {
+        let dir = dir.as_ref().to_path_buf();
+        let path = dir.join(LOG_FILE_NAME);
+        let not_readable = |source| MiniRedisError::FileNotReadable {
+            path: path.display().to_string(),
+            source,
+        };
+        let not_writable = |source| MiniRedisError::FileNotWritable {
+            path: path.display().to_string(),
+            source,
+        };
+
+        fs::create_dir_all(&dir).map_err(not_writable)?;
+        let mut file = open_log(&path).map_err(not_readable)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes).map_err(not_readable)?;
+
+        let corrupted = |reason| MiniRedisError::LogCorrupted {
+            path: path.display().to_string(),
+            reason,
+        };
+        let mut truncated_at = None;
+        let (index, garbage, end) = if HEADER.starts_with(&bytes) && bytes.len() < HEADER_LEN {
+            // A new log, or one whose creation was cut short.
+            file.set_len(0).map_err(not_writable)?;
+            file.write_all(&HEADER).map_err(not_writable)?;
+            file.sync_all().map_err(not_writable)?;
+            sync_dir(&dir).map_err(not_writable)?;
+            (HashMap::new(), 0, HEADER_LEN as u64)
+        } else {
+            let (index, garbage, end) = replay(&bytes).map_err(corrupted)?;
+            if end < bytes.len() as u64 {
+                if torn == TornTail::Refuse {
+                    return Err(corrupted(format!(
+                        "the last record, at offset {}, is incomplete after a crash mid-append",
+                        end
+                    )));
+                }
+                file.set_len(end).map_err(not_writable)?;
+                file.sync_all().map_err(not_writable)?;
+                truncated_at = Some(end);
+            }
+            (index, garbage, end)
+        };
+
+        Ok(Self {
+            dir,
+            path,
+            sync,
+            log: Mutex::new(Log {
+                file,
+                end,
+                scan_order: index.keys().collect(),
+                index,
+                garbage,
+            }),
+            truncated_at,
+        })
+    }
+
+    /// Gets where the log was cut off when it was opened, because its last record was torn.
+    ///
+    /// # Returns
+    ///
+    /// The offset of the torn record, where the log now ends, or None if the log was whole.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::file_storage::{FileStorage, SyncMode};
+    ///
+    /// let storage = FileStorage::open("/var/lib/miniredis", SyncMode::Always).unwrap();
+    /// if let Some(offset) = storage.truncated_at() {
+    ///     eprintln!("Dropped a torn record at offset {}", offset);
+    /// }
+    /// ```
+    pub fn truncated_at(&self) -> Option<u64> {
+        self.truncated_at
+    }
+
+    /// Rewrites the log so it only holds the current value of every key.
+    ///
+    /// The live entries are written to a temporary file, which is synced
+    /// and then renamed over the log, so a crash during compaction
+    /// leaves the old log in place.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the log was compacted successfully.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, or the log cannot be read or written, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::file_storage::{FileStorage, SyncMode};
+    /// use miniredis::storage::Storage;
+    ///
+    /// let storage = FileStorage::open("/var/lib/miniredis", SyncMode::Always).unwrap();
+    /// storage.set("key", "old").unwrap();
+    /// storage.set("key", "new").unwrap();
+    ///
+    /// storage.compact().unwrap();
+    /// ```
+    pub fn compact(&self) -> Result<(), MiniRedisError> {
+        let mut log = self.get_log()?;
+        self.compact_log(&mut log)
+    }
+
+    /// Gets the size of the log file.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes in the log, including stale records.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::file_storage::{FileStorage, SyncMode};
+    /// use miniredis::storage::Storage;
+    ///
+    /// let storage = FileStorage::open("/var/lib/miniredis", SyncMode::Always).unwrap();
+    /// let before = storage.log_size().unwrap();
+    /// storage.set("key", "value").unwrap();
+    ///
+    /// assert!(storage.log_size().unwrap() > before);
+    /// ```
+    pub fn log_size(&self) -> Result<u64, MiniRedisError> {
+        Ok(self.get_log()?.end)
+    }
+
+    /// Appends records to the log, syncing them if the sync mode asks for it.
+    ///
+    /// If the write fails, the log is cut back to its previous length,
+    /// so a partial record never ends up in front of later ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `log` - The locked log.
+    /// * `records` - The encoded records to append.
+    ///
+    /// # Errors
+    ///
+    /// If the records cannot be written or synced, it will return an error.
+    fn append(&self, log: &mut Log, records: &[u8]) -> Result<(), MiniRedisError> {
+        let result = log.file.write_all(records).and_then(|_| match self.sync {
+            SyncMode::Always => log.file.sync_data(),
+            SyncMode::Never => Ok(()),
+        });
+        if let Err(source) = result {
+            let _ = log.file.set_len(log.end);
+            return Err(self.not_writable(source));
+        }
+        log.end += records.len() as u64;
+        Ok(())
+    }
+
+    /// Reads the value a reference points to.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The log file.
+    /// * `value` - Where the value is stored.
+    ///
+    /// # Returns
+    ///
+    /// The bytes of the value.
+    ///
+    /// # Errors
+    ///
+    /// If the value cannot be read, it will return an error.
+    fn read_value(&self, file: &mut File, value: ValueRef) -> Result<Vec<u8>, MiniRedisError> {
+        let mut bytes = vec![0; value.len as usize];
+        file.seek(SeekFrom::Start(value.offset))
+            .and_then(|_| file.read_exact(&mut bytes))
+            .map_err(|source| MiniRedisError::FileNotReadable {
+                path: self.path.display().to_string(),
+                source,
+            })?;
+        Ok(bytes)
+    }
+
+    /// Appends a set record for a key and points the index at its value.
+    ///
+    /// # Arguments
+    ///
+    /// * `log` - The locked log.
+    /// * `key` - The key to set.
+    /// * `value` - The bytes of the value to set.
+    ///
+    /// # Errors
+    ///
+    /// If the record cannot be written, or compacting the log fails, it will return an error.
+    fn write_value(&self, log: &mut Log, key: &str, value: &[u8]) -> Result<(), MiniRedisError> {
+        let mut record = Vec::new();
+        encode_record(&mut record, OP_SET, key, value);
+        let offset = log.end + (RECORD_HEADER_LEN + key.len()) as u64;
+        self.append(log, &record)?;
+
+        let value = ValueRef {
+            offset,
+            len: value.len() as u32,
+        };
+        match log.index.insert(key.to_string(), value) {
+            Some(old) => log.garbage += record_len(key, old.len),
+            None => log.scan_order.insert(key),
+        }
+        self.compact_if_needed(log)
+    }
+
+    /// Appends a set record for each pair and points the index at the new values.
+    ///
+    /// All records are appended in a single write, so a batch costs one sync.
+    ///
+    /// # Arguments
+    ///
+    /// * `log` - The locked log.
+    /// * `pairs` - The keys to set, each with its value.
+    ///
+    /// # Errors
+    ///
+    /// If the records cannot be written, it will return an error.
+    fn write_values(
+        &self,
+        log: &mut Log,
+        pairs: &[(String, String)],
+    ) -> Result<(), MiniRedisError> {
+        if pairs.is_empty() {
+            return Ok(());
+        }
+        let mut records = Vec::new();
+        let mut offsets = Vec::new();
+        for (key, value) in pairs {
+            offsets.push(log.end + (records.len() + RECORD_HEADER_LEN + key.len()) as u64);
+            encode_record(&mut records, OP_SET, key, value.as_bytes());
+        }
+        self.append(log, &records)?;
+
+        for ((key, value), offset) in pairs.iter().zip(offsets) {
+            let value = ValueRef {
+                offset,
+                len: value.len() as u32,
+            };
+            match log.index.insert(key.clone(), value) {
+                Some(old) => log.garbage += record_len(key, old.len),
+                None => log.scan_order.insert(key),
+            }
+        }
+        self.compact_if_needed(log)
+    }
+
+    /// Deletes keys from the index and appends a delete record for each of them.
+    ///
+    /// All records are appended in a single write, so a batch costs one sync.
+    ///
+    /// # Arguments
+    ///
+    /// * `log` - The locked log.
+    /// * `keys` - The keys to delete, which must all be in the index.
+    ///
+    /// # Errors
+    ///
+    /// If the records cannot be written, it will return an error.
+    fn delete_keys(&self, log: &mut Log, keys: &[String]) -> Result<(), MiniRedisError> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+        let mut records = Vec::new();
+        for key in keys {
+            encode_record(&mut records, OP_DEL, key, b"");
+        }
+        self.append(log, &records)?;
+
+        for key in keys {
+            if let Some(old) = log.index.remove(key) {
+                log.scan_order.remove(key);
+                log.garbage += record_len(key, old.len) + record_len(key, 0);
+            }
+        }
+        self.compact_if_needed(log)
+    }
+
+    /// Compacts the log if enough of it is stale.
+    ///
+    /// # Arguments
+    ///
+    /// * `log` - The locked log.
+    ///
+    /// # Errors
+    ///
+    /// If the log cannot be compacted, it will return an error.
+    fn compact_if_needed(&self, log: &mut Log) -> Result<(), MiniRedisError> {
+        if log.garbage >= COMPACTION_MIN_GARBAGE && log.garbage * 2 >= log.end {
+            self.compact_log(log)?;
+        }
+        Ok(())
+    }
+
+    /// Rewrites the log so it only holds the current value of every key.
+    ///
+    /// # Arguments
+    ///
+    /// * `log` - The locked log.
+    ///
+    /// # Errors
+    ///
+    /// If the log cannot be read or written, it will return an error.
+    fn compact_log(&self, log: &mut Log) -> Result<(), MiniRedisError> {
+        let mut temp_path = self.path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+
+        let mut writer =
+            BufWriter::new(File::create(&temp_path).map_err(|e| self.not_writable(e))?);
+        writer
+            .write_all(&HEADER)
+            .map_err(|e| self.not_writable(e))?;
+
+        let mut index = HashMap::with_capacity(log.index.len());
+        let mut end = HEADER_LEN as u64;
+        let mut record = Vec::new();
+        let entries = log
+            .index
+            .iter()
+            .map(|(key, value)| (key.clone(), *value))
+            .collect::<Vec<(String, ValueRef)>>();
+        for (key, value_ref) in entries {
+            let value = self.read_value(&mut log.file, value_ref)?;
+            record.clear();
+            encode_record(&mut record, OP_SET, &key, &value);
+            writer
+                .write_all(&record)
+                .map_err(|e| self.not_writable(e))?;
+
+            let offset = end + (RECORD_HEADER_LEN + key.len()) as u64;
+            index.insert(
+                key,
+                ValueRef {
+                    offset,
+                    len: value_ref.len,
+                },
+            );
+            end += record.len() as u64;
+        }
+
+        let file = writer
+            .into_inner()
+            .map_err(|e| self.not_writable(e.into_error()))?;
+        file.sync_all().map_err(|e| self.not_writable(e))?;
+        drop(file);
+        fs::rename(&temp_path, &self.path).map_err(|e| self.not_writable(e))?;
+        sync_dir(&self.dir).map_err(|e| self.not_writable(e))?;
+
+        log.file = open_log(&self.path).map_err(|e| self.not_writable(e))?;
+        log.end = end;
+        log.index = index;
+        log.garbage = 0;
+        Ok(())
+    }
+
+    /// Creates the error for a failed write to the log.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The underlying IO error.
+    ///
+    /// # Returns
+    ///
+    /// A [`MiniRedisError::FileNotWritable`] for the log file.
+    fn not_writable(&self, source: std::io::Error) -> MiniRedisError {
+        MiniRedisError::FileNotWritable {
+            path: self.path.display().to_string(),
+            source,
+        }
+    }
+
+    /// Replaces the value of a key with one computed from it, under the lock of the log.
+    ///
+    /// The current value is read as text, the way [`Storage::get`] reads it.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to update.
+    /// * `compute` - Computes the new value from the current one, if the key is set.
+    ///
+    /// # Returns
+    ///
+    /// The new value.
+    ///
+    /// # Errors
+    ///
+    /// If `compute` fails, it will return its error and leave the value as it was.
+    /// If the log cannot be read or written, it will return an error.
+    fn update<T: ToString>(
+        &self,
+        key: &str,
+        compute: impl FnOnce(Option<&str>) -> Result<T, MiniRedisError>,
+    ) -> Result<T, MiniRedisError> {
+        self.update_bytes(key, |current| {
+            let value = compute(current.map(String::from_utf8_lossy).as_deref())?;
+            Ok((value.to_string().into_bytes(), value))
+        })
+    }
+
+    /// Replaces the bytes of the value of a key with ones computed from them,
+    /// under the lock of the log.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to update.
+    /// * `compute` - Computes the new bytes from the current ones, if the key is set,
+    ///   together with what to return.
+    ///
+    /// # Returns
+    ///
+    /// What `compute` returned besides the new bytes.
+    ///
+    /// # Errors
+    ///
+    /// If `compute` fails, it will return its error and leave the value as it was.
+    /// If the log cannot be read or written, it will return an error.
+    fn update_bytes<T>(
+        &self,
+        key: &str,
+        compute: impl FnOnce(Option<&[u8]>) -> Result<(Vec<u8>, T), MiniRedisError>,
+    ) -> Result<T, MiniRedisError> {
+        let mut log = self.get_log()?;
+        let current = match log.index.get(key).copied() {
+            Some(value) => Some(self.read_value(&mut log.file, value)?),
+            None => None,
+        };
+        let (bytes, value) = compute(current.as_deref())?;
+        self.write_value(&mut log, key, &bytes)?;
+        Ok(value)
+    }
+
+    /// Gets the log.
+    ///
+    /// # Returns
+    ///
+    /// A guard of the log.
+    ///
+    /// # Errors
+    ///
+    /// If the log is already locked, it will return an error.
+    fn get_log(&self) -> Result<MutexGuard<'_, Log>, MiniRedisError> {
+        self.log.lock().map_err(|_| MiniRedisError::StoreLocked)
+    }
+}
+
+impl Storage for FileStorage {
+    fn get(&self, key: &str) -> Result<Option<String>, MiniRedisError> {
+        Ok(self.get_bytes(key)?.map(storage::text))
+    }
+
+    fn get_bytes(&self, key: &str) -> Result<Option<Vec<u8>>, MiniRedisError> {
+        let mut log = self.get_log()?;
+        match log.index.get(key).copied() {
+            Some(value) => self.read_value(&mut log.file, value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), MiniRedisError> {
+        let mut log = self.get_log()?;
+        self.write_value(&mut log, key, value.as_bytes())
+    }
+
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool, MiniRedisError> {
+        let options = SetOptions {
+            condition: Some(SetCondition::IfAbsent),
+            ..SetOptions::default()
+        };
+        self.set_with(key, value, &options)
+    }
+
+    fn set_with(
+        &self,
+        key: &str,
+        value: &str,
+        options: &SetOptions,
+    ) -> Result<bool, MiniRedisError> {
+        if let SetExpiry::After(_) = options.expiry {
+            return Err(MiniRedisError::ExpiryNotSupported);
+        }
+        let mut log = self.get_log()?;
+        if !options.allows(log.index.contains_key(key)) {
+            return Ok(false);
+        }
+        self.write_value(&mut log, key, value.as_bytes())?;
+        Ok(true)
+    }
+
+    fn multi_set(&self, pairs: &[(String, String)]) -> Result<(), MiniRedisError> {
+        let mut log = self.get_log()?;
+        self.write_values(&mut log, pairs)
+    }
+
+    fn multi_set_if_absent(&self, pairs: &[(String, String)]) -> Result<bool, MiniRedisError> {
+        let mut log = self.get_log()?;
+        if pairs.iter().any(|(key, _)| log.index.contains_key(key)) {
+            return Ok(false);
+        }
+        self.write_values(&mut log, pairs)?;
+        Ok(true)
+    }
+
+    fn set_returning_old(&self, key: &str, value: &str) -> Result<Option<Vec<u8>>, MiniRedisError> {
+        let mut log = self.get_log()?;
+        let old = match log.index.get(key).copied() {
+            Some(old) => Some(self.read_value(&mut log.file, old)?),
+            None => None,
+        };
+        self.write_value(&mut log, key, value.as_bytes())?;
+        Ok(old)
+    }
+
+    fn increment(&self, key: &str, delta: i64) -> Result<i64, MiniRedisError> {
+        self.update(key, |current| {
+            current.map_or(Ok(delta), |value| storage::add_to_integer(value, delta))
+        })
+    }
+
+    fn increment_float(&self, key: &str, delta: f64) -> Result<f64, MiniRedisError> {
+        self.update(key, |current| {
+            current.map_or(Ok(delta), |value| storage::add_to_float(value, delta))
+        })
+    }
+
+    fn append(&self, key: &str, suffix: &str) -> Result<usize, MiniRedisError> {
+        self.update_bytes(key, |current| {
+            let value = [current.unwrap_or_default(), suffix.as_bytes()].concat();
+            let len = value.len();
+            Ok((value, len))
+        })
+    }
+
+    fn set_range(&self, key: &str, offset: usize, value: &str) -> Result<usize, MiniRedisError> {
+        self.update_bytes(key, |current| {
+            let value =
+                storage::overwrite_range(current.unwrap_or_default(), offset, value.as_bytes())?;
+            let len = value.len();
+            Ok((value, len))
+        })
+    }
+
+    fn set_bit(&self, key: &str, offset: usize, bit: bool) -> Result<bool, MiniRedisError> {
+        self.update_bytes(key, |current| {
+            storage::write_bit(current.unwrap_or_default(), offset, bit)
+        })
+    }
+
+    fn rename(&self, source: &str, destination: &str) -> Result<(), MiniRedisError> {
+        let mut log = self.get_log()?;
+        let value = match log.index.get(source).copied() {
+            Some(value) => self.read_value(&mut log.file, value)?,
+            None => return Err(MiniRedisError::NoSuchKey),
+        };
+        if source != destination {
+            self.write_value(&mut log, destination, &value)?;
+            self.delete_keys(&mut log, &[source.to_string()])?;
+        }
+        Ok(())
+    }
+
+    fn rename_if_absent(&self, source: &str, destination: &str) -> Result<bool, MiniRedisError> {
+        let mut log = self.get_log()?;
+        let value = match log.index.get(source).copied() {
+            Some(value) => self.read_value(&mut log.file, value)?,
+            None => return Err(MiniRedisError::NoSuchKey),
+        };
+        if log.index.contains_key(destination) {
+            return Ok(false);
+        }
+        self.write_value(&mut log, destination, &value)?;
+        self.delete_keys(&mut log, &[source.to_string()])?;
+        Ok(true)
+    }
+
+    fn del(&self, key: &str) -> Result<bool, MiniRedisError> {
+        let mut log = self.get_log()?;
+        if !log.index.contains_key(key) {
+            return Ok(false);
+        }
+        self.delete_keys(&mut log, &[key.to_string()])?;
+        Ok(true)
+    }
+
+    fn del_prefix(&self, prefix: &str) -> Result<usize, MiniRedisError> {
+        let mut log = self.get_log()?;
+        let keys = log
+            .index
+            .keys()
+            .filter(|key| key.starts_with(prefix))
+            .cloned()
+            .collect::<Vec<String>>();
+        self.delete_keys(&mut log, &keys)?;
+        Ok(keys.len())
+    }
+
+    fn exists(&self, key: &str) -> Result<bool, MiniRedisError> {
+        Ok(self.get_log()?.index.contains_key(key))
+    }
+
+    fn len(&self) -> Result<usize, MiniRedisError> {
+        Ok(self.get_log()?.index.len())
+    }
+
+    fn keys(&self) -> Result<Vec<String>, MiniRedisError> {
+        Ok(self.get_log()?.index.keys().cloned().collect())
+    }
+
+    fn random_key(&self) -> Result<Option<String>, MiniRedisError> {
+        let log = self.get_log()?;
+        if log.index.is_empty() {
+            return Ok(None);
+        }
+        let index = Rng::new().below(log.index.len());
+        Ok(log.index.keys().nth(index).cloned())
+    }
+
+    fn scan(&self, cursor: u64, count: usize) -> Result<(u64, Vec<String>), MiniRedisError> {
+        let log = self.get_log()?;
+        Ok(log.scan_order.scan(cursor, count, |_| true))
+    }
+}
+
+/// Opens the log file for reading and appending, creating it if needed.
+///
+/// # Arguments
+///
+/// * `path` - The path of the log file.
+///
+/// # Returns
+///
+/// The opened file.
+///
+/// # Errors
+///
+/// If the file cannot be opened, it will return an error.
+fn open_log(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)
+}
+
+/// Syncs a directory, so a newly created or renamed file in it survives a crash.
+///
+/// # Arguments
+///
+/// * `dir` - The directory to sync.
+///
+/// # Errors
+///
+/// If the directory cannot be opened or synced, it will return an error.
+fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+/// Gets the length of an encoded record.
+///
+/// # Arguments
+///
+/// * `key` - The key of the record.
+/// * `value_len` - The length of the value of the record.
+///
+/// # Returns
+///
+/// The number of bytes the record takes up in the log.
+fn record_len(key: &str, value_len: u32) -> u64 {
+    (RECORD_HEADER_LEN + key.len() + value_len as usize + CHECKSUM_LEN) as u64
+}
+
+/// Appends an encoded record to a buffer.
+///
+/// A record is the operation byte, the key and value lengths,
+/// the key and value, and a CRC32 checksum of everything before it.
+/// All integers are little endian.
+///
+/// # Arguments
+///
+/// * `bytes` - The buffer to append to.
+/// * `op` - The operation of the record.
+/// * `key` - The key of the record.
+/// * `value` - The bytes of the value of the record, empty for deletes.
+fn encode_record(bytes: &mut Vec<u8>, op: u8, key: &str, value: &[u8]) {
+    let start = bytes.len();
+    bytes.push(op);
+    bytes.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(key.as_bytes());
+    bytes.extend_from_slice(value);
+    let checksum = crc32(&bytes[start..]);
+    bytes.extend_from_slice(&checksum.to_le_bytes());
+}
+
+/// Checks whether a whole, valid record starts anywhere in some bytes.
+///
+/// # Arguments
+///
+/// * `bytes` - The bytes to look through.
+///
+/// # Returns
+///
+/// True if a record with a valid checksum starts at any offset of the bytes.
+fn holds_record(bytes: &[u8]) -> bool {
+    (0..bytes.len()).any(|start| {
+        let rest = &bytes[start..];
+        if rest.len() < RECORD_HEADER_LEN + CHECKSUM_LEN || ![OP_SET, OP_DEL].contains(&rest[0]) {
+            return false;
+        }
+        let key_len = u32::from_le_bytes(rest[1..5].try_into().unwrap()) as usize;
+        let value_len = u32::from_le_bytes(rest[5..9].try_into().unwrap()) as usize;
+        let len = RECORD_HEADER_LEN + key_len + value_len + CHECKSUM_LEN;
+        rest.len() >= len
+            && crc32(&rest[..len - CHECKSUM_LEN]).to_le_bytes() == rest[len - CHECKSUM_LEN..len]
+    })
+}
+
+/// Rebuilds the index from the contents of a log file.
+///
+/// # Arguments
+///
+/// * `bytes` - The contents of the log file.
+///
+/// # Returns
+///
+/// The index, the number of stale bytes, and the length of the valid part of the log.
+/// The valid part is shorter than `bytes` if the last record is torn.
+///
+/// # Errors
+///
+/// If the header is invalid, or a record other than the last one is damaged,
+/// it will return a description of the problem with the offset of the record.
+fn replay(bytes: &[u8]) -> Result<(HashMap<String, ValueRef>, u64, u64), String> {
+    if bytes.len() < HEADER_LEN || &bytes[..MAGIC.len()] != MAGIC {
+        return Err("not a log file".to_string());
+    }
+    if bytes[MAGIC.len()] != VERSION {
+        return Err("unsupported log version".to_string());
+    }
+
+    let mut index: HashMap<String, ValueRef> = HashMap::new();
+    let mut garbage = 0;
+    let mut pos = HEADER_LEN;
+    while pos < bytes.len() {
+        let rest = &bytes[pos..];
+        if rest.len() < RECORD_HEADER_LEN {
+            break;
+        }
+        let key_len = u32::from_le_bytes(rest[1..5].try_into().unwrap()) as usize;
+        let value_len = u32::from_le_bytes(rest[5..9].try_into().unwrap());
+        let len = RECORD_HEADER_LEN + key_len + value_len as usize + CHECKSUM_LEN;
+        if rest.len() < len {
+            // A torn record is the last write, so whole records after it
+            // mean its lengths were damaged instead.
+            if holds_record(&rest[1..]) {
+                return Err(format!("damaged record at offset {}", pos));
+            }
+            break;
+        }
+
+        let (body, checksum) = rest[..len].split_at(len - CHECKSUM_LEN);
+        let key = std::str::from_utf8(&body[RECORD_HEADER_LEN..RECORD_HEADER_LEN + key_len]);
+        let valid = crc32(body).to_le_bytes() == checksum && key.is_ok();
+        if !valid && rest.len() == len {
+            // The last record was torn by a crash in the middle of an append.
+            break;
+        }
+        let key = match (valid, key) {
+            (true, Ok(key)) => key,
+            _ => return Err(format!("damaged record at offset {}", pos)),
+        };
+
+        match body[0] {
+            OP_SET => {
+                let value = ValueRef {
+                    offset: (pos + RECORD_HEADER_LEN + key_len) as u64,
+                    len: value_len,
+                };
+                if let Some(old) = index.insert(key.to_string(), value) {
+                    garbage += record_len(key, old.len);
+                }
+            }
+            OP_DEL => {
+                if let Some(old) = index.remove(key) {
+                    garbage += record_len(key, old.len);
+                }
+                garbage += len as u64;
+            }
+            op => return Err(format!("unknown operation {} at offset {}", op, pos)),
+        }
+        pos += len;
+    }
+    Ok((index, garbage, pos as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Creates an empty directory in the temporary directory, unique to this process.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("miniredis-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn open_creates_empty_log() {
+        let dir = temp_dir("file-storage-empty");
+
+        let storage = FileStorage::open(&dir, SyncMode::Always).unwrap();
+
+        assert_eq!(Ok(0), storage.len());
+        assert!(dir.join(LOG_FILE_NAME).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn entries_survive_reopening() {
+        let dir = temp_dir("file-storage-reopen");
+        {
+            let storage = FileStorage::open(&dir, SyncMode::Always).unwrap();
+            storage.set("a", "1").unwrap();
+            storage.set("b", "2").unwrap();
+            storage.set("a", "3").unwrap();
+            storage.del("b").unwrap();
+        }
+
+        let storage = FileStorage::open(&dir, SyncMode::Always).unwrap();
+
+        assert_eq!(Ok(Some("3".to_string())), storage.get("a"));
+        assert_eq!(Ok(None), storage.get("b"));
+        assert_eq!(Ok(1), storage.len());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn open_cuts_off_torn_final_record() {
+        let dir = temp_dir("file-storage-torn");
+        let size = {
+            let storage = FileStorage::open(&dir, SyncMode::Always).unwrap();
+            storage.set("kept", "value").unwrap();
+            storage.log_size().unwrap()
+        };
+        let mut record = Vec::new();
+        encode_record(&mut record, OP_SET, "torn", b"value");
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(dir.join(LOG_FILE_NAME))
+            .unwrap();
+        file.write_all(&record[..record.len() - 3]).unwrap();
+        drop(file);
+
+        let storage = FileStorage::open(&dir, SyncMode::Always).unwrap();
+        assert_eq!(Ok(size), storage.log_size());
+        assert_eq!(Ok(Some("value".to_string())), storage.get("kept"));
+        assert_eq!(Ok(None), storage.get("torn"));
+
+        storage.set("after", "value").unwrap();
+        drop(storage);
+        let storage = FileStorage::open(&dir, SyncMode::Always).unwrap();
+        assert_eq!(Ok(Some("value".to_string())), storage.get("after"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn open_returns_error_for_damaged_record_before_the_end() {
+        let dir = temp_dir("file-storage-damaged");
+        {
+            let storage = FileStorage::open(&dir, SyncMode::Always).unwrap();
+            storage.set("a", "1").unwrap();
+            storage.set("b", "2").unwrap();
+        }
+        let path = dir.join(LOG_FILE_NAME);
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[HEADER_LEN + RECORD_HEADER_LEN] ^= 0x01;
+        fs::write(&path, bytes).unwrap();
+
+        let result = FileStorage::open(&dir, SyncMode::Always);
+
+        assert!(matches!(result, Err(MiniRedisError::LogCorrupted { .. })));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Writes a log holding a record for every key, and returns its path.
+    fn write_log(dir: &Path, keys: &[&str]) -> PathBuf {
+        let storage = FileStorage::open(dir, SyncMode::Always).unwrap();
+        for key in keys {
+            storage.set(key, "value").unwrap();
+        }
+        dir.join(LOG_FILE_NAME)
+    }
+
+    #[test]
+    fn open_with_refuse_reports_a_torn_record_by_its_offset() {
+        let dir = temp_dir("file-storage-refuse-torn");
+        let path = write_log(&dir, &["a", "b"]);
+        let bytes = fs::read(&path).unwrap();
+        let last = bytes.len() as u64 - record_len("b", 5);
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+
+        let result = FileStorage::open_with(&dir, SyncMode::Always, TornTail::Refuse);
+
+        match result {
+            Err(MiniRedisError::LogCorrupted { reason, .. }) => {
+                assert!(reason.contains(&format!("offset {}", last)), "{}", reason)
+            }
+            _ => panic!("a torn record was not refused"),
+        }
+        assert_eq!(bytes.len() - 1, fs::read(&path).unwrap().len());
+        let storage = FileStorage::open(&dir, SyncMode::Always).unwrap();
+        assert_eq!(Some(last), storage.truncated_at());
+        assert_eq!(Ok(vec!["a".to_string()]), storage.keys());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn open_cuts_off_logs_truncated_at_any_byte_of_the_last_record() {
+        let dir = temp_dir("file-storage-truncated");
+        let path = write_log(&dir, &["a", "b"]);
+        let bytes = fs::read(&path).unwrap();
+        let last = bytes.len() - record_len("b", 5) as usize;
+
+        for len in last + 1..bytes.len() {
+            fs::write(&path, &bytes[..len]).unwrap();
+
+            let storage = FileStorage::open(&dir, SyncMode::Always).unwrap();
+
+            assert_eq!(Some(last as u64), storage.truncated_at(), "{}", len);
+            assert_eq!(Ok(vec!["a".to_string()]), storage.keys(), "{}", len);
+        }
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(
+            None,
+            FileStorage::open(&dir, SyncMode::Always)
+                .unwrap()
+                .truncated_at()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn open_refuses_a_damaged_length_before_the_end_instead_of_cutting_the_log_off() {
+        let dir = temp_dir("file-storage-damaged-length");
+        let path = write_log(&dir, &["a", "b", "c"]);
+        let mut bytes = fs::read(&path).unwrap();
+        // The value length of the first record, now far past the end of the log.
+        bytes[HEADER_LEN + 8] ^= 0x40;
+        fs::write(&path, &bytes).unwrap();
+
+        for torn in [TornTail::Truncate, TornTail::Refuse] {
+            let result = FileStorage::open_with(&dir, SyncMode::Always, torn);
+
+            assert!(
+                matches!(
+                    &result,
+                    Err(MiniRedisError::LogCorrupted { reason, .. })
+                        if reason == &format!("damaged record at offset {}", HEADER_LEN)
+                ),
+                "{:?}",
+                torn
+            );
+        }
+        assert_eq!(bytes, fs::read(&path).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn compact_drops_stale_records_and_keeps_live_ones() {
+        let dir = temp_dir("file-storage-compact");
+        let storage = FileStorage::open(&dir, SyncMode::Never).unwrap();
+        for i in 0..100 {
+            storage.set("key", &i.to_string()).unwrap();
+        }
+        storage.set("other", "value").unwrap();
+        storage.del("other").unwrap();
+        let before = storage.log_size().unwrap();
+
+        storage.compact().unwrap();
+
+        assert!(storage.log_size().unwrap() < before);
+        assert_eq!(Ok(Some("99".to_string())), storage.get("key"));
+        drop(storage);
+        let storage = FileStorage::open(&dir, SyncMode::Never).unwrap();
+        assert_eq!(Ok(Some("99".to_string())), storage.get("key"));
+        assert_eq!(Ok(1), storage.len());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn log_is_compacted_automatically_when_mostly_stale() {
+        let dir = temp_dir("file-storage-auto-compact");
+        let storage = FileStorage::open(&dir, SyncMode::Never).unwrap();
+        let value = "x".repeat(64 * 1024);
+        for _ in 0..64 {
+            storage.set("key", &value).unwrap();
+        }
+
+        // 4 MiB were written, but stale values never make up much more than the threshold.
+        assert!(storage.log_size().unwrap() < 2 * COMPACTION_MIN_GARBAGE);
+        assert_eq!(Ok(Some(value)), storage.get("key"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn del_prefix_deletes_matching_keys_durably() {
+        let dir = temp_dir("file-storage-del-prefix");
+        {
+            let storage = FileStorage::open(&dir, SyncMode::Always).unwrap();
+            storage.set("session:1", "a").unwrap();
+            storage.set("session:2", "b").unwrap();
+            storage.set("user:1", "c").unwrap();
+            assert_eq!(Ok(2), storage.del_prefix("session:"));
+        }
+
+        let storage = FileStorage::open(&dir, SyncMode::Always).unwrap();
+
+        assert_eq!(Ok(vec!["user:1".to_string()]), storage.keys());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn increments_survive_reopening() {
+        let dir = temp_dir("file-storage-increment");
+        {
+            let storage = FileStorage::open(&dir, SyncMode::Always).unwrap();
+            assert_eq!(Ok(1), storage.increment("visits", 1));
+            assert_eq!(Ok(11), storage.increment("visits", 10));
+        }
+
+        let storage = FileStorage::open(&dir, SyncMode::Always).unwrap();
+
+        assert_eq!(Ok(10), storage.increment("visits", -1));
+        assert_eq!(Ok(Some("10".to_string())), storage.get("visits"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn appends_survive_reopening() {
+        let dir = temp_dir("file-storage-append");
+        {
+            let storage = FileStorage::open(&dir, SyncMode::Always).unwrap();
+            assert_eq!(Ok(5), Storage::append(&storage, "greeting", "hello"));
+        }
+
+        let storage = FileStorage::open(&dir, SyncMode::Always).unwrap();
+
+        assert_eq!(Ok(11), Storage::append(&storage, "greeting", " world"));
+        assert_eq!(Ok(Some("hello world".to_string())), storage.get("greeting"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn bits_survive_reopening_whatever_bytes_they_make() {
+        let dir = temp_dir("file-storage-bits");
+        {
+            let storage = FileStorage::open(&dir, SyncMode::Always).unwrap();
+            for offset in 0..8 {
+                assert_eq!(Ok(false), Storage::set_bit(&storage, "bits", offset, true));
+            }
+        }
+
+        let storage = FileStorage::open(&dir, SyncMode::Always).unwrap();
+
+        assert_eq!(Ok(Some(vec![0xff])), storage.get_bytes("bits"));
+        assert_eq!(Ok(true), Storage::set_bit(&storage, "bits", 0, false));
+        assert_eq!(Ok(Some(vec![0x7f])), storage.get_bytes("bits"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn set_with_refuses_a_time_to_live() {
+        let dir = temp_dir("file-storage-set-expiry");
+        let storage = FileStorage::open(&dir, SyncMode::Always).unwrap();
+        let after = SetOptions {
+            expiry: SetExpiry::After(Duration::from_secs(10)),
+            ..SetOptions::default()
+        };
+        let keep = SetOptions {
+            expiry: SetExpiry::Keep,
+            ..SetOptions::default()
+        };
+
+        assert_eq!(
+            Err(MiniRedisError::ExpiryNotSupported),
+            storage.set_with("key", "a", &after)
+        );
+        assert_eq!(Ok(None), storage.get("key"));
+        assert_eq!(Ok(true), storage.set_with("key", "b", &keep));
+        assert_eq!(Ok(Some("b".to_string())), storage.get("key"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn multi_sets_survive_reopening() {
+        let dir = temp_dir("file-storage-multi-set");
+        let pairs = [
+            ("a".to_string(), "1".to_string()),
+            ("b".to_string(), "22".to_string()),
+            ("a".to_string(), "333".to_string()),
+        ];
+        {
+            let storage = FileStorage::open(&dir, SyncMode::Always).unwrap();
+            storage.multi_set(&pairs).unwrap();
+        }
+
+        let storage = FileStorage::open(&dir, SyncMode::Always).unwrap();
+
+        assert_eq!(Ok(Some("333".to_string())), storage.get("a"));
+        assert_eq!(Ok(Some("22".to_string())), storage.get("b"));
+        assert_eq!(Ok(2), storage.len());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
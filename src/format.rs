@@ -0,0 +1,538 @@
+use crate::error::MiniRedisError;
+use crate::protocol;
+use crate::response::Response;
+
+/// How the client prints responses.
+///
+/// # Examples
+///
+/// ```rust
+/// use miniredis::format::{Format, format_response};
+/// use miniredis::response::Response;
+///
+/// assert_eq!("5", format_response(&Response::Integer(5), Format::Raw));
+/// assert_eq!("(integer) 5", format_response(&Response::Integer(5), Format::Pretty));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// Exactly the payload of the response, for piping into other tools.
+    Raw,
+    /// The response decorated for humans, with its kind and numbered elements.
+    Pretty,
+    /// The response as one line of JSON, for tools like `jq`.
+    Json,
+    /// The response as rows of comma-separated values, for spreadsheets.
+    Csv,
+}
+
+/// Formats a response for printing.
+///
+/// In raw format, every value is printed as is, one per line,
+/// with the fields of a map on the line before their values.
+///
+/// In pretty format, values are quoted, integers start with `(integer)`,
+/// errors with `(error)`, and nil is `(nil)`. The elements of arrays and maps
+/// are numbered, nested elements are indented under their parent,
+/// the values of a map line up, and empty arrays and maps are marked `(empty)`.
+///
+/// In JSON format, the response is a single line: values are strings, integers are numbers,
+/// nil is `null`, arrays are arrays, maps are objects, and errors are `{"error": "<message>"}`.
+///
+/// In CSV format, every element of an array, and every field of a map, is a row.
+/// The fields of a row are the elements of a nested array, after the field of a map,
+/// so an array of arrays is a table. Any other response is a row with a single field,
+/// nil is an empty field, and fields are quoted when they hold commas, quotes, or line endings.
+///
+/// # Arguments
+///
+/// * `response` - The response to format.
+/// * `format` - How to format it.
+///
+/// # Returns
+///
+/// The formatted response, without a trailing line ending.
+///
+/// # Examples
+///
+/// ```rust
+/// use miniredis::format::{Format, format_response};
+/// use miniredis::response::Response;
+///
+/// let response = Response::Array(vec![
+///     Response::Bulk("alice".to_string()),
+///     Response::Nil,
+/// ]);
+///
+/// assert_eq!("alice\nnil", format_response(&response, Format::Raw));
+/// assert_eq!("1) \"alice\"\n2) (nil)", format_response(&response, Format::Pretty));
+/// ```
+pub fn format_response(response: &Response, format: Format) -> String {
+    match format {
+        Format::Raw => raw(response),
+        Format::Pretty => pretty(response),
+        Format::Json => json(response),
+        Format::Csv => csv(response),
+    }
+}
+
+/// Formats a response in raw format.
+///
+/// # Arguments
+///
+/// * `response` - The response to format.
+///
+/// # Returns
+///
+/// The payload of the response.
+fn raw(response: &Response) -> String {
+    match response {
+        Response::Simple(value) | Response::Bulk(value) => value.clone(),
+        Response::Error(e) => format!("ERR {}", error_message(e)),
+        Response::Array(items) => items.iter().map(raw).collect::<Vec<String>>().join("\n"),
+        Response::Map(fields) => fields
+            .iter()
+            .map(|(field, value)| format!("{}\n{}", field, raw(value)))
+            .collect::<Vec<String>>()
+            .join("\n"),
+        _ => response.to_string(),
+    }
+}
+
+/// Formats a response in JSON format.
+///
+/// # Arguments
+///
+/// * `response` - The response to format.
+///
+/// # Returns
+///
+/// The response as a JSON value on one line.
+fn json(response: &Response) -> String {
+    match response {
+        Response::Simple(value) | Response::Bulk(value) => json_string(value),
+        Response::BulkBytes(value) => json_string(&String::from_utf8_lossy(value)),
+        Response::Integer(n) => n.to_string(),
+        Response::Nil => "null".to_string(),
+        Response::Error(e) => format!("{{\"error\":{}}}", json_string(&error_message(e))),
+        Response::Array(items) => format!(
+            "[{}]",
+            items.iter().map(json).collect::<Vec<String>>().join(",")
+        ),
+        Response::Map(fields) => format!(
+            "{{{}}}",
+            fields
+                .iter()
+                .map(|(field, value)| format!("{}:{}", json_string(field), json(value)))
+                .collect::<Vec<String>>()
+                .join(",")
+        ),
+    }
+}
+
+/// Quotes a value as a JSON string.
+///
+/// # Arguments
+///
+/// * `value` - The value to quote.
+///
+/// # Returns
+///
+/// The value in double quotes, with quotes, backslashes, and control characters escaped.
+pub(crate) fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Formats a response in CSV format.
+///
+/// # Arguments
+///
+/// * `response` - The response to format.
+///
+/// # Returns
+///
+/// The rows of the response, one per line.
+fn csv(response: &Response) -> String {
+    let rows = match response {
+        Response::Array(items) => items.iter().map(csv_fields).collect(),
+        Response::Map(fields) => fields
+            .iter()
+            .map(|(field, value)| {
+                let mut row = vec![csv_field(field)];
+                row.extend(csv_fields(value));
+                row
+            })
+            .collect(),
+        _ => vec![csv_fields(response)],
+    };
+    rows.iter()
+        .map(|row: &Vec<String>| row.join(","))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Flattens a response into the fields of one CSV row.
+///
+/// # Arguments
+///
+/// * `response` - The response to flatten.
+///
+/// # Returns
+///
+/// The quoted fields.
+fn csv_fields(response: &Response) -> Vec<String> {
+    match response {
+        Response::Array(items) => items.iter().flat_map(csv_fields).collect(),
+        Response::Map(fields) => fields
+            .iter()
+            .flat_map(|(field, value)| {
+                let mut fields = vec![csv_field(field)];
+                fields.extend(csv_fields(value));
+                fields
+            })
+            .collect(),
+        Response::Nil => vec![String::new()],
+        Response::Error(e) => vec![csv_field(&format!("ERR {}", error_message(e)))],
+        _ => vec![csv_field(&raw(response))],
+    }
+}
+
+/// Quotes a CSV field if it needs it.
+///
+/// # Arguments
+///
+/// * `value` - The value of the field.
+///
+/// # Returns
+///
+/// The value as it is, or in double quotes with its quotes doubled
+/// if it holds a comma, a quote, or a line ending.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Gets the message of an error, as the server wrote it after `ERR `, with its code.
+///
+/// # Arguments
+///
+/// * `error` - The error of an error reply.
+///
+/// # Returns
+///
+/// The message of the error.
+fn error_message(error: &MiniRedisError) -> String {
+    match error {
+        MiniRedisError::ServerError { message } => message.clone(),
+        e => protocol::error_message(e),
+    }
+}
+
+/// Formats a response in pretty format.
+///
+/// # Arguments
+///
+/// * `response` - The response to format.
+///
+/// # Returns
+///
+/// The response decorated for humans.
+fn pretty(response: &Response) -> String {
+    match response {
+        Response::Simple(status) => status.clone(),
+        Response::Bulk(value) => format!("{:?}", value),
+        // Like redis-cli, bytes that are not text are shown as escapes such as `\xff`.
+        Response::BulkBytes(value) => format!("\"{}\"", value.escape_ascii()),
+        Response::Integer(n) => format!("(integer) {}", n),
+        Response::Nil => "(nil)".to_string(),
+        Response::Error(_) => format!("(error) {}", raw(response)),
+        Response::Array(items) if items.is_empty() => "(empty array)".to_string(),
+        Response::Array(items) => numbered(items.iter().map(pretty).collect()),
+        Response::Map(fields) if fields.is_empty() => "(empty hash)".to_string(),
+        Response::Map(fields) => {
+            let width = fields.iter().map(|(field, _)| field.chars().count()).max();
+            let width = width.unwrap_or(0);
+            numbered(
+                fields
+                    .iter()
+                    .map(|(field, value)| {
+                        let value = indent(&pretty(value), width + 1);
+                        format!("{:<width$} {}", field, value, width = width)
+                    })
+                    .collect(),
+            )
+        }
+    }
+}
+
+/// Numbers formatted elements, one per line, with the numbers right-aligned.
+///
+/// # Arguments
+///
+/// * `elements` - The formatted elements, which may span several lines.
+///
+/// # Returns
+///
+/// The numbered elements, with later lines of an element indented under its first.
+fn numbered(elements: Vec<String>) -> String {
+    let width = elements.len().to_string().len();
+    elements
+        .iter()
+        .enumerate()
+        .map(|(i, element)| {
+            let element = indent(element, width + 2);
+            format!("{:>width$}) {}", i + 1, element, width = width)
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Indents every line but the first.
+///
+/// # Arguments
+///
+/// * `text` - The text to indent.
+/// * `spaces` - How many spaces to indent by.
+///
+/// # Returns
+///
+/// The indented text.
+fn indent(text: &str, spaces: usize) -> String {
+    text.replace('\n', &format!("\n{}", " ".repeat(spaces)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bulk(value: &str) -> Response {
+        Response::Bulk(value.to_string())
+    }
+
+    #[test]
+    fn raw_prints_payload_of_single_replies() {
+        assert_eq!("OK", format_response(&Response::ok(), Format::Raw));
+        assert_eq!(
+            "hello world",
+            format_response(&bulk("hello world"), Format::Raw)
+        );
+        assert_eq!("5", format_response(&Response::Integer(5), Format::Raw));
+        assert_eq!("nil", format_response(&Response::Nil, Format::Raw));
+    }
+
+    #[test]
+    fn raw_prints_error_replies_as_the_server_wrote_them() {
+        let reply = Response::from_line("ERR Invalid command: UNKNOWN.");
+        let local = Response::Error(MiniRedisError::StoreFull);
+
+        assert_eq!(
+            "ERR Invalid command: UNKNOWN.",
+            format_response(&reply, Format::Raw)
+        );
+        assert_eq!(
+            "ERR OOM The key value store is full.",
+            format_response(&local, Format::Raw)
+        );
+    }
+
+    #[test]
+    fn raw_prints_one_element_per_line() {
+        let array = Response::Array(vec![bulk("a"), Response::Integer(1)]);
+        let map = Response::Map(vec![("name".to_string(), bulk("alice"))]);
+
+        assert_eq!("a\n1", format_response(&array, Format::Raw));
+        assert_eq!("name\nalice", format_response(&map, Format::Raw));
+        assert_eq!("", format_response(&Response::Array(vec![]), Format::Raw));
+    }
+
+    #[test]
+    fn raw_prints_values_that_look_like_headers_as_they_are() {
+        let array = Response::Array(vec![bulk("*2"), bulk("%1")]);
+
+        assert_eq!("*2\n%1", format_response(&array, Format::Raw));
+    }
+
+    #[test]
+    fn pretty_marks_kind_of_single_replies() {
+        assert_eq!("OK", format_response(&Response::ok(), Format::Pretty));
+        assert_eq!(
+            "\"hello world\"",
+            format_response(&bulk("hello world"), Format::Pretty)
+        );
+        assert_eq!(
+            "(integer) 5",
+            format_response(&Response::Integer(5), Format::Pretty)
+        );
+        assert_eq!("(nil)", format_response(&Response::Nil, Format::Pretty));
+    }
+
+    #[test]
+    fn pretty_marks_error_replies() {
+        let reply = Response::from_line("ERR Invalid command: UNKNOWN.");
+
+        assert_eq!(
+            "(error) ERR Invalid command: UNKNOWN.",
+            format_response(&reply, Format::Pretty)
+        );
+    }
+
+    #[test]
+    fn pretty_escapes_values() {
+        assert_eq!(
+            "\"say \\\"hi\\\"\\n\"",
+            format_response(&bulk("say \"hi\"\n"), Format::Pretty)
+        );
+    }
+
+    #[test]
+    fn pretty_numbers_elements_with_aligned_numbers() {
+        let array = Response::Array((1..=10).map(Response::Integer).collect());
+
+        let formatted = format_response(&array, Format::Pretty);
+
+        assert!(formatted.starts_with(" 1) (integer) 1\n 2) (integer) 2\n"));
+        assert!(formatted.ends_with("\n10) (integer) 10"));
+    }
+
+    #[test]
+    fn pretty_indents_nested_arrays() {
+        let array = Response::Array(vec![bulk("a"), Response::Array(vec![bulk("b"), bulk("c")])]);
+
+        assert_eq!(
+            "1) \"a\"\n2) 1) \"b\"\n   2) \"c\"",
+            format_response(&array, Format::Pretty)
+        );
+    }
+
+    #[test]
+    fn pretty_aligns_values_of_maps() {
+        let map = Response::Map(vec![
+            ("name".to_string(), bulk("alice")),
+            ("age".to_string(), Response::Integer(30)),
+        ]);
+
+        assert_eq!(
+            "1) name \"alice\"\n2) age  (integer) 30",
+            format_response(&map, Format::Pretty)
+        );
+    }
+
+    #[test]
+    fn pretty_marks_empty_arrays_and_maps() {
+        assert_eq!(
+            "(empty array)",
+            format_response(&Response::Array(vec![]), Format::Pretty)
+        );
+        assert_eq!(
+            "(empty hash)",
+            format_response(&Response::Map(vec![]), Format::Pretty)
+        );
+    }
+
+    #[test]
+    fn json_writes_single_replies_as_typed_values() {
+        assert_eq!("\"OK\"", format_response(&Response::ok(), Format::Json));
+        assert_eq!("\"alice\"", format_response(&bulk("alice"), Format::Json));
+        assert_eq!("-5", format_response(&Response::Integer(-5), Format::Json));
+        assert_eq!("null", format_response(&Response::Nil, Format::Json));
+    }
+
+    #[test]
+    fn json_writes_errors_as_objects() {
+        let reply = Response::from_line("ERR Invalid command: UNKNOWN.");
+
+        assert_eq!(
+            "{\"error\":\"Invalid command: UNKNOWN.\"}",
+            format_response(&reply, Format::Json)
+        );
+    }
+
+    #[test]
+    fn json_escapes_strings() {
+        assert_eq!(
+            "\"say \\\"hi\\\"\\n\\\\ \\u0007\"",
+            format_response(&bulk("say \"hi\"\n\\ \u{7}"), Format::Json)
+        );
+    }
+
+    #[test]
+    fn json_writes_arrays_and_maps_on_one_line() {
+        let response = Response::Map(vec![
+            ("name".to_string(), bulk("alice")),
+            (
+                "tags".to_string(),
+                Response::Array(vec![bulk("a"), Response::Integer(1), Response::Nil]),
+            ),
+            ("empty".to_string(), Response::Array(vec![])),
+        ]);
+
+        assert_eq!(
+            "{\"name\":\"alice\",\"tags\":[\"a\",1,null],\"empty\":[]}",
+            format_response(&response, Format::Json)
+        );
+    }
+
+    #[test]
+    fn csv_writes_single_replies_as_one_field() {
+        assert_eq!("OK", format_response(&Response::ok(), Format::Csv));
+        assert_eq!("5", format_response(&Response::Integer(5), Format::Csv));
+        assert_eq!("", format_response(&Response::Nil, Format::Csv));
+        assert_eq!(
+            "ERR Invalid command: UNKNOWN.",
+            format_response(
+                &Response::from_line("ERR Invalid command: UNKNOWN."),
+                Format::Csv
+            )
+        );
+    }
+
+    #[test]
+    fn csv_writes_a_row_per_element_and_field() {
+        let array = Response::Array(vec![
+            bulk("a"),
+            Response::Array(vec![bulk("b"), Response::Integer(2), Response::Nil]),
+        ]);
+        let map = Response::Map(vec![
+            ("name".to_string(), bulk("alice")),
+            (
+                "tags".to_string(),
+                Response::Array(vec![bulk("x"), bulk("y")]),
+            ),
+        ]);
+
+        assert_eq!("a\nb,2,", format_response(&array, Format::Csv));
+        assert_eq!("name,alice\ntags,x,y", format_response(&map, Format::Csv));
+        assert_eq!("", format_response(&Response::Array(vec![]), Format::Csv));
+    }
+
+    #[test]
+    fn csv_quotes_fields_that_need_it() {
+        let array = Response::Array(vec![Response::Array(vec![
+            bulk("a,b"),
+            bulk("say \"hi\""),
+            bulk("two\nlines"),
+            bulk("plain"),
+        ])]);
+
+        assert_eq!(
+            "\"a,b\",\"say \"\"hi\"\"\",\"two\nlines\",plain",
+            format_response(&array, Format::Csv)
+        );
+    }
+}
@@ -0,0 +1,161 @@
+/// Checks whether a text matches a glob-style pattern.
+///
+/// The pattern supports the same syntax as Redis:
+///
+/// * `*` matches any sequence of characters, including an empty one.
+/// * `?` matches exactly one character.
+/// * `[abc]` matches one of the listed characters, `[a-z]` a range,
+///   and `[^abc]` any character that is not listed.
+/// * `\` escapes the next character so it is matched literally.
+///
+/// # Arguments
+///
+/// * `pattern` - The glob pattern.
+/// * `text` - The text to match against the pattern.
+///
+/// # Returns
+///
+/// True if the whole text matches the pattern, false otherwise.
+pub(crate) fn matches(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let text = text.chars().collect::<Vec<char>>();
+    matches_from(&pattern, &text)
+}
+
+/// Matches the remaining pattern against the remaining text.
+///
+/// # Arguments
+///
+/// * `pattern` - The remaining pattern.
+/// * `text` - The remaining text.
+///
+/// # Returns
+///
+/// True if the remaining text matches the remaining pattern.
+fn matches_from(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') => {
+            let rest = &pattern[1..];
+            (0..=text.len()).any(|skip| matches_from(rest, &text[skip..]))
+        }
+        Some('?') => !text.is_empty() && matches_from(&pattern[1..], &text[1..]),
+        Some('[') => match (text.first(), class_end(pattern)) {
+            (Some(c), Some(end)) => {
+                class_matches(&pattern[1..end], *c) && matches_from(&pattern[end + 1..], &text[1..])
+            }
+            // An unterminated class is matched as a literal '['.
+            (Some('['), None) => matches_from(&pattern[1..], &text[1..]),
+            _ => false,
+        },
+        Some('\\') if pattern.len() > 1 => {
+            text.first() == Some(&pattern[1]) && matches_from(&pattern[2..], &text[1..])
+        }
+        Some(c) => text.first() == Some(c) && matches_from(&pattern[1..], &text[1..]),
+    }
+}
+
+/// Finds the index of the `]` closing the class that starts the pattern.
+///
+/// # Arguments
+///
+/// * `pattern` - The pattern, starting with `[`.
+///
+/// # Returns
+///
+/// The index of the closing bracket, or None if the class is not terminated.
+fn class_end(pattern: &[char]) -> Option<usize> {
+    let mut i = 1;
+    while i < pattern.len() {
+        match pattern[i] {
+            '\\' => i += 2,
+            ']' => return Some(i),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// Checks whether a character is part of a character class.
+///
+/// # Arguments
+///
+/// * `class` - The contents of the class, without the brackets.
+/// * `c` - The character to check.
+///
+/// # Returns
+///
+/// True if the character matches the class.
+fn class_matches(class: &[char], c: char) -> bool {
+    let (negated, class) = match class.first() {
+        Some('^') => (true, &class[1..]),
+        _ => (false, class),
+    };
+
+    let mut found = false;
+    let mut i = 0;
+    while i < class.len() {
+        if class[i] == '\\' && i + 1 < class.len() {
+            found |= class[i + 1] == c;
+            i += 2;
+        } else if i + 2 < class.len() && class[i + 1] == '-' {
+            let (low, high) = if class[i] <= class[i + 2] {
+                (class[i], class[i + 2])
+            } else {
+                (class[i + 2], class[i])
+            };
+            found |= low <= c && c <= high;
+            i += 3;
+        } else {
+            found |= class[i] == c;
+            i += 1;
+        }
+    }
+    found != negated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_literal_text() {
+        assert!(matches("key", "key"));
+        assert!(!matches("key", "keys"));
+    }
+
+    #[test]
+    fn star_matches_any_sequence() {
+        assert!(matches("user:*", "user:"));
+        assert!(matches("user:*", "user:42"));
+        assert!(matches("*:name", "user:42:name"));
+        assert!(!matches("user:*", "session:42"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_character() {
+        assert!(matches("h?llo", "hello"));
+        assert!(!matches("h?llo", "hllo"));
+    }
+
+    #[test]
+    fn class_matches_listed_characters_and_ranges() {
+        assert!(matches("h[ae]llo", "hallo"));
+        assert!(!matches("h[ae]llo", "hillo"));
+        assert!(matches("key[0-9]", "key7"));
+        assert!(!matches("key[0-9]", "keyx"));
+    }
+
+    #[test]
+    fn negated_class_matches_unlisted_characters() {
+        assert!(matches("h[^e]llo", "hallo"));
+        assert!(!matches("h[^e]llo", "hello"));
+    }
+
+    #[test]
+    fn backslash_escapes_special_characters() {
+        assert!(matches("what\\?", "what?"));
+        assert!(!matches("what\\?", "whats"));
+        assert!(matches("star\\*", "star*"));
+    }
+}
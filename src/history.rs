@@ -0,0 +1,273 @@
+use crate::command::Command;
+use crate::error::MiniRedisError;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// The name of the history file in the home directory.
+pub(crate) const HISTORY_FILE_NAME: &str = ".miniredis_history";
+
+/// How many commands the history keeps by default.
+pub(crate) const DEFAULT_HISTORY_SIZE: usize = 1000;
+
+/// The commands typed into the prompt, kept across sessions in a file.
+///
+/// Commands are appended to the file when the prompt ends, so several clients
+/// sharing a file each add their own commands instead of overwriting the others'.
+/// Once the file holds more commands than the history keeps,
+/// it is rewritten with only the newest ones.
+#[derive(Debug)]
+pub(crate) struct History {
+    path: Option<PathBuf>,
+    max_len: usize,
+    entries: Vec<String>,
+    unsaved: Vec<String>,
+}
+
+impl History {
+    /// Creates a history that is not kept in a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_len` - How many commands to keep.
+    ///
+    /// # Returns
+    ///
+    /// An empty history.
+    pub(crate) fn in_memory(max_len: usize) -> Self {
+        Self {
+            path: None,
+            max_len,
+            entries: Vec::new(),
+            unsaved: Vec::new(),
+        }
+    }
+
+    /// Loads the history from a file.
+    ///
+    /// A file that does not exist yet is an empty history.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the history file.
+    /// * `max_len` - How many commands to keep.
+    ///
+    /// # Returns
+    ///
+    /// The newest commands of the file, up to `max_len` of them.
+    ///
+    /// # Errors
+    ///
+    /// If the file exists but cannot be read, it will return [`MiniRedisError::FileNotReadable`].
+    pub(crate) fn load(path: &Path, max_len: usize) -> Result<Self, MiniRedisError> {
+        let mut history = Self::in_memory(max_len);
+        history.path = Some(path.to_path_buf());
+        match fs::read_to_string(path) {
+            Ok(contents) => history.entries = tail(contents.lines(), max_len),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(source) => {
+                return Err(MiniRedisError::FileNotReadable {
+                    path: path.display().to_string(),
+                    source,
+                });
+            }
+        }
+        Ok(history)
+    }
+
+    /// Adds a command to the history.
+    ///
+    /// Blank lines, repeats of the last command, and `AUTH` commands,
+    /// which hold a password, are not added.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The command as it was typed.
+    pub(crate) fn push(&mut self, line: &str) {
+        let line = line.trim();
+        let secret = Command::parse(line).is_some_and(|command| command.name == "AUTH");
+        if line.is_empty() || secret || self.entries.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.entries.push(line.to_string());
+        self.unsaved.push(line.to_string());
+        if self.entries.len() > self.max_len {
+            self.entries.remove(0);
+        }
+    }
+
+    /// Gets the commands of the history.
+    ///
+    /// # Returns
+    ///
+    /// The commands, from the oldest to the newest.
+    pub(crate) fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Appends the commands added since loading to the history file,
+    /// and trims the file to the newest commands if it grew too long.
+    ///
+    /// A history that is not kept in a file is left as it is.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the history was saved.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be read or written, it will return an error.
+    pub(crate) fn save(&mut self) -> Result<(), MiniRedisError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let not_writable = |source| MiniRedisError::FileNotWritable {
+            path: path.display().to_string(),
+            source,
+        };
+        if !self.unsaved.is_empty() {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(not_writable)?;
+            let lines = self.unsaved.iter().map(|line| format!("{}\n", line));
+            file.write_all(lines.collect::<String>().as_bytes())
+                .map_err(not_writable)?;
+            self.unsaved.clear();
+        }
+
+        let contents =
+            fs::read_to_string(path).map_err(|source| MiniRedisError::FileNotReadable {
+                path: path.display().to_string(),
+                source,
+            })?;
+        if contents.lines().count() <= self.max_len {
+            return Ok(());
+        }
+        // Rename a trimmed copy over the file, so a client reading it never sees half of it.
+        let temp_path = path.with_extension(format!("tmp-{}", std::process::id()));
+        let lines = tail(contents.lines(), self.max_len);
+        let trimmed = lines.iter().map(|line| format!("{}\n", line));
+        fs::write(&temp_path, trimmed.collect::<String>()).map_err(not_writable)?;
+        fs::rename(&temp_path, path).map_err(not_writable)
+    }
+}
+
+/// Finds the history file in the home directory.
+///
+/// # Returns
+///
+/// The path of the history file, or None if there is no home directory.
+pub(crate) fn default_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE_NAME))
+}
+
+/// Keeps the last lines of many.
+///
+/// # Arguments
+///
+/// * `lines` - The lines.
+/// * `max_len` - How many lines to keep.
+///
+/// # Returns
+///
+/// The last `max_len` lines that are not blank, in order.
+fn tail<'a>(lines: impl Iterator<Item = &'a str>, max_len: usize) -> Vec<String> {
+    let lines = lines
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<&str>>();
+    let start = lines.len().saturating_sub(max_len);
+    lines[start..].iter().map(|line| line.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a path in the temporary directory, unique to this process, with no file at it.
+    fn temp_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("miniredis-{}-{}", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn history_survives_saving_and_loading() {
+        let path = temp_file("history-reload");
+        let mut history = History::load(&path, 10).unwrap();
+
+        history.push("SET a 1");
+        history.push("GET a");
+        history.save().unwrap();
+
+        assert_eq!(
+            vec!["SET a 1", "GET a"],
+            History::load(&path, 10).unwrap().entries()
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn push_skips_blank_lines_repeats_and_passwords() {
+        let mut history = History::in_memory(10);
+
+        for line in [
+            "GET a",
+            "GET a",
+            "  ",
+            "auth secret",
+            "AUTH alice secret",
+            "GET b",
+        ] {
+            history.push(line);
+        }
+
+        assert_eq!(vec!["GET a", "GET b"], history.entries());
+    }
+
+    #[test]
+    fn history_keeps_only_the_newest_commands() {
+        let path = temp_file("history-cap");
+        let mut history = History::load(&path, 3).unwrap();
+
+        for i in 0..5 {
+            history.push(&format!("GET {}", i));
+        }
+        history.save().unwrap();
+
+        assert_eq!(vec!["GET 2", "GET 3", "GET 4"], history.entries());
+        assert_eq!("GET 2\nGET 3\nGET 4\n", fs::read_to_string(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn concurrent_sessions_append_their_own_commands() {
+        let path = temp_file("history-concurrent");
+        let mut first = History::load(&path, 10).unwrap();
+        let mut second = History::load(&path, 10).unwrap();
+
+        first.push("GET first");
+        second.push("GET second");
+        first.save().unwrap();
+        second.save().unwrap();
+
+        assert_eq!(
+            vec!["GET first", "GET second"],
+            History::load(&path, 10).unwrap().entries()
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn load_fails_for_a_file_that_cannot_be_read() {
+        let path = temp_file("history-dir");
+        fs::create_dir_all(&path).unwrap();
+
+        assert!(matches!(
+            History::load(&path, 10),
+            Err(MiniRedisError::FileNotReadable { .. })
+        ));
+        fs::remove_dir_all(&path).unwrap();
+    }
+}
@@ -0,0 +1,97 @@
+use crate::command::Command;
+use crate::response::Response;
+use std::time::Duration;
+
+/// What the server knows about the client that sent a command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectionInfo {
+    /// The number of the connection, counting up from 1 since the server started.
+    pub id: u64,
+    /// The address of the client, if the server could read it.
+    pub address: Option<String>,
+}
+
+impl std::fmt::Display for ConnectionInfo {
+    /// Formats the client for logs, like `client 3 (127.0.0.1:50000)`.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - The formatter to write the client to.
+    ///
+    /// # Errors
+    ///
+    /// If the client cannot be formatted, it will return an error.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.address {
+            Some(address) => write!(f, "client {} ({})", self.id, address),
+            None => write!(f, "client {} (unknown address)", self.id),
+        }
+    }
+}
+
+/// Behavior that runs around every command the server executes,
+/// like logging requests, rewriting keys, or rejecting commands.
+///
+/// Hooks are added to a server with [`ServerBuilder::hook`](crate::server::ServerBuilder::hook),
+/// and run in the order they were added. Both methods do nothing by default,
+/// so a hook only implements the ones it needs.
+///
+/// # Examples
+///
+/// ```rust
+/// use miniredis::command::Command;
+/// use miniredis::hook::{CommandHook, ConnectionInfo};
+/// use miniredis::response::Response;
+/// use miniredis::server::Server;
+///
+/// /// Keeps the keys of every client under a prefix of its own.
+/// struct TenantPrefix;
+///
+/// impl CommandHook for TenantPrefix {
+///     fn before(&self, client: &ConnectionInfo, command: &mut Command) -> Option<Response> {
+///         if let Some(key) = command.args.first_mut() {
+///             *key = format!("tenant:{}:{}", client.id, key);
+///         }
+///         None
+///     }
+/// }
+///
+/// let server = Server::builder("127.0.0.1:6379")
+///     .hook(Box::new(TenantPrefix))
+///     .build();
+/// ```
+pub trait CommandHook: Send + Sync {
+    /// Runs before a command is executed, and may change it or answer it instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The client that sent the command.
+    /// * `command` - The command, which the hook may change before it is executed.
+    ///
+    /// # Returns
+    ///
+    /// None to go on with the command, or the reply to send instead of executing it,
+    /// in which case the hooks after this one do not run before it.
+    fn before(&self, client: &ConnectionInfo, command: &mut Command) -> Option<Response> {
+        let _ = (client, command);
+        None
+    }
+
+    /// Runs after a command is answered, whether it was executed or a hook answered it.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The client that sent the command.
+    /// * `command` - The command, as the hooks before it left it.
+    /// * `response` - The reply to the command.
+    /// * `elapsed` - How long the hooks and the command took.
+    fn after(
+        &self,
+        client: &ConnectionInfo,
+        command: &Command,
+        response: &Response,
+        elapsed: Duration,
+    ) {
+        let _ = (client, command, response, elapsed);
+    }
+}
@@ -0,0 +1,73 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Whether Ctrl+C was pressed since [`catch`] was called.
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// The longest [`sleep`] waits before checking for an interrupt again.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Makes Ctrl+C request a graceful stop instead of killing the process.
+///
+/// Once called, the process no longer exits on Ctrl+C,
+/// so the caller must check [`requested`] regularly and stop by itself.
+/// On platforms other than Unix this does nothing, and Ctrl+C still kills the process.
+pub(crate) fn catch() {
+    #[cfg(unix)]
+    unix::catch_sigint();
+}
+
+/// Checks whether Ctrl+C was pressed since [`catch`] was called.
+///
+/// # Returns
+///
+/// True if a stop was requested.
+pub(crate) fn requested() -> bool {
+    REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Sleeps, waking up early if Ctrl+C is pressed.
+///
+/// # Arguments
+///
+/// * `duration` - How long to sleep.
+pub(crate) fn sleep(duration: Duration) {
+    let deadline = Instant::now() + duration;
+    while !requested() {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return;
+        }
+        thread::sleep(left.min(POLL_INTERVAL));
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::REQUESTED;
+    use std::sync::atomic::Ordering;
+
+    /// The number of the signal sent by Ctrl+C.
+    const SIGINT: i32 = 2;
+
+    unsafe extern "C" {
+        /// Installs a signal handler, from the C library.
+        fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    }
+
+    /// Records the interrupt. Only touches an atomic, so it is safe in a signal handler.
+    extern "C" fn on_sigint(_: i32) {
+        REQUESTED.store(true, Ordering::Relaxed);
+    }
+
+    /// Installs the handler for Ctrl+C.
+    pub(super) fn catch_sigint() {
+        // SAFETY: the handler only stores to an atomic, which is async-signal-safe.
+        unsafe {
+            signal(SIGINT, on_sigint);
+        }
+    }
+}
@@ -1,14 +1,70 @@
 use std::{
     collections::HashMap,
-    sync::{Arc, Mutex, MutexGuard},
+    path::Path,
+    sync::{
+        Arc, Mutex, MutexGuard,
+        mpsc::{self, Receiver, Sender},
+    },
+    time::{Duration, Instant, SystemTime},
 };
 
+use crate::backing::{Backing, Loader, Writer};
 use crate::error::MiniRedisError;
+use crate::eviction::{Capacity, EvictionPolicy};
+use crate::glob;
+use crate::namespace::NamespacedStore;
+use crate::rng::Rng;
+use crate::snapshot;
+use crate::stats::{Counters, KeyspaceStats, StoreStats};
+use crate::storage::{
+    self, ExpireCondition, KeyInfo, ScanIndex, SetCondition, SetExpiry, SetOptions,
+};
 
-/// A key-value store that can be shared between threads.
+/// The kind of change a [`KeyEvent`] describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEventKind {
+    /// The key was set to a new value.
+    Set,
+    /// The key was deleted.
+    Del,
+    /// The key was evicted to make room for another write.
+    Evicted,
+    /// The key was removed because its time to live ran out.
+    Expired,
+}
+
+/// A change to a key, delivered to subscribers of the store.
 ///
-/// KVStore is a thread-safe key-value store that can be used to store and retrieve data between threads.
-/// It includes a set of methods to get, set, and delete key-value pairs.
+/// # Examples
+///
+/// ```rust
+/// use miniredis::kv_store::{KVStore, KeyEvent, KeyEventKind};
+///
+/// let store = KVStore::new();
+/// let events = store.subscribe("user:*");
+///
+/// store.set("user:1", "alice").unwrap();
+///
+/// let event = events.recv().unwrap();
+/// assert_eq!("user:1", event.key);
+/// assert_eq!(KeyEventKind::Set, event.kind);
+/// assert_eq!(Some("alice".to_string()), event.value);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyEvent {
+    /// The key that changed.
+    pub key: String,
+    /// The kind of change.
+    pub kind: KeyEventKind,
+    /// The new value of the key, if the change gave it one.
+    pub value: Option<String>,
+}
+
+/// A point-in-time copy of all entries of a store.
+///
+/// A snapshot is taken with [`KVStore::snapshot_handle`] and is independent
+/// of the store afterwards, so it can be read at leisure, for example
+/// to write a backup, while writes to the store continue.
 ///
 /// # Examples
 ///
@@ -16,22 +72,24 @@ use crate::error::MiniRedisError;
 /// use miniredis::kv_store::KVStore;
 ///
 /// let store = KVStore::new();
-/// 
-/// store.set("key", "value");
-/// let value = store.get("key");
-/// 
-/// assert_eq!(Ok(Some("value".to_string())), value);
+/// store.set("key", "value").unwrap();
+///
+/// let snapshot = store.snapshot_handle().unwrap();
+/// store.set("key", "changed").unwrap();
+///
+/// assert_eq!(vec![("key", &b"value"[..])], snapshot.iter().collect::<Vec<_>>());
 /// ```
-pub struct KVStore {
-    store: Arc<Mutex<HashMap<String, String>>>,
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoreSnapshot {
+    entries: Vec<(String, Vec<u8>)>,
 }
 
-impl KVStore {
-    /// Creates a new KVStore.
+impl StoreSnapshot {
+    /// Gets the number of entries in the snapshot.
     ///
     /// # Returns
     ///
-    /// A new KVStore.
+    /// The number of entries the store held when the snapshot was taken.
     ///
     /// # Examples
     ///
@@ -39,26 +97,19 @@ impl KVStore {
     /// use miniredis::kv_store::KVStore;
     ///
     /// let store = KVStore::new();
+    /// store.set("key", "value").unwrap();
+    ///
+    /// assert_eq!(1, store.snapshot_handle().unwrap().len());
     /// ```
-    pub fn new() -> Self {
-        Self {
-            store: Arc::new(Mutex::new(HashMap::new())),
-        }
+    pub fn len(&self) -> usize {
+        self.entries.len()
     }
 
-    /// Gets a value from the store.
-    ///
-    /// # Arguments
-    ///
-    /// * `key` - The key to get the value for.
+    /// Checks whether the snapshot has no entries.
     ///
     /// # Returns
     ///
-    /// The value associated with the key, or None if the key is not found.
-    ///
-    /// # Errors
-    ///
-    /// If the store is already locked, it will return an error.
+    /// True if the store was empty when the snapshot was taken.
     ///
     /// # Examples
     ///
@@ -66,55 +117,18 @@ impl KVStore {
     /// use miniredis::kv_store::KVStore;
     ///
     /// let store = KVStore::new();
-    /// 
-    /// store.set("key", "value");
-    /// let value = store.get("key");
-    /// 
-    /// assert_eq!(Ok(Some("value".to_string())), value);
-    /// ```
-    pub fn get(&self, key: &str) -> Result<Option<String>, MiniRedisError> {
-        let store = self.get_store()?;
-        Ok(store.get(key).cloned())
-    }
-
-    /// Sets a value in the store.
-    ///
-    /// # Arguments
-    ///
-    /// * `key` - The key to set the value for.
-    /// * `value` - The value to set.
-    ///
-    /// # Errors
     ///
-    /// If the store is already locked, it will return an error.
-    ///
-    /// # Examples
-    ///
-    /// ```rust
-    /// use miniredis::kv_store::KVStore;
-    ///
-    /// let store = KVStore::new();
-    /// 
-    /// store.set("key", "value");
-    /// let value = store.get("key");
-    /// 
-    /// assert_eq!(Ok(Some("value".to_string())), value);
+    /// assert!(store.snapshot_handle().unwrap().is_empty());
     /// ```
-    pub fn set(&self, key: &str, value: &str) -> Result<(), MiniRedisError> {
-        let mut store = self.get_store()?;
-        store.insert(key.to_string(), value.to_string());
-        Ok(())
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
     }
 
-    /// Deletes a value from the store.
-    ///
-    /// # Arguments
-    ///
-    /// * `key` - The key to delete the value for.
+    /// Iterates over the entries of the snapshot.
     ///
-    /// # Errors
+    /// # Returns
     ///
-    /// If the store is already locked, it will return an error.
+    /// An iterator over the keys and values, in no particular order.
     ///
     /// # Examples
     ///
@@ -122,125 +136,5112 @@ impl KVStore {
     /// use miniredis::kv_store::KVStore;
     ///
     /// let store = KVStore::new();
-    /// 
-    /// store.set("key", "value");
-    /// store.del("key");
-    /// 
-    /// let value = store.get("key");
-    /// 
-    /// assert_eq!(Ok(None), value);
+    /// store.set("key", "value").unwrap();
+    ///
+    /// for (key, value) in store.snapshot_handle().unwrap().iter() {
+    ///     println!("{} = {}", key, String::from_utf8_lossy(value));
+    /// }
     /// ```
-    pub fn del(&self, key: &str) -> Result<(), MiniRedisError> {
-        let mut store = self.get_store()?;
-        store.remove(key);
-        Ok(())
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.entries
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_slice()))
     }
+}
 
-    /// Gets a mutable reference to the store.
+impl IntoIterator for StoreSnapshot {
+    type Item = (String, Vec<u8>);
+    type IntoIter = std::vec::IntoIter<(String, Vec<u8>)>;
+
+    /// Turns the snapshot into an iterator over its entries.
     ///
     /// # Returns
     ///
-    /// A mutable reference to the store.
-    ///
-    /// # Errors
-    ///
-    /// If the store is already locked, it will return an error.
-    fn get_store(&self) -> Result<MutexGuard<HashMap<String, String>>, MiniRedisError> {
-        self.store.lock().map_err(|_| MiniRedisError::StoreLocked)
+    /// An iterator over the owned keys and values, in no particular order.
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// The estimated number of bytes an entry uses besides its key and value.
+///
+/// This covers the key and value headers of the entry and the hash map
+/// bookkeeping around it, so it is an approximation rather than an exact figure.
+pub const ENTRY_OVERHEAD: usize = 64;
 
-    #[test]
-    fn new_creates_empty_store() {
-        let store = KVStore::new();
+/// A value in the store, together with the metadata used for eviction and expiry.
+#[derive(Clone)]
+struct Entry {
+    value: Vec<u8>,
+    last_access: u64,
+    /// When the entry was last read or written, which only `describe` reports.
+    accessed_at: Instant,
+    hits: u64,
+    expires_at: Option<Instant>,
+}
 
-        assert_eq!(Ok(None), store.get("key"));
+impl Entry {
+    /// Checks whether the time to live of the entry has run out.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time.
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
     }
+}
 
-    #[test]
-    fn get_returns_value_if_set() {
-        let store = KVStore::new();
-
-        store.set("key", "value").unwrap();
+/// The entries of a store, together with their estimated memory usage.
+///
+/// All changes to the entries go through `insert` and `remove`,
+/// which keep the memory estimate and the scan order up to date incrementally.
+///
+/// Expired entries are removed lazily: a lookup of an expired key removes it,
+/// and everything else that reads entries skips them until then.
+struct Data {
+    entries: HashMap<String, Entry>,
+    /// The keys in the order `SCAN` visits them.
+    scan_order: ScanIndex,
+    memory: usize,
+    clock: u64,
+    limit: Option<(Capacity, EvictionPolicy)>,
+    rng: Rng,
+}
 
-        assert_eq!(Ok(Some("value".to_string())), store.get("key"));
+impl Data {
+    /// Creates the data of an empty store.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The number of entries to allocate room for up front.
+    fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::with_capacity(capacity),
+            scan_order: ScanIndex::default(),
+            memory: 0,
+            clock: 0,
+            limit: None,
+            rng: Rng::new(),
+        }
     }
 
-    #[test]
-    fn get_returns_none_if_not_set() {
-        let store = KVStore::new();
-
-        assert_eq!(Ok(None), store.get("key"));
+    /// Copies the data into an independent instance.
+    fn duplicate(&self) -> Self {
+        Self {
+            entries: self.entries.clone(),
+            scan_order: self.scan_order.clone(),
+            memory: self.memory,
+            clock: self.clock,
+            limit: self.limit,
+            rng: Rng::new(),
+        }
     }
 
-    #[test]
-    fn get_returns_none_if_not_set_and_other_key_is_set() {
-        let store = KVStore::new();
-
-        store.set("key", "value").unwrap();
-
-        assert_eq!(Ok(None), store.get("other_key"));
+    /// Gets the value of a key, recording the access for eviction.
+    fn get(&mut self, key: &str) -> Option<&[u8]> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.entries.get_mut(key).map(|entry| {
+            entry.last_access = clock;
+            entry.accessed_at = Instant::now();
+            entry.hits = entry.hits.saturating_add(1);
+            entry.value.as_slice()
+        })
     }
 
-    #[test]
-    fn get_returns_value_if_set_and_other_key_is_set() {
-        let store = KVStore::new();
-
-        store.set("key", "value").unwrap();
-        store.set("other_key", "other_value").unwrap();
-
-        assert_eq!(Ok(Some("value".to_string())), store.get("key"));
+    /// Gets the value of a key without recording an access.
+    fn peek(&self, key: &str) -> Option<&[u8]> {
+        self.entries.get(key).map(|entry| entry.value.as_slice())
     }
 
-    #[test]
-    fn get_returns_none_if_deleted() {
-        let store = KVStore::new();
+    /// Gets the value of a key without recording an access, unless it has expired.
+    fn peek_live(&self, key: &str) -> Option<&[u8]> {
+        self.entries
+            .get(key)
+            .filter(|entry| !entry.is_expired(Instant::now()))
+            .map(|entry| entry.value.as_slice())
+    }
 
-        store.set("key", "value").unwrap();
-        store.del("key").unwrap();
+    /// Iterates over the entries that have not expired.
+    ///
+    /// # Returns
+    ///
+    /// An iterator over the keys and entries, in no particular order.
+    fn live(&self) -> impl Iterator<Item = (&String, &Entry)> {
+        let now = Instant::now();
+        self.entries
+            .iter()
+            .filter(move |(_, entry)| !entry.is_expired(now))
+    }
 
-        assert_eq!(Ok(None), store.get("key"));
+    /// Inserts a value, replacing any previous one and its expiry time.
+    ///
+    /// Writing a key counts as an access to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to insert.
+    /// * `value` - The value to insert.
+    /// * `expires_at` - The time the entry expires at, or None if it never expires.
+    ///
+    /// # Returns
+    ///
+    /// The previous value of the key, if any.
+    fn insert(
+        &mut self,
+        key: String,
+        value: Vec<u8>,
+        expires_at: Option<Instant>,
+    ) -> Option<Vec<u8>> {
+        self.clock += 1;
+        self.memory += entry_size(&key, &value);
+        let hits = self.entries.get(&key).map_or(0, |entry| entry.hits);
+        let entry = Entry {
+            value,
+            last_access: self.clock,
+            accessed_at: Instant::now(),
+            hits: hits.saturating_add(1),
+            expires_at,
+        };
+        let previous = self.entries.insert(key.clone(), entry);
+        if previous.is_none() {
+            self.scan_order.insert(&key);
+        }
+        previous.map(|previous| {
+            self.memory -= entry_size(&key, &previous.value);
+            previous.value
+        })
     }
 
-    #[test]
-    fn set_sets_value() {
-        let store = KVStore::new();
+    /// Removes a key.
+    ///
+    /// # Returns
+    ///
+    /// The removed value, if the key existed.
+    fn remove(&mut self, key: &str) -> Option<Vec<u8>> {
+        let removed = self.entries.remove(key);
+        removed.map(|entry| {
+            self.scan_order.remove(key);
+            self.memory -= entry_size(key, &entry.value);
+            entry.value
+        })
+    }
 
-        store.set("key", "value").unwrap();
+    /// Removes a key if its time to live has run out.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to check.
+    ///
+    /// # Returns
+    ///
+    /// True if the key had expired and was removed.
+    fn expire(&mut self, key: &str) -> bool {
+        let expired = self
+            .entries
+            .get(key)
+            .and_then(|entry| entry.expires_at)
+            .is_some_and(|at| at <= Instant::now());
+        if expired {
+            self.remove(key);
+        }
+        expired
+    }
 
-        assert_eq!(Ok(Some("value".to_string())), store.get("key"));
+    /// Removes every key whose time to live has run out.
+    ///
+    /// # Returns
+    ///
+    /// The removed keys.
+    fn remove_expired(&mut self) -> Vec<String> {
+        let now = Instant::now();
+        let expired = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.is_expired(now))
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<String>>();
+        for key in &expired {
+            self.remove(key);
+        }
+        expired
     }
 
-    #[test]
-    fn delete_deletes_value() {
-        let store = KVStore::new();
+    /// Makes room for writing a value, according to the capacity and policy of the store.
+    ///
+    /// Must be called before inserting, and in the same critical section,
+    /// so the store never exceeds its capacity.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key that will be written. It is never evicted.
+    /// * `value_len` - The length of the value that will be written.
+    ///
+    /// # Returns
+    ///
+    /// The keys that were evicted to make room.
+    ///
+    /// # Errors
+    ///
+    /// If the write cannot fit, or the policy is to reject it, it will return an error.
+    fn make_room(&mut self, key: &str, value_len: usize) -> Result<Vec<String>, MiniRedisError> {
+        let (capacity, policy) = match self.limit {
+            Some(limit) => limit,
+            None => return Ok(Vec::new()),
+        };
+        let fits_alone = match capacity {
+            Capacity::Entries(max) => max >= 1,
+            Capacity::Bytes(max) => key.len() + value_len + ENTRY_OVERHEAD <= max,
+        };
+        if !fits_alone {
+            return Err(MiniRedisError::StoreFull);
+        }
+        let existing_len = self.peek(key).map(|value| value.len());
 
-        store.set("key", "value").unwrap();
-        store.del("key").unwrap();
+        let mut evicted = Vec::new();
+        loop {
+            let fits = match capacity {
+                Capacity::Entries(max) => {
+                    self.entries.len() + usize::from(existing_len.is_none()) <= max
+                }
+                Capacity::Bytes(max) => {
+                    let freed = existing_len.map_or(0, |len| entry_size(key, b"") + len);
+                    self.memory - freed + key.len() + value_len + ENTRY_OVERHEAD <= max
+                }
+            };
+            if fits {
+                return Ok(evicted);
+            }
+            if policy == EvictionPolicy::Reject {
+                return Err(MiniRedisError::StoreFull);
+            }
+            match self.choose_victim(key, policy) {
+                Some(victim) => {
+                    self.remove(&victim);
+                    evicted.push(victim);
+                }
+                // Unreachable, as the write fits once every other key is gone.
+                None => return Err(MiniRedisError::StoreFull),
+            }
+        }
+    }
 
-        assert_eq!(Ok(None), store.get("key"));
+    /// Chooses the entry to evict next.
+    ///
+    /// # Arguments
+    ///
+    /// * `exclude` - The key that must not be chosen.
+    /// * `policy` - The eviction policy.
+    ///
+    /// # Returns
+    ///
+    /// The key to evict, or None if there is no other key.
+    fn choose_victim(&mut self, exclude: &str, policy: EvictionPolicy) -> Option<String> {
+        let candidates = self
+            .entries
+            .iter()
+            .filter(|(key, _)| key.as_str() != exclude);
+        let victim = match policy {
+            EvictionPolicy::Lru => candidates.min_by_key(|(_, entry)| entry.last_access),
+            EvictionPolicy::Lfu => {
+                candidates.min_by_key(|(_, entry)| (entry.hits, entry.last_access))
+            }
+            EvictionPolicy::Random => {
+                let count = self.entries.len() - usize::from(self.entries.contains_key(exclude));
+                if count == 0 {
+                    return None;
+                }
+                let index = self.rng.below(count);
+                self.entries
+                    .iter()
+                    .filter(|(key, _)| key.as_str() != exclude)
+                    .nth(index)
+            }
+            EvictionPolicy::Reject => None,
+        };
+        victim.map(|(key, _)| key.clone())
     }
+}
 
-    #[test]
-    fn delete_does_nothing_if_key_not_set() {
-        let store = KVStore::new();
+/// Estimates the memory used by an entry.
+///
+/// # Arguments
+///
+/// * `key` - The key of the entry.
+/// * `value` - The value of the entry.
+///
+/// # Returns
+///
+/// The length of the key and value plus [`ENTRY_OVERHEAD`].
+fn entry_size(key: &str, value: &[u8]) -> usize {
+    key.len() + value.len() + ENTRY_OVERHEAD
+}
 
-        store.del("key").unwrap();
+/// Turns a time to live into the moment it runs out.
+///
+/// # Arguments
+///
+/// * `command` - The name of the command setting the time to live, for the error.
+/// * `ttl` - The time to live.
+///
+/// # Returns
+///
+/// The moment the time to live runs out.
+///
+/// # Errors
+///
+/// If the moment is beyond what the clock can hold, it will return
+/// [`MiniRedisError::InvalidExpireTime`].
+fn deadline(command: &str, ttl: Duration) -> Result<Instant, MiniRedisError> {
+    Instant::now()
+        .checked_add(ttl)
+        .ok_or_else(|| MiniRedisError::InvalidExpireTime {
+            command: command.to_string(),
+        })
+}
 
-        assert_eq!(Ok(None), store.get("key"));
-    }
+/// The number of entries bulk operations, like [`KVStore::for_each`],
+/// handle per lock acquisition.
+const CHUNK_SIZE: usize = 1024;
 
-    #[test]
-    fn delete_does_nothing_if_key_not_set_and_other_key_is_set() {
-        let store = KVStore::new();
+/// The writes of a batch, gathered under the lock by [`KVStore::insert_batch`]
+/// and passed on by [`KVStore::finish_batch`] once it is released.
+#[derive(Default)]
+struct Batch {
+    /// The values to write through to the backing store, with their tickets.
+    writes: Vec<(u64, String, String)>,
+    /// The events for subscribers, in the order they happened.
+    events: Vec<(String, KeyEventKind, Option<String>)>,
+    /// The number of entries written.
+    written: usize,
+    /// The number of entries evicted to make room.
+    evicted: usize,
+}
 
-        store.set("other_key", "other_value").unwrap();
-        store.del("key").unwrap();
+/// A subscription to changes of the keys matching a pattern.
+struct Subscriber {
+    pattern: String,
+    sender: Sender<KeyEvent>,
+}
 
-        assert_eq!(Ok(None), store.get("key"));
+/// A key-value store that can be shared between threads.
+///
+/// KVStore is a thread-safe key-value store that can be used to store and retrieve data between threads.
+/// It includes a set of methods to get, set, and delete key-value pairs.
+///
+/// Cloning a KVStore is cheap and returns another handle to the same data:
+/// writes made through one handle are visible through every clone,
+/// and subscriptions are shared as well.
+/// Use [`KVStore::deep_clone`] for an independent copy.
+///
+/// # Examples
+///
+/// ```rust
+/// use miniredis::kv_store::KVStore;
+///
+/// let store = KVStore::new();
+///
+/// store.set("key", "value");
+/// let value = store.get("key");
+///
+/// assert_eq!(Ok(Some("value".to_string())), value);
+/// ```
+pub struct KVStore {
+    store: Arc<Mutex<Data>>,
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+    counters: Arc<Counters>,
+    backing: Arc<Backing>,
+}
+
+impl KVStore {
+    /// Creates a new KVStore.
+    ///
+    /// # Returns
+    ///
+    /// A new KVStore.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// ```
+    pub fn new() -> Self {
+        Self::from_data(Data::new(0))
+    }
+
+    /// Creates a new KVStore with room for a number of entries.
+    ///
+    /// Pre-sizing the store avoids rehashing the entries over and over
+    /// while loading a dataset of a known size.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The number of entries to allocate room for.
+    ///
+    /// # Returns
+    ///
+    /// A new, empty KVStore.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::with_capacity(1_000);
+    ///
+    /// assert!(store.capacity().unwrap() >= 1_000);
+    /// ```
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::from_data(Data::new(capacity))
+    }
+
+    /// Creates a new KVStore that never grows beyond a capacity.
+    ///
+    /// Writes that would exceed the capacity make room according to the policy,
+    /// atomically with the write itself.
+    /// Evicted keys are reported to subscribers as [`KeyEventKind::Evicted`].
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The maximum number of entries or bytes.
+    /// * `policy` - How to make room when a write would exceed the capacity.
+    ///
+    /// # Returns
+    ///
+    /// A new KVStore.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::eviction::{Capacity, EvictionPolicy};
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::with_capacity_policy(Capacity::Entries(2), EvictionPolicy::Lru);
+    ///
+    /// store.set("a", "1").unwrap();
+    /// store.set("b", "2").unwrap();
+    /// store.get("a").unwrap();
+    /// store.set("c", "3").unwrap();
+    ///
+    /// assert_eq!(Ok(None), store.get("b"));
+    /// assert_eq!(Ok(2), store.len());
+    /// ```
+    pub fn with_capacity_policy(capacity: Capacity, policy: EvictionPolicy) -> Self {
+        let mut data = Data::new(0);
+        data.limit = Some((capacity, policy));
+        Self::from_data(data)
+    }
+
+    /// Creates a builder for a store with options, like a backing store to read and write through to.
+    ///
+    /// # Returns
+    ///
+    /// A builder with every option at its default.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::builder().capacity(1_000).build();
+    /// ```
+    pub fn builder() -> KVStoreBuilder {
+        KVStoreBuilder::default()
+    }
+
+    /// Creates a new KVStore holding the given data.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data of the store.
+    ///
+    /// # Returns
+    ///
+    /// A new KVStore.
+    fn from_data(data: Data) -> Self {
+        Self {
+            store: Arc::new(Mutex::new(data)),
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+            counters: Arc::new(Counters::default()),
+            backing: Arc::new(Backing::default()),
+        }
+    }
+
+    /// Creates a new KVStore holding the given entries.
+    ///
+    /// Room for the entries is allocated up front, from the size hint of the iterator.
+    /// Duplicate keys are resolved by last-write-wins.
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - The keys, values, and expiry times of the entries.
+    ///
+    /// # Returns
+    ///
+    /// A new KVStore without a capacity limit.
+    fn from_entries(entries: impl Iterator<Item = (String, Vec<u8>, Option<Instant>)>) -> Self {
+        let mut data = Data::new(entries.size_hint().0);
+        for (key, value, expires_at) in entries {
+            data.insert(key, value, expires_at);
+        }
+        Self::from_data(data)
+    }
+
+    /// Creates an independent copy of the store.
+    ///
+    /// The entries are copied under the lock, so the copy is a consistent
+    /// snapshot of the store at the time of the call.
+    /// Later writes to either store are not visible in the other,
+    /// and the copy starts without subscribers, with zeroed stats,
+    /// and without the loader and writer of a backing store.
+    ///
+    /// # Returns
+    ///
+    /// A new KVStore with the same entries, capacity, and policy.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("key", "value").unwrap();
+    ///
+    /// let copy = store.deep_clone().unwrap();
+    /// store.set("key", "changed").unwrap();
+    ///
+    /// assert_eq!(Ok(Some("value".to_string())), copy.get("key"));
+    /// ```
+    pub fn deep_clone(&self) -> Result<KVStore, MiniRedisError> {
+        let data = self.get_store()?.duplicate();
+        Ok(Self::from_data(data))
+    }
+
+    /// Gets a value from the store.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to get the value for.
+    ///
+    /// # Returns
+    ///
+    /// The value associated with the key, or None if the key is not found.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    ///
+    /// store.set("key", "value");
+    /// let value = store.get("key");
+    ///
+    /// assert_eq!(Ok(Some("value".to_string())), value);
+    /// ```
+    pub fn get(&self, key: &str) -> Result<Option<String>, MiniRedisError> {
+        Ok(self.get_bytes(key)?.map(storage::text))
+    }
+
+    /// Gets a value from the store as the bytes it is stored as.
+    ///
+    /// Unlike [`KVStore::get`], bytes that are not valid UTF-8,
+    /// which [`KVStore::set_bit`] and [`KVStore::set_range`] can write, come back as they are.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to get the value for.
+    ///
+    /// # Returns
+    ///
+    /// The bytes of the value, or None if the key is not found.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set_bit("key", 0, true).unwrap();
+    ///
+    /// assert_eq!(Ok(Some(vec![0x80])), store.get_bytes("key"));
+    /// assert_eq!(Ok(Some("\u{fffd}".to_string())), store.get("key"));
+    /// ```
+    pub fn get_bytes(&self, key: &str) -> Result<Option<Vec<u8>>, MiniRedisError> {
+        let (value, expired) = {
+            let mut store = self.get_store()?;
+            let expired = store.expire(key);
+            (store.get(key).map(<[u8]>::to_vec), expired)
+        };
+        if expired {
+            self.expired(key);
+        }
+        self.counters.record_get(value.is_some());
+        match value {
+            None if self.backing.loads() => self.load(key),
+            value => Ok(value),
+        }
+    }
+
+    /// Sets a value in the store.
+    ///
+    /// The key never expires, even if it had a time to live before.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to set the value for.
+    /// * `value` - The value to set.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    /// If the store is full and cannot make room for the value, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    ///
+    /// store.set("key", "value");
+    /// let value = store.get("key");
+    ///
+    /// assert_eq!(Ok(Some("value".to_string())), value);
+    /// ```
+    pub fn set(&self, key: &str, value: &str) -> Result<(), MiniRedisError> {
+        let (evicted, ticket) = {
+            let mut store = self.get_store()?;
+            let evicted = store.make_room(key, value.len())?;
+            store.insert(key.to_string(), value.into(), None);
+            (evicted, self.ticket())
+        };
+        self.write_through(ticket, key, Some(value));
+        self.counters.record_set();
+        self.counters.record_evictions(evicted.len());
+        for victim in evicted {
+            self.notify(&victim, KeyEventKind::Evicted, None);
+        }
+        self.notify(key, KeyEventKind::Set, Some(value));
+        Ok(())
+    }
+
+    /// Sets a value in the store only if the key does not exist, atomically.
+    ///
+    /// The check and the write happen under one lock,
+    /// so of many callers racing for the same key, exactly one sets it.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to set the value for.
+    /// * `value` - The value to set.
+    ///
+    /// # Returns
+    ///
+    /// True if the value was set, false if the key already existed.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, or full and cannot make room for the value,
+    /// it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    ///
+    /// assert_eq!(Ok(true), store.set_if_absent("lock", "alice"));
+    /// assert_eq!(Ok(false), store.set_if_absent("lock", "bob"));
+    /// assert_eq!(Ok(Some("alice".to_string())), store.get("lock"));
+    /// ```
+    pub fn set_if_absent(&self, key: &str, value: &str) -> Result<bool, MiniRedisError> {
+        let options = SetOptions {
+            condition: Some(SetCondition::IfAbsent),
+            ..SetOptions::default()
+        };
+        self.set_with(key, value, &options)
+    }
+
+    /// Sets a value in the store if the key meets a condition, atomically,
+    /// like `SET` with its `NX`, `XX`, `EX`, `PX` and `KEEPTTL` options.
+    ///
+    /// The check and the write happen under one lock, so with [`SetExpiry::Keep`]
+    /// the key keeps the deadline it had at the moment it was overwritten.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to set the value for.
+    /// * `value` - The value to set.
+    /// * `options` - When to set the value.
+    ///
+    /// # Returns
+    ///
+    /// True if the value was set, false if the key did not meet the condition.
+    ///
+    /// # Errors
+    ///
+    /// If the time to live is too long for the clock, it will return
+    /// [`MiniRedisError::InvalidExpireTime`]. If the store is already locked,
+    /// or full and cannot make room for the value, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    /// use miniredis::storage::{SetCondition, SetOptions};
+    ///
+    /// let store = KVStore::new();
+    /// let options = SetOptions {
+    ///     condition: Some(SetCondition::IfPresent),
+    ///     ..SetOptions::default()
+    /// };
+    ///
+    /// assert_eq!(Ok(false), store.set_with("name", "alice", &options));
+    /// store.set("name", "alice").unwrap();
+    /// assert_eq!(Ok(true), store.set_with("name", "bob", &options));
+    /// assert_eq!(Ok(Some("bob".to_string())), store.get("name"));
+    /// ```
+    pub fn set_with(
+        &self,
+        key: &str,
+        value: &str,
+        options: &SetOptions,
+    ) -> Result<bool, MiniRedisError> {
+        // The backing store may hold the key even if this store never loaded it.
+        if options.condition.is_some() && self.backing.loads() && !self.exists(key)? {
+            self.load(key)?;
+        }
+        let after = match options.expiry {
+            SetExpiry::After(ttl) => Some(deadline("SET", ttl)?),
+            _ => None,
+        };
+        let (result, expired) = {
+            let mut store = self.get_store()?;
+            let expired = store.expire(key);
+            let result = if options.allows(store.entries.contains_key(key)) {
+                let expires_at = match options.expiry {
+                    SetExpiry::Persist => None,
+                    SetExpiry::Keep => store.entries.get(key).and_then(|entry| entry.expires_at),
+                    SetExpiry::After(_) => after,
+                };
+                store.make_room(key, value.len()).map(|evicted| {
+                    store.insert(key.to_string(), value.into(), expires_at);
+                    Some((evicted, self.ticket()))
+                })
+            } else {
+                Ok(None)
+            };
+            (result, expired)
+        };
+        if expired {
+            self.expired(key);
+        }
+        let Some((evicted, ticket)) = result? else {
+            return Ok(false);
+        };
+        self.write_through(ticket, key, Some(value));
+        self.counters.record_set();
+        self.counters.record_evictions(evicted.len());
+        for victim in evicted {
+            self.notify(&victim, KeyEventKind::Evicted, None);
+        }
+        self.notify(key, KeyEventKind::Set, Some(value));
+        Ok(true)
+    }
+
+    /// Sets many values in the store, atomically.
+    ///
+    /// Every pair is written under one lock, so no reader ever sees some of them
+    /// written and others not. Room for all of them is reserved up front, so the
+    /// store grows at most once. Like [`KVStore::set`], the keys never expire afterwards,
+    /// and a key given twice ends up with its last value.
+    ///
+    /// # Arguments
+    ///
+    /// * `pairs` - The keys to set, each with its value.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    /// If the store is full and cannot make room for a value, it will return an error,
+    /// and the pairs before it stay written.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// let pairs = [
+    ///     ("first".to_string(), "alice".to_string()),
+    ///     ("last".to_string(), "smith".to_string()),
+    /// ];
+    ///
+    /// store.multi_set(&pairs).unwrap();
+    ///
+    /// assert_eq!(Ok(Some("alice".to_string())), store.get("first"));
+    /// assert_eq!(Ok(Some("smith".to_string())), store.get("last"));
+    /// ```
+    pub fn multi_set(&self, pairs: &[(String, String)]) -> Result<(), MiniRedisError> {
+        self.write_pairs(pairs, false).map(|_| ())
+    }
+
+    /// Sets many values in the store only if none of the keys exist, atomically.
+    ///
+    /// The check and the writes happen under one lock,
+    /// so either every pair is written or none of them are.
+    ///
+    /// # Arguments
+    ///
+    /// * `pairs` - The keys to set, each with its value.
+    ///
+    /// # Returns
+    ///
+    /// True if the pairs were written, false if any of the keys already existed.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    /// If the store is full and cannot make room for a value, it will return an error,
+    /// and the pairs before it stay written.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("b", "taken").unwrap();
+    /// let pairs = [
+    ///     ("a".to_string(), "1".to_string()),
+    ///     ("b".to_string(), "2".to_string()),
+    /// ];
+    ///
+    /// assert_eq!(Ok(false), store.multi_set_if_absent(&pairs));
+    /// assert_eq!(Ok(None), store.get("a"));
+    /// ```
+    pub fn multi_set_if_absent(&self, pairs: &[(String, String)]) -> Result<bool, MiniRedisError> {
+        // The backing store may hold a key even if this store never loaded it.
+        if self.backing.loads() {
+            for (key, _) in pairs {
+                if !self.exists(key)? {
+                    self.load(key)?;
+                }
+            }
+        }
+        self.write_pairs(pairs, true)
+    }
+
+    /// Writes many pairs under one lock, for [`KVStore::multi_set`]
+    /// and [`KVStore::multi_set_if_absent`].
+    ///
+    /// # Arguments
+    ///
+    /// * `pairs` - The keys to set, each with its value.
+    /// * `if_absent` - Whether to write nothing if any of the keys exists.
+    ///
+    /// # Returns
+    ///
+    /// True if the pairs were written.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, or full and cannot make room for a value,
+    /// it will return an error.
+    fn write_pairs(
+        &self,
+        pairs: &[(String, String)],
+        if_absent: bool,
+    ) -> Result<bool, MiniRedisError> {
+        let mut expired = Vec::new();
+        let mut batch = Batch::default();
+        let result = {
+            let mut store = self.get_store()?;
+            for (key, _) in pairs {
+                if store.expire(key) {
+                    expired.push(key);
+                }
+            }
+            if if_absent && pairs.iter().any(|(key, _)| store.entries.contains_key(key)) {
+                Ok(false)
+            } else {
+                let entries = pairs
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.clone(), None));
+                self.insert_batch(&mut store, entries, &mut batch)
+                    .map(|()| true)
+            }
+        };
+        for key in expired {
+            self.expired(key);
+        }
+        self.finish_batch(batch);
+        result
+    }
+
+    /// Sets a value in the store, atomically, and gets the value it replaced.
+    ///
+    /// Like [`KVStore::set`], the key never expires afterwards.
+    /// The old value is read and the new one written under one lock,
+    /// so no two callers ever get the same old value back.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to set the value for.
+    /// * `value` - The value to set.
+    ///
+    /// # Returns
+    ///
+    /// The old value, or None if the key did not exist.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, or full and cannot make room for the value,
+    /// it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    ///
+    /// assert_eq!(Ok(None), store.set_returning_old("token", "a"));
+    /// assert_eq!(Ok(Some(b"a".to_vec())), store.set_returning_old("token", "b"));
+    /// assert_eq!(Ok(Some("b".to_string())), store.get("token"));
+    /// ```
+    pub fn set_returning_old(
+        &self,
+        key: &str,
+        value: &str,
+    ) -> Result<Option<Vec<u8>>, MiniRedisError> {
+        // The backing store may hold the old value even if this store never loaded it.
+        if self.backing.loads() && !self.exists(key)? {
+            self.load(key)?;
+        }
+        let (result, expired) = {
+            let mut store = self.get_store()?;
+            let expired = store.expire(key);
+            let result = store.make_room(key, value.len()).map(|evicted| {
+                let old = store.insert(key.to_string(), value.into(), None);
+                (old, evicted, self.ticket())
+            });
+            (result, expired)
+        };
+        if expired {
+            self.expired(key);
+        }
+        let (old, evicted, ticket) = result?;
+        self.write_through(ticket, key, Some(value));
+        self.counters.record_get(old.is_some());
+        self.counters.record_set();
+        self.counters.record_evictions(evicted.len());
+        for victim in evicted {
+            self.notify(&victim, KeyEventKind::Evicted, None);
+        }
+        self.notify(key, KeyEventKind::Set, Some(value));
+        Ok(old)
+    }
+
+    /// Adds to the integer value of a key, atomically, treating a missing key as 0.
+    ///
+    /// The value is read, added to and written back under one lock,
+    /// so concurrent increments never lose one another. The key keeps its time to live.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to add to.
+    /// * `delta` - What to add, negative to subtract.
+    ///
+    /// # Returns
+    ///
+    /// The new value of the key.
+    ///
+    /// # Errors
+    ///
+    /// If the value is not a 64-bit integer, it will return [`MiniRedisError::NotAnInteger`],
+    /// and if the result would not fit in one, [`MiniRedisError::IncrementOverflow`],
+    /// leaving the value as it was.
+    /// If the store is already locked, or full and cannot make room for the value,
+    /// it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::error::MiniRedisError;
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("name", "alice").unwrap();
+    ///
+    /// assert_eq!(Ok(1), store.increment("visits", 1));
+    /// assert_eq!(Ok(-1), store.increment("visits", -2));
+    /// assert_eq!(Err(MiniRedisError::NotAnInteger), store.increment("name", 1));
+    /// ```
+    pub fn increment(&self, key: &str, delta: i64) -> Result<i64, MiniRedisError> {
+        self.update(key, |current| {
+            current.map_or(Ok(delta), |value| storage::add_to_integer(value, delta))
+        })
+    }
+
+    /// Adds to the floating point value of a key, atomically, treating a missing key as 0.
+    ///
+    /// The new value is stored in its shortest form, so 3.1 is stored as `3.1`
+    /// and 3.0 as `3`. The key keeps its time to live.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to add to.
+    /// * `delta` - What to add, negative to subtract.
+    ///
+    /// # Returns
+    ///
+    /// The new value of the key.
+    ///
+    /// # Errors
+    ///
+    /// If the value is not a finite number, it will return [`MiniRedisError::NotAFloat`],
+    /// and if the result would be NaN or infinite, [`MiniRedisError::IncrementNotFinite`],
+    /// leaving the value as it was.
+    /// If the store is already locked, or full and cannot make room for the value,
+    /// it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    ///
+    /// assert_eq!(Ok(1.5), store.increment_float("average", 1.5));
+    /// assert_eq!(Ok(3.1), store.increment_float("average", 1.6));
+    /// assert_eq!(Ok(Some("3.1".to_string())), store.get("average"));
+    /// ```
+    pub fn increment_float(&self, key: &str, delta: f64) -> Result<f64, MiniRedisError> {
+        self.update(key, |current| {
+            current.map_or(Ok(delta), |value| storage::add_to_float(value, delta))
+        })
+    }
+
+    /// Appends to the value of a key, atomically, setting it if the key is missing.
+    ///
+    /// The value is read, appended to and written back under one lock,
+    /// so concurrent appends never lose one another. The key keeps its time to live.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to append to.
+    /// * `suffix` - What to append.
+    ///
+    /// # Returns
+    ///
+    /// The length of the new value, in bytes.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, or full and cannot make room for the value,
+    /// it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    ///
+    /// assert_eq!(Ok(5), store.append("greeting", "hello"));
+    /// assert_eq!(Ok(11), store.append("greeting", " world"));
+    /// assert_eq!(Ok(Some("hello world".to_string())), store.get("greeting"));
+    /// ```
+    pub fn append(&self, key: &str, suffix: &str) -> Result<usize, MiniRedisError> {
+        self.update_bytes(key, |current| {
+            let value = [current.unwrap_or_default(), suffix.as_bytes()].concat();
+            let len = value.len();
+            Ok((value, len))
+        })
+    }
+
+    /// Overwrites part of the value of a key, atomically, from a byte offset on.
+    ///
+    /// A missing key counts as an empty value, and a value shorter than the offset
+    /// is padded with NUL bytes, like in Redis. The key keeps its time to live.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to overwrite.
+    /// * `offset` - The byte offset to start writing at.
+    /// * `value` - What to write.
+    ///
+    /// # Returns
+    ///
+    /// The length of the new value, in bytes.
+    ///
+    /// # Errors
+    ///
+    /// If the new value would be longer than [`storage::MAX_VALUE_LEN`], it will return
+    /// [`MiniRedisError::ValueTooLarge`], leaving the key as it was.
+    /// If the store is already locked, or full and cannot make room for the value,
+    /// it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("greeting", "Hello World").unwrap();
+    ///
+    /// assert_eq!(Ok(11), store.set_range("greeting", 6, "Redis"));
+    /// assert_eq!(Ok(Some("Hello Redis".to_string())), store.get("greeting"));
+    /// ```
+    pub fn set_range(
+        &self,
+        key: &str,
+        offset: usize,
+        value: &str,
+    ) -> Result<usize, MiniRedisError> {
+        self.update_bytes(key, |current| {
+            let value =
+                storage::overwrite_range(current.unwrap_or_default(), offset, value.as_bytes())?;
+            let len = value.len();
+            Ok((value, len))
+        })
+    }
+
+    /// Sets or clears one bit of the value of a key, atomically.
+    ///
+    /// Bit 0 is the most significant bit of the first byte, like in Redis.
+    /// A missing key counts as an empty value,
+    /// and a value too short for the bit is padded with NUL bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to change.
+    /// * `offset` - The offset of the bit, in bits.
+    /// * `bit` - True to set the bit, false to clear it.
+    ///
+    /// # Returns
+    ///
+    /// The bit before it changed.
+    ///
+    /// # Errors
+    ///
+    /// If the new value would be longer than [`storage::MAX_VALUE_LEN`], it will return
+    /// [`MiniRedisError::ValueTooLarge`], leaving the value as it was.
+    /// If the store is already locked, or full and cannot make room for the value,
+    /// it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    ///
+    /// assert_eq!(Ok(false), store.set_bit("flags", 7, true));
+    /// assert_eq!(Ok(true), store.set_bit("flags", 7, true));
+    /// assert_eq!(Ok(Some("\u{1}".to_string())), store.get("flags"));
+    /// ```
+    pub fn set_bit(&self, key: &str, offset: usize, bit: bool) -> Result<bool, MiniRedisError> {
+        self.update_bytes(key, |current| {
+            storage::write_bit(current.unwrap_or_default(), offset, bit)
+        })
+    }
+
+    /// Replaces the value of a key with one computed from it, under one lock.
+    ///
+    /// The current value is read as text, the way [`KVStore::get`] reads it.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to update.
+    /// * `compute` - Computes the new value from the current one, if the key is set.
+    ///
+    /// # Returns
+    ///
+    /// The new value.
+    ///
+    /// # Errors
+    ///
+    /// If `compute` fails, it will return its error and leave the value as it was.
+    /// If the store is already locked, or full and cannot make room for the value,
+    /// it will return an error.
+    fn update<T: ToString>(
+        &self,
+        key: &str,
+        compute: impl FnOnce(Option<&str>) -> Result<T, MiniRedisError>,
+    ) -> Result<T, MiniRedisError> {
+        self.update_bytes(key, |current| {
+            let value = compute(current.map(String::from_utf8_lossy).as_deref())?;
+            Ok((value.to_string().into_bytes(), value))
+        })
+    }
+
+    /// Replaces the bytes of the value of a key with ones computed from them, under one lock.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to update.
+    /// * `compute` - Computes the new bytes from the current ones, if the key is set,
+    ///   together with what to return.
+    ///
+    /// # Returns
+    ///
+    /// What `compute` returned besides the new bytes.
+    ///
+    /// # Errors
+    ///
+    /// If `compute` fails, it will return its error and leave the value as it was.
+    /// If the store is already locked, or full and cannot make room for the value,
+    /// it will return an error.
+    fn update_bytes<T>(
+        &self,
+        key: &str,
+        compute: impl FnOnce(Option<&[u8]>) -> Result<(Vec<u8>, T), MiniRedisError>,
+    ) -> Result<T, MiniRedisError> {
+        // The backing store may hold the value even if this store never loaded it.
+        if self.backing.loads() && !self.exists(key)? {
+            self.load(key)?;
+        }
+        let (result, expired) = {
+            let mut store = self.get_store()?;
+            let expired = store.expire(key);
+            let result = compute(store.peek(key)).and_then(|(bytes, value)| {
+                let expires_at = store.entries.get(key).and_then(|entry| entry.expires_at);
+                let evicted = store.make_room(key, bytes.len())?;
+                store.insert(key.to_string(), bytes.clone(), expires_at);
+                Ok((value, storage::text(bytes), evicted, self.ticket()))
+            });
+            (result, expired)
+        };
+        if expired {
+            self.expired(key);
+        }
+        let (value, text, evicted, ticket) = result?;
+        self.write_through(ticket, key, Some(&text));
+        self.counters.record_set();
+        self.counters.record_evictions(evicted.len());
+        for victim in evicted {
+            self.notify(&victim, KeyEventKind::Evicted, None);
+        }
+        self.notify(key, KeyEventKind::Set, Some(&text));
+        Ok(value)
+    }
+
+    /// Sets many values in the store at once.
+    ///
+    /// The whole batch is written under a single lock acquisition,
+    /// with room for it reserved up front from the size hint of the iterator,
+    /// which makes this much faster than calling [`KVStore::set`] in a loop.
+    /// Other threads cannot see a partially written batch, but they also wait
+    /// for the whole batch, so split very large imports into several calls
+    /// if readers must stay responsive.
+    ///
+    /// Duplicate keys within the batch are resolved by last-write-wins.
+    /// Every entry is counted as a set and notified to subscribers,
+    /// exactly as if it had been written with [`KVStore::set`].
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - The keys and values to set.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    /// If the store is full and cannot make room for an entry, it will return an error.
+    /// The entries before it are kept, and the entries after it are not written.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    ///
+    /// store
+    ///     .extend([
+    ///         ("a".to_string(), "1".to_string()),
+    ///         ("b".to_string(), "2".to_string()),
+    ///         ("a".to_string(), "3".to_string()),
+    ///     ])
+    ///     .unwrap();
+    ///
+    /// assert_eq!(Ok(2), store.len());
+    /// assert_eq!(Ok(Some("3".to_string())), store.get("a"));
+    /// ```
+    pub fn extend(
+        &self,
+        entries: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<(), MiniRedisError> {
+        self.extend_with_ttl(entries.into_iter().map(|(key, value)| (key, value, None)))
+    }
+
+    /// Sets many values in the store at once, each with an optional time to live.
+    ///
+    /// This is [`KVStore::extend`] for entries that expire.
+    /// An entry with a time to live is removed once that much time has passed,
+    /// and an entry without one never expires.
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - The keys, values, and times to live to set.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    /// If the store is full and cannot make room for an entry, it will return an error.
+    /// The entries before it are kept, and the entries after it are not written.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    /// use std::time::Duration;
+    ///
+    /// let store = KVStore::new();
+    ///
+    /// store
+    ///     .extend_with_ttl([
+    ///         ("session".to_string(), "abc".to_string(), Some(Duration::from_secs(60))),
+    ///         ("user".to_string(), "alice".to_string(), None),
+    ///     ])
+    ///     .unwrap();
+    ///
+    /// assert!(store.ttl("session").unwrap().is_some());
+    /// assert_eq!(Ok(None), store.ttl("user"));
+    /// ```
+    pub fn extend_with_ttl(
+        &self,
+        entries: impl IntoIterator<Item = (String, String, Option<Duration>)>,
+    ) -> Result<(), MiniRedisError> {
+        let mut batch = Batch::default();
+        let result = {
+            let mut store = self.get_store()?;
+            let now = Instant::now();
+            let entries = entries
+                .into_iter()
+                .map(|(key, value, ttl)| (key, value, ttl.map(|ttl| now + ttl)));
+            self.insert_batch(&mut store, entries, &mut batch)
+        };
+        self.finish_batch(batch);
+        result
+    }
+
+    /// Writes a batch of entries under the lock the caller holds,
+    /// for [`KVStore::extend_with_ttl`] and [`KVStore::multi_set`].
+    ///
+    /// Room for the batch is reserved up front from the size hint of the iterator.
+    /// What has to happen once the lock is released is gathered in `batch`,
+    /// for [`KVStore::finish_batch`].
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The locked store.
+    /// * `entries` - The keys, values, and deadlines to set.
+    /// * `batch` - Gathers the writes, evictions, and events of the entries.
+    ///
+    /// # Errors
+    ///
+    /// If the store is full and cannot make room for an entry, it will return an error.
+    /// The entries before it are kept, and the entries after it are not written.
+    fn insert_batch(
+        &self,
+        store: &mut Data,
+        entries: impl Iterator<Item = (String, String, Option<Instant>)>,
+        batch: &mut Batch,
+    ) -> Result<(), MiniRedisError> {
+        let notify = self.has_subscribers();
+        store.entries.reserve(entries.size_hint().0);
+        for (key, value, expires_at) in entries {
+            let evicted = store.make_room(&key, value.len())?;
+            batch.written += 1;
+            batch.evicted += evicted.len();
+            if notify {
+                batch.events.extend(
+                    evicted
+                        .into_iter()
+                        .map(|victim| (victim, KeyEventKind::Evicted, None)),
+                );
+                batch
+                    .events
+                    .push((key.clone(), KeyEventKind::Set, Some(value.clone())));
+            }
+            if let Some(ticket) = self.ticket() {
+                batch.writes.push((ticket, key.clone(), value.clone()));
+            }
+            store.insert(key, value.into_bytes(), expires_at);
+        }
+        Ok(())
+    }
+
+    /// Writes a batch through to the backing store, counts it, and notifies subscribers,
+    /// once the lock it was written under is released.
+    ///
+    /// # Arguments
+    ///
+    /// * `batch` - What [`KVStore::insert_batch`] gathered.
+    fn finish_batch(&self, batch: Batch) {
+        for (ticket, key, value) in batch.writes {
+            self.backing.write(ticket, &key, Some(&value));
+        }
+        for _ in 0..batch.written {
+            self.counters.record_set();
+        }
+        self.counters.record_evictions(batch.evicted);
+        for (key, kind, value) in batch.events {
+            self.notify(&key, kind, value.as_deref());
+        }
+    }
+
+    /// Deletes a value from the store.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to delete the value for.
+    ///
+    /// # Returns
+    ///
+    /// True if the key existed, false otherwise.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    ///
+    /// store.set("key", "value");
+    ///
+    /// assert_eq!(Ok(true), store.del("key"));
+    /// assert_eq!(Ok(false), store.del("key"));
+    /// assert_eq!(Ok(None), store.get("key"));
+    /// ```
+    pub fn del(&self, key: &str) -> Result<bool, MiniRedisError> {
+        let (removed, expired, ticket) = {
+            let mut store = self.get_store()?;
+            let expired = store.expire(key);
+            (store.remove(key).is_some(), expired, self.ticket())
+        };
+        // The backing store may hold the key even if this store never loaded it.
+        self.write_through(ticket, key, None);
+        if expired {
+            self.expired(key);
+        }
+        self.counters.record_del();
+        if removed {
+            self.notify(key, KeyEventKind::Del, None);
+        }
+        Ok(removed)
+    }
+
+    /// Deletes keys under one lock, and hands their values over to be freed elsewhere.
+    ///
+    /// The store lets go of the values without dropping them, so a large value
+    /// does not hold up the lock, or the caller, while its memory is given back.
+    ///
+    /// # Arguments
+    ///
+    /// * `keys` - The keys to delete. A key given twice is counted once.
+    /// * `free` - Gets the value of every key that was deleted.
+    ///
+    /// # Returns
+    ///
+    /// The number of the keys that existed and were deleted.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("key", "value").unwrap();
+    /// let mut freed = Vec::new();
+    ///
+    /// let count = store.unlink(&["key".to_string(), "other_key".to_string()], &mut |value| {
+    ///     freed.push(value)
+    /// });
+    ///
+    /// assert_eq!(Ok(1), count);
+    /// assert_eq!(vec![b"value".to_vec()], freed);
+    /// assert_eq!(Ok(None), store.get("key"));
+    /// ```
+    pub fn unlink(
+        &self,
+        keys: &[String],
+        free: &mut dyn FnMut(Vec<u8>),
+    ) -> Result<usize, MiniRedisError> {
+        let mut expired = Vec::new();
+        let removed = {
+            let mut store = self.get_store()?;
+            keys.iter()
+                .map(|key| {
+                    if store.expire(key) {
+                        expired.push(key.as_str());
+                    }
+                    (key, store.remove(key), self.ticket())
+                })
+                .collect::<Vec<_>>()
+        };
+        for key in expired {
+            self.expired(key);
+        }
+        let mut count = 0;
+        for (key, value, ticket) in removed {
+            // The backing store may hold the key even if this store never loaded it.
+            self.write_through(ticket, key, None);
+            self.counters.record_del();
+            if let Some(value) = value {
+                self.notify(key, KeyEventKind::Del, None);
+                free(value);
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Moves the value of a key to another key, atomically, overwriting it.
+    ///
+    /// The value and its time to live leave the source and reach the destination
+    /// under one lock, so no reader ever finds the value in neither key or in both.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The key to move the value from.
+    /// * `destination` - The key to move the value to.
+    ///
+    /// # Errors
+    ///
+    /// If the source does not exist, it will return [`MiniRedisError::NoSuchKey`].
+    /// If the store is already locked, or full and cannot make room for the destination,
+    /// it will return an error, and the source keeps its value.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("draft", "text").unwrap();
+    ///
+    /// store.rename("draft", "final").unwrap();
+    ///
+    /// assert_eq!(Ok(None), store.get("draft"));
+    /// assert_eq!(Ok(Some("text".to_string())), store.get("final"));
+    /// ```
+    pub fn rename(&self, source: &str, destination: &str) -> Result<(), MiniRedisError> {
+        self.move_key(source, destination, false).map(|_| ())
+    }
+
+    /// Moves the value of a key to another key only if that key does not exist, atomically.
+    ///
+    /// The check and the move happen under one lock,
+    /// so of many callers racing to move onto the same key, exactly one does.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The key to move the value from.
+    /// * `destination` - The key to move the value to.
+    ///
+    /// # Returns
+    ///
+    /// True if the value was moved, false if the destination already existed.
+    ///
+    /// # Errors
+    ///
+    /// If the source does not exist, it will return [`MiniRedisError::NoSuchKey`].
+    /// If the store is already locked, or full and cannot make room for the destination,
+    /// it will return an error, and the source keeps its value.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("draft", "text").unwrap();
+    /// store.set("final", "taken").unwrap();
+    ///
+    /// assert_eq!(Ok(false), store.rename_if_absent("draft", "final"));
+    /// assert_eq!(Ok(true), store.rename_if_absent("draft", "other"));
+    /// ```
+    pub fn rename_if_absent(
+        &self,
+        source: &str,
+        destination: &str,
+    ) -> Result<bool, MiniRedisError> {
+        // The backing store may hold the destination even if this store never loaded it.
+        if self.backing.loads() && !self.exists(destination)? {
+            self.load(destination)?;
+        }
+        self.move_key(source, destination, true)
+    }
+
+    /// Moves the value of a key to another key under one lock, for [`KVStore::rename`]
+    /// and [`KVStore::rename_if_absent`].
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The key to move the value from.
+    /// * `destination` - The key to move the value to.
+    /// * `if_absent` - Whether to leave both keys alone if the destination exists.
+    ///
+    /// # Returns
+    ///
+    /// False if the destination existed and `if_absent` was set, true otherwise.
+    ///
+    /// # Errors
+    ///
+    /// If the source does not exist, it will return [`MiniRedisError::NoSuchKey`].
+    /// If the store is already locked, or full and cannot make room for the destination,
+    /// it will return an error.
+    fn move_key(
+        &self,
+        source: &str,
+        destination: &str,
+        if_absent: bool,
+    ) -> Result<bool, MiniRedisError> {
+        // The backing store may hold the source even if this store never loaded it.
+        if self.backing.loads() && !self.exists(source)? {
+            self.load(source)?;
+        }
+        let mut expired = Vec::new();
+        let result = {
+            let mut store = self.get_store()?;
+            for key in [source, destination] {
+                if store.expire(key) {
+                    expired.push(key);
+                }
+            }
+            match store.entries.get(source) {
+                None => Err(MiniRedisError::NoSuchKey),
+                Some(_) if if_absent && store.entries.contains_key(destination) => Ok(None),
+                Some(_) if source == destination => Ok(Some(None)),
+                Some(entry) => {
+                    let expires_at = entry.expires_at;
+                    let value = store.remove(source).unwrap_or_default();
+                    match store.make_room(destination, value.len()) {
+                        Ok(evicted) => {
+                            let source_ticket = self.ticket();
+                            store.insert(destination.to_string(), value.clone(), expires_at);
+                            Ok(Some(Some((value, evicted, source_ticket, self.ticket()))))
+                        }
+                        Err(error) => {
+                            store.insert(source.to_string(), value, expires_at);
+                            Err(error)
+                        }
+                    }
+                }
+            }
+        };
+        for key in expired {
+            self.expired(key);
+        }
+        let Some(moved) = result? else {
+            return Ok(false);
+        };
+        let Some((value, evicted, source_ticket, destination_ticket)) = moved else {
+            return Ok(true);
+        };
+        self.write_through(source_ticket, source, None);
+        let value = storage::text(value);
+        self.write_through(destination_ticket, destination, Some(&value));
+        self.counters.record_del();
+        self.counters.record_set();
+        self.counters.record_evictions(evicted.len());
+        for victim in evicted {
+            self.notify(&victim, KeyEventKind::Evicted, None);
+        }
+        self.notify(source, KeyEventKind::Del, None);
+        self.notify(destination, KeyEventKind::Set, Some(&value));
+        Ok(true)
+    }
+
+    /// Deletes every key that starts with a prefix.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The prefix of the keys to delete.
+    ///
+    /// # Returns
+    ///
+    /// The number of keys that were deleted.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("session:1", "a").unwrap();
+    /// store.set("session:2", "b").unwrap();
+    /// store.set("user:1", "c").unwrap();
+    ///
+    /// assert_eq!(Ok(2), store.del_prefix("session:"));
+    /// assert_eq!(Ok(1), store.len());
+    /// ```
+    pub fn del_prefix(&self, prefix: &str) -> Result<usize, MiniRedisError> {
+        self.del_where(|key| key.starts_with(prefix))
+    }
+
+    /// Deletes every key that matches a glob-style pattern.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - A glob-style pattern, like `user:*:session`, that keys must match.
+    ///
+    /// # Returns
+    ///
+    /// The number of keys that were deleted.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("user:1:session", "a").unwrap();
+    /// store.set("user:1:name", "b").unwrap();
+    ///
+    /// assert_eq!(Ok(1), store.del_matching("user:*:session"));
+    /// assert_eq!(Ok(Some("b".to_string())), store.get("user:1:name"));
+    /// ```
+    pub fn del_matching(&self, pattern: &str) -> Result<usize, MiniRedisError> {
+        self.del_where(|key| glob::matches(pattern, key))
+    }
+
+    /// Reserves room for at least `additional` more entries.
+    ///
+    /// # Arguments
+    ///
+    /// * `additional` - The number of entries to reserve room for.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.reserve(1_000).unwrap();
+    ///
+    /// assert!(store.capacity().unwrap() >= 1_000);
+    /// ```
+    pub fn reserve(&self, additional: usize) -> Result<(), MiniRedisError> {
+        self.get_store()?.entries.reserve(additional);
+        Ok(())
+    }
+
+    /// Shrinks the allocated room of the store to fit its entries.
+    ///
+    /// This lets a long-lived process give memory back after deleting many keys.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::with_capacity(1_000);
+    /// store.shrink_to_fit().unwrap();
+    ///
+    /// assert!(store.capacity().unwrap() < 1_000);
+    /// ```
+    pub fn shrink_to_fit(&self) -> Result<(), MiniRedisError> {
+        self.get_store()?.entries.shrink_to_fit();
+        Ok(())
+    }
+
+    /// Gets the number of entries the store can hold without reallocating.
+    ///
+    /// # Returns
+    ///
+    /// The allocated capacity, which is at least the number of entries.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::with_capacity(10);
+    ///
+    /// assert!(store.capacity().unwrap() >= 10);
+    /// ```
+    pub fn capacity(&self) -> Result<usize, MiniRedisError> {
+        Ok(self.get_store()?.entries.capacity())
+    }
+
+    /// Gets the number of entries in the store.
+    ///
+    /// Expired keys are removed lazily, so this may include keys whose time to live
+    /// has run out but that were not looked up since.
+    /// Call [`KVStore::purge_expired`] first for an exact count.
+    ///
+    /// # Returns
+    ///
+    /// The number of entries.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("key", "value").unwrap();
+    ///
+    /// assert_eq!(Ok(1), store.len());
+    /// ```
+    pub fn len(&self) -> Result<usize, MiniRedisError> {
+        let store = self.get_store()?;
+        Ok(store.entries.len())
+    }
+
+    /// Checks whether the store has no entries.
+    ///
+    /// # Returns
+    ///
+    /// True if the store is empty.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    ///
+    /// assert_eq!(Ok(true), store.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> Result<bool, MiniRedisError> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Checks whether a key exists.
+    ///
+    /// Unlike [`KVStore::get`], this does not count as an access of the key,
+    /// and is not counted in the stats.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to check.
+    ///
+    /// # Returns
+    ///
+    /// True if the key exists.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("key", "value").unwrap();
+    ///
+    /// assert_eq!(Ok(true), store.exists("key"));
+    /// assert_eq!(Ok(false), store.exists("other_key"));
+    /// ```
+    pub fn exists(&self, key: &str) -> Result<bool, MiniRedisError> {
+        let (exists, expired) = {
+            let mut store = self.get_store()?;
+            let expired = store.expire(key);
+            (store.entries.contains_key(key), expired)
+        };
+        if expired {
+            self.expired(key);
+        }
+        Ok(exists)
+    }
+
+    /// Gets the type of the value of a key, as `TYPE` names it.
+    ///
+    /// Every value is a string so far, so this is `string` for any key that exists.
+    /// Like [`KVStore::exists`], it does not count as an access of the key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to check.
+    ///
+    /// # Returns
+    ///
+    /// The type of the value, like `string`, or None if the key does not exist.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("key", "value").unwrap();
+    ///
+    /// assert_eq!(Ok(Some("string")), store.value_type("key"));
+    /// assert_eq!(Ok(None), store.value_type("other_key"));
+    /// ```
+    pub fn value_type(&self, key: &str) -> Result<Option<&'static str>, MiniRedisError> {
+        Ok(self.exists(key)?.then_some("string"))
+    }
+
+    /// Describes a key without counting as an access to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to describe.
+    ///
+    /// # Returns
+    ///
+    /// What the store knows about the key, or None if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("key", "value").unwrap();
+    ///
+    /// let info = store.describe("key").unwrap().unwrap();
+    ///
+    /// assert_eq!("raw", info.encoding);
+    /// assert_eq!(5, info.serialized_length);
+    /// assert_eq!(Ok(None), store.describe("other_key"));
+    /// ```
+    pub fn describe(&self, key: &str) -> Result<Option<KeyInfo>, MiniRedisError> {
+        let (info, expired) = {
+            let mut store = self.get_store()?;
+            let expired = store.expire(key);
+            let now = Instant::now();
+            let info = store.entries.get(key).map(|entry| {
+                KeyInfo::new(
+                    key,
+                    &entry.value,
+                    now.saturating_duration_since(entry.accessed_at),
+                    entry.expires_at.map(|at| at.saturating_duration_since(now)),
+                )
+            });
+            (info, expired)
+        };
+        if expired {
+            self.expired(key);
+        }
+        Ok(info)
+    }
+
+    /// Records an access of keys without reading them, and counts how many exist.
+    ///
+    /// A touched key counts as just used for eviction, like after a [`KVStore::get`],
+    /// but not in the hit and miss stats. A key given twice is counted twice.
+    ///
+    /// # Arguments
+    ///
+    /// * `keys` - The keys to touch.
+    ///
+    /// # Returns
+    ///
+    /// The number of the keys that exist.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("key", "value").unwrap();
+    ///
+    /// assert_eq!(Ok(1), store.touch(&["key".to_string(), "other_key".to_string()]));
+    /// ```
+    pub fn touch(&self, keys: &[String]) -> Result<usize, MiniRedisError> {
+        let mut expired = Vec::new();
+        let touched = {
+            let mut store = self.get_store()?;
+            keys.iter()
+                .filter(|key| {
+                    if store.expire(key) {
+                        expired.push(key.as_str());
+                    }
+                    store.get(key).is_some()
+                })
+                .count()
+        };
+        for key in expired {
+            self.expired(key);
+        }
+        Ok(touched)
+    }
+
+    /// Gets the value of a key and, under the same lock, sets or removes its time to live.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to get.
+    /// * `ttl` - The new time to live of the key, or None to make it never expire.
+    ///
+    /// # Returns
+    ///
+    /// The value of the key, or None if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// If the time to live is too long for the clock, it will return
+    /// [`MiniRedisError::InvalidExpireTime`]. If the store is already locked,
+    /// it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    /// use std::time::Duration;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("session", "abc").unwrap();
+    ///
+    /// let value = store.get_and_expire("session", Some(Duration::from_secs(60)));
+    ///
+    /// assert_eq!(Ok(Some(b"abc".to_vec())), value);
+    /// assert!(store.ttl("session").unwrap().is_some());
+    /// ```
+    pub fn get_and_expire(
+        &self,
+        key: &str,
+        ttl: Option<Duration>,
+    ) -> Result<Option<Vec<u8>>, MiniRedisError> {
+        // The backing store may hold the key even if this store never loaded it.
+        if self.backing.loads() && !self.exists(key)? {
+            self.load(key)?;
+        }
+        let expires_at = ttl.map(|ttl| deadline("GETEX", ttl)).transpose()?;
+        let (value, expired) = {
+            let mut store = self.get_store()?;
+            let expired = store.expire(key);
+            let value = store.get(key).map(<[u8]>::to_vec);
+            if let Some(entry) = store.entries.get_mut(key) {
+                entry.expires_at = expires_at;
+            }
+            (value, expired)
+        };
+        if expired {
+            self.expired(key);
+        }
+        self.counters.record_get(value.is_some());
+        Ok(value)
+    }
+
+    /// Sets the time to live of a key, if it exists and the condition holds.
+    ///
+    /// The condition is checked against the current deadline under the same lock
+    /// the new one is set in. A time to live of zero removes the key at once, as expired.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to expire.
+    /// * `ttl` - The new time to live of the key.
+    /// * `condition` - When to set the time to live, or None to always set it.
+    ///
+    /// # Returns
+    ///
+    /// True if the time to live was set, false if the key does not exist
+    /// or the condition does not hold.
+    ///
+    /// # Errors
+    ///
+    /// If the time to live is too long for the clock, it will return
+    /// [`MiniRedisError::InvalidExpireTime`]. If the store is already locked,
+    /// it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    /// use std::time::Duration;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("session", "abc").unwrap();
+    ///
+    /// assert_eq!(Ok(true), store.expire("session", Duration::from_secs(60), None));
+    /// assert_eq!(Ok(false), store.expire("other_key", Duration::from_secs(60), None));
+    /// assert!(store.ttl("session").unwrap().is_some());
+    /// ```
+    pub fn expire(
+        &self,
+        key: &str,
+        ttl: Duration,
+        condition: Option<ExpireCondition>,
+    ) -> Result<bool, MiniRedisError> {
+        // The backing store may hold the key even if this store never loaded it.
+        if self.backing.loads() && !self.exists(key)? {
+            self.load(key)?;
+        }
+        let expires_at = deadline("EXPIRE", ttl)?;
+        let (set, expired) = {
+            let mut store = self.get_store()?;
+            let expired = store.expire(key);
+            let set = match store.entries.get_mut(key) {
+                Some(entry)
+                    if condition
+                        .is_none_or(|condition| condition.allows(entry.expires_at, expires_at)) =>
+                {
+                    entry.expires_at = Some(expires_at);
+                    true
+                }
+                _ => false,
+            };
+            (set, expired || (set && store.expire(key)))
+        };
+        if expired {
+            self.expired(key);
+        }
+        Ok(set)
+    }
+
+    /// Removes the time to live of a key, so it never expires.
+    ///
+    /// The value of the key is left as it is.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to persist.
+    ///
+    /// # Returns
+    ///
+    /// True if a time to live was removed, false if the key has none or does not exist.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    /// use std::time::Duration;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("session", "abc").unwrap();
+    /// store.expire("session", Duration::from_secs(60), None).unwrap();
+    ///
+    /// assert_eq!(Ok(true), store.persist("session"));
+    /// assert_eq!(Ok(false), store.persist("session"));
+    /// assert_eq!(Ok(None), store.ttl("session"));
+    /// ```
+    pub fn persist(&self, key: &str) -> Result<bool, MiniRedisError> {
+        let (removed, expired) = {
+            let mut store = self.get_store()?;
+            let expired = store.expire(key);
+            let removed = store
+                .entries
+                .get_mut(key)
+                .and_then(|entry| entry.expires_at.take())
+                .is_some();
+            (removed, expired)
+        };
+        if expired {
+            self.expired(key);
+        }
+        Ok(removed)
+    }
+
+    /// Gets the remaining time to live of a key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to get the time to live for.
+    ///
+    /// # Returns
+    ///
+    /// The time left until the key expires, or None if the key does not exist
+    /// or never expires. Use [`KVStore::exists`] to tell the two apart.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    /// use std::time::Duration;
+    ///
+    /// let store = KVStore::new();
+    /// let ttl = Duration::from_secs(60);
+    /// store
+    ///     .extend_with_ttl([("key".to_string(), "value".to_string(), Some(ttl))])
+    ///     .unwrap();
+    ///
+    /// assert!(store.ttl("key").unwrap().unwrap() <= ttl);
+    /// ```
+    pub fn ttl(&self, key: &str) -> Result<Option<Duration>, MiniRedisError> {
+        let (ttl, expired) = {
+            let mut store = self.get_store()?;
+            let expired = store.expire(key);
+            let ttl = store
+                .entries
+                .get(key)
+                .and_then(|entry| entry.expires_at)
+                .map(|at| at.saturating_duration_since(Instant::now()));
+            (ttl, expired)
+        };
+        if expired {
+            self.expired(key);
+        }
+        Ok(ttl)
+    }
+
+    /// Removes every key whose time to live has run out.
+    ///
+    /// Expired keys are never returned by the store, but they are only removed
+    /// when they are looked up. This removes the rest, freeing their memory.
+    ///
+    /// # Returns
+    ///
+    /// The number of keys that were removed.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    /// use std::time::Duration;
+    ///
+    /// let store = KVStore::new();
+    /// store
+    ///     .extend_with_ttl([("key".to_string(), "value".to_string(), Some(Duration::ZERO))])
+    ///     .unwrap();
+    ///
+    /// assert_eq!(Ok(1), store.purge_expired());
+    /// assert_eq!(Ok(0), store.len());
+    /// ```
+    pub fn purge_expired(&self) -> Result<usize, MiniRedisError> {
+        let expired = self.get_store()?.remove_expired();
+        for key in &expired {
+            self.expired(key);
+        }
+        Ok(expired.len())
+    }
+
+    /// Gets all keys of the store.
+    ///
+    /// # Returns
+    ///
+    /// The keys, in no particular order.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("key", "value").unwrap();
+    ///
+    /// assert_eq!(Ok(vec!["key".to_string()]), store.keys());
+    /// ```
+    pub fn keys(&self) -> Result<Vec<String>, MiniRedisError> {
+        let store = self.get_store()?;
+        Ok(store.live().map(|(key, _)| key.clone()).collect())
+    }
+
+    /// Gets a key of the store, chosen uniformly at random.
+    ///
+    /// The key is found by walking the entries to a random position under the lock,
+    /// so no key is copied but the one returned.
+    ///
+    /// # Returns
+    ///
+    /// A random key, or None if the store is empty.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// assert_eq!(Ok(None), store.random_key());
+    ///
+    /// store.set("key", "value").unwrap();
+    /// assert_eq!(Ok(Some("key".to_string())), store.random_key());
+    /// ```
+    pub fn random_key(&self) -> Result<Option<String>, MiniRedisError> {
+        self.random_key_with_prefix("")
+    }
+
+    /// Gets a key that starts with a prefix, chosen uniformly at random, with the prefix removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The prefix of the keys.
+    ///
+    /// # Returns
+    ///
+    /// The rest of a random key starting with the prefix, or None if there is none.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    pub(crate) fn random_key_with_prefix(
+        &self,
+        prefix: &str,
+    ) -> Result<Option<String>, MiniRedisError> {
+        let mut store = self.get_store()?;
+        let count = store
+            .live()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .count();
+        if count == 0 {
+            return Ok(None);
+        }
+        let index = store.rng.below(count);
+        Ok(store
+            .live()
+            .filter_map(|(key, _)| key.strip_prefix(prefix))
+            .nth(index)
+            .map(str::to_string))
+    }
+
+    /// Gets the keys that start with a prefix, with the prefix removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The prefix of the keys.
+    ///
+    /// # Returns
+    ///
+    /// The rest of every key starting with the prefix, in no particular order.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    pub(crate) fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, MiniRedisError> {
+        let store = self.get_store()?;
+        Ok(store
+            .live()
+            .filter_map(|(key, _)| key.strip_prefix(prefix))
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// Gets the next batch of keys of an incremental iteration.
+    ///
+    /// Keys are visited in the order of a hash of the key, and the cursor is
+    /// the hash to continue from, so the iteration needs no state in the store
+    /// and survives any number of writes in between calls.
+    /// An iteration starts with cursor 0 and is done when the returned cursor is 0.
+    /// Every key that exists during the whole iteration is returned exactly once.
+    /// Keys added or removed meanwhile may or may not be returned.
+    ///
+    /// The store keeps its keys in that order as they are written, so each call
+    /// only looks at about `count` keys, whatever the size of the store.
+    /// A batch can hold more than `count` keys when several keys have the same hash,
+    /// since they are never split across batches, and fewer when some have expired.
+    ///
+    /// # Arguments
+    ///
+    /// * `cursor` - The cursor returned by the previous call, or 0 to start.
+    /// * `count` - How many keys to look at, at least 1.
+    ///
+    /// # Returns
+    ///
+    /// The cursor to continue with and a batch of keys.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// for i in 0..10 {
+    ///     store.set(&format!("key{}", i), "value").unwrap();
+    /// }
+    ///
+    /// let mut keys = Vec::new();
+    /// let mut cursor = 0;
+    /// loop {
+    ///     let (next, batch) = store.scan(cursor, 3).unwrap();
+    ///     keys.extend(batch);
+    ///     if next == 0 {
+    ///         break;
+    ///     }
+    ///     cursor = next;
+    /// }
+    ///
+    /// assert_eq!(10, keys.len());
+    /// ```
+    pub fn scan(&self, cursor: u64, count: usize) -> Result<(u64, Vec<String>), MiniRedisError> {
+        let store = self.get_store()?;
+        let now = Instant::now();
+        Ok(store.scan_order.scan(cursor, count, |key| {
+            store
+                .entries
+                .get(key)
+                .is_some_and(|entry| !entry.is_expired(now))
+        }))
+    }
+
+    /// Creates a view of the keys that start with a prefix.
+    ///
+    /// The view shares the data of the store. Keys passed to the view get the prefix
+    /// added, and keys returned by it have the prefix removed, so subsystems sharing
+    /// a store can each use their own namespace without building keys by hand.
+    /// The prefix is used as is, so include a separator like `:` if you want one.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The prefix of the keys in the namespace.
+    ///
+    /// # Returns
+    ///
+    /// A view of the namespace.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// let sessions = store.namespace("sessions:");
+    ///
+    /// sessions.set("42", "alice").unwrap();
+    ///
+    /// assert_eq!(Ok(Some("alice".to_string())), store.get("sessions:42"));
+    /// assert_eq!(Ok(vec!["42".to_string()]), sessions.keys());
+    /// ```
+    pub fn namespace(&self, prefix: &str) -> NamespacedStore {
+        NamespacedStore::new(self.clone(), prefix.to_string())
+    }
+
+    /// Estimates the memory used by the store.
+    ///
+    /// The estimate is the sum of the key and value lengths of all entries,
+    /// plus [`ENTRY_OVERHEAD`] per entry.
+    /// It is maintained as entries change, so calling this is cheap.
+    ///
+    /// # Returns
+    ///
+    /// The estimated number of bytes used by the entries.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::{ENTRY_OVERHEAD, KVStore};
+    ///
+    /// let store = KVStore::new();
+    /// store.set("key", "value").unwrap();
+    ///
+    /// assert_eq!(Ok(3 + 5 + ENTRY_OVERHEAD), store.memory_usage());
+    /// ```
+    pub fn memory_usage(&self) -> Result<usize, MiniRedisError> {
+        let store = self.get_store()?;
+        Ok(store.memory)
+    }
+
+    /// Counts the keys of the store, and how many of them expire and when.
+    ///
+    /// Keys are counted like [`KVStore::len`] counts them, so a key whose time to live
+    /// ran out counts until it is looked up or purged, with no time left.
+    /// Expiry and eviction remove keys from the store, so they lower the counts too.
+    ///
+    /// # Returns
+    ///
+    /// The number of keys, the number of them with a time to live,
+    /// and the average time they have left.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("key", "value").unwrap();
+    ///
+    /// assert_eq!(1, store.keyspace().unwrap().keys);
+    /// assert_eq!(0, store.keyspace().unwrap().expires);
+    /// ```
+    pub fn keyspace(&self) -> Result<KeyspaceStats, MiniRedisError> {
+        let store = self.get_store()?;
+        let now = Instant::now();
+        let (expires, total) = store
+            .entries
+            .values()
+            .filter_map(|entry| entry.expires_at)
+            .fold((0, Duration::ZERO), |(expires, total), at| {
+                (expires + 1, total + at.saturating_duration_since(now))
+            });
+        Ok(KeyspaceStats {
+            keys: store.entries.len(),
+            expires,
+            avg_ttl: total / expires.max(1) as u32,
+        })
+    }
+
+    /// Estimates the memory used by a single key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to estimate the memory for.
+    ///
+    /// # Returns
+    ///
+    /// The estimated number of bytes used by the entry, or None if the key is not found.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::{ENTRY_OVERHEAD, KVStore};
+    ///
+    /// let store = KVStore::new();
+    /// store.set("key", "value").unwrap();
+    ///
+    /// assert_eq!(Ok(Some(3 + 5 + ENTRY_OVERHEAD)), store.memory_usage_of("key"));
+    /// assert_eq!(Ok(None), store.memory_usage_of("other_key"));
+    /// ```
+    pub fn memory_usage_of(&self, key: &str) -> Result<Option<usize>, MiniRedisError> {
+        let store = self.get_store()?;
+        Ok(store.peek_live(key).map(|value| entry_size(key, value)))
+    }
+
+    /// Gets the operation counters of the store.
+    ///
+    /// The counters are shared by all clones of the store,
+    /// and count from its creation or the last [`KVStore::reset_stats`].
+    ///
+    /// # Returns
+    ///
+    /// A copy of the current counters.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("key", "value").unwrap();
+    ///
+    /// assert_eq!(1, store.stats().sets);
+    /// ```
+    pub fn stats(&self) -> StoreStats {
+        self.counters.snapshot()
+    }
+
+    /// Sets the operation counters of the store back to zero.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("key", "value").unwrap();
+    /// store.reset_stats();
+    ///
+    /// assert_eq!(0, store.stats().sets);
+    /// ```
+    pub fn reset_stats(&self) {
+        self.counters.reset();
+    }
+
+    /// Subscribes to changes of the keys matching a pattern.
+    ///
+    /// Every change to a matching key is sent to the returned receiver
+    /// after the change has been applied and the store lock released,
+    /// so a subscriber may freely call back into the store.
+    /// Deleting a key that does not exist is not a change and sends nothing.
+    ///
+    /// The subscription ends when the receiver is dropped.
+    /// It is removed from the store the next time a matching key changes.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - A glob-style pattern, like `user:*`, that keys must match.
+    ///
+    /// # Returns
+    ///
+    /// A receiver of the events for the matching keys.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::{KVStore, KeyEventKind};
+    ///
+    /// let store = KVStore::new();
+    /// let events = store.subscribe("*");
+    ///
+    /// store.set("key", "value").unwrap();
+    /// store.del("key").unwrap();
+    ///
+    /// assert_eq!(KeyEventKind::Set, events.recv().unwrap().kind);
+    /// assert_eq!(KeyEventKind::Del, events.recv().unwrap().kind);
+    /// ```
+    pub fn subscribe(&self, pattern: &str) -> Receiver<KeyEvent> {
+        let (sender, receiver) = mpsc::channel();
+        // A poisoned subscriber list is still structurally sound, so keep using it.
+        let mut subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        subscribers.push(Subscriber {
+            pattern: pattern.to_string(),
+            sender,
+        });
+        receiver
+    }
+
+    /// Copies all entries of the store.
+    ///
+    /// The entries are copied under the lock, which is released before returning,
+    /// so the result is a consistent point-in-time view of the whole store.
+    /// The entries are in no particular order.
+    ///
+    /// # Returns
+    ///
+    /// The key-value pairs of the store.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("key", "value").unwrap();
+    ///
+    /// assert_eq!(Ok(vec![("key".to_string(), b"value".to_vec())]), store.snapshot());
+    /// ```
+    pub fn snapshot(&self) -> Result<Vec<(String, Vec<u8>)>, MiniRedisError> {
+        let store = self.get_store()?;
+        Ok(store
+            .live()
+            .map(|(key, entry)| (key.clone(), entry.value.clone()))
+            .collect())
+    }
+
+    /// Takes a point-in-time snapshot of the store.
+    ///
+    /// The snapshot is consistent and unaffected by later writes, which makes it
+    /// suitable for backups taken while the store is in use.
+    /// It is a copy of every entry made under the lock, so writers are paused for
+    /// the duration of the copy, which grows with the size of the store:
+    /// around a few hundred milliseconds for a million small entries
+    /// (see the `snapshot_handle` case of `cargo bench --bench store`).
+    ///
+    /// # Returns
+    ///
+    /// A snapshot of the entries of the store.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("key", "value").unwrap();
+    ///
+    /// let snapshot = store.snapshot_handle().unwrap();
+    /// store.del("key").unwrap();
+    ///
+    /// assert_eq!(1, snapshot.len());
+    /// ```
+    pub fn snapshot_handle(&self) -> Result<StoreSnapshot, MiniRedisError> {
+        Ok(StoreSnapshot {
+            entries: self.snapshot()?,
+        })
+    }
+
+    /// Calls a function for every entry of the store.
+    ///
+    /// Unlike [`KVStore::snapshot`], this does not copy all values at once.
+    /// The keys are copied first, then the values are copied in chunks with
+    /// one lock acquisition per chunk, and the lock is never held while `f` runs.
+    /// This bounds peak memory for large stores, at the cost of consistency:
+    /// each chunk is a point-in-time view, but the whole iteration is not.
+    /// Keys deleted before their chunk is reached are skipped,
+    /// and keys added after the call started are not visited.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - The function to call with each key and value.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether every entry was visited.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("a", "1").unwrap();
+    /// store.set("b", "2").unwrap();
+    ///
+    /// let mut total = 0;
+    /// store
+    ///     .for_each(|_, value| total += std::str::from_utf8(value).unwrap().parse::<i32>().unwrap())
+    ///     .unwrap();
+    ///
+    /// assert_eq!(3, total);
+    /// ```
+    pub fn for_each(&self, mut f: impl FnMut(&str, &[u8])) -> Result<(), MiniRedisError> {
+        let keys = {
+            let store = self.get_store()?;
+            store
+                .live()
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<String>>()
+        };
+
+        for chunk in keys.chunks(CHUNK_SIZE) {
+            let entries = {
+                let store = self.get_store()?;
+                chunk
+                    .iter()
+                    .filter_map(|key| store.peek_live(key).map(|value| (key, value.to_vec())))
+                    .collect::<Vec<(&String, Vec<u8>)>>()
+            };
+            for (key, value) in entries {
+                f(key, &value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Saves the store to a snapshot file.
+    ///
+    /// The contents are copied under the lock and written after it is released,
+    /// so other threads can keep using the store while the file is written.
+    /// The snapshot is written to a temporary file that is renamed over `path`,
+    /// so an existing snapshot is only replaced once the new one is complete.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the snapshot file.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the snapshot was saved successfully.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, or the file cannot be written, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("key", "value").unwrap();
+    ///
+    /// store.save_to_file("dump.mrdb").unwrap();
+    /// ```
+    pub fn save_to_file(&self, path: impl AsRef<Path>) -> Result<(), MiniRedisError> {
+        snapshot::write(path.as_ref(), &self.snapshot_records()?, false)
+    }
+
+    /// Saves the store to a snapshot file, with its entries compressed.
+    ///
+    /// It works like [`KVStore::save_to_file`], but the file is smaller when the values
+    /// repeat themselves, like JSON documents do. [`KVStore::load_from_file`] tells
+    /// compressed snapshots from plain ones by their header.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the snapshot file.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the snapshot was saved successfully.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, or the file cannot be written, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("user:1", r#"{"name":"alice"}"#).unwrap();
+    ///
+    /// store.save_to_file_compressed("dump.mrdb").unwrap();
+    /// ```
+    #[cfg(feature = "compression")]
+    pub fn save_to_file_compressed(&self, path: impl AsRef<Path>) -> Result<(), MiniRedisError> {
+        snapshot::write(path.as_ref(), &self.snapshot_records()?, true)
+    }
+
+    /// Copies the live entries of the store under the lock, with their expiry times
+    /// as wall clock times, for a snapshot file.
+    fn snapshot_records(&self) -> Result<Vec<snapshot::Record>, MiniRedisError> {
+        let store = self.get_store()?;
+        let (now, wall_now) = (Instant::now(), SystemTime::now());
+        Ok(store
+            .live()
+            .map(|(key, entry)| {
+                let expires_at = entry
+                    .expires_at
+                    .map(|at| wall_now + at.saturating_duration_since(now));
+                (key.clone(), entry.value.clone(), expires_at)
+            })
+            .collect())
+    }
+
+    /// Loads a store from a snapshot file.
+    ///
+    /// Keys keep their time to live, counted from when the snapshot was saved,
+    /// and keys that expired since are not loaded.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the snapshot file.
+    ///
+    /// # Returns
+    ///
+    /// A new KVStore containing the entries of the snapshot.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be read, or is not a valid snapshot, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::load_from_file("dump.mrdb").unwrap();
+    /// let value = store.get("key");
+    /// ```
+    pub fn load_from_file(path: impl AsRef<Path>) -> Result<KVStore, MiniRedisError> {
+        let records = snapshot::read(path.as_ref())?;
+        let (now, wall_now) = (Instant::now(), SystemTime::now());
+        let entries = records
+            .into_iter()
+            .filter_map(|(key, value, expires_at)| match expires_at {
+                None => Some((key, value, None)),
+                Some(at) => at
+                    .duration_since(wall_now)
+                    .ok()
+                    .filter(|ttl| !ttl.is_zero())
+                    .map(|ttl| (key, value, Some(now + ttl))),
+            });
+        Ok(Self::from_entries(entries))
+    }
+
+    /// Deletes every key accepted by a predicate.
+    ///
+    /// The keys are copied under the lock and filtered outside it,
+    /// then removed in chunks so the lock is never held for long,
+    /// even when a large part of the store is deleted.
+    ///
+    /// # Arguments
+    ///
+    /// * `predicate` - Returns true for the keys to delete.
+    ///
+    /// # Returns
+    ///
+    /// The number of keys that were deleted.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    fn del_where(&self, predicate: impl Fn(&str) -> bool) -> Result<usize, MiniRedisError> {
+        let keys = {
+            let store = self.get_store()?;
+            store
+                .live()
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<String>>()
+        };
+        let matching = keys
+            .into_iter()
+            .filter(|key| predicate(key))
+            .collect::<Vec<String>>();
+
+        let mut deleted = 0;
+        for chunk in matching.chunks(CHUNK_SIZE) {
+            let removed = {
+                let mut store = self.get_store()?;
+                chunk
+                    .iter()
+                    .filter(|key| store.remove(key).is_some())
+                    .map(|key| (key, self.ticket()))
+                    .collect::<Vec<(&String, Option<u64>)>>()
+            };
+            deleted += removed.len();
+            for (key, ticket) in removed {
+                self.write_through(ticket, key, None);
+                self.counters.record_del();
+                self.notify(key, KeyEventKind::Del, None);
+            }
+        }
+        Ok(deleted)
+    }
+
+    /// Loads a key this store is missing from the backing store, once for every thread missing it.
+    ///
+    /// The value is stored without a time to live, unless the key was set in the meantime,
+    /// in which case that value wins. Loading it is not a set, so it is neither counted,
+    /// notified, nor written back.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key that was missed.
+    ///
+    /// # Returns
+    ///
+    /// The bytes of the value of the key, or None if the backing store lacks it too.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, or full and cannot make room for the value,
+    /// it will return an error.
+    fn load(&self, key: &str) -> Result<Option<Vec<u8>>, MiniRedisError> {
+        self.backing.load(
+            key,
+            |loaded| {
+                let Some(loaded) = loaded.map(String::into_bytes) else {
+                    return Ok(None);
+                };
+                let (value, evicted) = {
+                    let mut store = self.get_store()?;
+                    match store.peek_live(key) {
+                        Some(value) => (value.to_vec(), Vec::new()),
+                        None => {
+                            let evicted = store.make_room(key, loaded.len())?;
+                            store.insert(key.to_string(), loaded.clone(), None);
+                            (loaded, evicted)
+                        }
+                    }
+                };
+                self.counters.record_evictions(evicted.len());
+                for victim in evicted {
+                    self.notify(&victim, KeyEventKind::Evicted, None);
+                }
+                Ok(Some(value))
+            },
+            || Ok(self.get_store()?.get(key).map(<[u8]>::to_vec)),
+        )
+    }
+
+    /// Hands out the number of a write to go through to the backing store,
+    /// while the store is still locked after making it.
+    ///
+    /// # Returns
+    ///
+    /// The number of the write, or None if writes do not go through.
+    fn ticket(&self) -> Option<u64> {
+        self.backing.writes().then(|| self.backing.ticket())
+    }
+
+    /// Writes a change through to the backing store, in the order the changes were made.
+    ///
+    /// Must not be called while holding the store lock.
+    ///
+    /// # Arguments
+    ///
+    /// * `ticket` - The number of the write, from [`KVStore::ticket`].
+    /// * `key` - The key that changed.
+    /// * `value` - The new value of the key, or None if it was deleted.
+    fn write_through(&self, ticket: Option<u64>, key: &str, value: Option<&str>) {
+        if let Some(ticket) = ticket {
+            self.backing.write(ticket, key, value);
+        }
+    }
+
+    /// Counts and notifies a key that was removed because it expired.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key that expired.
+    fn expired(&self, key: &str) {
+        self.counters.record_expired(1);
+        self.notify(key, KeyEventKind::Expired, None);
+    }
+
+    /// Checks whether anyone is subscribed to the store.
+    ///
+    /// # Returns
+    ///
+    /// True if there is at least one subscriber.
+    fn has_subscribers(&self) -> bool {
+        let subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        !subscribers.is_empty()
+    }
+
+    /// Notifies the subscribers matching a key about a change.
+    ///
+    /// Subscribers whose receiver has been dropped are removed.
+    /// Must not be called while holding the store lock.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key that changed.
+    /// * `kind` - The kind of change.
+    /// * `value` - The new value of the key, if any.
+    fn notify(&self, key: &str, kind: KeyEventKind, value: Option<&str>) {
+        let mut subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        if subscribers.is_empty() {
+            return;
+        }
+
+        subscribers.retain(|subscriber| {
+            if !glob::matches(&subscriber.pattern, key) {
+                return true;
+            }
+            let event = KeyEvent {
+                key: key.to_string(),
+                kind,
+                value: value.map(|v| v.to_string()),
+            };
+            subscriber.sender.send(event).is_ok()
+        });
+    }
+
+    /// Gets a mutable reference to the store.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the store.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    fn get_store(&self) -> Result<MutexGuard<'_, Data>, MiniRedisError> {
+        self.store.lock().map_err(|_| MiniRedisError::StoreLocked)
+    }
+}
+
+impl Default for KVStore {
+    /// Creates a new, empty KVStore.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::default();
+    ///
+    /// assert_eq!(Ok(true), store.is_empty());
+    /// ```
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FromIterator<(String, String)> for KVStore {
+    /// Creates a new KVStore holding the given entries.
+    ///
+    /// Room for the entries is allocated up front, from the size hint of the iterator,
+    /// and duplicate keys are resolved by last-write-wins.
+    /// Building a store this way is not counted in its stats.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = (0..3)
+    ///     .map(|i| (format!("key{}", i), i.to_string()))
+    ///     .collect::<KVStore>();
+    ///
+    /// assert_eq!(Ok(3), store.len());
+    /// assert_eq!(Ok(Some("2".to_string())), store.get("key2"));
+    /// ```
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        Self::from_entries(
+            iter.into_iter()
+                .map(|(key, value)| (key, value.into_bytes(), None)),
+        )
+    }
+}
+
+impl Clone for KVStore {
+    /// Creates another handle to the same store.
+    ///
+    /// This only clones the internal `Arc`s, so it is cheap,
+    /// and both handles read and write the same data.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// let handle = store.clone();
+    ///
+    /// handle.set("key", "value").unwrap();
+    ///
+    /// assert_eq!(Ok(Some("value".to_string())), store.get("key"));
+    /// ```
+    fn clone(&self) -> Self {
+        Self {
+            store: Arc::clone(&self.store),
+            subscribers: Arc::clone(&self.subscribers),
+            counters: Arc::clone(&self.counters),
+            backing: Arc::clone(&self.backing),
+        }
+    }
+}
+
+/// Builds a [`KVStore`] with options, like a backing store it reads and writes through to.
+///
+/// # Examples
+///
+/// ```rust
+/// use miniredis::eviction::{Capacity, EvictionPolicy};
+/// use miniredis::kv_store::KVStore;
+///
+/// let store = KVStore::builder()
+///     .capacity_policy(Capacity::Entries(1_000), EvictionPolicy::Lru)
+///     .loader(|key| Some(format!("loaded {}", key)))
+///     .build();
+///
+/// assert_eq!(Ok(Some("loaded a".to_string())), store.get("a"));
+/// ```
+#[derive(Default)]
+pub struct KVStoreBuilder {
+    capacity: usize,
+    limit: Option<(Capacity, EvictionPolicy)>,
+    loader: Option<Box<Loader>>,
+    writer: Option<Box<Writer>>,
+}
+
+impl KVStoreBuilder {
+    /// Allocates room for a number of entries up front, like [`KVStore::with_capacity`].
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The number of entries to allocate room for.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::builder().capacity(1_000).build();
+    ///
+    /// assert!(store.capacity().unwrap() >= 1_000);
+    /// ```
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Keeps the store from growing beyond a capacity, like [`KVStore::with_capacity_policy`].
+    ///
+    /// A key evicted to make room is only dropped from the store, and not deleted
+    /// from the backing store, so a loader brings it back on the next miss.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The maximum number of entries or bytes.
+    /// * `policy` - How to make room when a write would exceed the capacity.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::eviction::{Capacity, EvictionPolicy};
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let builder = KVStore::builder().capacity_policy(Capacity::Entries(2), EvictionPolicy::Lru);
+    /// ```
+    pub fn capacity_policy(mut self, capacity: Capacity, policy: EvictionPolicy) -> Self {
+        self.limit = Some((capacity, policy));
+        self
+    }
+
+    /// Reads through to a backing store on a miss.
+    ///
+    /// A get of a key the store does not hold calls the loader, stores what it found,
+    /// and returns it. Threads that miss the same key while it loads wait for that load,
+    /// instead of calling the loader again. The loader runs without the store locked,
+    /// and one that panics is taken for one that found nothing.
+    ///
+    /// # Arguments
+    ///
+    /// * `loader` - Gets the value of a key from the backing store, or None if it lacks it too.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::builder()
+    ///     .loader(|key| (key == "user").then(|| "alice".to_string()))
+    ///     .build();
+    ///
+    /// assert_eq!(Ok(Some("alice".to_string())), store.get("user"));
+    /// assert_eq!(Ok(1), store.len());
+    /// assert_eq!(Ok(None), store.get("missing"));
+    /// ```
+    pub fn loader(
+        mut self,
+        loader: impl Fn(&str) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.loader = Some(Box::new(loader));
+        self
+    }
+
+    /// Writes through to a backing store after every change.
+    ///
+    /// The writer is called with the key and its value once a set is made,
+    /// and with None once a delete is, whether or not the store held the key.
+    /// Keys that are evicted or expire are not written. The writer runs without the store
+    /// locked, in the order the changes were made, and one that panics loses that change
+    /// and nothing else. It must not write to the store itself.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - Writes a key, with its value or None, to the backing store.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let written = Arc::new(Mutex::new(Vec::new()));
+    /// let log = Arc::clone(&written);
+    /// let store = KVStore::builder()
+    ///     .writer(move |key, value| {
+    ///         log.lock().unwrap().push((key.to_string(), value.map(str::to_string)))
+    ///     })
+    ///     .build();
+    ///
+    /// store.set("user", "alice").unwrap();
+    /// store.del("user").unwrap();
+    ///
+    /// assert_eq!(
+    ///     vec![("user".to_string(), Some("alice".to_string())), ("user".to_string(), None)],
+    ///     *written.lock().unwrap()
+    /// );
+    /// ```
+    pub fn writer(mut self, writer: impl Fn(&str, Option<&str>) + Send + Sync + 'static) -> Self {
+        self.writer = Some(Box::new(writer));
+        self
+    }
+
+    /// Builds the store.
+    ///
+    /// # Returns
+    ///
+    /// A new, empty KVStore.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::builder().build();
+    ///
+    /// assert_eq!(Ok(true), store.is_empty());
+    /// ```
+    pub fn build(self) -> KVStore {
+        let mut data = Data::new(self.capacity);
+        data.limit = self.limit;
+        KVStore {
+            backing: Arc::new(Backing::new(self.loader, self.writer)),
+            ..KVStore::from_data(data)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// Returns a path in the temporary directory that is unique to the test.
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("miniredis-{}-{}", std::process::id(), name))
+    }
+
+    /// Checks whether the temporary file used while saving `path` was left behind.
+    fn snapshot_temp_exists(path: &Path) -> bool {
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        Path::new(&temp).exists()
+    }
+
+    #[test]
+    fn keyspace_counts_keys_with_a_time_to_live() {
+        let store = KVStore::new();
+        store.set("a", "1").unwrap();
+        store
+            .extend_with_ttl([
+                (
+                    "b".to_string(),
+                    "2".to_string(),
+                    Some(Duration::from_secs(100)),
+                ),
+                (
+                    "c".to_string(),
+                    "3".to_string(),
+                    Some(Duration::from_secs(200)),
+                ),
+            ])
+            .unwrap();
+
+        let keyspace = store.keyspace().unwrap();
+
+        assert_eq!(3, keyspace.keys);
+        assert_eq!(2, keyspace.expires);
+        assert!(
+            keyspace.avg_ttl <= Duration::from_secs(150)
+                && keyspace.avg_ttl > Duration::from_secs(149)
+        );
+
+        store.del("b").unwrap();
+        assert_eq!(1, store.keyspace().unwrap().expires);
+    }
+
+    #[test]
+    fn increment_keeps_the_time_to_live_and_refuses_overflow() {
+        let store = KVStore::new();
+        store
+            .extend_with_ttl([(
+                "n".to_string(),
+                i64::MAX.to_string(),
+                Some(Duration::from_secs(100)),
+            )])
+            .unwrap();
+
+        assert_eq!(Ok(i64::MAX - 1), store.increment("n", -1));
+        assert!(store.ttl("n").unwrap().is_some());
+        assert_eq!(
+            Err(MiniRedisError::IncrementOverflow),
+            store.increment("n", 2)
+        );
+        assert_eq!(Ok(Some((i64::MAX - 1).to_string())), store.get("n"));
+    }
+
+    #[test]
+    fn increment_float_keeps_the_time_to_live_and_refuses_infinity() {
+        let store = KVStore::new();
+        store
+            .extend_with_ttl([(
+                "n".to_string(),
+                "1.5".to_string(),
+                Some(Duration::from_secs(100)),
+            )])
+            .unwrap();
+
+        assert_eq!(Ok(2.0), store.increment_float("n", 0.5));
+        assert!(store.ttl("n").unwrap().is_some());
+        assert_eq!(
+            Err(MiniRedisError::IncrementNotFinite),
+            store.increment_float("n", f64::MAX * 2.0)
+        );
+        assert_eq!(Ok(Some("2".to_string())), store.get("n"));
+    }
+
+    #[test]
+    fn set_returning_old_clears_the_time_to_live_and_skips_expired_values() {
+        let store = KVStore::new();
+        store
+            .extend_with_ttl([
+                (
+                    "live".to_string(),
+                    "a".to_string(),
+                    Some(Duration::from_secs(100)),
+                ),
+                ("dead".to_string(), "a".to_string(), Some(Duration::ZERO)),
+            ])
+            .unwrap();
+
+        assert_eq!(
+            Ok(Some(b"a".to_vec())),
+            store.set_returning_old("live", "b")
+        );
+        assert_eq!(Ok(None), store.ttl("live"));
+        assert_eq!(Ok(None), store.set_returning_old("dead", "b"));
+    }
+
+    #[test]
+    fn expire_sets_the_time_to_live_of_existing_keys_only() {
+        let store = KVStore::new();
+        store.set("key", "value").unwrap();
+
+        assert_eq!(
+            Ok(true),
+            store.expire("key", Duration::from_secs(100), None)
+        );
+        let ttl = store.ttl("key").unwrap().unwrap();
+        assert!(ttl > Duration::from_secs(99) && ttl <= Duration::from_secs(100));
+        assert_eq!(
+            Ok(false),
+            store.expire("missing", Duration::from_secs(100), None)
+        );
+        assert_eq!(Ok(false), store.exists("missing"));
+
+        assert_eq!(Ok(true), store.expire("key", Duration::ZERO, None));
+        assert_eq!(Ok(None), store.get("key"));
+        assert_eq!(
+            Ok(false),
+            store.expire("key", Duration::from_secs(100), None)
+        );
+    }
+
+    #[test]
+    fn expire_checks_its_condition_against_the_current_deadline() {
+        let store = KVStore::new();
+        store.set("key", "value").unwrap();
+        let minute = Duration::from_secs(60);
+
+        assert_eq!(
+            Ok(false),
+            store.expire("key", minute, Some(ExpireCondition::IfLater))
+        );
+        assert_eq!(
+            Ok(true),
+            store.expire("key", minute, Some(ExpireCondition::IfNone))
+        );
+        assert_eq!(
+            Ok(false),
+            store.expire("key", minute * 2, Some(ExpireCondition::IfEarlier))
+        );
+        assert_eq!(
+            Ok(false),
+            store.expire("key", Duration::ZERO, Some(ExpireCondition::IfNone))
+        );
+        assert_eq!(Ok(true), store.exists("key"));
+        assert_eq!(
+            Ok(true),
+            store.expire("key", Duration::ZERO, Some(ExpireCondition::IfEarlier))
+        );
+        assert_eq!(Ok(false), store.exists("key"));
+    }
+
+    #[test]
+    fn persist_removes_only_the_time_to_live() {
+        let store = KVStore::new();
+        store.set("key", "value").unwrap();
+        store.expire("key", Duration::from_secs(100), None).unwrap();
+
+        assert_eq!(Ok(true), store.persist("key"));
+        assert_eq!(Ok(None), store.ttl("key"));
+        assert_eq!(Ok(Some("value".to_string())), store.get("key"));
+        assert_eq!(Ok(false), store.persist("key"));
+        assert_eq!(Ok(false), store.persist("missing"));
+    }
+
+    #[test]
+    fn get_and_expire_sets_and_removes_the_time_to_live() {
+        let store = KVStore::new();
+        store.set("key", "value").unwrap();
+
+        assert_eq!(
+            Ok(Some(b"value".to_vec())),
+            store.get_and_expire("key", Some(Duration::from_secs(100)))
+        );
+        let ttl = store.ttl("key").unwrap().unwrap();
+        assert!(ttl > Duration::from_secs(99) && ttl <= Duration::from_secs(100));
+
+        assert_eq!(
+            Ok(Some(b"value".to_vec())),
+            store.get_and_expire("key", None)
+        );
+        assert_eq!(Ok(None), store.ttl("key"));
+        assert_eq!(Ok(true), store.exists("key"));
+
+        assert_eq!(
+            Ok(None),
+            store.get_and_expire("missing", Some(Duration::from_secs(1)))
+        );
+        assert_eq!(Ok(false), store.exists("missing"));
+    }
+
+    #[test]
+    fn set_with_if_present_skips_missing_and_expired_keys() {
+        let store = KVStore::new();
+        store
+            .extend_with_ttl([("old".to_string(), "a".to_string(), Some(Duration::ZERO))])
+            .unwrap();
+        let options = SetOptions {
+            condition: Some(SetCondition::IfPresent),
+            ..SetOptions::default()
+        };
+
+        assert_eq!(Ok(false), store.set_with("missing", "b", &options));
+        assert_eq!(Ok(false), store.set_with("old", "b", &options));
+        assert_eq!(Ok(false), store.exists("old"));
+        assert_eq!(Ok(true), store.set_with("old", "c", &SetOptions::default()));
+        assert_eq!(Ok(true), store.set_with("old", "d", &options));
+        assert_eq!(Ok(Some("d".to_string())), store.get("old"));
+    }
+
+    #[test]
+    fn set_with_sets_keeps_or_clears_the_time_to_live() {
+        let store = KVStore::new();
+        let after = |ttl| SetOptions {
+            expiry: SetExpiry::After(ttl),
+            ..SetOptions::default()
+        };
+        let keep = SetOptions {
+            expiry: SetExpiry::Keep,
+            ..SetOptions::default()
+        };
+
+        assert_eq!(
+            Ok(true),
+            store.set_with("key", "a", &after(Duration::from_secs(100)))
+        );
+        assert!(store.ttl("key").unwrap().unwrap() > Duration::from_secs(99));
+        assert_eq!(Ok(true), store.set_with("key", "b", &keep));
+        assert!(store.ttl("key").unwrap().unwrap() > Duration::from_secs(99));
+        assert_eq!(Ok(Some("b".to_string())), store.get("key"));
+
+        assert_eq!(Ok(true), store.set_with("key", "c", &SetOptions::default()));
+        assert_eq!(Ok(None), store.ttl("key"));
+        assert_eq!(Ok(true), store.set_with("other", "d", &keep));
+        assert_eq!(Ok(None), store.ttl("other"));
+    }
+
+    #[test]
+    fn set_if_absent_takes_the_place_of_an_expired_key() {
+        let store = KVStore::new();
+        store
+            .extend_with_ttl([("lock".to_string(), "old".to_string(), Some(Duration::ZERO))])
+            .unwrap();
+
+        assert_eq!(Ok(true), store.set_if_absent("lock", "new"));
+        assert_eq!(Ok(None), store.ttl("lock"));
+        assert_eq!(Ok(Some("new".to_string())), store.get("lock"));
+    }
+
+    #[test]
+    fn multi_set_writes_every_pair_and_clears_times_to_live() {
+        let store = KVStore::new();
+        store.set("a", "old").unwrap();
+        store
+            .get_and_expire("a", Some(Duration::from_secs(100)))
+            .unwrap();
+        let pairs = [
+            ("a".to_string(), "1".to_string()),
+            ("b".to_string(), "2".to_string()),
+            ("a".to_string(), "3".to_string()),
+        ];
+
+        assert_eq!(Ok(()), store.multi_set(&pairs));
+        assert_eq!(Ok(Some("3".to_string())), store.get("a"));
+        assert_eq!(Ok(Some("2".to_string())), store.get("b"));
+        assert_eq!(Ok(None), store.ttl("a"));
+        assert_eq!(Ok(2), store.len());
+    }
+
+    #[test]
+    fn multi_set_if_absent_writes_nothing_if_any_key_exists() {
+        let store = KVStore::new();
+        store.set("k3", "taken").unwrap();
+        let pairs = (1..=5)
+            .map(|i| (format!("k{}", i), format!("v{}", i)))
+            .collect::<Vec<(String, String)>>();
+
+        assert_eq!(Ok(false), store.multi_set_if_absent(&pairs));
+        for key in ["k1", "k2", "k4", "k5"] {
+            assert_eq!(Ok(None), store.get(key), "{}", key);
+        }
+        assert_eq!(Ok(Some("taken".to_string())), store.get("k3"));
+
+        store.del("k3").unwrap();
+        assert_eq!(Ok(true), store.multi_set_if_absent(&pairs));
+        assert_eq!(Ok(5), store.len());
+    }
+
+    #[test]
+    fn concurrent_readers_never_see_half_of_a_multi_set() {
+        let store = KVStore::new();
+        let done = std::sync::atomic::AtomicBool::new(false);
+
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                for round in 0..200 {
+                    let pairs = (0..10)
+                        .map(|i| (format!("key:{}:{}", round, i), "value".to_string()))
+                        .collect::<Vec<(String, String)>>();
+                    store.multi_set(&pairs).unwrap();
+                }
+                done.store(true, std::sync::atomic::Ordering::Release);
+            });
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    while !done.load(std::sync::atomic::Ordering::Acquire) {
+                        let len = store.len().unwrap();
+                        assert_eq!(0, len % 10, "saw {} keys", len);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(Ok(2000), store.len());
+    }
+
+    #[test]
+    fn concurrent_appends_are_never_lost() {
+        let store = KVStore::new();
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..100 {
+                        store.append("log", "x").unwrap();
+                    }
+                });
+            }
+        });
+
+        assert_eq!(Ok(Some("x".repeat(800))), store.get("log"));
+    }
+
+    #[test]
+    fn concurrent_increments_are_never_lost() {
+        let store = KVStore::new();
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..1_000 {
+                        store.increment("n", 1).unwrap();
+                    }
+                });
+            }
+        });
+
+        assert_eq!(Ok(Some("8000".to_string())), store.get("n"));
+    }
+
+    #[test]
+    fn new_creates_empty_store() {
+        let store = KVStore::new();
+
+        assert_eq!(Ok(None), store.get("key"));
+    }
+
+    #[test]
+    fn get_returns_value_if_set() {
+        let store = KVStore::new();
+
+        store.set("key", "value").unwrap();
+
+        assert_eq!(Ok(Some("value".to_string())), store.get("key"));
+    }
+
+    #[test]
+    fn get_returns_none_if_not_set() {
+        let store = KVStore::new();
+
+        assert_eq!(Ok(None), store.get("key"));
+    }
+
+    #[test]
+    fn get_returns_none_if_not_set_and_other_key_is_set() {
+        let store = KVStore::new();
+
+        store.set("key", "value").unwrap();
+
+        assert_eq!(Ok(None), store.get("other_key"));
+    }
+
+    #[test]
+    fn get_returns_value_if_set_and_other_key_is_set() {
+        let store = KVStore::new();
+
+        store.set("key", "value").unwrap();
+        store.set("other_key", "other_value").unwrap();
+
+        assert_eq!(Ok(Some("value".to_string())), store.get("key"));
+    }
+
+    #[test]
+    fn get_returns_none_if_deleted() {
+        let store = KVStore::new();
+
+        store.set("key", "value").unwrap();
+        store.del("key").unwrap();
+
+        assert_eq!(Ok(None), store.get("key"));
+    }
+
+    #[test]
+    fn set_sets_value() {
+        let store = KVStore::new();
+
+        store.set("key", "value").unwrap();
+
+        assert_eq!(Ok(Some("value".to_string())), store.get("key"));
+    }
+
+    #[test]
+    fn delete_deletes_value() {
+        let store = KVStore::new();
+
+        store.set("key", "value").unwrap();
+        store.del("key").unwrap();
+
+        assert_eq!(Ok(None), store.get("key"));
+    }
+
+    #[test]
+    fn delete_does_nothing_if_key_not_set() {
+        let store = KVStore::new();
+
+        store.del("key").unwrap();
+
+        assert_eq!(Ok(None), store.get("key"));
+    }
+
+    #[test]
+    fn delete_does_nothing_if_key_not_set_and_other_key_is_set() {
+        let store = KVStore::new();
+
+        store.set("other_key", "other_value").unwrap();
+        store.del("key").unwrap();
+
+        assert_eq!(Ok(None), store.get("key"));
+    }
+
+    #[test]
+    fn exists_does_not_count_as_get() {
+        let store = KVStore::new();
+        store.set("key", "value").unwrap();
+
+        assert_eq!(Ok(true), store.exists("key"));
+        assert_eq!(Ok(false), store.exists("other_key"));
+        assert_eq!(0, store.stats().gets);
+    }
+
+    #[test]
+    fn scan_returns_every_key_once() {
+        let store = KVStore::new();
+        for i in 0..100 {
+            store.set(&format!("key{}", i), "value").unwrap();
+        }
+
+        let mut keys = Vec::new();
+        let mut cursor = 0;
+        loop {
+            let (next, batch) = store.scan(cursor, 7).unwrap();
+            assert!(batch.len() <= 7);
+            keys.extend(batch);
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+
+        let mut expected = store.keys().unwrap();
+        expected.sort();
+        keys.sort();
+        assert_eq!(expected, keys);
+    }
+
+    #[test]
+    fn scan_returns_keys_that_survive_writes_between_batches() {
+        let store = KVStore::new();
+        for i in 0..50 {
+            store.set(&format!("keep{}", i), "value").unwrap();
+        }
+
+        let mut keys = Vec::new();
+        let mut cursor = 0;
+        let mut round = 0;
+        loop {
+            let (next, batch) = store.scan(cursor, 5).unwrap();
+            keys.extend(batch);
+            store.set(&format!("new{}", round), "value").unwrap();
+            round += 1;
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+
+        for i in 0..50 {
+            let key = format!("keep{}", i);
+            assert_eq!(1, keys.iter().filter(|k| **k == key).count());
+        }
+    }
+
+    #[test]
+    fn random_key_returns_every_key_sometimes_and_never_an_expired_one() {
+        let store = KVStore::new();
+        for i in 0..5 {
+            store.set(&format!("key:{}", i), "value").unwrap();
+        }
+        store.set("gone", "value").unwrap();
+        store
+            .get_and_expire("gone", Some(Duration::from_millis(1)))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+
+        let seen = (0..500)
+            .map(|_| store.random_key().unwrap().unwrap())
+            .collect::<std::collections::HashSet<String>>();
+
+        let expected = (0..5)
+            .map(|i| format!("key:{}", i))
+            .collect::<std::collections::HashSet<String>>();
+        assert_eq!(expected, seen);
+    }
+
+    #[test]
+    fn rename_moves_the_value_and_time_to_live_over_the_destination() {
+        let store = KVStore::new();
+        store.set("source", "new").unwrap();
+        store
+            .get_and_expire("source", Some(Duration::from_secs(100)))
+            .unwrap();
+        store.set("destination", "old").unwrap();
+
+        assert_eq!(Ok(()), store.rename("source", "destination"));
+        assert_eq!(Ok(false), store.exists("source"));
+        assert_eq!(Ok(Some("new".to_string())), store.get("destination"));
+        assert!(store.ttl("destination").unwrap().unwrap() > Duration::from_secs(99));
+        assert_eq!(Ok(1), store.len());
+    }
+
+    #[test]
+    fn rename_of_a_missing_key_fails_and_onto_itself_changes_nothing() {
+        let store = KVStore::new();
+        store.set("key", "value").unwrap();
+
+        assert_eq!(
+            Err(MiniRedisError::NoSuchKey),
+            store.rename("missing", "key")
+        );
+        assert_eq!(Ok(Some("value".to_string())), store.get("key"));
+        assert_eq!(Ok(()), store.rename("key", "key"));
+        assert_eq!(Ok(Some("value".to_string())), store.get("key"));
+    }
+
+    #[test]
+    fn rename_if_absent_leaves_an_existing_destination_alone() {
+        let store = KVStore::new();
+        store.set("source", "new").unwrap();
+        store.set("destination", "old").unwrap();
+
+        assert_eq!(Ok(false), store.rename_if_absent("source", "destination"));
+        assert_eq!(Ok(Some("new".to_string())), store.get("source"));
+        assert_eq!(Ok(Some("old".to_string())), store.get("destination"));
+        assert_eq!(Ok(false), store.rename_if_absent("source", "source"));
+
+        assert_eq!(Ok(true), store.rename_if_absent("source", "other"));
+        assert_eq!(Ok(Some("new".to_string())), store.get("other"));
+        assert_eq!(
+            Err(MiniRedisError::NoSuchKey),
+            store.rename_if_absent("source", "elsewhere")
+        );
+    }
+
+    #[test]
+    fn rename_keeps_the_source_if_the_destination_does_not_fit() {
+        let store = KVStore::with_capacity_policy(Capacity::Bytes(100), EvictionPolicy::Reject);
+        store.set("a", "value").unwrap();
+
+        assert_eq!(
+            Err(MiniRedisError::StoreFull),
+            store.rename("a", &"b".repeat(200))
+        );
+        assert_eq!(Ok(Some("value".to_string())), store.get("a"));
+    }
+
+    #[test]
+    fn scan_of_empty_store_is_done_immediately() {
+        let store = KVStore::new();
+
+        assert_eq!(Ok((0, Vec::new())), store.scan(0, 10));
+    }
+
+    #[test]
+    fn snapshot_handle_is_independent_of_later_writes() {
+        let store = KVStore::new();
+        store.set("a", "1").unwrap();
+        store.set("b", "2").unwrap();
+
+        let snapshot = store.snapshot_handle().unwrap();
+        store.set("a", "changed").unwrap();
+        store.del("b").unwrap();
+        store.set("c", "3").unwrap();
+
+        let mut entries = snapshot.into_iter().collect::<Vec<(String, Vec<u8>)>>();
+        entries.sort();
+        assert_eq!(
+            vec![
+                ("a".to_string(), b"1".to_vec()),
+                ("b".to_string(), b"2".to_vec())
+            ],
+            entries
+        );
+    }
+
+    #[test]
+    fn snapshot_handle_is_consistent_while_writers_run() {
+        let store = KVStore::new();
+        for i in 0..100 {
+            store.set(&format!("key{}", i), "0").unwrap();
+        }
+        let writer = {
+            let store = store.clone();
+            std::thread::spawn(move || {
+                for round in 1..=50 {
+                    // The keys are updated in order, round after round, so any
+                    // point-in-time view holds values of at most two adjacent rounds.
+                    for i in 0..100 {
+                        store.set(&format!("key{}", i), &round.to_string()).unwrap();
+                    }
+                }
+            })
+        };
+
+        for _ in 0..20 {
+            let snapshot = store.snapshot_handle().unwrap();
+            assert_eq!(100, snapshot.len());
+            let rounds = snapshot
+                .iter()
+                .map(|(_, value)| std::str::from_utf8(value).unwrap().parse::<i32>().unwrap())
+                .collect::<Vec<i32>>();
+            let (min, max) = (rounds.iter().min().unwrap(), rounds.iter().max().unwrap());
+            assert!(max - min <= 1);
+        }
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn load_from_file_returns_saved_entries() {
+        let path = temp_file("round-trip.mrdb");
+        let store = KVStore::new();
+        store.set("key", "value").unwrap();
+        store.set("other_key", "other value").unwrap();
+
+        store.save_to_file(&path).unwrap();
+        let loaded = KVStore::load_from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(Ok(Some("value".to_string())), loaded.get("key"));
+        assert_eq!(Ok(Some("other value".to_string())), loaded.get("other_key"));
+    }
+
+    #[test]
+    fn save_to_file_replaces_existing_snapshot() {
+        let path = temp_file("replace.mrdb");
+        let store = KVStore::new();
+        store.set("key", "old").unwrap();
+        store.save_to_file(&path).unwrap();
+
+        store.set("key", "new").unwrap();
+        store.save_to_file(&path).unwrap();
+        let loaded = KVStore::load_from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(Ok(Some("new".to_string())), loaded.get("key"));
+        assert!(!snapshot_temp_exists(&path));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn load_from_file_returns_compressed_entries() {
+        let path = temp_file("compressed.mrdb");
+        let plain_path = temp_file("plain.mrdb");
+        let store = KVStore::new();
+        for i in 0..1000 {
+            let value = format!("{{\"id\":{},\"name\":\"alice\",\"active\":true}}", i);
+            store.set(&format!("user:{}", i), &value).unwrap();
+        }
+
+        store.save_to_file_compressed(&path).unwrap();
+        store.save_to_file(&plain_path).unwrap();
+        let loaded = KVStore::load_from_file(&path).unwrap();
+        let (size, plain_size) = (
+            std::fs::metadata(&path).unwrap().len(),
+            std::fs::metadata(&plain_path).unwrap().len(),
+        );
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&plain_path).unwrap();
+
+        let (mut saved, mut loaded) = (store.snapshot().unwrap(), loaded.snapshot().unwrap());
+        saved.sort();
+        loaded.sort();
+        assert_eq!(saved, loaded);
+        assert!(size < plain_size / 2);
+    }
+
+    #[test]
+    fn load_from_file_returns_error_if_file_is_missing() {
+        let path = temp_file("missing.mrdb");
+
+        let result = KVStore::load_from_file(&path);
+
+        assert!(matches!(
+            result,
+            Err(MiniRedisError::FileNotReadable { source, .. })
+                if source.kind() == std::io::ErrorKind::NotFound
+        ));
+    }
+
+    #[test]
+    fn load_from_file_returns_error_if_file_is_corrupted() {
+        let path = temp_file("corrupted.mrdb");
+        std::fs::write(&path, b"not a snapshot at all").unwrap();
+
+        let result = KVStore::load_from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(
+            result,
+            Err(MiniRedisError::SnapshotCorrupted { .. })
+        ));
+    }
+
+    #[test]
+    fn subscribe_receives_set_events_for_matching_keys() {
+        let store = KVStore::new();
+        let events = store.subscribe("user:*");
+
+        store.set("user:1", "alice").unwrap();
+
+        assert_eq!(
+            Ok(KeyEvent {
+                key: "user:1".to_string(),
+                kind: KeyEventKind::Set,
+                value: Some("alice".to_string()),
+            }),
+            events.try_recv()
+        );
+    }
+
+    #[test]
+    fn subscribe_receives_del_events_for_existing_keys() {
+        let store = KVStore::new();
+        store.set("user:1", "alice").unwrap();
+        let events = store.subscribe("user:*");
+
+        store.del("user:1").unwrap();
+        store.del("user:2").unwrap();
+
+        assert_eq!(
+            Ok(KeyEvent {
+                key: "user:1".to_string(),
+                kind: KeyEventKind::Del,
+                value: None,
+            }),
+            events.try_recv()
+        );
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn subscribe_ignores_non_matching_keys() {
+        let store = KVStore::new();
+        let events = store.subscribe("user:*");
+
+        store.set("session:1", "token").unwrap();
+
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn dropped_subscriptions_are_removed_on_next_matching_change() {
+        let store = KVStore::new();
+        let events = store.subscribe("*");
+        drop(events);
+
+        store.set("key", "value").unwrap();
+
+        assert_eq!(0, store.subscribers.lock().unwrap().len());
+    }
+
+    #[test]
+    fn subscriber_can_call_back_into_the_store() {
+        let store = Arc::new(KVStore::new());
+        let events = store.subscribe("key");
+
+        let reader = {
+            let store = Arc::clone(&store);
+            std::thread::spawn(move || {
+                let event = events.recv().unwrap();
+                store.get(&event.key).unwrap()
+            })
+        };
+        store.set("key", "value").unwrap();
+
+        assert_eq!(Some("value".to_string()), reader.join().unwrap());
+    }
+
+    #[test]
+    fn memory_usage_is_zero_for_empty_store() {
+        let store = KVStore::new();
+
+        assert_eq!(Ok(0), store.memory_usage());
+    }
+
+    #[test]
+    fn memory_usage_accounts_for_overwrites_and_deletes() {
+        let store = KVStore::new();
+
+        store.set("key", "value").unwrap();
+        store.set("key", "longer value").unwrap();
+        assert_eq!(Ok(3 + 12 + ENTRY_OVERHEAD), store.memory_usage());
+
+        store.del("key").unwrap();
+        assert_eq!(Ok(0), store.memory_usage());
+    }
+
+    #[test]
+    fn memory_usage_matches_recomputation_after_random_workload() {
+        let store = KVStore::new();
+        // A small linear congruential generator keeps the workload reproducible.
+        let mut seed: u64 = 42;
+        let mut next = || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as usize
+        };
+
+        for _ in 0..10_000 {
+            let key = format!("key_{}", next() % 100);
+            if next() % 3 == 0 {
+                store.del(&key).unwrap();
+            } else {
+                store.set(&key, &"x".repeat(next() % 50)).unwrap();
+            }
+        }
+
+        let recomputed: usize = store
+            .get_store()
+            .unwrap()
+            .entries
+            .iter()
+            .map(|(key, entry)| entry_size(key, &entry.value))
+            .sum();
+        assert_eq!(Ok(recomputed), store.memory_usage());
+    }
+
+    #[test]
+    fn memory_usage_of_loaded_store_matches_saved_store() {
+        let path = temp_file("memory.mrdb");
+        let store = KVStore::new();
+        store.set("key", "value").unwrap();
+
+        store.save_to_file(&path).unwrap();
+        let loaded = KVStore::load_from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(store.memory_usage(), loaded.memory_usage());
+    }
+
+    /// Runs a reproducible random workload of sets and deletes against a store,
+    /// checking after every operation that it stays within its capacity.
+    fn assert_workload_respects_capacity(capacity: Capacity, policy: EvictionPolicy) {
+        let store = KVStore::with_capacity_policy(capacity, policy);
+        let mut rng = Rng::with_seed(1234);
+
+        for _ in 0..2_000 {
+            let key = format!("key_{}", rng.below(200));
+            match rng.below(4) {
+                0 => {
+                    store.del(&key).unwrap();
+                }
+                1 => {
+                    store.get(&key).unwrap();
+                }
+                _ => match store.set(&key, &"x".repeat(rng.below(40))) {
+                    Ok(()) | Err(MiniRedisError::StoreFull) => {}
+                    Err(e) => panic!("unexpected error: {}", e),
+                },
+            }
+
+            match capacity {
+                Capacity::Entries(max) => assert!(store.len().unwrap() <= max),
+                Capacity::Bytes(max) => assert!(store.memory_usage().unwrap() <= max),
+            }
+        }
+    }
+
+    #[test]
+    fn every_policy_respects_entry_capacity() {
+        for policy in [
+            EvictionPolicy::Lru,
+            EvictionPolicy::Lfu,
+            EvictionPolicy::Random,
+            EvictionPolicy::Reject,
+        ] {
+            assert_workload_respects_capacity(Capacity::Entries(50), policy);
+        }
+    }
+
+    #[test]
+    fn every_policy_respects_byte_capacity() {
+        for policy in [
+            EvictionPolicy::Lru,
+            EvictionPolicy::Lfu,
+            EvictionPolicy::Random,
+            EvictionPolicy::Reject,
+        ] {
+            assert_workload_respects_capacity(Capacity::Bytes(2_000), policy);
+        }
+    }
+
+    #[test]
+    fn lru_evicts_least_recently_used_key() {
+        let store = KVStore::with_capacity_policy(Capacity::Entries(2), EvictionPolicy::Lru);
+        store.set("a", "1").unwrap();
+        store.set("b", "2").unwrap();
+        store.get("a").unwrap();
+
+        store.set("c", "3").unwrap();
+
+        assert_eq!(Ok(Some("1".to_string())), store.get("a"));
+        assert_eq!(Ok(None), store.get("b"));
+        assert_eq!(Ok(Some("3".to_string())), store.get("c"));
+    }
+
+    #[test]
+    fn unlink_hands_over_the_values_it_deletes() {
+        let store = KVStore::new();
+        store.set("a", "1").unwrap();
+        store.set("b", "2").unwrap();
+        let mut freed = Vec::new();
+
+        let keys = ["a", "missing", "b", "a"].map(String::from);
+        let count = store.unlink(&keys, &mut |value| freed.push(value));
+
+        assert_eq!(Ok(2), count);
+        assert_eq!(vec![b"1".to_vec(), b"2".to_vec()], freed);
+        assert_eq!(Ok(0), store.len());
+    }
+
+    #[test]
+    fn describe_reports_the_metadata_of_an_entry() {
+        let store = KVStore::new();
+        store.set("counter", "-42").unwrap();
+        store.set("padded", "042").unwrap();
+        store.set("huge", "99999999999999999999").unwrap();
+        store
+            .expire("counter", Duration::from_secs(100), None)
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+
+        let counter = store.describe("counter").unwrap().unwrap();
+        assert_eq!("int", counter.encoding);
+        assert_eq!(3, counter.serialized_length);
+        assert_eq!(entry_size("counter", b"-42"), counter.memory);
+        assert!(counter.idle >= Duration::from_millis(20));
+        assert!(
+            counter
+                .ttl
+                .is_some_and(|ttl| ttl <= Duration::from_secs(100))
+        );
+        assert_eq!("raw", store.describe("padded").unwrap().unwrap().encoding);
+        assert_eq!("raw", store.describe("huge").unwrap().unwrap().encoding);
+
+        // Describing a key is not an access, but reading it is.
+        assert!(store.describe("counter").unwrap().unwrap().idle >= Duration::from_millis(20));
+        store.get("counter").unwrap();
+        assert!(store.describe("counter").unwrap().unwrap().idle < Duration::from_millis(20));
+        assert_eq!(Ok(None), store.describe("missing"));
+    }
+
+    #[test]
+    fn touched_keys_survive_eviction_over_untouched_ones() {
+        let store = KVStore::with_capacity_policy(Capacity::Entries(3), EvictionPolicy::Lru);
+        for key in ["a", "b", "c"] {
+            store.set(key, "value").unwrap();
+        }
+
+        assert_eq!(
+            Ok(2),
+            store.touch(&["a".to_string(), "missing".to_string(), "a".to_string()])
+        );
+        store.set("d", "value").unwrap();
+
+        assert_eq!(Ok(true), store.exists("a"));
+        assert_eq!(Ok(false), store.exists("b"));
+        assert_eq!(Ok(true), store.exists("c"));
+        assert_eq!(Ok(false), store.exists("missing"));
+        assert_eq!(0, store.stats().gets);
+    }
+
+    #[test]
+    fn stats_count_evictions() {
+        let store = KVStore::with_capacity_policy(Capacity::Entries(2), EvictionPolicy::Lru);
+        for key in ["a", "b", "c", "d"] {
+            store.set(key, "value").unwrap();
+        }
+
+        assert_eq!(2, store.stats().evictions);
+    }
+
+    #[test]
+    fn lfu_evicts_least_frequently_used_key() {
+        let store = KVStore::with_capacity_policy(Capacity::Entries(2), EvictionPolicy::Lfu);
+        store.set("a", "1").unwrap();
+        store.set("b", "2").unwrap();
+        store.get("b").unwrap();
+        store.get("a").unwrap();
+        store.get("a").unwrap();
+
+        store.set("c", "3").unwrap();
+
+        assert_eq!(Ok(None), store.get("b"));
+        assert_eq!(Ok(Some("1".to_string())), store.get("a"));
+    }
+
+    #[test]
+    fn overwriting_a_key_at_capacity_evicts_nothing() {
+        let store = KVStore::with_capacity_policy(Capacity::Entries(2), EvictionPolicy::Lru);
+        store.set("a", "1").unwrap();
+        store.set("b", "2").unwrap();
+
+        store.set("a", "3").unwrap();
+
+        assert_eq!(Ok(2), store.len());
+        assert_eq!(Ok(Some("2".to_string())), store.get("b"));
+    }
+
+    #[test]
+    fn reject_policy_returns_store_full() {
+        let store = KVStore::with_capacity_policy(Capacity::Entries(1), EvictionPolicy::Reject);
+        store.set("a", "1").unwrap();
+
+        assert_eq!(Err(MiniRedisError::StoreFull), store.set("b", "2"));
+        assert_eq!(Ok(None), store.get("b"));
+    }
+
+    #[test]
+    fn value_larger_than_byte_capacity_is_rejected() {
+        let store = KVStore::with_capacity_policy(Capacity::Bytes(100), EvictionPolicy::Lru);
+        store.set("a", "1").unwrap();
+
+        assert_eq!(
+            Err(MiniRedisError::StoreFull),
+            store.set("big", &"x".repeat(100))
+        );
+        assert_eq!(Ok(Some("1".to_string())), store.get("a"));
+    }
+
+    #[test]
+    fn evicted_keys_are_reported_to_subscribers() {
+        let store = KVStore::with_capacity_policy(Capacity::Entries(1), EvictionPolicy::Lru);
+        store.set("a", "1").unwrap();
+        let events = store.subscribe("*");
+
+        store.set("b", "2").unwrap();
+
+        let evicted = events.try_recv().unwrap();
+        assert_eq!(
+            ("a", KeyEventKind::Evicted),
+            (evicted.key.as_str(), evicted.kind)
+        );
+        assert_eq!(KeyEventKind::Set, events.try_recv().unwrap().kind);
+    }
+
+    #[test]
+    fn default_creates_empty_store() {
+        let store = KVStore::default();
+
+        assert_eq!(Ok(0), store.len());
+    }
+
+    #[test]
+    fn clone_observes_writes_made_through_original() {
+        let store = KVStore::new();
+        let handle = store.clone();
+
+        store.set("key", "value").unwrap();
+        handle.set("other_key", "other_value").unwrap();
+
+        assert_eq!(Ok(Some("value".to_string())), handle.get("key"));
+        assert_eq!(Ok(Some("other_value".to_string())), store.get("other_key"));
+    }
+
+    #[test]
+    fn clone_shares_subscriptions() {
+        let store = KVStore::new();
+        let events = store.subscribe("*");
+
+        store.clone().set("key", "value").unwrap();
+
+        assert_eq!(KeyEventKind::Set, events.try_recv().unwrap().kind);
+    }
+
+    #[test]
+    fn deep_clone_does_not_observe_later_writes() {
+        let store = KVStore::new();
+        store.set("key", "value").unwrap();
+
+        let copy = store.deep_clone().unwrap();
+        store.set("key", "changed").unwrap();
+        store.set("new_key", "new_value").unwrap();
+        copy.del("key").unwrap();
+
+        assert_eq!(Ok(None), copy.get("new_key"));
+        assert_eq!(Ok(Some("changed".to_string())), store.get("key"));
+        assert_eq!(Ok(0), copy.memory_usage());
+    }
+
+    #[test]
+    fn deep_clone_keeps_capacity_policy() {
+        let store = KVStore::with_capacity_policy(Capacity::Entries(1), EvictionPolicy::Reject);
+        store.set("a", "1").unwrap();
+
+        let copy = store.deep_clone().unwrap();
+
+        assert_eq!(Err(MiniRedisError::StoreFull), copy.set("b", "2"));
+    }
+
+    #[test]
+    fn snapshot_returns_all_entries() {
+        let store = KVStore::new();
+        store.set("a", "1").unwrap();
+        store.set("b", "2").unwrap();
+
+        let mut entries = store.snapshot().unwrap();
+        entries.sort();
+
+        assert_eq!(
+            vec![
+                ("a".to_string(), b"1".to_vec()),
+                ("b".to_string(), b"2".to_vec())
+            ],
+            entries
+        );
+    }
+
+    #[test]
+    fn snapshot_is_independent_of_later_writes() {
+        let store = KVStore::new();
+        store.set("key", "value").unwrap();
+
+        let entries = store.snapshot().unwrap();
+        store.set("key", "changed").unwrap();
+
+        assert_eq!(vec![("key".to_string(), b"value".to_vec())], entries);
+    }
+
+    #[test]
+    fn for_each_visits_every_entry_across_chunks() {
+        let store = KVStore::new();
+        let count = CHUNK_SIZE * 2 + 10;
+        for i in 0..count {
+            store.set(&format!("key_{}", i), &i.to_string()).unwrap();
+        }
+
+        let mut visited = Vec::new();
+        store
+            .for_each(|key, value| {
+                assert_eq!(format!("key_{}", String::from_utf8_lossy(value)), key);
+                visited.push(key.to_string());
+            })
+            .unwrap();
+        visited.sort();
+        visited.dedup();
+
+        assert_eq!(count, visited.len());
+    }
+
+    #[test]
+    fn for_each_allows_writing_to_the_store() {
+        let store = KVStore::new();
+        store.set("a", "1").unwrap();
+
+        store
+            .for_each(|key, value| {
+                let value = std::str::from_utf8(value).unwrap();
+                store.set(&format!("copy_of_{}", key), value).unwrap();
+            })
+            .unwrap();
+
+        assert_eq!(Ok(Some("1".to_string())), store.get("copy_of_a"));
+    }
+
+    #[test]
+    fn stats_count_known_sequence_of_operations() {
+        let store = KVStore::new();
+
+        store.set("a", "1").unwrap();
+        store.set("b", "2").unwrap();
+        store.set("a", "3").unwrap();
+        store.get("a").unwrap();
+        store.get("b").unwrap();
+        store.get("missing").unwrap();
+        store.del("b").unwrap();
+        store.del("missing").unwrap();
+        store.get("b").unwrap();
+
+        assert_eq!(
+            StoreStats {
+                gets: 4,
+                sets: 3,
+                dels: 2,
+                hits: 2,
+                misses: 2,
+                evictions: 0,
+                expired: 0,
+            },
+            store.stats()
+        );
+    }
+
+    #[test]
+    fn stats_do_not_count_rejected_sets() {
+        let store = KVStore::with_capacity_policy(Capacity::Entries(1), EvictionPolicy::Reject);
+        store.set("a", "1").unwrap();
+
+        store.set("b", "2").unwrap_err();
+
+        assert_eq!(1, store.stats().sets);
+    }
+
+    #[test]
+    fn reset_stats_sets_counters_to_zero() {
+        let store = KVStore::new();
+        store.set("a", "1").unwrap();
+        store.get("a").unwrap();
+
+        store.reset_stats();
+        store.get("a").unwrap();
+
+        assert_eq!(
+            StoreStats {
+                gets: 1,
+                hits: 1,
+                ..StoreStats::default()
+            },
+            store.stats()
+        );
+    }
+
+    #[test]
+    fn stats_are_never_seen_half_reset() {
+        let store = KVStore::new();
+        store.set("a", "1").unwrap();
+        let done = Arc::new(AtomicBool::new(false));
+        let readers = (0..4)
+            .map(|_| {
+                let (store, done) = (store.clone(), Arc::clone(&done));
+                std::thread::spawn(move || {
+                    while !done.load(Ordering::Relaxed) {
+                        store.get("a").unwrap();
+                        store.get("b").unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for _ in 0..1000 {
+            store.reset_stats();
+            // A get counts itself before its hit or miss, so only a half reset
+            // could leave more hits and misses than gets.
+            let stats = store.stats();
+            assert!(stats.hits + stats.misses <= stats.gets, "{:?}", stats);
+        }
+        done.store(true, Ordering::Relaxed);
+        for reader in readers {
+            reader.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn stats_are_shared_between_clones_but_not_deep_clones() {
+        let store = KVStore::new();
+        let handle = store.clone();
+        let copy = store.deep_clone().unwrap();
+
+        handle.set("a", "1").unwrap();
+
+        assert_eq!(1, store.stats().sets);
+        assert_eq!(0, copy.stats().sets);
+    }
+
+    #[test]
+    fn del_prefix_removes_only_keys_with_prefix() {
+        let store = KVStore::new();
+        for i in 0..50_000 {
+            store.set(&format!("session:{}", i), "s").unwrap();
+            store.set(&format!("user:{}", i), "u").unwrap();
+        }
+
+        let deleted = store.del_prefix("session:").unwrap();
+
+        assert_eq!(50_000, deleted);
+        assert_eq!(Ok(50_000), store.len());
+        assert_eq!(Ok(None), store.get("session:123"));
+        assert_eq!(Ok(Some("u".to_string())), store.get("user:123"));
+    }
+
+    #[test]
+    fn del_prefix_returns_zero_when_nothing_matches() {
+        let store = KVStore::new();
+        store.set("user:1", "u").unwrap();
+
+        assert_eq!(Ok(0), store.del_prefix("session:"));
+        assert_eq!(Ok(1), store.len());
+    }
+
+    #[test]
+    fn del_matching_uses_glob_patterns() {
+        let store = KVStore::new();
+        store.set("user:1:session", "a").unwrap();
+        store.set("user:2:session", "b").unwrap();
+        store.set("user:1:name", "c").unwrap();
+
+        assert_eq!(Ok(2), store.del_matching("user:?:session"));
+        assert_eq!(Ok(Some("c".to_string())), store.get("user:1:name"));
+    }
+
+    #[test]
+    fn del_prefix_notifies_subscribers_and_updates_memory() {
+        let store = KVStore::new();
+        store.set("tmp:a", "1").unwrap();
+        store.set("tmp:b", "2").unwrap();
+        let events = store.subscribe("tmp:*");
+
+        store.del_prefix("tmp:").unwrap();
+
+        assert_eq!(2, events.try_iter().count());
+        assert_eq!(Ok(0), store.memory_usage());
+    }
+
+    #[test]
+    fn with_capacity_creates_empty_store_with_room() {
+        let store = KVStore::with_capacity(100);
+
+        assert_eq!(Ok(0), store.len());
+        assert!(store.capacity().unwrap() >= 100);
+    }
+
+    #[test]
+    fn reserve_grows_capacity_beyond_entries() {
+        let store = KVStore::new();
+        store.set("key", "value").unwrap();
+
+        store.reserve(100).unwrap();
+
+        assert!(store.capacity().unwrap() >= 101);
+    }
+
+    #[test]
+    fn shrink_to_fit_releases_room_after_deletes() {
+        let store = KVStore::new();
+        for i in 0..1_000 {
+            store.set(&format!("key_{}", i), "value").unwrap();
+        }
+        store.del_prefix("key_").unwrap();
+
+        store.shrink_to_fit().unwrap();
+
+        assert!(store.capacity().unwrap() < 1_000);
+    }
+
+    /// Builds owned entries from borrowed keys and values.
+    fn entries(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn extend_sets_every_entry_with_last_write_wins() {
+        let store = KVStore::new();
+        store.set("a", "old").unwrap();
+
+        store
+            .extend(entries(&[("a", "1"), ("b", "2"), ("b", "3")]))
+            .unwrap();
+
+        assert_eq!(Ok(2), store.len());
+        assert_eq!(Ok(Some("1".to_string())), store.get("a"));
+        assert_eq!(Ok(Some("3".to_string())), store.get("b"));
+        assert_eq!(4, store.stats().sets);
+    }
+
+    #[test]
+    fn extend_notifies_subscribers_of_every_entry() {
+        let store = KVStore::new();
+        let events = store.subscribe("*");
+
+        store.extend(entries(&[("a", "1"), ("b", "2")])).unwrap();
+
+        let received = events.try_iter().collect::<Vec<KeyEvent>>();
+        assert_eq!(
+            vec![("a", Some("1")), ("b", Some("2"))],
+            received
+                .iter()
+                .map(|event| (event.key.as_str(), event.value.as_deref()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn extend_evicts_to_stay_within_capacity() {
+        let store = KVStore::with_capacity_policy(Capacity::Entries(2), EvictionPolicy::Lru);
+
+        store
+            .extend(entries(&[("a", "1"), ("b", "2"), ("c", "3")]))
+            .unwrap();
+
+        assert_eq!(Ok(2), store.len());
+        assert_eq!(Ok(None), store.get("a"));
+        assert_eq!(1, store.stats().evictions);
+    }
+
+    #[test]
+    fn extend_keeps_entries_before_one_that_does_not_fit() {
+        let store = KVStore::with_capacity_policy(Capacity::Entries(1), EvictionPolicy::Reject);
+
+        let result = store.extend(entries(&[("a", "1"), ("b", "2")]));
+
+        assert_eq!(Err(MiniRedisError::StoreFull), result);
+        assert_eq!(Ok(Some("1".to_string())), store.get("a"));
+        assert_eq!(Ok(None), store.get("b"));
+        assert_eq!(1, store.stats().sets);
+    }
+
+    #[test]
+    fn from_iter_creates_store_with_entries() {
+        let store = entries(&[("a", "1"), ("b", "2"), ("a", "3")])
+            .into_iter()
+            .collect::<KVStore>();
+
+        assert_eq!(Ok(2), store.len());
+        assert_eq!(Ok(Some("3".to_string())), store.get("a"));
+        assert_eq!(0, store.stats().sets);
+    }
+
+    #[test]
+    fn expired_keys_are_not_returned() {
+        let store = KVStore::new();
+        store
+            .extend_with_ttl([
+                ("gone".to_string(), "1".to_string(), Some(Duration::ZERO)),
+                (
+                    "kept".to_string(),
+                    "2".to_string(),
+                    Some(Duration::from_secs(60)),
+                ),
+            ])
+            .unwrap();
+
+        assert_eq!(Ok(vec!["kept".to_string()]), store.keys());
+        assert_eq!(Ok(false), store.exists("gone"));
+        assert_eq!(Ok(None), store.get("gone"));
+        assert_eq!(Ok(Some("2".to_string())), store.get("kept"));
+        assert_eq!(Ok(1), store.len());
+    }
+
+    #[test]
+    fn expired_keys_are_counted_and_notified_once() {
+        let store = KVStore::new();
+        let events = store.subscribe("*");
+        store
+            .extend_with_ttl([("key".to_string(), "value".to_string(), Some(Duration::ZERO))])
+            .unwrap();
+        events.try_recv().unwrap();
+
+        store.get("key").unwrap();
+        store.get("key").unwrap();
+
+        assert_eq!(KeyEventKind::Expired, events.try_recv().unwrap().kind);
+        assert!(events.try_recv().is_err());
+        assert_eq!(1, store.stats().expired);
+    }
+
+    #[test]
+    fn purge_expired_frees_memory_of_expired_keys() {
+        let store = KVStore::new();
+        let ttl = Some(Duration::ZERO);
+        store
+            .extend_with_ttl([
+                ("a".to_string(), "1".to_string(), ttl),
+                ("b".to_string(), "2".to_string(), ttl),
+                ("c".to_string(), "3".to_string(), None),
+            ])
+            .unwrap();
+
+        assert_eq!(Ok(2), store.purge_expired());
+        assert_eq!(Ok(1), store.len());
+        assert_eq!(Ok(entry_size("c", b"3")), store.memory_usage());
+    }
+
+    #[test]
+    fn set_removes_time_to_live() {
+        let store = KVStore::new();
+        store
+            .extend_with_ttl([(
+                "key".to_string(),
+                "value".to_string(),
+                Some(Duration::from_secs(60)),
+            )])
+            .unwrap();
+
+        store.set("key", "changed").unwrap();
+
+        assert_eq!(Ok(None), store.ttl("key"));
+        assert_eq!(Ok(true), store.exists("key"));
+    }
+
+    #[test]
+    fn load_from_file_keeps_time_to_live() {
+        let path = temp_file("ttl.mrdb");
+        let store = KVStore::new();
+        let ttl = Duration::from_secs(60);
+        store
+            .extend_with_ttl([
+                ("session".to_string(), "a".to_string(), Some(ttl)),
+                ("gone".to_string(), "b".to_string(), Some(Duration::ZERO)),
+                ("user".to_string(), "c".to_string(), None),
+            ])
+            .unwrap();
+
+        store.save_to_file(&path).unwrap();
+        let loaded = KVStore::load_from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let remaining = loaded.ttl("session").unwrap().unwrap();
+        assert!(remaining <= ttl && remaining > ttl - Duration::from_secs(10));
+        assert_eq!(Ok(None), loaded.ttl("user"));
+        assert_eq!(Ok(2), loaded.len());
+    }
+
+    /// A backing store that a store reads and writes through to.
+    #[derive(Default)]
+    struct Database {
+        rows: Mutex<HashMap<String, String>>,
+        loads: std::sync::atomic::AtomicUsize,
+        writes: Mutex<Vec<(String, Option<String>)>>,
+    }
+
+    /// Creates a store that reads and writes through to a database.
+    fn backed_by(database: &Arc<Database>) -> KVStore {
+        let (reads, writes) = (Arc::clone(database), Arc::clone(database));
+        KVStore::builder()
+            .loader(move |key| {
+                reads.loads.fetch_add(1, Ordering::SeqCst);
+                // Slow enough for the other threads to miss the key while it loads.
+                std::thread::sleep(Duration::from_millis(50));
+                reads.rows.lock().unwrap().get(key).cloned()
+            })
+            .writer(move |key, value| {
+                let mut rows = writes.rows.lock().unwrap();
+                match value {
+                    Some(value) => rows.insert(key.to_string(), value.to_string()),
+                    None => rows.remove(key),
+                };
+                let value = value.map(str::to_string);
+                writes.writes.lock().unwrap().push((key.to_string(), value));
+            })
+            .build()
+    }
+
+    #[test]
+    fn get_fills_a_miss_from_the_loader() {
+        let database = Arc::new(Database::default());
+        database
+            .rows
+            .lock()
+            .unwrap()
+            .insert("user".to_string(), "alice".to_string());
+        let store = backed_by(&database);
+
+        assert_eq!(Ok(Some("alice".to_string())), store.get("user"));
+        assert_eq!(Ok(Some("alice".to_string())), store.get("user"));
+        assert_eq!(Ok(None), store.get("missing"));
+
+        assert_eq!(2, database.loads.load(Ordering::SeqCst));
+        assert_eq!(Ok(vec!["user".to_string()]), store.keys());
+        // Loading a key is not a write, so it does not go back to the database.
+        assert!(database.writes.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn get_loads_a_key_once_for_every_thread_missing_it() {
+        let database = Arc::new(Database::default());
+        database
+            .rows
+            .lock()
+            .unwrap()
+            .insert("hot".to_string(), "1".to_string());
+        let store = backed_by(&database);
+
+        let gets = (0..8)
+            .map(|_| {
+                let store = store.clone();
+                std::thread::spawn(move || store.get("hot"))
+            })
+            .collect::<Vec<_>>();
+
+        for get in gets {
+            assert_eq!(Ok(Some("1".to_string())), get.join().unwrap());
+        }
+        assert_eq!(1, database.loads.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn writes_go_through_in_the_order_they_were_made() {
+        let database = Arc::new(Database::default());
+        let store = backed_by(&database);
+
+        let writers = (0..4)
+            .map(|thread| {
+                let store = store.clone();
+                std::thread::spawn(move || {
+                    for i in 0..100 {
+                        store.set("shared", &format!("{}:{}", thread, i)).unwrap();
+                    }
+                    store.del(&format!("never-set-{}", thread)).unwrap();
+                })
+            })
+            .collect::<Vec<_>>();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let writes = database.writes.lock().unwrap();
+        assert_eq!(404, writes.len());
+        // Every thread's own writes arrive in order, and the last one to arrive is what the store kept.
+        for thread in 0..4 {
+            let own = writes
+                .iter()
+                .filter_map(|(key, value)| value.as_deref().filter(|_| key == "shared"))
+                .filter(|value| value.starts_with(&format!("{}:", thread)))
+                .collect::<Vec<_>>();
+            let expected = (0..100)
+                .map(|i| format!("{}:{}", thread, i))
+                .collect::<Vec<_>>();
+            assert_eq!(expected, own);
+        }
+        assert_eq!(
+            store.get("shared").unwrap().as_ref(),
+            database.rows.lock().unwrap().get("shared")
+        );
+        assert!(writes.contains(&("never-set-0".to_string(), None)));
+    }
+
+    #[test]
+    fn panicking_callbacks_leave_the_store_usable() {
+        let store = KVStore::builder()
+            .loader(|key| panic!("the database is down, cannot load {}", key))
+            .writer(|key, _| panic!("the database is down, cannot write {}", key))
+            .build();
+
+        assert_eq!(Ok(None), store.get("a"));
+        assert_eq!(Ok(()), store.set("a", "1"));
+        assert_eq!(Ok(Some("1".to_string())), store.get("a"));
+        assert_eq!(Ok(true), store.del("a"));
+        assert_eq!(Ok(true), store.is_empty());
     }
 }
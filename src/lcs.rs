@@ -0,0 +1,135 @@
+use crate::error::MiniRedisError;
+use std::ops::RangeInclusive;
+
+/// How many cells the table of an `LCS` comparison may have by default,
+/// the length of one value times the length of the other.
+pub(crate) const DEFAULT_LIMIT: usize = 1 << 24;
+
+/// The longest common subsequence of two strings, and where its parts are in each of them.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Lcs {
+    /// The subsequence.
+    pub(crate) text: String,
+    /// The runs of characters that make up the subsequence, from the end of the strings back,
+    /// as the positions of each run in the first and in the second string.
+    pub(crate) matches: Vec<(RangeInclusive<usize>, RangeInclusive<usize>)>,
+}
+
+/// Finds the longest common subsequence of two strings.
+///
+/// Positions count characters. The table the subsequence is found with holds a cell
+/// for every pair of characters, so the strings are refused when it would grow past a limit,
+/// instead of spending minutes and gigabytes on them.
+///
+/// # Arguments
+///
+/// * `a` - The first string.
+/// * `b` - The second string.
+/// * `limit` - The most cells the table may have.
+///
+/// # Returns
+///
+/// The subsequence, and the runs it is made of.
+///
+/// # Errors
+///
+/// If the table would have more cells than the limit,
+/// it will return [`MiniRedisError::ComparisonTooLarge`].
+pub(crate) fn lcs(a: &str, b: &str, limit: usize) -> Result<Lcs, MiniRedisError> {
+    let a = a.chars().collect::<Vec<char>>();
+    let b = b.chars().collect::<Vec<char>>();
+    let cells = a.len().saturating_mul(b.len());
+    if cells > limit {
+        return Err(MiniRedisError::ComparisonTooLarge { cells, limit });
+    }
+
+    // The cell of i and j holds the length of the subsequence of a[..i] and b[..j].
+    let width = b.len() + 1;
+    let mut table = vec![0u32; (a.len() + 1) * width];
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            table[i * width + j] = if a[i - 1] == b[j - 1] {
+                table[(i - 1) * width + j - 1] + 1
+            } else {
+                table[(i - 1) * width + j].max(table[i * width + j - 1])
+            };
+        }
+    }
+
+    let mut text = Vec::new();
+    let mut matches = Vec::new();
+    let mut run: Option<(RangeInclusive<usize>, RangeInclusive<usize>)> = None;
+    let (mut i, mut j) = (a.len(), b.len());
+    while i > 0 && j > 0 {
+        if a[i - 1] == b[j - 1] {
+            text.push(a[i - 1]);
+            run = Some(match run {
+                Some((in_a, in_b)) => (i - 1..=*in_a.end(), j - 1..=*in_b.end()),
+                None => (i - 1..=i - 1, j - 1..=j - 1),
+            });
+            i -= 1;
+            j -= 1;
+        } else {
+            matches.extend(run.take());
+            if table[(i - 1) * width + j] > table[i * width + j - 1] {
+                i -= 1;
+            } else {
+                j -= 1;
+            }
+        }
+    }
+    matches.extend(run);
+
+    Ok(Lcs {
+        text: text.into_iter().rev().collect(),
+        matches,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lcs_finds_the_subsequence_and_its_runs() {
+        assert_eq!(
+            Lcs {
+                text: "mytext".to_string(),
+                matches: vec![(4..=7, 5..=8), (2..=3, 0..=1)],
+            },
+            lcs("ohmytext", "mynewtext", DEFAULT_LIMIT).unwrap()
+        );
+        assert_eq!(
+            Lcs {
+                text: "ö".to_string(),
+                matches: vec![(1..=1, 0..=0)],
+            },
+            lcs("aö", "öb", DEFAULT_LIMIT).unwrap()
+        );
+    }
+
+    #[test]
+    fn lcs_of_strings_without_common_characters_is_empty() {
+        for (a, b) in [("abc", "xyz"), ("", "abc"), ("abc", "")] {
+            assert_eq!(
+                Lcs {
+                    text: String::new(),
+                    matches: vec![],
+                },
+                lcs(a, b, DEFAULT_LIMIT).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn lcs_refuses_strings_past_the_limit() {
+        assert_eq!(
+            Err(MiniRedisError::ComparisonTooLarge {
+                cells: 12,
+                limit: 11
+            }),
+            lcs("abcd", "abc", 11)
+        );
+        assert!(lcs("abcd", "abc", 12).is_ok());
+    }
+}
@@ -1,4 +1,40 @@
+#[cfg(feature = "async")]
+pub mod aio;
+mod backing;
+pub mod benchmark;
+pub mod cache;
+pub mod client;
+pub mod cluster;
+pub mod command;
+#[cfg(feature = "compression")]
+mod compress;
+pub mod connection;
+pub mod dump;
+pub mod engine;
+pub mod error;
+pub mod eviction;
+pub mod file_storage;
+pub mod format;
+mod glob;
+mod history;
+pub mod hook;
+mod interrupt;
 pub mod kv_store;
+mod lcs;
+pub mod log;
+pub mod namespace;
+pub mod output;
+mod pause;
+pub mod pool;
+mod protocol;
+mod replication;
+pub mod response;
+mod rng;
 pub mod server;
-pub mod error;
-pub mod client;
\ No newline at end of file
+pub mod shard;
+mod snapshot;
+mod sort;
+pub mod stats;
+pub mod storage;
+pub mod testing;
+mod tokenizer;
@@ -0,0 +1,162 @@
+/// How important a log message is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Detail for chasing down a problem, like clients going away mid-command.
+    Debug,
+    /// What the server is doing, like clients connecting and disconnecting.
+    Info,
+    /// Something failed, like a connection that could not be accepted.
+    Warning,
+}
+
+impl std::fmt::Display for Level {
+    /// Formats the level as its name in capitals.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - The formatter to write the level to.
+    ///
+    /// # Errors
+    ///
+    /// If the level cannot be formatted, it will return an error.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Level::Debug => write!(f, "DEBUG"),
+            Level::Info => write!(f, "INFO"),
+            Level::Warning => write!(f, "WARNING"),
+        }
+    }
+}
+
+impl std::str::FromStr for Level {
+    type Err = crate::error::MiniRedisError;
+
+    /// Parses a level from its name, in any case.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the level, like `debug`.
+    ///
+    /// # Returns
+    ///
+    /// The level.
+    ///
+    /// # Errors
+    ///
+    /// If the name is not one of a level, it will return [`MiniRedisError::InvalidArguments`].
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "debug" => Ok(Level::Debug),
+            "info" => Ok(Level::Info),
+            "warning" => Ok(Level::Warning),
+            _ => Err(crate::error::MiniRedisError::InvalidArguments {
+                arguments: vec![name.to_string()],
+            }),
+        }
+    }
+}
+
+/// Where the server writes what it is doing.
+///
+/// The server logs through a logger it is built with,
+/// so embedding code can send the messages to its own telemetry.
+///
+/// # Examples
+///
+/// ```rust
+/// use miniredis::log::{Level, Logger};
+/// use miniredis::server::Server;
+///
+/// struct Telemetry;
+///
+/// impl Logger for Telemetry {
+///     fn log(&self, level: Level, message: &str) {
+///         if level >= Level::Info {
+///             println!("miniredis: {}", message);
+///         }
+///     }
+/// }
+///
+/// let server = Server::builder("127.0.0.1:6379").logger(Telemetry).build();
+/// ```
+pub trait Logger: Send + Sync {
+    /// Logs a message.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - How important the message is.
+    /// * `message` - The message, without a line ending.
+    fn log(&self, level: Level, message: &str);
+}
+
+/// A logger that writes the messages of a level and above to stderr.
+///
+/// # Examples
+///
+/// ```rust
+/// use miniredis::log::{Level, Logger, StderrLogger};
+///
+/// let logger = StderrLogger::new(Level::Warning);
+///
+/// logger.log(Level::Info, "Not written");
+/// logger.log(Level::Warning, "Written");
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct StderrLogger {
+    level: Level,
+}
+
+impl StderrLogger {
+    /// Creates a logger that writes to stderr.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The least important level to write.
+    ///
+    /// # Returns
+    ///
+    /// A new logger.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::log::{Level, StderrLogger};
+    ///
+    /// let logger = StderrLogger::new(Level::Debug);
+    /// ```
+    pub fn new(level: Level) -> Self {
+        Self { level }
+    }
+}
+
+impl Logger for StderrLogger {
+    /// Writes the message to stderr, after its level, if it is important enough.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - How important the message is.
+    /// * `message` - The message, without a line ending.
+    fn log(&self, level: Level, message: &str) {
+        if level >= self.level {
+            eprintln!("{} {}", level, message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_parse_from_their_names_in_any_case() {
+        assert_eq!(Ok(Level::Debug), "debug".parse());
+        assert_eq!(Ok(Level::Warning), "WARNING".parse());
+        assert!("verbose".parse::<Level>().is_err());
+        assert_eq!("INFO", Level::Info.to_string());
+    }
+
+    #[test]
+    fn levels_are_ordered_by_importance() {
+        assert!(Level::Debug < Level::Info && Level::Info < Level::Warning);
+    }
+}
@@ -0,0 +1,604 @@
+use crate::error::MiniRedisError;
+use crate::kv_store::KVStore;
+use crate::storage::{ExpireCondition, KeyInfo, SetOptions, Storage};
+use std::time::Duration;
+
+/// A view of the keys of a [`KVStore`] that start with a prefix.
+///
+/// A namespaced store is created with [`KVStore::namespace`]. It shares the data
+/// of the store it was created from, and is as cheap to clone as a [`KVStore`].
+/// Every key passed to it gets the prefix added, and every key it returns has the
+/// prefix removed, so two namespaces never see each other's keys,
+/// while the root store sees all keys with their prefixes.
+///
+/// Namespaces nest: the namespace `b:` of the namespace `a:` holds the keys
+/// starting with `a:b:` in the root store.
+///
+/// # Examples
+///
+/// ```rust
+/// use miniredis::kv_store::KVStore;
+///
+/// let store = KVStore::new();
+/// let users = store.namespace("users:");
+/// let admins = users.namespace("admins:");
+///
+/// admins.set("1", "alice").unwrap();
+///
+/// assert_eq!(Ok(Some("alice".to_string())), users.get("admins:1"));
+/// assert_eq!(Ok(Some("alice".to_string())), store.get("users:admins:1"));
+/// ```
+#[derive(Clone)]
+pub struct NamespacedStore {
+    store: KVStore,
+    prefix: String,
+}
+
+impl NamespacedStore {
+    /// Creates a new namespaced view of a store.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The store holding the data.
+    /// * `prefix` - The prefix of the keys in the namespace.
+    ///
+    /// # Returns
+    ///
+    /// A view of the namespace.
+    pub(crate) fn new(store: KVStore, prefix: String) -> Self {
+        Self { store, prefix }
+    }
+
+    /// Gets the prefix of the namespace in the root store.
+    ///
+    /// # Returns
+    ///
+    /// The full prefix, including the prefixes of any enclosing namespaces.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// let admins = store.namespace("users:").namespace("admins:");
+    ///
+    /// assert_eq!("users:admins:", admins.prefix());
+    /// ```
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Creates a namespace inside this namespace.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The prefix of the keys in the nested namespace, relative to this one.
+    ///
+    /// # Returns
+    ///
+    /// A view of the nested namespace.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// let admins = store.namespace("users:").namespace("admins:");
+    ///
+    /// admins.set("1", "alice").unwrap();
+    ///
+    /// assert_eq!(Ok(Some("alice".to_string())), store.get("users:admins:1"));
+    /// ```
+    pub fn namespace(&self, prefix: &str) -> NamespacedStore {
+        NamespacedStore::new(self.store.clone(), self.key(prefix))
+    }
+
+    /// Gets a value from the namespace.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to get the value for, without the prefix.
+    ///
+    /// # Returns
+    ///
+    /// The value associated with the key, or None if the key is not found.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("sessions:42", "alice").unwrap();
+    ///
+    /// assert_eq!(Ok(Some("alice".to_string())), store.namespace("sessions:").get("42"));
+    /// ```
+    pub fn get(&self, key: &str) -> Result<Option<String>, MiniRedisError> {
+        self.store.get(&self.key(key))
+    }
+
+    /// Sets a value in the namespace.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to set the value for, without the prefix.
+    /// * `value` - The value to set.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, or has no room for the value, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    ///
+    /// store.namespace("sessions:").set("42", "alice").unwrap();
+    ///
+    /// assert_eq!(Ok(Some("alice".to_string())), store.get("sessions:42"));
+    /// ```
+    pub fn set(&self, key: &str, value: &str) -> Result<(), MiniRedisError> {
+        self.store.set(&self.key(key), value)
+    }
+
+    /// Deletes a value from the namespace.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to delete, without the prefix.
+    ///
+    /// # Returns
+    ///
+    /// True if the key existed, false otherwise.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("sessions:42", "alice").unwrap();
+    ///
+    /// store.namespace("sessions:").del("42").unwrap();
+    ///
+    /// assert_eq!(Ok(None), store.get("sessions:42"));
+    /// ```
+    pub fn del(&self, key: &str) -> Result<bool, MiniRedisError> {
+        self.store.del(&self.key(key))
+    }
+
+    /// Deletes every key of the namespace that starts with a prefix.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The prefix of the keys to delete, relative to the namespace.
+    ///
+    /// # Returns
+    ///
+    /// The number of keys that were deleted.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// let sessions = store.namespace("sessions:");
+    /// sessions.set("1", "alice").unwrap();
+    /// sessions.set("2", "bob").unwrap();
+    ///
+    /// assert_eq!(Ok(2), sessions.del_prefix(""));
+    /// ```
+    pub fn del_prefix(&self, prefix: &str) -> Result<usize, MiniRedisError> {
+        self.store.del_prefix(&self.key(prefix))
+    }
+
+    /// Checks whether a key exists in the namespace.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to check, without the prefix.
+    ///
+    /// # Returns
+    ///
+    /// True if the key exists.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("sessions:42", "alice").unwrap();
+    ///
+    /// assert_eq!(Ok(true), store.namespace("sessions:").exists("42"));
+    /// ```
+    pub fn exists(&self, key: &str) -> Result<bool, MiniRedisError> {
+        self.store.exists(&self.key(key))
+    }
+
+    /// Gets the number of keys in the namespace.
+    ///
+    /// This looks at every key of the store.
+    ///
+    /// # Returns
+    ///
+    /// The number of keys starting with the prefix.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("sessions:42", "alice").unwrap();
+    /// store.set("users:1", "bob").unwrap();
+    ///
+    /// assert_eq!(Ok(1), store.namespace("sessions:").len());
+    /// ```
+    pub fn len(&self) -> Result<usize, MiniRedisError> {
+        Ok(self.keys()?.len())
+    }
+
+    /// Checks whether the namespace has no keys.
+    ///
+    /// # Returns
+    ///
+    /// True if no key starts with the prefix.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("users:1", "bob").unwrap();
+    ///
+    /// assert_eq!(Ok(true), store.namespace("sessions:").is_empty());
+    /// ```
+    pub fn is_empty(&self) -> Result<bool, MiniRedisError> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Gets all keys of the namespace.
+    ///
+    /// # Returns
+    ///
+    /// The keys without the prefix, in no particular order.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("sessions:42", "alice").unwrap();
+    ///
+    /// assert_eq!(Ok(vec!["42".to_string()]), store.namespace("sessions:").keys());
+    /// ```
+    pub fn keys(&self) -> Result<Vec<String>, MiniRedisError> {
+        self.store.keys_with_prefix(&self.prefix)
+    }
+
+    /// Gets the next batch of keys of an incremental iteration over the namespace.
+    ///
+    /// This works like [`KVStore::scan`], with the keys returned without the prefix.
+    /// The cursor walks the keys of the whole store, so keys outside the namespace
+    /// count towards `count`, and a batch can be empty while the iteration goes on.
+    ///
+    /// # Arguments
+    ///
+    /// * `cursor` - The cursor returned by the previous call, or 0 to start.
+    /// * `count` - How many keys of the store to look at, at least 1.
+    ///
+    /// # Returns
+    ///
+    /// The cursor to continue with and a batch of keys.
+    ///
+    /// # Errors
+    ///
+    /// If the store is already locked, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    ///
+    /// let store = KVStore::new();
+    /// store.set("sessions:42", "alice").unwrap();
+    ///
+    /// assert_eq!(Ok((0, vec!["42".to_string()])), store.namespace("sessions:").scan(0, 10));
+    /// ```
+    pub fn scan(&self, cursor: u64, count: usize) -> Result<(u64, Vec<String>), MiniRedisError> {
+        let (cursor, keys) = self.store.scan(cursor, count)?;
+        let keys = keys
+            .into_iter()
+            .filter_map(|key| key.strip_prefix(&self.prefix).map(str::to_string))
+            .collect();
+        Ok((cursor, keys))
+    }
+
+    /// Adds the prefix to a key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key without the prefix.
+    ///
+    /// # Returns
+    ///
+    /// The key in the root store.
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+}
+
+impl Storage for NamespacedStore {
+    fn get(&self, key: &str) -> Result<Option<String>, MiniRedisError> {
+        NamespacedStore::get(self, key)
+    }
+
+    fn get_bytes(&self, key: &str) -> Result<Option<Vec<u8>>, MiniRedisError> {
+        self.store.get_bytes(&self.key(key))
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), MiniRedisError> {
+        NamespacedStore::set(self, key, value)
+    }
+
+    fn del(&self, key: &str) -> Result<bool, MiniRedisError> {
+        NamespacedStore::del(self, key)
+    }
+
+    fn rename(&self, source: &str, destination: &str) -> Result<(), MiniRedisError> {
+        self.store.rename(&self.key(source), &self.key(destination))
+    }
+
+    fn rename_if_absent(&self, source: &str, destination: &str) -> Result<bool, MiniRedisError> {
+        self.store
+            .rename_if_absent(&self.key(source), &self.key(destination))
+    }
+
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool, MiniRedisError> {
+        self.store.set_if_absent(&self.key(key), value)
+    }
+
+    fn set_with(
+        &self,
+        key: &str,
+        value: &str,
+        options: &SetOptions,
+    ) -> Result<bool, MiniRedisError> {
+        self.store.set_with(&self.key(key), value, options)
+    }
+
+    fn set_returning_old(&self, key: &str, value: &str) -> Result<Option<Vec<u8>>, MiniRedisError> {
+        self.store.set_returning_old(&self.key(key), value)
+    }
+
+    fn multi_set(&self, pairs: &[(String, String)]) -> Result<(), MiniRedisError> {
+        let pairs = pairs
+            .iter()
+            .map(|(key, value)| (self.key(key), value.clone()))
+            .collect::<Vec<(String, String)>>();
+        self.store.multi_set(&pairs)
+    }
+
+    fn multi_set_if_absent(&self, pairs: &[(String, String)]) -> Result<bool, MiniRedisError> {
+        let pairs = pairs
+            .iter()
+            .map(|(key, value)| (self.key(key), value.clone()))
+            .collect::<Vec<(String, String)>>();
+        self.store.multi_set_if_absent(&pairs)
+    }
+
+    fn increment(&self, key: &str, delta: i64) -> Result<i64, MiniRedisError> {
+        self.store.increment(&self.key(key), delta)
+    }
+
+    fn increment_float(&self, key: &str, delta: f64) -> Result<f64, MiniRedisError> {
+        self.store.increment_float(&self.key(key), delta)
+    }
+
+    fn append(&self, key: &str, suffix: &str) -> Result<usize, MiniRedisError> {
+        self.store.append(&self.key(key), suffix)
+    }
+
+    fn set_range(&self, key: &str, offset: usize, value: &str) -> Result<usize, MiniRedisError> {
+        self.store.set_range(&self.key(key), offset, value)
+    }
+
+    fn set_bit(&self, key: &str, offset: usize, bit: bool) -> Result<bool, MiniRedisError> {
+        self.store.set_bit(&self.key(key), offset, bit)
+    }
+
+    fn get_and_expire(
+        &self,
+        key: &str,
+        ttl: Option<Duration>,
+    ) -> Result<Option<Vec<u8>>, MiniRedisError> {
+        self.store.get_and_expire(&self.key(key), ttl)
+    }
+
+    fn expire(
+        &self,
+        key: &str,
+        ttl: Duration,
+        condition: Option<ExpireCondition>,
+    ) -> Result<bool, MiniRedisError> {
+        self.store.expire(&self.key(key), ttl, condition)
+    }
+
+    fn persist(&self, key: &str) -> Result<bool, MiniRedisError> {
+        self.store.persist(&self.key(key))
+    }
+
+    fn ttl(&self, key: &str) -> Result<Option<Duration>, MiniRedisError> {
+        self.store.ttl(&self.key(key))
+    }
+
+    fn del_prefix(&self, prefix: &str) -> Result<usize, MiniRedisError> {
+        NamespacedStore::del_prefix(self, prefix)
+    }
+
+    fn exists(&self, key: &str) -> Result<bool, MiniRedisError> {
+        NamespacedStore::exists(self, key)
+    }
+
+    fn len(&self) -> Result<usize, MiniRedisError> {
+        NamespacedStore::len(self)
+    }
+
+    fn describe(&self, key: &str) -> Result<Option<KeyInfo>, MiniRedisError> {
+        // The memory estimate is of the key as it is stored, with the prefix.
+        self.store.describe(&self.key(key))
+    }
+
+    fn touch(&self, keys: &[String]) -> Result<usize, MiniRedisError> {
+        let keys = keys
+            .iter()
+            .map(|key| self.key(key))
+            .collect::<Vec<String>>();
+        self.store.touch(&keys)
+    }
+
+    fn unlink(
+        &self,
+        keys: &[String],
+        free: &mut dyn FnMut(Vec<u8>),
+    ) -> Result<usize, MiniRedisError> {
+        let keys = keys
+            .iter()
+            .map(|key| self.key(key))
+            .collect::<Vec<String>>();
+        self.store.unlink(&keys, free)
+    }
+
+    fn keys(&self) -> Result<Vec<String>, MiniRedisError> {
+        NamespacedStore::keys(self)
+    }
+
+    fn random_key(&self) -> Result<Option<String>, MiniRedisError> {
+        self.store.random_key_with_prefix(&self.prefix)
+    }
+
+    fn scan(&self, cursor: u64, count: usize) -> Result<(u64, Vec<String>), MiniRedisError> {
+        NamespacedStore::scan(self, cursor, count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn namespaces_are_isolated_from_each_other() {
+        let store = KVStore::new();
+        let sessions = store.namespace("sessions:");
+        let users = store.namespace("users:");
+
+        sessions.set("1", "session").unwrap();
+        users.set("1", "user").unwrap();
+
+        assert_eq!(Ok(Some("session".to_string())), sessions.get("1"));
+        assert_eq!(Ok(Some("user".to_string())), users.get("1"));
+
+        sessions.del("1").unwrap();
+        assert_eq!(Ok(None), sessions.get("1"));
+        assert_eq!(Ok(Some("user".to_string())), users.get("1"));
+    }
+
+    #[test]
+    fn root_store_sees_prefixed_keys() {
+        let store = KVStore::new();
+        let sessions = store.namespace("sessions:");
+
+        sessions.set("1", "a").unwrap();
+        sessions.set("2", "b").unwrap();
+
+        let mut keys = store.keys().unwrap();
+        keys.sort();
+        assert_eq!(vec!["sessions:1", "sessions:2"], keys);
+    }
+
+    #[test]
+    fn keys_strips_prefix_and_ignores_other_keys() {
+        let store = KVStore::new();
+        store.set("sessions:1", "a").unwrap();
+        store.set("users:1", "b").unwrap();
+        store.set("sessions", "c").unwrap();
+
+        let sessions = store.namespace("sessions:");
+
+        assert_eq!(Ok(vec!["1".to_string()]), sessions.keys());
+        assert_eq!(Ok(1), sessions.len());
+    }
+
+    #[test]
+    fn nested_namespaces_compose() {
+        let store = KVStore::new();
+        let users = store.namespace("users:");
+        let admins = users.namespace("admins:");
+
+        admins.set("1", "alice").unwrap();
+        users.set("2", "bob").unwrap();
+
+        assert_eq!("users:admins:", admins.prefix());
+        assert_eq!(Ok(vec!["1".to_string()]), admins.keys());
+        assert_eq!(Ok(2), users.len());
+        assert_eq!(Ok(Some("alice".to_string())), store.get("users:admins:1"));
+    }
+
+    #[test]
+    fn del_prefix_only_deletes_inside_namespace() {
+        let store = KVStore::new();
+        store.set("a:x1", "1").unwrap();
+        store.set("b:x1", "2").unwrap();
+
+        assert_eq!(Ok(1), store.namespace("a:").del_prefix("x"));
+        assert_eq!(Ok(Some("2".to_string())), store.get("b:x1"));
+    }
+
+    #[test]
+    fn namespace_can_be_used_as_storage() {
+        let store = KVStore::new();
+        let storage: Box<dyn Storage> = Box::new(store.namespace("cache:"));
+
+        storage.set("key", "value").unwrap();
+
+        assert_eq!(Ok(true), storage.exists("key"));
+        assert_eq!(Ok((0, vec!["key".to_string()])), storage.scan(0, 10));
+        assert_eq!(Ok(Some("value".to_string())), store.get("cache:key"));
+    }
+}
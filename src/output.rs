@@ -0,0 +1,369 @@
+use crate::error::MiniRedisError;
+use std::io::{self, Write};
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// How many bytes of replies a client may leave unread before the server drops it,
+/// like the `client-output-buffer-limit` of Redis.
+///
+/// A limit of zero is no limit.
+///
+/// # Examples
+///
+/// ```rust
+/// use miniredis::output::OutputLimit;
+/// use std::time::Duration;
+///
+/// let limit: OutputLimit = "256mb 64mb 60".parse().unwrap();
+///
+/// assert_eq!(256 << 20, limit.hard);
+/// assert_eq!(64 << 20, limit.soft);
+/// assert_eq!(Duration::from_secs(60), limit.soft_duration);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputLimit {
+    /// The client is dropped as soon as it has this many bytes unread.
+    pub hard: usize,
+    /// The client is dropped once it has had this many bytes unread for `soft_duration`.
+    pub soft: usize,
+    /// How long a client may stay over the soft limit.
+    pub soft_duration: Duration,
+}
+
+impl OutputLimit {
+    /// Finds the limit a client is over.
+    ///
+    /// # Arguments
+    ///
+    /// * `pending` - The bytes the client has not read yet.
+    /// * `over_soft_for` - How long the client has been over the soft limit.
+    ///
+    /// # Returns
+    ///
+    /// The limit the client is over, or None if it may stay.
+    fn exceeded(&self, pending: usize, over_soft_for: Duration) -> Option<usize> {
+        if self.hard > 0 && pending > self.hard {
+            Some(self.hard)
+        } else if self.soft > 0 && pending > self.soft && over_soft_for >= self.soft_duration {
+            Some(self.soft)
+        } else {
+            None
+        }
+    }
+}
+
+impl std::str::FromStr for OutputLimit {
+    type Err = MiniRedisError;
+
+    /// Parses a hard limit, a soft limit and the seconds a client may stay over the soft one,
+    /// separated by spaces, like `256mb 64mb 60`.
+    ///
+    /// The limits are in bytes, or in `kb`, `mb` or `gb` of 1024 times the one before.
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - The limits.
+    ///
+    /// # Returns
+    ///
+    /// The limit.
+    ///
+    /// # Errors
+    ///
+    /// If there are not three parts, or one is not a size or a number of seconds,
+    /// it will return [`MiniRedisError::InvalidArguments`] holding the limits.
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let invalid = || MiniRedisError::InvalidArguments {
+            arguments: vec![spec.to_string()],
+        };
+        let parts: Vec<&str> = spec.split_whitespace().collect();
+        let [hard, soft, seconds] = parts[..] else {
+            return Err(invalid());
+        };
+        Ok(OutputLimit {
+            hard: parse_size(hard).ok_or_else(invalid)?,
+            soft: parse_size(soft).ok_or_else(invalid)?,
+            soft_duration: Duration::from_secs(seconds.parse().map_err(|_| invalid())?),
+        })
+    }
+}
+
+/// Parses a number of bytes, with an optional `kb`, `mb` or `gb` unit.
+///
+/// # Arguments
+///
+/// * `size` - The number, in any case.
+///
+/// # Returns
+///
+/// The number of bytes, or None if it is not a size.
+fn parse_size(size: &str) -> Option<usize> {
+    let size = size.to_ascii_lowercase();
+    let (number, shift) = [("kb", 10), ("mb", 20), ("gb", 30)]
+        .into_iter()
+        .find_map(|(unit, shift)| size.strip_suffix(unit).map(|number| (number, shift)))
+        .unwrap_or((&size, 0));
+    number.parse::<usize>().ok()?.checked_mul(1 << shift)
+}
+
+/// The replies of a client on their way to its socket.
+#[derive(Debug, Default)]
+struct Queue {
+    /// The replies not yet handed to the socket.
+    queued: Vec<u8>,
+    /// The bytes being written to the socket.
+    writing: usize,
+    /// When the client went over the soft limit, or None if it is under it.
+    over_soft_since: Option<Instant>,
+    /// Whether the client has no more replies coming.
+    closed: bool,
+    /// Whether writing to the socket failed.
+    broken: bool,
+}
+
+/// Replies that one thread queues up to the limits, and another writes to the socket.
+///
+/// Keeping them apart means a client that does not read its replies only stalls the writer,
+/// while the thread executing its commands sees the unread replies pile up,
+/// and can drop the client once they are over the limit.
+#[derive(Debug)]
+pub(crate) struct OutputBuffer {
+    limit: OutputLimit,
+    queue: Mutex<Queue>,
+    changed: Condvar,
+}
+
+impl OutputBuffer {
+    /// Creates an empty buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - How many bytes the client may leave unread.
+    pub(crate) fn new(limit: OutputLimit) -> Self {
+        OutputBuffer {
+            limit,
+            queue: Mutex::new(Queue::default()),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Queues a reply for the writer.
+    ///
+    /// The bytes still being written count as unread, since a client that does not read
+    /// blocks the writer in the middle of them.
+    ///
+    /// # Arguments
+    ///
+    /// * `reply` - The reply.
+    ///
+    /// # Errors
+    ///
+    /// If the writer has failed, it will return [`MiniRedisError::StreamClosed`],
+    /// and if the client is over a limit, [`MiniRedisError::OutputBufferExceeded`].
+    pub(crate) fn push(&self, reply: &[u8]) -> Result<(), MiniRedisError> {
+        let mut queue = self.queue.lock().unwrap_or_else(PoisonError::into_inner);
+        if queue.broken {
+            return Err(MiniRedisError::StreamClosed);
+        }
+        queue.queued.extend_from_slice(reply);
+        self.changed.notify_one();
+
+        let pending = queue.queued.len() + queue.writing;
+        let over_soft_for = if pending > self.limit.soft {
+            queue
+                .over_soft_since
+                .get_or_insert_with(Instant::now)
+                .elapsed()
+        } else {
+            queue.over_soft_since = None;
+            Duration::ZERO
+        };
+        match self.limit.exceeded(pending, over_soft_for) {
+            Some(limit) => Err(MiniRedisError::OutputBufferExceeded { pending, limit }),
+            None => Ok(()),
+        }
+    }
+
+    /// Tells the writer that no more replies are coming, so it ends once it wrote the queued ones.
+    pub(crate) fn close(&self) {
+        self.queue
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .closed = true;
+        self.changed.notify_one();
+    }
+
+    /// Writes the queued replies until the buffer is closed and empty.
+    ///
+    /// Every reply queued while the last ones were written goes out in one write,
+    /// so a pipelined batch gets its replies together.
+    ///
+    /// # Arguments
+    ///
+    /// * `socket` - Where to write the replies.
+    ///
+    /// # Errors
+    ///
+    /// If writing fails, it will return the error, and the buffer takes no more replies.
+    pub(crate) fn write_to(&self, mut socket: impl Write) -> io::Result<()> {
+        let mut batch = Vec::new();
+        loop {
+            {
+                let mut queue = self.queue.lock().unwrap_or_else(PoisonError::into_inner);
+                queue.writing = 0;
+                // The client read what it was sent, so its time over the soft limit starts over.
+                if queue.queued.len() <= self.limit.soft {
+                    queue.over_soft_since = None;
+                }
+                while queue.queued.is_empty() && !queue.closed {
+                    queue = self
+                        .changed
+                        .wait(queue)
+                        .unwrap_or_else(PoisonError::into_inner);
+                }
+                if queue.queued.is_empty() {
+                    return Ok(());
+                }
+                batch.clear();
+                std::mem::swap(&mut batch, &mut queue.queued);
+                queue.writing = batch.len();
+            }
+            if let Err(e) = socket.write_all(&batch).and_then(|()| socket.flush()) {
+                self.queue
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .broken = true;
+                return Err(e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn limit(hard: usize, soft: usize, soft_duration: Duration) -> OutputLimit {
+        OutputLimit {
+            hard,
+            soft,
+            soft_duration,
+        }
+    }
+
+    #[test]
+    fn from_str_reads_sizes_with_units() {
+        assert_eq!(
+            Ok(limit(1024, 2 << 20, Duration::from_secs(10))),
+            "1KB 2mb 10".parse::<OutputLimit>()
+        );
+        assert_eq!(Ok(OutputLimit::default()), "0 0 0".parse::<OutputLimit>());
+    }
+
+    #[test]
+    fn from_str_rejects_anything_but_three_parts() {
+        for spec in ["", "1 2", "1 2 3 4", "1tb 2 3", "-1 2 3", "1 2 x"] {
+            assert_eq!(
+                Err(MiniRedisError::InvalidArguments {
+                    arguments: vec![spec.to_string()]
+                }),
+                spec.parse::<OutputLimit>(),
+                "{:?}",
+                spec
+            );
+        }
+    }
+
+    #[test]
+    fn push_drops_a_client_over_the_hard_limit_at_once() {
+        let buffer = OutputBuffer::new(limit(8, 0, Duration::ZERO));
+
+        assert_eq!(Ok(()), buffer.push(b"12345678"));
+        assert_eq!(
+            Err(MiniRedisError::OutputBufferExceeded {
+                pending: 9,
+                limit: 8
+            }),
+            buffer.push(b"9")
+        );
+    }
+
+    #[test]
+    fn push_drops_a_client_only_after_staying_over_the_soft_limit() {
+        let buffer = OutputBuffer::new(limit(0, 4, Duration::from_millis(50)));
+
+        assert_eq!(Ok(()), buffer.push(b"12345"));
+        thread::sleep(Duration::from_millis(60));
+
+        assert_eq!(
+            Err(MiniRedisError::OutputBufferExceeded {
+                pending: 6,
+                limit: 4
+            }),
+            buffer.push(b"6")
+        );
+    }
+
+    #[test]
+    fn push_restarts_the_soft_clock_once_the_client_catches_up() {
+        let buffer = Arc::new(OutputBuffer::new(limit(0, 4, Duration::from_millis(50))));
+        buffer.push(b"12345").unwrap();
+        thread::sleep(Duration::from_millis(60));
+
+        buffer.close();
+        let mut written = Vec::new();
+        buffer.write_to(&mut written).unwrap();
+
+        assert_eq!(b"12345".to_vec(), written);
+        assert_eq!(Ok(()), buffer.push(b"12345"));
+    }
+
+    #[test]
+    fn write_to_writes_every_reply_until_closed() {
+        let buffer = Arc::new(OutputBuffer::new(OutputLimit::default()));
+        let writer = {
+            let buffer = Arc::clone(&buffer);
+            thread::spawn(move || {
+                let mut written = Vec::new();
+                buffer.write_to(&mut written).map(|()| written)
+            })
+        };
+
+        buffer.push(b"+OK\r\n").unwrap();
+        buffer.push(b"$1\r\na\r\n").unwrap();
+        buffer.close();
+
+        assert_eq!(
+            b"+OK\r\n$1\r\na\r\n".to_vec(),
+            writer.join().unwrap().unwrap()
+        );
+    }
+
+    /// A socket that never takes a byte.
+    struct Broken;
+
+    impl Write for Broken {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn push_fails_once_the_writer_has_failed() {
+        let buffer = OutputBuffer::new(OutputLimit::default());
+        buffer.push(b"+OK\r\n").unwrap();
+        buffer.close();
+
+        assert_eq!(
+            Some(io::ErrorKind::BrokenPipe),
+            buffer.write_to(Broken).err().map(|e| e.kind())
+        );
+        assert_eq!(Err(MiniRedisError::StreamClosed), buffer.push(b"+OK\r\n"));
+    }
+}
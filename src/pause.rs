@@ -0,0 +1,158 @@
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Which commands a `CLIENT PAUSE` holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum PauseMode {
+    /// Only the commands that change the data.
+    Write,
+    /// Every command.
+    All,
+}
+
+impl std::fmt::Display for PauseMode {
+    /// Formats the mode the way `CLIENT PAUSE` takes it, in lowercase.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - The formatter to write the mode to.
+    ///
+    /// # Errors
+    ///
+    /// If the mode cannot be formatted, it will return an error.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PauseMode::Write => write!(f, "write"),
+            PauseMode::All => write!(f, "all"),
+        }
+    }
+}
+
+/// Holds the commands of clients until a deadline passes, or the pause is lifted.
+///
+/// Waiting clients block on a condition variable, so lifting the pause wakes them
+/// at once, instead of on their next poll.
+#[derive(Debug, Default)]
+pub(crate) struct Pause {
+    /// The deadline of the pause and what it holds, or None if nothing is held.
+    state: Mutex<Option<(Instant, PauseMode)>>,
+    lifted: Condvar,
+}
+
+impl Pause {
+    /// Starts holding commands, or widens the pause already in effect.
+    ///
+    /// A pause that is already in effect keeps the later of the two deadlines,
+    /// and holds every command if either of them does.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - How long to hold commands for.
+    /// * `mode` - Which commands to hold.
+    pub(crate) fn pause(&self, timeout: Duration, mode: PauseMode) {
+        let now = Instant::now();
+        // A timeout past what the clock can count to holds commands for a century instead.
+        let deadline = now
+            .checked_add(timeout)
+            .unwrap_or_else(|| now + Duration::from_secs(100 * 365 * 24 * 60 * 60));
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        *state = Some(match *state {
+            Some((until, held)) => (until.max(deadline), held.max(mode)),
+            None => (deadline, mode),
+        });
+    }
+
+    /// Lifts the pause, letting every held command go on.
+    pub(crate) fn unpause(&self) {
+        *self.state.lock().unwrap_or_else(PoisonError::into_inner) = None;
+        self.lifted.notify_all();
+    }
+
+    /// Gets what the pause holds, and for how long.
+    ///
+    /// # Returns
+    ///
+    /// The commands held and the time left, or None if nothing is held.
+    pub(crate) fn state(&self) -> Option<(PauseMode, Duration)> {
+        let state = *self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state
+            .map(|(until, mode)| (mode, until.saturating_duration_since(Instant::now())))
+            .filter(|(_, left)| !left.is_zero())
+    }
+
+    /// Waits until the pause no longer holds a command.
+    ///
+    /// # Arguments
+    ///
+    /// * `write` - Whether the command changes the data.
+    pub(crate) fn wait(&self, write: bool) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        while let Some((until, mode)) = *state {
+            if mode == PauseMode::Write && !write {
+                break;
+            }
+            let now = Instant::now();
+            if now >= until {
+                *state = None;
+                break;
+            }
+            state = self
+                .lifted
+                .wait_timeout(state, until - now)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    /// Measures how long a pause holds a command.
+    fn held(pause: &Pause, write: bool) -> Duration {
+        let start = Instant::now();
+        pause.wait(write);
+        start.elapsed()
+    }
+
+    #[test]
+    fn write_pauses_hold_only_writes_until_the_deadline() {
+        let pause = Pause::default();
+        pause.pause(Duration::from_millis(50), PauseMode::Write);
+
+        assert!(held(&pause, false) < Duration::from_millis(50));
+        assert!(held(&pause, true) >= Duration::from_millis(40));
+        assert_eq!(None, pause.state());
+    }
+
+    #[test]
+    fn pauses_widen_to_the_later_deadline_and_the_stricter_mode() {
+        let pause = Pause::default();
+        pause.pause(Duration::from_secs(60), PauseMode::Write);
+        pause.pause(Duration::from_millis(1), PauseMode::All);
+
+        let (mode, left) = pause.state().unwrap();
+
+        assert_eq!(PauseMode::All, mode);
+        assert!(left > Duration::from_secs(50));
+    }
+
+    #[test]
+    fn unpausing_wakes_held_commands() {
+        let pause = Arc::new(Pause::default());
+        pause.pause(Duration::from_secs(60), PauseMode::All);
+        let waiting = {
+            let pause = Arc::clone(&pause);
+            thread::spawn(move || held(&pause, false))
+        };
+
+        thread::sleep(Duration::from_millis(20));
+        pause.unpause();
+
+        assert!(waiting.join().unwrap() < Duration::from_secs(60));
+        assert_eq!(None, pause.state());
+    }
+}
@@ -0,0 +1,538 @@
+use crate::connection::Connection;
+use crate::error::MiniRedisError;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// A pool of connections to a server, shared between threads.
+///
+/// Connections are opened lazily, when every open connection is in use,
+/// up to the size of the pool. When all of them are in use, checking one out
+/// waits until another thread returns one. A connection goes back to the pool
+/// when its [`PooledConnection`] is dropped, unless its stream failed,
+/// in which case it is closed and a new one is opened the next time it is needed.
+///
+/// Cloning a pool is cheap and returns another handle to the same connections.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use miniredis::pool::Pool;
+/// use std::thread;
+///
+/// let pool = Pool::new("127.0.0.1:6379", 4);
+///
+/// let workers = (0..8)
+///     .map(|i| {
+///         let pool = pool.clone();
+///         thread::spawn(move || {
+///             let mut connection = pool.get().unwrap();
+///             connection.set(&format!("key:{}", i), "value").unwrap();
+///         })
+///     })
+///     .collect::<Vec<_>>();
+/// for worker in workers {
+///     worker.join().unwrap();
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Pool {
+    shared: Arc<Shared>,
+}
+
+/// The part of a pool that every handle and checked out connection shares.
+struct Shared {
+    address: String,
+    max_size: usize,
+    checkout_timeout: Duration,
+    health_check_after: Option<Duration>,
+    state: Mutex<State>,
+    returned: Condvar,
+}
+
+/// The connections of a pool.
+struct State {
+    /// Connections that are not checked out, with when they were returned.
+    idle: Vec<(Connection, Instant)>,
+    /// How many connections are open, idle or checked out.
+    open: usize,
+}
+
+impl Pool {
+    /// Creates a pool of connections to a server.
+    ///
+    /// Checking a connection out waits at most 30 seconds for one to be returned,
+    /// and idle connections are not checked before they are handed out.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address of the server to connect to.
+    /// * `max_size` - The most connections to open at once.
+    ///
+    /// # Returns
+    ///
+    /// A new pool, without any open connections.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::pool::Pool;
+    ///
+    /// let pool = Pool::new("127.0.0.1:6379", 4);
+    ///
+    /// assert_eq!(0, pool.open_connections());
+    /// ```
+    pub fn new(address: &str, max_size: usize) -> Self {
+        Self::builder(address, max_size).build()
+    }
+
+    /// Creates a builder for a pool with more options than [`Pool::new`].
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address of the server to connect to.
+    /// * `max_size` - The most connections to open at once.
+    ///
+    /// # Returns
+    ///
+    /// A builder with every option at its default.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::pool::Pool;
+    /// use std::time::Duration;
+    ///
+    /// let pool = Pool::builder("127.0.0.1:6379", 4)
+    ///     .checkout_timeout(Duration::from_secs(1))
+    ///     .health_check_after(Duration::from_secs(60))
+    ///     .build();
+    /// ```
+    pub fn builder(address: &str, max_size: usize) -> PoolBuilder {
+        PoolBuilder {
+            address: address.to_string(),
+            max_size,
+            checkout_timeout: Duration::from_secs(30),
+            health_check_after: None,
+        }
+    }
+
+    /// Checks a connection out of the pool.
+    ///
+    /// It hands out an idle connection if there is one, and opens a new one
+    /// if the pool is not full. Otherwise it waits for a connection to be returned.
+    /// An idle connection that fails its health check is closed, and another is tried.
+    ///
+    /// # Returns
+    ///
+    /// A connection, which goes back to the pool when it is dropped.
+    ///
+    /// # Errors
+    ///
+    /// If no connection is returned before the checkout timeout,
+    /// it will return [`MiniRedisError::PoolExhausted`].
+    /// If opening a new connection fails, it will return that error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::pool::Pool;
+    ///
+    /// let pool = Pool::new("127.0.0.1:6379", 4);
+    ///
+    /// let mut connection = pool.get().unwrap();
+    /// connection.set("name", "alice").unwrap();
+    /// ```
+    pub fn get(&self) -> Result<PooledConnection, MiniRedisError> {
+        let deadline = Instant::now() + self.shared.checkout_timeout;
+        let mut state = self.shared.lock();
+
+        loop {
+            if let Some((connection, returned_at)) = state.idle.pop() {
+                drop(state);
+                if let Some(connection) = self.checked(connection, returned_at) {
+                    return Ok(self.lend(connection));
+                }
+                state = self.shared.lock();
+                state.open -= 1;
+                continue;
+            }
+
+            if state.open < self.shared.max_size {
+                state.open += 1;
+                drop(state);
+                return match Connection::connect(&self.shared.address) {
+                    Ok(connection) => Ok(self.lend(connection)),
+                    Err(e) => {
+                        self.shared.close();
+                        Err(e)
+                    }
+                };
+            }
+
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(MiniRedisError::PoolExhausted {
+                    max_size: self.shared.max_size,
+                    timeout: self.shared.checkout_timeout,
+                });
+            }
+            state = self
+                .shared
+                .returned
+                .wait_timeout(state, left)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+
+    /// Gets how many connections are open, whether idle or checked out.
+    ///
+    /// # Returns
+    ///
+    /// The number of open connections, which is never more than the size of the pool.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::pool::Pool;
+    ///
+    /// let pool = Pool::new("127.0.0.1:6379", 4);
+    /// let connection = pool.get().unwrap();
+    ///
+    /// assert_eq!(1, pool.open_connections());
+    /// ```
+    pub fn open_connections(&self) -> usize {
+        self.shared.lock().open
+    }
+
+    /// Gets how many open connections are waiting in the pool to be checked out.
+    ///
+    /// # Returns
+    ///
+    /// The number of idle connections.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::pool::Pool;
+    ///
+    /// let pool = Pool::new("127.0.0.1:6379", 4);
+    /// drop(pool.get().unwrap());
+    ///
+    /// assert_eq!(1, pool.idle_connections());
+    /// ```
+    pub fn idle_connections(&self) -> usize {
+        self.shared.lock().idle.len()
+    }
+
+    /// Runs the health check on an idle connection, if it has been idle long enough.
+    ///
+    /// # Arguments
+    ///
+    /// * `connection` - The idle connection.
+    /// * `returned_at` - When the connection was returned to the pool.
+    ///
+    /// # Returns
+    ///
+    /// The connection if it can be used, or None if it failed the health check.
+    fn checked(&self, mut connection: Connection, returned_at: Instant) -> Option<Connection> {
+        match self.shared.health_check_after {
+            Some(idle) if returned_at.elapsed() >= idle => {
+                connection.ping().ok().map(|()| connection)
+            }
+            _ => Some(connection),
+        }
+    }
+
+    /// Wraps a connection that was checked out.
+    ///
+    /// # Arguments
+    ///
+    /// * `connection` - The connection.
+    ///
+    /// # Returns
+    ///
+    /// A guard that returns the connection to the pool when dropped.
+    fn lend(&self, connection: Connection) -> PooledConnection {
+        PooledConnection {
+            connection: Some(connection),
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl Shared {
+    /// Locks the connections of the pool.
+    ///
+    /// A thread that panicked while holding the lock cannot have left
+    /// the counts half updated, so a poisoned lock is used as it is.
+    ///
+    /// # Returns
+    ///
+    /// The locked connections.
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Counts a connection as closed, making room for a new one.
+    fn close(&self) {
+        self.lock().open -= 1;
+        self.returned.notify_one();
+    }
+}
+
+/// Builds a [`Pool`] with options.
+///
+/// # Examples
+///
+/// ```rust
+/// use miniredis::pool::Pool;
+/// use std::time::Duration;
+///
+/// let pool = Pool::builder("127.0.0.1:6379", 4)
+///     .checkout_timeout(Duration::from_millis(500))
+///     .build();
+/// ```
+pub struct PoolBuilder {
+    address: String,
+    max_size: usize,
+    checkout_timeout: Duration,
+    health_check_after: Option<Duration>,
+}
+
+impl PoolBuilder {
+    /// Sets how long checking out a connection waits for one to be returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - How long to wait when every connection is in use.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::pool::Pool;
+    /// use std::time::Duration;
+    ///
+    /// let builder = Pool::builder("127.0.0.1:6379", 4).checkout_timeout(Duration::from_secs(1));
+    /// ```
+    pub fn checkout_timeout(mut self, timeout: Duration) -> Self {
+        self.checkout_timeout = timeout;
+        self
+    }
+
+    /// Checks idle connections with `PING` before handing them out.
+    ///
+    /// This weeds out connections the server closed while they were idle,
+    /// like after a restart of the server.
+    ///
+    /// # Arguments
+    ///
+    /// * `idle` - How long a connection must have been idle to be checked.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::pool::Pool;
+    /// use std::time::Duration;
+    ///
+    /// let builder = Pool::builder("127.0.0.1:6379", 4).health_check_after(Duration::from_secs(60));
+    /// ```
+    pub fn health_check_after(mut self, idle: Duration) -> Self {
+        self.health_check_after = Some(idle);
+        self
+    }
+
+    /// Builds the pool.
+    ///
+    /// # Returns
+    ///
+    /// A new pool, without any open connections.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::pool::Pool;
+    ///
+    /// let pool = Pool::builder("127.0.0.1:6379", 4).build();
+    /// ```
+    pub fn build(self) -> Pool {
+        Pool {
+            shared: Arc::new(Shared {
+                address: self.address,
+                max_size: self.max_size,
+                checkout_timeout: self.checkout_timeout,
+                health_check_after: self.health_check_after,
+                state: Mutex::new(State {
+                    idle: Vec::new(),
+                    open: 0,
+                }),
+                returned: Condvar::new(),
+            }),
+        }
+    }
+}
+
+/// A connection checked out of a [`Pool`].
+///
+/// It derefs to a [`Connection`], and goes back to the pool when dropped.
+/// If its stream failed, it is closed instead.
+pub struct PooledConnection {
+    connection: Option<Connection>,
+    shared: Arc<Shared>,
+}
+
+impl Deref for PooledConnection {
+    type Target = Connection;
+
+    /// Gets the connection.
+    ///
+    /// # Returns
+    ///
+    /// The connection that was checked out.
+    fn deref(&self) -> &Connection {
+        self.connection
+            .as_ref()
+            .expect("The connection is only taken when dropped")
+    }
+}
+
+impl DerefMut for PooledConnection {
+    /// Gets the connection for sending commands.
+    ///
+    /// # Returns
+    ///
+    /// The connection that was checked out.
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.connection
+            .as_mut()
+            .expect("The connection is only taken when dropped")
+    }
+}
+
+impl Drop for PooledConnection {
+    /// Returns the connection to the pool, or closes it if its stream failed.
+    fn drop(&mut self) {
+        let Some(connection) = self.connection.take() else {
+            return;
+        };
+        if connection.is_broken() {
+            self.shared.close();
+            return;
+        }
+        self.shared.lock().idle.push((connection, Instant::now()));
+        self.shared.returned.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    /// Starts a fake server that answers every line with `OK`,
+    /// but closes the first connection after `first_replies` lines.
+    ///
+    /// Returns the address and a counter of accepted connections.
+    fn fake_server(first_replies: usize) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&accepted);
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                let first = counter.fetch_add(1, Ordering::SeqCst) == 0;
+                thread::spawn(move || answer(stream, first.then_some(first_replies)));
+            }
+        });
+        (address, accepted)
+    }
+
+    /// Answers lines on a stream with `OK`, closing it after `limit` lines if there is one.
+    ///
+    /// `HELLO` is rejected like an older server would, and does not count against the limit.
+    fn answer(mut stream: TcpStream, limit: Option<usize>) {
+        let reader = BufReader::new(stream.try_clone().unwrap());
+        let mut answered = 0;
+        for line in reader.lines() {
+            let Ok(line) = line else {
+                return;
+            };
+            let reply: &[u8] = if line == "HELLO" {
+                b"ERR Invalid command: HELLO.\n"
+            } else if limit.is_some_and(|limit| answered >= limit) {
+                return;
+            } else {
+                answered += 1;
+                b"OK\n"
+            };
+            if stream.write_all(reply).is_err() {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn broken_connection_is_closed_instead_of_returned() {
+        let (address, _) = fake_server(0);
+        let pool = Pool::new(&address, 2);
+
+        let mut connection = pool.get().unwrap();
+        assert_eq!(Err(MiniRedisError::StreamClosed), connection.ping());
+        drop(connection);
+
+        assert_eq!(0, pool.open_connections());
+        assert_eq!(0, pool.idle_connections());
+    }
+
+    #[test]
+    fn healthy_connection_is_returned_to_the_pool() {
+        let (address, _) = fake_server(usize::MAX);
+        let pool = Pool::new(&address, 1);
+
+        pool.get().unwrap().ping().unwrap();
+
+        assert_eq!(1, pool.idle_connections());
+    }
+
+    #[test]
+    fn health_check_replaces_connection_closed_while_idle() {
+        let (address, accepted) = fake_server(1);
+        let pool = Pool::builder(&address, 1)
+            .health_check_after(Duration::ZERO)
+            .build();
+
+        pool.get().unwrap().set("a", "1").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        let mut connection = pool.get().unwrap();
+
+        assert_eq!(Ok(()), connection.ping());
+        assert_eq!(2, accepted.load(Ordering::SeqCst));
+        assert_eq!(1, pool.open_connections());
+    }
+
+    #[test]
+    fn idle_connections_are_not_checked_without_health_check() {
+        let (address, accepted) = fake_server(1);
+        let pool = Pool::new(&address, 1);
+
+        pool.get().unwrap().set("a", "1").unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        assert!(pool.get().unwrap().ping().is_err());
+        assert_eq!(1, accepted.load(Ordering::SeqCst));
+    }
+}
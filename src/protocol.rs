@@ -0,0 +1,1235 @@
+use crate::command::Command;
+use crate::error::MiniRedisError;
+use crate::response::Response;
+use std::io::{self, BufRead, Read};
+
+/// The end of every error message that points to the help of the client.
+const HELP_HINT: &str = ". Run 'miniredis-client --help' for more information.";
+
+/// The first character of the line before the elements of an array reply.
+const ARRAY_HEADER: char = '*';
+
+/// The first character of the line before the fields of a map reply.
+const MAP_HEADER: char = '%';
+
+/// The longest bulk string a RESP frame may hold, so a bad length cannot exhaust memory.
+const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+
+/// How deeply arrays and maps may nest in a RESP reply, so a bad reply cannot overflow the stack.
+const MAX_DEPTH: usize = 128;
+
+/// The first byte of a RESP request, which a line of the inline protocol never starts with.
+pub(crate) const RESP_REQUEST: u8 = b'*';
+
+/// Encodes a command as the line the server reads.
+///
+/// A word with spaces or quotes is quoted, so it stays one argument.
+/// It does not touch a stream, so every transport encodes commands the same way.
+///
+/// # Arguments
+///
+/// * `parts` - The name of the command, followed by its arguments.
+///
+/// # Returns
+///
+/// The line of the command, without a line ending.
+///
+/// # Errors
+///
+/// If there is no command name, it will return [`MiniRedisError::InvalidArguments`].
+pub(crate) fn encode_command(parts: &[&str]) -> Result<String, MiniRedisError> {
+    let (name, args) = parts
+        .split_first()
+        .ok_or(MiniRedisError::InvalidArguments { arguments: vec![] })?;
+    let args = args.iter().map(|arg| arg.to_string()).collect();
+    Ok(Command::new(name, args).to_string())
+}
+
+/// Encodes a response as the reply the server writes.
+///
+/// Most replies are one line. An array is a line like `*2` with its number of elements,
+/// followed by every element, and a map is a line like `%2` with its number of fields,
+/// followed by every field on a line of its own and then its value.
+/// A value that would read as such a line gets a backslash in front, which
+/// [`read_reply`] removes again. The lines are text, so the bytes of a value
+/// that are not valid UTF-8 are written as U+FFFD; only RESP sends them as they are.
+///
+/// # Arguments
+///
+/// * `response` - The response to encode.
+///
+/// # Returns
+///
+/// The lines of the reply, joined by line endings, without a trailing one.
+pub(crate) fn encode_reply(response: &Response) -> String {
+    match response {
+        Response::Simple(value) | Response::Bulk(value) => escape(value),
+        Response::BulkBytes(value) => escape(&String::from_utf8_lossy(value)),
+        Response::Integer(n) => n.to_string(),
+        Response::Nil => "nil".to_string(),
+        Response::Error(e) => format!("ERR {}", error_message(e)),
+        Response::Array(items) => std::iter::once(format!("{}{}", ARRAY_HEADER, items.len()))
+            .chain(items.iter().map(encode_reply))
+            .collect::<Vec<String>>()
+            .join("\n"),
+        Response::Map(fields) => std::iter::once(format!("{}{}", MAP_HEADER, fields.len()))
+            .chain(
+                fields
+                    .iter()
+                    .flat_map(|(field, value)| [escape(field), encode_reply(value)]),
+            )
+            .collect::<Vec<String>>()
+            .join("\n"),
+    }
+}
+
+/// Reads one complete reply, however many lines it spans.
+///
+/// Lines are read until the reply is complete, so a reply is never cut short
+/// and its last lines are never mistaken for the reply to the next command.
+///
+/// # Arguments
+///
+/// * `next_line` - Reads the next line of the reply, without its line ending.
+///
+/// # Returns
+///
+/// The response, with error replies as [`Response::Error`]
+/// holding a [`MiniRedisError::ServerError`].
+///
+/// # Errors
+///
+/// If a line cannot be read, it will return the error of `next_line`.
+pub(crate) fn read_reply(
+    next_line: &mut dyn FnMut() -> Result<String, MiniRedisError>,
+) -> Result<Response, MiniRedisError> {
+    let line = next_line()?;
+    match header(&line) {
+        Some((ARRAY_HEADER, len)) => Ok(Response::Array(
+            (0..len)
+                .map(|_| read_reply(next_line))
+                .collect::<Result<Vec<Response>, MiniRedisError>>()?,
+        )),
+        Some((_, len)) => Ok(Response::Map(
+            (0..len)
+                .map(|_| Ok((unescape(&next_line()?).to_string(), read_reply(next_line)?)))
+                .collect::<Result<Vec<(String, Response)>, MiniRedisError>>()?,
+        )),
+        None => Ok(Response::from_line(&line)),
+    }
+}
+
+/// Removes the backslash [`encode_reply`] puts in front of values that read as a header.
+///
+/// # Arguments
+///
+/// * `line` - A line of a reply.
+///
+/// # Returns
+///
+/// The value the line holds.
+pub(crate) fn unescape(line: &str) -> &str {
+    match line.strip_prefix('\\') {
+        Some(rest) if header(rest.trim_start_matches('\\')).is_some() => rest,
+        _ => line,
+    }
+}
+
+/// Decodes a reply the client ran a command for.
+///
+/// It does not touch a stream, so every transport decodes replies the same way.
+///
+/// # Arguments
+///
+/// * `reply` - The reply, as [`read_reply`] read it.
+///
+/// # Returns
+///
+/// The response, which is never [`Response::Error`].
+///
+/// # Errors
+///
+/// If the reply is an error, it will return the error the server ran into,
+/// or [`MiniRedisError::ServerError`] if the error is not one the client knows.
+pub(crate) fn decode_reply(reply: Response) -> Result<Response, MiniRedisError> {
+    match reply {
+        Response::Error(MiniRedisError::ServerError { message }) => Err(error_from_reply(&message)),
+        response => Ok(response),
+    }
+}
+
+/// Gets the message of an error reply, as it follows `ERR ` in a reply.
+///
+/// An error with a code other than `ERR` has its code in front,
+/// like `OOM The key value store is full.`, the way a RESP reply starts with it.
+///
+/// # Arguments
+///
+/// * `error` - The error to reply with.
+///
+/// # Returns
+///
+/// The message of the reply.
+pub(crate) fn error_message(error: &MiniRedisError) -> String {
+    match error.code() {
+        "ERR" => error.to_string(),
+        code => format!("{} {}", code, error),
+    }
+}
+
+/// Turns the message of an error reply back into the error the server ran into.
+///
+/// # Arguments
+///
+/// * `message` - The message of the reply, after `ERR `.
+///
+/// # Returns
+///
+/// The error the code or the message describes, or [`MiniRedisError::ServerError`]
+/// holding the message if it is not an error the client knows.
+fn error_from_reply(message: &str) -> MiniRedisError {
+    if let Some((code, rest)) = message.split_once(' ') {
+        match code {
+            "OOM" => return MiniRedisError::StoreFull,
+            "BUSY" => return MiniRedisError::StoreLocked,
+            "WRONGTYPE" => return MiniRedisError::WrongType,
+            "NOAUTH" => {
+                return MiniRedisError::AuthenticationFailed {
+                    message: rest.to_string(),
+                };
+            }
+            "MOVED" => {
+                if let Some((slot, address)) = rest
+                    .split_once(' ')
+                    .and_then(|(slot, address)| Some((slot.parse().ok()?, address)))
+                {
+                    return MiniRedisError::Moved {
+                        slot,
+                        address: address.to_string(),
+                    };
+                }
+            }
+            _ => {}
+        }
+    }
+    if message == MiniRedisError::StoreFull.to_string() {
+        return MiniRedisError::StoreFull;
+    }
+    if message == MiniRedisError::StoreLocked.to_string() {
+        return MiniRedisError::StoreLocked;
+    }
+    if message == MiniRedisError::ExpiryNotSupported.to_string() {
+        return MiniRedisError::ExpiryNotSupported;
+    }
+    if let Some(command) = message
+        .strip_prefix("wrong number of arguments for '")
+        .and_then(|rest| rest.strip_suffix("' command"))
+    {
+        return MiniRedisError::WrongArity {
+            command: command.to_uppercase(),
+        };
+    }
+    if let Some(command) = message
+        .strip_prefix("invalid expire time in '")
+        .and_then(|rest| rest.strip_suffix("' command"))
+    {
+        return MiniRedisError::InvalidExpireTime {
+            command: command.to_uppercase(),
+        };
+    }
+    if let Some(options) = message.strip_suffix(" options at the same time are not compatible") {
+        return MiniRedisError::IncompatibleOptions {
+            options: options.to_string(),
+        };
+    }
+    if message == MiniRedisError::SyntaxError.to_string() {
+        return MiniRedisError::SyntaxError;
+    }
+    if message == MiniRedisError::NotAnInteger.to_string() {
+        return MiniRedisError::NotAnInteger;
+    }
+    if message == MiniRedisError::IncrementOverflow.to_string() {
+        return MiniRedisError::IncrementOverflow;
+    }
+    if message == MiniRedisError::NotAFloat.to_string() {
+        return MiniRedisError::NotAFloat;
+    }
+    if message == MiniRedisError::IncrementNotFinite.to_string() {
+        return MiniRedisError::IncrementNotFinite;
+    }
+    if message == MiniRedisError::NotADouble.to_string() {
+        return MiniRedisError::NotADouble;
+    }
+    if message == MiniRedisError::ValueTooLarge.to_string() {
+        return MiniRedisError::ValueTooLarge;
+    }
+    if message == MiniRedisError::NoSuchKey.to_string() {
+        return MiniRedisError::NoSuchKey;
+    }
+    if let Some(command) = message
+        .strip_prefix("Invalid command: ")
+        .and_then(|rest| rest.strip_suffix(HELP_HINT))
+    {
+        return MiniRedisError::InvalidCommand {
+            command: command.to_string(),
+        };
+    }
+    if let Some(arguments) = message
+        .strip_prefix("Invalid arguments: ")
+        .and_then(|rest| rest.strip_suffix(HELP_HINT))
+        .and_then(parse_arguments)
+    {
+        return MiniRedisError::InvalidArguments { arguments };
+    }
+    MiniRedisError::ServerError {
+        message: message.to_string(),
+    }
+}
+
+/// Encodes a command as a RESP array of bulk strings.
+///
+/// Every word is sent with its length, so words hold spaces,
+/// quotes, and line endings as they are, without any quoting.
+///
+/// # Arguments
+///
+/// * `parts` - The name of the command, followed by its arguments.
+///
+/// # Returns
+///
+/// The frame of the command, ending with a line ending.
+///
+/// # Errors
+///
+/// If there is no command name, it will return [`MiniRedisError::InvalidArguments`].
+pub(crate) fn encode_resp_command<S: AsRef<str>>(parts: &[S]) -> Result<String, MiniRedisError> {
+    if parts.is_empty() {
+        return Err(MiniRedisError::InvalidArguments { arguments: vec![] });
+    }
+    let mut frame = format!("*{}\r\n", parts.len());
+    for part in parts {
+        let part = part.as_ref();
+        frame.push_str(&format!("${}\r\n{}\r\n", part.len(), part));
+    }
+    Ok(frame)
+}
+
+/// Encodes a response as a RESP reply.
+///
+/// Nil is a null bulk string, and maps use the `%` type of RESP3.
+/// Bulk strings are sent as the bytes they hold, whether or not they are valid UTF-8.
+///
+/// # Arguments
+///
+/// * `response` - The response to encode.
+///
+/// # Returns
+///
+/// The frame of the reply, ending with a line ending.
+pub(crate) fn encode_resp_reply(response: &Response) -> Vec<u8> {
+    match response {
+        Response::Simple(status) => format!("+{}\r\n", status).into_bytes(),
+        Response::Bulk(value) => resp_bulk(value.as_bytes()),
+        Response::BulkBytes(value) => resp_bulk(value),
+        Response::Integer(n) => format!(":{}\r\n", n).into_bytes(),
+        Response::Nil => b"$-1\r\n".to_vec(),
+        Response::Error(e) => format!("-{} {}\r\n", e.code(), e).into_bytes(),
+        Response::Array(items) => {
+            let items = items.iter().flat_map(encode_resp_reply);
+            format!("*{}\r\n", response_len(response))
+                .into_bytes()
+                .into_iter()
+                .chain(items)
+                .collect()
+        }
+        Response::Map(fields) => {
+            let fields = fields
+                .iter()
+                .flat_map(|(field, value)| [resp_bulk(field.as_bytes()), encode_resp_reply(value)])
+                .flatten();
+            format!("%{}\r\n", response_len(response))
+                .into_bytes()
+                .into_iter()
+                .chain(fields)
+                .collect()
+        }
+    }
+}
+
+/// Reads one RESP frame, however many lines it spans.
+///
+/// # Arguments
+///
+/// * `reader` - The reader to read the frame from.
+///
+/// # Returns
+///
+/// The response the frame holds, with error replies as [`Response::Error`]
+/// holding a [`MiniRedisError::ServerError`].
+///
+/// # Errors
+///
+/// If the reader fails, it will return its error. If the frame is not valid RESP,
+/// or nests deeper than [`MAX_DEPTH`], it will return an error of kind
+/// [`io::ErrorKind::InvalidData`].
+pub(crate) fn read_resp<R: BufRead>(reader: &mut R) -> io::Result<Response> {
+    read_resp_nested(reader, 0)
+}
+
+/// Reads one RESP frame that is nested in arrays or maps.
+///
+/// # Arguments
+///
+/// * `reader` - The reader to read the frame from.
+/// * `depth` - How many arrays and maps the frame is nested in.
+///
+/// # Returns
+///
+/// The response the frame holds.
+///
+/// # Errors
+///
+/// If the reader fails, it will return its error. If the frame is not valid RESP,
+/// or nests too deep, it will return an error of kind [`io::ErrorKind::InvalidData`].
+fn read_resp_nested<R: BufRead>(reader: &mut R, depth: usize) -> io::Result<Response> {
+    let line = read_resp_line(reader)?;
+    let mut chars = line.chars();
+    let kind = chars.next().ok_or_else(|| invalid_data("empty line"))?;
+    let rest = chars.as_str();
+
+    match kind {
+        '+' => Ok(Response::Simple(rest.to_string())),
+        '-' => Ok(Response::Error(MiniRedisError::ServerError {
+            message: rest.strip_prefix("ERR ").unwrap_or(rest).to_string(),
+        })),
+        ':' => rest
+            .parse()
+            .map(Response::Integer)
+            .map_err(|_| invalid_data("invalid integer")),
+        '_' => Ok(Response::Nil),
+        '$' => match resp_len(rest)? {
+            None => Ok(Response::Nil),
+            Some(len) => read_resp_bulk(reader, len).map(Response::bulk),
+        },
+        '*' | '%' if depth >= MAX_DEPTH => Err(invalid_data("too deeply nested")),
+        '*' => match resp_len(rest)? {
+            None => Ok(Response::Nil),
+            Some(len) => (0..len)
+                .map(|_| read_resp_nested(reader, depth + 1))
+                .collect::<io::Result<Vec<Response>>>()
+                .map(Response::Array),
+        },
+        '%' => {
+            let len = resp_len(rest)?.ok_or_else(|| invalid_data("null map"))?;
+            (0..len)
+                .map(|_| {
+                    Ok((
+                        resp_string(read_resp_nested(reader, depth + 1)?)?,
+                        read_resp_nested(reader, depth + 1)?,
+                    ))
+                })
+                .collect::<io::Result<Vec<(String, Response)>>>()
+                .map(Response::Map)
+        }
+        _ => Err(invalid_data("unknown type")),
+    }
+}
+
+/// Reads a command sent as a RESP array of bulk strings.
+///
+/// A command is never nested, so an array or map inside it is rejected
+/// as soon as its header is read.
+///
+/// # Arguments
+///
+/// * `reader` - The reader to read the command from.
+///
+/// # Returns
+///
+/// The command, or None if the array is empty.
+///
+/// # Errors
+///
+/// If the reader fails, it will return its error. If the frame is not valid RESP,
+/// or not an array of bulk strings, it will return an error of kind [`io::ErrorKind::InvalidData`].
+pub(crate) fn read_resp_command<R: BufRead>(reader: &mut R) -> io::Result<Option<Command>> {
+    let Some(len) = read_resp_line(reader)?
+        .strip_prefix(ARRAY_HEADER)
+        .map(resp_len)
+        .transpose()?
+        .flatten()
+    else {
+        return Err(invalid_data("a command must be an array"));
+    };
+    let mut words = Vec::new();
+    for _ in 0..len {
+        let Some(len) = read_resp_line(reader)?
+            .strip_prefix('$')
+            .map(resp_len)
+            .transpose()?
+            .flatten()
+        else {
+            return Err(invalid_data("a command must be an array of bulk strings"));
+        };
+        let word = read_resp_bulk(reader, len)?;
+        words.push(String::from_utf8(word).map_err(|_| invalid_data("bulk string is not UTF-8"))?);
+    }
+    let mut words = words.into_iter();
+    Ok(words
+        .next()
+        .map(|name| Command::new(&name, words.collect())))
+}
+
+/// Finds how long the first RESP frame in a buffer is, without reading past the buffer.
+///
+/// This lets a reader that cannot block, like the async client, wait until a whole frame
+/// has arrived before handing it to [`read_resp`], so the frame is parsed all at once.
+/// A frame that is not valid RESP, or nests deeper than [`MAX_DEPTH`], is measured
+/// up to the line that shows it, so [`read_resp`] reports the error instead of waiting for more.
+///
+/// # Arguments
+///
+/// * `buffer` - The bytes read so far.
+///
+/// # Returns
+///
+/// The length of the first frame, or None if the buffer ends before it does.
+#[cfg(any(feature = "async", test))]
+pub(crate) fn resp_frame_len(buffer: &[u8]) -> Option<usize> {
+    let mut pos = 0;
+    let mut remaining = vec![1usize];
+    loop {
+        while remaining.last() == Some(&0) {
+            remaining.pop();
+        }
+        let Some(count) = remaining.last_mut() else {
+            return Some(pos);
+        };
+        *count -= 1;
+        let line;
+        (line, pos) = frame_line(buffer, pos)?;
+        let len = || line.get(1..).and_then(|len| resp_len(len).ok());
+        match line.chars().next() {
+            Some('$') => match len() {
+                Some(None) => {}
+                Some(Some(len)) if len <= MAX_BULK_LEN => {
+                    pos += len + 2;
+                    if pos > buffer.len() {
+                        return None;
+                    }
+                }
+                _ => return Some(pos),
+            },
+            Some('*' | '%') if remaining.len() > MAX_DEPTH => return Some(pos),
+            Some(kind @ ('*' | '%')) => match len() {
+                Some(None) => {}
+                Some(Some(len)) if kind == '%' => remaining.push(len.saturating_mul(2)),
+                Some(Some(len)) => remaining.push(len),
+                None => return Some(pos),
+            },
+            Some('+' | '-' | ':' | '_') => {}
+            _ => return Some(pos),
+        }
+    }
+}
+
+/// Finds how long the first command in a buffer is, if it is a RESP array of bulk strings,
+/// without reading past the buffer.
+///
+/// This is [`resp_frame_len`] for [`read_resp_command`]: a line that cannot be part
+/// of a command ends the measure there, so the command is rejected as soon as the line arrives.
+///
+/// # Arguments
+///
+/// * `buffer` - The bytes read so far.
+///
+/// # Returns
+///
+/// The length of the first command, or None if the buffer ends before it does.
+#[cfg(any(feature = "async", test))]
+pub(crate) fn resp_command_len(buffer: &[u8]) -> Option<usize> {
+    let (header, mut pos) = frame_line(buffer, 0)?;
+    let Some(count) = header
+        .strip_prefix(ARRAY_HEADER)
+        .and_then(|len| resp_len(len).ok().flatten())
+    else {
+        return Some(pos);
+    };
+    for _ in 0..count {
+        let line;
+        (line, pos) = frame_line(buffer, pos)?;
+        match line
+            .strip_prefix('$')
+            .and_then(|len| resp_len(len).ok().flatten())
+        {
+            Some(len) if len <= MAX_BULK_LEN => {
+                pos += len + 2;
+                if pos > buffer.len() {
+                    return None;
+                }
+            }
+            _ => return Some(pos),
+        }
+    }
+    Some(pos)
+}
+
+/// Finds the line of a frame that starts at a position in a buffer.
+///
+/// # Arguments
+///
+/// * `buffer` - The bytes read so far.
+/// * `pos` - Where the line starts.
+///
+/// # Returns
+///
+/// The line without its line ending, and where the next line starts,
+/// or None if the buffer ends before the line does.
+#[cfg(any(feature = "async", test))]
+fn frame_line(buffer: &[u8], pos: usize) -> Option<(String, usize)> {
+    let end = pos + buffer[pos..].iter().position(|&b| b == b'\n')?;
+    let line = String::from_utf8_lossy(&buffer[pos..end]);
+    Some((line.trim_end_matches('\r').to_string(), end + 1))
+}
+
+/// Reads the body of a bulk string, after the line with its length.
+///
+/// The body is read into a buffer that grows as it arrives, so a length
+/// that is never followed by the data does not take up memory.
+///
+/// # Arguments
+///
+/// * `reader` - The reader to read the body from.
+/// * `len` - The length of the bulk string.
+///
+/// # Returns
+///
+/// The bytes of the bulk string, without its line ending.
+///
+/// # Errors
+///
+/// If the reader fails or ends before the body, it will return an error.
+/// If the length is over [`MAX_BULK_LEN`], or the body lacks its line ending,
+/// it will return an error of kind [`io::ErrorKind::InvalidData`].
+fn read_resp_bulk<R: BufRead>(reader: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    if len > MAX_BULK_LEN {
+        return Err(invalid_data("bulk string too long"));
+    }
+    let mut value = Vec::new();
+    reader.take(len as u64 + 2).read_to_end(&mut value)?;
+    if value.len() < len + 2 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    if !value.ends_with(b"\r\n") {
+        return Err(invalid_data("bulk string without a line ending"));
+    }
+    value.truncate(len);
+    Ok(value)
+}
+
+/// Encodes a value as a RESP bulk string.
+///
+/// # Arguments
+///
+/// * `value` - The value to encode.
+///
+/// # Returns
+///
+/// The bulk string, ending with a line ending.
+fn resp_bulk(value: &[u8]) -> Vec<u8> {
+    [format!("${}\r\n", value.len()).as_bytes(), value, b"\r\n"].concat()
+}
+
+/// Counts the elements of an array or the fields of a map.
+///
+/// # Arguments
+///
+/// * `response` - The array or map.
+///
+/// # Returns
+///
+/// The number of elements or fields, or 0 for any other response.
+fn response_len(response: &Response) -> usize {
+    match response {
+        Response::Array(items) => items.len(),
+        Response::Map(fields) => fields.len(),
+        _ => 0,
+    }
+}
+
+/// Reads a line of a RESP frame.
+///
+/// # Arguments
+///
+/// * `reader` - The reader to read the line from.
+///
+/// # Returns
+///
+/// The line, without its line ending.
+///
+/// # Errors
+///
+/// If the reader fails or ends before the line, it will return an error.
+fn read_resp_line<R: BufRead>(reader: &mut R) -> io::Result<String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Parses the length of a bulk string or an array.
+///
+/// # Arguments
+///
+/// * `len` - The length, after the type of the frame.
+///
+/// # Returns
+///
+/// The length, or None for the null length `-1`.
+///
+/// # Errors
+///
+/// If the length is not a number of at least -1, it will return an invalid data error.
+fn resp_len(len: &str) -> io::Result<Option<usize>> {
+    match len.parse::<i64>() {
+        Ok(-1) => Ok(None),
+        Ok(len) if len >= 0 => Ok(Some(len as usize)),
+        _ => Err(invalid_data("invalid length")),
+    }
+}
+
+/// Takes the string out of a bulk string or simple string frame.
+///
+/// # Arguments
+///
+/// * `response` - The response a frame holds.
+///
+/// # Returns
+///
+/// The string.
+///
+/// # Errors
+///
+/// If the frame holds anything else, it will return an invalid data error.
+fn resp_string(response: Response) -> io::Result<String> {
+    match response {
+        Response::Bulk(value) | Response::Simple(value) => Ok(value),
+        _ => Err(invalid_data("expected a string")),
+    }
+}
+
+/// Builds the error for a frame that is not valid RESP.
+///
+/// # Arguments
+///
+/// * `reason` - What is wrong with the frame.
+///
+/// # Returns
+///
+/// An error of kind [`io::ErrorKind::InvalidData`].
+fn invalid_data(reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Protocol error: {}", reason),
+    )
+}
+
+/// Puts a backslash in front of a value that would read as a header, or as such an escaped value.
+///
+/// # Arguments
+///
+/// * `value` - The value to write on a line of a reply.
+///
+/// # Returns
+///
+/// The line holding the value.
+fn escape(value: &str) -> String {
+    match header(value.trim_start_matches('\\')) {
+        Some(_) => format!("\\{}", value),
+        None => value.to_string(),
+    }
+}
+
+/// Parses the line before the elements of an array or the fields of a map, like `*2`.
+///
+/// # Arguments
+///
+/// * `line` - A line of a reply.
+///
+/// # Returns
+///
+/// The first character of the line and the number after it,
+/// or None if the line is not a header.
+fn header(line: &str) -> Option<(char, usize)> {
+    let kind = line
+        .chars()
+        .next()
+        .filter(|c| [ARRAY_HEADER, MAP_HEADER].contains(c))?;
+    let len = &line[1..];
+    if len.is_empty() || !len.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((kind, len.parse().ok()?))
+}
+
+/// Parses a list of arguments the way an error message shows them, like `["a", "b"]`.
+///
+/// # Arguments
+///
+/// * `list` - The list of arguments.
+///
+/// # Returns
+///
+/// The arguments, or None if the list holds quotes or escapes it cannot tell apart.
+fn parse_arguments(list: &str) -> Option<Vec<String>> {
+    let list = list.strip_prefix('[')?.strip_suffix(']')?;
+    if list.is_empty() {
+        return Some(Vec::new());
+    }
+    let list = list.strip_prefix('"')?.strip_suffix('"')?;
+    let arguments = list
+        .split("\", \"")
+        .map(str::to_string)
+        .collect::<Vec<String>>();
+    if arguments
+        .iter()
+        .any(|argument| argument.contains(['"', '\\']))
+    {
+        return None;
+    }
+    Some(arguments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_command_quotes_words_with_spaces() {
+        assert_eq!(
+            Ok("SET msg \"hello world\"".to_string()),
+            encode_command(&["set", "msg", "hello world"])
+        );
+    }
+
+    #[test]
+    fn encode_command_needs_a_name() {
+        assert_eq!(
+            Err(MiniRedisError::InvalidArguments { arguments: vec![] }),
+            encode_command(&[])
+        );
+    }
+
+    /// Reads a reply from the lines of some text.
+    fn read(text: &str) -> Result<Response, MiniRedisError> {
+        let mut lines = text.lines().map(str::to_string);
+        read_reply(&mut || lines.next().ok_or(MiniRedisError::StreamClosed))
+    }
+
+    fn bulk(value: &str) -> Response {
+        Response::Bulk(value.to_string())
+    }
+
+    #[test]
+    fn decode_reply_returns_values_and_errors() {
+        assert_eq!(
+            Ok(Response::Integer(2)),
+            decode_reply(Response::from_line("2"))
+        );
+        assert_eq!(Ok(Response::Nil), decode_reply(Response::from_line("nil")));
+        assert_eq!(
+            Err(MiniRedisError::StoreFull),
+            decode_reply(Response::from_line("ERR The key value store is full."))
+        );
+    }
+
+    #[test]
+    fn encode_reply_writes_single_replies_on_one_line() {
+        assert_eq!("OK", encode_reply(&Response::ok()));
+        assert_eq!("hello world", encode_reply(&bulk("hello world")));
+        assert_eq!("-3", encode_reply(&Response::Integer(-3)));
+        assert_eq!("nil", encode_reply(&Response::Nil));
+    }
+
+    #[test]
+    fn encode_reply_counts_elements_of_arrays_and_maps() {
+        let array = Response::Array(vec![bulk("a"), Response::Array(vec![]), Response::Nil]);
+        let map = Response::Map(vec![("keys".to_string(), Response::Integer(2))]);
+
+        assert_eq!("*3\na\n*0\nnil", encode_reply(&array));
+        assert_eq!("%1\nkeys\n2", encode_reply(&map));
+    }
+
+    #[test]
+    fn read_reply_reads_every_line_of_nested_replies() {
+        let reply = || {
+            Response::Array(vec![
+                bulk("a"),
+                Response::Map(vec![
+                    ("name".to_string(), bulk("alice")),
+                    (
+                        "tags".to_string(),
+                        Response::Array(vec![bulk("x"), bulk("y")]),
+                    ),
+                ]),
+                Response::Array(vec![]),
+                Response::Integer(1),
+            ])
+        };
+
+        assert_eq!(Ok(reply()), read(&encode_reply(&reply())));
+    }
+
+    #[test]
+    fn read_reply_reads_only_its_own_lines() {
+        let mut lines = ["*2", "a", "b", "next"].into_iter().map(str::to_string);
+        let mut next_line = || lines.next().ok_or(MiniRedisError::StreamClosed);
+
+        assert_eq!(
+            Ok(Response::Array(vec![bulk("a"), bulk("b")])),
+            read_reply(&mut next_line)
+        );
+        assert_eq!(Ok(bulk("next")), read_reply(&mut next_line));
+    }
+
+    #[test]
+    fn read_reply_fails_on_a_reply_cut_short() {
+        assert_eq!(Err(MiniRedisError::StreamClosed), read("*3\na\nb"));
+    }
+
+    /// Reads a RESP frame from some text.
+    fn read_frame(frame: impl AsRef<[u8]>) -> io::Result<Response> {
+        read_resp(&mut io::Cursor::new(frame.as_ref()))
+    }
+
+    #[test]
+    fn encode_resp_command_sends_words_with_their_lengths() {
+        assert_eq!(
+            Ok("*3\r\n$3\r\nSET\r\n$3\r\nmsg\r\n$12\r\nhello\r\nworld\r\n".to_string()),
+            encode_resp_command(&["SET", "msg", "hello\r\nworld"])
+        );
+        assert_eq!(
+            Err(MiniRedisError::InvalidArguments { arguments: vec![] }),
+            encode_resp_command::<&str>(&[])
+        );
+    }
+
+    #[test]
+    fn encode_resp_reply_writes_every_type() {
+        assert_eq!(b"+OK\r\n".to_vec(), encode_resp_reply(&Response::ok()));
+        assert_eq!(b"$2\r\nhi\r\n".to_vec(), encode_resp_reply(&bulk("hi")));
+        assert_eq!(
+            b":-3\r\n".to_vec(),
+            encode_resp_reply(&Response::Integer(-3))
+        );
+        assert_eq!(
+            b"$2\r\n\xff\x00\r\n".to_vec(),
+            encode_resp_reply(&Response::BulkBytes(vec![0xff, 0]))
+        );
+        assert_eq!(b"$-1\r\n".to_vec(), encode_resp_reply(&Response::Nil));
+        assert_eq!(
+            b"-ERR Invalid command: X. Run 'miniredis-client --help' for more information.\r\n"
+                .to_vec(),
+            encode_resp_reply(&Response::Error(MiniRedisError::InvalidCommand {
+                command: "X".to_string()
+            }))
+        );
+        assert_eq!(
+            b"*2\r\n$1\r\na\r\n%1\r\n$1\r\nn\r\n:1\r\n".to_vec(),
+            encode_resp_reply(&Response::Array(vec![
+                bulk("a"),
+                Response::Map(vec![("n".to_string(), Response::Integer(1))]),
+            ]))
+        );
+    }
+
+    #[test]
+    fn read_resp_reads_what_encode_resp_reply_writes() {
+        let reply = || {
+            Response::Array(vec![
+                bulk("line one\nline two"),
+                Response::BulkBytes(vec![0xff, 0, b'\r', b'\n']),
+                Response::Nil,
+                Response::Integer(7),
+                Response::Simple("PONG".to_string()),
+                Response::Map(vec![("tags".to_string(), Response::Array(vec![bulk("")]))]),
+            ])
+        };
+
+        assert_eq!(reply(), read_frame(encode_resp_reply(&reply())).unwrap());
+    }
+
+    #[test]
+    fn read_resp_reads_error_replies_as_server_errors() {
+        assert_eq!(
+            Response::Error(MiniRedisError::ServerError {
+                message: "Invalid command: X.".to_string()
+            }),
+            read_frame("-ERR Invalid command: X.\r\n").unwrap()
+        );
+    }
+
+    #[test]
+    fn read_resp_rejects_invalid_frames() {
+        for frame in [
+            "",
+            "hello\r\n",
+            "$x\r\n",
+            "$5\r\nab\r\n",
+            "$2\r\nabcd",
+            ":1.5\r\n",
+            "*-2\r\n",
+        ] {
+            assert!(read_frame(frame).is_err(), "{:?}", frame);
+        }
+        assert_eq!(
+            io::ErrorKind::InvalidData,
+            read_frame("?\r\n").unwrap_err().kind()
+        );
+    }
+
+    #[test]
+    fn read_resp_command_reads_commands_and_skips_empty_ones() {
+        let frame = encode_resp_command(&["set", "msg", "hello world"]).unwrap();
+
+        assert_eq!(
+            Some(Command::new(
+                "SET",
+                vec!["msg".to_string(), "hello world".to_string()]
+            )),
+            read_resp_command(&mut io::Cursor::new(frame.as_bytes())).unwrap()
+        );
+        assert_eq!(
+            None,
+            read_resp_command(&mut io::Cursor::new(b"*0\r\n".as_slice())).unwrap()
+        );
+        assert!(read_resp_command(&mut io::Cursor::new(b"*1\r\n:1\r\n".as_slice())).is_err());
+    }
+
+    #[test]
+    fn read_resp_command_rejects_nested_frames() {
+        let deep = "*1\r\n".repeat(300_000);
+
+        for frame in [deep.as_str(), "*2\r\n$3\r\nGET\r\n*1\r\n$1\r\na\r\n"] {
+            let error = read_resp_command(&mut io::Cursor::new(frame.as_bytes())).unwrap_err();
+            assert_eq!(io::ErrorKind::InvalidData, error.kind());
+        }
+    }
+
+    #[test]
+    fn read_resp_rejects_replies_nested_too_deep() {
+        let nested = |depth: usize| "*1\r\n".repeat(depth) + ":1\r\n";
+
+        assert!(read_frame(nested(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            io::ErrorKind::InvalidData,
+            read_frame(nested(300_000)).unwrap_err().kind()
+        );
+    }
+
+    #[test]
+    fn resp_frame_len_waits_for_the_whole_frame() {
+        let reply = Response::Array(vec![
+            Response::BulkBytes(vec![0xff, b'\r', b'\n']),
+            Response::Nil,
+            Response::Map(vec![("n".to_string(), Response::Integer(1))]),
+        ]);
+        let frame = encode_resp_reply(&reply);
+
+        for end in 0..frame.len() {
+            assert_eq!(None, resp_frame_len(&frame[..end]), "{}", end);
+        }
+        let mut buffer = frame.clone();
+        buffer.extend_from_slice(b"+OK\r\n");
+        assert_eq!(Some(frame.len()), resp_frame_len(&buffer));
+        assert_eq!(Some(reply), read_frame(&buffer[..frame.len()]).ok());
+    }
+
+    #[test]
+    fn resp_frame_len_stops_at_the_line_of_an_invalid_frame() {
+        assert_eq!(Some(3), resp_frame_len(b"?x\n$3\r\n"));
+        assert_eq!(Some(8), resp_frame_len(b"*1\r\n$x\r\n:1\r\n"));
+        let nested = "*1\r\n".repeat(300_000);
+        assert_eq!(Some(4 * (MAX_DEPTH + 1)), resp_frame_len(nested.as_bytes()));
+        assert!(read_frame(&nested.as_bytes()[..4 * (MAX_DEPTH + 1)]).is_err());
+    }
+
+    #[test]
+    fn resp_command_len_measures_commands_and_stops_at_anything_else() {
+        let frame = encode_resp_command(&["SET", "msg", "hello\r\nworld"]).unwrap();
+        let mut buffer = frame.clone().into_bytes();
+        buffer.extend_from_slice(b"*1\r\n");
+
+        for end in 0..frame.len() {
+            assert_eq!(None, resp_command_len(&buffer[..end]), "{}", end);
+        }
+        assert_eq!(Some(frame.len()), resp_command_len(&buffer));
+        assert_eq!(Some(5), resp_command_len(b"GET\r\n"));
+        assert_eq!(Some(17), resp_command_len(b"*2\r\n$3\r\nGET\r\n*1\r\n"));
+        assert!(read_resp_command(&mut &b"*2\r\n$3\r\nGET\r\n*1\r\n"[..]).is_err());
+    }
+
+    /// A reader that remembers the largest buffer it was asked to fill.
+    struct Recorder {
+        data: io::Cursor<Vec<u8>>,
+        largest: usize,
+    }
+
+    impl Read for Recorder {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.largest = self.largest.max(buf.len());
+            self.data.read(buf)
+        }
+    }
+
+    impl BufRead for Recorder {
+        fn fill_buf(&mut self) -> io::Result<&[u8]> {
+            self.data.fill_buf()
+        }
+
+        fn consume(&mut self, amount: usize) {
+            self.data.consume(amount)
+        }
+    }
+
+    #[test]
+    fn bulk_strings_take_memory_only_as_their_data_arrives() {
+        let mut reader = Recorder {
+            data: io::Cursor::new(format!("*1\r\n${}\r\nabc", MAX_BULK_LEN).into_bytes()),
+            largest: 0,
+        };
+
+        let error = read_resp_command(&mut reader).unwrap_err();
+
+        assert_eq!(io::ErrorKind::UnexpectedEof, error.kind());
+        assert!(reader.largest < 1024 * 1024, "{}", reader.largest);
+    }
+
+    #[test]
+    fn values_that_look_like_headers_survive_a_round_trip() {
+        for value in ["*3", "%0", "\\*3", "\\\\%12", "*", "*a", "\\plain"] {
+            let reply = || Response::Array(vec![bulk(value)]);
+
+            assert_eq!(Ok(reply()), read(&encode_reply(&reply())), "{}", value);
+            assert_eq!(
+                Ok(bulk(value)),
+                read(&encode_reply(&bulk(value))),
+                "{}",
+                value
+            );
+        }
+        assert_eq!("\\*3", encode_reply(&bulk("*3")));
+        assert_eq!("\\plain", encode_reply(&bulk("\\plain")));
+    }
+
+    #[test]
+    fn error_from_reply_restores_known_errors() {
+        let errors = [
+            MiniRedisError::StoreFull,
+            MiniRedisError::StoreLocked,
+            MiniRedisError::ExpiryNotSupported,
+            MiniRedisError::InvalidCommand {
+                command: "UNKNOWN".to_string(),
+            },
+            MiniRedisError::InvalidArguments { arguments: vec![] },
+            MiniRedisError::InvalidArguments {
+                arguments: vec!["a".to_string(), "b c".to_string()],
+            },
+            MiniRedisError::WrongArity {
+                command: "DELPREFIX".to_string(),
+            },
+            MiniRedisError::InvalidExpireTime {
+                command: "GETEX".to_string(),
+            },
+            MiniRedisError::IncompatibleOptions {
+                options: "NX and XX, GT or LT".to_string(),
+            },
+            MiniRedisError::SyntaxError,
+            MiniRedisError::NotAnInteger,
+            MiniRedisError::IncrementOverflow,
+            MiniRedisError::NotAFloat,
+            MiniRedisError::IncrementNotFinite,
+            MiniRedisError::NotADouble,
+            MiniRedisError::ValueTooLarge,
+            MiniRedisError::NoSuchKey,
+        ];
+
+        for error in errors {
+            assert_eq!(error, error_from_reply(&error.to_string()));
+        }
+    }
+
+    #[test]
+    fn error_from_reply_keeps_unknown_errors_as_server_errors() {
+        assert_eq!(
+            MiniRedisError::ServerError {
+                message: "Something went wrong.".to_string()
+            },
+            error_from_reply("Something went wrong.")
+        );
+        assert_eq!(
+            MiniRedisError::ServerError {
+                message: "NOSCRIPT No matching script.".to_string()
+            },
+            error_from_reply("NOSCRIPT No matching script.")
+        );
+    }
+
+    #[test]
+    fn error_replies_start_with_the_code_of_the_error() {
+        assert_eq!(
+            b"-OOM The key value store is full.\r\n".to_vec(),
+            encode_resp_reply(&Response::Error(MiniRedisError::StoreFull))
+        );
+        assert_eq!(
+            "ERR OOM The key value store is full.",
+            encode_reply(&Response::Error(MiniRedisError::StoreFull))
+        );
+        assert_eq!(
+            "ERR Invalid arguments: []. Run 'miniredis-client --help' for more information.",
+            encode_reply(&Response::Error(MiniRedisError::InvalidArguments {
+                arguments: vec![]
+            }))
+        );
+    }
+
+    #[test]
+    fn decode_reply_restores_errors_from_their_code() {
+        for error in [
+            MiniRedisError::StoreFull,
+            MiniRedisError::StoreLocked,
+            MiniRedisError::WrongType,
+            MiniRedisError::Moved {
+                slot: 12182,
+                address: "127.0.0.1:7001".to_string(),
+            },
+        ] {
+            let resp = format!("-{} {}\r\n", error.code(), error);
+            let reply = read_frame(&resp).unwrap();
+
+            let line = format!("ERR {}", error_message(&error));
+
+            assert_eq!(error, decode_reply(reply).unwrap_err(), "{}", resp);
+            assert_eq!(
+                error,
+                decode_reply(Response::from_line(&line)).unwrap_err(),
+                "{}",
+                line
+            );
+        }
+        assert_eq!(
+            Err(MiniRedisError::AuthenticationFailed {
+                message: "Authentication required.".to_string()
+            }),
+            decode_reply(read_frame("-NOAUTH Authentication required.\r\n").unwrap())
+        );
+        assert_eq!(
+            b"-MOVED 3999 127.0.0.1:6381\r\n".to_vec(),
+            encode_resp_reply(&Response::Error(MiniRedisError::Moved {
+                slot: 3999,
+                address: "127.0.0.1:6381".to_string()
+            }))
+        );
+    }
+}
@@ -0,0 +1,654 @@
+use crate::command::Command;
+use crate::dump;
+use crate::engine::Engine;
+use crate::error::MiniRedisError;
+use crate::log::{Level, Logger};
+use crate::protocol;
+use crate::response::Response;
+use crate::storage::Storage;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How long a replica waits after losing its primary before it connects again.
+const RECONNECT_BACKOFF: Duration = Duration::from_millis(500);
+
+/// The replicas of a server, and how far each of them got in the stream of its writes.
+///
+/// Every write that succeeds is sent to the replicas as its command, in the order
+/// the writes were executed, and the replication offset grows by the length of its frame.
+/// A replica acknowledges the offset it has executed up to with `REPLCONF ACK`,
+/// and `WAIT` blocks on a condition variable until enough of them got to its offset.
+#[derive(Debug, Default)]
+pub(crate) struct Replication {
+    state: Mutex<State>,
+    acknowledged: Condvar,
+}
+
+/// The state of [`Replication`], behind its lock.
+#[derive(Debug, Default)]
+struct State {
+    /// How many bytes of commands have been sent to the replicas so far.
+    offset: u64,
+    replicas: Vec<Replica>,
+    next_id: u64,
+    /// Whether the server shut down, which lets every `WAIT` go.
+    closed: bool,
+}
+
+/// A replica that is attached to the server.
+#[derive(Debug)]
+struct Replica {
+    id: u64,
+    /// Where the frames of the writes go, for the connection of the replica to send.
+    feed: Sender<Vec<u8>>,
+    /// The offset the replica last acknowledged, or None if it never did.
+    acknowledged: Option<u64>,
+}
+
+/// A replica that was just attached, with what it needs to catch up.
+#[derive(Debug)]
+pub(crate) struct Attached {
+    pub(crate) id: u64,
+    /// The replication offset the snapshot was taken at.
+    pub(crate) offset: u64,
+    /// The commands that set every key the server held, as of the offset.
+    pub(crate) snapshot: Vec<Vec<String>>,
+    /// The frames of the writes after the offset, which ends once the replica is detached.
+    pub(crate) feed: Receiver<Vec<u8>>,
+}
+
+impl State {
+    /// Sends a frame to every replica, and moves the offset past it.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The frame to send.
+    fn send(&mut self, frame: Vec<u8>) {
+        self.offset += frame.len() as u64;
+        // A replica whose connection is gone is detached by it.
+        for replica in &self.replicas {
+            let _ = replica.feed.send(frame.clone());
+        }
+    }
+
+    /// Counts the replicas that acknowledged an offset, or one past it.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - The offset to count acknowledgements of.
+    ///
+    /// # Returns
+    ///
+    /// The number of replicas that got to the offset.
+    fn acknowledged(&self, offset: u64) -> usize {
+        self.replicas
+            .iter()
+            .filter(|replica| replica.acknowledged.is_some_and(|acked| acked >= offset))
+            .count()
+    }
+}
+
+impl Replication {
+    /// Executes a write, and sends it to the replicas if it succeeds.
+    ///
+    /// The lock is held while the write is executed, so the replicas get the writes
+    /// in the order they were executed in, and a snapshot taken by [`Replication::attach`]
+    /// holds either all of a write or none of it.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The write.
+    /// * `execute` - Executes the write.
+    ///
+    /// # Returns
+    ///
+    /// What executing the write returned.
+    ///
+    /// # Errors
+    ///
+    /// If executing the write fails, it will return the error, and the write is not sent.
+    pub(crate) fn write(
+        &self,
+        command: Command,
+        execute: impl FnOnce(Command) -> Result<Response, MiniRedisError>,
+    ) -> Result<Response, MiniRedisError> {
+        let mut state = self.lock();
+        // Without replicas, nothing needs the frame or the offset.
+        let frame = (!state.replicas.is_empty())
+            .then(|| encode(&command))
+            .transpose()?;
+        let result = execute(command);
+        if let (Ok(_), Some(frame)) = (&result, frame) {
+            state.send(frame);
+        }
+        result
+    }
+
+    /// Attaches a replica, with a snapshot of the data to start it from.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - The backend to take the snapshot of.
+    ///
+    /// # Returns
+    ///
+    /// The replica, with the snapshot and the writes that follow it.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot be read, it will return an error.
+    pub(crate) fn attach(&self, storage: &dyn Storage) -> Result<Attached, MiniRedisError> {
+        let mut state = self.lock();
+        let mut snapshot = Vec::new();
+        for key in storage.keys()? {
+            // A key that expired since it was listed is left out.
+            let Some(value) = storage.get_bytes(&key)? else {
+                continue;
+            };
+            let ttl = storage
+                .ttl(&key)?
+                .map(|ttl| u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX));
+            snapshot.extend(dump::set_commands(&key, &value, ttl));
+        }
+        let (feed, frames) = mpsc::channel();
+        state.next_id += 1;
+        let id = state.next_id;
+        state.replicas.push(Replica {
+            id,
+            feed,
+            acknowledged: None,
+        });
+        Ok(Attached {
+            id,
+            offset: state.offset,
+            snapshot,
+            feed: frames,
+        })
+    }
+
+    /// Records the offset a replica executed the writes up to, and wakes every `WAIT`.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The replica.
+    /// * `offset` - The offset it acknowledged.
+    pub(crate) fn acknowledge(&self, id: u64, offset: u64) {
+        let mut state = self.lock();
+        if let Some(replica) = state.replicas.iter_mut().find(|replica| replica.id == id) {
+            replica.acknowledged = Some(offset);
+        }
+        self.acknowledged.notify_all();
+    }
+
+    /// Detaches a replica, which ends its feed.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The replica.
+    pub(crate) fn detach(&self, id: u64) {
+        self.lock().replicas.retain(|replica| replica.id != id);
+    }
+
+    /// Detaches every replica, and lets every `WAIT` go, when the server shuts down.
+    pub(crate) fn close(&self) {
+        let mut state = self.lock();
+        state.closed = true;
+        state.replicas.clear();
+        self.acknowledged.notify_all();
+    }
+
+    /// Waits until replicas acknowledge every write executed so far.
+    ///
+    /// Replicas that are behind are asked for their offset with `REPLCONF GETACK`.
+    ///
+    /// # Arguments
+    ///
+    /// * `replicas` - How many replicas to wait for.
+    /// * `timeout` - How long to wait at most, or None to wait for as long as it takes.
+    ///
+    /// # Returns
+    ///
+    /// How many replicas acknowledged the writes, which is fewer than asked for
+    /// if the timeout passed or the server shut down first.
+    pub(crate) fn wait(&self, replicas: usize, timeout: Option<Duration>) -> usize {
+        let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        let mut state = self.lock();
+        let offset = state.offset;
+        if state.acknowledged(offset) >= replicas || state.closed {
+            return state.acknowledged(offset);
+        }
+        if let Ok(frame) = encode(&Command::new("REPLCONF", vec!["GETACK".into(), "*".into()])) {
+            state.send(frame);
+        }
+        while state.acknowledged(offset) < replicas && !state.closed {
+            state = match deadline {
+                None => self
+                    .acknowledged
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break;
+                    }
+                    self.acknowledged
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0
+                }
+            };
+        }
+        state.acknowledged(offset)
+    }
+
+    /// Locks the state, even if a thread panicked while holding the lock.
+    ///
+    /// # Returns
+    ///
+    /// The locked state.
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Encodes a command as the RESP frame that is sent to replicas.
+///
+/// # Arguments
+///
+/// * `command` - The command.
+///
+/// # Returns
+///
+/// The frame.
+///
+/// # Errors
+///
+/// If the command has no name, it will return [`MiniRedisError::InvalidArguments`].
+fn encode(command: &Command) -> Result<Vec<u8>, MiniRedisError> {
+    let mut parts = vec![command.name.as_str()];
+    parts.extend(command.args.iter().map(String::as_str));
+    Ok(protocol::encode_resp_command(&parts)?.into_bytes())
+}
+
+/// Serves a replica that sent `SYNC`, until it goes away or the server shuts down.
+///
+/// The replica first gets `+FULLRESYNC <OFFSET> <COMMANDS>`, and the commands of a snapshot
+/// of the data, then every write after it. A thread of its own reads the `REPLCONF ACK`s
+/// of the replica meanwhile.
+///
+/// # Arguments
+///
+/// * `replication` - The replicas of the server.
+/// * `storage` - The backend to take the snapshot of.
+/// * `acks` - What the replica sends after `SYNC`.
+/// * `socket` - The stream of the replica, which is closed once it is served.
+/// * `send` - Writes frames to the replica, and flushes them.
+///
+/// # Returns
+///
+/// A result indicating whether the replica was served until it went away.
+///
+/// # Errors
+///
+/// If the snapshot cannot be taken, or the replica cannot be written to,
+/// it will return the error.
+pub(crate) fn serve_replica(
+    replication: &Replication,
+    storage: &dyn Storage,
+    mut acks: impl BufRead + Send,
+    socket: &TcpStream,
+    mut send: impl FnMut(&[u8]) -> Result<(), MiniRedisError>,
+) -> Result<(), MiniRedisError> {
+    let replica = replication.attach(storage)?;
+    let id = replica.id;
+    let result = thread::scope(|scope| {
+        scope.spawn(|| {
+            while let Ok(command) = protocol::read_resp_command(&mut acks) {
+                let Some(Command { name, args }) = command else {
+                    continue;
+                };
+                if let ("REPLCONF", [ack, offset]) = (name.as_str(), args.as_slice())
+                    && ack.eq_ignore_ascii_case("ACK")
+                    && let Ok(offset) = offset.parse()
+                {
+                    replication.acknowledge(id, offset);
+                }
+            }
+            // A replica that went away ends its feed, and with it the loop below.
+            replication.detach(id);
+        });
+        let result = feed(replica, &mut send);
+        let _ = socket.shutdown(Shutdown::Both);
+        result
+    });
+    replication.detach(id);
+    result
+}
+
+/// Sends a replica its snapshot, then every write, until it is detached.
+///
+/// # Arguments
+///
+/// * `replica` - The replica.
+/// * `send` - Writes frames to the replica, and flushes them.
+///
+/// # Errors
+///
+/// If the replica cannot be written to, it will return the error.
+fn feed(
+    replica: Attached,
+    send: &mut impl FnMut(&[u8]) -> Result<(), MiniRedisError>,
+) -> Result<(), MiniRedisError> {
+    let mut frames = format!(
+        "+FULLRESYNC {} {}\r\n",
+        replica.offset,
+        replica.snapshot.len()
+    )
+    .into_bytes();
+    for command in &replica.snapshot {
+        frames.extend(protocol::encode_resp_command(command)?.into_bytes());
+    }
+    send(&frames)?;
+    for mut frames in &replica.feed {
+        // Writes that piled up while the last ones were sent go out together.
+        replica
+            .feed
+            .try_iter()
+            .for_each(|frame| frames.extend(frame));
+        send(&frames)?;
+    }
+    Ok(())
+}
+
+/// The thread of a replica that copies the writes of its primary.
+///
+/// It connects to the primary, sends `SYNC`, replaces the data of the replica with
+/// the snapshot it gets, and then executes every write that follows, acknowledging
+/// its offset whenever the primary asks. If the primary goes away, it connects again.
+pub(crate) struct Follower {
+    stopped: Arc<AtomicBool>,
+    /// The connection to the primary, to close it when stopping.
+    stream: Arc<Mutex<Option<TcpStream>>>,
+    thread: JoinHandle<()>,
+}
+
+impl Follower {
+    /// Starts following a primary.
+    ///
+    /// # Arguments
+    ///
+    /// * `primary` - The address of the primary.
+    /// * `engine` - The engine of the replica, to execute the writes on.
+    /// * `logger` - Where to log losing the primary.
+    ///
+    /// # Returns
+    ///
+    /// The follower, which runs until it is stopped.
+    pub(crate) fn start(primary: String, engine: Engine, logger: Arc<dyn Logger>) -> Self {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stream = Arc::new(Mutex::new(None));
+        let thread = {
+            let stopped = Arc::clone(&stopped);
+            let stream = Arc::clone(&stream);
+            thread::spawn(move || {
+                while !stopped.load(Ordering::SeqCst) {
+                    let result = follow(&primary, &engine, &stream, &stopped);
+                    if stopped.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let reason = result.err().unwrap_or(MiniRedisError::StreamClosed);
+                    logger.log(
+                        Level::Warning,
+                        &format!("Lost the primary {}: {}", primary, reason),
+                    );
+                    // Stopping unparks the thread, so it does not wait out the backoff.
+                    thread::park_timeout(RECONNECT_BACKOFF);
+                }
+            })
+        };
+        Self {
+            stopped,
+            stream,
+            thread,
+        }
+    }
+
+    /// Stops following the primary, and waits for the thread to end.
+    pub(crate) fn stop(self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        if let Some(stream) = &*self.stream.lock().unwrap_or_else(PoisonError::into_inner) {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        self.thread.thread().unpark();
+        let _ = self.thread.join();
+    }
+}
+
+/// Follows a primary over one connection, until it is lost.
+///
+/// # Arguments
+///
+/// * `primary` - The address of the primary.
+/// * `engine` - The engine of the replica.
+/// * `slot` - Where to keep the connection, for [`Follower::stop`] to close it.
+/// * `stopped` - Whether the follower was stopped.
+///
+/// # Returns
+///
+/// Ok once the follower is stopped; the connection is only lost with an error.
+///
+/// # Errors
+///
+/// If the primary cannot be connected to, read from, or written to,
+/// or does not answer `SYNC` with a snapshot, it will return an error.
+fn follow(
+    primary: &str,
+    engine: &Engine,
+    slot: &Mutex<Option<TcpStream>>,
+    stopped: &AtomicBool,
+) -> Result<(), MiniRedisError> {
+    let stream =
+        TcpStream::connect(primary).map_err(|source| MiniRedisError::StreamNotConnected {
+            address: primary.to_string(),
+            source,
+        })?;
+    let mut writer = stream
+        .try_clone()
+        .map_err(|source| MiniRedisError::StreamNotConnected {
+            address: primary.to_string(),
+            source,
+        })?;
+    *slot.lock().unwrap_or_else(PoisonError::into_inner) = Some(stream.try_clone().map_err(
+        |source| MiniRedisError::StreamNotConnected {
+            address: primary.to_string(),
+            source,
+        },
+    )?);
+    // A stop that came before the connection was kept could not close it.
+    if stopped.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    let request = protocol::encode_resp_command(&["SYNC"])?;
+    writer
+        .write_all(request.as_bytes())
+        .map_err(|source| MiniRedisError::StreamNotWritable { source })?;
+    let mut acknowledge = |offset: u64| {
+        let frame = protocol::encode_resp_command(&["REPLCONF", "ACK", &offset.to_string()])?;
+        writer
+            .write_all(frame.as_bytes())
+            .map_err(|source| MiniRedisError::StreamNotWritable { source })
+    };
+    let mut reader = BufReader::new(stream);
+
+    let header = protocol::read_resp(&mut reader).map_err(read_error)?;
+    let resync = match &header {
+        Response::Simple(line) => line.strip_prefix("FULLRESYNC ").and_then(|rest| {
+            let (offset, commands) = rest.split_once(' ')?;
+            Some((offset.parse::<u64>().ok()?, commands.parse::<usize>().ok()?))
+        }),
+        _ => None,
+    };
+    let Some((mut offset, commands)) = resync else {
+        return Err(MiniRedisError::ServerError {
+            message: format!("{} answered SYNC with {}", primary, header),
+        });
+    };
+    engine.storage().del_prefix("")?;
+    for _ in 0..commands {
+        if let Some(command) = protocol::read_resp_command(&mut reader).map_err(read_error)? {
+            engine.execute(command);
+        }
+    }
+    acknowledge(offset)?;
+
+    loop {
+        let Some(command) = protocol::read_resp_command(&mut reader).map_err(read_error)? else {
+            continue;
+        };
+        offset += encode(&command)?.len() as u64;
+        match (command.name.as_str(), command.args.as_slice()) {
+            ("REPLCONF", [getack, _]) if getack.eq_ignore_ascii_case("GETACK") => {
+                acknowledge(offset)?;
+            }
+            _ => {
+                engine.execute(command);
+            }
+        }
+    }
+}
+
+/// Makes an IO error of reading from the primary into a [`MiniRedisError`].
+///
+/// # Arguments
+///
+/// * `source` - The IO error.
+///
+/// # Returns
+///
+/// [`MiniRedisError::StreamClosed`] if the primary closed the connection,
+/// and [`MiniRedisError::StreamNotReadable`] otherwise.
+fn read_error(source: std::io::Error) -> MiniRedisError {
+    if source.kind() == std::io::ErrorKind::UnexpectedEof {
+        MiniRedisError::StreamClosed
+    } else {
+        MiniRedisError::StreamNotReadable { source }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv_store::KVStore;
+
+    fn set(key: &str, value: &str) -> Command {
+        Command::new("SET", vec![key.to_string(), value.to_string()])
+    }
+
+    #[test]
+    fn write_sends_only_the_writes_that_succeed_and_moves_the_offset() {
+        let replication = Replication::default();
+        let replica = replication.attach(&KVStore::new()).unwrap();
+
+        replication
+            .write(set("name", "alice"), |_| Ok(Response::ok()))
+            .unwrap();
+        let failed = replication.write(set("name", "bob"), |_| Err(MiniRedisError::StoreFull));
+
+        assert_eq!(Err(MiniRedisError::StoreFull), failed);
+        let frame = b"*3\r\n$3\r\nSET\r\n$4\r\nname\r\n$5\r\nalice\r\n".to_vec();
+        assert_eq!(
+            vec![frame.clone()],
+            replica.feed.try_iter().collect::<Vec<_>>()
+        );
+        assert_eq!(frame.len() as u64, replication.lock().offset);
+    }
+
+    #[test]
+    fn attach_takes_a_snapshot_of_every_key_with_its_ttl() {
+        let store = KVStore::new();
+        store.set("name", "alice").unwrap();
+        store.set("session", "token").unwrap();
+        store
+            .expire("session", Duration::from_secs(60), None)
+            .unwrap();
+        let replication = Replication::default();
+
+        let mut snapshot = replication.attach(&store).unwrap().snapshot;
+        snapshot.sort();
+
+        assert_eq!(vec!["SET", "name", "alice"], snapshot[0]);
+        assert_eq!(&["SET", "session", "token", "PX"], &snapshot[1][..4]);
+    }
+
+    #[test]
+    fn wait_gives_up_on_a_slow_replica_at_the_timeout() {
+        let replication = Replication::default();
+        let replica = replication.attach(&KVStore::new()).unwrap();
+        replication
+            .write(set("name", "alice"), |_| Ok(Response::ok()))
+            .unwrap();
+
+        let start = Instant::now();
+        assert_eq!(0, replication.wait(1, Some(Duration::from_millis(50))));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        // An acknowledgement of an older offset does not count either.
+        replication.acknowledge(replica.id, 1);
+        assert_eq!(0, replication.wait(1, Some(Duration::from_millis(10))));
+    }
+
+    #[test]
+    fn wait_without_a_timeout_blocks_until_a_replica_acknowledges() {
+        let replication = Arc::new(Replication::default());
+        let replica = replication.attach(&KVStore::new()).unwrap();
+        replication
+            .write(set("name", "alice"), |_| Ok(Response::ok()))
+            .unwrap();
+        let waiting = {
+            let replication = Arc::clone(&replication);
+            thread::spawn(move || replication.wait(1, None))
+        };
+
+        // The wait asks the replica where it got to.
+        let frames: Vec<Vec<u8>> = replica.feed.iter().take(2).collect();
+        assert!(frames[1].ends_with(b"GETACK\r\n$1\r\n*\r\n"));
+        thread::sleep(Duration::from_millis(50));
+        assert!(!waiting.is_finished());
+
+        replication.acknowledge(
+            replica.id,
+            frames.iter().map(|frame| frame.len() as u64).sum(),
+        );
+        assert_eq!(1, waiting.join().unwrap());
+        assert_eq!(1, replication.wait(1, Some(Duration::from_millis(10))));
+    }
+
+    #[test]
+    fn close_lets_every_wait_go() {
+        let replication = Arc::new(Replication::default());
+        let waiting = {
+            let replication = Arc::clone(&replication);
+            thread::spawn(move || replication.wait(1, None))
+        };
+        thread::sleep(Duration::from_millis(50));
+
+        replication.close();
+
+        assert_eq!(0, waiting.join().unwrap());
+    }
+
+    #[test]
+    fn detach_ends_the_feed_of_the_replica() {
+        let replication = Replication::default();
+        let replica = replication.attach(&KVStore::new()).unwrap();
+
+        replication.detach(replica.id);
+
+        assert!(replica.feed.recv().is_err());
+    }
+}
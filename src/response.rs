@@ -0,0 +1,154 @@
+use crate::error::MiniRedisError;
+use crate::protocol;
+
+/// The result of executing a [`Command`](crate::command::Command).
+///
+/// A response is displayed the way the server writes it to clients.
+/// Errors start with `ERR `, so clients can tell them apart from values,
+/// followed by the [code](MiniRedisError::code) of the error unless it is `ERR` itself.
+/// Arrays and maps start with a line counting their elements, like `*2` or `%2`,
+/// followed by one element per line, with every field on the line before its value.
+///
+/// # Examples
+///
+/// ```rust
+/// use miniredis::error::MiniRedisError;
+/// use miniredis::response::Response;
+///
+/// assert_eq!("OK", Response::ok().to_string());
+/// assert_eq!("nil", Response::Nil.to_string());
+/// assert_eq!("2", Response::Integer(2).to_string());
+/// assert_eq!(
+///     "ERR OOM The key value store is full.",
+///     Response::Error(MiniRedisError::StoreFull).to_string()
+/// );
+/// ```
+#[derive(Debug, PartialEq)]
+pub enum Response {
+    /// A status reply, like `OK`.
+    Simple(String),
+    /// A value read from the store.
+    Bulk(String),
+    /// A value read from the store whose bytes are not valid UTF-8.
+    ///
+    /// Values that are valid UTF-8 are always a [`Response::Bulk`], see [`Response::bulk`].
+    BulkBytes(Vec<u8>),
+    /// A number, like the count of deleted keys.
+    Integer(i64),
+    /// The absence of a value.
+    Nil,
+    /// The command failed.
+    Error(MiniRedisError),
+    /// A list of responses, like the values of several keys.
+    Array(Vec<Response>),
+    /// Fields and their values, like the fields of a hash.
+    Map(Vec<(String, Response)>),
+}
+
+impl Response {
+    /// Creates the `OK` status reply.
+    ///
+    /// # Returns
+    ///
+    /// A simple response holding `OK`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::response::Response;
+    ///
+    /// assert_eq!(Response::Simple("OK".to_string()), Response::ok());
+    /// ```
+    pub fn ok() -> Self {
+        Response::Simple("OK".to_string())
+    }
+
+    /// Creates the reply holding the bytes of a value.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The bytes of the value.
+    ///
+    /// # Returns
+    ///
+    /// A [`Response::Bulk`] if the bytes are valid UTF-8, and a [`Response::BulkBytes`] if not.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::response::Response;
+    ///
+    /// assert_eq!(Response::Bulk("hi".to_string()), Response::bulk(b"hi".to_vec()));
+    /// assert_eq!(Response::BulkBytes(vec![0xff]), Response::bulk(vec![0xff]));
+    /// ```
+    pub fn bulk(value: Vec<u8>) -> Self {
+        match String::from_utf8(value) {
+            Ok(value) => Response::Bulk(value),
+            Err(error) => Response::BulkBytes(error.into_bytes()),
+        }
+    }
+
+    /// Parses a line of a reply, the way the server displays a response.
+    ///
+    /// A line does not say which kind of response it holds, so the kind
+    /// is inferred: `ERR ` starts an error, `nil` is nil, `OK` is a status,
+    /// a number is an integer, and anything else is a value.
+    /// A value that looks like a number is therefore parsed as an integer.
+    /// The line must not start an array or a map, which span several lines.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The line of the reply, without its line ending.
+    ///
+    /// # Returns
+    ///
+    /// The response the line most likely holds.
+    /// An error reply is a [`MiniRedisError::ServerError`] holding the message after `ERR `.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::error::MiniRedisError;
+    /// use miniredis::response::Response;
+    ///
+    /// assert_eq!(Response::ok(), Response::from_line("OK"));
+    /// assert_eq!(Response::Integer(2), Response::from_line("2"));
+    /// assert_eq!(Response::Bulk("alice".to_string()), Response::from_line("alice"));
+    /// assert_eq!(
+    ///     Response::Error(MiniRedisError::ServerError {
+    ///         message: "The key value store is full.".to_string()
+    ///     }),
+    ///     Response::from_line("ERR The key value store is full.")
+    /// );
+    /// ```
+    pub fn from_line(line: &str) -> Self {
+        if let Some(message) = line.strip_prefix("ERR ") {
+            return Response::Error(MiniRedisError::ServerError {
+                message: message.to_string(),
+            });
+        }
+        match line {
+            "nil" => Response::Nil,
+            "OK" => Response::ok(),
+            _ => match line.parse::<i64>() {
+                Ok(n) => Response::Integer(n),
+                Err(_) => Response::Bulk(protocol::unescape(line).to_string()),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for Response {
+    /// Formats the response as a string.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - The formatter to write the response to.
+    ///
+    /// # Errors
+    ///
+    /// If the response cannot be formatted, it will return an error.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", protocol::encode_reply(self))
+    }
+}
@@ -0,0 +1,78 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+};
+
+/// A small, fast pseudo-random number generator (xorshift64*).
+///
+/// It is not cryptographically secure, it is only meant for things like
+/// picking random keys, where an external dependency would be overkill.
+pub(crate) struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Creates a new generator with a random seed.
+    ///
+    /// The seed comes from the random keys the standard library
+    /// generates for every `RandomState`.
+    pub(crate) fn new() -> Self {
+        let seed = RandomState::new().build_hasher().finish();
+        Self::with_seed(seed)
+    }
+
+    /// Creates a new generator with a fixed seed.
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - The seed. Zero is replaced, as xorshift would only ever return zero.
+    pub(crate) fn with_seed(seed: u64) -> Self {
+        Self {
+            state: if seed == 0 {
+                0x9E37_79B9_7F4A_7C15
+            } else {
+                seed
+            },
+        }
+    }
+
+    /// Returns the next random number.
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Returns a random number in `0..bound`.
+    ///
+    /// # Arguments
+    ///
+    /// * `bound` - The exclusive upper bound, which must not be zero.
+    pub(crate) fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn below_stays_within_bound() {
+        let mut rng = Rng::with_seed(7);
+
+        assert!((0..1000).all(|_| rng.below(10) < 10));
+    }
+
+    #[test]
+    fn same_seed_gives_same_sequence() {
+        let mut a = Rng::with_seed(7);
+        let mut b = Rng::with_seed(7);
+
+        assert_eq!(
+            (0..10).map(|_| a.next_u64()).collect::<Vec<u64>>(),
+            (0..10).map(|_| b.next_u64()).collect::<Vec<u64>>()
+        );
+    }
+}
@@ -1,12 +1,31 @@
+use crate::cluster::SlotMap;
+use crate::command::Command;
+use crate::engine::Engine;
 use crate::error::MiniRedisError;
+use crate::file_storage::{FileStorage, SyncMode, TornTail};
+use crate::hook::{CommandHook, ConnectionInfo};
 use crate::kv_store::KVStore;
+use crate::log::{Level, Logger, StderrLogger};
+use crate::output::{OutputBuffer, OutputLimit};
+use crate::protocol;
+use crate::replication::{self, Follower};
+use crate::response::Response;
+use crate::storage::Storage;
 use std::{
-    io::{BufRead, BufReader, Write},
-    net::{TcpListener, TcpStream},
-    sync::Arc,
-    thread,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Sender},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
+/// How long the server waits after failing to accept a connection before accepting again.
+pub(crate) const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
 /// A server that listens for client connections and handles requests.
 ///
 /// The server is responsible for accepting client connections,
@@ -21,8 +40,505 @@ use std::{
 /// server.run();
 /// ```
 pub struct Server {
+    pub(crate) address: String,
+    pub(crate) engine: Engine,
+    hooks: Arc<Vec<Box<dyn CommandHook>>>,
+    mode: ExecutionMode,
+    pub(crate) logger: Arc<dyn Logger>,
+    pub(crate) shutdown: ShutdownHandle,
+    pub(crate) output_limit: Option<OutputLimit>,
+    /// The address of the primary whose writes the server copies, if it is a replica.
+    primary: Option<String>,
+}
+
+/// How a server executes the commands of its clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExecutionMode {
+    /// The thread of every client executes its commands, and the lock of the store
+    /// lets one of them in at a time.
+    #[default]
+    Threaded,
+    /// One thread executes every command, strictly in the order they arrive, the way Redis does.
+    /// The threads of the clients only read commands, and write the replies back.
+    ///
+    /// The order of commands from different clients is then the order the thread got them in,
+    /// which makes ordering problems easier to reproduce, but commands cannot run side by side.
+    SingleWriter,
+}
+
+/// A command for the executor thread, with where to send its response.
+pub(crate) type Job = (Command, Sender<Response>);
+
+/// Builds a [`Server`] with options.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use miniredis::kv_store::KVStore;
+/// use miniredis::server::Server;
+///
+/// let server = Server::builder("127.0.0.1:6379")
+///     .storage(KVStore::with_capacity(1000))
+///     .build();
+/// server.run();
+/// ```
+pub struct ServerBuilder {
     address: String,
-    store: Arc<KVStore>,
+    storage: Option<Arc<dyn Storage>>,
+    hooks: Vec<Box<dyn CommandHook>>,
+    mode: ExecutionMode,
+    logger: Option<Arc<dyn Logger>>,
+    lcs_limit: Option<usize>,
+    output_limit: Option<OutputLimit>,
+    primary: Option<String>,
+}
+
+impl ServerBuilder {
+    /// Sets the storage backend that commands are executed against.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - The backend, instead of a [`KVStore`] of the server's own.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    /// use miniredis::server::Server;
+    ///
+    /// let builder = Server::builder("127.0.0.1:6379").storage(KVStore::new());
+    /// ```
+    pub fn storage(mut self, storage: impl Storage + 'static) -> Self {
+        self.storage = Some(Arc::new(storage));
+        self
+    }
+
+    /// Adds a hook that runs around every command, after the hooks added before it.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - The hook to add.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::command::Command;
+    /// use miniredis::hook::{CommandHook, ConnectionInfo};
+    /// use miniredis::response::Response;
+    /// use miniredis::server::Server;
+    /// use std::time::Duration;
+    ///
+    /// struct LogRequests;
+    ///
+    /// impl CommandHook for LogRequests {
+    ///     fn after(&self, client: &ConnectionInfo, command: &Command, _: &Response, elapsed: Duration) {
+    ///         println!("{:?} {} took {:?}", client.address, command.name, elapsed);
+    ///     }
+    /// }
+    ///
+    /// let builder = Server::builder("127.0.0.1:6379").hook(Box::new(LogRequests));
+    /// ```
+    pub fn hook(mut self, hook: Box<dyn CommandHook>) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    /// Hands hash slots to other nodes, so that commands on their keys are redirected
+    /// with [`MiniRedisError::Moved`] instead of executed here.
+    ///
+    /// The map runs as a hook, after the hooks added before it.
+    ///
+    /// # Arguments
+    ///
+    /// * `slots` - The slots other nodes serve.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::cluster::SlotMap;
+    /// use miniredis::server::Server;
+    ///
+    /// let builder = Server::builder("127.0.0.1:7000")
+    ///     .slots(SlotMap::new().assign(8192..=16383, "127.0.0.1:7001"));
+    /// ```
+    pub fn slots(self, slots: SlotMap) -> Self {
+        self.hook(Box::new(slots))
+    }
+
+    /// Sets how the server executes the commands of its clients.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - The execution mode, [`ExecutionMode::Threaded`] by default.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::server::{ExecutionMode, Server};
+    ///
+    /// let builder = Server::builder("127.0.0.1:6379").execution_mode(ExecutionMode::SingleWriter);
+    /// ```
+    pub fn execution_mode(mut self, mode: ExecutionMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets where the server logs what it is doing.
+    ///
+    /// # Arguments
+    ///
+    /// * `logger` - The logger, instead of a [`StderrLogger`] of [`Level::Info`] and above.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::log::{Level, StderrLogger};
+    /// use miniredis::server::Server;
+    ///
+    /// let builder = Server::builder("127.0.0.1:6379").logger(StderrLogger::new(Level::Debug));
+    /// ```
+    pub fn logger(mut self, logger: impl Logger + 'static) -> Self {
+        self.logger = Some(Arc::new(logger));
+        self
+    }
+
+    /// Sets how large a comparison `LCS` takes on before it is refused,
+    /// in cells of its table, the length of one value times the length of the other.
+    ///
+    /// # Arguments
+    ///
+    /// * `cells` - The most cells, 16777216 by default.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::server::Server;
+    ///
+    /// let builder = Server::builder("127.0.0.1:6379").lcs_limit(1 << 20);
+    /// ```
+    pub fn lcs_limit(mut self, cells: usize) -> Self {
+        self.lcs_limit = Some(cells);
+        self
+    }
+
+    /// Sets how many bytes of replies a client may leave unread before it is dropped.
+    ///
+    /// Without a limit, replies are written by the thread that executes the commands,
+    /// so a client that stops reading only ever blocks its own thread.
+    /// With one, every client gets a writer thread of its own, and a client over the limit
+    /// is disconnected, logged, and counted in `INFO stats`.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The hard and soft limits.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::server::Server;
+    ///
+    /// let builder = Server::builder("127.0.0.1:6379")
+    ///     .client_output_buffer_limit("256mb 64mb 60".parse().unwrap());
+    /// ```
+    pub fn client_output_buffer_limit(mut self, limit: OutputLimit) -> Self {
+        self.output_limit = Some(limit);
+        self
+    }
+
+    /// Makes the server a replica of another, which copies every write of the primary.
+    ///
+    /// While it runs, the server keeps a connection to the primary, and connects again
+    /// if it is lost. Each time, the data of the replica is replaced with a snapshot
+    /// of the primary, and the writes after it are executed as they come,
+    /// so that `WAIT` on the primary can count the replica once it has caught up.
+    ///
+    /// # Arguments
+    ///
+    /// * `primary` - The address of the primary.
+    ///
+    /// # Returns
+    ///
+    /// The builder.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::server::Server;
+    ///
+    /// let builder = Server::builder("127.0.0.1:6380").replica_of("127.0.0.1:6379");
+    /// ```
+    pub fn replica_of(mut self, primary: &str) -> Self {
+        self.primary = Some(primary.to_string());
+        self
+    }
+
+    /// Builds the server.
+    ///
+    /// # Returns
+    ///
+    /// A new server, which starts serving once it is run.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::server::Server;
+    ///
+    /// let server = Server::builder("127.0.0.1:6379").build();
+    /// ```
+    pub fn build(self) -> Server {
+        let storage = self
+            .storage
+            .unwrap_or_else(|| Arc::new(KVStore::new()) as Arc<dyn Storage>);
+        // Values that UNLINK deletes are dropped on this thread, away from the clients.
+        // It stops once the engine, and every clone of it, is dropped.
+        let (values, freed) = mpsc::channel::<Vec<u8>>();
+        thread::spawn(move || freed.into_iter().for_each(drop));
+        let mut engine = Engine::with_storage(storage).lazy_free(values);
+        if let Some(cells) = self.lcs_limit {
+            engine = engine.lcs_limit(cells);
+        }
+        Server {
+            address: self.address,
+            engine,
+            hooks: Arc::new(self.hooks),
+            mode: self.mode,
+            logger: self
+                .logger
+                .unwrap_or_else(|| Arc::new(StderrLogger::new(Level::Info))),
+            shutdown: ShutdownHandle::default(),
+            output_limit: self.output_limit,
+            primary: self.primary,
+        }
+    }
+}
+
+/// The engine of a server, with the hooks that run around every command it executes.
+#[derive(Clone)]
+pub(crate) struct Dispatcher {
+    engine: Engine,
+    hooks: Arc<Vec<Box<dyn CommandHook>>>,
+    /// Where to send commands for the executor thread, in [`ExecutionMode::SingleWriter`].
+    queue: Option<Sender<Job>>,
+}
+
+impl Dispatcher {
+    /// Executes a command, running the hooks before and after it.
+    ///
+    /// A command that `CLIENT PAUSE` holds waits first, blocking the thread of its client
+    /// with the connection left open.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The client that sent the command.
+    /// * `command` - The command to execute.
+    ///
+    /// # Returns
+    ///
+    /// The response to the command, or the reply of the first hook that answered it instead.
+    pub(crate) fn execute(&self, client: &ConnectionInfo, mut command: Command) -> Response {
+        self.engine.wait_while_paused(&command);
+        if self.hooks.is_empty() {
+            return self.run(command);
+        }
+        let start = Instant::now();
+        let response = self
+            .hooks
+            .iter()
+            .find_map(|hook| hook.before(client, &mut command))
+            .unwrap_or_else(|| self.run(command.clone()));
+        let elapsed = start.elapsed();
+        for hook in self.hooks.iter() {
+            hook.after(client, &command, &response, elapsed);
+        }
+        response
+    }
+
+    /// Checks whether a command may wait before it is answered, rather than be answered right away,
+    /// so the async server executes it where waiting does not hold up other clients.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command about to be executed.
+    ///
+    /// # Returns
+    ///
+    /// True if the command is `WAIT`, or `CLIENT PAUSE` holds it, false otherwise.
+    #[cfg(feature = "async")]
+    pub(crate) fn may_wait(&self, command: &Command) -> bool {
+        command.name == "WAIT" || self.engine.is_paused_for(command)
+    }
+
+    /// Executes a command on the engine, or has the executor thread execute it.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command to execute.
+    ///
+    /// # Returns
+    ///
+    /// The response to the command.
+    /// If the executor thread is gone, it is a [`MiniRedisError::StoreLocked`] error.
+    fn run(&self, command: Command) -> Response {
+        // WAIT only reads the replication offset, and waiting on the executor would hold up every client.
+        let Some(queue) = self.queue.as_ref().filter(|_| command.name != "WAIT") else {
+            return self.engine.execute(command);
+        };
+        let (reply, response) = mpsc::channel();
+        if queue.send((command, reply)).is_err() {
+            return Response::Error(MiniRedisError::StoreLocked);
+        }
+        response
+            .recv()
+            .unwrap_or(Response::Error(MiniRedisError::StoreLocked))
+    }
+
+    /// Executes a line read from a client and builds the reply.
+    ///
+    /// This is all of handling a line that does not touch the stream,
+    /// so every way of serving clients replies the same way.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The client that sent the line.
+    /// * `line` - The line read from the client.
+    ///
+    /// # Returns
+    ///
+    /// The reply to write back, without a line ending,
+    /// or None if the line holds no command and gets no reply.
+    pub(crate) fn reply(&self, client: &ConnectionInfo, line: &str) -> Option<String> {
+        Command::parse(line).map(|command| self.execute(client, command).to_string())
+    }
+
+    /// Serves a client that sent `SYNC` as a replica, until it goes away or the server shuts down.
+    ///
+    /// # Arguments
+    ///
+    /// * `acks` - What the replica sends after `SYNC`.
+    /// * `socket` - The stream of the replica, which is closed once it is served.
+    /// * `send` - Writes frames to the replica, and flushes them.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the replica was served until it went away.
+    ///
+    /// # Errors
+    ///
+    /// If the snapshot cannot be taken, or the replica cannot be written to,
+    /// it will return the error.
+    pub(crate) fn serve_replica(
+        &self,
+        acks: impl BufRead + Send,
+        socket: &TcpStream,
+        send: impl FnMut(&[u8]) -> Result<(), MiniRedisError>,
+    ) -> Result<(), MiniRedisError> {
+        replication::serve_replica(
+            self.engine.replication(),
+            self.engine.storage(),
+            acks,
+            socket,
+            send,
+        )
+    }
+}
+
+/// A handle that stops a running [`Server`] from another thread.
+///
+/// Shutting down stops the server from accepting connections, closes the connections
+/// of its clients, and waits for their threads, before [`Server::run`] returns.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use miniredis::server::Server;
+/// use std::thread;
+///
+/// let server = Server::new("127.0.0.1:6379");
+/// let shutdown = server.shutdown_handle();
+/// let running = thread::spawn(move || server.run());
+///
+/// shutdown.shutdown();
+/// running.join().unwrap().unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownHandle {
+    state: Arc<ShutdownState>,
+}
+
+/// What a server and its shutdown handles share.
+#[derive(Debug, Default)]
+struct ShutdownState {
+    requested: AtomicBool,
+    address: Mutex<Option<SocketAddr>>,
+}
+
+impl ShutdownHandle {
+    /// Asks the server to shut down.
+    ///
+    /// It returns right away. The server shuts down once it sees the request,
+    /// which is also right away if it is waiting for a connection.
+    pub fn shutdown(&self) {
+        self.state.requested.store(true, Ordering::SeqCst);
+        let address = *self
+            .state
+            .address
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(address) = address {
+            // Wake the server up from waiting for a connection.
+            let _ = TcpStream::connect(address);
+        }
+    }
+
+    /// Checks whether the server has been asked to shut down.
+    ///
+    /// # Returns
+    ///
+    /// True if [`ShutdownHandle::shutdown`] has been called.
+    pub fn is_shutdown(&self) -> bool {
+        self.state.requested.load(Ordering::SeqCst)
+    }
+
+    /// Records where the server listens, so shutting down can wake it up.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address of the listener of the server.
+    pub(crate) fn listening_on(&self, address: SocketAddr) {
+        *self
+            .state
+            .address
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(address);
+    }
 }
 
 impl Server {
@@ -44,14 +560,96 @@ impl Server {
     /// let server = Server::new("127.0.0.1:6379");
     /// ```
     pub fn new(address: &str) -> Self {
-        Self {
+        Self::with_storage(address, KVStore::new())
+    }
+
+    /// Creates a new server that keeps its data in a custom storage backend.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address to listen on.
+    /// * `storage` - The backend that commands are executed against.
+    ///
+    /// # Returns
+    ///
+    /// A new server.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    /// use miniredis::server::Server;
+    /// use miniredis::storage::LoggingStorage;
+    ///
+    /// let server = Server::with_storage("127.0.0.1:6379", LoggingStorage::new(KVStore::new()));
+    /// ```
+    pub fn with_storage(address: &str, storage: impl Storage + 'static) -> Self {
+        Self::builder(address).storage(storage).build()
+    }
+
+    /// Creates a builder for a server with options, like hooks that run around every command.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address to listen on.
+    ///
+    /// # Returns
+    ///
+    /// A builder with every option at its default.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::server::Server;
+    ///
+    /// let server = Server::builder("127.0.0.1:6379").build();
+    /// ```
+    pub fn builder(address: &str) -> ServerBuilder {
+        ServerBuilder {
             address: address.to_string(),
-            store: Arc::new(KVStore::new()),
+            storage: None,
+            hooks: Vec::new(),
+            mode: ExecutionMode::default(),
+            logger: None,
+            lcs_limit: None,
+            output_limit: None,
+            primary: None,
         }
     }
 
+    /// Gets a handle that shuts the server down from another thread.
+    ///
+    /// # Returns
+    ///
+    /// A handle to shut the server down with.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::server::Server;
+    ///
+    /// let server = Server::new("127.0.0.1:6379");
+    /// let shutdown = server.shutdown_handle();
+    ///
+    /// assert!(!shutdown.is_shutdown());
+    /// ```
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
     /// Creates a new server from command line arguments.
     ///
+    /// The first argument that is not an option is the address to listen on.
+    /// `--storage file --dir <DIR>` keeps the data in a [`FileStorage`] log in `DIR`
+    /// instead of in memory, and `--fsync always|never` picks its [`SyncMode`].
+    /// `--aof-load-truncated no` refuses to start if the last record of the log is torn,
+    /// instead of cutting it off with a warning, as [`TornTail`] describes.
+    /// `--execution threaded|single-writer` picks the [`ExecutionMode`],
+    /// and `--loglevel debug|info|warning` the least important [`Level`] to log.
+    /// `--slots <SPEC>` hands hash slots to other nodes, as parsed by [`SlotMap`].
+    /// `--lcs-limit <CELLS>` sets the largest comparison `LCS` takes on.
+    /// `--replicaof <ADDRESS>` makes the server a replica of the primary at `ADDRESS`.
+    ///
     /// # Arguments
     ///
     /// * `args` - The command line arguments.
@@ -60,61 +658,448 @@ impl Server {
     ///
     /// A new server.
     ///
+    /// # Errors
+    ///
+    /// If an option is unknown or misses its value, or the storage cannot be opened,
+    /// it will return an error.
+    ///
     /// # Examples
     ///
-    /// ```rust,no_run
-    /// use miniredis::server::Server;
+    /// ```rust,no_run
+    /// use miniredis::server::Server;
+    ///
+    /// let server = Server::from_args(&["miniredis".to_string(), "127.0.0.1:6379".to_string()]).unwrap();
+    /// server.run();
+    /// ```
+    pub fn from_args(args: &[String]) -> Result<Self, MiniRedisError> {
+        let mut address = None;
+        let mut storage = "memory";
+        let mut dir = None;
+        let mut sync = SyncMode::Always;
+        let mut torn = TornTail::default();
+        let mut mode = ExecutionMode::default();
+        let mut level = Level::Info;
+        let mut slots = None;
+        let mut lcs_limit = None;
+        let mut output_limit = None;
+        let mut primary = None;
+
+        let mut args_iter = args.iter().skip(1);
+        while let Some(arg) = args_iter.next() {
+            let mut value = |option: &String| {
+                args_iter
+                    .next()
+                    .ok_or_else(|| MiniRedisError::InvalidArguments {
+                        arguments: vec![option.clone()],
+                    })
+            };
+            match arg.as_str() {
+                "--storage" => storage = value(arg)?,
+                "--dir" => dir = Some(value(arg)?),
+                "--fsync" => {
+                    sync = match value(arg)?.as_str() {
+                        "always" => SyncMode::Always,
+                        "never" => SyncMode::Never,
+                        other => {
+                            return Err(MiniRedisError::InvalidArguments {
+                                arguments: vec![arg.clone(), other.to_string()],
+                            });
+                        }
+                    }
+                }
+                "--aof-load-truncated" => {
+                    torn = match value(arg)?.as_str() {
+                        "yes" => TornTail::Truncate,
+                        "no" => TornTail::Refuse,
+                        other => {
+                            return Err(MiniRedisError::InvalidArguments {
+                                arguments: vec![arg.clone(), other.to_string()],
+                            });
+                        }
+                    }
+                }
+                "--execution" => {
+                    mode = match value(arg)?.as_str() {
+                        "threaded" => ExecutionMode::Threaded,
+                        "single-writer" => ExecutionMode::SingleWriter,
+                        other => {
+                            return Err(MiniRedisError::InvalidArguments {
+                                arguments: vec![arg.clone(), other.to_string()],
+                            });
+                        }
+                    }
+                }
+                "--loglevel" => level = value(arg)?.parse()?,
+                "--slots" => slots = Some(value(arg)?.parse::<SlotMap>()?),
+                "--lcs-limit" => {
+                    let cells = value(arg)?;
+                    lcs_limit = Some(cells.parse::<usize>().map_err(|_| {
+                        MiniRedisError::InvalidArguments {
+                            arguments: vec![arg.clone(), cells.clone()],
+                        }
+                    })?);
+                }
+                "--client-output-buffer-limit" => {
+                    output_limit = Some(value(arg)?.parse::<OutputLimit>()?);
+                }
+                "--replicaof" => primary = Some(value(arg)?),
+                _ if arg.starts_with("--") => {
+                    return Err(MiniRedisError::InvalidArguments {
+                        arguments: vec![arg.clone()],
+                    });
+                }
+                _ => {
+                    if address.is_none() {
+                        address = Some(arg.as_str());
+                    }
+                }
+            }
+        }
+
+        let logger = StderrLogger::new(level);
+        let mut builder = Self::builder(address.unwrap_or("127.0.0.1:6379"))
+            .execution_mode(mode)
+            .logger(logger);
+        if let Some(slots) = slots {
+            builder = builder.slots(slots);
+        }
+        if let Some(cells) = lcs_limit {
+            builder = builder.lcs_limit(cells);
+        }
+        if let Some(limit) = output_limit {
+            builder = builder.client_output_buffer_limit(limit);
+        }
+        if let Some(primary) = primary {
+            builder = builder.replica_of(primary);
+        }
+        match (storage, dir) {
+            ("memory", None) => Ok(builder.build()),
+            ("file", Some(dir)) => {
+                let storage = FileStorage::open_with(dir, sync, torn)?;
+                if let Some(offset) = storage.truncated_at() {
+                    logger.log(
+                        Level::Warning,
+                        &format!(
+                            "Cut off a torn record at offset {} of the log in {}, left by a crash",
+                            offset, dir
+                        ),
+                    );
+                }
+                Ok(builder.storage(storage).build())
+            }
+            (storage, _) => Err(MiniRedisError::InvalidArguments {
+                arguments: vec!["--storage".to_string(), storage.to_string()],
+            }),
+        }
+    }
+
+    /// Runs the server.
+    ///
+    /// Run starts the server and listens for client connections.
+    /// When receiving a client connection, it will spawn a new thread.
+    /// It will then handle the client messages in a loop.
+    /// Each message is parsed and then executed by the engine,
+    /// and the response is written back to the client.
+    ///
+    /// Every client is logged with its address when it connects, and again when it
+    /// disconnects, with how long it stayed and how many commands it sent.
+    /// A connection that cannot be accepted, say because the server ran out of
+    /// file descriptors, is logged as a warning and the server keeps listening.
+    /// A client that fails is logged as a warning with what failed,
+    /// and a client that goes away mid-command only at the debug level.
+    ///
+    /// It runs until a [`ShutdownHandle`] of the server shuts it down.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the server was started successfully.
+    ///
+    /// # Errors
+    ///
+    /// If the server fails to bind to the address, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust, no_run
+    /// use miniredis::server::Server;
+    ///
+    /// let server = Server::new("127.0.0.1:6379");
+    /// server.run();
+    /// ```
+    pub fn run(&self) -> Result<(), MiniRedisError> {
+        let listener = TcpListener::bind(&self.address)
+            .map_err(|source| MiniRedisError::AddressNotBound { source })?;
+        println!("MiniRedis is running on {}", self.address);
+        self.serve(listener)
+    }
+
+    /// Runs the server on a thread of its own, like [`Server::run`] does on the calling thread.
+    ///
+    /// # Returns
+    ///
+    /// The thread, which ends with what [`Server::run`] returned once a [`ShutdownHandle`]
+    /// of the server shuts it down.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::server::Server;
+    ///
+    /// let server = Server::new("127.0.0.1:6379");
+    /// let shutdown = server.shutdown_handle();
+    /// let running = server.spawn();
+    ///
+    /// shutdown.shutdown();
+    /// running.join().unwrap().unwrap();
+    /// ```
+    pub fn spawn(self) -> JoinHandle<Result<(), MiniRedisError>> {
+        thread::spawn(move || self.run())
+    }
+
+    /// Serves clients on a listener that is already bound, like [`Server::run`] does
+    /// on the address of the server.
+    ///
+    /// Binding first lets the caller listen on port 0 and learn the port it got,
+    /// and connect to the server before it is serving.
+    ///
+    /// # Arguments
+    ///
+    /// * `listener` - The listener to accept clients on.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the server ran until it was shut down.
+    ///
+    /// # Errors
+    ///
+    /// If the address of the listener cannot be read, it will return an error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::server::Server;
+    /// use std::net::TcpListener;
+    ///
+    /// let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    /// println!("Listening on {}", listener.local_addr().unwrap());
+    /// Server::new("127.0.0.1:0").serve(listener).unwrap();
+    /// ```
+    pub fn serve(&self, listener: TcpListener) -> Result<(), MiniRedisError> {
+        let local_address = listener.local_addr().map_err(|source| MiniRedisError::Io {
+            operation: "read the address of the listener",
+            address: None,
+            source,
+        })?;
+        self.shutdown.listening_on(local_address);
+
+        let (queue, executor) = self.start_executor();
+        let follower = self.follow();
+        let mut clients: Vec<(TcpStream, JoinHandle<()>)> = Vec::new();
+        let mut next_id = 0;
+        while !self.shutdown.is_shutdown() {
+            let stream = match listener.accept().map(|(stream, _)| stream) {
+                Ok(stream) => stream,
+                Err(source) => {
+                    let error = MiniRedisError::Io {
+                        operation: "accept a connection",
+                        address: Some(self.address.clone()),
+                        source,
+                    };
+                    self.logger.log(Level::Warning, &error.to_string());
+                    // Give a server out of file descriptors the time to close some.
+                    thread::sleep(ACCEPT_BACKOFF);
+                    continue;
+                }
+            };
+            if self.shutdown.is_shutdown() {
+                break;
+            }
+            next_id += 1;
+            // Some platforms cannot always tell the address, which is no reason to turn the client away.
+            let info = ConnectionInfo {
+                id: next_id,
+                address: stream.peer_addr().ok().map(|address| address.to_string()),
+            };
+            // Keep a clone of every stream, to close it when shutting down.
+            let client = match stream.try_clone() {
+                Ok(client) => client,
+                Err(source) => {
+                    let error = MiniRedisError::Io {
+                        operation: "clone the stream",
+                        address: info.address.clone(),
+                        source,
+                    };
+                    self.logger.log(Level::Warning, &error.to_string());
+                    continue;
+                }
+            };
+            let dispatcher = self.accept(&info, &queue);
+            let logger = Arc::clone(&self.logger);
+            let limit = self.output_limit;
+            let handle = thread::spawn(move || {
+                Self::serve_client(stream, dispatcher, info, limit, logger.as_ref())
+            });
+            clients.retain(|(_, handle)| !handle.is_finished());
+            clients.push((client, handle));
+        }
+
+        for (client, _) in &clients {
+            let _ = client.shutdown(Shutdown::Both);
+        }
+        // A client held by CLIENT PAUSE or WAIT only sees its stream closed once it is let go.
+        self.engine.unpause();
+        self.engine.replication().close();
+        for (_, handle) in clients {
+            let _ = handle.join();
+        }
+        if let Some(follower) = follower {
+            follower.stop();
+        }
+        // The executor ends once the last sender of commands is gone.
+        drop(queue);
+        if let Some(executor) = executor {
+            let _ = executor.join();
+        }
+        Ok(())
+    }
+
+    /// Handles a client connection until it ends, and logs how it went.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The client stream.
+    /// * `dispatcher` - The engine executing the commands, with the hooks of the server.
+    /// * `client` - What the server knows about the client.
+    /// * `limit` - How many bytes of replies the client may leave unread, if there is a limit.
+    /// * `logger` - Where to log the end of the connection.
+    fn serve_client(
+        stream: TcpStream,
+        dispatcher: Dispatcher,
+        client: ConnectionInfo,
+        limit: Option<OutputLimit>,
+        logger: &dyn Logger,
+    ) {
+        let start = Instant::now();
+        let mut served = 0;
+        let engine = dispatcher.engine.clone();
+        let result = Self::handle_client(stream, dispatcher, &client, limit, &mut served);
+        Self::log_closed(&engine, logger, &client, result, start, served);
+    }
+
+    /// Starts the executor thread, if the server executes commands in [`ExecutionMode::SingleWriter`].
+    ///
+    /// # Returns
+    ///
+    /// Where to send commands for the executor, and the executor, or None for both if
+    /// the threads of the clients execute their own commands.
+    pub(crate) fn start_executor(&self) -> (Option<Sender<Job>>, Option<JoinHandle<()>>) {
+        match self.mode {
+            ExecutionMode::Threaded => (None, None),
+            ExecutionMode::SingleWriter => {
+                let (queue, executor) = Self::spawn_executor(self.engine.clone());
+                (Some(queue), Some(executor))
+            }
+        }
+    }
+
+    /// Starts copying the writes of the primary, if the server is a replica.
+    ///
+    /// # Returns
     ///
-    /// let server = Server::from_args(&["miniredis".to_string(), "127.0.0.1:6379".to_string()]);
-    /// server.run();
-    /// ```
-    pub fn from_args(args: &[String]) -> Self {
-        let address = if args.len() > 1 {
-            &args[1]
-        } else {
-            "127.0.0.1:6379"
-        };
-        Self::new(address)
+    /// The thread that copies them, which runs until it is stopped, or None if there is no primary.
+    pub(crate) fn follow(&self) -> Option<Follower> {
+        let primary = self.primary.clone()?;
+        Some(Follower::start(
+            primary,
+            self.engine.clone(),
+            Arc::clone(&self.logger),
+        ))
     }
 
-    /// Runs the server.
+    /// Logs and counts a client that was accepted, and gets what executes its commands.
     ///
-    /// Run starts the server and listens for client connections.
-    /// When receiving a client connection, it will spawn a new thread.
-    /// It will then handle the client messages in a loop.
-    /// Each message is parsed and then executed through the key value store,
-    /// and the response is written back to the client.
+    /// # Arguments
+    ///
+    /// * `client` - What the server knows about the client.
+    /// * `queue` - Where to send commands for the executor, in [`ExecutionMode::SingleWriter`].
     ///
     /// # Returns
     ///
-    /// A result indicating whether the server was started successfully.
+    /// The engine of the server, with its hooks, for the client.
+    pub(crate) fn accept(
+        &self,
+        client: &ConnectionInfo,
+        queue: &Option<Sender<Job>>,
+    ) -> Dispatcher {
+        self.logger
+            .log(Level::Info, &format!("Accepted {}", client));
+        self.engine.record_connection();
+        Dispatcher {
+            engine: self.engine.clone(),
+            hooks: Arc::clone(&self.hooks),
+            queue: queue.clone(),
+        }
+    }
+
+    /// Logs how handling a client ended, and how long it took.
     ///
-    /// # Errors
+    /// # Arguments
     ///
-    /// If the server fails to bind to the address,
-    /// read from the stream, or write to the stream, it will return an error.
+    /// * `engine` - The engine of the server, which counts clients dropped for their unread replies.
+    /// * `logger` - Where to log.
+    /// * `client` - What the server knows about the client.
+    /// * `result` - How handling the client ended.
+    /// * `start` - When the client was accepted.
+    /// * `served` - How many commands the client got replies to.
+    pub(crate) fn log_closed(
+        engine: &Engine,
+        logger: &dyn Logger,
+        client: &ConnectionInfo,
+        result: Result<(), MiniRedisError>,
+        start: Instant,
+        served: u64,
+    ) {
+        match result {
+            Ok(()) => {}
+            Err(e @ MiniRedisError::OutputBufferExceeded { .. }) => {
+                engine.record_output_buffer_disconnection();
+                logger.log(Level::Warning, &format!("Dropped {}: {}", client, e));
+            }
+            Err(e) if e.is_disconnect() => {
+                logger.log(Level::Debug, &format!("Lost {}: {}", client, e));
+            }
+            Err(e) => logger.log(Level::Warning, &format!("Failed {}: {}", client, e)),
+        }
+        logger.log(
+            Level::Info,
+            &format!(
+                "Closed {} after {:.1?} and {} commands",
+                client,
+                start.elapsed(),
+                served
+            ),
+        );
+    }
+
+    /// Starts the thread that executes every command in [`ExecutionMode::SingleWriter`].
     ///
-    /// # Examples
+    /// # Arguments
     ///
-    /// ```rust, no_run
-    /// use miniredis::server::Server;
+    /// * `engine` - The engine to execute the commands on.
     ///
-    /// let server = Server::new("127.0.0.1:6379");
-    /// server.run();
-    /// ```
-    pub fn run(&self) -> Result<(), MiniRedisError> {
-        let listener =
-            TcpListener::bind(&self.address).map_err(|_| MiniRedisError::AddressNotBound)?;
-        println!("MiniRedis is running on {}", self.address);
-
-        for stream in listener.incoming() {
-            let stream = stream.map_err(|_| MiniRedisError::StreamNotConnected {
-                address: self.address.clone(),
-            })?;
-            let store = Arc::clone(&self.store);
-            thread::spawn(move || Self::handle_client(stream, store));
-        }
-        Ok(())
+    /// # Returns
+    ///
+    /// Where to send commands for the thread, and the thread,
+    /// which ends when every sender has been dropped.
+    fn spawn_executor(engine: Engine) -> (Sender<Job>, JoinHandle<()>) {
+        let (queue, jobs) = mpsc::channel::<Job>();
+        let executor = thread::spawn(move || {
+            for (command, reply) in jobs {
+                // A client that went away no longer needs its response.
+                let _ = reply.send(engine.execute(command));
+            }
+        });
+        (queue, executor)
     }
 
     /// Prints the help message.
@@ -132,13 +1117,43 @@ impl Server {
         println!("Starts the MiniRedis server and listens for client connections.");
         println!();
         println!("USAGE:");
-        println!("    miniredis server <ADDRESS>");
+        println!("    miniredis server [OPTIONS] <ADDRESS>");
         println!();
         println!("ARGS:");
         println!("    <ADDRESS>    The address to listen on [default: 127.0.0.1:6379]");
         println!();
+        println!("OPTIONS:");
+        println!("    --storage <memory|file>    Where to keep the data [default: memory]");
+        println!("    --dir <DIR>                The data directory, required with --storage file");
+        println!(
+            "    --fsync <always|never>     Whether to sync every write to disk [default: always]"
+        );
+        println!("    --aof-load-truncated <yes|no>");
+        println!(
+            "                               Whether to cut off a torn last record of the log [default: yes]"
+        );
+        println!(
+            "    --execution <MODE>         threaded, or single-writer to run every command on one thread"
+        );
+        println!("    --loglevel <LEVEL>         debug, info or warning [default: info]");
+        println!(
+            "    --slots <SPEC>             Hash slots other nodes serve, like 8192-16383=127.0.0.1:7001"
+        );
+        println!(
+            "    --lcs-limit <CELLS>        The largest LCS comparison, in cells [default: 16777216]"
+        );
+        println!("    --client-output-buffer-limit \"<HARD> <SOFT> <SECONDS>\"");
+        println!(
+            "                               Drop clients that leave this many bytes of replies unread, like \"256mb 64mb 60\""
+        );
+        println!("    --replicaof <ADDRESS>      Copy every write of the primary at ADDRESS");
+        println!(
+            "    --async                    Serve clients on tokio tasks instead of threads, with the async feature"
+        );
+        println!();
         println!("EXAMPLES:");
         println!("    miniredis server 127.0.0.1:6379");
+        println!("    miniredis server --storage file --dir /var/lib/miniredis");
         println!("    miniredis server --help");
     }
 
@@ -147,10 +1162,31 @@ impl Server {
     /// handle_client reads commands from a stream, parses them,
     /// executes them, and writes the responses back to the stream.
     ///
+    /// Every command is either a line of the inline protocol or a RESP array,
+    /// told apart by its first byte, and is replied to in the protocol it was sent in.
+    /// A command that arrives in pieces is executed once all of it is there,
+    /// and one cut off by the client going away is not executed at all.
+    /// A RESP frame that cannot be parsed gets an error reply, and ends the connection,
+    /// since the rest of the stream can no longer be split into commands.
+    ///
+    /// Replies are buffered, and written in one go once every command the client has sent
+    /// so far is answered, so a pipelined batch gets its replies together.
+    /// The replies are flushed before waiting for more commands, so a reply that could not
+    /// be written ends the connection instead of being taken for sent.
+    ///
+    /// With a limit on the replies the client leaves unread, a writer thread writes them
+    /// instead, and a client over the limit has its stream closed.
+    ///
+    /// A client that sends `SYNC` in RESP is a replica, and is sent the writes
+    /// of the server from then on, instead of replies.
+    ///
     /// # Arguments
     ///
     /// * `stream` - The client stream.
-    /// * `store` - The shared key-value store.
+    /// * `dispatcher` - The engine executing the commands, with the hooks of the server.
+    /// * `client` - What the server knows about the client.
+    /// * `limit` - How many bytes of replies the client may leave unread, if there is a limit.
+    /// * `served` - Counts the commands the client got replies to.
     ///
     /// # Returns
     ///
@@ -158,136 +1194,246 @@ impl Server {
     ///
     /// # Errors
     ///
-    /// If the stream is not readable, writable, or closed, it will return an error.
-    fn handle_client(mut stream: TcpStream, store: Arc<KVStore>) -> Result<(), MiniRedisError> {
+    /// If the stream cannot be cloned, read from, or written to,
+    /// it will return [`MiniRedisError::Io`] with what failed and the address of the client,
+    /// and if the client is over the limit, [`MiniRedisError::OutputBufferExceeded`].
+    fn handle_client(
+        stream: TcpStream,
+        dispatcher: Dispatcher,
+        client: &ConnectionInfo,
+        limit: Option<OutputLimit>,
+        served: &mut u64,
+    ) -> Result<(), MiniRedisError> {
         let mut reader = BufReader::new(
             stream
                 .try_clone()
-                .map_err(|_| MiniRedisError::StreamClosed)?,
+                .map_err(io_error(client, "clone the stream"))?,
         );
+        let mut replies = match limit {
+            None => Replies::Direct(BufWriter::new(stream)),
+            Some(limit) => Replies::buffered(stream, limit),
+        };
+        let result = Self::answer(&mut reader, &mut replies, &dispatcher, client, served);
+        if result.is_err() {
+            // Unblocks a writer stuck on a client that does not read.
+            let _ = reader.get_ref().shutdown(Shutdown::Both);
+        }
+        replies.finish(client, result)
+    }
 
+    /// Answers the commands of a client until it stops sending them, for [`Server::handle_client`].
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The client stream to read the commands from.
+    /// * `replies` - Where to write the replies.
+    /// * `dispatcher` - The engine executing the commands, with the hooks of the server.
+    /// * `client` - What the server knows about the client.
+    /// * `served` - Counts the commands the client got replies to.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether every command was answered.
+    ///
+    /// # Errors
+    ///
+    /// If the stream cannot be read from, or a reply cannot be written,
+    /// it will return the error.
+    fn answer(
+        reader: &mut BufReader<TcpStream>,
+        replies: &mut Replies,
+        dispatcher: &Dispatcher,
+        client: &ConnectionInfo,
+        served: &mut u64,
+    ) -> Result<(), MiniRedisError> {
         let mut line = String::new();
 
         loop {
-            line.clear();
-            if reader
-                .read_line(&mut line)
-                .map_err(|_| MiniRedisError::StreamNotReadable)?
-                == 0
-            {
-                break;
+            // Only a command that is not all there yet can make the next read wait.
+            if !reader.buffer().ends_with(b"\n") {
+                replies.flush(client)?;
             }
 
-            let (command, args) = match Self::parse_command(&line) {
-                Some((command, args)) => (command, args),
-                None => continue,
-            };
+            let first_byte = reader
+                .fill_buf()
+                .map_err(io_error(client, "read the command"))?
+                .first()
+                .copied();
 
-            let response = match Self::handle_command(&command, args, &store) {
-                Ok(response) => response,
-                Err(e) => e.to_string(),
+            let response = match first_byte {
+                None => break,
+                Some(protocol::RESP_REQUEST) => match protocol::read_resp_command(reader) {
+                    Ok(Some(command)) if command.name == "SYNC" => {
+                        replies.flush(client)?;
+                        let socket = reader
+                            .get_ref()
+                            .try_clone()
+                            .map_err(io_error(client, "clone the stream"))?;
+                        return dispatcher.serve_replica(reader, &socket, |frames| {
+                            replies.write(client, frames)?;
+                            replies.flush(client)
+                        });
+                    }
+                    Ok(command) => command.map(|command| {
+                        protocol::encode_resp_reply(&dispatcher.execute(client, command))
+                    }),
+                    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                    Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                        replies.write(client, format!("-ERR {}\r\n", e).as_bytes())?;
+                        break;
+                    }
+                    Err(source) => return Err(io_error(client, "read the command")(source)),
+                },
+                Some(_) => {
+                    line.clear();
+                    reader
+                        .read_line(&mut line)
+                        .map_err(io_error(client, "read the command"))?;
+                    if !line.ends_with('\n') {
+                        // The client went away halfway through the command.
+                        break;
+                    }
+                    dispatcher
+                        .reply(client, &line)
+                        .map(|response| (response + "\n").into_bytes())
+                }
             };
 
-            stream
-                .write_all(response.as_bytes())
-                .map_err(|_| MiniRedisError::StreamNotWritable)?;
-            stream
-                .write_all(b"\n")
-                .map_err(|_| MiniRedisError::StreamNotWritable)?;
+            if let Some(response) = response {
+                *served += 1;
+                replies.write(client, &response)?;
+            }
         }
         Ok(())
     }
+}
+
+/// Makes an IO error of a client stream into a [`MiniRedisError::Io`].
+///
+/// # Arguments
+///
+/// * `client` - The client of the stream.
+/// * `operation` - What failed.
+///
+/// # Returns
+///
+/// A function that wraps the IO error with what failed and the address of the client.
+pub(crate) fn io_error(
+    client: &ConnectionInfo,
+    operation: &'static str,
+) -> impl FnOnce(io::Error) -> MiniRedisError {
+    let address = client.address.clone();
+    move |source| MiniRedisError::Io {
+        operation,
+        address,
+        source,
+    }
+}
+
+/// Where the replies to a client go.
+enum Replies {
+    /// Straight to the stream, by the thread that executes the commands.
+    Direct(BufWriter<TcpStream>),
+    /// Into a buffer with a limit, that a writer thread of the client empties into the stream.
+    Buffered {
+        buffer: Arc<OutputBuffer>,
+        writer: JoinHandle<io::Result<()>>,
+    },
+}
 
-    /// Parses a command from a stream.
+impl Replies {
+    /// Starts a writer thread for the replies to a client.
     ///
     /// # Arguments
     ///
-    /// * `line` - The line to read the command from.
+    /// * `stream` - The client stream, for the writer.
+    /// * `limit` - How many bytes of replies the client may leave unread.
     ///
     /// # Returns
     ///
-    /// A optional tuple containing the command and its arguments.
-    /// If the command is empty or the line is empty, None is returned.
-    fn parse_command(line: &str) -> Option<(String, Vec<String>)> {
-        let mut parts = line.split_whitespace();
-        let command = match parts.next() {
-            Some(command) => command.to_uppercase(),
-            None => return None,
+    /// The replies, which go to the writer.
+    fn buffered(stream: TcpStream, limit: OutputLimit) -> Self {
+        let buffer = Arc::new(OutputBuffer::new(limit));
+        let writer = {
+            let buffer = Arc::clone(&buffer);
+            thread::spawn(move || buffer.write_to(stream))
         };
-        let args = parts.map(|s| s.to_string()).collect::<Vec<String>>();
-        Some((command, args))
+        Replies::Buffered { buffer, writer }
+    }
+
+    /// Writes a reply, or queues it for the writer.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The client the reply is for.
+    /// * `reply` - The reply.
+    ///
+    /// # Errors
+    ///
+    /// If the reply cannot be written, it will return [`MiniRedisError::Io`],
+    /// and if the client is over the limit, [`MiniRedisError::OutputBufferExceeded`].
+    fn write(&mut self, client: &ConnectionInfo, reply: &[u8]) -> Result<(), MiniRedisError> {
+        match self {
+            Replies::Direct(writer) => writer
+                .write_all(reply)
+                .map_err(io_error(client, "write the reply")),
+            Replies::Buffered { buffer, .. } => buffer.push(reply),
+        }
+    }
+
+    /// Writes the replies so far, which the writer does on its own.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The client the replies are for.
+    ///
+    /// # Errors
+    ///
+    /// If the replies cannot be written, it will return [`MiniRedisError::Io`].
+    fn flush(&mut self, client: &ConnectionInfo) -> Result<(), MiniRedisError> {
+        match self {
+            Replies::Direct(writer) => writer
+                .flush()
+                .map_err(io_error(client, "flush the replies")),
+            Replies::Buffered { .. } => Ok(()),
+        }
     }
 
-    /// Handles a command.
+    /// Writes the last replies once the client is answered, and waits for the writer.
     ///
     /// # Arguments
     ///
-    /// * `command` - The command to handle.
-    /// * `args` - The arguments to the command.
-    /// * `store` - The shared key-value store.
+    /// * `client` - The client the replies are for.
+    /// * `answered` - How answering the client went.
     ///
     /// # Returns
     ///
-    /// A string containing the response to the command.
-    /// Can either be an error message or a response to the command.
+    /// How answering the client went, or how writing the last replies failed.
     ///
     /// # Errors
     ///
-    /// If the command is invalid, the arguments are invalid,
-    /// or the key is not found, it will return an error.
-    fn handle_command(
-        command: &str,
-        args: Vec<String>,
-        store: &Arc<KVStore>,
-    ) -> Result<String, MiniRedisError> {
-        let key: Option<&String> = args.get(0);
-        let value: Option<&String> = args.get(1);
-        let args_len = args.len();
-
-        match command {
-            "GET" => {
-                if args_len != 1 {
-                    return Err(MiniRedisError::InvalidArguments { arguments: args });
-                }
-                match key {
-                    Some(key) => match store.get(key) {
-                        Ok(Some(value)) => Ok(value),
-                        Ok(None) => Ok("nil".to_string()),
-                        Err(e) => Err(e),
-                    },
-                    None => Err(MiniRedisError::InvalidArguments { arguments: args }),
-                }
-            }
-            "SET" => {
-                if args_len != 2 {
-                    return Err(MiniRedisError::InvalidArguments { arguments: args });
-                }
-                match key {
-                    Some(key) => match value {
-                        Some(value) => {
-                            store.set(key, value)?;
-                            Ok("OK".to_string())
-                        }
-                        None => Err(MiniRedisError::InvalidArguments { arguments: args }),
-                    },
-                    None => Err(MiniRedisError::InvalidArguments { arguments: args }),
-                }
+    /// If answering the client failed, it will return that error, and otherwise
+    /// [`MiniRedisError::Io`] if the replies cannot be written.
+    fn finish(
+        self,
+        client: &ConnectionInfo,
+        answered: Result<(), MiniRedisError>,
+    ) -> Result<(), MiniRedisError> {
+        match self {
+            Replies::Direct(mut writer) => {
+                answered?;
+                writer
+                    .flush()
+                    .map_err(io_error(client, "flush the replies"))
             }
-            "DEL" => {
-                if args_len != 1 {
-                    return Err(MiniRedisError::InvalidArguments { arguments: args });
-                }
-                match key {
-                    Some(key) => {
-                        store.del(key)?;
-                        Ok("OK".to_string())
-                    }
-                    None => Err(MiniRedisError::InvalidArguments { arguments: args }),
-                }
+            Replies::Buffered { buffer, writer } => {
+                buffer.close();
+                let written = writer
+                    .join()
+                    .unwrap_or_else(|_| Err(io::ErrorKind::Other.into()));
+                answered?;
+                written.map_err(io_error(client, "write the reply"))
             }
-            _ => Err(MiniRedisError::InvalidCommand {
-                command: command.to_string(),
-            }),
         }
     }
 }
@@ -306,281 +1452,537 @@ mod tests {
     #[test]
     fn new_creates_server_with_empty_store() {
         let server = Server::new("127.0.0.1:0");
-        assert!(server.store.get("nonexistent_key").unwrap().is_none());
+        assert_eq!(Ok(0), server.engine.storage().len());
     }
 
     #[test]
-    fn from_args_uses_default_address_when_no_args_provided() {
-        let args = vec!["miniredis".to_string()];
-        let server = Server::from_args(&args);
-        assert_eq!("127.0.0.1:6379", server.address);
-    }
+    fn with_storage_executes_commands_against_given_backend() {
+        let store = KVStore::new();
+        let server = Server::with_storage("127.0.0.1:0", store.clone());
 
-    #[test]
-    fn from_args_uses_provided_address_when_args_given() {
-        let expected_address = "localhost:9999";
-        let args = vec!["miniredis".to_string(), expected_address.to_string()];
-        let server = Server::from_args(&args);
-        assert_eq!(expected_address.to_string(), server.address);
+        server.engine.execute(Command::new(
+            "SET",
+            vec!["testkey".to_string(), "testvalue".to_string()],
+        ));
+
+        assert_eq!(Some("testvalue".to_string()), store.get("testkey").unwrap());
     }
 
-    #[test]
-    fn from_args_uses_first_argument_as_address() {
-        let expected_address = "test.example.com:1234";
-        let args = vec![
-            "miniredis".to_string(),
-            expected_address.to_string(),
-            "ignored_arg".to_string(),
-        ];
-        let server = Server::from_args(&args);
-        assert_eq!(expected_address.to_string(), server.address);
+    /// Creates a dispatcher on a fresh store, with the given hooks.
+    fn dispatcher(hooks: Vec<Box<dyn CommandHook>>) -> Dispatcher {
+        Dispatcher {
+            engine: Engine::with_store(Arc::new(KVStore::new())),
+            hooks: Arc::new(hooks),
+            queue: None,
+        }
     }
-    #[test]
-    fn parse_command_parses_get_command() {
-        let line = "GET mykey\n";
-        let result = Server::parse_command(line);
-        assert_eq!(Some(("GET".to_string(), vec!["mykey".to_string()])), result);
+
+    /// A client as the server would see it.
+    fn info() -> ConnectionInfo {
+        ConnectionInfo {
+            id: 1,
+            address: Some("127.0.0.1:50000".to_string()),
+        }
     }
 
     #[test]
-    fn parse_command_parses_set_command() {
-        let line = "SET mykey myvalue\n";
-        let result = Server::parse_command(line);
+    fn reply_executes_the_command_on_a_line() {
+        let dispatcher = dispatcher(Vec::new());
+
         assert_eq!(
-            Some((
-                "SET".to_string(),
-                vec!["mykey".to_string(), "myvalue".to_string()]
-            )),
-            result
+            Some("OK".to_string()),
+            dispatcher.reply(&info(), "SET a 1\r\n")
         );
+        assert_eq!(Some("1".to_string()), dispatcher.reply(&info(), "GET a\n"));
     }
 
     #[test]
-    fn parse_command_parses_del_command() {
-        let line = "DEL mykey\n";
-        let result = Server::parse_command(line);
-        assert_eq!(Some(("DEL".to_string(), vec!["mykey".to_string()])), result);
+    fn single_writer_executes_commands_from_many_threads_in_order() {
+        let mut dispatcher = dispatcher(Vec::new());
+        let (queue, executor) = Server::spawn_executor(dispatcher.engine.clone());
+        dispatcher.queue = Some(queue);
+
+        let writers = (0..4)
+            .map(|writer| {
+                let dispatcher = dispatcher.clone();
+                thread::spawn(move || {
+                    for i in 0..50 {
+                        let key = format!("{}:{}", writer, i);
+                        assert_eq!(
+                            Some("OK".to_string()),
+                            dispatcher.reply(&info(), &format!("SET {} {}", key, i))
+                        );
+                        assert_eq!(
+                            Some(i.to_string()),
+                            dispatcher.reply(&info(), &format!("GET {}", key))
+                        );
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        assert_eq!(Ok(200), dispatcher.engine.storage().len());
+        drop(dispatcher);
+        executor.join().unwrap();
     }
 
     #[test]
-    fn parse_command_handles_lowercase_commands() {
-        let line = "get mykey\n";
-        let result = Server::parse_command(line);
-        assert_eq!(Some(("GET".to_string(), vec!["mykey".to_string()])), result);
+    fn reply_skips_lines_without_a_command() {
+        let dispatcher = dispatcher(Vec::new());
+
+        assert_eq!(None, dispatcher.reply(&info(), "   \n"));
+    }
+
+    /// Prefixes the first argument of every command, and refuses `DELPREFIX`.
+    struct Rewrite;
+
+    impl CommandHook for Rewrite {
+        fn before(&self, client: &ConnectionInfo, command: &mut Command) -> Option<Response> {
+            if command.name == "DELPREFIX" {
+                return Some(Response::Error(MiniRedisError::InvalidCommand {
+                    command: command.name.clone(),
+                }));
+            }
+            if let Some(key) = command.args.first_mut() {
+                *key = format!("{}:{}", client.id, key);
+            }
+            None
+        }
+    }
+
+    /// Records every command it sees, in the place it has among the hooks.
+    struct Record(&'static str, Arc<std::sync::Mutex<Vec<String>>>);
+
+    impl CommandHook for Record {
+        fn before(&self, _: &ConnectionInfo, command: &mut Command) -> Option<Response> {
+            self.1
+                .lock()
+                .unwrap()
+                .push(format!("{} before {}", self.0, command));
+            None
+        }
+
+        fn after(&self, _: &ConnectionInfo, command: &Command, response: &Response, _: Duration) {
+            self.1
+                .lock()
+                .unwrap()
+                .push(format!("{} after {} {}", self.0, command, response));
+        }
     }
 
     #[test]
-    fn parse_command_handles_mixed_case_commands() {
-        let line = "GeT mykey\n";
-        let result = Server::parse_command(line);
-        assert_eq!(Some(("GET".to_string(), vec!["mykey".to_string()])), result);
+    fn hooks_run_in_order_around_every_command() {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let dispatcher = dispatcher(vec![
+            Box::new(Record("first", Arc::clone(&seen))),
+            Box::new(Rewrite),
+            Box::new(Record("last", Arc::clone(&seen))),
+        ]);
+
+        dispatcher.reply(&info(), "SET a 1");
+
+        assert_eq!(
+            Ok(Some("1".to_string())),
+            dispatcher.engine.storage().get("1:a")
+        );
+        assert_eq!(
+            vec![
+                "first before SET a 1",
+                "last before SET 1:a 1",
+                "first after SET 1:a 1 OK",
+                "last after SET 1:a 1 OK",
+            ],
+            *seen.lock().unwrap()
+        );
     }
 
     #[test]
-    fn parse_command_handles_extra_whitespace() {
-        let line = "  SET   mykey   myvalue  \n";
-        let result = Server::parse_command(line);
+    fn a_hook_can_answer_a_command_instead_of_the_engine() {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let dispatcher = dispatcher(vec![
+            Box::new(Rewrite),
+            Box::new(Record("after", Arc::clone(&seen))),
+        ]);
+        dispatcher.engine.storage().set("user:1", "alice").unwrap();
+
+        let reply = dispatcher.reply(&info(), "DELPREFIX user:").unwrap();
+
+        assert!(
+            reply.starts_with("ERR Invalid command: DELPREFIX."),
+            "{}",
+            reply
+        );
         assert_eq!(
-            Some((
-                "SET".to_string(),
-                vec!["mykey".to_string(), "myvalue".to_string()]
-            )),
-            result
+            Ok(Some("alice".to_string())),
+            dispatcher.engine.storage().get("user:1")
         );
+        assert_eq!(1, seen.lock().unwrap().len());
     }
 
     #[test]
-    fn parse_command_returns_none_for_empty_line() {
-        let line = "\n";
-        let result = Server::parse_command(line);
-        assert_eq!(None, result);
+    fn handle_client_answers_a_pipelined_batch_in_order() {
+        // Written by the thread that executes the commands, and by a writer of their own.
+        for limit in [None, Some(OutputLimit::default())] {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            let (stream, _) = listener.accept().unwrap();
+            let handler = thread::spawn(move || {
+                Server::handle_client(stream, dispatcher(Vec::new()), &info(), limit, &mut 0)
+            });
+
+            client
+                .write_all(b"SET a 1\nGET a\n*2\r\n$3\r\nGET\r\n$1\r\na\r\nGET b\nGE")
+                .unwrap();
+            let mut reader = BufReader::new(client.try_clone().unwrap());
+            let mut replies = String::new();
+            for _ in 0..5 {
+                reader.read_line(&mut replies).unwrap();
+            }
+            // The rest of a command sent in pieces is still answered.
+            client.write_all(b"T a\n").unwrap();
+            reader.read_line(&mut replies).unwrap();
+
+            assert_eq!("OK\n1\n$1\r\n1\r\nnil\n1\n", replies, "{:?}", limit);
+            client.shutdown(Shutdown::Both).unwrap();
+            assert!(handler.join().unwrap().is_ok(), "{:?}", limit);
+        }
     }
 
     #[test]
-    fn parse_command_returns_none_for_whitespace_only() {
-        let line = "   \n";
-        let result = Server::parse_command(line);
-        assert_eq!(None, result);
+    fn handle_client_skips_a_command_cut_off_by_the_client() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        client.write_all(b"SET a 1").unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+        let dispatcher = dispatcher(Vec::new());
+
+        let result = Server::handle_client(stream, dispatcher.clone(), &info(), None, &mut 0);
+
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(Ok(None), dispatcher.engine.storage().get("a"));
     }
 
     #[test]
-    fn handle_command_get_returns_value_when_key_exists() {
-        let store = Arc::new(KVStore::new());
-        store.set("testkey", "testvalue").unwrap();
+    fn handle_client_ends_when_the_client_goes_away() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        client.write_all(b"SET a 1\n").unwrap();
+        drop(client);
 
-        let response = Server::handle_command("GET", vec!["testkey".to_string()], &store);
-        assert_eq!("testvalue", response.unwrap());
+        let result = Server::handle_client(stream, dispatcher(Vec::new()), &info(), None, &mut 0);
+
+        assert!(
+            result
+                .as_ref()
+                .map_or_else(MiniRedisError::is_disconnect, |_| true),
+            "{:?}",
+            result
+        );
     }
 
     #[test]
-    fn handle_command_get_returns_nil_when_key_does_not_exist() {
-        let store = Arc::new(KVStore::new());
+    fn spawn_runs_the_server_until_it_is_shut_down() {
+        let server = Server::new("127.0.0.1:0");
+        let shutdown = server.shutdown_handle();
+        let running = server.spawn();
+
+        shutdown.shutdown();
 
-        let response = Server::handle_command("GET", vec!["nonexistent".to_string()], &store);
-        assert_eq!("nil", response.unwrap());
+        assert!(running.join().unwrap().is_ok());
     }
 
     #[test]
-    fn handle_command_get_returns_error_with_no_arguments() {
-        let store = Arc::new(KVStore::new());
+    fn from_args_uses_default_address_when_no_args_provided() {
+        let args = vec!["miniredis".to_string()];
+        let server = Server::from_args(&args).unwrap();
+        assert_eq!("127.0.0.1:6379", server.address);
+    }
 
-        let response = Server::handle_command("GET", vec![], &store);
-        assert!(response.is_err());
+    #[test]
+    fn from_args_uses_provided_address_when_args_given() {
+        let expected_address = "localhost:9999";
+        let args = vec!["miniredis".to_string(), expected_address.to_string()];
+        let server = Server::from_args(&args).unwrap();
+        assert_eq!(expected_address.to_string(), server.address);
     }
 
     #[test]
-    fn handle_command_set_stores_value_and_returns_ok() {
-        let store = Arc::new(KVStore::new());
+    fn from_args_uses_first_argument_as_address() {
+        let expected_address = "test.example.com:1234";
+        let args = vec![
+            "miniredis".to_string(),
+            expected_address.to_string(),
+            "ignored_arg".to_string(),
+        ];
+        let server = Server::from_args(&args).unwrap();
+        assert_eq!(expected_address.to_string(), server.address);
+    }
+    #[test]
+    fn from_args_opens_file_storage_in_given_dir() {
+        let dir = std::env::temp_dir().join(format!("miniredis-{}-server-dir", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let args = vec![
+            "miniredis".to_string(),
+            "--storage".to_string(),
+            "file".to_string(),
+            "--dir".to_string(),
+            dir.display().to_string(),
+        ];
 
-        let response = Server::handle_command(
-            "SET",
-            vec!["testkey".to_string(), "testvalue".to_string()],
-            &store,
+        let server = Server::from_args(&args).unwrap();
+        server.engine.storage().set("key", "value").unwrap();
+        drop(server);
+
+        let server = Server::from_args(&args).unwrap();
+        assert_eq!(
+            Some("value".to_string()),
+            server.engine.storage().get("key").unwrap()
         );
-        assert_eq!("OK", response.unwrap());
-        assert_eq!(Some("testvalue".to_string()), store.get("testkey").unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
     #[test]
-    fn handle_command_set_overwrites_existing_value() {
-        let store = Arc::new(KVStore::new());
-        store.set("testkey", "oldvalue").unwrap();
+    fn from_args_refuses_a_torn_log_only_with_aof_load_truncated_no() {
+        let dir =
+            std::env::temp_dir().join(format!("miniredis-{}-server-torn", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let args = ["miniredis", "--storage", "file", "--dir"]
+            .map(str::to_string)
+            .into_iter()
+            .chain([dir.display().to_string()])
+            .collect::<Vec<String>>();
+        let server = Server::from_args(&args).unwrap();
+        server.engine.storage().set("key", "value").unwrap();
+        drop(server);
+        let path = dir.join(crate::file_storage::LOG_FILE_NAME);
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
 
-        let response = Server::handle_command(
-            "SET",
-            vec!["testkey".to_string(), "newvalue".to_string()],
-            &store,
+        let strict = [
+            &args[..],
+            &["--aof-load-truncated".to_string(), "no".to_string()],
+        ]
+        .concat();
+        assert!(matches!(
+            Server::from_args(&strict).err(),
+            Some(MiniRedisError::LogCorrupted { .. })
+        ));
+        assert_eq!(
+            Ok(0),
+            Server::from_args(&args).unwrap().engine.storage().len()
         );
-        assert_eq!("OK", response.unwrap());
-        assert_eq!(Some("newvalue".to_string()), store.get("testkey").unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
     #[test]
-    fn handle_command_set_returns_error_with_no_value() {
-        let store = Arc::new(KVStore::new());
+    fn from_args_returns_error_for_file_storage_without_dir() {
+        let args = vec![
+            "miniredis".to_string(),
+            "--storage".to_string(),
+            "file".to_string(),
+        ];
+
+        assert!(Server::from_args(&args).is_err());
+    }
 
-        let response = Server::handle_command("SET", vec!["testkey".to_string()], &store);
+    #[test]
+    fn from_args_picks_the_execution_mode() {
+        let args = ["miniredis", "--execution", "single-writer"].map(str::to_string);
 
-        assert!(response.is_err());
         assert_eq!(
-            MiniRedisError::InvalidArguments {
-                arguments: vec!["testkey".to_string()]
-            },
-            response.unwrap_err()
+            ExecutionMode::SingleWriter,
+            Server::from_args(&args).unwrap().mode
+        );
+        assert_eq!(
+            ExecutionMode::Threaded,
+            Server::from_args(&args[..1]).unwrap().mode
+        );
+        assert!(
+            Server::from_args(&["miniredis", "--execution", "both"].map(str::to_string)).is_err()
         );
     }
 
     #[test]
-    fn handle_command_set_returns_error_with_no_arguments() {
-        let store = Arc::new(KVStore::new());
+    fn from_args_sets_the_lcs_limit() {
+        let args = ["miniredis", "--lcs-limit", "10"].map(str::to_string);
+        let engine = Server::from_args(&args).unwrap().engine;
+        engine.execute_str("SET a abcd");
+        engine.execute_str("SET b abc");
 
-        let response = Server::handle_command("SET", vec![], &store);
-
-        assert!(response.is_err());
         assert_eq!(
-            MiniRedisError::InvalidArguments { arguments: vec![] },
-            response.unwrap_err()
+            Response::Error(MiniRedisError::ComparisonTooLarge {
+                cells: 12,
+                limit: 10
+            }),
+            engine.execute_str("LCS a b")
+        );
+        assert_eq!(
+            Some(MiniRedisError::InvalidArguments {
+                arguments: vec!["--lcs-limit".to_string(), "many".to_string()]
+            }),
+            Server::from_args(&["miniredis", "--lcs-limit", "many"].map(str::to_string)).err()
         );
     }
 
     #[test]
-    fn handle_command_del_removes_key_and_returns_ok() {
-        let store = Arc::new(KVStore::new());
-        store.set("testkey", "testvalue").unwrap();
-
-        let response = Server::handle_command("DEL", vec!["testkey".to_string()], &store);
+    fn from_args_makes_the_server_a_replica() {
+        let args = ["miniredis", "--replicaof", "127.0.0.1:6379"].map(str::to_string);
 
-        assert_eq!("OK", response.unwrap());
-        assert_eq!(None, store.get("testkey").unwrap());
+        assert_eq!(
+            Some("127.0.0.1:6379".to_string()),
+            Server::from_args(&args).unwrap().primary
+        );
+        assert_eq!(
+            None,
+            Server::from_args(&["miniredis".to_string()])
+                .unwrap()
+                .primary
+        );
     }
 
     #[test]
-    fn handle_command_del_returns_ok_even_if_key_does_not_exist() {
-        let store = Arc::new(KVStore::new());
+    fn from_args_sets_the_client_output_buffer_limit() {
+        let args = [
+            "miniredis".to_string(),
+            "--client-output-buffer-limit".to_string(),
+            "1mb 512kb 10".to_string(),
+        ];
 
-        let response = Server::handle_command("DEL", vec!["nonexistent".to_string()], &store);
+        let server = Server::from_args(&args).unwrap();
 
-        assert_eq!("OK", response.unwrap());
+        assert_eq!(
+            Some(OutputLimit {
+                hard: 1 << 20,
+                soft: 512 << 10,
+                soft_duration: Duration::from_secs(10),
+            }),
+            server.output_limit
+        );
     }
 
     #[test]
-    fn handle_command_del_returns_error_with_no_arguments() {
-        let store = Arc::new(KVStore::new());
-
-        let response = Server::handle_command("DEL", vec![], &store);
+    fn from_args_returns_error_for_unknown_option() {
+        let args = vec!["miniredis".to_string(), "--bogus".to_string()];
 
-        assert!(response.is_err());
         assert_eq!(
-            MiniRedisError::InvalidArguments { arguments: vec![] },
-            response.unwrap_err()
+            Some(MiniRedisError::InvalidArguments {
+                arguments: vec!["--bogus".to_string()]
+            }),
+            Server::from_args(&args).err()
         );
     }
 
+    /// A logger that keeps what it is given.
+    struct Recorded(Arc<std::sync::Mutex<Vec<(Level, String)>>>);
+
+    impl Logger for Recorded {
+        fn log(&self, level: Level, message: &str) {
+            self.0.lock().unwrap().push((level, message.to_string()));
+        }
+    }
+
     #[test]
-    fn handle_command_returns_error_for_unknown_command() {
-        let store = Arc::new(KVStore::new());
+    fn serve_logs_every_client_connecting_and_disconnecting() {
+        let logged = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let server = crate::testing::TestServer::serve(
+            Server::builder("127.0.0.1:0")
+                .logger(Recorded(Arc::clone(&logged)))
+                .build(),
+        );
+        let mut client = TcpStream::connect(server.address()).unwrap();
+        let peer = client.local_addr().unwrap().to_string();
+        client.write_all(b"SET a 1\nGET a\n").unwrap();
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+        let mut reply = String::new();
+        reader.read_line(&mut reply).unwrap();
+        reader.read_line(&mut reply).unwrap();
+        drop((reader, client));
 
-        let response = Server::handle_command("UNKNOWN", vec!["arg".to_string()], &store);
+        drop(server);
 
-        assert!(response.is_err());
+        let logged = logged.lock().unwrap();
+        assert_eq!(2, logged.len(), "{:?}", logged);
         assert_eq!(
-            MiniRedisError::InvalidCommand {
-                command: "UNKNOWN".to_string()
-            },
-            response.unwrap_err()
+            (Level::Info, format!("Accepted client 1 ({})", peer)),
+            logged[0]
+        );
+        assert_eq!(Level::Info, logged[1].0);
+        assert!(
+            logged[1]
+                .1
+                .starts_with(&format!("Closed client 1 ({}) after ", peer))
+                && logged[1].1.ends_with(" and 2 commands"),
+            "{}",
+            logged[1].1
         );
     }
 
     #[test]
-    fn handle_command_returns_error_for_extra_arguments() {
-        let store = Arc::new(KVStore::new());
-
-        let response = Server::handle_command(
-            "GET",
-            vec!["testkey".to_string(), "extra".to_string()],
-            &store,
+    fn serve_drops_a_client_that_stops_reading_and_keeps_serving_the_rest() {
+        let logged = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let server = crate::testing::TestServer::serve(
+            Server::builder("127.0.0.1:0")
+                .logger(Recorded(Arc::clone(&logged)))
+                .client_output_buffer_limit(OutputLimit {
+                    hard: 1 << 20,
+                    ..OutputLimit::default()
+                })
+                .build(),
         );
-
-        assert!(response.is_err());
+        let mut stalled = TcpStream::connect(server.address()).unwrap();
+        let peer = stalled.local_addr().unwrap().to_string();
         assert_eq!(
-            MiniRedisError::InvalidArguments {
-                arguments: vec!["testkey".to_string(), "extra".to_string()]
-            },
-            response.unwrap_err()
+            "OK",
+            server.send(&format!("SET big {}", "x".repeat(64 * 1024)))
         );
 
-        let response = Server::handle_command(
-            "SET",
-            vec![
-                "testkey".to_string(),
-                "testvalue".to_string(),
-                "extra".to_string(),
-            ],
-            &store,
+        // A quarter of a gigabyte of replies, far more than the socket buffers hold.
+        let _ = stalled.write_all("GET big\n".repeat(4096).as_bytes());
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let stats = loop {
+            let stats = server.send("INFO stats");
+            if stats.ends_with("client_output_buffer_limit_disconnections\n1")
+                || Instant::now() > deadline
+            {
+                break stats;
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert!(
+            stats.ends_with("client_output_buffer_limit_disconnections\n1"),
+            "{}",
+            stats
         );
-        assert!(response.is_err());
-        assert_eq!(
-            MiniRedisError::InvalidArguments {
-                arguments: vec![
-                    "testkey".to_string(),
-                    "testvalue".to_string(),
-                    "extra".to_string()
-                ]
-            },
-            response.unwrap_err()
-        );
-
-        let response = Server::handle_command(
-            "DEL",
-            vec!["testkey".to_string(), "extra".to_string()],
-            &store,
-        );
-        assert!(response.is_err());
-        assert_eq!(
-            MiniRedisError::InvalidArguments {
-                arguments: vec!["testkey".to_string(), "extra".to_string()]
-            },
-            response.unwrap_err()
+
+        // The replies the sockets took are all the client gets before the stream ends.
+        stalled
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        let drained = io::copy(&mut stalled, &mut io::sink());
+        assert!(
+            drained.as_ref().map_or_else(
+                |e| e.kind() != io::ErrorKind::WouldBlock,
+                |read| *read < 256 << 20
+            ),
+            "{:?}",
+            drained
+        );
+        assert_eq!("PONG", server.send("PING"));
+
+        drop(server);
+        let logged = logged.lock().unwrap();
+        let dropped = format!("Dropped client 1 ({}): The client left ", peer);
+        assert!(
+            logged
+                .iter()
+                .any(|(level, message)| *level == Level::Warning && message.starts_with(&dropped)),
+            "{:?}",
+            logged
         );
     }
 }
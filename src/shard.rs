@@ -0,0 +1,624 @@
+use crate::cluster;
+use crate::command::{ArgKind, CommandInfo};
+use crate::connection::{self, Connection};
+use crate::error::MiniRedisError;
+use crate::response::Response;
+
+/// How many points every node gets on the ring, so the keys spread evenly between nodes.
+const VIRTUAL_NODES: usize = 160;
+
+/// The nodes of a sharded connection, placed at many points on a circle of hashes.
+///
+/// A key belongs to the node of the first point at or after its hash, going around.
+/// Adding a node only takes over the keys just before its own points,
+/// so about one key in the new number of nodes moves, and the rest stay where they are.
+#[derive(Debug)]
+struct Ring {
+    /// The points, sorted by hash, with the index of their node.
+    points: Vec<(u64, usize)>,
+}
+
+impl Ring {
+    /// Places every node on the ring.
+    ///
+    /// # Arguments
+    ///
+    /// * `addresses` - The addresses of the nodes, which name their points.
+    fn new(addresses: &[String]) -> Self {
+        let mut points = addresses
+            .iter()
+            .enumerate()
+            .flat_map(|(node, address)| {
+                (0..VIRTUAL_NODES)
+                    .map(move |point| (hash(format!("{}#{}", address, point).as_bytes()), node))
+            })
+            .collect::<Vec<(u64, usize)>>();
+        points.sort_unstable();
+        Ring { points }
+    }
+
+    /// Finds the node that owns a key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key. Only its hash tag is hashed, if it has one.
+    ///
+    /// # Returns
+    ///
+    /// The index of the node.
+    fn node_of(&self, key: &str) -> usize {
+        let hash = hash(cluster::hash_tag(key));
+        let point = self.points.partition_point(|(point, _)| *point < hash);
+        self.points[point % self.points.len()].1
+    }
+}
+
+/// Hashes bytes onto the ring.
+///
+/// This is 64-bit FNV-1a, with the bits mixed afterwards,
+/// since the names of the points of a node differ only in their last few bytes.
+///
+/// # Arguments
+///
+/// * `bytes` - The bytes to hash.
+///
+/// # Returns
+///
+/// The hash.
+fn hash(bytes: &[u8]) -> u64 {
+    let hash = bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    let hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    let hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+/// The commands for one node, by the index of the node,
+/// each with its position among every command sent.
+type Batch<'a> = (usize, Vec<(usize, Vec<&'a str>)>);
+
+/// A node of a sharded connection.
+struct Shard {
+    address: String,
+    /// The connection to the node, opened when it is first needed,
+    /// and closed when it fails, so the next command connects again.
+    connection: Option<Connection>,
+}
+
+impl Shard {
+    /// Sends commands to the node, connecting first if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `commands` - The commands, each the name of the command followed by its arguments.
+    ///
+    /// # Returns
+    ///
+    /// The response to every command, in order, with error replies as [`Response::Error`].
+    ///
+    /// # Errors
+    ///
+    /// If the node cannot be reached, it will return [`MiniRedisError::ShardFailed`]
+    /// holding the address of the node.
+    fn send(&mut self, commands: &[Vec<&str>]) -> Result<Vec<Response>, MiniRedisError> {
+        let connection = match &mut self.connection {
+            Some(connection) => connection,
+            None => self
+                .connection
+                .insert(Connection::connect(&self.address).map_err(|e| self.failed(e))?),
+        };
+        let result = connection.send_commands(commands);
+        match result {
+            Err(e) if connection::is_connection_error(&e) || connection.is_broken() => {
+                self.connection = None;
+                Err(self.failed(e))
+            }
+            result => result,
+        }
+    }
+
+    /// Names the node in an error it failed with.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The error.
+    ///
+    /// # Returns
+    ///
+    /// A [`MiniRedisError::ShardFailed`] holding the address of the node.
+    fn failed(&self, source: MiniRedisError) -> MiniRedisError {
+        MiniRedisError::ShardFailed {
+            address: self.address.clone(),
+            source: Box::new(source),
+        }
+    }
+}
+
+/// A connection to several servers that each hold a share of the keys.
+///
+/// Every key is hashed onto a ring with many points per server, and the command goes
+/// to the server that owns it. Keys with the same hash tag, like `{user:1}:name`
+/// and `{user:1}:email`, are owned by the same server.
+///
+/// Commands that read or write several keys are split per server and merged
+/// where that means the same as sending them to one server, like [`ShardedConnection::mget`],
+/// and are rejected otherwise.
+///
+/// Connections are opened when a server is first needed, so a server that is down only
+/// fails the commands for its own keys, with a [`MiniRedisError::ShardFailed`] naming it.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use miniredis::shard::ShardedConnection;
+///
+/// let mut connection = ShardedConnection::new(&["10.0.0.1:6379", "10.0.0.2:6379"]).unwrap();
+/// connection.set("name", "alice").unwrap();
+///
+/// assert_eq!(Some("alice".to_string()), connection.get("name").unwrap());
+/// ```
+pub struct ShardedConnection {
+    shards: Vec<Shard>,
+    ring: Ring,
+}
+
+impl ShardedConnection {
+    /// Creates a connection to several servers, without connecting yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `addresses` - The addresses of the servers. Which keys a server owns depends
+    ///   on its address and the addresses of the others, not on their order.
+    ///
+    /// # Returns
+    ///
+    /// A connection to the servers.
+    ///
+    /// # Errors
+    ///
+    /// If there are no addresses, or an address is given twice,
+    /// it will return [`MiniRedisError::InvalidArguments`] holding the addresses.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::shard::ShardedConnection;
+    ///
+    /// let connection = ShardedConnection::new(&["10.0.0.1:6379", "10.0.0.2:6379"]).unwrap();
+    ///
+    /// assert!(ShardedConnection::new(&[]).is_err());
+    /// ```
+    pub fn new(addresses: &[&str]) -> Result<Self, MiniRedisError> {
+        let invalid = || MiniRedisError::InvalidArguments {
+            arguments: addresses
+                .iter()
+                .map(|address| address.to_string())
+                .collect(),
+        };
+        let addresses = addresses
+            .iter()
+            .map(|address| address.trim().to_string())
+            .collect::<Vec<String>>();
+        let duplicate = addresses
+            .iter()
+            .enumerate()
+            .any(|(i, address)| addresses[..i].contains(address));
+        if addresses.is_empty() || duplicate {
+            return Err(invalid());
+        }
+
+        Ok(ShardedConnection {
+            ring: Ring::new(&addresses),
+            shards: addresses
+                .into_iter()
+                .map(|address| Shard {
+                    address,
+                    connection: None,
+                })
+                .collect(),
+        })
+    }
+
+    /// Finds the server that owns a key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key.
+    ///
+    /// # Returns
+    ///
+    /// The address of the server.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::shard::ShardedConnection;
+    ///
+    /// let connection = ShardedConnection::new(&["10.0.0.1:6379", "10.0.0.2:6379"]).unwrap();
+    ///
+    /// assert_eq!(
+    ///     connection.address_of("{user:1}:name"),
+    ///     connection.address_of("{user:1}:email")
+    /// );
+    /// ```
+    pub fn address_of(&self, key: &str) -> &str {
+        &self.shards[self.ring.node_of(key)].address
+    }
+
+    /// Gets the value of a key from the server that owns it.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to get.
+    ///
+    /// # Returns
+    ///
+    /// The value of the key, or None if the key does not exist.
+    ///
+    /// # Errors
+    ///
+    /// If the server cannot be reached, it will return [`MiniRedisError::ShardFailed`].
+    /// If the server replies with an error, it will return that error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::shard::ShardedConnection;
+    ///
+    /// let mut connection = ShardedConnection::new(&["10.0.0.1:6379", "10.0.0.2:6379"]).unwrap();
+    ///
+    /// assert_eq!(None, connection.get("missing").unwrap());
+    /// ```
+    pub fn get(&mut self, key: &str) -> Result<Option<String>, MiniRedisError> {
+        Ok(self.mget(&[key])?.remove(0))
+    }
+
+    /// Sets the value of a key on the server that owns it.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to set.
+    /// * `value` - The value to set the key to.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether the server set the key.
+    ///
+    /// # Errors
+    ///
+    /// If the server cannot be reached, it will return [`MiniRedisError::ShardFailed`].
+    /// If the server replies with an error, like [`MiniRedisError::StoreFull`], it will return that error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::shard::ShardedConnection;
+    ///
+    /// let mut connection = ShardedConnection::new(&["10.0.0.1:6379", "10.0.0.2:6379"]).unwrap();
+    ///
+    /// connection.set("name", "alice").unwrap();
+    /// ```
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), MiniRedisError> {
+        self.mset(&[(key, value)])
+    }
+
+    /// Deletes a key on the server that owns it.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to delete.
+    ///
+    /// # Returns
+    ///
+    /// True if the key existed, false otherwise.
+    ///
+    /// # Errors
+    ///
+    /// If the server cannot be reached, it will return [`MiniRedisError::ShardFailed`].
+    /// If the server replies with an error, it will return that error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::shard::ShardedConnection;
+    ///
+    /// let mut connection = ShardedConnection::new(&["10.0.0.1:6379", "10.0.0.2:6379"]).unwrap();
+    ///
+    /// connection.del("name").unwrap();
+    /// ```
+    pub fn del(&mut self, key: &str) -> Result<bool, MiniRedisError> {
+        let mut responses =
+            self.execute(vec![(self.ring.node_of(key), vec![(0, vec!["DEL", key])])])?;
+        Ok(matches!(responses.remove(0), Response::Integer(count) if count > 0))
+    }
+
+    /// Gets the values of several keys, asking every server for the keys it owns at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `keys` - The keys to get.
+    ///
+    /// # Returns
+    ///
+    /// The value of every key, in the order of the keys, with None for keys that do not exist.
+    ///
+    /// # Errors
+    ///
+    /// If a server cannot be reached, it will return [`MiniRedisError::ShardFailed`]
+    /// for the first one that failed, and no values.
+    /// If a server replies with an error, it will return that error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::shard::ShardedConnection;
+    ///
+    /// let mut connection = ShardedConnection::new(&["10.0.0.1:6379", "10.0.0.2:6379"]).unwrap();
+    /// connection.mset(&[("a", "1"), ("b", "2")]).unwrap();
+    ///
+    /// assert_eq!(
+    ///     vec![Some("1".to_string()), None, Some("2".to_string())],
+    ///     connection.mget(&["a", "missing", "b"]).unwrap()
+    /// );
+    /// ```
+    pub fn mget(&mut self, keys: &[&str]) -> Result<Vec<Option<String>>, MiniRedisError> {
+        let responses = self.execute(self.split(keys.iter().map(|key| vec!["GET", key])))?;
+        responses
+            .into_iter()
+            .map(|response| match response {
+                Response::Nil => Ok(None),
+                Response::Bulk(value) | Response::Simple(value) => Ok(Some(value)),
+                response => Ok(Some(response.to_string())),
+            })
+            .collect()
+    }
+
+    /// Sets several keys, sending every server the keys it owns at once.
+    ///
+    /// The servers set their keys independently, so if one of them fails,
+    /// the keys of the others may still be set.
+    ///
+    /// # Arguments
+    ///
+    /// * `pairs` - The keys to set, with their values.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating whether every server set its keys.
+    ///
+    /// # Errors
+    ///
+    /// If a server cannot be reached, it will return [`MiniRedisError::ShardFailed`]
+    /// for the first one that failed.
+    /// If a server replies with an error, it will return that error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::shard::ShardedConnection;
+    ///
+    /// let mut connection = ShardedConnection::new(&["10.0.0.1:6379", "10.0.0.2:6379"]).unwrap();
+    ///
+    /// connection.mset(&[("a", "1"), ("b", "2")]).unwrap();
+    /// ```
+    pub fn mset(&mut self, pairs: &[(&str, &str)]) -> Result<(), MiniRedisError> {
+        self.execute(self.split(pairs.iter().map(|(key, value)| vec!["SET", key, value])))
+            .map(|_| ())
+    }
+
+    /// Sends a command to the server that owns its keys.
+    ///
+    /// Commands over keys owned by several servers, like `LCS a b`, would give
+    /// another answer than one server holding every key would, so they are rejected.
+    ///
+    /// # Arguments
+    ///
+    /// * `parts` - The name of the command, followed by its arguments.
+    ///
+    /// # Returns
+    ///
+    /// The response of the server.
+    ///
+    /// # Errors
+    ///
+    /// If the command has no keys, or its keys are owned by several servers,
+    /// it will return [`MiniRedisError::InvalidArguments`] holding the command.
+    /// If the server cannot be reached, it will return [`MiniRedisError::ShardFailed`].
+    /// If the server replies with an error, it will return that error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use miniredis::response::Response;
+    /// use miniredis::shard::ShardedConnection;
+    ///
+    /// let mut connection = ShardedConnection::new(&["10.0.0.1:6379", "10.0.0.2:6379"]).unwrap();
+    /// connection.set("{user:1}:name", "alice").unwrap();
+    ///
+    /// assert_eq!(
+    ///     Response::Integer(5),
+    ///     connection.command(&["STRLEN", "{user:1}:name"]).unwrap()
+    /// );
+    /// ```
+    pub fn command(&mut self, parts: &[&str]) -> Result<Response, MiniRedisError> {
+        let invalid = || MiniRedisError::InvalidArguments {
+            arguments: parts.iter().map(|part| part.to_string()).collect(),
+        };
+        let name = parts.first().ok_or_else(invalid)?.to_ascii_uppercase();
+        let mut nodes = CommandInfo::find(&name)
+            .map(|info| {
+                info.args
+                    .iter()
+                    .zip(&parts[1..])
+                    .filter(|(kind, _)| **kind == ArgKind::Key)
+                    .map(|(_, key)| self.ring.node_of(key))
+                    .collect::<Vec<usize>>()
+            })
+            .unwrap_or_default();
+        nodes.sort_unstable();
+        nodes.dedup();
+        let [node] = nodes[..] else {
+            return Err(invalid());
+        };
+
+        let mut responses = self.execute(vec![(node, vec![(0, parts.to_vec())])])?;
+        Ok(responses.remove(0))
+    }
+
+    /// Groups commands over one key each by the server that owns the key.
+    ///
+    /// # Arguments
+    ///
+    /// * `commands` - The commands, each with its key as its first argument.
+    ///
+    /// # Returns
+    ///
+    /// The commands of every server that owns any of the keys, with the position of each command.
+    fn split<'a>(&self, commands: impl Iterator<Item = Vec<&'a str>>) -> Vec<Batch<'a>> {
+        let mut batches: Vec<Vec<(usize, Vec<&str>)>> =
+            self.shards.iter().map(|_| Vec::new()).collect();
+        for (position, command) in commands.enumerate() {
+            batches[self.ring.node_of(command[1])].push((position, command));
+        }
+        batches
+            .into_iter()
+            .enumerate()
+            .filter(|(_, batch)| !batch.is_empty())
+            .collect()
+    }
+
+    /// Sends every server its commands at once, and puts the responses back in order.
+    ///
+    /// # Arguments
+    ///
+    /// * `batches` - The commands of every server, from [`ShardedConnection::split`].
+    ///
+    /// # Returns
+    ///
+    /// The response to every command, in the order of the positions of the commands.
+    ///
+    /// # Errors
+    ///
+    /// If a server cannot be reached, it will return [`MiniRedisError::ShardFailed`]
+    /// for the first one that failed, after every server was sent its commands.
+    /// If a server replies with an error, it will return the first such error.
+    fn execute(&mut self, batches: Vec<Batch>) -> Result<Vec<Response>, MiniRedisError> {
+        let total = batches.iter().map(|(_, batch)| batch.len()).sum();
+        let mut responses = std::iter::repeat_with(|| Response::Nil)
+            .take(total)
+            .collect::<Vec<Response>>();
+        let mut failure = None;
+        for (node, batch) in batches {
+            let (positions, commands): (Vec<usize>, Vec<Vec<&str>>) = batch.into_iter().unzip();
+            match self.shards[node].send(&commands) {
+                Ok(replies) => {
+                    for (position, reply) in positions.into_iter().zip(replies) {
+                        responses[position] = reply;
+                    }
+                }
+                Err(e) => {
+                    failure.get_or_insert(e);
+                }
+            }
+        }
+        if let Some(e) = failure {
+            return Err(e);
+        }
+        responses
+            .into_iter()
+            .map(|response| match response {
+                Response::Error(e) => Err(e),
+                response => Ok(response),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ring(nodes: usize) -> Ring {
+        Ring::new(
+            &(0..nodes)
+                .map(|node| format!("10.0.0.{}:6379", node))
+                .collect::<Vec<String>>(),
+        )
+    }
+
+    #[test]
+    fn ring_spreads_keys_evenly() {
+        let ring = ring(3);
+        let mut owned = [0; 3];
+        for i in 0..30_000 {
+            owned[ring.node_of(&format!("key:{}", i))] += 1;
+        }
+
+        for count in owned {
+            assert!((7_000..13_000).contains(&count), "{:?}", owned);
+        }
+    }
+
+    #[test]
+    fn ring_moves_only_the_keys_of_a_new_node() {
+        let (before, after) = (ring(3), ring(4));
+        let keys = (0..30_000)
+            .map(|i| format!("key:{}", i))
+            .collect::<Vec<String>>();
+
+        let moved = keys
+            .iter()
+            .filter(|key| before.node_of(key) != after.node_of(key))
+            .inspect(|key| assert_eq!(3, after.node_of(key)))
+            .count();
+
+        assert!((4_000..11_000).contains(&moved), "{}", moved);
+    }
+
+    #[test]
+    fn ring_keeps_keys_with_a_hash_tag_together() {
+        let ring = ring(3);
+
+        for user in 0..100 {
+            assert_eq!(
+                ring.node_of(&format!("{{user:{}}}:name", user)),
+                ring.node_of(&format!("{{user:{}}}:email", user))
+            );
+        }
+    }
+
+    #[test]
+    fn new_rejects_no_addresses_and_duplicates() {
+        for addresses in [&[][..], &["a:1", "b:2", " a:1"][..]] {
+            assert_eq!(
+                Some(MiniRedisError::InvalidArguments {
+                    arguments: addresses
+                        .iter()
+                        .map(|address| address.to_string())
+                        .collect()
+                }),
+                ShardedConnection::new(addresses).err()
+            );
+        }
+    }
+
+    #[test]
+    fn command_rejects_keys_of_several_nodes_without_connecting() {
+        let mut connection = ShardedConnection::new(&["10.0.0.1:6379", "10.0.0.2:6379"]).unwrap();
+        let a = "key:0".to_string();
+        let b = (1..)
+            .map(|i| format!("key:{}", i))
+            .find(|key| connection.address_of(key) != connection.address_of(&a))
+            .unwrap();
+
+        for parts in [vec!["LCS", &a, &b], vec!["PING"], vec![]] {
+            assert_eq!(
+                Some(MiniRedisError::InvalidArguments {
+                    arguments: parts.iter().map(|part| part.to_string()).collect()
+                }),
+                connection.command(&parts).err()
+            );
+        }
+    }
+}
@@ -0,0 +1,455 @@
+#[cfg(feature = "compression")]
+use crate::compress;
+use crate::error::MiniRedisError;
+use std::{
+    borrow::Cow,
+    fs::{self, File},
+    io::{BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The magic bytes at the start of every snapshot file.
+const MAGIC: &[u8; 4] = b"MRDB";
+
+/// The version of the snapshot format written by this build.
+///
+/// Version 1 has no expiry times. It is still read, with every entry persistent.
+const VERSION: u8 = 2;
+
+/// The version of the snapshot format whose entries are compressed.
+///
+/// It is version 2 with a byte after the version naming the codec, and the count and
+/// entries after it compressed. Plain snapshots keep being written as version 2,
+/// so builds that cannot decompress still read them.
+const COMPRESSED_VERSION: u8 = 3;
+
+/// The codec byte of entries compressed with [`compress`](crate::compress).
+const CODEC_LZ: u8 = 1;
+
+/// An entry of a snapshot: a key, the bytes of its value, and the time it expires at, if any.
+pub(crate) type Record = (String, Vec<u8>, Option<SystemTime>);
+
+/// Writes entries to a snapshot file.
+///
+/// The entries are first written to a temporary file next to `path`,
+/// which is synced to disk and then renamed over `path`,
+/// so a crash mid-write never leaves a half-written snapshot behind.
+///
+/// The format is the magic bytes, a version byte, the number of entries,
+/// each entry as a length-prefixed key and value followed by its expiry time
+/// in milliseconds since the Unix epoch (0 if it never expires),
+/// and a CRC32 checksum of everything before it. All integers are little endian.
+/// A compressed snapshot has a codec byte after the version, and the count and entries
+/// compressed; its checksum covers the compressed bytes, so damage is found before decompressing.
+///
+/// # Arguments
+///
+/// * `path` - The path of the snapshot file.
+/// * `entries` - The entries to write.
+/// * `compressed` - Whether to compress the entries.
+///
+/// # Returns
+///
+/// A result indicating whether the snapshot was written successfully.
+///
+/// # Errors
+///
+/// If the temporary file cannot be written, synced, or renamed, it will return an error.
+pub(crate) fn write(
+    path: &Path,
+    entries: &[Record],
+    compressed: bool,
+) -> Result<(), MiniRedisError> {
+    let bytes = encode(entries, compressed);
+    let temp_path = temp_path(path);
+    let not_writable = |source| MiniRedisError::FileNotWritable {
+        path: path.display().to_string(),
+        source,
+    };
+
+    let file = File::create(&temp_path).map_err(not_writable)?;
+    let mut writer = BufWriter::new(file);
+    writer.write_all(&bytes).map_err(not_writable)?;
+    let file = writer
+        .into_inner()
+        .map_err(|e| not_writable(e.into_error()))?;
+    file.sync_all().map_err(not_writable)?;
+    fs::rename(&temp_path, path).map_err(not_writable)?;
+    Ok(())
+}
+
+/// Reads entries from a snapshot file, compressed or not.
+///
+/// # Arguments
+///
+/// * `path` - The path of the snapshot file.
+///
+/// # Returns
+///
+/// The entries stored in the snapshot.
+///
+/// # Errors
+///
+/// If the file cannot be read, or its contents are not a valid snapshot, it will return an error.
+pub(crate) fn read(path: &Path) -> Result<Vec<Record>, MiniRedisError> {
+    let mut bytes = Vec::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut bytes))
+        .map_err(|source| MiniRedisError::FileNotReadable {
+            path: path.display().to_string(),
+            source,
+        })?;
+
+    decode(&bytes).map_err(|reason| MiniRedisError::SnapshotCorrupted {
+        path: path.display().to_string(),
+        reason: reason.to_string(),
+    })
+}
+
+/// Encodes entries into the snapshot format.
+///
+/// # Arguments
+///
+/// * `entries` - The entries to encode.
+/// * `compressed` - Whether to compress the entries. Without the `compression` feature,
+///   they are written plain.
+///
+/// # Returns
+///
+/// The encoded bytes, including the trailing checksum.
+fn encode(entries: &[Record], compressed: bool) -> Vec<u8> {
+    let mut payload = Vec::new();
+    payload.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+    for (key, value, expires_at) in entries {
+        write_chunk(&mut payload, key.as_bytes());
+        write_chunk(&mut payload, value);
+        payload.extend_from_slice(&to_millis(*expires_at).to_le_bytes());
+    }
+
+    let mut bytes = MAGIC.to_vec();
+    match compressed {
+        #[cfg(feature = "compression")]
+        true => {
+            bytes.extend_from_slice(&[COMPRESSED_VERSION, CODEC_LZ]);
+            bytes.extend_from_slice(&compress::compress(&payload));
+        }
+        _ => {
+            bytes.push(VERSION);
+            bytes.extend_from_slice(&payload);
+        }
+    }
+    let checksum = crc32(&bytes);
+    bytes.extend_from_slice(&checksum.to_le_bytes());
+    bytes
+}
+
+/// Decodes entries from the snapshot format.
+///
+/// # Arguments
+///
+/// * `bytes` - The bytes to decode.
+///
+/// # Returns
+///
+/// The decoded entries.
+///
+/// # Errors
+///
+/// If the bytes are not a valid snapshot, it will return a description of the problem.
+fn decode(bytes: &[u8]) -> Result<Vec<Record>, &'static str> {
+    if bytes.len() < MAGIC.len() + 1 + 8 + 4 {
+        return Err("file is too short");
+    }
+    let (body, checksum) = bytes.split_at(bytes.len() - 4);
+    if crc32(body).to_le_bytes() != checksum {
+        return Err("checksum mismatch");
+    }
+
+    let mut reader = Reader { bytes: body };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err("not a snapshot file");
+    }
+    let version = reader.take(1)?[0];
+    let payload = match version {
+        1 | VERSION => Cow::Borrowed(reader.bytes),
+        COMPRESSED_VERSION => match reader.take(1)?[0] {
+            #[cfg(feature = "compression")]
+            CODEC_LZ => Cow::Owned(compress::decompress(reader.bytes)?),
+            #[cfg(not(feature = "compression"))]
+            CODEC_LZ => return Err("snapshot is compressed, and this build cannot decompress"),
+            _ => return Err("unsupported compression codec"),
+        },
+        _ => return Err("unsupported snapshot version"),
+    };
+    let mut reader = Reader { bytes: &payload };
+    let count = reader.read_u64()?;
+
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let key = reader.read_string()?;
+        let value = reader.read_bytes()?.to_vec();
+        let expires_at = if version == 1 {
+            None
+        } else {
+            from_millis(reader.read_u64()?)
+        };
+        entries.push((key, value, expires_at));
+    }
+    if !reader.bytes.is_empty() {
+        return Err("trailing bytes after the last entry");
+    }
+    Ok(entries)
+}
+
+/// Appends a length-prefixed chunk of bytes.
+///
+/// # Arguments
+///
+/// * `bytes` - The buffer to append to.
+/// * `chunk` - The chunk to append.
+fn write_chunk(bytes: &mut Vec<u8>, chunk: &[u8]) {
+    bytes.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(chunk);
+}
+
+/// Converts an expiry time to milliseconds since the Unix epoch.
+///
+/// # Arguments
+///
+/// * `expires_at` - The expiry time, or None if the entry never expires.
+///
+/// # Returns
+///
+/// The milliseconds since the epoch, at least 1, or 0 if the entry never expires.
+fn to_millis(expires_at: Option<SystemTime>) -> u64 {
+    expires_at.map_or(0, |at| {
+        let millis = at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        (millis as u64).max(1)
+    })
+}
+
+/// Converts milliseconds since the Unix epoch back to an expiry time.
+///
+/// # Arguments
+///
+/// * `millis` - The milliseconds since the epoch, or 0 if the entry never expires.
+///
+/// # Returns
+///
+/// The expiry time, or None if the entry never expires.
+fn from_millis(millis: u64) -> Option<SystemTime> {
+    (millis != 0).then(|| UNIX_EPOCH + Duration::from_millis(millis))
+}
+
+/// Returns the path of the temporary file used while writing `path`.
+///
+/// # Arguments
+///
+/// * `path` - The path of the snapshot file.
+///
+/// # Returns
+///
+/// The path with `.tmp` appended to its file name.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    PathBuf::from(temp)
+}
+
+/// A cursor over the bytes of a snapshot.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// Takes the next `len` bytes.
+    ///
+    /// # Errors
+    ///
+    /// If fewer than `len` bytes are left, it will return an error.
+    fn take(&mut self, len: usize) -> Result<&'a [u8], &'static str> {
+        if self.bytes.len() < len {
+            return Err("unexpected end of file");
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    /// Reads a little endian u64.
+    fn read_u64(&mut self) -> Result<u64, &'static str> {
+        let bytes = self.take(8)?;
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    /// Reads a length-prefixed chunk of bytes.
+    fn read_bytes(&mut self) -> Result<&'a [u8], &'static str> {
+        let len = u32::from_le_bytes(self.take(4)?.try_into().unwrap()) as usize;
+        self.take(len)
+    }
+
+    /// Reads a length-prefixed UTF-8 string.
+    fn read_string(&mut self) -> Result<String, &'static str> {
+        let bytes = self.read_bytes()?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| "key is not valid UTF-8")
+    }
+}
+
+/// The lookup table for the CRC32 (IEEE) checksum.
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Computes the CRC32 (IEEE) checksum of some bytes.
+///
+/// # Arguments
+///
+/// * `bytes` - The bytes to checksum.
+///
+/// # Returns
+///
+/// The checksum.
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF;
+    for byte in bytes {
+        crc = CRC32_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_matches_known_value() {
+        assert_eq!(0xCBF4_3926, crc32(b"123456789"));
+    }
+
+    #[test]
+    fn decode_returns_encoded_entries() {
+        let expires_at = UNIX_EPOCH + Duration::from_millis(1_700_000_000_000);
+        let entries = vec![
+            ("key".to_string(), b"value".to_vec(), None),
+            ("empty".to_string(), Vec::new(), Some(expires_at)),
+        ];
+
+        assert_eq!(Ok(entries.clone()), decode(&encode(&entries, false)));
+    }
+
+    #[test]
+    fn decode_returns_values_that_are_not_utf8_as_they_are() {
+        let entries = vec![("bits".to_string(), vec![0xff, 0x00, 0xc3], None)];
+
+        assert_eq!(Ok(entries.clone()), decode(&encode(&entries, false)));
+    }
+
+    #[test]
+    fn decode_reads_version_1_without_expiry_times() {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(1);
+        bytes.extend_from_slice(&1u64.to_le_bytes());
+        write_chunk(&mut bytes, b"key");
+        write_chunk(&mut bytes, b"value");
+        let checksum = crc32(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+
+        assert_eq!(
+            Ok(vec![("key".to_string(), b"value".to_vec(), None)]),
+            decode(&bytes)
+        );
+    }
+
+    #[test]
+    fn decode_rejects_flipped_bit() {
+        let mut bytes = encode(&[("key".to_string(), b"value".to_vec(), None)], false);
+        bytes[10] ^= 0x01;
+
+        assert_eq!(Err("checksum mismatch"), decode(&bytes));
+    }
+
+    #[test]
+    fn decode_rejects_truncated_file() {
+        let bytes = encode(&[("key".to_string(), b"value".to_vec(), None)], false);
+
+        assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn decode_returns_compressed_entries() {
+        let expires_at = UNIX_EPOCH + Duration::from_millis(1_700_000_000_000);
+        let entries = (0..100)
+            .map(|i| {
+                let value = format!("{{\"id\":{},\"name\":\"alice\",\"active\":true}}", i);
+                (
+                    format!("user:{}", i),
+                    value.into_bytes(),
+                    (i % 2 == 0).then_some(expires_at),
+                )
+            })
+            .collect::<Vec<Record>>();
+
+        let bytes = encode(&entries, true);
+
+        assert_eq!(COMPRESSED_VERSION, bytes[MAGIC.len()]);
+        assert_eq!(Ok(entries), decode(&bytes));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn encode_compressed_shrinks_repetitive_values() {
+        let entries = (0..1000)
+            .map(|i| {
+                let value = "{\"user\":\"alice\",\"roles\":[\"admin\",\"editor\"],\"active\":true}";
+                (format!("session:{}", i), value.as_bytes().to_vec(), None)
+            })
+            .collect::<Vec<Record>>();
+
+        let plain = encode(&entries, false);
+        let compressed = encode(&entries, true);
+
+        assert!(compressed.len() < plain.len() / 4);
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn decode_checks_compressed_bytes_before_decompressing() {
+        let mut bytes = encode(&[("key".to_string(), b"value".repeat(10), None)], true);
+        bytes[12] ^= 0x01;
+
+        assert_eq!(Err("checksum mismatch"), decode(&bytes));
+    }
+
+    #[test]
+    fn decode_rejects_unknown_codec() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[COMPRESSED_VERSION, 0xFF]);
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+        let checksum = crc32(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+
+        assert_eq!(Err("unsupported compression codec"), decode(&bytes));
+    }
+}
@@ -0,0 +1,220 @@
+use crate::command;
+use crate::error::MiniRedisError;
+
+/// How `SORT` orders the elements of a collection, and which of them it keeps.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct SortOptions {
+    /// Whether to compare the elements as text instead of as numbers.
+    pub(crate) alpha: bool,
+    /// Whether to sort from the largest element down.
+    pub(crate) desc: bool,
+    /// How many sorted elements to skip.
+    pub(crate) offset: usize,
+    /// How many sorted elements to keep after the skipped ones, or None for all of them.
+    pub(crate) count: Option<usize>,
+    /// The key to store the sorted elements in, instead of replying with them.
+    pub(crate) store: Option<String>,
+}
+
+impl SortOptions {
+    /// Parses the options of `SORT` that follow its key, in any order and case.
+    ///
+    /// Every option reads its values from the words after its name,
+    /// so options like `BY <PATTERN>` can be added as arms of their own.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The words after the key, like `LIMIT 0 10 DESC`.
+    ///
+    /// # Returns
+    ///
+    /// The options. A negative offset skips nothing, and a negative count keeps every element,
+    /// like in Redis.
+    ///
+    /// # Errors
+    ///
+    /// If a word is not an option, or an option misses its values, it will return
+    /// [`MiniRedisError::InvalidArguments`] holding the options. If the offset or the count
+    /// of `LIMIT` is not an integer, it will return [`MiniRedisError::NotAnInteger`].
+    pub(crate) fn parse(options: &[String]) -> Result<Self, MiniRedisError> {
+        let invalid = || MiniRedisError::InvalidArguments {
+            arguments: options.to_vec(),
+        };
+        let mut parsed = SortOptions::default();
+        let mut words = options.iter();
+        while let Some(word) = words.next() {
+            match word.to_ascii_uppercase().as_str() {
+                "ASC" => parsed.desc = false,
+                "DESC" => parsed.desc = true,
+                "ALPHA" => parsed.alpha = true,
+                "LIMIT" => {
+                    let (Some(offset), Some(count)) = (words.next(), words.next()) else {
+                        return Err(invalid());
+                    };
+                    parsed.offset = usize::try_from(command::integer(offset)?).unwrap_or(0);
+                    parsed.count = usize::try_from(command::integer(count)?).ok();
+                }
+                "STORE" => parsed.store = Some(words.next().ok_or_else(invalid)?.clone()),
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// Sorts the elements of a collection.
+///
+/// Elements are compared as numbers unless the options ask for text,
+/// and elements with the same number are ordered as text.
+/// The elements are a copy of the collection, so sorting a large one
+/// does not hold up other clients of the store.
+///
+/// # Arguments
+///
+/// * `elements` - The elements, in the order the collection holds them.
+/// * `options` - How to order the elements, and which of them to keep.
+///
+/// # Returns
+///
+/// The elements that the limit keeps, in order.
+///
+/// # Errors
+///
+/// If the elements are compared as numbers and one of them is not a number,
+/// it will return [`MiniRedisError::NotADouble`].
+pub(crate) fn sort(
+    mut elements: Vec<String>,
+    options: &SortOptions,
+) -> Result<Vec<String>, MiniRedisError> {
+    if options.alpha {
+        elements.sort();
+    } else {
+        let mut scored = elements
+            .into_iter()
+            .map(|element| Ok((score(&element)?, element)))
+            .collect::<Result<Vec<(f64, String)>, MiniRedisError>>()?;
+        scored.sort_by(|(a, x), (b, y)| a.total_cmp(b).then_with(|| x.cmp(y)));
+        elements = scored.into_iter().map(|(_, element)| element).collect();
+    }
+    if options.desc {
+        elements.reverse();
+    }
+    Ok(elements
+        .into_iter()
+        .skip(options.offset)
+        .take(options.count.unwrap_or(usize::MAX))
+        .collect())
+}
+
+/// Parses an element that is sorted as a number.
+///
+/// # Arguments
+///
+/// * `element` - The element.
+///
+/// # Returns
+///
+/// The number, which may be infinite.
+///
+/// # Errors
+///
+/// If the element is not a number, it will return [`MiniRedisError::NotADouble`].
+fn score(element: &str) -> Result<f64, MiniRedisError> {
+    element
+        .parse::<f64>()
+        .ok()
+        .filter(|score| !score.is_nan())
+        .ok_or(MiniRedisError::NotADouble)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Turns words into the arguments of a command.
+    fn words(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn options_parse_in_any_order_and_case() {
+        assert_eq!(
+            SortOptions {
+                alpha: true,
+                desc: true,
+                offset: 1,
+                count: Some(2),
+                store: Some("out".to_string()),
+            },
+            SortOptions::parse(&words("store out desc LIMIT 1 2 alpha")).unwrap()
+        );
+        assert_eq!(
+            SortOptions::default(),
+            SortOptions::parse(&words("LIMIT -5 -1 DESC ASC")).unwrap()
+        );
+        assert_eq!(
+            Err(MiniRedisError::NotAnInteger),
+            SortOptions::parse(&words("LIMIT 0 x"))
+        );
+        for options in ["LIMIT 0", "STORE", "BY weight_*", "REVERSE"] {
+            assert_eq!(
+                Err(MiniRedisError::InvalidArguments {
+                    arguments: words(options)
+                }),
+                SortOptions::parse(&words(options))
+            );
+        }
+    }
+
+    #[test]
+    fn elements_sort_as_numbers_unless_alpha() {
+        let elements = words("10 9 -1.5 1e1 inf");
+
+        assert_eq!(
+            words("-1.5 9 10 1e1 inf"),
+            sort(elements.clone(), &SortOptions::default()).unwrap()
+        );
+        assert_eq!(
+            words("-1.5 10 1e1 9 inf"),
+            sort(
+                elements,
+                &SortOptions {
+                    alpha: true,
+                    ..SortOptions::default()
+                }
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            Err(MiniRedisError::NotADouble),
+            sort(words("1 two 3"), &SortOptions::default())
+        );
+        assert_eq!(
+            Err(MiniRedisError::NotADouble),
+            sort(words("NaN"), &SortOptions::default())
+        );
+    }
+
+    #[test]
+    fn limit_keeps_a_window_of_the_sorted_elements() {
+        let options = SortOptions {
+            desc: true,
+            offset: 1,
+            count: Some(2),
+            ..SortOptions::default()
+        };
+
+        assert_eq!(words("4 3"), sort(words("1 5 3 2 4"), &options).unwrap());
+        assert_eq!(
+            Vec::<String>::new(),
+            sort(
+                words("1 2"),
+                &SortOptions {
+                    offset: 5,
+                    ..SortOptions::default()
+                }
+            )
+            .unwrap()
+        );
+    }
+}
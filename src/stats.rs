@@ -0,0 +1,317 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{RwLock, RwLockReadGuard};
+use std::time::Duration;
+
+/// Counters of the operations performed on a key-value store.
+///
+/// # Examples
+///
+/// ```rust
+/// use miniredis::kv_store::KVStore;
+///
+/// let store = KVStore::new();
+/// store.set("key", "value").unwrap();
+/// store.get("key").unwrap();
+/// store.get("missing").unwrap();
+///
+/// let stats = store.stats();
+/// assert_eq!(2, stats.gets);
+/// assert_eq!(1, stats.hits);
+/// assert_eq!(1, stats.misses);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StoreStats {
+    /// The number of gets.
+    pub gets: u64,
+    /// The number of sets.
+    pub sets: u64,
+    /// The number of deletes, whether or not the key existed.
+    pub dels: u64,
+    /// The number of gets that found the key.
+    pub hits: u64,
+    /// The number of gets that did not find the key.
+    pub misses: u64,
+    /// The number of keys evicted to make room for new data.
+    pub evictions: u64,
+    /// The number of keys removed because their time to live ran out.
+    pub expired: u64,
+}
+
+impl StoreStats {
+    /// Computes the ratio of gets that found the key.
+    ///
+    /// # Returns
+    ///
+    /// The hit ratio between 0 and 1, or None if there were no gets.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::stats::StoreStats;
+    ///
+    /// let stats = StoreStats { gets: 4, hits: 3, misses: 1, ..StoreStats::default() };
+    ///
+    /// assert_eq!(Some(0.75), stats.hit_ratio());
+    /// ```
+    pub fn hit_ratio(&self) -> Option<f64> {
+        if self.gets == 0 {
+            None
+        } else {
+            Some(self.hits as f64 / self.gets as f64)
+        }
+    }
+}
+
+/// How many keys a database holds, and how many of them expire.
+///
+/// # Examples
+///
+/// ```rust
+/// use miniredis::kv_store::KVStore;
+/// use std::time::Duration;
+///
+/// let store = KVStore::new();
+/// store.set("user", "alice").unwrap();
+/// store
+///     .extend_with_ttl([("session".to_string(), "token".to_string(), Some(Duration::from_secs(60)))])
+///     .unwrap();
+///
+/// let keyspace = store.keyspace().unwrap();
+/// assert_eq!(2, keyspace.keys);
+/// assert_eq!(1, keyspace.expires);
+/// assert!(keyspace.avg_ttl <= Duration::from_secs(60));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyspaceStats {
+    /// The number of keys.
+    pub keys: usize,
+    /// The number of keys with a time to live.
+    pub expires: usize,
+    /// The average time to live left of the keys that have one, or zero if none has.
+    pub avg_ttl: Duration,
+}
+
+impl std::fmt::Display for KeyspaceStats {
+    /// Formats the stats like a line of the keyspace section of `INFO`,
+    /// like `keys=2,expires=1,avg_ttl=59998`, with the average time to live in milliseconds.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - The formatter to write the stats to.
+    ///
+    /// # Errors
+    ///
+    /// If the stats cannot be formatted, it will return an error.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "keys={},expires={},avg_ttl={}",
+            self.keys,
+            self.expires,
+            self.avg_ttl.as_millis()
+        )
+    }
+}
+
+/// Counters of what a server has done, shown by `INFO stats`.
+///
+/// They count from the start of the server or the last `CONFIG RESETSTAT`.
+///
+/// # Examples
+///
+/// ```rust
+/// use miniredis::engine::Engine;
+///
+/// let engine = Engine::new();
+/// engine.execute_str("PING");
+///
+/// assert_eq!(1, engine.stats().commands_processed);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ServerStats {
+    /// The number of commands executed, including the ones that failed.
+    pub commands_processed: u64,
+    /// The number of connections accepted.
+    pub connections_received: u64,
+    /// The number of clients dropped for leaving too many replies unread.
+    pub output_buffer_disconnections: u64,
+}
+
+/// A set of counters that are reset together.
+///
+/// Counting takes the read side of the lock, so counters are still bumped side by side.
+/// A reset swaps in a fresh set under the write side instead of zeroing the counters
+/// one by one, so reading the set never sees some of them zeroed and others not.
+#[derive(Default)]
+struct Epoch<T> {
+    current: RwLock<T>,
+}
+
+impl<T: Default> Epoch<T> {
+    /// Gets the current set of counters.
+    fn current(&self) -> RwLockReadGuard<'_, T> {
+        self.current
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Swaps in a fresh set of counters.
+    fn reset(&self) {
+        *self
+            .current
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = T::default();
+    }
+}
+
+/// The counters of one epoch of a store.
+#[derive(Default)]
+struct StoreCounters {
+    gets: AtomicU64,
+    sets: AtomicU64,
+    dels: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    expired: AtomicU64,
+}
+
+/// The live counters behind [`StoreStats`].
+///
+/// The counters use relaxed atomics, so counting never waits for another count.
+/// This means a snapshot taken while other threads are counting
+/// may be off by the operations in flight, but never half reset.
+#[derive(Default)]
+pub(crate) struct Counters {
+    epoch: Epoch<StoreCounters>,
+}
+
+impl Counters {
+    /// Counts a get.
+    ///
+    /// # Arguments
+    ///
+    /// * `hit` - Whether the get found the key.
+    pub(crate) fn record_get(&self, hit: bool) {
+        let counters = self.epoch.current();
+        counters.gets.fetch_add(1, Ordering::Relaxed);
+        // Released after the get is counted, so a snapshot never sees more hits and misses than gets.
+        if hit {
+            counters.hits.fetch_add(1, Ordering::Release);
+        } else {
+            counters.misses.fetch_add(1, Ordering::Release);
+        }
+    }
+
+    /// Counts a set.
+    pub(crate) fn record_set(&self) {
+        self.epoch.current().sets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a delete.
+    pub(crate) fn record_del(&self) {
+        self.epoch.current().dels.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts evicted keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - The number of keys that were evicted.
+    pub(crate) fn record_evictions(&self, count: usize) {
+        self.epoch
+            .current()
+            .evictions
+            .fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    /// Counts expired keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - The number of keys that were removed because they expired.
+    pub(crate) fn record_expired(&self, count: usize) {
+        self.epoch
+            .current()
+            .expired
+            .fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    /// Reads the current values of the counters.
+    pub(crate) fn snapshot(&self) -> StoreStats {
+        let counters = self.epoch.current();
+        let hits = counters.hits.load(Ordering::Acquire);
+        let misses = counters.misses.load(Ordering::Acquire);
+        StoreStats {
+            gets: counters.gets.load(Ordering::Relaxed),
+            sets: counters.sets.load(Ordering::Relaxed),
+            dels: counters.dels.load(Ordering::Relaxed),
+            hits,
+            misses,
+            evictions: counters.evictions.load(Ordering::Relaxed),
+            expired: counters.expired.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Sets all counters back to zero at once.
+    pub(crate) fn reset(&self) {
+        self.epoch.reset();
+    }
+}
+
+/// The counters of one epoch of a server.
+#[derive(Default)]
+struct ServerEpoch {
+    commands_processed: AtomicU64,
+    connections_received: AtomicU64,
+    output_buffer_disconnections: AtomicU64,
+}
+
+/// The live counters behind [`ServerStats`], which reset like [`Counters`].
+#[derive(Default)]
+pub(crate) struct ServerCounters {
+    epoch: Epoch<ServerEpoch>,
+}
+
+impl ServerCounters {
+    /// Counts an executed command.
+    pub(crate) fn record_command(&self) {
+        self.epoch
+            .current()
+            .commands_processed
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts an accepted connection.
+    pub(crate) fn record_connection(&self) {
+        self.epoch
+            .current()
+            .connections_received
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a client dropped for leaving too many replies unread.
+    pub(crate) fn record_output_buffer_disconnection(&self) {
+        self.epoch
+            .current()
+            .output_buffer_disconnections
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Reads the current values of the counters.
+    pub(crate) fn snapshot(&self) -> ServerStats {
+        let counters = self.epoch.current();
+        ServerStats {
+            commands_processed: counters.commands_processed.load(Ordering::Relaxed),
+            connections_received: counters.connections_received.load(Ordering::Relaxed),
+            output_buffer_disconnections: counters
+                .output_buffer_disconnections
+                .load(Ordering::Relaxed),
+        }
+    }
+
+    /// Sets all counters back to zero at once.
+    pub(crate) fn reset(&self) {
+        self.epoch.reset();
+    }
+}
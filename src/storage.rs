@@ -0,0 +1,1967 @@
+use crate::error::MiniRedisError;
+use crate::glob;
+use crate::kv_store::{self, KVStore};
+use crate::stats::{KeyspaceStats, StoreStats};
+use std::collections::BTreeSet;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+/// The storage operations the server needs from a key-value backend.
+///
+/// The command layer only talks to storage through this trait,
+/// so a server can run on any backend, like a disk-backed one,
+/// through `Server::with_storage`. The trait is object safe.
+/// [`KVStore`] is the default, in-memory implementation.
+///
+/// # Examples
+///
+/// ```rust
+/// use miniredis::kv_store::KVStore;
+/// use miniredis::storage::Storage;
+///
+/// let storage: Box<dyn Storage> = Box::new(KVStore::new());
+///
+/// storage.set("key", "value").unwrap();
+///
+/// assert_eq!(Ok(Some("value".to_string())), storage.get("key"));
+/// ```
+pub trait Storage: Send + Sync {
+    /// Gets the value of a key.
+    ///
+    /// Values are bytes, which [`Storage::set_range`] and [`Storage::set_bit`] can leave
+    /// as bytes that are not valid UTF-8. Those bytes read as U+FFFD here,
+    /// and exactly as they are through [`Storage::get_bytes`].
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to get the value for.
+    ///
+    /// # Returns
+    ///
+    /// The value associated with the key, or None if the key is not found.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot be accessed, it will return an error.
+    fn get(&self, key: &str) -> Result<Option<String>, MiniRedisError>;
+
+    /// Gets the value of a key as the bytes it is stored as.
+    ///
+    /// Backends whose values are always valid UTF-8 get them through [`Storage::get`] by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to get the value for.
+    ///
+    /// # Returns
+    ///
+    /// The bytes of the value, or None if the key is not found.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot be accessed, it will return an error.
+    fn get_bytes(&self, key: &str) -> Result<Option<Vec<u8>>, MiniRedisError> {
+        Ok(self.get(key)?.map(String::into_bytes))
+    }
+
+    /// Sets the value of a key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to set the value for.
+    /// * `value` - The value to set.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot be accessed, or has no room for the value, it will return an error.
+    fn set(&self, key: &str, value: &str) -> Result<(), MiniRedisError>;
+
+    /// Deletes a key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to delete.
+    ///
+    /// # Returns
+    ///
+    /// True if the key existed, false otherwise.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot be accessed, it will return an error.
+    fn del(&self, key: &str) -> Result<bool, MiniRedisError>;
+
+    /// Moves the value of a key to another key, overwriting it, atomically where the backend allows it.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The key to move the value from.
+    /// * `destination` - The key to move the value to.
+    ///
+    /// # Errors
+    ///
+    /// If the source does not exist, it will return [`MiniRedisError::NoSuchKey`].
+    /// If the backend cannot be accessed, or has no room for the value, it will return an error.
+    fn rename(&self, source: &str, destination: &str) -> Result<(), MiniRedisError>;
+
+    /// Moves the value of a key to another key only if that key does not exist,
+    /// atomically where the backend allows it.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The key to move the value from.
+    /// * `destination` - The key to move the value to.
+    ///
+    /// # Returns
+    ///
+    /// True if the value was moved, false if the destination already existed.
+    ///
+    /// # Errors
+    ///
+    /// If the source does not exist, it will return [`MiniRedisError::NoSuchKey`].
+    /// If the backend cannot be accessed, or has no room for the value, it will return an error.
+    fn rename_if_absent(&self, source: &str, destination: &str) -> Result<bool, MiniRedisError>;
+
+    /// Sets the value of a key only if the key does not exist, atomically.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to set.
+    /// * `value` - The value to set.
+    ///
+    /// # Returns
+    ///
+    /// True if the value was set, false if the key already existed.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot be accessed, or has no room for the value, it will return an error.
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool, MiniRedisError>;
+
+    /// Sets the value of a key if the key meets a condition, atomically.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to set.
+    /// * `value` - The value to set.
+    /// * `options` - When to set the value.
+    ///
+    /// # Returns
+    ///
+    /// True if the value was set, false if the key did not meet the condition.
+    ///
+    /// # Errors
+    ///
+    /// If the options give the key a time to live and the backend has no keys that expire,
+    /// it will return [`MiniRedisError::ExpiryNotSupported`].
+    /// If the backend cannot be accessed, or has no room for the value, it will return an error.
+    fn set_with(
+        &self,
+        key: &str,
+        value: &str,
+        options: &SetOptions,
+    ) -> Result<bool, MiniRedisError>;
+
+    /// Sets the value of a key, atomically, and gets the value it replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to set.
+    /// * `value` - The value to set.
+    ///
+    /// # Returns
+    ///
+    /// The old value, or None if the key did not exist.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot be accessed, or has no room for the value, it will return an error.
+    fn set_returning_old(&self, key: &str, value: &str) -> Result<Option<Vec<u8>>, MiniRedisError>;
+
+    /// Sets the values of many keys, atomically where the backend allows it.
+    ///
+    /// # Arguments
+    ///
+    /// * `pairs` - The keys to set, each with its value.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot be accessed, or has no room for the values, it will return an error.
+    fn multi_set(&self, pairs: &[(String, String)]) -> Result<(), MiniRedisError>;
+
+    /// Sets the values of many keys only if none of the keys exist,
+    /// atomically where the backend allows it.
+    ///
+    /// # Arguments
+    ///
+    /// * `pairs` - The keys to set, each with its value.
+    ///
+    /// # Returns
+    ///
+    /// True if the pairs were written, false if any of the keys already existed.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot be accessed, or has no room for the values, it will return an error.
+    fn multi_set_if_absent(&self, pairs: &[(String, String)]) -> Result<bool, MiniRedisError>;
+
+    /// Adds to the integer value of a key, atomically, treating a missing key as 0.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to add to.
+    /// * `delta` - What to add, negative to subtract.
+    ///
+    /// # Returns
+    ///
+    /// The new value of the key.
+    ///
+    /// # Errors
+    ///
+    /// If the value is not a 64-bit integer, it will return [`MiniRedisError::NotAnInteger`],
+    /// and if the result would not fit in one, [`MiniRedisError::IncrementOverflow`],
+    /// leaving the value as it was.
+    /// If the backend cannot be accessed, or has no room for the value, it will return an error.
+    fn increment(&self, key: &str, delta: i64) -> Result<i64, MiniRedisError>;
+
+    /// Adds to the floating point value of a key, atomically, treating a missing key as 0.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to add to.
+    /// * `delta` - What to add, negative to subtract.
+    ///
+    /// # Returns
+    ///
+    /// The new value of the key.
+    ///
+    /// # Errors
+    ///
+    /// If the value is not a finite number, it will return [`MiniRedisError::NotAFloat`],
+    /// and if the result would be NaN or infinite, [`MiniRedisError::IncrementNotFinite`],
+    /// leaving the value as it was.
+    /// If the backend cannot be accessed, or has no room for the value, it will return an error.
+    fn increment_float(&self, key: &str, delta: f64) -> Result<f64, MiniRedisError>;
+
+    /// Appends to the value of a key, atomically, setting it if the key is missing.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to append to.
+    /// * `suffix` - What to append.
+    ///
+    /// # Returns
+    ///
+    /// The length of the new value, in bytes.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot be accessed, or has no room for the value, it will return an error.
+    fn append(&self, key: &str, suffix: &str) -> Result<usize, MiniRedisError>;
+
+    /// Overwrites part of the value of a key, atomically, from a byte offset on.
+    ///
+    /// A missing key counts as an empty value,
+    /// and a value shorter than the offset is padded with NUL bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to overwrite.
+    /// * `offset` - The byte offset to start writing at.
+    /// * `value` - What to write.
+    ///
+    /// # Returns
+    ///
+    /// The length of the new value, in bytes.
+    ///
+    /// # Errors
+    ///
+    /// If the new value would be longer than [`MAX_VALUE_LEN`], it will return
+    /// [`MiniRedisError::ValueTooLarge`].
+    /// If the backend cannot be accessed, or has no room for the value, it will return an error.
+    fn set_range(&self, key: &str, offset: usize, value: &str) -> Result<usize, MiniRedisError>;
+
+    /// Sets or clears one bit of the value of a key, atomically.
+    ///
+    /// Bit 0 is the most significant bit of the first byte, like in Redis.
+    /// A missing key counts as an empty value,
+    /// and a value too short for the bit is padded with NUL bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to change.
+    /// * `offset` - The offset of the bit, in bits.
+    /// * `bit` - True to set the bit, false to clear it.
+    ///
+    /// # Returns
+    ///
+    /// The bit before it changed.
+    ///
+    /// # Errors
+    ///
+    /// If the new value would be longer than [`MAX_VALUE_LEN`], it will return
+    /// [`MiniRedisError::ValueTooLarge`].
+    /// If the backend cannot be accessed, or has no room for the value, it will return an error.
+    fn set_bit(&self, key: &str, offset: usize, bit: bool) -> Result<bool, MiniRedisError>;
+
+    /// Gets the value of a key and, in the same step, sets or removes its time to live.
+    ///
+    /// Backends without keys that expire return an error by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to get.
+    /// * `ttl` - The new time to live of the key, or None to make it never expire.
+    ///
+    /// # Returns
+    ///
+    /// The value of the key, or None if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot give keys a time to live, it will return
+    /// [`MiniRedisError::ExpiryNotSupported`].
+    /// If the backend cannot be accessed, it will return an error.
+    fn get_and_expire(
+        &self,
+        key: &str,
+        ttl: Option<Duration>,
+    ) -> Result<Option<Vec<u8>>, MiniRedisError> {
+        let _ = (key, ttl);
+        Err(MiniRedisError::ExpiryNotSupported)
+    }
+
+    /// Sets the time to live of a key, if it exists and the condition holds.
+    ///
+    /// Backends without keys that expire return an error by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to expire.
+    /// * `ttl` - The new time to live of the key. If it is zero, the key expires at once.
+    /// * `condition` - When to set the time to live, or None to always set it.
+    ///
+    /// # Returns
+    ///
+    /// True if the time to live was set, false if the key does not exist
+    /// or the condition does not hold.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot give keys a time to live, it will return
+    /// [`MiniRedisError::ExpiryNotSupported`].
+    /// If the backend cannot be accessed, it will return an error.
+    fn expire(
+        &self,
+        key: &str,
+        ttl: Duration,
+        condition: Option<ExpireCondition>,
+    ) -> Result<bool, MiniRedisError> {
+        let _ = (key, ttl, condition);
+        Err(MiniRedisError::ExpiryNotSupported)
+    }
+
+    /// Removes the time to live of a key, so it never expires.
+    ///
+    /// By default, for backends without keys that expire, there is never one to remove.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to persist.
+    ///
+    /// # Returns
+    ///
+    /// True if a time to live was removed, false if the key has none or does not exist.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot be accessed, it will return an error.
+    fn persist(&self, key: &str) -> Result<bool, MiniRedisError> {
+        let _ = key;
+        Ok(false)
+    }
+
+    /// Gets the remaining time to live of a key.
+    ///
+    /// By default, for backends without keys that expire, no key has one.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to get the time to live for.
+    ///
+    /// # Returns
+    ///
+    /// The time left until the key expires, or None if the key does not exist
+    /// or never expires.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot be accessed, it will return an error.
+    fn ttl(&self, key: &str) -> Result<Option<Duration>, MiniRedisError> {
+        let _ = key;
+        Ok(None)
+    }
+
+    /// Deletes every key that starts with a prefix.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The prefix of the keys to delete.
+    ///
+    /// # Returns
+    ///
+    /// The number of keys that were deleted.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot be accessed, it will return an error.
+    fn del_prefix(&self, prefix: &str) -> Result<usize, MiniRedisError>;
+
+    /// Checks whether a key exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to check.
+    ///
+    /// # Returns
+    ///
+    /// True if the key exists.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot be accessed, it will return an error.
+    fn exists(&self, key: &str) -> Result<bool, MiniRedisError>;
+
+    /// Gets the type of the value of a key, as `TYPE` names it.
+    ///
+    /// Every backend only stores strings so far, so by default this is
+    /// `string` for any key that exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to check.
+    ///
+    /// # Returns
+    ///
+    /// The type of the value, like `string`, or None if the key does not exist.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot be accessed, it will return an error.
+    fn value_type(&self, key: &str) -> Result<Option<&'static str>, MiniRedisError> {
+        Ok(self.exists(key)?.then_some("string"))
+    }
+
+    /// Describes a key without counting as an access to it.
+    ///
+    /// By default, for backends that do not keep track of accesses,
+    /// this reads the value and reports the key as never idle.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to describe.
+    ///
+    /// # Returns
+    ///
+    /// What is known about the key, or None if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot be accessed, it will return an error.
+    fn describe(&self, key: &str) -> Result<Option<KeyInfo>, MiniRedisError> {
+        let Some(value) = self.get_bytes(key)? else {
+            return Ok(None);
+        };
+        Ok(Some(KeyInfo::new(
+            key,
+            &value,
+            Duration::ZERO,
+            self.ttl(key)?,
+        )))
+    }
+
+    /// Records an access of keys without reading them, and counts how many exist.
+    ///
+    /// Backends that evict keys count a touched key as just used.
+    /// By default, for backends that do not, this only counts the keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `keys` - The keys to touch. A key given twice is counted twice.
+    ///
+    /// # Returns
+    ///
+    /// The number of the keys that exist.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot be accessed, it will return an error.
+    fn touch(&self, keys: &[String]) -> Result<usize, MiniRedisError> {
+        let mut count = 0;
+        for key in keys {
+            count += usize::from(self.exists(key)?);
+        }
+        Ok(count)
+    }
+
+    /// Deletes keys, and hands their values over to be freed elsewhere.
+    ///
+    /// Backends that keep values in memory pass every value they delete to `free`,
+    /// instead of dropping it while they hold a lock.
+    /// By default, for backends that do not, this deletes the keys one by one
+    /// and never calls `free`.
+    ///
+    /// # Arguments
+    ///
+    /// * `keys` - The keys to delete. A key given twice is counted once.
+    /// * `free` - Gets the value of every key that was deleted.
+    ///
+    /// # Returns
+    ///
+    /// The number of the keys that existed and were deleted.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot be accessed, it will return an error.
+    fn unlink(
+        &self,
+        keys: &[String],
+        free: &mut dyn FnMut(Vec<u8>),
+    ) -> Result<usize, MiniRedisError> {
+        let _ = free;
+        let mut count = 0;
+        for key in keys {
+            count += usize::from(self.del(key)?);
+        }
+        Ok(count)
+    }
+
+    /// Gets the number of keys.
+    ///
+    /// # Returns
+    ///
+    /// The number of keys.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot be accessed, it will return an error.
+    fn len(&self) -> Result<usize, MiniRedisError>;
+
+    /// Checks whether there are no keys.
+    ///
+    /// # Returns
+    ///
+    /// True if there are no keys.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot be accessed, it will return an error.
+    fn is_empty(&self) -> Result<bool, MiniRedisError> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Counts the keys, and how many of them expire and when.
+    ///
+    /// Backends without times to live count every key as one that never expires.
+    ///
+    /// # Returns
+    ///
+    /// The number of keys, the number of them with a time to live,
+    /// and the average time they have left.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot be accessed, it will return an error.
+    fn keyspace(&self) -> Result<KeyspaceStats, MiniRedisError> {
+        Ok(KeyspaceStats {
+            keys: self.len()?,
+            ..KeyspaceStats::default()
+        })
+    }
+
+    /// Gets all keys.
+    ///
+    /// # Returns
+    ///
+    /// The keys, in no particular order.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot be accessed, it will return an error.
+    fn keys(&self) -> Result<Vec<String>, MiniRedisError>;
+
+    /// Gets a key chosen at random.
+    ///
+    /// # Returns
+    ///
+    /// A random key, or None if there are no keys.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot be accessed, it will return an error.
+    fn random_key(&self) -> Result<Option<String>, MiniRedisError>;
+
+    /// Gets the next batch of keys of an incremental iteration.
+    ///
+    /// An iteration starts with cursor 0 and continues with the returned cursor
+    /// until it is 0 again. Every key that exists during the whole iteration
+    /// is returned at least once. Keys added or removed meanwhile may or may not be.
+    ///
+    /// # Arguments
+    ///
+    /// * `cursor` - The cursor returned by the previous call, or 0 to start.
+    /// * `count` - A hint for how many keys to return.
+    ///
+    /// # Returns
+    ///
+    /// The cursor to continue with and a batch of keys.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot be accessed, it will return an error.
+    fn scan(&self, cursor: u64, count: usize) -> Result<(u64, Vec<String>), MiniRedisError>;
+
+    /// Gets the next batch of keys of an incremental iteration, keeping only the keys
+    /// the options ask for.
+    ///
+    /// The keys are filtered after the batch is picked, so every call looks at about
+    /// `options.count` keys however few of them are kept. A batch can be empty
+    /// while the iteration goes on, and the returned cursor still moves past every key looked at.
+    ///
+    /// # Arguments
+    ///
+    /// * `cursor` - The cursor returned by the previous call, or 0 to start.
+    /// * `options` - Which keys to keep, and how many keys to look at.
+    ///
+    /// # Returns
+    ///
+    /// The cursor to continue with and the kept keys of the batch.
+    ///
+    /// # Errors
+    ///
+    /// If the backend cannot be accessed, it will return an error.
+    fn scan_with(
+        &self,
+        cursor: u64,
+        options: &ScanOptions,
+    ) -> Result<(u64, Vec<String>), MiniRedisError> {
+        let (next, keys) = self.scan(cursor, options.count)?;
+        Ok((
+            next,
+            keys.into_iter().filter(|key| options.keeps(key)).collect(),
+        ))
+    }
+
+    /// Gets the operation counters of the backend, for `INFO stats`.
+    ///
+    /// Backends that do not count their operations report zeros.
+    ///
+    /// # Returns
+    ///
+    /// A copy of the current counters.
+    fn stats(&self) -> StoreStats {
+        StoreStats::default()
+    }
+
+    /// Sets the operation counters of the backend back to zero, for `CONFIG RESETSTAT`.
+    ///
+    /// Backends that do not count their operations have nothing to reset.
+    fn reset_stats(&self) {}
+}
+
+impl Storage for KVStore {
+    fn get(&self, key: &str) -> Result<Option<String>, MiniRedisError> {
+        KVStore::get(self, key)
+    }
+
+    fn get_bytes(&self, key: &str) -> Result<Option<Vec<u8>>, MiniRedisError> {
+        KVStore::get_bytes(self, key)
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), MiniRedisError> {
+        KVStore::set(self, key, value)
+    }
+
+    fn del(&self, key: &str) -> Result<bool, MiniRedisError> {
+        KVStore::del(self, key)
+    }
+
+    fn rename(&self, source: &str, destination: &str) -> Result<(), MiniRedisError> {
+        KVStore::rename(self, source, destination)
+    }
+
+    fn rename_if_absent(&self, source: &str, destination: &str) -> Result<bool, MiniRedisError> {
+        KVStore::rename_if_absent(self, source, destination)
+    }
+
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool, MiniRedisError> {
+        KVStore::set_if_absent(self, key, value)
+    }
+
+    fn set_with(
+        &self,
+        key: &str,
+        value: &str,
+        options: &SetOptions,
+    ) -> Result<bool, MiniRedisError> {
+        KVStore::set_with(self, key, value, options)
+    }
+
+    fn set_returning_old(&self, key: &str, value: &str) -> Result<Option<Vec<u8>>, MiniRedisError> {
+        KVStore::set_returning_old(self, key, value)
+    }
+
+    fn multi_set(&self, pairs: &[(String, String)]) -> Result<(), MiniRedisError> {
+        KVStore::multi_set(self, pairs)
+    }
+
+    fn multi_set_if_absent(&self, pairs: &[(String, String)]) -> Result<bool, MiniRedisError> {
+        KVStore::multi_set_if_absent(self, pairs)
+    }
+
+    fn increment(&self, key: &str, delta: i64) -> Result<i64, MiniRedisError> {
+        KVStore::increment(self, key, delta)
+    }
+
+    fn increment_float(&self, key: &str, delta: f64) -> Result<f64, MiniRedisError> {
+        KVStore::increment_float(self, key, delta)
+    }
+
+    fn append(&self, key: &str, suffix: &str) -> Result<usize, MiniRedisError> {
+        KVStore::append(self, key, suffix)
+    }
+
+    fn set_range(&self, key: &str, offset: usize, value: &str) -> Result<usize, MiniRedisError> {
+        KVStore::set_range(self, key, offset, value)
+    }
+
+    fn set_bit(&self, key: &str, offset: usize, bit: bool) -> Result<bool, MiniRedisError> {
+        KVStore::set_bit(self, key, offset, bit)
+    }
+
+    fn get_and_expire(
+        &self,
+        key: &str,
+        ttl: Option<Duration>,
+    ) -> Result<Option<Vec<u8>>, MiniRedisError> {
+        KVStore::get_and_expire(self, key, ttl)
+    }
+
+    fn expire(
+        &self,
+        key: &str,
+        ttl: Duration,
+        condition: Option<ExpireCondition>,
+    ) -> Result<bool, MiniRedisError> {
+        KVStore::expire(self, key, ttl, condition)
+    }
+
+    fn persist(&self, key: &str) -> Result<bool, MiniRedisError> {
+        KVStore::persist(self, key)
+    }
+
+    fn ttl(&self, key: &str) -> Result<Option<Duration>, MiniRedisError> {
+        KVStore::ttl(self, key)
+    }
+
+    fn del_prefix(&self, prefix: &str) -> Result<usize, MiniRedisError> {
+        KVStore::del_prefix(self, prefix)
+    }
+
+    fn exists(&self, key: &str) -> Result<bool, MiniRedisError> {
+        KVStore::exists(self, key)
+    }
+
+    fn value_type(&self, key: &str) -> Result<Option<&'static str>, MiniRedisError> {
+        KVStore::value_type(self, key)
+    }
+
+    fn describe(&self, key: &str) -> Result<Option<KeyInfo>, MiniRedisError> {
+        KVStore::describe(self, key)
+    }
+
+    fn touch(&self, keys: &[String]) -> Result<usize, MiniRedisError> {
+        KVStore::touch(self, keys)
+    }
+
+    fn unlink(
+        &self,
+        keys: &[String],
+        free: &mut dyn FnMut(Vec<u8>),
+    ) -> Result<usize, MiniRedisError> {
+        KVStore::unlink(self, keys, free)
+    }
+
+    fn len(&self) -> Result<usize, MiniRedisError> {
+        KVStore::len(self)
+    }
+
+    fn keyspace(&self) -> Result<KeyspaceStats, MiniRedisError> {
+        KVStore::keyspace(self)
+    }
+
+    fn keys(&self) -> Result<Vec<String>, MiniRedisError> {
+        KVStore::keys(self)
+    }
+
+    fn random_key(&self) -> Result<Option<String>, MiniRedisError> {
+        KVStore::random_key(self)
+    }
+
+    fn scan(&self, cursor: u64, count: usize) -> Result<(u64, Vec<String>), MiniRedisError> {
+        KVStore::scan(self, cursor, count)
+    }
+
+    fn stats(&self) -> StoreStats {
+        KVStore::stats(self)
+    }
+
+    fn reset_stats(&self) {
+        KVStore::reset_stats(self)
+    }
+}
+
+/// Adds to an integer stored as text, for [`Storage::increment`].
+///
+/// # Arguments
+///
+/// * `value` - The stored value.
+/// * `delta` - What to add.
+///
+/// # Returns
+///
+/// The sum.
+///
+/// # Errors
+///
+/// If the value is not a 64-bit integer, it will return [`MiniRedisError::NotAnInteger`],
+/// and if the sum would not fit in one, [`MiniRedisError::IncrementOverflow`].
+pub(crate) fn add_to_integer(value: &str, delta: i64) -> Result<i64, MiniRedisError> {
+    value
+        .parse::<i64>()
+        .map_err(|_| MiniRedisError::NotAnInteger)?
+        .checked_add(delta)
+        .ok_or(MiniRedisError::IncrementOverflow)
+}
+
+/// The longest a value can grow to through [`Storage::set_range`], like in Redis.
+pub const MAX_VALUE_LEN: usize = 512 * 1024 * 1024;
+
+/// Turns the bytes of a value into text.
+///
+/// Bytes that are not valid UTF-8 are replaced with U+FFFD, the way
+/// [`Storage::get`] reads values that [`Storage::set_bit`] made binary.
+///
+/// # Arguments
+///
+/// * `value` - The bytes of the value.
+///
+/// # Returns
+///
+/// The value as text.
+pub(crate) fn text(value: Vec<u8>) -> String {
+    String::from_utf8(value)
+        .unwrap_or_else(|error| String::from_utf8_lossy(error.as_bytes()).into_owned())
+}
+
+/// Overwrites part of a value from a byte offset on, for [`Storage::set_range`].
+///
+/// # Arguments
+///
+/// * `current` - The stored value, empty if the key is missing.
+/// * `offset` - The byte offset to start writing at.
+/// * `value` - What to write.
+///
+/// # Returns
+///
+/// The new value, padded with NUL bytes up to the offset if it was shorter.
+///
+/// # Errors
+///
+/// If the new value would be longer than [`MAX_VALUE_LEN`], it will return
+/// [`MiniRedisError::ValueTooLarge`].
+pub(crate) fn overwrite_range(
+    current: &[u8],
+    offset: usize,
+    value: &[u8],
+) -> Result<Vec<u8>, MiniRedisError> {
+    let end = offset
+        .checked_add(value.len())
+        .filter(|end| *end <= MAX_VALUE_LEN)
+        .ok_or(MiniRedisError::ValueTooLarge)?;
+    let mut bytes = current.to_vec();
+    if bytes.len() < end {
+        bytes.resize(end, 0);
+    }
+    bytes[offset..end].copy_from_slice(value);
+    Ok(bytes)
+}
+
+/// Finds a bit of a value, for `SETBIT` and `GETBIT`.
+///
+/// # Arguments
+///
+/// * `offset` - The offset of the bit, in bits, from the most significant bit of the first byte.
+///
+/// # Returns
+///
+/// The index of the byte holding the bit, and the mask of the bit in it.
+///
+/// # Errors
+///
+/// If the byte is past the longest a value can be, [`MAX_VALUE_LEN`],
+/// it will return [`MiniRedisError::ValueTooLarge`].
+fn bit_position(offset: usize) -> Result<(usize, u8), MiniRedisError> {
+    let byte = offset / 8;
+    if byte >= MAX_VALUE_LEN {
+        return Err(MiniRedisError::ValueTooLarge);
+    }
+    Ok((byte, 0x80 >> (offset % 8)))
+}
+
+/// Reads a bit of a value, for `GETBIT`.
+///
+/// # Arguments
+///
+/// * `value` - The value, empty if the key is missing.
+/// * `offset` - The offset of the bit, in bits.
+///
+/// # Returns
+///
+/// The bit, false if it is past the end of the value.
+///
+/// # Errors
+///
+/// If the offset is past the longest a value can be, it will return
+/// [`MiniRedisError::ValueTooLarge`].
+pub(crate) fn read_bit(value: &[u8], offset: usize) -> Result<bool, MiniRedisError> {
+    let (byte, mask) = bit_position(offset)?;
+    Ok(value.get(byte).is_some_and(|byte| byte & mask != 0))
+}
+
+/// Sets or clears a bit of a value, for [`Storage::set_bit`].
+///
+/// # Arguments
+///
+/// * `current` - The stored value, empty if the key is missing.
+/// * `offset` - The offset of the bit, in bits.
+/// * `bit` - True to set the bit, false to clear it.
+///
+/// # Returns
+///
+/// The new value, padded with NUL bytes up to the bit if it was shorter,
+/// and the bit before it changed.
+///
+/// # Errors
+///
+/// If the new value would be longer than [`MAX_VALUE_LEN`], it will return
+/// [`MiniRedisError::ValueTooLarge`].
+pub(crate) fn write_bit(
+    current: &[u8],
+    offset: usize,
+    bit: bool,
+) -> Result<(Vec<u8>, bool), MiniRedisError> {
+    let (byte, mask) = bit_position(offset)?;
+    let mut bytes = current.to_vec();
+    if bytes.len() <= byte {
+        bytes.resize(byte + 1, 0);
+    }
+    let previous = bytes[byte] & mask != 0;
+    if bit {
+        bytes[byte] |= mask;
+    } else {
+        bytes[byte] &= !mask;
+    }
+    Ok((bytes, previous))
+}
+
+/// Finds the first set or clear bit of a value in a range of bytes, for `BITPOS`.
+///
+/// Like in Redis, when no clear bit is found and the range has no end given,
+/// the value counts as padded with clear bits, so the bit right after it is returned.
+///
+/// # Arguments
+///
+/// * `value` - The bytes of the value.
+/// * `bit` - True to find a set bit, false to find a clear one.
+/// * `start` - The index of the first byte, which counts from the end if negative.
+/// * `end` - The index of the last byte, which counts from the end if negative,
+///   or None for the last byte of the value.
+///
+/// # Returns
+///
+/// The offset of the bit, in bits from the start of the value, or -1 if there is none.
+pub(crate) fn find_bit(value: &[u8], bit: bool, start: i64, end: Option<i64>) -> i64 {
+    let Some(range) = byte_range(value.len(), start, end.unwrap_or(-1)) else {
+        return -1;
+    };
+    // Whole bytes without the bit are skipped, and the bit is found in the first other one.
+    let skip = if bit { 0x00 } else { 0xff };
+    let found = value[range.clone()]
+        .iter()
+        .position(|byte| *byte != skip)
+        .map(|index| {
+            let byte = value[range.start + index];
+            let byte = if bit { byte } else { !byte };
+            (range.start + index) * 8 + byte.leading_zeros() as usize
+        });
+    match found {
+        Some(offset) => offset as i64,
+        None if !bit && end.is_none() => (range.end * 8) as i64,
+        None => -1,
+    }
+}
+
+/// Turns the inclusive indexes of `GETRANGE` into a range of bytes of a value.
+///
+/// Negative indexes count from the end, so -1 is the last byte,
+/// and indexes past either end are clamped to it.
+///
+/// # Arguments
+///
+/// * `len` - The length of the value, in bytes.
+/// * `start` - The index of the first byte.
+/// * `end` - The index of the last byte.
+///
+/// # Returns
+///
+/// The range of bytes, or None if it is empty.
+pub(crate) fn byte_range(len: usize, start: i64, end: i64) -> Option<Range<usize>> {
+    let len = i64::try_from(len).unwrap_or(i64::MAX);
+    let resolve = |index: i64| {
+        if index < 0 {
+            len.saturating_add(index).max(0)
+        } else {
+            index
+        }
+    };
+    let (start, end) = (resolve(start), resolve(end).min(len - 1));
+    (start <= end).then(|| start as usize..end as usize + 1)
+}
+
+/// Parses a floating point number like `INCRBYFLOAT` does, refusing NaN and infinity.
+///
+/// # Arguments
+///
+/// * `value` - The text of the number.
+///
+/// # Returns
+///
+/// The number.
+///
+/// # Errors
+///
+/// If the text is not a finite number, it will return [`MiniRedisError::NotAFloat`].
+pub(crate) fn parse_float(value: &str) -> Result<f64, MiniRedisError> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|value| value.is_finite())
+        .ok_or(MiniRedisError::NotAFloat)
+}
+
+/// Adds to a floating point number stored as text, for [`Storage::increment_float`].
+///
+/// # Arguments
+///
+/// * `value` - The stored value.
+/// * `delta` - What to add.
+///
+/// # Returns
+///
+/// The sum.
+///
+/// # Errors
+///
+/// If the value is not a finite number, it will return [`MiniRedisError::NotAFloat`],
+/// and if the sum is not finite, [`MiniRedisError::IncrementNotFinite`].
+pub(crate) fn add_to_float(value: &str, delta: f64) -> Result<f64, MiniRedisError> {
+    Some(parse_float(value)? + delta)
+        .filter(|sum| sum.is_finite())
+        .ok_or(MiniRedisError::IncrementNotFinite)
+}
+
+/// The types a value can have in Redis, which `SCAN ... TYPE` accepts.
+pub(crate) const TYPES: &[&str] = &["string", "list", "set", "zset", "hash", "stream"];
+
+/// When a set writes its value, like the `NX` and `XX` options of `SET`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetCondition {
+    /// Only if the key does not exist.
+    IfAbsent,
+    /// Only if the key exists.
+    IfPresent,
+}
+
+/// When an expire sets the time to live of a key,
+/// like the `NX`, `XX`, `GT` and `LT` options of `EXPIRE`.
+///
+/// A key without a time to live never expires, so it counts as later than any deadline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpireCondition {
+    /// Only if the key has no time to live.
+    IfNone,
+    /// Only if the key has a time to live.
+    IfSome,
+    /// Only if the new deadline is later than the current one.
+    IfLater,
+    /// Only if the new deadline is earlier than the current one.
+    IfEarlier,
+    /// Only if the key has a time to live, and the new deadline is earlier than it,
+    /// like `XX` and `LT` together.
+    IfSomeEarlier,
+}
+
+impl ExpireCondition {
+    /// Checks whether an expire may replace the deadline of a key.
+    ///
+    /// # Arguments
+    ///
+    /// * `current` - The deadline of the key, or None if it never expires.
+    /// * `new` - The deadline to set.
+    ///
+    /// # Returns
+    ///
+    /// True if the new deadline may be set.
+    pub(crate) fn allows(self, current: Option<Instant>, new: Instant) -> bool {
+        // No deadline sorts after every deadline.
+        let order = |at: Option<Instant>| (at.is_none(), at);
+        match self {
+            ExpireCondition::IfNone => current.is_none(),
+            ExpireCondition::IfSome => current.is_some(),
+            ExpireCondition::IfLater => order(Some(new)) > order(current),
+            ExpireCondition::IfEarlier => order(Some(new)) < order(current),
+            ExpireCondition::IfSomeEarlier => current.is_some_and(|current| new < current),
+        }
+    }
+}
+
+/// What a set does with the time to live of the key,
+/// like the `EX`, `PX` and `KEEPTTL` options of `SET`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SetExpiry {
+    /// The key never expires, even if it had a time to live before.
+    #[default]
+    Persist,
+    /// The key keeps the time to live it had, if any.
+    Keep,
+    /// The key expires after this long.
+    After(Duration),
+}
+
+/// How a set writes its value, like the options of `SET`.
+///
+/// # Examples
+///
+/// ```rust
+/// use miniredis::kv_store::KVStore;
+/// use miniredis::storage::{SetCondition, SetOptions, Storage};
+///
+/// let store = KVStore::new();
+/// let options = SetOptions {
+///     condition: Some(SetCondition::IfAbsent),
+///     ..SetOptions::default()
+/// };
+///
+/// assert_eq!(Ok(true), Storage::set_with(&store, "lock", "alice", &options));
+/// assert_eq!(Ok(false), Storage::set_with(&store, "lock", "bob", &options));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SetOptions {
+    /// When to write the value, or None to always write it.
+    pub condition: Option<SetCondition>,
+    /// What happens to the time to live of the key.
+    pub expiry: SetExpiry,
+}
+
+impl SetOptions {
+    /// Checks whether a set writes its value.
+    ///
+    /// # Arguments
+    ///
+    /// * `exists` - Whether the key exists.
+    ///
+    /// # Returns
+    ///
+    /// True if the key meets the condition, false otherwise.
+    pub fn allows(&self, exists: bool) -> bool {
+        match self.condition {
+            None => true,
+            Some(SetCondition::IfAbsent) => !exists,
+            Some(SetCondition::IfPresent) => exists,
+        }
+    }
+}
+
+/// Which keys a scan keeps, and how many keys it looks at per call,
+/// like the `MATCH`, `TYPE` and `COUNT` options of `SCAN`.
+///
+/// # Examples
+///
+/// ```rust
+/// use miniredis::kv_store::KVStore;
+/// use miniredis::storage::{ScanOptions, Storage};
+///
+/// let store = KVStore::new();
+/// store.set("session:1", "alice").unwrap();
+/// store.set("user:1", "alice").unwrap();
+///
+/// let options = ScanOptions {
+///     pattern: Some("session:*".to_string()),
+///     count: 100,
+///     ..ScanOptions::default()
+/// };
+///
+/// assert_eq!(Ok((0, vec!["session:1".to_string()])), store.scan_with(0, &options));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanOptions {
+    /// A glob pattern the keys must match, like `session:*`, or None for every key.
+    pub pattern: Option<String>,
+    /// The type the values must have, like `string`, or None for every type.
+    pub kind: Option<String>,
+    /// A hint for how many keys to look at per call.
+    pub count: usize,
+}
+
+impl Default for ScanOptions {
+    /// Creates options that keep every key and look at 10 keys per call, like plain `SCAN`.
+    ///
+    /// # Returns
+    ///
+    /// The default options.
+    fn default() -> Self {
+        Self {
+            pattern: None,
+            kind: None,
+            count: 10,
+        }
+    }
+}
+
+impl ScanOptions {
+    /// Checks whether a scan keeps a key.
+    ///
+    /// Every value is a string, so a type of `string` keeps every key, and any other type none.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key.
+    ///
+    /// # Returns
+    ///
+    /// True if the key matches the pattern and its value has the type, false otherwise.
+    pub fn keeps(&self, key: &str) -> bool {
+        self.pattern
+            .as_ref()
+            .is_none_or(|pattern| glob::matches(pattern, key))
+            && self
+                .kind
+                .as_ref()
+                .is_none_or(|kind| kind.eq_ignore_ascii_case("string"))
+    }
+}
+
+/// What a backend knows about a key, like `OBJECT ENCODING` and `DEBUG OBJECT` report.
+///
+/// # Examples
+///
+/// ```rust
+/// use miniredis::kv_store::KVStore;
+///
+/// let store = KVStore::new();
+/// store.set("counter", "42").unwrap();
+///
+/// let info = store.describe("counter").unwrap().unwrap();
+///
+/// assert_eq!("int", info.encoding);
+/// assert_eq!(2, info.serialized_length);
+/// assert_eq!(None, info.ttl);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyInfo {
+    /// How the value is encoded, `int` for an integer and `raw` for any other string.
+    pub encoding: &'static str,
+    /// The length of the value in bytes.
+    pub serialized_length: usize,
+    /// The estimated number of bytes the key and its value use in memory.
+    pub memory: usize,
+    /// How long ago the key was last read or written, or zero if the backend does not know.
+    pub idle: Duration,
+    /// The time to live left, or None if the key never expires.
+    pub ttl: Option<Duration>,
+}
+
+impl KeyInfo {
+    /// Describes a key from its value.
+    ///
+    /// Like in Redis, a value is an `int` only if it is an integer written the way
+    /// Redis would write it, so `12` is one, but `012` and `+12` are not.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key.
+    /// * `value` - The value of the key.
+    /// * `idle` - How long ago the key was last read or written.
+    /// * `ttl` - The time to live left, or None if the key never expires.
+    ///
+    /// # Returns
+    ///
+    /// What is known about the key.
+    pub(crate) fn new(key: &str, value: &[u8], idle: Duration, ttl: Option<Duration>) -> Self {
+        let integer = std::str::from_utf8(value).is_ok_and(|value| {
+            value
+                .parse::<i64>()
+                .is_ok_and(|integer| integer.to_string() == value)
+        });
+        Self {
+            encoding: if integer { "int" } else { "raw" },
+            serialized_length: value.len(),
+            memory: key.len() + value.len() + kv_store::ENTRY_OVERHEAD,
+            idle,
+            ttl,
+        }
+    }
+}
+
+/// Computes the hash that orders keys during a scan.
+///
+/// This is 64-bit FNV-1a, which unlike the hasher of a `HashMap` is
+/// the same for every backend and every run, so cursors stay valid.
+///
+/// # Arguments
+///
+/// * `key` - The key to hash.
+///
+/// # Returns
+///
+/// The hash of the key.
+pub(crate) fn scan_hash(key: &str) -> u64 {
+    key.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// The keys of a backend in the order a scan visits them.
+///
+/// Keys are ordered by [`scan_hash`], and the cursor of a scan is the hash
+/// to continue from, so a backend needs no iteration state to implement
+/// [`Storage::scan`], and a call only looks at the keys it returns.
+#[derive(Clone, Default)]
+pub(crate) struct ScanIndex {
+    keys: BTreeSet<(u64, String)>,
+}
+
+impl ScanIndex {
+    /// Adds a key to the index.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to add.
+    pub(crate) fn insert(&mut self, key: &str) {
+        self.keys.insert((scan_hash(key), key.to_string()));
+    }
+
+    /// Removes a key from the index.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to remove.
+    pub(crate) fn remove(&mut self, key: &str) {
+        self.keys.remove(&(scan_hash(key), key.to_string()));
+    }
+
+    /// Picks the next batch of a scan.
+    ///
+    /// Keys with the same hash are never split across batches,
+    /// so a batch can hold more than `count` keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `cursor` - The cursor returned by the previous call, or 0 to start.
+    /// * `count` - How many keys to look at, at least 1.
+    /// * `keep` - Whether to return a key that was looked at, like whether it is still live.
+    ///
+    /// # Returns
+    ///
+    /// The cursor to continue with, 0 if the scan is done, and a batch of keys.
+    pub(crate) fn scan(
+        &self,
+        cursor: u64,
+        count: usize,
+        keep: impl Fn(&str) -> bool,
+    ) -> (u64, Vec<String>) {
+        let count = count.max(1);
+        let mut batch = Vec::new();
+        let mut last = None;
+        for (seen, (hash, key)) in self.keys.range((cursor, String::new())..).enumerate() {
+            // The next hash is above the last one looked at, so it is never 0.
+            if seen >= count && last != Some(*hash) {
+                return (*hash, batch);
+            }
+            last = Some(*hash);
+            if keep(key) {
+                batch.push(key.clone());
+            }
+        }
+        (0, batch)
+    }
+}
+
+impl<'a> FromIterator<&'a String> for ScanIndex {
+    /// Builds the index of some keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `keys` - The keys to index.
+    fn from_iter<I: IntoIterator<Item = &'a String>>(keys: I) -> Self {
+        Self {
+            keys: keys
+                .into_iter()
+                .map(|key| (scan_hash(key), key.clone()))
+                .collect(),
+        }
+    }
+}
+
+/// A storage backend that logs every operation before passing it on.
+///
+/// This is mostly useful for debugging, or as a starting point
+/// for writing a backend of your own.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use miniredis::kv_store::KVStore;
+/// use miniredis::server::Server;
+/// use miniredis::storage::LoggingStorage;
+///
+/// let server = Server::with_storage("127.0.0.1:6379", LoggingStorage::new(KVStore::new()));
+/// server.run();
+/// ```
+pub struct LoggingStorage<S> {
+    inner: S,
+}
+
+impl<S: Storage> LoggingStorage<S> {
+    /// Creates a new logging backend.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The backend to pass the operations on to.
+    ///
+    /// # Returns
+    ///
+    /// A new logging backend.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    /// use miniredis::storage::{LoggingStorage, Storage};
+    ///
+    /// let storage = LoggingStorage::new(KVStore::new());
+    /// storage.set("key", "value").unwrap();
+    /// ```
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    /// Gets the backend the operations are passed on to.
+    ///
+    /// # Returns
+    ///
+    /// A reference to the inner backend.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    /// use miniredis::storage::{LoggingStorage, Storage};
+    ///
+    /// let storage = LoggingStorage::new(KVStore::new());
+    /// storage.set("key", "value").unwrap();
+    ///
+    /// assert_eq!(Ok(1), storage.inner().len());
+    /// ```
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Logs an operation.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - A description of the operation.
+    fn log(&self, operation: &str) {
+        eprintln!("[storage] {}", operation);
+    }
+}
+
+impl<S: Storage> Storage for LoggingStorage<S> {
+    fn get(&self, key: &str) -> Result<Option<String>, MiniRedisError> {
+        self.log(&format!("GET {}", key));
+        self.inner.get(key)
+    }
+
+    fn get_bytes(&self, key: &str) -> Result<Option<Vec<u8>>, MiniRedisError> {
+        self.log(&format!("GET {}", key));
+        self.inner.get_bytes(key)
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), MiniRedisError> {
+        self.log(&format!("SET {} ({} bytes)", key, value.len()));
+        self.inner.set(key, value)
+    }
+
+    fn del(&self, key: &str) -> Result<bool, MiniRedisError> {
+        self.log(&format!("DEL {}", key));
+        self.inner.del(key)
+    }
+
+    fn rename(&self, source: &str, destination: &str) -> Result<(), MiniRedisError> {
+        self.log(&format!("RENAME {} {}", source, destination));
+        self.inner.rename(source, destination)
+    }
+
+    fn rename_if_absent(&self, source: &str, destination: &str) -> Result<bool, MiniRedisError> {
+        self.log(&format!("RENAMENX {} {}", source, destination));
+        self.inner.rename_if_absent(source, destination)
+    }
+
+    fn set_if_absent(&self, key: &str, value: &str) -> Result<bool, MiniRedisError> {
+        self.log(&format!("SETNX {} ({} bytes)", key, value.len()));
+        self.inner.set_if_absent(key, value)
+    }
+
+    fn set_with(
+        &self,
+        key: &str,
+        value: &str,
+        options: &SetOptions,
+    ) -> Result<bool, MiniRedisError> {
+        self.log(&format!(
+            "SET {} ({} bytes) {:?}",
+            key,
+            value.len(),
+            options
+        ));
+        self.inner.set_with(key, value, options)
+    }
+
+    fn set_returning_old(&self, key: &str, value: &str) -> Result<Option<Vec<u8>>, MiniRedisError> {
+        self.log(&format!("GETSET {} ({} bytes)", key, value.len()));
+        self.inner.set_returning_old(key, value)
+    }
+
+    fn multi_set(&self, pairs: &[(String, String)]) -> Result<(), MiniRedisError> {
+        self.log(&format!("MSET ({} keys)", pairs.len()));
+        self.inner.multi_set(pairs)
+    }
+
+    fn multi_set_if_absent(&self, pairs: &[(String, String)]) -> Result<bool, MiniRedisError> {
+        self.log(&format!("MSETNX ({} keys)", pairs.len()));
+        self.inner.multi_set_if_absent(pairs)
+    }
+
+    fn increment(&self, key: &str, delta: i64) -> Result<i64, MiniRedisError> {
+        self.log(&format!("INCRBY {} {}", key, delta));
+        self.inner.increment(key, delta)
+    }
+
+    fn increment_float(&self, key: &str, delta: f64) -> Result<f64, MiniRedisError> {
+        self.log(&format!("INCRBYFLOAT {} {}", key, delta));
+        self.inner.increment_float(key, delta)
+    }
+
+    fn append(&self, key: &str, suffix: &str) -> Result<usize, MiniRedisError> {
+        self.log(&format!("APPEND {} ({} bytes)", key, suffix.len()));
+        self.inner.append(key, suffix)
+    }
+
+    fn set_range(&self, key: &str, offset: usize, value: &str) -> Result<usize, MiniRedisError> {
+        self.log(&format!(
+            "SETRANGE {} {} ({} bytes)",
+            key,
+            offset,
+            value.len()
+        ));
+        self.inner.set_range(key, offset, value)
+    }
+
+    fn set_bit(&self, key: &str, offset: usize, bit: bool) -> Result<bool, MiniRedisError> {
+        self.log(&format!("SETBIT {} {} {}", key, offset, u8::from(bit)));
+        self.inner.set_bit(key, offset, bit)
+    }
+
+    fn get_and_expire(
+        &self,
+        key: &str,
+        ttl: Option<Duration>,
+    ) -> Result<Option<Vec<u8>>, MiniRedisError> {
+        self.log(&format!("GETEX {} {:?}", key, ttl));
+        self.inner.get_and_expire(key, ttl)
+    }
+
+    fn expire(
+        &self,
+        key: &str,
+        ttl: Duration,
+        condition: Option<ExpireCondition>,
+    ) -> Result<bool, MiniRedisError> {
+        self.log(&format!("EXPIRE {} {:?} {:?}", key, ttl, condition));
+        self.inner.expire(key, ttl, condition)
+    }
+
+    fn persist(&self, key: &str) -> Result<bool, MiniRedisError> {
+        self.log(&format!("PERSIST {}", key));
+        self.inner.persist(key)
+    }
+
+    fn ttl(&self, key: &str) -> Result<Option<Duration>, MiniRedisError> {
+        self.log(&format!("TTL {}", key));
+        self.inner.ttl(key)
+    }
+
+    fn del_prefix(&self, prefix: &str) -> Result<usize, MiniRedisError> {
+        self.log(&format!("DELPREFIX {}", prefix));
+        self.inner.del_prefix(prefix)
+    }
+
+    fn exists(&self, key: &str) -> Result<bool, MiniRedisError> {
+        self.log(&format!("EXISTS {}", key));
+        self.inner.exists(key)
+    }
+
+    fn value_type(&self, key: &str) -> Result<Option<&'static str>, MiniRedisError> {
+        self.log(&format!("TYPE {}", key));
+        self.inner.value_type(key)
+    }
+
+    fn describe(&self, key: &str) -> Result<Option<KeyInfo>, MiniRedisError> {
+        self.log(&format!("DESCRIBE {}", key));
+        self.inner.describe(key)
+    }
+
+    fn touch(&self, keys: &[String]) -> Result<usize, MiniRedisError> {
+        self.log(&format!("TOUCH ({} keys)", keys.len()));
+        self.inner.touch(keys)
+    }
+
+    fn unlink(
+        &self,
+        keys: &[String],
+        free: &mut dyn FnMut(Vec<u8>),
+    ) -> Result<usize, MiniRedisError> {
+        self.log(&format!("UNLINK ({} keys)", keys.len()));
+        self.inner.unlink(keys, free)
+    }
+
+    fn len(&self) -> Result<usize, MiniRedisError> {
+        self.log("LEN");
+        self.inner.len()
+    }
+
+    fn keyspace(&self) -> Result<KeyspaceStats, MiniRedisError> {
+        self.log("KEYSPACE");
+        self.inner.keyspace()
+    }
+
+    fn keys(&self) -> Result<Vec<String>, MiniRedisError> {
+        self.log("KEYS");
+        self.inner.keys()
+    }
+
+    fn random_key(&self) -> Result<Option<String>, MiniRedisError> {
+        self.log("RANDOMKEY");
+        self.inner.random_key()
+    }
+
+    fn scan(&self, cursor: u64, count: usize) -> Result<(u64, Vec<String>), MiniRedisError> {
+        self.log(&format!("SCAN {} COUNT {}", cursor, count));
+        self.inner.scan(cursor, count)
+    }
+
+    fn stats(&self) -> StoreStats {
+        self.inner.stats()
+    }
+
+    fn reset_stats(&self) {
+        self.log("RESETSTAT");
+        self.inner.reset_stats()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs every trait operation against a backend, through a trait object.
+    fn exercise(storage: &dyn Storage) {
+        storage.set("a", "1").unwrap();
+        storage.set("b", "2").unwrap();
+        storage.set("prefix:c", "3").unwrap();
+
+        assert_eq!(Ok(Some("1".to_string())), storage.get("a"));
+        assert_eq!(Ok(true), storage.exists("b"));
+        assert_eq!(Ok(3), storage.len());
+
+        let mut keys = storage.keys().unwrap();
+        keys.sort();
+        assert_eq!(vec!["a", "b", "prefix:c"], keys);
+
+        assert_eq!(Ok(1), storage.del_prefix("prefix:"));
+        storage.del("a").unwrap();
+        assert_eq!(Ok(false), storage.exists("a"));
+
+        assert_eq!(Ok(5), storage.increment("n", 5));
+        assert_eq!(Ok(3), storage.increment("n", -2));
+        storage.set("n", "three").unwrap();
+        assert_eq!(Err(MiniRedisError::NotAnInteger), storage.increment("n", 1));
+        assert_eq!(
+            Err(MiniRedisError::NotAFloat),
+            storage.increment_float("n", 1.0)
+        );
+        storage.set("n", "1.5").unwrap();
+        assert_eq!(Ok(1.75), storage.increment_float("n", 0.25));
+        assert_eq!(Ok(6), storage.append("n", "ab"));
+        assert_eq!(Ok(false), storage.set_if_absent("n", "y"));
+        assert_eq!(Ok(true), storage.set_if_absent("m", "y"));
+        let if_present = SetOptions {
+            condition: Some(SetCondition::IfPresent),
+            ..SetOptions::default()
+        };
+        assert_eq!(Ok(true), storage.set_with("m", "z", &if_present));
+        assert_eq!(Ok(false), storage.set_with("o", "z", &if_present));
+        storage.del("m").unwrap();
+        assert_eq!(
+            Ok(Some(b"1.75ab".to_vec())),
+            storage.set_returning_old("n", "x")
+        );
+        storage.del("n").unwrap();
+        assert_eq!(Ok(false), storage.is_empty());
+    }
+
+    #[test]
+    fn byte_range_keeps_indexes_inside_the_value() {
+        assert_eq!(Some(0..5), byte_range(5, 0, 4));
+        assert_eq!(Some(1..3), byte_range(5, 1, 2));
+        assert_eq!(Some(2..3), byte_range(5, 2, 2));
+    }
+
+    #[test]
+    fn byte_range_counts_negative_indexes_from_the_end() {
+        assert_eq!(Some(0..5), byte_range(5, 0, -1));
+        assert_eq!(Some(2..5), byte_range(5, -3, -1));
+        assert_eq!(Some(1..4), byte_range(5, 1, -2));
+        assert_eq!(Some(4..5), byte_range(5, -1, 4));
+        assert_eq!(Some(0..2), byte_range(5, -100, 1));
+        assert_eq!(Some(0..1), byte_range(5, i64::MIN, -100));
+    }
+
+    #[test]
+    fn byte_range_clamps_the_end_to_the_last_byte() {
+        assert_eq!(Some(0..5), byte_range(5, 0, 100));
+        assert_eq!(Some(3..5), byte_range(5, 3, i64::MAX));
+        assert_eq!(Some(0..5), byte_range(5, -5, 5));
+    }
+
+    #[test]
+    fn byte_range_is_empty_when_out_of_range_or_inverted() {
+        assert_eq!(None, byte_range(5, 5, 10));
+        assert_eq!(None, byte_range(5, 100, -1));
+        assert_eq!(None, byte_range(5, 3, 1));
+        assert_eq!(None, byte_range(5, -1, -2));
+        assert_eq!(None, byte_range(0, 0, -1));
+        assert_eq!(None, byte_range(0, 0, 0));
+    }
+
+    #[test]
+    fn overwrite_range_pads_overwrites_and_extends() {
+        assert_eq!(Ok(b"\0\0\0abc".to_vec()), overwrite_range(b"", 3, b"abc"));
+        assert_eq!(Ok(b"hi\0\0yo".to_vec()), overwrite_range(b"hi", 4, b"yo"));
+        assert_eq!(
+            Ok(b"Hello Redis".to_vec()),
+            overwrite_range(b"Hello World", 6, b"Redis")
+        );
+        assert_eq!(Ok(b"heLLo".to_vec()), overwrite_range(b"hello", 2, b"LL"));
+        assert_eq!(
+            Ok(b"hello world".to_vec()),
+            overwrite_range(b"hello", 3, b"lo world")
+        );
+        assert_eq!(Ok(b"hello".to_vec()), overwrite_range(b"hello", 0, b""));
+    }
+
+    #[test]
+    fn overwrite_range_refuses_values_too_large() {
+        assert_eq!(
+            Err(MiniRedisError::ValueTooLarge),
+            overwrite_range(b"", MAX_VALUE_LEN, b"x")
+        );
+        assert_eq!(
+            Err(MiniRedisError::ValueTooLarge),
+            overwrite_range(b"", usize::MAX, b"x")
+        );
+    }
+
+    #[test]
+    fn overwrite_range_splits_characters_into_their_bytes() {
+        assert_eq!(
+            Ok(vec![0xc3, b'x']),
+            overwrite_range("é".as_bytes(), 1, b"x")
+        );
+    }
+
+    #[test]
+    fn write_bit_pads_and_reports_the_previous_bit() {
+        assert_eq!(Ok((b"\0\0\x02".to_vec(), false)), write_bit(b"", 22, true));
+        assert_eq!(Ok((b"`".to_vec(), true)), write_bit(b"a", 7, false));
+        assert_eq!(Ok((b"a".to_vec(), true)), write_bit(b"a", 1, true));
+        assert_eq!(Ok((b"a\0".to_vec(), false)), write_bit(b"a", 15, false));
+        assert_eq!(Ok(true), read_bit(b"a", 7));
+        assert_eq!(Ok(false), read_bit(b"a", 0));
+        assert_eq!(Ok(false), read_bit(b"a", 1000));
+    }
+
+    #[test]
+    fn write_bit_sets_any_bit_of_any_byte() {
+        assert_eq!(Ok((vec![0xe1], false)), write_bit(b"a", 0, true));
+        assert_eq!(Ok((vec![0xff], false)), write_bit(&[0xfe], 7, true));
+        assert_eq!(Ok(true), read_bit(&[0xe1], 0));
+    }
+
+    #[test]
+    fn write_bit_refuses_values_too_large() {
+        assert_eq!(
+            Err(MiniRedisError::ValueTooLarge),
+            write_bit(b"", MAX_VALUE_LEN * 8, true)
+        );
+        assert_eq!(
+            Err(MiniRedisError::ValueTooLarge),
+            read_bit(b"", MAX_VALUE_LEN * 8)
+        );
+    }
+
+    #[test]
+    fn find_bit_finds_the_first_set_or_clear_bit_in_a_range() {
+        let value = [0x00, 0x0f, 0xff, 0x80];
+
+        assert_eq!(12, find_bit(&value, true, 0, None));
+        assert_eq!(16, find_bit(&value, true, 2, None));
+        assert_eq!(24, find_bit(&value, true, -1, None));
+        assert_eq!(0, find_bit(&value, false, 0, None));
+        assert_eq!(8, find_bit(&value, false, 1, Some(2)));
+        assert_eq!(25, find_bit(&value, false, 2, None));
+        assert_eq!(-1, find_bit(&value, true, 0, Some(0)));
+        assert_eq!(-1, find_bit(&value, true, 3, Some(1)));
+    }
+
+    #[test]
+    fn find_bit_pads_an_all_ones_value_only_without_an_end() {
+        let value = [0xff, 0xff, 0xff];
+
+        assert_eq!(24, find_bit(&value, false, 0, None));
+        assert_eq!(24, find_bit(&value, false, 1, None));
+        assert_eq!(-1, find_bit(&value, false, 0, Some(-1)));
+        assert_eq!(-1, find_bit(&value, false, 0, Some(1)));
+        assert_eq!(0, find_bit(&value, true, 0, None));
+        assert_eq!(-1, find_bit(&[], true, 0, None));
+    }
+
+    #[test]
+    fn scan_index_looks_at_only_count_keys_per_batch() {
+        let mut expected = (0..10_000)
+            .map(|i| format!("key:{}", i))
+            .collect::<Vec<String>>();
+        let index = expected.iter().collect::<ScanIndex>();
+
+        let mut keys = Vec::new();
+        let mut cursor = 0;
+        loop {
+            let looked_at = std::cell::Cell::new(0);
+            let (next, batch) = index.scan(cursor, 10, |_| {
+                looked_at.set(looked_at.get() + 1);
+                true
+            });
+            assert!(looked_at.get() <= 10, "{}", looked_at.get());
+            keys.extend(batch);
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+
+        keys.sort();
+        expected.sort();
+        assert_eq!(expected, keys);
+    }
+
+    #[test]
+    fn scan_index_leaves_out_removed_keys_and_keys_it_does_not_keep() {
+        let mut index = ScanIndex::default();
+        for key in ["a", "b", "c", "d"] {
+            index.insert(key);
+        }
+        index.remove("b");
+
+        let (next, mut batch) = index.scan(0, 10, |key| key != "c");
+
+        batch.sort();
+        assert_eq!(0, next);
+        assert_eq!(vec!["a".to_string(), "d".to_string()], batch);
+    }
+
+    #[test]
+    fn scan_with_moves_the_cursor_past_keys_it_filters_out() {
+        let store = KVStore::new();
+        for i in 0..20 {
+            store.set(&format!("key:{}", i), "value").unwrap();
+        }
+        let options = ScanOptions {
+            pattern: Some("nothing:*".to_string()),
+            count: 5,
+            ..ScanOptions::default()
+        };
+
+        let (next, batch) = store.scan_with(0, &options).unwrap();
+
+        assert_ne!(0, next);
+        assert!(batch.is_empty());
+        assert_eq!(store.scan(0, 5).unwrap().0, next);
+    }
+
+    #[test]
+    fn scan_options_keep_keys_by_pattern_and_type() {
+        let options = |pattern: Option<&str>, kind: Option<&str>| ScanOptions {
+            pattern: pattern.map(str::to_string),
+            kind: kind.map(str::to_string),
+            ..ScanOptions::default()
+        };
+
+        assert!(options(None, None).keeps("a"));
+        assert!(options(Some("a*"), Some("STRING")).keeps("ab"));
+        assert!(!options(Some("a*"), None).keeps("ba"));
+        assert!(!options(None, Some("list")).keeps("a"));
+    }
+
+    #[test]
+    fn kv_store_implements_storage() {
+        exercise(&KVStore::new());
+    }
+
+    #[test]
+    fn logging_storage_passes_operations_on() {
+        let storage = LoggingStorage::new(KVStore::new());
+
+        exercise(&storage);
+
+        assert_eq!(Ok(Some("2".to_string())), storage.inner().get("b"));
+    }
+
+    #[test]
+    fn boxed_storage_can_be_used_as_trait_object() {
+        let storage: Box<dyn Storage> = Box::new(LoggingStorage::new(KVStore::new()));
+
+        exercise(storage.as_ref());
+    }
+}
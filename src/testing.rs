@@ -0,0 +1,245 @@
+use crate::error::MiniRedisError;
+use crate::kv_store::KVStore;
+use crate::log::{Level, StderrLogger};
+use crate::protocol;
+use crate::server::{Server, ShutdownHandle};
+use crate::storage::Storage;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+
+/// A server running in the same process, for testing against.
+///
+/// It listens on a free port of the loopback interface, and is ready for connections
+/// as soon as it is started. Dropping it shuts the server down,
+/// closing the connections of its clients and freeing the port.
+///
+/// # Examples
+///
+/// ```rust
+/// use miniredis::connection::Connection;
+/// use miniredis::testing::TestServer;
+///
+/// let server = TestServer::start();
+///
+/// assert_eq!("OK", server.send("SET name alice"));
+/// let mut connection = Connection::connect(server.address()).unwrap();
+/// assert_eq!(Some("alice".to_string()), connection.get("name").unwrap());
+/// ```
+pub struct TestServer {
+    address: String,
+    shutdown: ShutdownHandle,
+    thread: Option<JoinHandle<Result<(), MiniRedisError>>>,
+}
+
+impl TestServer {
+    /// Starts a server that keeps its data in memory.
+    ///
+    /// The server only logs warnings, to keep the output of tests readable.
+    ///
+    /// # Returns
+    ///
+    /// The running server.
+    ///
+    /// # Panics
+    ///
+    /// If no port can be bound on the loopback interface, it will panic,
+    /// since a test cannot go on without its server.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::testing::TestServer;
+    ///
+    /// let server = TestServer::start();
+    /// ```
+    pub fn start() -> Self {
+        Self::with_storage(KVStore::new())
+    }
+
+    /// Starts a server that keeps its data in a custom storage backend.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - The backend that commands are executed against.
+    ///
+    /// # Returns
+    ///
+    /// The running server.
+    ///
+    /// # Panics
+    ///
+    /// If no port can be bound on the loopback interface, it will panic.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::kv_store::KVStore;
+    /// use miniredis::testing::TestServer;
+    ///
+    /// let store = KVStore::new();
+    /// let server = TestServer::with_storage(store.clone());
+    ///
+    /// server.send("SET name alice");
+    /// assert_eq!(Ok(Some("alice".to_string())), store.get("name"));
+    /// ```
+    pub fn with_storage(storage: impl Storage + 'static) -> Self {
+        Self::serve(
+            Server::builder("127.0.0.1:0")
+                .storage(storage)
+                .logger(StderrLogger::new(Level::Warning))
+                .build(),
+        )
+    }
+
+    /// Starts serving a server built with options, on a free port instead of its own address.
+    ///
+    /// # Arguments
+    ///
+    /// * `server` - The server to run.
+    ///
+    /// # Returns
+    ///
+    /// The running server.
+    ///
+    /// # Panics
+    ///
+    /// If no port can be bound on the loopback interface, it will panic.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::server::{ExecutionMode, Server};
+    /// use miniredis::testing::TestServer;
+    ///
+    /// let server = TestServer::serve(
+    ///     Server::builder("127.0.0.1:0")
+    ///         .execution_mode(ExecutionMode::SingleWriter)
+    ///         .build(),
+    /// );
+    ///
+    /// assert_eq!("OK", server.send("SET name alice"));
+    /// ```
+    pub fn serve(server: Server) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to a port");
+        let address = listener
+            .local_addr()
+            .expect("Failed to get local address")
+            .to_string();
+        let shutdown = server.shutdown_handle();
+        let thread = thread::spawn(move || server.serve(listener));
+        Self {
+            address,
+            shutdown,
+            thread: Some(thread),
+        }
+    }
+
+    /// Gets the address the server listens on.
+    ///
+    /// # Returns
+    ///
+    /// The address, like `127.0.0.1:51234`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::testing::TestServer;
+    ///
+    /// let server = TestServer::start();
+    ///
+    /// assert!(server.address().starts_with("127.0.0.1:"));
+    /// ```
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Sends a command over a new connection and reads its reply.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command, as it would be typed into the prompt.
+    ///
+    /// # Returns
+    ///
+    /// The reply as the server wrote it, with the lines of a reply
+    /// that spans several joined by line endings.
+    ///
+    /// # Panics
+    ///
+    /// If the command cannot be sent, or its reply cannot be read, it will panic.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use miniredis::testing::TestServer;
+    ///
+    /// let server = TestServer::start();
+    ///
+    /// assert_eq!("nil", server.send("GET name"));
+    /// assert!(server.send("BOGUS").starts_with("ERR Invalid command: BOGUS."));
+    /// ```
+    pub fn send(&self, command: &str) -> String {
+        let mut stream = TcpStream::connect(&self.address).expect("Failed to connect");
+        let mut reader = BufReader::new(stream.try_clone().expect("Failed to clone the stream"));
+        stream
+            .write_all(format!("{}\n", command).as_bytes())
+            .expect("Failed to send the command");
+
+        let mut lines = Vec::new();
+        protocol::read_reply(&mut || {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(MiniRedisError::StreamClosed);
+            }
+            let line = line.trim_end_matches(['\r', '\n']).to_string();
+            lines.push(line.clone());
+            Ok(line)
+        })
+        .expect("Failed to read the reply");
+        lines.join("\n")
+    }
+}
+
+impl Drop for TestServer {
+    /// Shuts the server down and waits for it to stop.
+    fn drop(&mut self) {
+        self.shutdown.shutdown();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn send_reads_every_line_of_a_reply() {
+        let server = TestServer::start();
+        server.send("SET a 1");
+
+        assert_eq!(
+            format!("%2\nversion\n{}\nkeys\n1", env!("CARGO_PKG_VERSION")),
+            server.send("INFO")
+        );
+    }
+
+    #[test]
+    fn dropping_the_server_frees_its_port_and_closes_its_clients() {
+        let server = TestServer::start();
+        let address = server.address().to_string();
+        let mut client = TcpStream::connect(&address).unwrap();
+        client.write_all(b"SET a 1\n").unwrap();
+        let mut reply = String::new();
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+        reader.read_line(&mut reply).unwrap();
+
+        drop(server);
+
+        reply.clear();
+        assert_eq!(0, reader.read_line(&mut reply).unwrap());
+        assert!(TcpListener::bind(&address).is_ok());
+    }
+}
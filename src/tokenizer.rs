@@ -0,0 +1,169 @@
+use crate::error::MiniRedisError;
+
+/// Splits a line into words, honoring double quotes.
+///
+/// This is the one grammar of command lines, used by the server to parse them
+/// and by the client to check them before sending.
+/// A quote that is never closed runs to the end of the line.
+///
+/// # Arguments
+///
+/// * `line` - The line to split.
+///
+/// # Returns
+///
+/// The words of the line, with quotes removed and escapes resolved.
+pub(crate) fn split_words(line: &str) -> Vec<String> {
+    scan(line).0
+}
+
+/// Checks that every quote of a line is closed.
+///
+/// # Arguments
+///
+/// * `line` - The line to check.
+///
+/// # Returns
+///
+/// A result indicating whether the line splits into words the way it reads.
+///
+/// # Errors
+///
+/// If a quote is never closed, it will return [`MiniRedisError::UnterminatedQuote`].
+pub(crate) fn check_quotes(line: &str) -> Result<(), MiniRedisError> {
+    match scan(line) {
+        (_, true) => Ok(()),
+        (_, false) => Err(MiniRedisError::UnterminatedQuote {
+            line: line.to_string(),
+        }),
+    }
+}
+
+/// Quotes a word if it would not survive [`split_words`] as is.
+///
+/// # Arguments
+///
+/// * `word` - The word to quote.
+///
+/// # Returns
+///
+/// The word, wrapped in double quotes and escaped if needed.
+pub(crate) fn quote(word: &str) -> String {
+    let needs_quotes = word.is_empty()
+        || word
+            .chars()
+            .any(|c| c.is_whitespace() || c == '"' || c == '\\');
+    if !needs_quotes {
+        return word.to_string();
+    }
+
+    let mut quoted = String::from("\"");
+    for c in word.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Splits a line into words, and tells whether the last quote was closed.
+///
+/// # Arguments
+///
+/// * `line` - The line to split.
+///
+/// # Returns
+///
+/// The words of the line, and false if the line ended inside quotes.
+fn scan(line: &str) -> (Vec<String>, bool) {
+    let mut words = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            return (words, true);
+        }
+
+        let mut word = String::new();
+        let mut quoted = false;
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => quoted = !quoted,
+                '\\' if quoted && matches!(chars.peek(), Some('"' | '\\')) => {
+                    word.extend(chars.next());
+                }
+                c if c.is_whitespace() && !quoted => break,
+                c => word.push(c),
+            }
+        }
+        words.push(word);
+        if quoted {
+            return (words, false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_quotes_accepts_closed_quotes() {
+        for line in [
+            "GET a",
+            r#"SET msg "hello world""#,
+            r#"SET "" "\"""#,
+            r"SET a b\",
+        ] {
+            assert_eq!(Ok(()), check_quotes(line), "{}", line);
+        }
+    }
+
+    #[test]
+    fn check_quotes_rejects_unclosed_quotes() {
+        for line in [
+            r#"SET msg "hello world"#,
+            r#"SET msg "say \"hi\""#,
+            r#"SET a "b""c"#,
+        ] {
+            assert_eq!(
+                Err(MiniRedisError::UnterminatedQuote {
+                    line: line.to_string()
+                }),
+                check_quotes(line)
+            );
+        }
+    }
+
+    #[test]
+    fn split_words_still_splits_unclosed_quotes() {
+        assert_eq!(
+            vec![
+                "SET".to_string(),
+                "msg".to_string(),
+                "hello world".to_string()
+            ],
+            split_words(r#"SET msg "hello world"#)
+        );
+    }
+
+    #[test]
+    fn quote_leaves_plain_words_alone() {
+        assert_eq!("hello", quote("hello"));
+        assert_eq!("\"\"", quote(""));
+        assert_eq!(r#""a \"b\" \\ c""#, quote(r#"a "b" \ c"#));
+    }
+
+    #[test]
+    fn quoted_words_split_back_into_themselves() {
+        let words = ["", "hello world", r#"quote " and \ backslash"#, "tab\there"];
+
+        let line = words.map(quote).join(" ");
+
+        assert_eq!(Ok(()), check_quotes(&line));
+        assert_eq!(words.to_vec(), split_words(&line));
+    }
+}
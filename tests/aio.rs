@@ -0,0 +1,553 @@
+#![cfg(feature = "async")]
+
+use miniredis::aio::{Connection, Server};
+use miniredis::connection;
+use miniredis::error::MiniRedisError;
+use miniredis::log::{Level, StderrLogger};
+use miniredis::output::OutputLimit;
+use miniredis::response::Response;
+use miniredis::server::{self, ShutdownHandle};
+use miniredis::testing::TestServer;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Runs a future to completion on a runtime of its own.
+fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to build the runtime")
+        .block_on(future)
+}
+
+#[test]
+fn async_connection_sets_gets_and_deletes_keys() {
+    let server = TestServer::start();
+
+    block_on(async {
+        let mut connection = Connection::connect(server.address()).await.unwrap();
+
+        connection.set("name", "alice smith").await.unwrap();
+        assert_eq!(
+            Some("alice smith".to_string()),
+            connection.get("name").await.unwrap()
+        );
+        assert!(connection.del("name").await.unwrap());
+        assert!(!connection.del("name").await.unwrap());
+        assert_eq!(None, connection.get("name").await.unwrap());
+        connection.ping().await.unwrap();
+    });
+    assert_eq!("nil", server.send("GET name"));
+}
+
+#[test]
+fn async_connection_gets_values_byte_for_byte() {
+    let server = TestServer::start();
+    server.send("SETBIT bits 0 1");
+
+    block_on(async {
+        let mut connection = Connection::connect(server.address()).await.unwrap();
+
+        assert_eq!(
+            Some(vec![0x80]),
+            connection.get_bytes("bits").await.unwrap()
+        );
+        assert_eq!(
+            Some("\u{fffd}".to_string()),
+            connection.get("bits").await.unwrap()
+        );
+    });
+}
+
+#[test]
+fn async_connection_returns_error_replies_as_errors() {
+    let server = TestServer::start();
+
+    block_on(async {
+        let mut connection = Connection::connect(server.address()).await.unwrap();
+
+        assert_eq!(
+            Err(MiniRedisError::InvalidCommand {
+                command: "NOPE".to_string()
+            }),
+            connection.command(&["NOPE"]).await
+        );
+        assert_eq!(
+            Response::Integer(1),
+            connection.command(&["INCR", "n"]).await.unwrap()
+        );
+    });
+}
+
+#[test]
+fn async_connection_tries_every_address_in_order() {
+    let server = TestServer::start();
+    let closed = TcpListener::bind("127.0.0.1:0").unwrap();
+    let closed_address = closed.local_addr().unwrap().to_string();
+    drop(closed);
+
+    block_on(async {
+        let addresses = format!("{},{}", closed_address, server.address());
+        let mut connection = Connection::connect(&addresses).await.unwrap();
+
+        assert_eq!(server.address(), connection.address());
+        connection.ping().await.unwrap();
+        assert!(matches!(
+            Connection::connect(&closed_address).await,
+            Err(MiniRedisError::StreamNotConnected { .. })
+        ));
+    });
+}
+
+#[test]
+fn async_connection_stays_in_sync_after_a_future_is_dropped_mid_reply() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut requests = Vec::new();
+        let mut buffer = [0; 1024];
+        while !requests.ends_with(b"key\r\n") {
+            let read = stream.read(&mut buffer).unwrap();
+            requests.extend_from_slice(&buffer[..read]);
+        }
+        stream.write_all(b"$5\r\nhel").unwrap();
+        while !requests.ends_with(b"PING\r\n") {
+            let read = stream.read(&mut buffer).unwrap();
+            requests.extend_from_slice(&buffer[..read]);
+        }
+        stream.write_all(b"lo\r\n+PONG\r\n").unwrap();
+        requests
+    });
+
+    block_on(async {
+        let mut connection = Connection::connect(&address).await.unwrap();
+
+        let get = tokio::time::timeout(Duration::from_millis(100), connection.get("key")).await;
+        assert!(get.is_err());
+        assert_eq!(
+            Response::Simple("PONG".to_string()),
+            connection.command(&["PING"]).await.unwrap()
+        );
+    });
+    assert_eq!(
+        b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n*1\r\n$4\r\nPING\r\n".to_vec(),
+        server.join().unwrap()
+    );
+}
+
+#[test]
+fn async_connection_skips_replies_of_dropped_futures_from_a_real_server() {
+    let server = TestServer::start();
+
+    block_on(async {
+        let mut connection = Connection::connect(server.address()).await.unwrap();
+        let big = "x".repeat(4 * 1024 * 1024);
+        connection.set("big", &big).await.unwrap();
+        connection.set("small", "1").await.unwrap();
+
+        for _ in 0..3 {
+            let _ = tokio::time::timeout(Duration::ZERO, connection.get("big")).await;
+        }
+
+        assert_eq!(
+            Some("1".to_string()),
+            connection.get("small").await.unwrap()
+        );
+        assert_eq!(Some(big), connection.get("big").await.unwrap());
+    });
+}
+
+#[test]
+fn async_connections_run_side_by_side_on_one_thread() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    block_on(async {
+        let tasks = (0..20)
+            .map(|task| {
+                let address = address.clone();
+                tokio::spawn(async move {
+                    let mut connection = Connection::connect(&address).await.unwrap();
+                    for _ in 0..50 {
+                        connection.command(&["INCR", "counter"]).await.unwrap();
+                    }
+                    connection
+                        .set(&format!("task:{}", task), "done")
+                        .await
+                        .unwrap();
+                })
+            })
+            .collect::<Vec<_>>();
+        for task in tasks {
+            task.await.unwrap();
+        }
+    });
+    assert_eq!("1000", server.send("GET counter"));
+    assert_eq!("done", server.send("GET task:19"));
+}
+
+/// An async server serving on a runtime of its own, on a free port, until it is dropped.
+struct AsyncServer {
+    address: String,
+    shutdown: ShutdownHandle,
+    thread: Option<JoinHandle<Result<(), MiniRedisError>>>,
+}
+
+impl AsyncServer {
+    /// Starts serving the clients of a server on tasks.
+    fn serve(server: server::Server) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = Server::from(server);
+        let shutdown = server.shutdown_handle();
+        let thread = thread::spawn(move || {
+            block_on(async move {
+                let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+                server.serve(listener).await
+            })
+        });
+        Self {
+            address,
+            shutdown,
+            thread: Some(thread),
+        }
+    }
+
+    /// Starts an async server that keeps its data in memory.
+    fn start() -> Self {
+        Self::serve(server::Server::new("127.0.0.1:0"))
+    }
+
+    /// Sends an inline command over a new connection and reads the first line of its reply.
+    fn send(&self, command: &str) -> String {
+        let mut stream = TcpStream::connect(&self.address).unwrap();
+        stream
+            .write_all(format!("{}\n", command).as_bytes())
+            .unwrap();
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply).unwrap();
+        reply.trim_end().to_string()
+    }
+}
+
+impl Drop for AsyncServer {
+    fn drop(&mut self) {
+        self.shutdown.shutdown();
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap().unwrap();
+        }
+    }
+}
+
+#[test]
+fn async_server_answers_sync_async_and_inline_clients() {
+    let server = AsyncServer::start();
+
+    let mut sync = connection::Connection::connect(&server.address).unwrap();
+    sync.set("name", "alice smith").unwrap();
+    assert_eq!(Some("alice smith".to_string()), sync.get("name").unwrap());
+    assert_eq!("alice smith", server.send("GET name"));
+    block_on(async {
+        let mut connection = Connection::connect(&server.address).await.unwrap();
+        assert_eq!(
+            Some("alice smith".to_string()),
+            connection.get("name").await.unwrap()
+        );
+        assert!(connection.del("name").await.unwrap());
+    });
+    assert_eq!("nil", server.send("GET name"));
+}
+
+#[test]
+fn async_server_answers_a_pipelined_batch_in_order() {
+    let server = AsyncServer::start();
+    let mut stream = TcpStream::connect(&server.address).unwrap();
+
+    stream
+        .write_all(b"SET n 1\n\nINCR n\n*2\r\n$3\r\nGET\r\n$1\r\nn\r\nGET ")
+        .unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut replies = String::new();
+    for _ in 0..4 {
+        reader.read_line(&mut replies).unwrap();
+    }
+    assert_eq!("OK\n2\n$1\r\n2\r\n", replies);
+
+    stream.write_all(b"n\n").unwrap();
+    replies.clear();
+    reader.read_line(&mut replies).unwrap();
+    assert_eq!("2\n", replies);
+}
+
+#[test]
+fn async_server_replies_to_an_invalid_frame_and_closes_the_connection() {
+    let server = AsyncServer::start();
+    let mut stream = TcpStream::connect(&server.address).unwrap();
+
+    stream
+        .write_all(b"*1\r\n$4\r\nPING\r\n*1\r\n:1\r\n")
+        .unwrap();
+    let mut replies = String::new();
+    stream.read_to_string(&mut replies).unwrap();
+
+    assert!(
+        replies.starts_with("+PONG\r\n-ERR Protocol error: "),
+        "{:?}",
+        replies
+    );
+    assert_eq!("PONG", server.send("PING"));
+}
+
+#[test]
+fn async_server_keeps_serving_while_a_command_waits_for_a_pause() {
+    let server = AsyncServer::start();
+    assert_eq!("OK", server.send("CLIENT PAUSE 1000 WRITE"));
+
+    let address = server.address.clone();
+    let held = thread::spawn(move || {
+        let start = Instant::now();
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(b"SET held 1\n").unwrap();
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply).unwrap();
+        (reply, start.elapsed())
+    });
+    thread::sleep(Duration::from_millis(100));
+
+    let start = Instant::now();
+    assert_eq!("nil", server.send("GET held"));
+    assert!(start.elapsed() < Duration::from_millis(500));
+    let (reply, waited) = held.join().unwrap();
+    assert_eq!("OK\n", reply);
+    assert!(waited >= Duration::from_millis(800), "{:?}", waited);
+}
+
+/// Reads how many clients a server dropped for leaving too many replies unread.
+fn output_buffer_disconnections(address: &str) -> Response {
+    let mut connection = connection::Connection::connect(address).unwrap();
+    match connection.command(&["INFO", "stats"]).unwrap() {
+        Response::Map(fields) => {
+            fields
+                .into_iter()
+                .find(|(name, _)| name == "client_output_buffer_limit_disconnections")
+                .unwrap()
+                .1
+        }
+        reply => panic!("{:?}", reply),
+    }
+}
+
+#[test]
+fn async_server_drops_a_client_over_the_output_limit() {
+    let server = AsyncServer::serve(
+        server::Server::builder("127.0.0.1:0")
+            .client_output_buffer_limit(OutputLimit {
+                hard: 1 << 20,
+                ..OutputLimit::default()
+            })
+            .build(),
+    );
+    assert_eq!(
+        "OK",
+        server.send(&format!("SET big {}", "x".repeat(64 * 1024)))
+    );
+    let mut stalled = TcpStream::connect(&server.address).unwrap();
+
+    // Far more replies than the limit, asked for without reading any of them.
+    stalled
+        .write_all("GET big\n".repeat(4096).as_bytes())
+        .unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while output_buffer_disconnections(&server.address) != Response::Integer(1)
+        && Instant::now() < deadline
+    {
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    assert_eq!(
+        Response::Integer(1),
+        output_buffer_disconnections(&server.address)
+    );
+    stalled
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    // The stream ends, or is reset for the requests the server never read, instead of timing out.
+    let drained = io::copy(&mut stalled, &mut io::sink());
+    assert!(
+        drained.as_ref().map_or_else(
+            |e| e.kind() != io::ErrorKind::WouldBlock,
+            |read| *read < 1 << 20
+        ),
+        "{:?}",
+        drained
+    );
+    assert_eq!("PONG", server.send("PING"));
+}
+
+#[test]
+fn async_server_drops_a_client_that_leaves_a_batch_over_the_soft_limit_unread() {
+    let server = AsyncServer::serve(
+        server::Server::builder("127.0.0.1:0")
+            .client_output_buffer_limit(OutputLimit {
+                hard: 0,
+                soft: 1 << 20,
+                soft_duration: Duration::from_millis(200),
+            })
+            .build(),
+    );
+    assert_eq!(
+        "OK",
+        server.send(&format!("SET big {}", "x".repeat(1 << 20)))
+    );
+    let mut stalled = TcpStream::connect(&server.address).unwrap();
+
+    stalled
+        .write_all("GET big\n".repeat(64).as_bytes())
+        .unwrap();
+    thread::sleep(Duration::from_millis(500));
+
+    assert_eq!(
+        Response::Integer(1),
+        output_buffer_disconnections(&server.address)
+    );
+}
+
+#[test]
+fn async_server_shuts_down_and_closes_its_clients() {
+    let server = AsyncServer::start();
+    let mut client = TcpStream::connect(&server.address).unwrap();
+    assert_eq!("PONG", server.send("PING"));
+
+    drop(server);
+
+    client
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    assert_eq!(0, client.read(&mut [0; 16]).unwrap());
+}
+
+#[test]
+fn async_server_spawns_onto_the_runtime_it_is_called_from() {
+    block_on(async {
+        let server = Server::new("127.0.0.1:0");
+        let shutdown = server.shutdown_handle();
+        let running = server.spawn();
+        tokio::task::yield_now().await;
+
+        shutdown.shutdown();
+        running.await.unwrap().unwrap();
+    });
+}
+
+#[test]
+fn async_server_takes_the_arguments_of_the_sync_server() {
+    let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+    assert!(
+        Server::from_args(&args(&[
+            "miniredis",
+            "127.0.0.1:0",
+            "--execution",
+            "single-writer"
+        ]))
+        .is_ok()
+    );
+    assert!(matches!(
+        Server::from_args(&args(&["miniredis", "--bogus"])),
+        Err(MiniRedisError::InvalidArguments { .. })
+    ));
+}
+
+#[test]
+fn async_server_answers_in_single_writer_mode() {
+    let server = AsyncServer::serve(
+        server::Server::builder("127.0.0.1:0")
+            .execution_mode(server::ExecutionMode::SingleWriter)
+            .build(),
+    );
+
+    assert_eq!("OK", server.send("SET name alice"));
+    assert_eq!("alice", server.send("GET name"));
+}
+
+/// Counts the threads of this process.
+#[cfg(target_os = "linux")]
+fn threads() -> usize {
+    std::fs::read_to_string("/proc/self/status")
+        .unwrap()
+        .lines()
+        .find_map(|line| line.strip_prefix("Threads:"))
+        .unwrap()
+        .trim()
+        .parse()
+        .unwrap()
+}
+
+#[test]
+fn async_server_waits_for_its_replica_off_the_tasks_of_other_clients() {
+    let primary = AsyncServer::start();
+    assert_eq!("OK", primary.send("SET before 1"));
+    let replica = TestServer::serve(
+        server::Server::builder("127.0.0.1:0")
+            .replica_of(&primary.address)
+            .logger(StderrLogger::new(Level::Warning))
+            .build(),
+    );
+
+    assert_eq!("1", primary.send("WAIT 1 5000"));
+    assert_eq!("OK", primary.send("SET after 2"));
+    assert_eq!("1", primary.send("WAIT 1 5000"));
+    assert_eq!("1", replica.send("GET before"));
+    assert_eq!("2", replica.send("GET after"));
+
+    // Two replicas never acknowledge, so this one waits for as long as the server runs.
+    let mut waiting = TcpStream::connect(&primary.address).unwrap();
+    waiting.write_all(b"WAIT 2 0\n").unwrap();
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!("OK", primary.send("SET during 3"));
+    assert_eq!("1", primary.send("WAIT 1 5000"));
+    assert_eq!("3", replica.send("GET during"));
+}
+
+/// Opens connections that each send a PING and get their PONG, one after the other,
+/// so the backlog of the listener never overflows.
+fn idle_clients(address: &str, count: usize) -> Vec<TcpStream> {
+    (0..count)
+        .map(|_| {
+            let mut client = TcpStream::connect(address).unwrap();
+            client.write_all(b"PING\n").unwrap();
+            let mut reply = [0; 5];
+            client.read_exact(&mut reply).unwrap();
+            assert_eq!(b"PONG\n", &reply);
+            client
+        })
+        .collect()
+}
+
+// Every thread of the threaded server has a stack of its own, while an idle client
+// of the async server only costs a task and its buffers.
+#[test]
+#[cfg(target_os = "linux")]
+fn async_server_holds_thousands_of_idle_clients_on_a_few_threads() {
+    const CLIENTS: usize = 2000;
+    let before = threads();
+
+    let threaded = TestServer::start();
+    let clients = idle_clients(threaded.address(), CLIENTS);
+    let threaded_threads = threads() - before;
+    drop(clients);
+    drop(threaded);
+
+    let server = AsyncServer::start();
+    let clients = idle_clients(&server.address, CLIENTS);
+    let async_threads = threads().saturating_sub(before);
+
+    assert!(threaded_threads >= CLIENTS, "{}", threaded_threads);
+    assert!(async_threads < 64, "{}", async_threads);
+    assert_eq!("PONG", server.send("PING"));
+    drop(clients);
+}
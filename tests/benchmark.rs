@@ -0,0 +1,84 @@
+mod helpers;
+use helpers::send_command;
+
+use miniredis::benchmark::Benchmark;
+use miniredis::error::MiniRedisError;
+use miniredis::testing::TestServer;
+
+fn benchmark(address: &str, options: &[&str]) -> Benchmark {
+    let (host, port) = address.split_once(':').unwrap();
+    let args = ["miniredis-benchmark", "--host", host, "--port", port]
+        .iter()
+        .chain(options)
+        .map(|arg| arg.to_string())
+        .collect::<Vec<String>>();
+    Benchmark::from_args(&args).unwrap()
+}
+
+#[test]
+fn benchmark_reports_every_command() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let reports = benchmark(
+        &address,
+        &["-c", "4", "-n", "40", "-t", "set,get,ping", "-P", "5"],
+    )
+    .run()
+    .unwrap();
+
+    let commands = reports
+        .iter()
+        .map(|report| report.command.as_str())
+        .collect::<Vec<&str>>();
+    assert_eq!(commands, vec!["SET", "GET", "PING"]);
+    for report in &reports {
+        assert_eq!(report.requests, 40);
+        assert_eq!(report.errors, 0);
+        assert!(report.throughput() > 0.0);
+        assert!(report.latency(50.0) <= report.latency(99.0));
+    }
+}
+
+#[test]
+fn benchmark_sets_values_of_the_given_size() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    benchmark(&address, &["-c", "2", "-n", "10", "-t", "set", "-d", "64"])
+        .run()
+        .unwrap();
+
+    let value = send_command(&address, "GET key").unwrap();
+    assert_eq!(value.len(), 64);
+}
+
+#[test]
+fn benchmark_spreads_random_keys_over_the_keyspace() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    benchmark(&address, &["-c", "2", "-n", "50", "-t", "set", "-r", "5"])
+        .run()
+        .unwrap();
+
+    assert_eq!(send_command(&address, "GET key").unwrap(), "nil");
+    let deleted: u64 = send_command(&address, "DELPREFIX key:")
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=5).contains(&deleted));
+}
+
+#[test]
+fn benchmark_fails_when_the_server_is_unreachable() {
+    let result = benchmark("127.0.0.1:1", &["-n", "1"]).run();
+
+    assert_eq!(
+        result.map(|_| ()),
+        Err(MiniRedisError::StreamNotConnected {
+            address: "127.0.0.1:1".to_string(),
+            source: std::io::ErrorKind::ConnectionRefused.into(),
+        })
+    );
+}
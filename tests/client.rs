@@ -0,0 +1,863 @@
+mod helpers;
+use helpers::send_command;
+
+use miniredis::testing::TestServer;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::process::{Command, Output, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Runs the client binary with the given arguments and waits for it to exit.
+fn run_client(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_miniredis-client"))
+        .args(args)
+        .output()
+        .expect("Failed to run the client")
+}
+
+/// Runs the client binary with the given arguments, writing `input` to its stdin.
+fn run_client_with_input(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_miniredis-client"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run the client");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .expect("Failed to write to the client");
+    child
+        .wait_with_output()
+        .expect("Failed to wait for the client")
+}
+
+/// Starts a server that only accepts commands after `AUTH` with the password,
+/// returning its address and the lines it received.
+fn start_password_server(password: &str) -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to a port");
+    let address = listener.local_addr().unwrap().to_string();
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let lines = Arc::clone(&received);
+    let password = password.to_string();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut authenticated = false;
+            for line in BufReader::new(stream.try_clone().unwrap()).lines() {
+                let line = line.unwrap();
+                lines.lock().unwrap().push(line.clone());
+                let reply = if line == "HELLO" {
+                    "ERR Invalid command: HELLO."
+                } else if line.starts_with("AUTH ") {
+                    authenticated = line.rsplit(' ').next() == Some(password.as_str());
+                    if authenticated {
+                        "OK"
+                    } else {
+                        "ERR invalid password"
+                    }
+                } else if authenticated {
+                    "PONG"
+                } else {
+                    "ERR NOAUTH Authentication required."
+                };
+                stream.write_all(format!("{}\n", reply).as_bytes()).unwrap();
+            }
+        }
+    });
+    (address, received)
+}
+
+#[test]
+fn one_shot_command_prints_reply_and_exits_successfully() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let output = run_client(&[&address, "SET", "msg", "hello world"]);
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "OK\n");
+    let response = send_command(&address, "GET msg").expect("Failed to send GET command");
+    assert_eq!(response, "hello world");
+}
+
+#[test]
+fn one_shot_command_prints_value_of_key() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    send_command(&address, "SET name alice").expect("Failed to send SET command");
+
+    let output = run_client(&[&address, "get", "name"]);
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "alice\n");
+}
+
+#[test]
+fn one_shot_command_exits_with_failure_on_error_reply() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let output = run_client(&[&address, "UNKNOWN", "arg"]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("ERR Invalid command"));
+}
+
+#[test]
+fn one_shot_command_exits_with_failure_when_connection_fails() {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to a port");
+    let address = listener.local_addr().unwrap().to_string();
+    drop(listener);
+
+    let output = run_client(&[&address, "GET", "key"]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Could not connect"));
+}
+
+#[test]
+fn piped_commands_print_one_reply_per_line_without_prompt() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let output = run_client_with_input(&[&address], "SET a 1\n\nGET a\nGET b\n");
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "OK\n1\nnil\n");
+}
+
+#[test]
+fn multi_line_reply_is_read_completely_before_the_next_command() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    send_command(&address, "SET a 1").unwrap();
+
+    let output = run_client_with_input(&["--raw", &address], "INFO\nGET a\n");
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("version\n{}\nkeys\n1\n1\n", env!("CARGO_PKG_VERSION"))
+    );
+}
+
+#[test]
+fn multi_line_reply_is_printed_for_humans() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let output = run_client_with_input(&["--no-raw", &address], "INFO\nGET a\n");
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(
+            "1) version \"{}\"\n2) keys    (integer) 0\n(nil)\n",
+            env!("CARGO_PKG_VERSION")
+        )
+    );
+}
+
+#[test]
+fn piped_commands_exit_with_failure_if_any_reply_is_an_error() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let output = run_client_with_input(&["--pipe", &address], "UNKNOWN\nSET a 1");
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines = stdout.lines().collect::<Vec<&str>>();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("ERR Invalid command"));
+    assert_eq!(lines[1], "OK");
+    let response = send_command(&address, "GET a").expect("Failed to send GET command");
+    assert_eq!(response, "1");
+}
+
+#[test]
+fn piped_line_with_unterminated_quote_is_not_sent() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let output = run_client_with_input(&[&address], "SET msg \"hello world\nGET msg\n");
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines = stdout.lines().collect::<Vec<&str>>();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("ERR Unterminated quote in: SET msg \"hello world."));
+    assert_eq!(lines[1], "nil");
+}
+
+#[test]
+fn piped_quoted_value_reaches_the_server_as_one_argument() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let output = run_client_with_input(&[&address], "SET msg \"hello world\"\nGET msg\n");
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "OK\nhello world\n");
+    assert_eq!(send_command(&address, "GET msg").unwrap(), "hello world");
+}
+
+#[test]
+fn piped_commands_work_over_either_protocol() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    for protocol in ["--resp", "--inline"] {
+        let output = run_client_with_input(
+            &[protocol, &address],
+            "SET msg \"hello world\"\nGET msg\nINFO\n",
+        );
+
+        assert!(output.status.success());
+        assert!(
+            String::from_utf8_lossy(&output.stdout).starts_with("OK\nhello world\n"),
+            "{}",
+            protocol
+        );
+    }
+    assert_eq!(send_command(&address, "GET msg").unwrap(), "hello world");
+}
+
+#[test]
+fn empty_piped_input_exits_successfully() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let output = run_client_with_input(&[&address], "");
+
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn one_shot_command_authenticates_before_sending_the_command() {
+    let (address, received) = start_password_server("secret");
+
+    let output = run_client(&["--pass", "secret", &address, "PING"]);
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "PONG\n");
+    assert_eq!(*received.lock().unwrap(), ["HELLO", "AUTH secret", "PING"]);
+}
+
+#[test]
+fn password_is_read_from_the_environment() {
+    let (address, received) = start_password_server("secret");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_miniredis-client"))
+        .args(["--user", "alice", &address, "PING"])
+        .env("MINIREDIS_PASSWORD", "secret")
+        .output()
+        .expect("Failed to run the client");
+
+    assert!(output.status.success());
+    assert_eq!(
+        *received.lock().unwrap(),
+        ["HELLO", "AUTH alice secret", "PING"]
+    );
+}
+
+#[test]
+fn wrong_password_fails_without_sending_the_command() {
+    let (address, received) = start_password_server("secret");
+
+    let output = run_client_with_input(&["--pass", "wrong", &address], "PING\n");
+
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Authentication failed: invalid password")
+    );
+    assert!(output.stdout.is_empty());
+    assert_eq!(*received.lock().unwrap(), ["HELLO", "AUTH wrong"]);
+}
+
+/// Starts a server that closes the first `drops` connections after reading a line from them,
+/// and answers every line with `OK` after that, returning its address and the lines it received.
+fn start_flaky_server(drops: usize) -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to a port");
+    let address = listener.local_addr().unwrap().to_string();
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let lines = Arc::clone(&received);
+
+    thread::spawn(move || {
+        for (accepted, stream) in listener.incoming().enumerate() {
+            let mut stream = stream.unwrap();
+            for line in BufReader::new(stream.try_clone().unwrap()).lines() {
+                lines.lock().unwrap().push(line.unwrap());
+                if accepted < drops {
+                    break;
+                }
+                stream.write_all(b"OK\n").unwrap();
+            }
+        }
+    });
+    (address, received)
+}
+
+#[test]
+fn one_shot_command_retries_until_the_server_starts() {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to a port");
+    let address = listener.local_addr().unwrap().to_string();
+    drop(listener);
+    let server_address = address.clone();
+    thread::spawn(move || {
+        thread::sleep(std::time::Duration::from_millis(300));
+        let _ = miniredis::server::Server::new(&server_address).run();
+    });
+
+    let output = run_client(&["--retry", "10", "--retry-delay", "50", &address, "PING"]);
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "PONG\n");
+}
+
+#[test]
+fn failed_retries_report_the_number_of_attempts() {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to a port");
+    let address = listener.local_addr().unwrap().to_string();
+    drop(listener);
+
+    let output = run_client(&["--retry", "2", "--retry-delay", "1", &address, "PING"]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Gave up after 3 attempts."));
+}
+
+#[test]
+fn command_is_not_sent_again_without_retry_writes() {
+    let (address, received) = start_flaky_server(1);
+
+    let output = run_client(&["--inline", "--retry", "3", &address, "SET", "a", "1"]);
+
+    assert!(!output.status.success());
+    assert_eq!(*received.lock().unwrap(), ["SET a 1"]);
+}
+
+#[test]
+fn command_is_sent_again_with_retry_writes() {
+    let (address, received) = start_flaky_server(2);
+
+    let output = run_client(&[
+        "--inline",
+        "--retry",
+        "3",
+        "--retry-delay",
+        "1",
+        "--retry-writes",
+        &address,
+        "SET",
+        "a",
+        "1",
+    ]);
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "OK\n");
+    assert_eq!(*received.lock().unwrap(), ["SET a 1", "SET a 1", "SET a 1"]);
+}
+
+#[test]
+fn one_shot_command_exits_with_failure_when_server_never_replies() {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to a port");
+    let address = listener.local_addr().unwrap().to_string();
+
+    let output = run_client(&["--timeout", "0.2", &address, "GET", "key"]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Timed out"));
+}
+
+#[test]
+fn piped_commands_exit_with_failure_when_server_never_replies() {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to a port");
+    let address = listener.local_addr().unwrap().to_string();
+
+    let output = run_client_with_input(&["--timeout", "0.2", &address], "GET key\n");
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Timed out"));
+}
+
+#[test]
+fn repeated_command_prints_every_reply_and_a_summary() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let start = std::time::Instant::now();
+    let output = run_client(&[
+        "--repeat",
+        "3",
+        "--interval",
+        "0.1",
+        &address,
+        "SET",
+        "a",
+        "1",
+    ]);
+
+    assert!(output.status.success());
+    assert!(start.elapsed() >= std::time::Duration::from_millis(200));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "OK\nOK\nOK\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("3 commands, 0 errors"));
+}
+
+#[test]
+fn repeated_command_exits_with_failure_if_any_reply_is_an_error() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let output = run_client(&["--repeat", "2", &address, "UNKNOWN"]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("2 commands, 2 errors"));
+}
+
+#[cfg(unix)]
+#[test]
+fn endless_repeat_stops_gracefully_on_interrupt() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let child = Command::new(env!("CARGO_BIN_EXE_miniredis-client"))
+        .args(["--repeat", "-1", "--interval", "0.05", &address, "GET", "a"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run the client");
+
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .expect("Failed to interrupt the client");
+    let output = child
+        .wait_with_output()
+        .expect("Failed to wait for the client");
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("nil\n"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("commands, 0 errors"));
+}
+
+/// Runs the client binary with the given arguments until it is interrupted after `duration`.
+#[cfg(unix)]
+fn run_client_until_interrupted(args: &[&str], duration: std::time::Duration) -> Output {
+    let child = Command::new(env!("CARGO_BIN_EXE_miniredis-client"))
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run the client");
+
+    std::thread::sleep(duration);
+    Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .expect("Failed to interrupt the client");
+    child
+        .wait_with_output()
+        .expect("Failed to wait for the client")
+}
+
+#[cfg(unix)]
+#[test]
+fn latency_redraws_one_line_of_statistics_until_interrupted() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let output = run_client_until_interrupted(
+        &["--latency", &address],
+        std::time::Duration::from_millis(300),
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success());
+    assert_eq!(stdout.lines().count(), 1);
+    assert!(stdout.matches('\r').count() > 1);
+    assert!(stdout.trim_end().ends_with("samples)"));
+    assert!(stdout.contains("min: "));
+}
+
+#[cfg(unix)]
+#[test]
+fn latency_history_prints_a_line_per_period() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let output = run_client_until_interrupted(
+        &["--latency-history", "0.1", &address],
+        std::time::Duration::from_millis(450),
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success());
+    assert!(stdout.matches("seconds range").count() >= 2);
+    assert_eq!(send_command(&address, "PING").unwrap(), "PONG");
+}
+
+#[test]
+fn no_raw_prints_replies_for_humans_even_when_piped() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    send_command(&address, "SET msg \"hello world\"").expect("Failed to send SET command");
+
+    let value = run_client(&["--no-raw", &address, "GET", "msg"]);
+    let missing = run_client(&["--no-raw", &address, "GET", "missing"]);
+    let count = run_client(&["--no-raw", &address, "DELPREFIX", "m"]);
+    let error = run_client(&["--no-raw", &address, "UNKNOWN"]);
+
+    assert_eq!(String::from_utf8_lossy(&value.stdout), "\"hello world\"\n");
+    assert_eq!(String::from_utf8_lossy(&missing.stdout), "(nil)\n");
+    assert_eq!(String::from_utf8_lossy(&count.stdout), "(integer) 1\n");
+    assert!(String::from_utf8_lossy(&error.stdout).starts_with("(error) ERR Invalid command"));
+}
+
+#[test]
+fn raw_prints_replies_exactly_as_sent() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let output = run_client_with_input(&["--raw", &address], "SET a 1\nGET a\nGET b\n");
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "OK\n1\nnil\n");
+}
+
+#[test]
+fn json_output_prints_one_document_per_reply() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let output = run_client_with_input(
+        &["--output", "json", &address],
+        "SET msg \"hello world\"\nGET msg\nGET missing\nINFO\nUNKNOWN\n",
+    );
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines = stdout.lines().collect::<Vec<&str>>();
+    assert_eq!(lines[..3], ["\"OK\"", "\"hello world\"", "null"]);
+    assert!(lines[3].starts_with("{\"version\":\""), "{}", lines[3]);
+    assert!(lines[3].ends_with(",\"keys\":1}"), "{}", lines[3]);
+    assert!(lines[4].starts_with("{\"error\":\"Invalid command: UNKNOWN."));
+    assert_eq!(lines.len(), 5);
+}
+
+#[test]
+fn csv_output_prints_rows_of_multi_replies() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    send_command(&address, "SET a 1").expect("Failed to send SET command");
+
+    let output = run_client(&["--output", "csv", &address, "SCAN", "0"]);
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "0\na\n");
+}
+
+/// Writes a file of commands unique to this test, returning its path.
+fn write_script(name: &str, script: &str) -> String {
+    let path = std::env::temp_dir().join(format!("miniredis-{}-{}.txt", std::process::id(), name));
+    std::fs::write(&path, script).expect("Failed to write the script");
+    path.to_string_lossy().to_string()
+}
+
+#[test]
+fn file_commands_are_sent_and_summarized() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let mut script = "# seed\n\nSET greeting \"hello world\"\n".to_string();
+    for i in 0..250 {
+        script.push_str(&format!("SET key:{} {}\n", i, i));
+    }
+    let path = write_script("file-seed", &script);
+
+    let output = run_client(&["--file", &path, &address]);
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("251 commands, 0 errors, "));
+    assert_eq!(
+        send_command(&address, "GET greeting").unwrap(),
+        "hello world"
+    );
+    assert_eq!(send_command(&address, "GET key:249").unwrap(), "249");
+}
+
+#[test]
+fn file_errors_are_reported_with_their_line_numbers() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let path = write_script("file-errors", "SET a 1\nUNKNOWN\nSET b \"open\nSET c 3\n");
+
+    let output = run_client(&["--eval", &path, &address]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("line 2: ERR Invalid command: UNKNOWN."),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("line 3: ERR Unterminated quote"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("3 commands, 2 errors, "), "{}", stderr);
+    assert_eq!(send_command(&address, "GET c").unwrap(), "3");
+}
+
+#[test]
+fn file_stops_at_the_first_error_with_abort_on_error() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let path = write_script("file-abort", "SET a 1\nUNKNOWN\nSET c 3\n");
+
+    let output = run_client(&["--file", &path, "--abort-on-error", &address]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("2 commands, 1 errors, "));
+    assert_eq!(send_command(&address, "GET a").unwrap(), "1");
+    assert_eq!(send_command(&address, "GET c").unwrap(), "nil");
+}
+
+#[test]
+fn pipe_bulk_sends_more_commands_than_fit_in_flight_and_summarizes() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let mut input = String::new();
+    for i in 0..50_000 {
+        input.push_str(&format!("SET key:{} {}\n", i, i));
+    }
+
+    let output = run_client_with_input(&["--pipe-bulk", &address], &input);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(output.stdout.is_empty());
+    assert!(
+        stderr.starts_with("50000 commands, 50000 OK, 0 errors, "),
+        "{}",
+        stderr
+    );
+    assert_eq!(send_command(&address, "GET key:49999").unwrap(), "49999");
+}
+
+#[test]
+fn pipe_bulk_reports_the_first_errors_with_their_positions() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let input = "SET a 1\n\nUNKNOWN\nSET b \"open\nSET c 3\nBOGUS x\n";
+
+    let output = run_client_with_input(&["--pipe-bulk", "--no-validate", &address], input);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.starts_with("4 commands, 2 OK, 3 errors, "),
+        "{}",
+        stderr
+    );
+    let errors = stderr.lines().skip(1).collect::<Vec<&str>>();
+    assert_eq!(3, errors.len(), "{}", stderr);
+    assert!(
+        errors[0].starts_with("command 2 (line 3): ERR Invalid command: UNKNOWN."),
+        "{}",
+        stderr
+    );
+    assert!(
+        errors[1].starts_with("command 3 (line 4): ERR Unterminated quote"),
+        "{}",
+        stderr
+    );
+    assert!(
+        errors[2].starts_with("command 5 (line 6): ERR "),
+        "{}",
+        stderr
+    );
+    assert_eq!(send_command(&address, "GET c").unwrap(), "3");
+}
+
+#[test]
+fn bigkeys_reports_the_largest_key_and_size_percentiles() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    for i in 1..=20 {
+        send_command(&address, &format!("SET key:{} {}", i, "x".repeat(i)))
+            .expect("Failed to send SET command");
+    }
+    send_command(&address, &format!("SET huge {}", "y".repeat(500)))
+        .expect("Failed to send SET command");
+
+    let output = run_client(&["--bigkeys", &address]);
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Sampled 21 keys in the keyspace"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("Biggest string found \"huge\" has 500 bytes"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("p50 11, p90 19, p99 500, max 500"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn bigkeys_sample_stops_after_the_given_number_of_keys() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    for i in 0..30 {
+        send_command(&address, &format!("SET key:{} value", i))
+            .expect("Failed to send SET command");
+    }
+
+    let output = run_client(&["--bigkeys-sample", "5", &address]);
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Sampled 5 keys in the keyspace"));
+}
+
+#[test]
+fn show_rtt_prints_round_trip_times_to_stderr_outside_the_prompt() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let one_shot = run_client(&["--show-rtt", &address, "SET", "a", "1"]);
+    let piped = run_client_with_input(&["--show-rtt", &address], "GET a\nGET b\n");
+
+    assert_eq!(String::from_utf8_lossy(&one_shot.stdout), "OK\n");
+    assert_eq!(String::from_utf8_lossy(&piped.stdout), "1\nnil\n");
+    for output in [one_shot, piped] {
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        assert!(
+            stderr.lines().count() > 0
+                && stderr.lines().all(|line| {
+                    line.starts_with('(') && (line.ends_with("µs)") || line.ends_with("ms)"))
+                }),
+            "{}",
+            stderr
+        );
+    }
+}
+
+#[test]
+fn client_connects_to_the_first_address_that_accepts() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("Failed to bind to a port");
+    let closed = listener.local_addr().unwrap().to_string();
+    drop(listener);
+
+    let output = run_client(&[&format!("{},{}", closed, address), "PING"]);
+    let failed = run_client(&[&format!("{},{}", closed, closed), "PING"]);
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "PONG\n");
+    assert!(!failed.status.success());
+    let stderr = String::from_utf8_lossy(&failed.stderr);
+    assert!(
+        stderr.contains("Could not connect to any of 2 addresses"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn piped_commands_exit_with_failure_when_the_server_goes_away() {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to a port");
+    let address = listener.local_addr().unwrap().to_string();
+    drop(listener);
+    let mut server = Command::new(env!("CARGO_BIN_EXE_miniredis-server"))
+        .arg(&address)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to start the server");
+    while std::net::TcpStream::connect(&address).is_err() {
+        thread::sleep(std::time::Duration::from_millis(20));
+    }
+    let mut client = Command::new(env!("CARGO_BIN_EXE_miniredis-client"))
+        .arg(&address)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start the client");
+    let mut stdin = client.stdin.take().unwrap();
+    let mut stdout = BufReader::new(client.stdout.take().unwrap());
+
+    stdin.write_all(b"SET a 1\n").unwrap();
+    let mut reply = String::new();
+    stdout.read_line(&mut reply).unwrap();
+    server.kill().unwrap();
+    server.wait().unwrap();
+    stdin.write_all(b"GET a\nGET a\n").unwrap();
+    drop(stdin);
+    let output = client.wait_with_output().unwrap();
+
+    assert_eq!("OK\n", reply);
+    assert!(!output.status.success());
+    let mut rest = String::new();
+    stdout.read_to_string(&mut rest).unwrap();
+    assert_eq!("", rest);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("Client failed: "), "{}", stderr);
+}
+
+#[test]
+fn mistyped_command_is_refused_with_a_suggestion_before_sending() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let typo = run_client(&[&address, "GTE", "a"]);
+    let arity = run_client(&[&address, "SET", "a"]);
+    let unvalidated = run_client(&["--no-validate", &address, "GTE", "a"]);
+
+    assert!(!typo.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&typo.stdout),
+        "ERR Invalid command: GTE. Did you mean GET?\n"
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&arity.stdout),
+        "ERR Invalid arguments for SET. Usage: SET <KEY> <VALUE> [NX|XX] [EX <SECONDS>|PX <MILLISECONDS>|KEEPTTL]\n"
+    );
+    assert!(!unvalidated.status.success());
+    assert!(
+        String::from_utf8_lossy(&unvalidated.stdout).starts_with("ERR Invalid command: GTE. Run")
+    );
+}
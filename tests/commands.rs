@@ -1,9 +1,15 @@
 mod helpers;
-use helpers::{send_command, start_test_server};
+use helpers::send_command;
+use miniredis::testing::TestServer;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
 
 #[test]
 fn get_command_returns_nil_for_non_existing_key() {
-    let address = start_test_server();
+    let server = TestServer::start();
+    let address = server.address().to_string();
 
     let response =
         send_command(&address, "GET nonexistent_key").expect("Failed to send GET command");
@@ -13,7 +19,8 @@ fn get_command_returns_nil_for_non_existing_key() {
 
 #[test]
 fn set_command_stores_value_and_returns_ok() {
-    let address = start_test_server();
+    let server = TestServer::start();
+    let address = server.address().to_string();
 
     let response =
         send_command(&address, "SET test_key test_value").expect("Failed to send SET command");
@@ -23,7 +30,8 @@ fn set_command_stores_value_and_returns_ok() {
 
 #[test]
 fn get_command_returns_stored_value() {
-    let address = start_test_server();
+    let server = TestServer::start();
+    let address = server.address().to_string();
 
     // First set a value
     let set_response =
@@ -37,7 +45,8 @@ fn get_command_returns_stored_value() {
 
 #[test]
 fn set_command_overwrites_existing_value() {
-    let address = start_test_server();
+    let server = TestServer::start();
+    let address = server.address().to_string();
 
     // Set initial value
     send_command(&address, "SET overwrite_key initial_value")
@@ -55,8 +64,9 @@ fn set_command_overwrites_existing_value() {
 }
 
 #[test]
-fn del_command_removes_key_and_returns_ok() {
-    let address = start_test_server();
+fn del_command_removes_key_and_returns_count() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
 
     // First set a value
     send_command(&address, "SET delete_me some_value").expect("Failed to send SET command");
@@ -67,7 +77,7 @@ fn del_command_removes_key_and_returns_ok() {
 
     // Delete the key
     let del_response = send_command(&address, "DEL delete_me").expect("Failed to send DEL command");
-    assert_eq!(del_response, "OK");
+    assert_eq!(del_response, "1");
 
     // Verify it no longer exists
     let get_response_after_del =
@@ -76,18 +86,36 @@ fn del_command_removes_key_and_returns_ok() {
 }
 
 #[test]
-fn del_command_returns_ok_for_non_existing_key() {
-    let address = start_test_server();
+fn del_command_returns_zero_for_non_existing_key() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
 
     let response =
         send_command(&address, "DEL non_existing_key").expect("Failed to send DEL command");
 
-    assert_eq!(response, "OK");
+    assert_eq!(response, "0");
+}
+
+#[test]
+fn del_command_counts_only_the_keys_it_removed() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    send_command(&address, "SET k1 a").expect("Failed to send SET command");
+    send_command(&address, "SET k3 c").expect("Failed to send SET command");
+
+    let response = send_command(&address, "DEL k1 k2 k3").expect("Failed to send DEL command");
+    assert_eq!(response, "2");
+
+    let response = send_command(&address, "DEL k1 k2 k3").expect("Failed to send DEL command");
+    assert_eq!(response, "0");
+    let response = send_command(&address, "EXISTS k1 k3").expect("Failed to send EXISTS command");
+    assert_eq!(response, "0");
 }
 
 #[test]
 fn commands_are_case_insensitive() {
-    let address = start_test_server();
+    let server = TestServer::start();
+    let address = server.address().to_string();
 
     // Test lowercase commands
     let set_response = send_command(&address, "set case_key case_value")
@@ -100,12 +128,13 @@ fn commands_are_case_insensitive() {
 
     let del_response =
         send_command(&address, "del case_key").expect("Failed to send lowercase DEL command");
-    assert_eq!(del_response, "OK");
+    assert_eq!(del_response, "1");
 }
 
 #[test]
 fn invalid_command_returns_error() {
-    let address = start_test_server();
+    let server = TestServer::start();
+    let address = server.address().to_string();
 
     let response =
         send_command(&address, "INVALID_COMMAND some_arg").expect("Failed to send invalid command");
@@ -116,54 +145,371 @@ fn invalid_command_returns_error() {
 
 #[test]
 fn get_with_wrong_number_of_arguments_returns_error() {
-    let address = start_test_server();
+    let server = TestServer::start();
+    let address = server.address().to_string();
 
     // GET with no arguments
     let response = send_command(&address, "GET").expect("Failed to send GET with no args");
-    assert!(response.contains("Invalid arguments"));
+    assert!(response.contains("wrong number of arguments"));
 
     // GET with too many arguments
     let response =
         send_command(&address, "GET key1 key2").expect("Failed to send GET with too many args");
-    assert!(response.contains("Invalid arguments"));
+    assert!(response.contains("wrong number of arguments"));
 }
 
 #[test]
 fn set_with_wrong_number_of_arguments_returns_error() {
-    let address = start_test_server();
+    let server = TestServer::start();
+    let address = server.address().to_string();
 
     // SET with no arguments
     let response = send_command(&address, "SET").expect("Failed to send SET with no args");
-    assert!(response.contains("Invalid arguments"));
+    assert!(response.contains("wrong number of arguments"));
 
     // SET with only key (missing value)
     let response =
         send_command(&address, "SET only_key").expect("Failed to send SET with only key");
-    assert!(response.contains("Invalid arguments"));
+    assert!(response.contains("wrong number of arguments"));
 
-    // SET with too many arguments
+    // SET with an option it does not know
     let response = send_command(&address, "SET key value extra")
-        .expect("Failed to send SET with too many args");
-    assert!(response.contains("Invalid arguments"));
+        .expect("Failed to send SET with an unknown option");
+    assert!(
+        response.starts_with("ERR Invalid arguments"),
+        "{}",
+        response
+    );
 }
 
 #[test]
-fn del_with_wrong_number_of_arguments_returns_error() {
-    let address = start_test_server();
+fn set_command_with_nx_and_xx_writes_only_when_the_condition_holds() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
 
-    // DEL with no arguments
-    let response = send_command(&address, "DEL").expect("Failed to send DEL with no args");
-    assert!(response.contains("Invalid arguments"));
+    let response = send_command(&address, "SET key a XX").expect("Failed to send SET command");
+    assert_eq!(response, "nil");
+    let response = send_command(&address, "SET key b NX").expect("Failed to send SET command");
+    assert_eq!(response, "OK");
+    let response = send_command(&address, "SET key c nx").expect("Failed to send SET command");
+    assert_eq!(response, "nil");
+    let response = send_command(&address, "SET key d xx").expect("Failed to send SET command");
+    assert_eq!(response, "OK");
+    let response = send_command(&address, "SET key e NX XX").expect("Failed to send SET command");
+    assert!(
+        response.starts_with("ERR Invalid arguments"),
+        "{}",
+        response
+    );
+    let response = send_command(&address, "GET key").expect("Failed to send GET command");
+    assert_eq!(response, "d");
+}
+
+#[test]
+fn set_command_without_keepttl_clears_a_time_to_live_set_with_ex() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let response =
+        send_command(&address, "SET session a EX 1").expect("Failed to send SET command");
+    assert_eq!(response, "OK");
+    let response =
+        send_command(&address, "SET session b KEEPTTL").expect("Failed to send SET command");
+    assert_eq!(response, "OK");
+    let response =
+        send_command(&address, "SET other c PX 1000").expect("Failed to send SET command");
+    assert_eq!(response, "OK");
+    let response = send_command(&address, "SET other d").expect("Failed to send SET command");
+    assert_eq!(response, "OK");
+    let response =
+        send_command(&address, "SET bad e EX 1 PX 1000").expect("Failed to send SET command");
+    assert!(
+        response.starts_with("ERR Invalid arguments"),
+        "{}",
+        response
+    );
+    let response = send_command(&address, "SET bad e EX soon").expect("Failed to send SET command");
+    assert_eq!(response, "ERR value is not an integer or out of range");
+
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    let response = send_command(&address, "GET session").expect("Failed to send GET command");
+    assert_eq!(response, "nil");
+    let response = send_command(&address, "GET other").expect("Failed to send GET command");
+    assert_eq!(response, "d");
+}
+
+#[test]
+fn mset_command_sets_every_pair() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let response =
+        send_command(&address, "MSET first alice last smith").expect("Failed to send MSET command");
+    assert_eq!(response, "OK");
+    let response = send_command(&address, "GET first").expect("Failed to send GET command");
+    assert_eq!(response, "alice");
+    let response = send_command(&address, "GET last").expect("Failed to send GET command");
+    assert_eq!(response, "smith");
+
+    let response = send_command(&address, "MSET a 1 b").expect("Failed to send MSET command");
+    assert!(
+        response.starts_with("ERR Invalid arguments"),
+        "{}",
+        response
+    );
+    let response = send_command(&address, "GET a").expect("Failed to send GET command");
+    assert_eq!(response, "nil");
+}
+
+#[test]
+fn msetnx_command_writes_all_pairs_or_none() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let response = send_command(&address, "SET k3 taken").expect("Failed to send SET command");
+    assert_eq!(response, "OK");
+    let response = send_command(&address, "MSETNX k1 v1 k2 v2 k3 v3 k4 v4 k5 v5")
+        .expect("Failed to send MSETNX command");
+    assert_eq!(response, "0");
+    for key in ["k1", "k2", "k4", "k5"] {
+        let response =
+            send_command(&address, &format!("GET {}", key)).expect("Failed to send GET command");
+        assert_eq!(response, "nil", "{}", key);
+    }
+
+    let response =
+        send_command(&address, "MSETNX k1 v1 k2 v2").expect("Failed to send MSETNX command");
+    assert_eq!(response, "1");
+    let response = send_command(&address, "GET k2").expect("Failed to send GET command");
+    assert_eq!(response, "v2");
+}
+
+#[test]
+fn setrange_command_overwrites_part_of_a_value() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let response =
+        send_command(&address, "SET greeting \"Hello World\"").expect("Failed to send SET command");
+    assert_eq!(response, "OK");
+    let response = send_command(&address, "SETRANGE greeting 6 Redis")
+        .expect("Failed to send SETRANGE command");
+    assert_eq!(response, "11");
+    let response = send_command(&address, "GET greeting").expect("Failed to send GET command");
+    assert_eq!(response, "Hello Redis");
+
+    let response = send_command(&address, "SETRANGE greeting 536870911 xy")
+        .expect("Failed to send SETRANGE command");
+    assert_eq!(response, "ERR string exceeds maximum allowed size (512MB)");
+}
+
+#[test]
+fn setbit_and_getbit_commands_work_on_bits_of_the_value() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    for offset in [1, 6, 30] {
+        let response = send_command(&address, &format!("SETBIT flags {} 1", offset))
+            .expect("Failed to send SETBIT command");
+        assert_eq!(response, "0");
+    }
+    let response = send_command(&address, "STRLEN flags").expect("Failed to send STRLEN command");
+    assert_eq!(response, "4");
+    for (offset, bit) in [
+        (1, "1"),
+        (6, "1"),
+        (7, "0"),
+        (12, "0"),
+        (30, "1"),
+        (64, "0"),
+    ] {
+        let response = send_command(&address, &format!("GETBIT flags {}", offset))
+            .expect("Failed to send GETBIT command");
+        assert_eq!(response, bit, "{}", offset);
+    }
+    let response =
+        send_command(&address, "SETBIT flags 6 0").expect("Failed to send SETBIT command");
+    assert_eq!(response, "1");
+}
+
+#[test]
+fn bitcount_and_bitpos_commands_look_at_bits_of_the_value() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let response = send_command(&address, "SET key foobar").expect("Failed to send SET command");
+    assert_eq!(response, "OK");
+    let response = send_command(&address, "BITCOUNT key").expect("Failed to send BITCOUNT command");
+    assert_eq!(response, "26");
+    let response =
+        send_command(&address, "BITCOUNT key 1 -2").expect("Failed to send BITCOUNT command");
+    assert_eq!(response, "18");
+    let response = send_command(&address, "BITPOS key 1 1").expect("Failed to send BITPOS command");
+    assert_eq!(response, "9");
+    let response = send_command(&address, "BITPOS key 0").expect("Failed to send BITPOS command");
+    assert_eq!(response, "0");
+    let response =
+        send_command(&address, "BITPOS missing 1").expect("Failed to send BITPOS command");
+    assert_eq!(response, "-1");
+}
+
+#[test]
+fn bitcount_and_bitpos_commands_look_at_bytes_of_all_ones() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    for offset in 0..16 {
+        let response = send_command(&address, &format!("SETBIT ones {} 1", offset))
+            .expect("Failed to send SETBIT command");
+        assert_eq!(response, "0");
+    }
+    for (command, reply) in [
+        ("BITCOUNT ones", "16"),
+        ("BITCOUNT ones 1 1", "8"),
+        ("BITPOS ones 1", "0"),
+        ("BITPOS ones 0", "16"),
+        ("BITPOS ones 0 1", "16"),
+        ("BITPOS ones 0 0 -1", "-1"),
+        ("BITPOS ones 0 1 1", "-1"),
+    ] {
+        let response = send_command(&address, command).expect("Failed to send command");
+        assert_eq!(response, reply, "{}", command);
+    }
+}
+
+#[test]
+fn getrange_command_returns_part_of_a_value() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let response =
+        send_command(&address, "SET greeting \"Hello World\"").expect("Failed to send SET command");
+    assert_eq!(response, "OK");
+    let response =
+        send_command(&address, "GETRANGE greeting 0 4").expect("Failed to send GETRANGE command");
+    assert_eq!(response, "Hello");
+    let response =
+        send_command(&address, "GETRANGE greeting -5 -1").expect("Failed to send GETRANGE command");
+    assert_eq!(response, "World");
+    let response =
+        send_command(&address, "GETRANGE missing 0 -1").expect("Failed to send GETRANGE command");
+    assert_eq!(response, "");
+}
+
+#[test]
+fn randomkey_command_returns_keys_that_exist() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let response = send_command(&address, "RANDOMKEY").expect("Failed to send RANDOMKEY command");
+    assert_eq!(response, "nil");
+    let response =
+        send_command(&address, "MSET a 1 b 2 c 3 d 4 e 5").expect("Failed to send MSET command");
+    assert_eq!(response, "OK");
+
+    let mut seen = std::collections::HashSet::new();
+    for _ in 0..50 {
+        let key = send_command(&address, "RANDOMKEY").expect("Failed to send RANDOMKEY command");
+        assert!(["a", "b", "c", "d", "e"].contains(&key.as_str()), "{}", key);
+        seen.insert(key);
+    }
+    assert!(seen.len() > 1, "{:?}", seen);
+}
+
+#[test]
+fn rename_command_moves_a_value_or_reports_a_missing_key() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let response =
+        send_command(&address, "MSET draft text final old").expect("Failed to send MSET command");
+    assert_eq!(response, "OK");
+    let response =
+        send_command(&address, "RENAME draft final").expect("Failed to send RENAME command");
+    assert_eq!(response, "OK");
+    let response = send_command(&address, "GET final").expect("Failed to send GET command");
+    assert_eq!(response, "text");
+    let response = send_command(&address, "GET draft").expect("Failed to send GET command");
+    assert_eq!(response, "nil");
+
+    let response =
+        send_command(&address, "RENAME draft other").expect("Failed to send RENAME command");
+    assert_eq!(response, "ERR no such key");
+}
+
+#[test]
+fn touch_command_counts_existing_keys() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let response = send_command(&address, "MSET a 1 b 2").expect("Failed to send MSET command");
+    assert_eq!(response, "OK");
+    let response =
+        send_command(&address, "TOUCH a b missing").expect("Failed to send TOUCH command");
+    assert_eq!(response, "2");
+}
+
+#[test]
+fn unlink_command_deletes_keys_like_del() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let response = send_command(&address, "MSET a 1 b 2").expect("Failed to send MSET command");
+    assert_eq!(response, "OK");
+    let response =
+        send_command(&address, "UNLINK a missing b").expect("Failed to send UNLINK command");
+    assert_eq!(response, "2");
+    let response = send_command(&address, "EXISTS a b").expect("Failed to send EXISTS command");
+    assert_eq!(response, "0");
+}
+
+#[test]
+fn object_and_debug_object_commands_describe_a_key() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
 
-    // DEL with too many arguments
     let response =
-        send_command(&address, "DEL key1 key2").expect("Failed to send DEL with too many args");
-    assert!(response.contains("Invalid arguments"));
+        send_command(&address, "MSET n 100 s hello").expect("Failed to send MSET command");
+    assert_eq!(response, "OK");
+    let response =
+        send_command(&address, "OBJECT ENCODING n").expect("Failed to send OBJECT command");
+    assert_eq!(response, "int");
+    let response =
+        send_command(&address, "OBJECT ENCODING s").expect("Failed to send OBJECT command");
+    assert_eq!(response, "raw");
+    let response = send_command(&address, "DEBUG OBJECT s").expect("Failed to send DEBUG command");
+    assert!(response.contains("serializedlength:5"), "{}", response);
+    let response =
+        send_command(&address, "OBJECT ENCODING missing").expect("Failed to send OBJECT command");
+    assert_eq!(response, "ERR no such key");
+}
+
+#[test]
+fn type_command_returns_string_or_none() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let response = send_command(&address, "SET name alice").expect("Failed to send SET command");
+    assert_eq!(response, "OK");
+    let response = send_command(&address, "TYPE name").expect("Failed to send TYPE command");
+    assert_eq!(response, "string");
+    let response = send_command(&address, "TYPE missing").expect("Failed to send TYPE command");
+    assert_eq!(response, "none");
+}
+
+#[test]
+fn del_without_keys_returns_error() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let response = send_command(&address, "DEL").expect("Failed to send DEL with no args");
+    assert!(response.contains("wrong number of arguments"));
 }
 
 #[test]
 fn server_handles_commands_with_extra_whitespace() {
-    let address = start_test_server();
+    let server = TestServer::start();
+    let address = server.address().to_string();
 
     // Test commands with extra spaces
     let response = send_command(&address, "  SET   space_key   space_value  ")
@@ -174,3 +520,345 @@ fn server_handles_commands_with_extra_whitespace() {
         .expect("Failed to send GET with extra spaces");
     assert_eq!(response, "space_value");
 }
+
+#[test]
+fn exists_command_counts_repeated_keys_every_time() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    send_command(&address, "SET a 1").expect("Failed to send SET command");
+
+    let response = send_command(&address, "EXISTS a a a").expect("Failed to send EXISTS command");
+    assert_eq!(response, "3");
+    let response = send_command(&address, "EXISTS a a b").expect("Failed to send EXISTS command");
+    assert_eq!(response, "2");
+}
+
+#[test]
+fn exists_command_counts_only_the_keys_that_exist() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    send_command(&address, "SET a 1").expect("Failed to send SET command");
+    send_command(&address, "SET c 3").expect("Failed to send SET command");
+
+    let response = send_command(&address, "EXISTS a b c d").expect("Failed to send EXISTS command");
+    assert_eq!(response, "2");
+    let response = send_command(&address, "EXISTS b d").expect("Failed to send EXISTS command");
+    assert_eq!(response, "0");
+    let response = send_command(&address, "EXISTS").expect("Failed to send EXISTS command");
+    assert!(response.starts_with("ERR"), "{}", response);
+}
+
+#[test]
+fn expire_command_removes_the_key_once_its_time_to_live_runs_out() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    send_command(&address, "SET session abc").expect("Failed to send SET command");
+
+    let response = send_command(&address, "TTL session").expect("Failed to send TTL command");
+    assert_eq!(response, "-1");
+    let response =
+        send_command(&address, "EXPIRE session 1").expect("Failed to send EXPIRE command");
+    assert_eq!(response, "1");
+    let response = send_command(&address, "TTL session").expect("Failed to send TTL command");
+    assert_eq!(response, "1");
+    std::thread::sleep(std::time::Duration::from_millis(600));
+    let response = send_command(&address, "GET session").expect("Failed to send GET command");
+    assert_eq!(response, "abc");
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    let response = send_command(&address, "GET session").expect("Failed to send GET command");
+    assert_eq!(response, "nil");
+    let response = send_command(&address, "TTL session").expect("Failed to send TTL command");
+    assert_eq!(response, "-2");
+    let response =
+        send_command(&address, "EXPIRE session 1").expect("Failed to send EXPIRE command");
+    assert_eq!(response, "0");
+}
+
+#[test]
+fn expire_command_options_compare_with_the_current_time_to_live() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    send_command(&address, "SET session abc").expect("Failed to send SET command");
+
+    let response =
+        send_command(&address, "EXPIRE session 100 GT").expect("Failed to send EXPIRE command");
+    assert_eq!(response, "0");
+    let response =
+        send_command(&address, "EXPIRE session 100 NX").expect("Failed to send EXPIRE command");
+    assert_eq!(response, "1");
+    let response =
+        send_command(&address, "EXPIRE session 50 GT").expect("Failed to send EXPIRE command");
+    assert_eq!(response, "0");
+    let response =
+        send_command(&address, "EXPIRE session 50 LT").expect("Failed to send EXPIRE command");
+    assert_eq!(response, "1");
+    let response = send_command(&address, "TTL session").expect("Failed to send TTL command");
+    assert_eq!(response, "50");
+    for command in ["EXPIRE session 10 NX GT", "EXPIRE session 10 NX LT"] {
+        let response = send_command(&address, command).expect("Failed to send EXPIRE command");
+        assert_eq!(
+            response,
+            "ERR NX and XX, GT or LT options at the same time are not compatible"
+        );
+    }
+    let response =
+        send_command(&address, "EXPIRE session 10 GT LT").expect("Failed to send EXPIRE command");
+    assert_eq!(
+        response,
+        "ERR GT and LT options at the same time are not compatible"
+    );
+}
+
+#[test]
+fn pexpire_command_expires_the_key_in_milliseconds() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    send_command(&address, "SET session abc").expect("Failed to send SET command");
+
+    let response =
+        send_command(&address, "PEXPIRE session 150").expect("Failed to send PEXPIRE command");
+    assert_eq!(response, "1");
+    let first: i64 = send_command(&address, "PTTL session")
+        .expect("Failed to send PTTL command")
+        .parse()
+        .expect("PTTL did not return an integer");
+    assert!(first > 0 && first <= 150, "{}", first);
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    let second: i64 = send_command(&address, "PTTL session")
+        .expect("Failed to send PTTL command")
+        .parse()
+        .expect("PTTL did not return an integer");
+    assert!(second < first, "{} then {}", first, second);
+    std::thread::sleep(std::time::Duration::from_millis(150));
+    let response = send_command(&address, "GET session").expect("Failed to send GET command");
+    assert_eq!(response, "nil");
+    let response = send_command(&address, "PTTL session").expect("Failed to send PTTL command");
+    assert_eq!(response, "-2");
+}
+
+#[test]
+fn pexpireat_command_expires_the_key_at_a_unix_timestamp() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    send_command(&address, "MSET soon a past b").expect("Failed to send MSET command");
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .expect("Clock is before the Unix epoch")
+        .as_millis();
+
+    let response = send_command(&address, &format!("PEXPIREAT soon {}", now + 200))
+        .expect("Failed to send PEXPIREAT command");
+    assert_eq!(response, "1");
+    let response = send_command(&address, &format!("PEXPIREAT past {}", now - 1000))
+        .expect("Failed to send PEXPIREAT command");
+    assert_eq!(response, "1");
+    let response =
+        send_command(&address, "EXISTS soon past").expect("Failed to send EXISTS command");
+    assert_eq!(response, "1");
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    let response = send_command(&address, "GET soon").expect("Failed to send GET command");
+    assert_eq!(response, "nil");
+}
+
+#[test]
+fn persist_command_keeps_the_key_from_expiring() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    send_command(&address, "SET session abc").expect("Failed to send SET command");
+
+    let response =
+        send_command(&address, "PERSIST session").expect("Failed to send PERSIST command");
+    assert_eq!(response, "0");
+    send_command(&address, "PEXPIRE session 100").expect("Failed to send PEXPIRE command");
+    let response =
+        send_command(&address, "PERSIST session").expect("Failed to send PERSIST command");
+    assert_eq!(response, "1");
+    let response = send_command(&address, "TTL session").expect("Failed to send TTL command");
+    assert_eq!(response, "-1");
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    let response = send_command(&address, "GET session").expect("Failed to send GET command");
+    assert_eq!(response, "abc");
+}
+
+#[test]
+fn getex_command_expires_the_key_it_reads() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    send_command(&address, "SET session abc").expect("Failed to send SET command");
+
+    let response =
+        send_command(&address, "GETEX session EX 1").expect("Failed to send GETEX command");
+    assert_eq!(response, "abc");
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    let response = send_command(&address, "GETEX session").expect("Failed to send GETEX command");
+    assert_eq!(response, "nil");
+}
+
+#[test]
+fn getex_command_rejects_more_than_one_option() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    send_command(&address, "SET session abc").expect("Failed to send SET command");
+
+    for command in [
+        "GETEX session EX 10 PERSIST",
+        "GETEX session PERSIST PX 100",
+    ] {
+        let response = send_command(&address, command).expect("Failed to send GETEX command");
+        assert_eq!(response, "ERR syntax error", "{}", command);
+    }
+    let response = send_command(&address, "TTL session").expect("Failed to send TTL command");
+    assert_eq!(response, "-1");
+}
+
+#[test]
+fn incrby_and_decrby_commands_add_signed_amounts() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let response = send_command(&address, "INCRBY n -5").expect("Failed to send INCRBY command");
+    assert_eq!(response, "-5");
+    let response = send_command(&address, "DECRBY n -15").expect("Failed to send DECRBY command");
+    assert_eq!(response, "10");
+    let response = send_command(&address, "GET n").expect("Failed to send GET command");
+    assert_eq!(response, "10");
+}
+
+#[test]
+fn incrby_command_reports_overflow_and_keeps_the_value() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    send_command(&address, &format!("SET n {}", i64::MAX)).expect("Failed to send SET command");
+    send_command(&address, "SET name alice").expect("Failed to send SET command");
+
+    let response = send_command(&address, "INCRBY n 1").expect("Failed to send INCRBY command");
+    assert_eq!(response, "ERR increment or decrement would overflow");
+    let response = send_command(&address, "INCRBY name 1").expect("Failed to send INCRBY command");
+    assert_eq!(response, "ERR value is not an integer or out of range");
+    let response = send_command(&address, "GET n").expect("Failed to send GET command");
+    assert_eq!(response, i64::MAX.to_string());
+}
+
+#[test]
+fn incrbyfloat_command_adds_fractions_without_trailing_zeros() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let mut response = String::new();
+    for _ in 0..4 {
+        response = send_command(&address, "INCRBYFLOAT average 0.25")
+            .expect("Failed to send INCRBYFLOAT command");
+    }
+    assert_eq!(response, "1");
+    let response = send_command(&address, "INCRBYFLOAT average 2.1")
+        .expect("Failed to send INCRBYFLOAT command");
+    assert_eq!(response, "3.1");
+    let response = send_command(&address, "INCRBYFLOAT average inf")
+        .expect("Failed to send INCRBYFLOAT command");
+    assert_eq!(response, "ERR value is not a valid float");
+    let response = send_command(&address, "GET average").expect("Failed to send GET command");
+    assert_eq!(response, "3.1");
+}
+
+#[test]
+fn delprefix_command_removes_only_matching_keys() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    send_command(&address, "SET cache:1 a").expect("Failed to send SET command");
+    send_command(&address, "SET cache:2 b").expect("Failed to send SET command");
+    send_command(&address, "SET keep:1 c").expect("Failed to send SET command");
+
+    let response =
+        send_command(&address, "DELPREFIX cache:").expect("Failed to send DELPREFIX command");
+    assert_eq!(response, "2");
+
+    let response = send_command(&address, "GET cache:1").expect("Failed to send GET command");
+    assert_eq!(response, "nil");
+    let response = send_command(&address, "GET keep:1").expect("Failed to send GET command");
+    assert_eq!(response, "c");
+}
+
+#[test]
+fn help_command_explains_commands_without_failing_on_unknown_ones() {
+    let server = TestServer::start();
+
+    assert_eq!(
+        "*3\nSET <KEY> <VALUE> [NX|XX] [EX <SECONDS>|PX <MILLISECONDS>|KEEPTTL]\nSet the value of a key.\nSince 0.1.0.",
+        server.send("HELP set")
+    );
+    assert!(server.send("HELP").contains("\nSCAN <CURSOR> "));
+    assert_eq!(
+        "No help available for FLUSHALL, try HELP.",
+        server.send("HELP FLUSHALL")
+    );
+}
+
+#[test]
+fn commands_ending_in_crlf_store_values_without_the_carriage_return() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let response =
+        send_command(&address, "SET telnet value\r").expect("Failed to send SET command");
+    assert_eq!(response, "OK");
+    let response =
+        send_command(&address, "SET quoted \"two words\"\r").expect("Failed to send SET command");
+    assert_eq!(response, "OK");
+
+    assert_eq!(server.send("STRLEN telnet"), "5");
+    assert_eq!(server.send("GET quoted"), "two words");
+}
+
+/// Sends a request one byte at a time, and reads as many lines of replies.
+fn dribble(address: &str, request: &[u8], lines: usize) -> String {
+    let mut stream = TcpStream::connect(address).expect("Failed to connect");
+    stream.set_nodelay(true).unwrap();
+    for byte in request {
+        stream.write_all(&[*byte]).unwrap();
+        thread::sleep(Duration::from_millis(1));
+    }
+    let mut reader = BufReader::new(stream);
+    let mut replies = String::new();
+    for _ in 0..lines {
+        reader.read_line(&mut replies).unwrap();
+    }
+    replies
+}
+
+#[test]
+fn commands_sent_a_byte_at_a_time_are_executed_once_complete() {
+    let server = TestServer::start();
+
+    let inline = dribble(server.address(), b"SET slow \"a b\"\r\nGET slow\r\n", 2);
+    let resp = dribble(
+        server.address(),
+        b"*3\r\n$3\r\nSET\r\n$4\r\nresp\r\n$4\r\nx\r\ny\r\n*2\r\n$3\r\nGET\r\n$4\r\nresp\r\n",
+        4,
+    );
+
+    assert_eq!(inline, "OK\na b\n");
+    assert_eq!(resp, "+OK\r\n$4\r\nx\r\ny\r\n");
+}
+
+#[test]
+fn config_resetstat_zeroes_counters_that_then_count_again() {
+    let server = TestServer::start();
+    server.send("SET a 1");
+    server.send("GET a");
+
+    let before = server.send("INFO stats");
+    assert_eq!("OK", server.send("CONFIG RESETSTAT"));
+    server.send("GET missing");
+    let after = server.send("INFO stats");
+
+    assert!(before.contains("keyspace_hits\n1\n"), "{}", before);
+    assert!(
+        after.contains("total_connections_received\n2\n"),
+        "{}",
+        after
+    );
+    assert!(after.contains("total_commands_processed\n2\n"), "{}", after);
+    assert!(after.contains("keyspace_hits\n0\n"), "{}", after);
+    assert!(after.contains("keyspace_misses\n1\n"), "{}", after);
+}
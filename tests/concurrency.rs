@@ -1,13 +1,339 @@
 mod helpers;
-use helpers::{send_command, start_test_server};
+use helpers::send_command;
 
+use miniredis::connection::Connection;
+use miniredis::testing::TestServer;
+use std::sync::mpsc;
 use std::sync::{Arc, Barrier, Mutex};
 use std::thread;
 use std::time::Duration;
 
+#[test]
+fn concurrent_incr_from_many_clients_loses_no_increment() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let num_threads = 16;
+    let increments = 50;
+    let barrier = Arc::new(Barrier::new(num_threads));
+
+    let handles: Vec<_> = (0..num_threads)
+        .map(|_| {
+            let addr = address.clone();
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || {
+                barrier.wait();
+                for _ in 0..increments {
+                    let response =
+                        send_command(&addr, "INCR counter").expect("Failed to send INCR command");
+                    assert!(response.parse::<i64>().is_ok(), "{}", response);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().expect("Client thread panicked");
+    }
+
+    let response = send_command(&address, "GET counter").expect("Failed to send GET command");
+    assert_eq!(response, (num_threads * increments).to_string());
+}
+
+#[test]
+fn concurrent_incr_and_decr_cancel_out() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let handles: Vec<_> = (0..10)
+        .map(|i| {
+            let addr = address.clone();
+            let command = if i % 2 == 0 {
+                "INCR balance"
+            } else {
+                "DECR balance"
+            };
+            thread::spawn(move || {
+                for _ in 0..50 {
+                    send_command(&addr, command).expect("Failed to send command");
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().expect("Client thread panicked");
+    }
+
+    let response = send_command(&address, "GET balance").expect("Failed to send GET command");
+    assert_eq!(response, "0");
+}
+
+#[test]
+fn concurrent_append_from_many_clients_loses_no_suffix() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+
+    let handles: Vec<_> = (0..8)
+        .map(|i| {
+            let addr = address.clone();
+            thread::spawn(move || {
+                let command = format!("APPEND log {}", i);
+                for _ in 0..25 {
+                    send_command(&addr, &command).expect("Failed to send APPEND command");
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().expect("Client thread panicked");
+    }
+
+    let log = send_command(&address, "GET log").expect("Failed to send GET command");
+    assert_eq!(log.len(), 200);
+    for i in 0..8 {
+        assert_eq!(log.matches(&i.to_string()).count(), 25, "{}", log);
+    }
+    let response = send_command(&address, "APPEND log !").expect("Failed to send APPEND command");
+    assert_eq!(response, "201");
+    let response = send_command(&address, "STRLEN log").expect("Failed to send STRLEN command");
+    assert_eq!(response, "201");
+}
+
+#[test]
+fn concurrent_renamenx_onto_one_key_lets_exactly_one_client_win() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let response =
+        send_command(&address, "MSET first a second b").expect("Failed to send MSET command");
+    assert_eq!(response, "OK");
+    let barrier = Arc::new(Barrier::new(2));
+
+    let handles: Vec<_> = ["first", "second"]
+        .into_iter()
+        .map(|source| {
+            let addr = address.clone();
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || {
+                barrier.wait();
+                let response = send_command(&addr, &format!("RENAMENX {} winner", source))
+                    .expect("Failed to send RENAMENX command");
+                (source, response)
+            })
+        })
+        .collect();
+    let results: Vec<(&str, String)> = handles
+        .into_iter()
+        .map(|handle| handle.join().expect("Client thread panicked"))
+        .collect();
+
+    let winners: Vec<&str> = results
+        .iter()
+        .filter(|(_, response)| response == "1")
+        .map(|(source, _)| *source)
+        .collect();
+    assert_eq!(winners.len(), 1, "{:?}", results);
+    assert!(
+        results
+            .iter()
+            .all(|(_, response)| response == "1" || response == "0")
+    );
+    let value = send_command(&address, "GET winner").expect("Failed to send GET command");
+    let loser = if winners[0] == "first" {
+        "second"
+    } else {
+        "first"
+    };
+    assert_eq!(value, if winners[0] == "first" { "a" } else { "b" });
+    let response =
+        send_command(&address, &format!("GET {}", loser)).expect("Failed to send GET command");
+    assert_ne!(response, "nil");
+}
+
+#[test]
+fn server_keeps_serving_while_unlink_frees_a_large_value() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let response =
+        send_command(&address, "SETRANGE big 16777215 x").expect("Failed to send SETRANGE command");
+    assert_eq!(response, "16777216");
+    let barrier = Arc::new(Barrier::new(2));
+
+    let pinger = {
+        let addr = address.clone();
+        let barrier = Arc::clone(&barrier);
+        thread::spawn(move || {
+            barrier.wait();
+            (0..20)
+                .map(|_| send_command(&addr, "PING").expect("Failed to send PING command"))
+                .collect::<Vec<String>>()
+        })
+    };
+    barrier.wait();
+    let response = send_command(&address, "UNLINK big").expect("Failed to send UNLINK command");
+
+    assert_eq!(response, "1");
+    let pongs = pinger.join().expect("Client thread panicked");
+    assert!(pongs.iter().all(|pong| pong == "PONG"), "{:?}", pongs);
+    let response = send_command(&address, "GET big").expect("Failed to send GET command");
+    assert_eq!(response, "nil");
+}
+
+#[test]
+fn concurrent_unlink_leaves_every_key_fully_present_or_fully_gone() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let keys: Vec<String> = (0..20).map(|i| format!("big{}", i)).collect();
+    let original = "x".repeat(64 * 1024);
+    for key in &keys {
+        let response = send_command(&address, &format!("SET {} {}", key, original))
+            .expect("Failed to send SET command");
+        assert_eq!(response, "OK");
+    }
+    let barrier = Arc::new(Barrier::new(2));
+    let (done, racing) = mpsc::channel();
+
+    let unlinker = {
+        let addr = address.clone();
+        let barrier = Arc::clone(&barrier);
+        let command = format!("UNLINK {}", keys.join(" "));
+        thread::spawn(move || {
+            barrier.wait();
+            std::iter::from_fn(|| racing.try_recv().is_err().then_some(()))
+                .map(|_| {
+                    let response =
+                        send_command(&addr, &command).expect("Failed to send UNLINK command");
+                    response
+                        .parse::<usize>()
+                        .expect("UNLINK must reply with a count")
+                })
+                .sum::<usize>()
+        })
+    };
+    let racer = {
+        let addr = address.clone();
+        let barrier = Arc::clone(&barrier);
+        let keys = keys.clone();
+        let original = original.clone();
+        thread::spawn(move || {
+            barrier.wait();
+            let mut created = 0;
+            for _ in 0..5 {
+                for key in &keys {
+                    let value = send_command(&addr, &format!("GET {}", key))
+                        .expect("Failed to send GET command");
+                    assert!(
+                        value == "nil" || value == "again" || value == original,
+                        "GET {} returned a value of {} bytes",
+                        key,
+                        value.len()
+                    );
+                    let exists = send_command(&addr, &format!("EXISTS {}", key))
+                        .expect("Failed to send EXISTS command");
+                    assert!(exists == "0" || exists == "1", "{}", exists);
+                    if value == "nil" {
+                        let response = send_command(&addr, &format!("SET {} again NX", key))
+                            .expect("Failed to send SET command");
+                        if response == "OK" {
+                            created += 1;
+                        }
+                    }
+                }
+            }
+            done.send(()).expect("The unlinking thread stopped early");
+            created
+        })
+    };
+    let deleted = unlinker.join().expect("Client thread panicked");
+    let created = racer.join().expect("Client thread panicked");
+
+    let left = send_command(&address, &format!("EXISTS {}", keys.join(" ")))
+        .expect("Failed to send EXISTS command")
+        .parse::<usize>()
+        .expect("EXISTS must reply with a count");
+    // Every key that ever existed was either unlinked exactly once or is still there.
+    assert_eq!(keys.len() + created, deleted + left);
+    for key in &keys {
+        let value =
+            send_command(&address, &format!("GET {}", key)).expect("Failed to send GET command");
+        assert!(value == "nil" || value == "again" || value == original);
+    }
+}
+
+#[test]
+fn concurrent_setnx_lets_exactly_one_client_win() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let barrier = Arc::new(Barrier::new(20));
+
+    let handles: Vec<_> = (0..20)
+        .map(|i| {
+            let addr = address.clone();
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || {
+                barrier.wait();
+                let response = send_command(&addr, &format!("SETNX lock client{}", i))
+                    .expect("Failed to send SETNX command");
+                (i, response)
+            })
+        })
+        .collect();
+    let results: Vec<(usize, String)> = handles
+        .into_iter()
+        .map(|handle| handle.join().expect("Client thread panicked"))
+        .collect();
+
+    let winners: Vec<usize> = results
+        .iter()
+        .filter(|(_, response)| response == "1")
+        .map(|(i, _)| *i)
+        .collect();
+    assert_eq!(winners.len(), 1, "{:?}", results);
+    assert!(
+        results
+            .iter()
+            .all(|(_, response)| response == "1" || response == "0")
+    );
+    let holder = send_command(&address, "GET lock").expect("Failed to send GET command");
+    assert_eq!(holder, format!("client{}", winners[0]));
+}
+
+#[test]
+fn concurrent_getset_never_returns_an_old_value_twice() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    send_command(&address, "SET token initial").expect("Failed to send SET command");
+
+    let handles: Vec<_> = (0..8)
+        .map(|i| {
+            let addr = address.clone();
+            thread::spawn(move || {
+                (0..25)
+                    .map(|j| {
+                        send_command(&addr, &format!("GETSET token {}-{}", i, j))
+                            .expect("Failed to send GETSET command")
+                    })
+                    .collect::<Vec<String>>()
+            })
+        })
+        .collect();
+    let mut old_values = Vec::new();
+    for handle in handles {
+        old_values.extend(handle.join().expect("Client thread panicked"));
+    }
+    old_values.push(send_command(&address, "GET token").expect("Failed to send GET command"));
+
+    // Every value set is either handed back exactly once or is the one left in the key.
+    let mut expected: Vec<String> = (0..8)
+        .flat_map(|i| (0..25).map(move |j| format!("{}-{}", i, j)))
+        .collect();
+    expected.push("initial".to_string());
+    expected.sort();
+    old_values.sort();
+    assert_eq!(old_values, expected);
+}
+
 #[test]
 fn multiple_clients_can_connect_simultaneously() {
-    let address = start_test_server();
+    let server = TestServer::start();
+    let address = server.address().to_string();
 
     // Spawn multiple threads that act as different clients
     let handles: Vec<_> = (0..5)
@@ -38,7 +364,8 @@ fn multiple_clients_can_connect_simultaneously() {
 
 #[test]
 fn concurrent_operations_on_same_key() {
-    let address = start_test_server();
+    let server = TestServer::start();
+    let address = server.address().to_string();
     let num_threads = 10;
     let barrier = Arc::new(Barrier::new(num_threads));
 
@@ -74,7 +401,8 @@ fn concurrent_operations_on_same_key() {
 
 #[test]
 fn concurrent_set_and_get_operations() {
-    let address = start_test_server();
+    let server = TestServer::start();
+    let address = server.address().to_string();
     let num_operations = 20;
     let barrier = Arc::new(Barrier::new(num_operations));
 
@@ -115,7 +443,8 @@ fn concurrent_set_and_get_operations() {
 
 #[test]
 fn concurrent_delete_operations() {
-    let address = start_test_server();
+    let server = TestServer::start();
+    let address = server.address().to_string();
 
     // First, set up some initial data
     for i in 0..10 {
@@ -139,7 +468,7 @@ fn concurrent_delete_operations() {
                 let key = format!("delete_key_{}", i);
                 let response = send_command(&addr, &format!("DEL {}", key))
                     .expect("Failed to send DEL command");
-                assert_eq!(response, "OK");
+                assert_eq!(response, "1");
             })
         })
         .collect();
@@ -160,7 +489,8 @@ fn concurrent_delete_operations() {
 
 #[test]
 fn stress_test_many_concurrent_operations() {
-    let address = start_test_server();
+    let server = TestServer::start();
+    let address = server.address().to_string();
     let num_threads = 50;
     let operations_per_thread = 20;
     let barrier = Arc::new(Barrier::new(num_threads));
@@ -221,7 +551,8 @@ fn stress_test_many_concurrent_operations() {
 
 #[test]
 fn concurrent_read_heavy_workload() {
-    let address = start_test_server();
+    let server = TestServer::start();
+    let address = server.address().to_string();
 
     // Set up initial data
     let num_keys = 10;
@@ -266,7 +597,8 @@ fn concurrent_read_heavy_workload() {
 
 #[test]
 fn concurrent_write_heavy_workload() {
-    let address = start_test_server();
+    let server = TestServer::start();
+    let address = server.address().to_string();
     let num_writers = 15;
     let writes_per_writer = 30;
     let barrier = Arc::new(Barrier::new(num_writers));
@@ -312,7 +644,8 @@ fn concurrent_write_heavy_workload() {
 
 #[test]
 fn mixed_concurrent_workload() {
-    let address = start_test_server();
+    let server = TestServer::start();
+    let address = server.address().to_string();
     let total_threads = 30;
     let operations_per_thread = 25;
     let barrier = Arc::new(Barrier::new(total_threads));
@@ -357,7 +690,7 @@ fn mixed_concurrent_workload() {
                                 .expect("Failed to send SET command");
                             let response = send_command(&addr, &format!("DEL {}", key))
                                 .expect("Failed to send DEL command");
-                            assert_eq!(response, "OK");
+                            assert_eq!(response, "1");
                         }
                         _ => unreachable!(),
                     }
@@ -371,3 +704,81 @@ fn mixed_concurrent_workload() {
         handle.join().expect("Thread panicked");
     }
 }
+
+#[test]
+fn client_pause_write_holds_writes_until_unpaused() {
+    let server = TestServer::start();
+    server.send("SET key before");
+    assert_eq!("OK", server.send("CLIENT PAUSE 60000 WRITE"));
+
+    let (done, set) = mpsc::channel();
+    let address = server.address().to_string();
+    thread::spawn(move || done.send(send_command(&address, "SET key after").unwrap()));
+
+    assert!(set.recv_timeout(Duration::from_millis(200)).is_err());
+    assert_eq!("before", server.send("GET key"));
+
+    assert_eq!("OK", server.send("CLIENT UNPAUSE"));
+    assert_eq!("OK", set.recv_timeout(Duration::from_secs(5)).unwrap());
+    assert_eq!("after", server.send("GET key"));
+}
+
+#[test]
+fn shutting_down_lets_go_of_paused_clients() {
+    let server = TestServer::start();
+    assert_eq!("OK", server.send("CLIENT PAUSE 60000"));
+    let address = server.address().to_string();
+    let held = thread::spawn(move || send_command(&address, "GET key"));
+    thread::sleep(Duration::from_millis(100));
+
+    let (done, dropped) = mpsc::channel();
+    thread::spawn(move || {
+        drop(server);
+        done.send(()).unwrap();
+    });
+
+    assert!(dropped.recv_timeout(Duration::from_secs(5)).is_ok());
+    let _ = held.join();
+}
+
+#[test]
+fn scan_visits_every_key_that_exists_for_the_whole_scan_despite_concurrent_writes() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let mut expected = (0..300)
+        .map(|i| format!("stable:{}", i))
+        .collect::<Vec<String>>();
+    let mut connection = Connection::connect(&address).expect("Failed to connect");
+    for key in &expected {
+        connection.set(key, "value").unwrap();
+    }
+    let (stop_tx, stop_rx) = mpsc::channel::<()>();
+
+    let writer = {
+        let addr = address.clone();
+        thread::spawn(move || {
+            let mut i = 0;
+            while stop_rx.try_recv().is_err() {
+                send_command(&addr, &format!("SET churn:{} value", i))
+                    .expect("Failed to send SET command");
+                if i >= 10 {
+                    send_command(&addr, &format!("DEL churn:{}", i - 10))
+                        .expect("Failed to send DEL command");
+                }
+                i += 1;
+            }
+        })
+    };
+    let mut keys = Vec::new();
+    for key in connection.scan_iter(Some("stable:*")) {
+        keys.push(key.unwrap());
+        thread::sleep(Duration::from_micros(200));
+    }
+    stop_tx.send(()).unwrap();
+    writer.join().expect("Writer thread panicked");
+
+    keys.sort();
+    keys.dedup();
+    expected.sort();
+    assert_eq!(expected, keys);
+}
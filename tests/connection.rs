@@ -0,0 +1,497 @@
+mod helpers;
+use helpers::send_command;
+
+use miniredis::cluster::{SlotMap, key_slot};
+use miniredis::connection::{ConnectOptions, Connection, Protocol};
+use miniredis::error::MiniRedisError;
+use miniredis::kv_store::KVStore;
+use miniredis::log::{Level, StderrLogger};
+use miniredis::response::Response;
+use miniredis::server::Server;
+use miniredis::storage::ScanOptions;
+use miniredis::testing::TestServer;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+
+#[test]
+fn connection_sets_gets_and_deletes_keys_over_one_stream() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let mut connection = Connection::connect(&address).expect("Failed to connect");
+
+    connection.set("name", "alice smith").unwrap();
+    assert_eq!(
+        Some("alice smith".to_string()),
+        connection.get("name").unwrap()
+    );
+    assert!(connection.del("name").unwrap());
+    assert!(!connection.del("name").unwrap());
+    assert_eq!(None, connection.get("name").unwrap());
+}
+
+#[test]
+fn connection_sees_writes_from_other_clients() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let mut connection = Connection::connect(&address).expect("Failed to connect");
+
+    send_command(&address, "SET shared 1").expect("Failed to send SET command");
+
+    assert_eq!(Some("1".to_string()), connection.get("shared").unwrap());
+}
+
+#[test]
+fn connection_returns_structured_responses_from_any_command() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let mut connection = Connection::connect(&address).expect("Failed to connect");
+    connection.set("user:1", "alice").unwrap();
+    connection.set("user:2", "bob").unwrap();
+
+    let response = connection.command(&["DELPREFIX", "user:"]).unwrap();
+
+    assert_eq!(response, Response::Integer(2));
+}
+
+#[test]
+fn connection_maps_error_replies_to_errors() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let mut connection = Connection::connect(&address).expect("Failed to connect");
+
+    let unknown = connection.command(&["UNKNOWN", "arg"]);
+    let missing = connection.command(&["GET"]);
+    let empty = connection.command(&[]);
+
+    assert_eq!(
+        unknown,
+        Err(MiniRedisError::InvalidCommand {
+            command: "UNKNOWN".to_string()
+        })
+    );
+    assert_eq!(
+        missing,
+        Err(MiniRedisError::WrongArity {
+            command: "GET".to_string()
+        })
+    );
+    assert_eq!(
+        empty,
+        Err(MiniRedisError::InvalidArguments { arguments: vec![] })
+    );
+    assert_eq!(connection.get("still-usable").unwrap(), None);
+}
+
+#[test]
+fn connect_fails_when_no_server_listens() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("Failed to bind to a port");
+    let address = listener.local_addr().unwrap().to_string();
+    drop(listener);
+
+    assert_eq!(
+        Connection::connect(&address).map(|_| ()),
+        Err(MiniRedisError::StreamNotConnected {
+            address,
+            source: std::io::ErrorKind::ConnectionRefused.into(),
+        })
+    );
+}
+
+#[test]
+fn connect_errors_keep_the_io_error_behind_them() {
+    use std::error::Error;
+
+    let error = Connection::connect(&closed_address())
+        .map(|_| ())
+        .unwrap_err();
+
+    assert_eq!(Some(std::io::ErrorKind::ConnectionRefused), error.kind());
+    assert!(error.source().is_some());
+    assert!(
+        error
+            .to_string()
+            .contains(&error.source().unwrap().to_string())
+    );
+    let boxed: Box<dyn Error> = Box::new(error);
+    assert!(
+        boxed
+            .to_string()
+            .starts_with("Could not connect to the stream at")
+    );
+}
+
+/// Finds an address where nothing listens.
+fn closed_address() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("Failed to bind to a port");
+    listener.local_addr().unwrap().to_string()
+}
+
+#[test]
+fn connect_tries_every_address_in_order() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let closed = closed_address();
+
+    let mut connection =
+        Connection::connect(&format!("{},{}", closed, address)).expect("Failed to connect");
+
+    assert_eq!(address, connection.address());
+    connection.set("a", "1").unwrap();
+    assert_eq!(Some("1".to_string()), connection.get("a").unwrap());
+}
+
+#[test]
+fn connect_reports_the_failure_of_every_address() {
+    let first = closed_address();
+    let second = closed_address();
+
+    let result = Connection::connect(&format!("{}, {}", first, second)).map(|_| ());
+
+    assert_eq!(
+        Err(MiniRedisError::AddressesNotConnected {
+            failures: vec![
+                (
+                    first.clone(),
+                    MiniRedisError::StreamNotConnected {
+                        address: first.clone(),
+                        source: std::io::ErrorKind::ConnectionRefused.into(),
+                    }
+                ),
+                (
+                    second.clone(),
+                    MiniRedisError::StreamNotConnected {
+                        address: second.clone(),
+                        source: std::io::ErrorKind::ConnectionRefused.into(),
+                    }
+                ),
+            ]
+        }),
+        result
+    );
+    let message = result.unwrap_err().to_string();
+    assert!(message.contains(&first) && message.contains(&second));
+}
+
+#[test]
+fn connection_falls_over_to_the_next_address_when_the_connection_is_lost() {
+    // Answers one command, then goes away for good.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("Failed to bind to a port");
+    let primary = listener.local_addr().unwrap().to_string();
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut line = String::new();
+        BufReader::new(stream.try_clone().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        stream.write_all(b"OK\n").unwrap();
+    });
+    let replica_server = TestServer::start();
+    let replica = replica_server.address().to_string();
+    send_command(&replica, "SET a replicated").expect("Failed to send SET command");
+    let mut connection = Connection::builder(&format!("{},{}", primary, replica))
+        .protocol(Protocol::Inline)
+        .options(ConnectOptions {
+            retries: 1,
+            retry_writes: true,
+            ..ConnectOptions::default()
+        })
+        .connect()
+        .expect("Failed to connect");
+
+    connection.set("a", "1").unwrap();
+    assert_eq!(primary, connection.address());
+
+    assert_eq!(Some("replicated".to_string()), connection.get("a").unwrap());
+    assert_eq!(replica, connection.address());
+}
+
+#[test]
+fn connection_reads_multi_line_replies_without_desyncing() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let mut connection = Connection::connect(&address).expect("Failed to connect");
+    connection.set("a", "1").unwrap();
+
+    let info = connection.command(&["INFO"]).unwrap();
+
+    assert_eq!(
+        Response::Map(vec![
+            (
+                "version".to_string(),
+                Response::Bulk(env!("CARGO_PKG_VERSION").to_string())
+            ),
+            ("keys".to_string(), Response::Integer(1)),
+        ]),
+        info
+    );
+    assert_eq!(Some("1".to_string()), connection.get("a").unwrap());
+}
+
+#[test]
+fn connection_keeps_values_that_look_like_array_headers() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let mut connection = Connection::connect(&address).expect("Failed to connect");
+
+    connection.set("a", "*3").unwrap();
+    connection.set("b", "\\%1").unwrap();
+
+    assert_eq!(Some("*3".to_string()), connection.get("a").unwrap());
+    assert_eq!(Some("\\%1".to_string()), connection.get("b").unwrap());
+}
+
+#[test]
+fn connection_over_resp_gets_values_built_with_setbit_byte_for_byte() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let mut connection = Connection::builder(&address)
+        .protocol(Protocol::Resp)
+        .connect()
+        .expect("Failed to connect");
+
+    for offset in [0, 8, 9, 15] {
+        connection
+            .command(&["SETBIT", "bits", &offset.to_string(), "1"])
+            .unwrap();
+    }
+
+    assert_eq!(
+        Some(vec![0x80, 0xc1]),
+        connection.get_bytes("bits").unwrap()
+    );
+    assert_eq!(
+        Ok(Response::BulkBytes(vec![0xc1])),
+        connection.command(&["GETRANGE", "bits", "1", "1"])
+    );
+    assert_eq!(
+        Ok(Response::BulkBytes(vec![0x80, 0xc1])),
+        connection.command(&["GETRANGE", "bits", "0", "-1"])
+    );
+}
+
+#[test]
+fn connection_speaks_resp_when_the_server_offers_it() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let mut connection = Connection::connect(&address).expect("Failed to connect");
+
+    assert_eq!(Protocol::Resp, connection.protocol().unwrap());
+}
+
+#[test]
+fn connection_over_resp_keeps_values_with_spaces_newlines_and_quotes() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let mut connection = Connection::builder(&address)
+        .protocol(Protocol::Resp)
+        .connect()
+        .expect("Failed to connect");
+    let value = "two\nlines with \"quotes\" and *2";
+
+    connection.set("multi word key", value).unwrap();
+
+    assert_eq!(
+        Some(value.to_string()),
+        connection.get("multi word key").unwrap()
+    );
+    assert_eq!(
+        Err(MiniRedisError::WrongArity {
+            command: "GET".to_string()
+        }),
+        connection.command(&["GET"])
+    );
+}
+
+#[test]
+fn connection_forced_to_inline_still_works() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let mut connection = Connection::builder(&address)
+        .protocol(Protocol::Inline)
+        .connect()
+        .expect("Failed to connect");
+
+    connection.set("name", "alice smith").unwrap();
+
+    assert_eq!(Protocol::Inline, connection.protocol().unwrap());
+    assert_eq!(
+        Some("alice smith".to_string()),
+        send_command(&address, "GET name").ok()
+    );
+}
+
+#[test]
+fn server_answers_resp_requests_with_resp_replies() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let mut stream = TcpStream::connect(&address).expect("Failed to connect");
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    stream
+        .write_all(
+            b"*3\r\n$3\r\nSET\r\n$1\r\na\r\n$6\r\nx\r\ny z\r\n*2\r\n$3\r\nGET\r\n$1\r\na\r\n",
+        )
+        .unwrap();
+    let mut replies = vec![0; "+OK\r\n$6\r\nx\r\ny z\r\n".len()];
+    reader.read_exact(&mut replies).unwrap();
+
+    assert_eq!(
+        "+OK\r\n$6\r\nx\r\ny z\r\n",
+        String::from_utf8_lossy(&replies)
+    );
+}
+
+#[test]
+fn server_rejects_nested_resp_requests_and_keeps_serving() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let mut stream = TcpStream::connect(&address).expect("Failed to connect");
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    stream.write_all("*1\r\n".repeat(1000).as_bytes()).unwrap();
+    let mut reply = String::new();
+    reader.read_line(&mut reply).unwrap();
+
+    assert!(reply.starts_with("-ERR Protocol error"), "{}", reply);
+    assert_eq!(
+        Ok("PONG".to_string()),
+        send_command(&address, "PING").map_err(|e| e.kind())
+    );
+}
+
+#[test]
+fn scan_iter_yields_every_key_exactly_once() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let mut connection = Connection::connect(&address).expect("Failed to connect");
+    let mut expected = (0..300)
+        .map(|i| format!("key:{}", i))
+        .collect::<Vec<String>>();
+    for key in &expected {
+        connection.set(key, "value").unwrap();
+    }
+
+    let mut keys = connection
+        .scan_iter(None)
+        .collect::<Result<Vec<String>, MiniRedisError>>()
+        .unwrap();
+
+    keys.sort();
+    expected.sort();
+    assert_eq!(expected, keys);
+}
+
+#[test]
+fn scan_iter_yields_only_keys_matching_the_pattern() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    for key in ["user:1", "user:42", "session:1", "42"] {
+        send_command(&address, &format!("SET {} value", key)).expect("Failed to send SET command");
+    }
+    let mut connection = Connection::builder(&address)
+        .protocol(Protocol::Inline)
+        .connect()
+        .expect("Failed to connect");
+
+    let mut users = connection
+        .scan_iter(Some("user:*"))
+        .collect::<Result<Vec<String>, MiniRedisError>>()
+        .unwrap();
+    let numbers = connection
+        .scan_iter(Some("4?"))
+        .collect::<Result<Vec<String>, MiniRedisError>>()
+        .unwrap();
+
+    users.sort();
+    assert_eq!(vec!["user:1", "user:42"], users);
+    assert_eq!(vec!["42"], numbers);
+}
+
+#[test]
+fn scan_iter_with_passes_the_type_and_count_to_the_server() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    for i in 0..30 {
+        send_command(&address, &format!("SET session:{} value", i))
+            .expect("Failed to send SET command");
+    }
+    let mut connection = Connection::connect(&address).expect("Failed to connect");
+    let options = |kind: &str| ScanOptions {
+        pattern: Some("session:1*".to_string()),
+        kind: Some(kind.to_string()),
+        count: 7,
+    };
+
+    let mut sessions = connection
+        .scan_iter_with(options("string"))
+        .collect::<Result<Vec<String>, MiniRedisError>>()
+        .unwrap();
+    let lists = connection
+        .scan_iter_with(options("list"))
+        .collect::<Result<Vec<String>, MiniRedisError>>()
+        .unwrap();
+
+    sessions.sort();
+    let mut expected = (0..30)
+        .map(|i| format!("session:{}", i))
+        .filter(|key| key.starts_with("session:1"))
+        .collect::<Vec<String>>();
+    expected.sort();
+    assert_eq!(expected, sessions);
+    assert!(lists.is_empty());
+}
+
+#[test]
+fn scan_iter_ends_after_an_error() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("Failed to bind to a port");
+    let address = listener.local_addr().unwrap().to_string();
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        for _ in BufReader::new(stream.try_clone().unwrap()).lines() {
+            stream.write_all(b"ERR busy\n").unwrap();
+        }
+    });
+    let mut connection = Connection::connect(&address).expect("Failed to connect");
+
+    let mut keys = connection.scan_iter(None);
+
+    assert_eq!(
+        Some(Err(MiniRedisError::ServerError {
+            message: "busy".to_string()
+        })),
+        keys.next()
+    );
+    assert_eq!(None, keys.next());
+}
+
+#[test]
+fn connection_follows_moved_redirections_to_the_node_that_serves_the_key() {
+    let owner = TestServer::start();
+    let slots = SlotMap::new().assign(key_slot("foo")..=key_slot("foo"), owner.address());
+    let store = KVStore::new();
+    let redirecting = TestServer::serve(
+        Server::builder("127.0.0.1:0")
+            .storage(store.clone())
+            .slots(slots)
+            .logger(StderrLogger::new(Level::Warning))
+            .build(),
+    );
+
+    assert_eq!(
+        "ERR MOVED 12182 ".to_string() + owner.address(),
+        redirecting.send("SET foo 1")
+    );
+    assert_eq!("OK", redirecting.send("SET bar 2"));
+
+    for protocol in [Protocol::Inline, Protocol::Resp] {
+        let mut connection = Connection::builder(redirecting.address())
+            .protocol(protocol)
+            .connect()
+            .unwrap();
+        connection.set("foo", "1").unwrap();
+
+        assert_eq!(owner.address(), connection.address());
+        assert_eq!("1", owner.send("GET foo"));
+        assert_eq!(Ok(None), store.get("foo"));
+    }
+}
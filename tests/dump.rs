@@ -0,0 +1,190 @@
+use miniredis::connection::Connection;
+use miniredis::dump::{Dump, Restore, RestoreReport};
+use miniredis::error::MiniRedisError;
+use miniredis::testing::TestServer;
+use std::path::{Path, PathBuf};
+
+/// Helper function to get a path for a dump file unique to this test
+fn temp_file(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("miniredis-{}-{}.json", std::process::id(), name))
+}
+
+/// Helper function to build the arguments of a binary from its name and options
+fn args(name: &str, options: &[&str]) -> Vec<String> {
+    std::iter::once(name)
+        .chain(options.iter().copied())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Helper function to dump a server to a file
+fn dump(address: &str, path: &Path) -> usize {
+    let path = path.to_str().unwrap();
+    Dump::from_args(&args("miniredis-dump", &["--addr", address, "--out", path]))
+        .unwrap()
+        .run()
+        .unwrap()
+}
+
+/// Helper function to restore a file on a server with the given flags
+fn restore(address: &str, path: &Path, flags: &[&str]) -> Result<RestoreReport, MiniRedisError> {
+    let path = path.to_str().unwrap();
+    let mut options = vec!["--addr", address, "--in", path];
+    options.extend(flags);
+    Restore::from_args(&args("miniredis-restore", &options))?.run()
+}
+
+#[test]
+fn restore_sets_every_dumped_key_on_another_server() {
+    let source = TestServer::start();
+    let target = TestServer::start();
+    let path = temp_file("round-trip");
+    let values = [
+        ("user:1", r#"{"name":"alice","tags":["a","b"]}"#),
+        ("greeting", "hello world"),
+        ("quote", "say \"hi\" \\ bye"),
+        ("unicode", "zoë 😀"),
+    ];
+    let mut connection = Connection::connect(source.address()).unwrap();
+    for (key, value) in values {
+        connection.set(key, value).unwrap();
+    }
+    for i in 0..50 {
+        connection
+            .set(&format!("bulk:{}", i), &i.to_string())
+            .unwrap();
+    }
+
+    let dumped = dump(source.address(), &path);
+    let report = restore(target.address(), &path, &[]).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(54, dumped);
+    assert_eq!(
+        RestoreReport {
+            restored: 54,
+            skipped: 0
+        },
+        report
+    );
+    let mut restored = Connection::connect(target.address()).unwrap();
+    for (key, value) in values {
+        assert_eq!(Some(value.to_string()), restored.get(key).unwrap());
+    }
+    assert_eq!(Some("49".to_string()), restored.get("bulk:49").unwrap());
+}
+
+#[test]
+fn restore_keeps_times_to_live_and_values_that_are_not_utf8() {
+    let source = TestServer::start();
+    let target = TestServer::start();
+    let path = temp_file("ttl-and-bytes");
+    source.send("SET session abc");
+    source.send("PEXPIRE session 60000");
+    source.send("SET plain text");
+    // 0x80 0x61 0xff 0x00 0x01: invalid, valid, invalid, then valid bytes.
+    for offset in [0, 9, 10, 15, 16, 17, 18, 19, 20, 21, 22, 23, 39] {
+        source.send(&format!("SETBIT bits {} 1", offset));
+    }
+    source.send("PEXPIRE bits 60000");
+
+    let dumped = dump(source.address(), &path);
+    let contents = std::fs::read_to_string(&path).unwrap();
+    let report = restore(target.address(), &path, &[]).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(3, dumped);
+    assert!(
+        contents.contains(r#""value":"gGH/AAE=","encoding":"base64""#),
+        "{}",
+        contents
+    );
+    assert_eq!(3, report.restored);
+    let mut restored = Connection::connect(target.address()).unwrap();
+    assert_eq!(
+        Some(vec![0x80, 0x61, 0xff, 0x00, 0x01]),
+        restored.get_bytes("bits").unwrap()
+    );
+    assert_eq!(Some("abc".to_string()), restored.get("session").unwrap());
+    for key in ["session", "bits"] {
+        let ttl: u64 = target.send(&format!("PTTL {}", key)).parse().unwrap();
+        assert!(ttl > 50_000 && ttl <= 60_000, "{} {}", key, ttl);
+    }
+    assert_eq!("-1", target.send("PTTL plain"));
+}
+
+#[test]
+fn restore_skips_existing_keys_unless_told_to_replace_them() {
+    let server = TestServer::start();
+    let path = temp_file("replace");
+    std::fs::write(
+        &path,
+        "[\n{\"key\":\"a\",\"type\":\"string\",\"value\":\"new\",\"ttl\":null},\n\
+         {\"key\":\"b\",\"type\":\"string\",\"value\":\"new\",\"ttl\":null}\n]\n",
+    )
+    .unwrap();
+    server.send("SET a old");
+
+    let kept = restore(server.address(), &path, &[]).unwrap();
+    let value_after_kept = server.send("GET a");
+    let replaced = restore(server.address(), &path, &["--replace"]).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(
+        RestoreReport {
+            restored: 1,
+            skipped: 1
+        },
+        kept
+    );
+    assert_eq!("old", value_after_kept);
+    assert_eq!(
+        RestoreReport {
+            restored: 2,
+            skipped: 0
+        },
+        replaced
+    );
+    assert_eq!("new", server.send("GET a"));
+}
+
+#[test]
+fn dry_run_reports_without_setting_keys() {
+    let server = TestServer::start();
+    let path = temp_file("dry-run");
+    std::fs::write(
+        &path,
+        r#"[{"key":"a","value":"1"},{"key":"b","value":"2"}]"#,
+    )
+    .unwrap();
+    server.send("SET a 0");
+
+    let report = restore(server.address(), &path, &["--dry-run"]).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(
+        RestoreReport {
+            restored: 1,
+            skipped: 1
+        },
+        report
+    );
+    assert_eq!("0", server.send("GET a"));
+    assert_eq!("nil", server.send("GET b"));
+}
+
+#[test]
+fn restore_stops_at_an_invalid_entry() {
+    let server = TestServer::start();
+    let path = temp_file("invalid");
+    std::fs::write(&path, "[{\"key\":\"a\",\"value\":\"1\"},\n{\"key\":\"b\"}]").unwrap();
+
+    let result = restore(server.address(), &path, &[]);
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(matches!(
+        result,
+        Err(MiniRedisError::DumpInvalid { reason, .. }) if reason.starts_with("line 2:")
+    ));
+    assert_eq!("1", server.send("GET a"));
+}
@@ -0,0 +1,149 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+/// Helper function to find an available port
+fn find_available_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to a port");
+    listener
+        .local_addr()
+        .expect("Failed to get local address")
+        .port()
+}
+
+/// Helper function to create an empty data directory unique to this test
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("miniredis-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+/// Helper function to start the server binary with file storage and wait until it accepts connections
+fn start_server_process(dir: &Path) -> (Child, String) {
+    let address = format!("127.0.0.1:{}", find_available_port());
+    let mut child = Command::new(env!("CARGO_BIN_EXE_miniredis-server"))
+        .arg(&address)
+        .args(["--storage", "file", "--fsync", "always", "--dir"])
+        .arg(dir)
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("Failed to start server process");
+
+    for _ in 0..100 {
+        if TcpStream::connect(&address).is_ok() {
+            return (child, address);
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+    panic!("Server process did not start listening on {}", address);
+}
+
+/// Helper function to open a connection that sends commands one line at a time
+fn connect(address: &str) -> (TcpStream, BufReader<TcpStream>) {
+    let stream = TcpStream::connect(address).expect("Failed to connect");
+    let reader = BufReader::new(stream.try_clone().expect("Failed to clone stream"));
+    (stream, reader)
+}
+
+/// Helper function to send a command over an open connection and read the response
+fn send(
+    stream: &mut TcpStream,
+    reader: &mut BufReader<TcpStream>,
+    command: &str,
+) -> Option<String> {
+    stream.write_all(format!("{}\n", command).as_bytes()).ok()?;
+    let mut response = String::new();
+    match reader.read_line(&mut response) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(response.trim_end().to_string()),
+    }
+}
+
+#[test]
+fn data_survives_server_restart() {
+    let dir = temp_dir("persistence-restart");
+
+    let (mut server, address) = start_server_process(&dir);
+    let (mut stream, mut reader) = connect(&address);
+    assert_eq!(
+        Some("OK".to_string()),
+        send(&mut stream, &mut reader, "SET name alice")
+    );
+    assert_eq!(
+        Some("OK".to_string()),
+        send(&mut stream, &mut reader, "SET gone soon")
+    );
+    assert_eq!(
+        Some("1".to_string()),
+        send(&mut stream, &mut reader, "DEL gone")
+    );
+    server.kill().unwrap();
+    server.wait().unwrap();
+
+    let (mut server, address) = start_server_process(&dir);
+    let (mut stream, mut reader) = connect(&address);
+    assert_eq!(
+        Some("alice".to_string()),
+        send(&mut stream, &mut reader, "GET name")
+    );
+    assert_eq!(
+        Some("nil".to_string()),
+        send(&mut stream, &mut reader, "GET gone")
+    );
+    server.kill().unwrap();
+    server.wait().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn acknowledged_writes_survive_server_being_killed_mid_write() {
+    let dir = temp_dir("persistence-kill");
+    let mut acknowledged = Vec::new();
+
+    // Kill the server at a different point of the write stream each round,
+    // so some kills land between appending a record and syncing it.
+    for round in 0..5 {
+        let (mut server, address) = start_server_process(&dir);
+        let writer = thread::spawn(move || {
+            let (mut stream, mut reader) = connect(&address);
+            let mut acknowledged = Vec::new();
+            for i in 0.. {
+                let key = format!("round{}:key{}", round, i);
+                match send(&mut stream, &mut reader, &format!("SET {} value{}", key, i)) {
+                    Some(response) => {
+                        assert_eq!("OK", response);
+                        acknowledged.push((key, format!("value{}", i)));
+                    }
+                    None => break,
+                }
+            }
+            acknowledged
+        });
+
+        thread::sleep(Duration::from_millis(50 + 30 * round));
+        server.kill().unwrap();
+        server.wait().unwrap();
+        acknowledged.extend(writer.join().unwrap());
+    }
+
+    assert!(!acknowledged.is_empty());
+    let (mut server, address) = start_server_process(&dir);
+    let (mut stream, mut reader) = connect(&address);
+    for (key, value) in &acknowledged {
+        assert_eq!(
+            Some(value.clone()),
+            send(&mut stream, &mut reader, &format!("GET {}", key)),
+            "acknowledged write to {} was lost",
+            key
+        );
+    }
+    server.kill().unwrap();
+    server.wait().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,97 @@
+mod helpers;
+use helpers::send_command;
+
+use miniredis::error::MiniRedisError;
+use miniredis::pool::Pool;
+use miniredis::testing::TestServer;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn more_workers_than_connections_share_the_pool() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let pool = Pool::new(&address, 3);
+
+    let workers = (0..12)
+        .map(|worker| {
+            let pool = pool.clone();
+            thread::spawn(move || {
+                for i in 0..10 {
+                    let key = format!("worker:{}:{}", worker, i);
+                    let mut connection = pool.get().expect("Failed to check out a connection");
+                    connection.set(&key, &i.to_string()).unwrap();
+                    assert_eq!(Some(i.to_string()), connection.get(&key).unwrap());
+                    assert!(pool.open_connections() <= 3);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for worker in workers {
+        worker.join().expect("A worker panicked");
+    }
+
+    assert!(pool.open_connections() <= 3);
+    assert_eq!(pool.open_connections(), pool.idle_connections());
+    let response = send_command(&address, "GET worker:11:9").expect("Failed to send GET command");
+    assert_eq!(response, "9");
+}
+
+#[test]
+fn returned_connections_are_reused() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let pool = Pool::new(&address, 2);
+
+    for _ in 0..5 {
+        pool.get().unwrap().ping().unwrap();
+    }
+
+    assert_eq!(pool.open_connections(), 1);
+}
+
+#[test]
+fn checkout_times_out_when_every_connection_is_in_use() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let timeout = Duration::from_millis(100);
+    let pool = Pool::builder(&address, 1).checkout_timeout(timeout).build();
+
+    let _held = pool.get().unwrap();
+
+    assert_eq!(
+        pool.get().map(|_| ()),
+        Err(MiniRedisError::PoolExhausted {
+            max_size: 1,
+            timeout
+        })
+    );
+}
+
+#[test]
+fn checkout_waits_for_a_connection_to_be_returned() {
+    let server = TestServer::start();
+    let address = server.address().to_string();
+    let pool = Pool::new(&address, 1);
+    let held = pool.get().unwrap();
+
+    let returner = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        drop(held);
+    });
+    let mut connection = pool.get().expect("Failed to check out a connection");
+
+    connection.ping().unwrap();
+    returner.join().unwrap();
+}
+
+#[test]
+fn failed_connect_frees_its_place_in_the_pool() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("Failed to bind to a port");
+    let address = listener.local_addr().unwrap().to_string();
+    drop(listener);
+    let pool = Pool::new(&address, 1);
+
+    assert!(pool.get().is_err());
+    assert_eq!(pool.open_connections(), 0);
+}
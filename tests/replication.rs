@@ -0,0 +1,171 @@
+use miniredis::log::{Level, StderrLogger};
+use miniredis::server::{ExecutionMode, Server};
+use miniredis::testing::TestServer;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A replica driven by hand, which only acknowledges the writes when a test tells it to.
+struct SlowReplica {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+    /// How many bytes of writes the replica has read.
+    offset: u64,
+}
+
+impl SlowReplica {
+    /// Attaches to a primary, and reads the header of its snapshot.
+    fn attach(primary: &str) -> Self {
+        let mut stream = TcpStream::connect(primary).unwrap();
+        stream.write_all(b"*1\r\n$4\r\nSYNC\r\n").unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut header = String::new();
+        reader.read_line(&mut header).unwrap();
+        assert_eq!("+FULLRESYNC 0 0\r\n", header);
+        Self {
+            stream,
+            reader,
+            offset: 0,
+        }
+    }
+
+    /// Reads the next write the primary sent, as its words.
+    fn read_command(&mut self) -> Vec<String> {
+        let mut line = String::new();
+        self.offset += self.reader.read_line(&mut line).unwrap() as u64;
+        let len: usize = line.trim_end()[1..].parse().unwrap();
+        let mut words = Vec::new();
+        for _ in 0..len {
+            line.clear();
+            self.offset += self.reader.read_line(&mut line).unwrap() as u64;
+            let mut word = vec![0; line.trim_end()[1..].parse::<usize>().unwrap() + 2];
+            self.reader.read_exact(&mut word).unwrap();
+            self.offset += word.len() as u64;
+            words.push(String::from_utf8_lossy(&word[..word.len() - 2]).into_owned());
+        }
+        words
+    }
+
+    /// Acknowledges every write read so far.
+    fn acknowledge(&mut self) {
+        let offset = self.offset.to_string();
+        let frame = format!(
+            "*3\r\n$8\r\nREPLCONF\r\n$3\r\nACK\r\n${}\r\n{}\r\n",
+            offset.len(),
+            offset
+        );
+        self.stream.write_all(frame.as_bytes()).unwrap();
+    }
+}
+
+/// Sends an inline command over a new connection and reads the first line of its reply,
+/// for a thread that does not own the server.
+fn send(address: &str, command: &str) -> String {
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream
+        .write_all(format!("{}\n", command).as_bytes())
+        .unwrap();
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply).unwrap();
+    reply.trim_end().to_string()
+}
+
+#[test]
+fn wait_counts_a_slow_replica_only_once_it_acknowledges() {
+    let server = TestServer::start();
+    let mut replica = SlowReplica::attach(server.address());
+
+    assert_eq!("OK", server.send("SET name alice"));
+    let start = Instant::now();
+    assert_eq!("0", server.send("WAIT 1 100"));
+    assert!(start.elapsed() >= Duration::from_millis(100));
+
+    // A timeout of 0 blocks until the replica acknowledges, however long it takes.
+    let waiting = {
+        let address = server.address().to_string();
+        thread::spawn(move || send(&address, "WAIT 1 0"))
+    };
+    assert_eq!(vec!["SET", "name", "alice"], replica.read_command());
+    assert_eq!(vec!["REPLCONF", "GETACK", "*"], replica.read_command());
+    assert_eq!(vec!["REPLCONF", "GETACK", "*"], replica.read_command());
+    thread::sleep(Duration::from_millis(200));
+    assert!(!waiting.is_finished());
+
+    replica.acknowledge();
+    assert_eq!("1", waiting.join().unwrap());
+}
+
+#[test]
+fn wait_for_no_replicas_returns_at_once() {
+    let server = TestServer::start();
+    let _replica = SlowReplica::attach(server.address());
+
+    assert_eq!("OK", server.send("SET name alice"));
+    assert_eq!("0", server.send("WAIT 0 0"));
+}
+
+#[test]
+fn wait_in_single_writer_mode_does_not_hold_up_other_clients() {
+    let server = TestServer::serve(
+        Server::builder("127.0.0.1:0")
+            .execution_mode(ExecutionMode::SingleWriter)
+            .logger(StderrLogger::new(Level::Warning))
+            .build(),
+    );
+    let mut replica = SlowReplica::attach(server.address());
+    let waiting = {
+        let address = server.address().to_string();
+        thread::spawn(move || send(&address, "WAIT 1 0"))
+    };
+    assert_eq!(vec!["REPLCONF", "GETACK", "*"], replica.read_command());
+
+    assert_eq!("OK", server.send("SET name alice"));
+    assert_eq!("alice", server.send("GET name"));
+    assert!(!waiting.is_finished());
+
+    replica.acknowledge();
+    assert_eq!("1", waiting.join().unwrap());
+}
+
+#[test]
+fn shutting_down_lets_a_blocked_wait_go() {
+    let server = TestServer::start();
+    let _replica = SlowReplica::attach(server.address());
+    let mut stream = TcpStream::connect(server.address()).unwrap();
+    stream.write_all(b"WAIT 1 0\n").unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    drop(server);
+
+    // The server only finishes shutting down once the wait is let go.
+    let mut rest = String::new();
+    let _ = stream.read_to_string(&mut rest);
+}
+
+#[test]
+fn replica_copies_the_data_and_the_writes_of_its_primary() {
+    let primary = TestServer::start();
+    assert_eq!("OK", primary.send("SET before 1"));
+    assert_eq!("0", primary.send("SETBIT bits 0 1"));
+    assert_eq!("OK", primary.send("SET session token PX 60000"));
+
+    let replica = TestServer::serve(
+        Server::builder("127.0.0.1:0")
+            .replica_of(primary.address())
+            .logger(StderrLogger::new(Level::Warning))
+            .build(),
+    );
+    // A replica that attaches after the wait started still counts once it caught up.
+    assert_eq!("1", primary.send("WAIT 1 5000"));
+    assert_eq!("OK", primary.send("SET after 2"));
+    assert_eq!("2", primary.send("INCR before"));
+    assert_eq!("1", primary.send("WAIT 1 5000"));
+
+    assert_eq!("2", replica.send("GET before"));
+    assert_eq!("2", replica.send("GET after"));
+    assert_eq!("1", replica.send("GETBIT bits 0"));
+    assert_eq!("1", replica.send("STRLEN bits"));
+    let ttl: i64 = replica.send("PTTL session").parse().unwrap();
+    assert!(ttl > 0 && ttl <= 60000);
+}
@@ -0,0 +1,111 @@
+mod helpers;
+use helpers::send_command;
+
+use miniredis::error::MiniRedisError;
+use miniredis::response::Response;
+use miniredis::shard::ShardedConnection;
+use miniredis::testing::TestServer;
+
+fn start_three() -> (Vec<TestServer>, Vec<String>) {
+    let servers = (0..3).map(|_| TestServer::start()).collect::<Vec<_>>();
+    let addresses = servers
+        .iter()
+        .map(|server| server.address().to_string())
+        .collect();
+    (servers, addresses)
+}
+
+#[test]
+fn keys_are_spread_over_every_server_and_read_back_through_the_owner() {
+    let (_servers, addresses) = start_three();
+    let mut connection =
+        ShardedConnection::new(&addresses.iter().map(String::as_str).collect::<Vec<_>>()).unwrap();
+
+    for i in 0..300 {
+        connection
+            .set(&format!("key:{}", i), &i.to_string())
+            .unwrap();
+    }
+
+    for address in &addresses {
+        let owned = (0..300)
+            .filter(|i| send_command(address, &format!("GET key:{}", i)).unwrap() == i.to_string())
+            .count();
+        assert!(owned > 30, "{} owns only {} keys", address, owned);
+    }
+    for i in 0..300 {
+        let key = format!("key:{}", i);
+        assert_eq!(Some(i.to_string()), connection.get(&key).unwrap());
+        assert_eq!(
+            i.to_string(),
+            send_command(connection.address_of(&key), &format!("GET {}", key)).unwrap()
+        );
+    }
+    assert!(connection.del("key:0").unwrap());
+    assert!(!connection.del("key:0").unwrap());
+    assert_eq!(None, connection.get("key:0").unwrap());
+}
+
+#[test]
+fn mget_and_mset_are_split_per_server_and_merged_in_order() {
+    let (_servers, addresses) = start_three();
+    let mut connection =
+        ShardedConnection::new(&addresses.iter().map(String::as_str).collect::<Vec<_>>()).unwrap();
+    let keys = (0..20).map(|i| format!("key:{}", i)).collect::<Vec<_>>();
+    let pairs = keys
+        .iter()
+        .map(|key| (key.as_str(), key.as_str()))
+        .collect::<Vec<_>>();
+
+    connection.mset(&pairs).unwrap();
+    let mut wanted = keys.iter().map(String::as_str).collect::<Vec<_>>();
+    wanted.insert(5, "missing");
+
+    let mut values = keys.iter().cloned().map(Some).collect::<Vec<_>>();
+    values.insert(5, None);
+    assert_eq!(values, connection.mget(&wanted).unwrap());
+}
+
+#[test]
+fn command_runs_on_the_owner_of_keys_with_one_hash_tag() {
+    let (_servers, addresses) = start_three();
+    let mut connection =
+        ShardedConnection::new(&addresses.iter().map(String::as_str).collect::<Vec<_>>()).unwrap();
+    connection.set("{user:1}:a", "ohmytext").unwrap();
+    connection.set("{user:1}:b", "mynewtext").unwrap();
+
+    assert_eq!(
+        Response::Bulk("mytext".to_string()),
+        connection
+            .command(&["LCS", "{user:1}:a", "{user:1}:b"])
+            .unwrap()
+    );
+}
+
+#[test]
+fn a_server_that_is_down_fails_only_its_own_keys_naming_its_address() {
+    let (mut servers, addresses) = start_three();
+    let mut connection =
+        ShardedConnection::new(&addresses.iter().map(String::as_str).collect::<Vec<_>>()).unwrap();
+    let down = addresses[1].clone();
+    drop(servers.remove(1));
+    let keys = (0..100).map(|i| format!("key:{}", i)).collect::<Vec<_>>();
+
+    for key in &keys {
+        match connection.set(key, "1") {
+            Err(MiniRedisError::ShardFailed { address, .. }) => {
+                assert_eq!(down, address);
+                assert_eq!(down, connection.address_of(key));
+            }
+            result => {
+                assert_eq!(Ok(()), result);
+                assert_ne!(down, connection.address_of(key));
+            }
+        }
+    }
+    let wanted = keys.iter().map(String::as_str).collect::<Vec<_>>();
+    assert!(matches!(
+        connection.mget(&wanted),
+        Err(MiniRedisError::ShardFailed { address, .. }) if address == down
+    ));
+}